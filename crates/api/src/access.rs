@@ -0,0 +1,156 @@
+//! Data-access audit trail
+//!
+//! Every read, write, and delete of data through the API, and every
+//! federation token checked for it, is recorded as a `data_accessed` audit
+//! action: who accessed which data, with which permission, and whether
+//! access was granted. Requests with a [data token](crate::auth) are
+//! recorded under the token's issuer, and requests without a token as
+//! `anonymous`. A peer cluster is recorded under its name when it
+//! exchanges a federation token, and its reads with the exchanged token
+//! under `federation` with that token's ID, which ties them to the
+//! exchange.
+//!
+//! `GET /api/data/{uuid}/access` lists the records for one piece of data,
+//! oldest first, so a security review can tell exactly who read it.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+
+use swarmx_dataref::AccessToken;
+use swarmx_events::{AuditAction, AuditFilter, AuditRecord, Event};
+use swarmx_protocol::ApiResponse;
+
+use crate::{AppState, AuditQuery};
+
+/// Actor recorded for requests without a token
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Actor recorded for reads with an exchanged federation token
+pub const FEDERATION_ACTOR: &str = "federation";
+
+/// An access to data, to be recorded in the audit trail
+#[derive(Debug, Clone)]
+pub struct DataAccess {
+    actor: String,
+    data_uuid: Uuid,
+    workflow_id: Option<Uuid>,
+    permission: &'static str,
+    token_id: Option<String>,
+}
+
+impl DataAccess {
+    /// Read access by `actor`
+    pub fn read(actor: &str, data_uuid: Uuid) -> Self {
+        Self::new(actor, data_uuid, "read")
+    }
+
+    /// Write access by `actor`
+    pub fn write(actor: &str, data_uuid: Uuid) -> Self {
+        Self::new(actor, data_uuid, "write")
+    }
+
+    /// Delete access by `actor`
+    pub fn delete(actor: &str, data_uuid: Uuid) -> Self {
+        Self::new(actor, data_uuid, "delete")
+    }
+
+    fn new(actor: &str, data_uuid: Uuid, permission: &'static str) -> Self {
+        Self {
+            actor: actor.to_string(),
+            data_uuid,
+            workflow_id: None,
+            permission,
+            token_id: None,
+        }
+    }
+
+    /// Record the workflow the data belongs to
+    ///
+    /// Otherwise it is looked up in the local store when recording, which
+    /// fails once the data is deleted.
+    pub fn with_workflow(mut self, workflow_id: Uuid) -> Self {
+        self.workflow_id = Some(workflow_id);
+        self
+    }
+
+    /// Record the token presented for the access
+    pub fn with_token(mut self, token: &AccessToken) -> Self {
+        self.token_id = Some(token.token_id());
+        self
+    }
+
+    /// Record the access as granted, or as refused or failed with the
+    /// reason given
+    pub async fn record(self, state: &AppState, result: Result<(), String>) {
+        let workflow_id = self.workflow_id.or_else(|| {
+            state
+                .inner
+                .store
+                .get_ref(&self.data_uuid)
+                .map(|data_ref| data_ref.workflow_id)
+        });
+        let action = AuditAction::DataAccessed {
+            data_uuid: self.data_uuid,
+            workflow_id,
+            permission: self.permission.to_string(),
+            token_id: self.token_id,
+            granted: result.is_ok(),
+            reason: result.err(),
+        };
+        if let Err(e) = state
+            .inner
+            .events
+            .publish(Event::audit(self.actor, action))
+            .await
+        {
+            tracing::warn!(data_uuid = %self.data_uuid, "Failed to record data access: {e}");
+        }
+    }
+}
+
+/// Outcome of a handler as recorded in the audit trail
+pub fn access_result<T>(result: &Result<T, StatusCode>) -> Result<(), String> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(status) => Err(status_reason(*status)),
+    }
+}
+
+/// Reason recorded for a request that failed with `status`
+pub fn status_reason(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .map_or_else(|| status.to_string(), str::to_string)
+}
+
+/// List who accessed data, oldest first
+///
+/// Takes the same `actor`, `from`, `to`, and `limit` parameters as
+/// `GET /api/admin/audit`.
+pub async fn list_data_access(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    Query(query): Query<AuditQuery>,
+) -> (StatusCode, Json<ApiResponse<Vec<AuditRecord>>>) {
+    let filter = AuditFilter::from(query).data(uuid);
+    match state
+        .inner
+        .events
+        .wal()
+        .read_filtered(filter.to_event_filter())
+        .await
+    {
+        Ok(envelopes) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(filter.apply(&envelopes))),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
@@ -0,0 +1,213 @@
+//! Administrative endpoints for debugging executions
+//!
+//! These read the durable event log directly, so they work for workflows
+//! whose in-memory execution state is gone, e.g. after a crash.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::AppState;
+use swarmx_events::{
+    replay, AuditFilter, AuditRecord, BackupInfo, EventFilter, ReplayReport, WalError,
+};
+use swarmx_protocol::ApiResponse;
+
+/// Rebuild a workflow's timeline from its events
+///
+/// `GET /api/admin/workflows/{id}/replay` returns final node states,
+/// durations, retries, and any inconsistencies found in the log.
+pub async fn replay_workflow(
+    State(state): State<AppState>,
+    Path(workflow_id): Path<Uuid>,
+) -> (StatusCode, Json<ApiResponse<ReplayReport>>) {
+    let envelopes = match state
+        .inner
+        .events
+        .wal()
+        .read_filtered(EventFilter::new().workflow(workflow_id))
+        .await
+    {
+        Ok(envelopes) => envelopes,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+            )
+        }
+    };
+
+    if envelopes.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "No events for workflow")),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(replay(workflow_id, &envelopes))),
+    )
+}
+
+/// Audit trail query parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub workflow_id: Option<Uuid>,
+    pub data_uuid: Option<Uuid>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl From<AuditQuery> for AuditFilter {
+    fn from(query: AuditQuery) -> Self {
+        AuditFilter {
+            actor: query.actor,
+            action: query.action,
+            workflow_id: query.workflow_id,
+            data_uuid: query.data_uuid,
+            from_timestamp: query.from,
+            to_timestamp: query.to,
+            limit: query.limit,
+        }
+    }
+}
+
+/// Query the audit trail of control-plane actions and data access
+///
+/// `GET /api/admin/audit` returns matching records oldest first.
+pub async fn list_audit_records(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> (StatusCode, Json<ApiResponse<Vec<AuditRecord>>>) {
+    let filter = AuditFilter::from(query);
+    match state
+        .inner
+        .events
+        .wal()
+        .read_filtered(filter.to_event_filter())
+        .await
+    {
+        Ok(envelopes) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(filter.apply(&envelopes))),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
+
+/// Directory holding event log backups, unless `SWARMX_BACKUP_DIR` is set
+const DEFAULT_BACKUP_DIR: &str = "backups";
+
+/// Backup and restore request
+#[derive(Debug, Default, Deserialize)]
+pub struct BackupRequest {
+    /// File name within the backup directory; backups default to a
+    /// timestamped name
+    pub name: Option<String>,
+}
+
+/// Resolve a backup file name within the backup directory
+///
+/// Names are plain file names, so requests cannot reach outside it.
+fn backup_path(name: &str) -> Option<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    let dir = std::env::var("SWARMX_BACKUP_DIR").unwrap_or_else(|_| DEFAULT_BACKUP_DIR.into());
+    valid.then(|| PathBuf::from(dir).join(name))
+}
+
+fn invalid_backup_name<T>() -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::error(
+            "INVALID_NAME",
+            "Backup name may only contain letters, digits, '.', '-' and '_'",
+        )),
+    )
+}
+
+/// Back up the event log
+///
+/// `POST /api/admin/backup` writes a consistent copy of the log to the
+/// backup directory while appends carry on.
+pub async fn backup_event_log(
+    State(state): State<AppState>,
+    Json(request): Json<BackupRequest>,
+) -> (StatusCode, Json<ApiResponse<BackupInfo>>) {
+    let name = request
+        .name
+        .unwrap_or_else(|| format!("swarmx-events-{}.db", Utc::now().format("%Y%m%dT%H%M%SZ")));
+    let Some(path) = backup_path(&name) else {
+        return invalid_backup_name();
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+            );
+        }
+    }
+
+    match state.inner.events.wal().backup(&path).await {
+        Ok(info) => (StatusCode::CREATED, Json(ApiResponse::success(info))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
+
+/// Restore the event log from a backup
+///
+/// `POST /api/admin/restore` replaces the log's contents with a backup from
+/// the backup directory. The backup is verified first and must not end
+/// before the current log, so nothing is changed if it is damaged or stale.
+/// Executions held in memory are not rebuilt.
+pub async fn restore_event_log(
+    State(state): State<AppState>,
+    Json(request): Json<BackupRequest>,
+) -> (StatusCode, Json<ApiResponse<BackupInfo>>) {
+    let Some(path) = request.name.as_deref().and_then(backup_path) else {
+        return invalid_backup_name();
+    };
+    if !path.is_file() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Backup not found")),
+        );
+    }
+
+    match state.inner.events.wal().restore(&path).await {
+        Ok(info) => (StatusCode::OK, Json(ApiResponse::success(info))),
+        Err(e @ WalError::BackupBehind { .. }) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error("BACKUP_BEHIND", &e.to_string())),
+        ),
+        Err(e @ (WalError::Corrupt(_) | WalError::SequenceGap { .. })) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiResponse::error("INVALID_BACKUP", &e.to_string())),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
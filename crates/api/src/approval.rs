@@ -0,0 +1,240 @@
+//! Approval endpoints for human-in-the-loop gates
+//!
+//! Executions halt at `flow.approval` nodes until a reviewer posts a
+//! decision here. A background sweeper halts each gate once its upstream
+//! nodes are done, and applies the gate's default decision once its timeout
+//! elapses. Approving a gate immediately opens the gates behind it.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::AppState;
+use swarmx_core::{ApprovalDecision, NodeState, SharedWorkflowContext, StateError, WorkflowDag};
+use swarmx_protocol::ApiResponse;
+
+/// How often the sweeper checks for timed-out approvals
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Approval decision request
+#[derive(Debug, Deserialize)]
+pub struct ApprovalRequest {
+    pub decision: ApprovalDecision,
+    /// Who made the decision
+    #[serde(default)]
+    pub reviewer: Option<String>,
+    /// Free-form comment recorded with the transition
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// Approval decision response
+#[derive(Debug, Serialize)]
+pub struct ApprovalResolved {
+    pub execution_id: Uuid,
+    pub node_id: Uuid,
+    pub decision: ApprovalDecision,
+    pub state: NodeState,
+}
+
+/// Approve or reject a node waiting for approval
+pub async fn resolve_approval(
+    State(state): State<AppState>,
+    Path((execution_id, node_id)): Path<(Uuid, Uuid)>,
+    Json(request): Json<ApprovalRequest>,
+) -> (StatusCode, Json<ApiResponse<ApprovalResolved>>) {
+    // Clone the handles out so the store lock is released before mutating
+    let execution = {
+        let executions = state.inner.executions.read().await;
+        executions
+            .get(&execution_id)
+            .map(|e| (e.context.clone(), e.dag.clone()))
+    };
+    let Some((context, dag)) = execution else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Execution not found")),
+        );
+    };
+
+    let reason = match (&request.reviewer, &request.comment) {
+        (Some(reviewer), Some(comment)) => Some(format!("{reviewer}: {comment}")),
+        (Some(reviewer), None) => Some(format!("decided by {reviewer}")),
+        (None, Some(comment)) => Some(comment.clone()),
+        (None, None) => None,
+    };
+
+    match context.resolve_approval(node_id, request.decision, reason, Some(&dag)) {
+        Ok(transition) => {
+            open_gates(execution_id, &context, &dag);
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(ApprovalResolved {
+                    execution_id,
+                    node_id,
+                    decision: request.decision,
+                    state: transition.to,
+                })),
+            )
+        }
+        Err(StateError::NodeNotFound(_)) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Node not found")),
+        ),
+        Err(e) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error("NOT_AWAITING_APPROVAL", &e.to_string())),
+        ),
+    }
+}
+
+/// Periodically apply default decisions to timed-out approvals
+pub async fn approval_sweeper(state: AppState) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        sweep_approvals(&state, chrono::Utc::now()).await;
+    }
+}
+
+/// Halt ready gates and apply default decisions whose deadline has passed
+async fn sweep_approvals(state: &AppState, now: chrono::DateTime<chrono::Utc>) {
+    let executions: Vec<_> = {
+        let executions = state.inner.executions.read().await;
+        executions
+            .executions
+            .values()
+            .map(|e| (e.execution_id, e.context.clone(), e.dag.clone()))
+            .collect()
+    };
+
+    for (execution_id, context, dag) in executions {
+        open_gates(execution_id, &context, &dag);
+        for (node_id, decision) in context.expire_approvals(now, Some(&dag)) {
+            tracing::info!(
+                execution_id = %execution_id,
+                node_id = %node_id,
+                decision = ?decision,
+                "Approval timed out, applied default decision"
+            );
+        }
+    }
+}
+
+/// Halt the approval gates of an execution whose upstream nodes are done
+pub fn open_gates(execution_id: Uuid, context: &SharedWorkflowContext, dag: &WorkflowDag) {
+    for (node_id, result) in context.open_approval_gates(dag) {
+        match result {
+            Ok(_) => tracing::info!(
+                execution_id = %execution_id,
+                node_id = %node_id,
+                "Awaiting approval"
+            ),
+            Err(e) => tracing::warn!(
+                execution_id = %execution_id,
+                node_id = %node_id,
+                "Cannot open approval gate: {e}"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+
+    use swarmx_core::{
+        NodeBuilder, Scheduler, ServerInfo, WorkflowContext, WorkflowEdge, APPROVAL_NODE_TYPE,
+    };
+
+    use super::*;
+    use crate::ExecutionState;
+
+    fn edge() -> WorkflowEdge {
+        WorkflowEdge {
+            source_output: "out".to_string(),
+            target_input: "in".to_string(),
+            transform: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gate_opens_and_approval_unblocks_downstream() {
+        let mut dag = WorkflowDag::new();
+        let fetch = NodeBuilder::new("http.request", "Fetch").build();
+        let gate = NodeBuilder::new(APPROVAL_NODE_TYPE, "Review").build();
+        let send = NodeBuilder::new("http.request", "Send").build();
+        let (fetch_id, gate_id, send_id) = (fetch.id, gate.id, send.id);
+        dag.add_node(fetch);
+        dag.add_node(gate);
+        dag.add_node(send);
+        dag.add_edge(fetch_id, gate_id, edge()).unwrap();
+        dag.add_edge(gate_id, send_id, edge()).unwrap();
+
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        for id in [fetch_id, gate_id, send_id] {
+            ctx.add_node(id);
+        }
+        for to in [NodeState::Scheduled, NodeState::Running, NodeState::Done] {
+            ctx.get_node_mut(&fetch_id).unwrap().transition(to).unwrap();
+        }
+        let execution_id = ctx.execution_id;
+        let context = SharedWorkflowContext::new(ctx);
+        let dag = Arc::new(dag);
+
+        let state = AppState::new();
+        state.inner.executions.write().await.executions.insert(
+            execution_id,
+            ExecutionState {
+                execution_id,
+                workflow_id: dag.workflow_id(),
+                context: context.clone(),
+                dag: dag.clone(),
+                started_at: chrono::Utc::now(),
+            },
+        );
+        let app = Router::new()
+            .route(
+                "/api/executions/{id}/nodes/{node_id}/approval",
+                post(resolve_approval),
+            )
+            .with_state(state.clone());
+        let approve = || {
+            Request::post(format!(
+                "/api/executions/{execution_id}/nodes/{gate_id}/approval"
+            ))
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"decision":"approve","reviewer":"ops"}"#))
+            .unwrap()
+        };
+
+        let response = app.clone().oneshot(approve()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        sweep_approvals(&state, chrono::Utc::now()).await;
+        assert_eq!(
+            context.node_state(&gate_id),
+            Some(NodeState::WaitingForApproval)
+        );
+
+        let response = app.oneshot(approve()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(context.node_state(&gate_id), Some(NodeState::Done));
+
+        let mut scheduler = Scheduler::default();
+        scheduler.register_server(ServerInfo::new("server-a".to_string()));
+        let reasons = context
+            .read(|ctx| scheduler.blocking_reasons(ctx, send_id, &dag))
+            .unwrap();
+        assert!(reasons.is_empty(), "downstream is blocked: {reasons:?}");
+    }
+}
@@ -0,0 +1,592 @@
+//! Token enforcement for the data endpoints
+//!
+//! With data tokens configured, reading, writing, deriving from, pinning,
+//! leasing, and deleting data through `/api/data/{uuid}` requires an
+//! [`AccessToken`] sent as `Authorization: Bearer <token>`, the token's JSON
+//! base64url-encoded. The [`DataToken`] extractor verifies the token with
+//! the server's [`TokenManager`] and checks that it grants the permission
+//! the request needs on the requested data: `GET`, `HEAD`, and deriving
+//! read, `DELETE /api/data/{uuid}` deletes, and anything else, including
+//! pins and leases, writes. Pinning a workflow's data through
+//! `/api/workflows/{id}/data/pin` needs a token scoped to that workflow.
+//! Refused requests get a structured error and are recorded in the
+//! data-access audit trail; granted ones are recorded by the handler under
+//! the token's issuer. Without data tokens configured, requests need no
+//! token and are recorded as `anonymous`.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Path},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::Engine;
+use uuid::Uuid;
+
+use swarmx_dataref::{AccessToken, Permissions, TokenError, TokenScope};
+use swarmx_protocol::ApiResponse;
+
+use crate::{AppState, DataAccess, ANONYMOUS_ACTOR};
+
+/// Verified token of a request to a data endpoint
+///
+/// `None` if data tokens are not configured.
+#[derive(Debug, Clone)]
+pub struct DataToken(pub Option<AccessToken>);
+
+impl DataToken {
+    /// Actor accesses are recorded under: the token's issuer, or
+    /// `anonymous` without a token
+    pub fn actor(&self) -> &str {
+        self.0
+            .as_ref()
+            .map_or(ANONYMOUS_ACTOR, |token| token.issued_by.as_str())
+    }
+
+    /// Read access to `uuid` with this token
+    pub fn read(&self, uuid: Uuid) -> DataAccess {
+        self.access(DataAccess::read(self.actor(), uuid))
+    }
+
+    /// Write access to `uuid` with this token
+    pub fn write(&self, uuid: Uuid) -> DataAccess {
+        self.access(DataAccess::write(self.actor(), uuid))
+    }
+
+    /// Delete access to `uuid` with this token
+    pub fn delete(&self, uuid: Uuid) -> DataAccess {
+        self.access(DataAccess::delete(self.actor(), uuid))
+    }
+
+    fn access(&self, access: DataAccess) -> DataAccess {
+        match &self.0 {
+            Some(token) => access.with_token(token),
+            None => access,
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for DataToken {
+    type Rejection = TokenRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(tokens) = &state.inner.tokens else {
+            return Ok(Self(None));
+        };
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| TokenRejection::new(StatusCode::BAD_REQUEST, "INVALID_PATH", e))?;
+        let target = match (params.get("uuid"), params.get("id")) {
+            (Some(uuid), _) => Target::Data(path_uuid(uuid)?),
+            (None, Some(workflow_id)) => Target::Workflow(path_uuid(workflow_id)?),
+            (None, None) => {
+                return Err(TokenRejection::new(
+                    StatusCode::BAD_REQUEST,
+                    "INVALID_PATH",
+                    "Request names no data",
+                ))
+            }
+        };
+        let required = required_access(parts);
+
+        let Some(token) = bearer_token(&parts.headers) else {
+            let rejection = match parts.headers.get(header::AUTHORIZATION) {
+                Some(_) => TokenRejection::new(
+                    StatusCode::UNAUTHORIZED,
+                    "INVALID_TOKEN",
+                    "Authorization header is not a bearer access token",
+                ),
+                None => TokenRejection::new(
+                    StatusCode::UNAUTHORIZED,
+                    "MISSING_TOKEN",
+                    "Data access requires a bearer access token",
+                ),
+            };
+            target
+                .record(state, required, ANONYMOUS_ACTOR, None, &rejection)
+                .await;
+            return Err(rejection);
+        };
+
+        let authorized = match target {
+            // Data the server does not hold is reported missing by the
+            // handler, but only to tokens for exactly that data: workflow
+            // and prefix scopes cannot be checked without the data's
+            // reference
+            Target::Data(uuid) => match state.inner.store.get_ref(&uuid) {
+                Some(data_ref) => tokens.authorize(&token, [&data_ref], required.permissions()),
+                None => tokens
+                    .authorize(&token, [], required.permissions())
+                    .and_then(|()| match token.scope {
+                        TokenScope::Data { data_uuid } if data_uuid == uuid => Ok(()),
+                        _ => Err(TokenError::OutOfScope(uuid)),
+                    }),
+            },
+            // A workflow's data includes data it has yet to produce, so
+            // only a token for the whole workflow covers it
+            Target::Workflow(workflow_id) => tokens
+                .authorize(&token, [], required.permissions())
+                .and_then(|()| match token.scope {
+                    TokenScope::Workflow { workflow_id: id } if id == workflow_id => Ok(()),
+                    _ => Err(TokenError::OutOfScope(workflow_id)),
+                }),
+        };
+        if let Err(e) = authorized {
+            let rejection = TokenRejection::from(e);
+            target
+                .record(state, required, &token.issued_by, Some(&token), &rejection)
+                .await;
+            return Err(rejection);
+        }
+        Ok(Self(Some(token)))
+    }
+}
+
+/// Data a request to a data endpoint is for
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Data(Uuid),
+    Workflow(Uuid),
+}
+
+impl Target {
+    /// Record a refused request for the data: for a workflow, once for
+    /// each piece of its cataloged data
+    async fn record(
+        self,
+        state: &AppState,
+        required: Access,
+        actor: &str,
+        token: Option<&AccessToken>,
+        rejection: &TokenRejection,
+    ) {
+        let accesses = match self {
+            Self::Data(uuid) => vec![required.access(actor, uuid)],
+            Self::Workflow(workflow_id) => state
+                .inner
+                .catalog
+                .by_workflow(workflow_id)
+                .into_iter()
+                .map(|data_ref| {
+                    required
+                        .access(actor, data_ref.uuid)
+                        .with_workflow(workflow_id)
+                })
+                .collect(),
+        };
+        for access in accesses {
+            let access = match token {
+                Some(token) => access.with_token(token),
+                None => access,
+            };
+            access.record(state, Err(rejection.message.clone())).await;
+        }
+    }
+}
+
+/// Permission a request needs on its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Delete,
+}
+
+impl Access {
+    fn access(self, actor: &str, uuid: Uuid) -> DataAccess {
+        match self {
+            Self::Read => DataAccess::read(actor, uuid),
+            Self::Write => DataAccess::write(actor, uuid),
+            Self::Delete => DataAccess::delete(actor, uuid),
+        }
+    }
+
+    fn permissions(self) -> Permissions {
+        match self {
+            Self::Read => Permissions::read_only(),
+            Self::Write => Permissions {
+                write: true,
+                ..Permissions::none()
+            },
+            Self::Delete => Permissions {
+                delete: true,
+                ..Permissions::none()
+            },
+        }
+    }
+}
+
+/// Route of the data itself, the only one whose `DELETE` deletes data
+const DATA_ROUTE: &str = "/api/data/{uuid}";
+
+/// Permission a request needs: `GET`, `HEAD`, and deriving only read the
+/// data, `DELETE` on the data itself deletes it, and anything else,
+/// including removing pins and releasing leases, writes
+fn required_access(parts: &Parts) -> Access {
+    let route = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map_or("", MatchedPath::as_str);
+    match parts.method {
+        Method::GET | Method::HEAD => Access::Read,
+        _ if route.ends_with("/derive") => Access::Read,
+        Method::DELETE if route == DATA_ROUTE => Access::Delete,
+        _ => Access::Write,
+    }
+}
+
+/// Parse a UUID path parameter
+fn path_uuid(param: &str) -> Result<Uuid, TokenRejection> {
+    param
+        .parse()
+        .map_err(|e| TokenRejection::new(StatusCode::BAD_REQUEST, "INVALID_PATH", e))
+}
+
+/// Refusal of a request to a data endpoint
+#[derive(Debug, Clone)]
+pub struct TokenRejection {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl TokenRejection {
+    fn new(status: StatusCode, code: &'static str, message: impl ToString) -> Self {
+        Self {
+            status,
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<TokenError> for TokenRejection {
+    fn from(e: TokenError) -> Self {
+        let (status, code) = match &e {
+            TokenError::InsufficientPermissions { .. } => {
+                (StatusCode::FORBIDDEN, "INSUFFICIENT_PERMISSIONS")
+            }
+            TokenError::OutOfScope(_) => (StatusCode::FORBIDDEN, "OUT_OF_SCOPE"),
+            TokenError::Expired => (StatusCode::UNAUTHORIZED, "TOKEN_EXPIRED"),
+            TokenError::Revoked => (StatusCode::UNAUTHORIZED, "TOKEN_REVOKED"),
+            _ => (StatusCode::UNAUTHORIZED, "INVALID_TOKEN"),
+        };
+        Self::new(status, code, e)
+    }
+}
+
+impl IntoResponse for TokenRejection {
+    fn into_response(self) -> Response {
+        let body = ApiResponse::<()>::error(self.code, &self.message);
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Encode a token for an `Authorization: Bearer` header
+pub fn encode_token(token: &AccessToken) -> String {
+    let json = serde_json::to_vec(token).expect("tokens serialize");
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+}
+
+/// Decode the token of an `Authorization: Bearer` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<AccessToken> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Bearer ")?.trim();
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::Request,
+        routing::{delete, get, post},
+        Router,
+    };
+    use chrono::Duration;
+    use tower::ServiceExt;
+
+    use swarmx_dataref::{DataRef, Holder, TokenManager};
+    use swarmx_events::{AuditAction, AuditFilter};
+
+    use super::*;
+    use crate::{
+        acquire_lease, derive_data, pin_data, pin_workflow_data, release_lease, unpin_data,
+    };
+
+    async fn probe(_token: DataToken) -> StatusCode {
+        StatusCode::NO_CONTENT
+    }
+
+    /// Send a request for `uuid`, answering the status and error code
+    async fn send(
+        app: &Router,
+        method: Method,
+        uuid: Uuid,
+        authorization: Option<String>,
+    ) -> (StatusCode, Option<String>) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("/api/data/{uuid}"));
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let code = serde_json::from_slice::<ApiResponse<()>>(&body)
+            .ok()
+            .and_then(|response| response.error)
+            .map(|error| error.code);
+        (status, code)
+    }
+
+    fn bearer(token: &AccessToken) -> Option<String> {
+        Some(format!("Bearer {}", encode_token(token)))
+    }
+
+    #[tokio::test]
+    async fn test_data_token_enforcement() {
+        let tokens =
+            TokenManager::new("swarmx-api".to_string(), "k1", b"data-token-secret").unwrap();
+        let state = AppState::new().with_tokens(tokens);
+        let workflow_id = Uuid::new_v4();
+        let location = state.inner.store.location().to_string();
+        let data_ref = state
+            .inner
+            .store
+            .put(DataRef::bytes(location, workflow_id, b"data"), b"data")
+            .unwrap();
+        let tokens = state.inner.tokens.clone().unwrap();
+        let app = Router::new()
+            .route("/api/data/{uuid}", get(probe).patch(probe).delete(probe))
+            .with_state(state);
+        let (uuid, unknown) = (data_ref.uuid, Uuid::new_v4());
+        let ttl = Duration::minutes(5);
+
+        let (status, code) = send(&app, Method::GET, uuid, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(code.as_deref(), Some("MISSING_TOKEN"));
+        let (status, code) = send(&app, Method::GET, uuid, Some("Bearer !!".into())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(code.as_deref(), Some("INVALID_TOKEN"));
+
+        let read = tokens
+            .issue_token(uuid, Permissions::read_only(), ttl)
+            .unwrap();
+        assert_eq!(
+            send(&app, Method::GET, uuid, bearer(&read)).await.0,
+            StatusCode::NO_CONTENT
+        );
+        let (status, code) = send(&app, Method::DELETE, uuid, bearer(&read)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(code.as_deref(), Some("INSUFFICIENT_PERMISSIONS"));
+
+        let all = Permissions {
+            read: true,
+            write: true,
+            delete: true,
+        };
+        let other = tokens.issue_token(unknown, all, ttl).unwrap();
+        let (status, code) = send(&app, Method::GET, uuid, bearer(&other)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(code.as_deref(), Some("OUT_OF_SCOPE"));
+        // A token for data the server does not hold yet may write it
+        assert_eq!(
+            send(&app, Method::PATCH, unknown, bearer(&other)).await.0,
+            StatusCode::NO_CONTENT
+        );
+
+        let workflow = tokens
+            .issue_scoped_token(TokenScope::Workflow { workflow_id }, all, ttl)
+            .unwrap();
+        assert_eq!(
+            send(&app, Method::DELETE, uuid, bearer(&workflow)).await.0,
+            StatusCode::NO_CONTENT
+        );
+        let (status, code) = send(&app, Method::GET, unknown, bearer(&workflow)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(code.as_deref(), Some("OUT_OF_SCOPE"));
+
+        tokens.revoke_token(&workflow).unwrap();
+        let (status, code) = send(&app, Method::GET, uuid, bearer(&workflow)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(code.as_deref(), Some("TOKEN_REVOKED"));
+    }
+
+    #[tokio::test]
+    async fn test_derive_pin_and_lease_require_tokens() {
+        let tokens =
+            TokenManager::new("swarmx-api".to_string(), "k1", b"data-token-secret").unwrap();
+        let state = AppState::new().with_tokens(tokens);
+        let workflow_id = Uuid::new_v4();
+        let location = state.inner.store.location().to_string();
+        let data_ref = state
+            .inner
+            .store
+            .put(DataRef::bytes(location, workflow_id, b"data"), b"data")
+            .unwrap();
+        state.inner.catalog.register(&data_ref).unwrap();
+        state
+            .inner
+            .data
+            .write()
+            .await
+            .register(data_ref.clone(), Holder::Workflow { workflow_id });
+        let tokens = state.inner.tokens.clone().unwrap();
+        let app = Router::new()
+            .route("/api/data/{uuid}/derive", post(derive_data))
+            .route("/api/data/{uuid}/pin", post(pin_data).delete(unpin_data))
+            .route("/api/data/{uuid}/leases", post(acquire_lease))
+            .route("/api/data/{uuid}/leases/{lease_id}", delete(release_lease))
+            .route("/api/workflows/{id}/data/pin", post(pin_workflow_data))
+            .with_state(state.clone());
+        let uuid = data_ref.uuid;
+        let ttl = Duration::minutes(5);
+        let call = |method: Method, uri: String, token: Option<&AccessToken>| {
+            let mut request = Request::builder().method(method).uri(uri);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, bearer(token).unwrap());
+            }
+            let body = Body::from(r#"{"holder":"server-1"}"#);
+            let request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).ok(),
+                )
+            }
+        };
+
+        let (status, _) = call(Method::POST, format!("/api/data/{uuid}/derive"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Deriving only reads its source; pins and leases write
+        let read = tokens
+            .issue_token(uuid, Permissions::read_only(), ttl)
+            .unwrap();
+        let (status, _) = call(
+            Method::POST,
+            format!("/api/data/{uuid}/derive"),
+            Some(&read),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, body) = call(Method::POST, format!("/api/data/{uuid}/pin"), Some(&read)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body.unwrap()["error"]["code"], "INSUFFICIENT_PERMISSIONS");
+        let (status, _) = call(
+            Method::POST,
+            format!("/api/data/{uuid}/leases"),
+            Some(&read),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let write = tokens
+            .issue_token(uuid, Permissions::read_write(), ttl)
+            .unwrap();
+        let (status, _) = call(Method::POST, format!("/api/data/{uuid}/pin"), Some(&write)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(
+            Method::DELETE,
+            format!("/api/data/{uuid}/pin"),
+            Some(&write),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = call(
+            Method::POST,
+            format!("/api/data/{uuid}/leases"),
+            Some(&write),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let lease_id = body.unwrap()["data"]["lease_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let release = format!("/api/data/{uuid}/leases/{lease_id}");
+        let (status, _) = call(Method::DELETE, release.clone(), Some(&read)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(Method::DELETE, release, Some(&write)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // Only a token for the whole workflow pins all of its data
+        let pin_workflow = format!("/api/workflows/{workflow_id}/data/pin");
+        let (status, body) = call(Method::POST, pin_workflow.clone(), Some(&write)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body.unwrap()["error"]["code"], "OUT_OF_SCOPE");
+        let workflow = tokens
+            .issue_scoped_token(
+                TokenScope::Workflow { workflow_id },
+                Permissions::read_write(),
+                ttl,
+            )
+            .unwrap();
+        let (status, _) = call(Method::POST, pin_workflow, Some(&workflow)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let envelopes = state
+            .inner
+            .events
+            .wal()
+            .read_filtered(AuditFilter::new().data(uuid).to_event_filter())
+            .await
+            .unwrap();
+        let permissions: Vec<_> = AuditFilter::new()
+            .data(uuid)
+            .apply(&envelopes)
+            .into_iter()
+            .map(|record| match record.action {
+                AuditAction::DataAccessed {
+                    permission,
+                    granted,
+                    ..
+                } => (permission, granted),
+                action => panic!("unexpected audit action {action:?}"),
+            })
+            .collect();
+        let expected = [
+            ("read", false),
+            ("read", true),
+            ("write", false),
+            ("write", false),
+            ("write", true),
+            ("write", true),
+            ("write", true),
+            ("write", false),
+            ("write", true),
+            ("write", false),
+            ("write", true),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(permission, granted)| (permission.to_string(), granted))
+            .collect();
+        assert_eq!(permissions, expected);
+    }
+}
@@ -2,27 +2,46 @@
 //!
 //! Servers send callback messages to notify the client about:
 //! - Task progress updates
+//! - Output streamed before the task completes
 //! - Task completion with outputs
 //! - Task failure with error details
+//! - Task cancellation
 
-use axum::{extract::State, http::StatusCode, Json};
+use std::time::Duration;
 
-use crate::AppState;
-use swarmx_protocol::CallbackMessage;
+use axum::{extract::State, http::StatusCode};
+
+use uuid::Uuid;
+
+use crate::{callback_trace, cancel_tasks, open_gates, AppState, WireBody};
+use swarmx_core::{
+    FailurePolicy, NodeState, SharedWorkflowContext, TransitionOutcome, WorkflowState,
+};
+use swarmx_events::{AppendEntry, Event, TraceContext};
+use swarmx_protocol::{CallbackMessage, TaskCancelRequest, TaskOutput};
+
+/// How often held progress reports are checked
+const PROGRESS_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
 
 /// Handle callback from server
 ///
 /// This endpoint receives callbacks from SwarmX servers when:
 /// - A task makes progress
+/// - A task streams a chunk of output
 /// - A task completes successfully
 /// - A task fails
+/// - A task stops after being cancelled
 ///
 /// The handler updates the execution state and triggers downstream
-/// node scheduling when a node completes.
+/// node scheduling when a node completes. Events it records carry the
+/// trace context the server reported, so they join the execution's trace.
+/// Messages may be sent as JSON, MessagePack, or CBOR.
 pub async fn handle_callback(
     State(state): State<AppState>,
-    Json(message): Json<CallbackMessage>,
+    WireBody(message): WireBody<CallbackMessage>,
 ) -> StatusCode {
+    let trace = callback_trace(message.trace());
+    let trace_id = trace.as_ref().map(|t| t.trace_id.as_str());
     match &message {
         CallbackMessage::Progress {
             task_id,
@@ -32,11 +51,29 @@ pub async fn handle_callback(
         } => {
             tracing::info!(
                 task_id = %task_id,
+                trace_id = ?trace_id,
                 progress = %progress,
                 message = ?msg,
                 "Task progress update"
             );
-            handle_progress(state, task_id, *progress, msg.clone()).await
+            handle_progress(state, task_id, *progress, msg.clone(), trace).await
+        }
+        CallbackMessage::PartialOutput {
+            task_id,
+            output,
+            sequence,
+            chunk,
+            ..
+        } => {
+            tracing::debug!(
+                task_id = %task_id,
+                trace_id = ?trace_id,
+                output = %output,
+                sequence = %sequence,
+                bytes = chunk.len(),
+                "Task output chunk"
+            );
+            handle_partial_output(state, task_id, output, *sequence, chunk.clone(), trace).await
         }
         CallbackMessage::Complete {
             task_id,
@@ -46,11 +83,12 @@ pub async fn handle_callback(
         } => {
             tracing::info!(
                 task_id = %task_id,
+                trace_id = ?trace_id,
                 outputs = outputs.len(),
                 duration_ms = %duration_ms,
                 "Task completed"
             );
-            handle_complete(state, task_id, outputs, *duration_ms).await
+            handle_complete(state, task_id, outputs, *duration_ms, trace).await
         }
         CallbackMessage::Failed {
             task_id,
@@ -60,43 +98,423 @@ pub async fn handle_callback(
         } => {
             tracing::error!(
                 task_id = %task_id,
+                trace_id = ?trace_id,
                 error = %error,
                 error_code = ?error_code,
                 "Task failed"
             );
-            handle_failed(state, task_id, error, error_code.clone()).await
+            handle_failed(state, task_id, error, error_code.clone(), trace).await
+        }
+        CallbackMessage::Cancelled {
+            task_id,
+            reason,
+            forced,
+            ..
+        } => {
+            tracing::info!(
+                task_id = %task_id,
+                trace_id = ?trace_id,
+                reason = ?reason,
+                forced = %forced,
+                "Task cancelled"
+            );
+            handle_cancelled(state, task_id, reason.clone(), *forced, trace).await
         }
     }
 }
 
 /// Handle task progress update
+///
+/// Reports are published through the coalescing publisher, which holds
+/// back reports arriving faster than its interval. Reports for a node that
+/// is no longer running are late and dropped.
 async fn handle_progress(
-    _state: AppState,
-    _task_id: &uuid::Uuid,
-    _progress: f64,
-    _message: Option<String>,
+    state: AppState,
+    task_id: &uuid::Uuid,
+    progress: f64,
+    message: Option<String>,
+    trace: Option<TraceContext>,
 ) -> StatusCode {
-    todo!("Implement progress handling: update node state, emit event")
+    if !(0.0..=1.0).contains(&progress) {
+        return StatusCode::BAD_REQUEST;
+    }
+    let node = {
+        let executions = state.inner.executions.read().await;
+        executions.find_task(task_id).map(|(execution, node_id)| {
+            (
+                execution.workflow_id,
+                execution.context.node_state(&node_id),
+                node_id,
+            )
+        })
+    };
+    let Some((workflow_id, node_state, node_id)) = node else {
+        tracing::warn!(task_id = %task_id, "Progress of an unknown task");
+        return StatusCode::NOT_FOUND;
+    };
+    if node_state != Some(NodeState::Running) {
+        return StatusCode::OK;
+    }
+
+    let event = Event::NodeProgress {
+        workflow_id,
+        node_id,
+        progress,
+        message,
+        timestamp: chrono::Utc::now(),
+    };
+    let entry = AppendEntry::new(event).with_trace(trace);
+    if let Err(e) = state.inner.progress.publish_entry(entry).await {
+        tracing::warn!("Failed to publish task progress: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    StatusCode::OK
+}
+
+/// Handle a chunk of streamed output
+///
+/// Chunks are published as `node_output_chunk` events in sequence order:
+/// a chunk arriving early is held until the chunks before it arrive, and a
+/// repeated chunk is dropped. A chunk too far ahead to be held is refused
+/// with `400`.
+async fn handle_partial_output(
+    state: AppState,
+    task_id: &uuid::Uuid,
+    output: &str,
+    sequence: u64,
+    chunk: String,
+    trace: Option<TraceContext>,
+) -> StatusCode {
+    let node = {
+        let executions = state.inner.executions.read().await;
+        executions
+            .find_task(task_id)
+            .map(|(execution, node_id)| (execution.workflow_id, node_id))
+    };
+    let Some((workflow_id, node_id)) = node else {
+        tracing::warn!(task_id = %task_id, "Output chunk for an unknown task");
+        return StatusCode::NOT_FOUND;
+    };
+
+    let pushed = state
+        .inner
+        .partial_outputs
+        .write()
+        .await
+        .push(*task_id, output, sequence, chunk);
+    let chunks = match pushed {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            tracing::warn!(task_id = %task_id, output = %output, "Refused output chunk: {e}");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    for (sequence, chunk) in chunks {
+        let event = Event::NodeOutputChunk {
+            workflow_id,
+            node_id,
+            output: output.to_string(),
+            sequence,
+            chunk,
+            timestamp: chrono::Utc::now(),
+        };
+        let entry = AppendEntry::new(event).with_trace(trace.clone());
+        if let Err(e) = state.inner.progress.publish_entry(entry).await {
+            tracing::warn!("Failed to publish output chunk: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    StatusCode::OK
 }
 
 /// Handle task completion
+///
+/// The node is done and the task's streamed output is forgotten. Outputs
+/// stored as data are recorded by reference in the `node_completed` event.
+/// Approval gates waiting on the node open, and the workflow finishes with
+/// its last node. A repeated callback for the same task changes nothing.
 async fn handle_complete(
-    _state: AppState,
-    _task_id: &uuid::Uuid,
-    _outputs: &[swarmx_protocol::TaskOutput],
-    _duration_ms: u64,
+    state: AppState,
+    task_id: &uuid::Uuid,
+    outputs: &[TaskOutput],
+    duration_ms: u64,
+    trace: Option<TraceContext>,
 ) -> StatusCode {
-    todo!("Implement completion handling: update node state, store outputs, schedule downstream nodes")
+    let node = {
+        let executions = state.inner.executions.read().await;
+        executions.find_task(task_id).map(|(execution, node_id)| {
+            (
+                execution.execution_id,
+                execution.workflow_id,
+                execution.context.clone(),
+                execution.dag.clone(),
+                node_id,
+            )
+        })
+    };
+    let Some((execution_id, workflow_id, context, dag, node_id)) = node else {
+        tracing::warn!(task_id = %task_id, "Completion of an unknown task");
+        return StatusCode::NOT_FOUND;
+    };
+    state.inner.partial_outputs.write().await.finish(*task_id);
+
+    let was_terminal = context.read(|ctx| ctx.state.is_terminal());
+    match context.apply_transition(node_id, NodeState::Done, Some(*task_id), None) {
+        Ok(TransitionOutcome::Applied(_)) => {}
+        Ok(TransitionOutcome::AlreadyApplied(_)) => return StatusCode::OK,
+        Err(e) => {
+            tracing::warn!(task_id = %task_id, node_id = %node_id, "Cannot complete node: {e}");
+            return StatusCode::CONFLICT;
+        }
+    }
+    open_gates(execution_id, &context, &dag);
+
+    let now = chrono::Utc::now();
+    let output_refs = outputs
+        .iter()
+        .filter_map(|output| match output {
+            TaskOutput::Reference { data_ref, .. } => Some(data_ref.uuid),
+            TaskOutput::Inline { .. } => None,
+        })
+        .collect();
+    let mut events = vec![Event::NodeCompleted {
+        workflow_id,
+        node_id,
+        output_refs,
+        duration_ms,
+        timestamp: now,
+    }];
+    events.extend(workflow_finished(
+        &context,
+        workflow_id,
+        was_terminal,
+        || "nodes failed".to_string(),
+    ));
+
+    for event in events {
+        let entry = AppendEntry::new(event).with_trace(trace.clone());
+        if let Err(e) = state.inner.progress.publish_entry(entry).await {
+            tracing::warn!("Failed to record node completion: {e}");
+        }
+    }
+    StatusCode::OK
+}
+
+/// Finish a workflow whose last node just finished
+///
+/// Returns the event recording the end, unless the workflow had already
+/// ended, e.g. when cancelled, or has nodes left to run.
+fn workflow_finished(
+    context: &SharedWorkflowContext,
+    workflow_id: Uuid,
+    was_terminal: bool,
+    error: impl FnOnce() -> String,
+) -> Option<Event> {
+    let now = chrono::Utc::now();
+    match context.finalize().filter(|_| !was_terminal)? {
+        WorkflowState::Failed => Some(Event::WorkflowFailed {
+            workflow_id,
+            error: error(),
+            timestamp: now,
+        }),
+        WorkflowState::Completed | WorkflowState::CompletedWithErrors => {
+            let started_at = context.read(|ctx| ctx.started_at);
+            Some(Event::WorkflowCompleted {
+                workflow_id,
+                timestamp: now,
+                duration_ms: (now - started_at).num_milliseconds().max(0) as u64,
+            })
+        }
+        _ => None,
+    }
 }
 
 /// Handle task failure
+///
+/// A node with retries left moves on to retrying at once, so its
+/// `node_retrying` event reports no delay. Otherwise the workflow's failure
+/// policy is applied; under fail-fast the servers still running other nodes
+/// are asked to stop them. A repeated callback for the same task changes
+/// nothing.
 async fn handle_failed(
-    _state: AppState,
-    _task_id: &uuid::Uuid,
-    _error: &str,
-    _error_code: Option<String>,
+    state: AppState,
+    task_id: &uuid::Uuid,
+    error: &str,
+    error_code: Option<String>,
+    trace: Option<TraceContext>,
 ) -> StatusCode {
-    todo!("Implement failure handling: update node state, apply retry policy, emit event")
+    let node = {
+        let executions = state.inner.executions.read().await;
+        executions.find_task(task_id).map(|(execution, node_id)| {
+            (
+                execution.workflow_id,
+                execution.context.clone(),
+                execution.dag.clone(),
+                node_id,
+            )
+        })
+    };
+    let Some((workflow_id, context, dag, node_id)) = node else {
+        tracing::warn!(task_id = %task_id, "Failure of an unknown task");
+        return StatusCode::NOT_FOUND;
+    };
+    state.inner.partial_outputs.write().await.finish(*task_id);
+
+    let error = match error_code {
+        Some(code) => format!("{code}: {error}"),
+        None => error.to_string(),
+    };
+    match context.apply_transition(
+        node_id,
+        NodeState::Failed,
+        Some(*task_id),
+        Some(error.clone()),
+    ) {
+        Ok(TransitionOutcome::Applied(_)) => {}
+        Ok(TransitionOutcome::AlreadyApplied(_)) => return StatusCode::OK,
+        Err(e) => {
+            tracing::warn!(task_id = %task_id, node_id = %node_id, "Cannot fail node: {e}");
+            return StatusCode::CONFLICT;
+        }
+    }
+
+    let now = chrono::Utc::now();
+    let (retry_count, retry) = context
+        .update(|ctx| {
+            let node = ctx.get_node_mut(&node_id)?;
+            let failed_retries = node.retry_count;
+            let retry = node.can_retry()
+                && node
+                    .transition_with_reason(NodeState::Retrying, Some("retrying".to_string()))
+                    .is_ok();
+            Some((failed_retries, retry.then_some(node.retry_count)))
+        })
+        .unwrap_or_default();
+    let mut events = vec![Event::NodeFailed {
+        workflow_id,
+        node_id,
+        error: error.clone(),
+        retry_count,
+        timestamp: now,
+    }];
+
+    if let Some(retry_count) = retry {
+        events.push(Event::NodeRetrying {
+            workflow_id,
+            node_id,
+            retry_count,
+            delay_ms: 0,
+            timestamp: now,
+        });
+    } else {
+        let was_terminal = context.read(|ctx| ctx.state.is_terminal());
+        let reason = format!("node {node_id} failed");
+        events.extend(
+            context
+                .handle_node_failure(node_id, &dag)
+                .into_iter()
+                .map(|cancelled| Event::NodeCancelled {
+                    workflow_id,
+                    node_id: cancelled,
+                    reason: Some(reason.clone()),
+                    timestamp: now,
+                }),
+        );
+        if context.read(|ctx| ctx.failure_policy) == FailurePolicy::FailFast {
+            let running = context.read(|ctx| ctx.running_tasks());
+            let request = TaskCancelRequest::best_effort(Some(format!("fail-fast: {reason}")));
+            cancel_tasks(&state.inner.cancels, running, request);
+        }
+        events.extend(workflow_finished(
+            &context,
+            workflow_id,
+            was_terminal,
+            || error,
+        ));
+    }
+
+    for event in events {
+        let entry = AppendEntry::new(event).with_trace(trace.clone());
+        if let Err(e) = state.inner.progress.publish_entry(entry).await {
+            tracing::warn!("Failed to record node failure: {e}");
+        }
+    }
+    StatusCode::OK
+}
+
+/// Handle task cancellation
+///
+/// The node is cancelled without retrying, along with the nodes waiting
+/// on it. A repeated callback for the same task changes nothing.
+async fn handle_cancelled(
+    state: AppState,
+    task_id: &uuid::Uuid,
+    reason: Option<String>,
+    forced: bool,
+    trace: Option<TraceContext>,
+) -> StatusCode {
+    let node = {
+        let executions = state.inner.executions.read().await;
+        executions.find_task(task_id).map(|(execution, node_id)| {
+            (
+                execution.workflow_id,
+                execution.context.clone(),
+                execution.dag.clone(),
+                node_id,
+            )
+        })
+    };
+    let Some((workflow_id, context, dag, node_id)) = node else {
+        tracing::warn!(task_id = %task_id, "Cancellation of an unknown task");
+        return StatusCode::NOT_FOUND;
+    };
+    state.inner.partial_outputs.write().await.finish(*task_id);
+
+    let reason = reason.unwrap_or_else(|| {
+        let how = if forced { "killed" } else { "stopped" };
+        format!("task {how} on request")
+    });
+    match context.apply_transition(
+        node_id,
+        NodeState::Cancelled,
+        Some(*task_id),
+        Some(reason.clone()),
+    ) {
+        Ok(TransitionOutcome::Applied(_)) => {}
+        Ok(TransitionOutcome::AlreadyApplied(_)) => return StatusCode::OK,
+        Err(e) => {
+            tracing::warn!(task_id = %task_id, node_id = %node_id, "Cannot cancel node: {e}");
+            return StatusCode::CONFLICT;
+        }
+    }
+    context.cancel_downstream(node_id, &dag);
+
+    let event = Event::NodeCancelled {
+        workflow_id,
+        node_id,
+        reason: Some(reason),
+        timestamp: chrono::Utc::now(),
+    };
+    let entry = AppendEntry::new(event).with_trace(trace);
+    if let Err(e) = state.inner.progress.publish_entry(entry).await {
+        tracing::warn!("Failed to record node cancellation: {e}");
+    }
+    StatusCode::OK
+}
+
+/// Periodically publish coalesced progress reports of quiet nodes
+///
+/// Progress callbacks are published through `AppStateInner::progress`,
+/// which holds back reports arriving faster than its interval; this
+/// releases the last one once the interval has passed.
+pub async fn progress_flusher(state: AppState) {
+    let mut interval = tokio::time::interval(PROGRESS_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.inner.progress.flush_due().await {
+            tracing::warn!("Failed to publish coalesced progress: {e}");
+        }
+    }
 }
 
 /// Callback acknowledgment response
@@ -105,3 +523,242 @@ pub struct CallbackAck {
     pub received: bool,
     pub task_id: uuid::Uuid,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use swarmx_core::{NodeBuilder, SharedWorkflowContext, WorkflowContext, WorkflowDag};
+    use swarmx_events::{EventFilter, EventSubscription};
+
+    use super::*;
+    use crate::ExecutionState;
+
+    /// Start an execution of one node running `task_id`
+    async fn running_task(state: &AppState, task_id: Uuid) -> (Uuid, SharedWorkflowContext) {
+        let mut dag = WorkflowDag::new();
+        let node = NodeBuilder::new("llm.generate", "Generate").build();
+        let node_id = node.id;
+        dag.add_node(node);
+
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        ctx.add_node(node_id);
+        let node = ctx.get_node_mut(&node_id).unwrap();
+        for to in [NodeState::Scheduled, NodeState::Running] {
+            node.transition(to).unwrap();
+        }
+        node.assign("http://gpu-1:9000".to_string(), Some(task_id));
+
+        let execution_id = ctx.execution_id;
+        let context = SharedWorkflowContext::new(ctx);
+        state.inner.executions.write().await.executions.insert(
+            execution_id,
+            ExecutionState {
+                execution_id,
+                workflow_id: dag.workflow_id(),
+                context: context.clone(),
+                dag: Arc::new(dag),
+                started_at: chrono::Utc::now(),
+            },
+        );
+        (node_id, context)
+    }
+
+    async fn callback(state: &AppState, message: CallbackMessage) -> StatusCode {
+        let app = Router::new()
+            .route("/api/callback", post(handle_callback))
+            .with_state(state.clone());
+        let request = Request::post("/api/callback")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&message).unwrap()))
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    async fn next_event(subscription: &mut EventSubscription) -> Event {
+        tokio::time::timeout(Duration::from_secs(1), subscription.recv())
+            .await
+            .expect("no event published")
+            .unwrap()
+            .event
+    }
+
+    #[tokio::test]
+    async fn test_partial_output_is_published_until_completion() {
+        let state = AppState::new();
+        let task_id = Uuid::new_v4();
+        let (node_id, context) = running_task(&state, task_id).await;
+        let mut events = state.inner.events.subscribe(EventFilter::new());
+
+        let chunk = |sequence, text: &str| {
+            CallbackMessage::partial_output(task_id, "text", sequence, text.to_string())
+        };
+        assert_eq!(callback(&state, chunk(1, "lo")).await, StatusCode::OK);
+        assert_eq!(callback(&state, chunk(0, "Hel")).await, StatusCode::OK);
+        assert_eq!(callback(&state, chunk(0, "Hel")).await, StatusCode::OK);
+        assert_eq!(callback(&state, chunk(2, "!")).await, StatusCode::OK);
+
+        for (expected_sequence, expected_chunk) in [(0, "Hel"), (1, "lo"), (2, "!")] {
+            match next_event(&mut events).await {
+                Event::NodeOutputChunk {
+                    node_id: id,
+                    sequence,
+                    chunk,
+                    ..
+                } => {
+                    assert_eq!(id, node_id);
+                    assert_eq!(sequence, expected_sequence);
+                    assert_eq!(chunk, expected_chunk);
+                }
+                other => panic!("expected an output chunk, got {other:?}"),
+            }
+        }
+
+        let unknown = CallbackMessage::partial_output(Uuid::new_v4(), "text", 0, "x".into());
+        assert_eq!(callback(&state, unknown).await, StatusCode::NOT_FOUND);
+        assert_eq!(
+            callback(&state, chunk(u64::MAX, "?")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(callback(&state, chunk(5, "?")).await, StatusCode::OK);
+
+        // Completion finishes the node, the workflow, and the stream
+        let complete = CallbackMessage::complete(task_id, Vec::new(), 12);
+        assert_eq!(callback(&state, complete.clone()).await, StatusCode::OK);
+        assert_eq!(context.node_state(&node_id), Some(NodeState::Done));
+        assert_eq!(state.inner.partial_outputs.read().await.open_streams(), 0);
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::NodeCompleted { node_id: id, duration_ms: 12, .. } if id == node_id
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::WorkflowCompleted { .. }
+        ));
+        assert_eq!(callback(&state, complete).await, StatusCode::OK);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), events.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_task_is_not_retried() {
+        let state = AppState::new();
+        let task_id = Uuid::new_v4();
+        let (node_id, context) = running_task(&state, task_id).await;
+        let mut events = state.inner.events.subscribe(EventFilter::new());
+
+        let cancelled = CallbackMessage::cancelled(task_id, None, true);
+        assert_eq!(callback(&state, cancelled.clone()).await, StatusCode::OK);
+        assert_eq!(context.node_state(&node_id), Some(NodeState::Cancelled));
+        match next_event(&mut events).await {
+            Event::NodeCancelled {
+                node_id: id,
+                reason,
+                ..
+            } => {
+                assert_eq!(id, node_id);
+                assert_eq!(reason.as_deref(), Some("task killed on request"));
+            }
+            other => panic!("expected a node cancellation, got {other:?}"),
+        }
+
+        // A repeated callback is acknowledged without another event
+        assert_eq!(callback(&state, cancelled).await, StatusCode::OK);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), events.recv())
+                .await
+                .is_err()
+        );
+        let node = context.read(|ctx| ctx.get_node(&node_id).cloned()).unwrap();
+        assert_eq!(node.retry_count, 0);
+        assert_eq!(node.state, NodeState::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_failed_task_is_retried() {
+        let state = AppState::new();
+        let task_id = Uuid::new_v4();
+        let (node_id, context) = running_task(&state, task_id).await;
+        let mut events = state.inner.events.subscribe(EventFilter::new());
+
+        let failed = CallbackMessage::failed(task_id, "timed out".into(), Some("TIMEOUT".into()));
+        assert_eq!(callback(&state, failed.clone()).await, StatusCode::OK);
+        assert_eq!(context.node_state(&node_id), Some(NodeState::Retrying));
+        match next_event(&mut events).await {
+            Event::NodeFailed {
+                error, retry_count, ..
+            } => {
+                assert_eq!(error, "TIMEOUT: timed out");
+                assert_eq!(retry_count, 0);
+            }
+            other => panic!("expected a node failure, got {other:?}"),
+        }
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::NodeRetrying {
+                retry_count: 1,
+                delay_ms: 0,
+                ..
+            }
+        ));
+
+        // A repeated callback is acknowledged without another event
+        assert_eq!(callback(&state, failed).await, StatusCode::OK);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), events.recv())
+                .await
+                .is_err()
+        );
+        assert!(!context.read(|ctx| ctx.state.is_terminal()));
+    }
+
+    #[tokio::test]
+    async fn test_progress_precedes_failure() {
+        let state = AppState::new();
+        let task_id = Uuid::new_v4();
+        let (node_id, _) = running_task(&state, task_id).await;
+        let mut events = state.inner.events.subscribe(EventFilter::new());
+
+        let progress = |value| CallbackMessage::progress(task_id, value, None);
+        for value in [0.1, 0.2, 0.3] {
+            assert_eq!(callback(&state, progress(value)).await, StatusCode::OK);
+        }
+        assert_eq!(
+            callback(&state, progress(1.5)).await,
+            StatusCode::BAD_REQUEST
+        );
+        let failed = CallbackMessage::failed(task_id, "timed out".into(), None);
+        assert_eq!(callback(&state, failed).await, StatusCode::OK);
+
+        // The last held report is released just before the failure
+        for expected in [0.1, 0.3] {
+            match next_event(&mut events).await {
+                Event::NodeProgress { progress, .. } => assert_eq!(progress, expected),
+                other => panic!("expected node progress, got {other:?}"),
+            }
+        }
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::NodeFailed { node_id: id, .. } if id == node_id
+        ));
+
+        // Late reports of a node that stopped running are dropped
+        assert_eq!(callback(&state, progress(0.9)).await, StatusCode::OK);
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::NodeRetrying { .. }
+        ));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), events.recv())
+                .await
+                .is_err()
+        );
+    }
+}
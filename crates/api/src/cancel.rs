@@ -0,0 +1,332 @@
+//! Stopping tasks running on servers
+//!
+//! A task is stopped by sending the server running it a
+//! [`TaskCancelRequest`] as `POST /tasks/{id}/cancel`. The node is only
+//! cancelled once the server reports the task stopped with a `cancelled`
+//! callback; the [`TaskCancelResponse`] says whether one follows.
+
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use swarmx_core::RunningTask;
+use swarmx_protocol::{TaskCancelRequest, TaskCancelResponse};
+
+/// Time allowed for a server to answer a cancel request
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP client for asking servers to stop tasks, shared through
+/// [`AppStateInner::cancels`](crate::AppStateInner::cancels)
+pub fn cancel_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(CANCEL_TIMEOUT).build()
+}
+
+/// Ask the server running a task to stop it
+pub async fn send_cancel(
+    client: &reqwest::Client,
+    task: &RunningTask,
+    request: &TaskCancelRequest,
+) -> reqwest::Result<TaskCancelResponse> {
+    let url = format!(
+        "{}/tasks/{}/cancel",
+        task.server.trim_end_matches('/'),
+        task.task_id
+    );
+    client
+        .post(&url)
+        .json(request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// Ask the servers of running tasks to stop them
+///
+/// The requests are sent concurrently in the background, so an
+/// unreachable server delays no one. Failures are logged; a task whose
+/// server could not be reached keeps running until it completes or fails
+/// on its own.
+pub fn cancel_tasks(client: &reqwest::Client, tasks: Vec<RunningTask>, request: TaskCancelRequest) {
+    if tasks.is_empty() {
+        return;
+    }
+    let client = client.clone();
+    tokio::spawn(async move {
+        join_all(tasks.iter().map(|task| async {
+            match send_cancel(&client, task, &request).await {
+                Ok(response) => tracing::info!(
+                    task_id = %task.task_id,
+                    node_id = %task.node_id,
+                    outcome = ?response.outcome,
+                    "Requested task cancellation"
+                ),
+                Err(e) => tracing::warn!(
+                    task_id = %task.task_id,
+                    server = %task.server,
+                    "Failed to cancel task: {e}"
+                ),
+            }
+        }))
+        .await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        body::Body,
+        extract::Path,
+        http::{Request, StatusCode},
+        routing::post,
+        Json, Router,
+    };
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use swarmx_core::WorkflowState;
+    use swarmx_core::{
+        NodeBuilder, NodeState, SharedWorkflowContext, WorkflowContext, WorkflowDag,
+    };
+    use swarmx_events::{Event, EventFilter};
+    use swarmx_protocol::{CallbackMessage, CancelMode, CancelOutcome};
+
+    use super::*;
+    use crate::{cancel_execution, cancel_task, handle_callback, AppState, ExecutionState};
+
+    /// Start a server that records the cancel requests it receives
+    async fn task_server() -> (String, Arc<Mutex<Vec<(Uuid, TaskCancelRequest)>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorder = received.clone();
+        let app = Router::new().route(
+            "/tasks/{id}/cancel",
+            post(
+                move |Path(task_id): Path<Uuid>, Json(request): Json<TaskCancelRequest>| async move {
+                    recorder.lock().unwrap().push((task_id, request));
+                    Json(TaskCancelResponse {
+                        task_id,
+                        outcome: CancelOutcome::Cancelling,
+                    })
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (address, received)
+    }
+
+    /// Wait for a task server to have received `count` cancel requests
+    async fn received_requests(
+        received: &Mutex<Vec<(Uuid, TaskCancelRequest)>>,
+        count: usize,
+    ) -> Vec<(Uuid, TaskCancelRequest)> {
+        let wait = async {
+            loop {
+                let requests = received.lock().unwrap().clone();
+                if requests.len() >= count {
+                    return requests;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("cancel requests not received")
+    }
+
+    #[tokio::test]
+    async fn test_cancel_execution_stops_running_tasks() {
+        let (server, received) = task_server().await;
+        let mut dag = WorkflowDag::new();
+        let run = NodeBuilder::new("llm.generate", "Generate").build();
+        let wait = NodeBuilder::new("http.request", "Send").build();
+        let (run_id, wait_id) = (run.id, wait.id);
+        dag.add_node(run);
+        dag.add_node(wait);
+
+        let task_id = Uuid::new_v4();
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        ctx.add_node(run_id);
+        ctx.add_node(wait_id);
+        let node = ctx.get_node_mut(&run_id).unwrap();
+        node.transition(NodeState::Scheduled).unwrap();
+        node.transition(NodeState::Running).unwrap();
+        node.assign(server, Some(task_id));
+
+        let execution_id = ctx.execution_id;
+        let context = SharedWorkflowContext::new(ctx);
+        let state = AppState::new();
+        state.inner.executions.write().await.executions.insert(
+            execution_id,
+            ExecutionState {
+                execution_id,
+                workflow_id: dag.workflow_id(),
+                context: context.clone(),
+                dag: Arc::new(dag),
+                started_at: chrono::Utc::now(),
+            },
+        );
+        let app = Router::new()
+            .route("/api/executions/{id}/cancel", post(cancel_execution))
+            .route("/api/tasks/{id}/cancel", post(cancel_task))
+            .with_state(state);
+        let post = |uri: String| Request::post(uri).body(Body::empty()).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(post(format!("/api/tasks/{task_id}/cancel")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(r#""outcome":"cancelling""#));
+
+        let cancel = format!("/api/executions/{execution_id}/cancel?force=true&reason=done");
+        let response = app.clone().oneshot(post(cancel.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(context.node_state(&wait_id), Some(NodeState::Cancelled));
+        // Cancelled once its server reports the task stopped
+        assert_eq!(context.node_state(&run_id), Some(NodeState::Running));
+
+        let received = received_requests(&received, 2).await;
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].1.mode, CancelMode::BestEffort);
+        assert_eq!(
+            received[1],
+            (task_id, TaskCancelRequest::force(Some("done".into())))
+        );
+
+        let response = app.oneshot(post(cancel)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_stops_running_tasks() {
+        let (server, received) = task_server().await;
+        let mut dag = WorkflowDag::new();
+        let nodes: Vec<_> = ["Fail", "Run", "Wait"]
+            .iter()
+            .map(|name| NodeBuilder::new("llm.generate", name).build())
+            .collect();
+        let ids: Vec<Uuid> = nodes.iter().map(|n| n.id).collect();
+        for node in nodes {
+            dag.add_node(node);
+        }
+
+        let (failing_task, running_task) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        for id in &ids {
+            ctx.add_node(*id);
+        }
+        for (id, task_id) in [(ids[0], failing_task), (ids[1], running_task)] {
+            let node = ctx.get_node_mut(&id).unwrap();
+            node.max_retries = 0;
+            node.transition(NodeState::Scheduled).unwrap();
+            node.transition(NodeState::Running).unwrap();
+            node.assign(server.clone(), Some(task_id));
+        }
+
+        let execution_id = ctx.execution_id;
+        let context = SharedWorkflowContext::new(ctx);
+        let state = AppState::new();
+        state.inner.executions.write().await.executions.insert(
+            execution_id,
+            ExecutionState {
+                execution_id,
+                workflow_id: dag.workflow_id(),
+                context: context.clone(),
+                dag: Arc::new(dag),
+                started_at: chrono::Utc::now(),
+            },
+        );
+        let mut events = state.inner.events.subscribe(EventFilter::new());
+        let app = Router::new()
+            .route("/api/callback", post(handle_callback))
+            .with_state(state);
+
+        let failed = CallbackMessage::failed(failing_task, "out of memory".to_string(), None);
+        let request = Request::post("/api/callback")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&failed).unwrap()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(context.read(|ctx| ctx.state), WorkflowState::Failed);
+        assert_eq!(context.node_state(&ids[0]), Some(NodeState::Failed));
+        assert_eq!(context.node_state(&ids[2]), Some(NodeState::Cancelled));
+        // Cancelled once its server reports the task stopped
+        assert_eq!(context.node_state(&ids[1]), Some(NodeState::Running));
+        let received = received_requests(&received, 1).await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, running_task);
+        assert_eq!(received[0].1.mode, CancelMode::BestEffort);
+
+        let mut published = Vec::new();
+        while let Ok(Ok(entry)) =
+            tokio::time::timeout(Duration::from_millis(100), events.recv()).await
+        {
+            published.push(entry.event);
+        }
+        assert!(matches!(
+            published.as_slice(),
+            [
+                Event::NodeFailed { node_id, .. },
+                Event::NodeCancelled { node_id: cancelled, .. },
+                Event::WorkflowFailed { .. },
+            ] if *node_id == ids[0] && *cancelled == ids[2]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_servers_do_not_delay_cancellation() {
+        let app = Router::new().route(
+            "/tasks/{id}/cancel",
+            post(std::future::pending::<StatusCode>),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut dag = WorkflowDag::new();
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        for _ in 0..3 {
+            let node = NodeBuilder::new("llm.generate", "Generate").build();
+            ctx.add_node(node.id);
+            let ctx = ctx.get_node_mut(&node.id).unwrap();
+            ctx.transition(NodeState::Scheduled).unwrap();
+            ctx.transition(NodeState::Running).unwrap();
+            ctx.assign(server.clone(), Some(Uuid::new_v4()));
+            dag.add_node(node);
+        }
+
+        let execution_id = ctx.execution_id;
+        let state = AppState::new();
+        state.inner.executions.write().await.executions.insert(
+            execution_id,
+            ExecutionState {
+                execution_id,
+                workflow_id: dag.workflow_id(),
+                context: SharedWorkflowContext::new(ctx),
+                dag: Arc::new(dag),
+                started_at: chrono::Utc::now(),
+            },
+        );
+        let app = Router::new()
+            .route("/api/executions/{id}/cancel", post(cancel_execution))
+            .with_state(state);
+        let request = Request::post(format!("/api/executions/{execution_id}/cancel"))
+            .body(Body::empty())
+            .unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(1), app.oneshot(request))
+            .await
+            .expect("cancellation waited for the servers")
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+}
@@ -0,0 +1,278 @@
+//! Cross-cluster data federation
+//!
+//! With a [`Federation`] configured, the API server shares data with peer
+//! clusters. `POST /api/federation/import` copies data held by a peer into
+//! the local store: it exchanges a federation token at the peer's
+//! `POST /api/federation/token`, then streams the payload from the peer's
+//! `GET /api/federation/data/{uuid}`, presenting the exchanged token as a
+//! bearer token. The copy keeps the data's UUID, is checked against the
+//! peer's checksum, and is recorded with a `data_transferred` event.
+//! `GET /api/federation/clusters` lists the peers. Token exchanges and
+//! federated reads are recorded in the data-access audit trail.
+
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+    Json,
+};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_dataref::{
+    AccessToken, DataRef, DataStoreError, Federation, FederationError, Holder, RemoteCluster,
+    TokenScope,
+};
+use swarmx_events::Event;
+use swarmx_protocol::ApiResponse;
+
+use crate::{
+    access_result, bearer_token, encode_token, serve_data, status_reason, transfer_progress,
+    with_store, AppState, DataAccess, ANONYMOUS_ACTOR, FEDERATION_ACTOR,
+};
+
+/// Time allowed to connect to a peer cluster
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// This cluster and the clusters it shares data with
+#[derive(Debug, Serialize)]
+pub struct FederationInfo {
+    pub cluster: String,
+    pub peers: Vec<RemoteCluster>,
+}
+
+/// List the peer clusters
+pub async fn list_clusters(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<ApiResponse<FederationInfo>>) {
+    let Some(federation) = &state.inner.federation else {
+        return not_configured();
+    };
+    let info = FederationInfo {
+        cluster: federation.cluster().to_string(),
+        peers: federation.peers().cloned().collect(),
+    };
+    (StatusCode::OK, Json(ApiResponse::success(info)))
+}
+
+/// Exchange a peer cluster's federation token for one of this cluster
+pub async fn exchange_token(
+    State(state): State<AppState>,
+    Json(token): Json<AccessToken>,
+) -> (StatusCode, Json<ApiResponse<AccessToken>>) {
+    let Some(federation) = &state.inner.federation else {
+        return not_configured();
+    };
+    let exchanged = federation.exchange(&token);
+    if let TokenScope::Data { data_uuid } = token.scope {
+        let access = DataAccess::read(&token.issued_by, data_uuid);
+        let access = match &exchanged {
+            Ok(exchanged) => access.with_token(exchanged),
+            Err(_) => access.with_token(&token),
+        };
+        let result = exchanged.as_ref().map(|_| ()).map_err(|e| e.to_string());
+        access.record(&state, result).await;
+    }
+    match exchanged {
+        Ok(exchanged) => (StatusCode::OK, Json(ApiResponse::success(exchanged))),
+        Err(e) => {
+            tracing::warn!(cluster = %token.issued_by, "Refused federation token: {e}");
+            (
+                StatusCode::FORBIDDEN,
+                Json(ApiResponse::error("FORBIDDEN", &e.to_string())),
+            )
+        }
+    }
+}
+
+/// Serve local data to a peer cluster holding an exchanged token
+///
+/// Supports the same `Range` header as `GET /api/data/{uuid}`.
+pub async fn get_federated_data(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let federation = state
+        .inner
+        .federation
+        .as_ref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let Some(token) = bearer_token(&headers) else {
+        DataAccess::read(ANONYMOUS_ACTOR, uuid)
+            .record(&state, Err(status_reason(StatusCode::UNAUTHORIZED)))
+            .await;
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    let access = DataAccess::read(FEDERATION_ACTOR, uuid).with_token(&token);
+    let Some(data_ref) = state.inner.store.get_ref(&uuid) else {
+        access
+            .record(&state, Err(status_reason(StatusCode::NOT_FOUND)))
+            .await;
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if let Err(e) = federation.authorize(&token, &data_ref) {
+        tracing::warn!(data_uuid = %uuid, cluster = %token.issued_by, "Refused federated read: {e}");
+        access.record(&state, Err(e.to_string())).await;
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let served = serve_data(&state, uuid, &headers).await;
+    access.record(&state, access_result(&served)).await;
+    served
+}
+
+/// Data to copy from a peer cluster
+#[derive(Debug, Deserialize)]
+pub struct ImportDataRequest {
+    /// Ref of the data, located at the peer's API endpoint
+    pub data_ref: DataRef,
+    /// Local workflow the copy belongs to; it is kept until the workflow
+    /// terminates, then collected
+    #[serde(default)]
+    pub workflow_id: Option<Uuid>,
+}
+
+/// Copy data held by a peer cluster into the local store
+pub async fn import_data(
+    State(state): State<AppState>,
+    Json(request): Json<ImportDataRequest>,
+) -> (StatusCode, Json<ApiResponse<DataRef>>) {
+    let Some(federation) = &state.inner.federation else {
+        return not_configured();
+    };
+    let uuid = request.data_ref.uuid;
+    match import(&state, federation, request).await {
+        Ok(data_ref) => (StatusCode::CREATED, Json(ApiResponse::success(data_ref))),
+        Err(e) => {
+            tracing::warn!(data_uuid = %uuid, "Failed to import federated data: {e}");
+            let (status, code) = match &e {
+                ImportError::Federation(_) => (StatusCode::BAD_REQUEST, "NOT_FEDERATED"),
+                ImportError::Http(_) | ImportError::Refused(_) => {
+                    (StatusCode::BAD_GATEWAY, "PEER_ERROR")
+                }
+                ImportError::Store(DataStoreError::ChecksumMismatch { .. }) => {
+                    (StatusCode::BAD_GATEWAY, "CHECKSUM_MISMATCH")
+                }
+                ImportError::Store(_) => (StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR"),
+            };
+            (status, Json(ApiResponse::error(code, &e.to_string())))
+        }
+    }
+}
+
+/// Fetch federated data from its cluster, store it, and record it
+async fn import(
+    state: &AppState,
+    federation: &Federation,
+    request: ImportDataRequest,
+) -> Result<DataRef, ImportError> {
+    let started = Instant::now();
+    let remote = request.data_ref;
+    let peer = federation
+        .cluster_of(&remote)
+        .ok_or(FederationError::NotFederated(remote.uuid))?;
+    let workflow_id = request.workflow_id.unwrap_or_else(Uuid::nil);
+    let copy = federation.local_copy(&remote, workflow_id)?;
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()?;
+
+    let exchanged: ApiResponse<AccessToken> = client
+        .post(peer.url("federation/token"))
+        .json(&federation.request_token(&remote)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let token = exchanged.data.ok_or_else(|| {
+        ImportError::Refused(exchanged.error.map(|e| e.message).unwrap_or_default())
+    })?;
+    let response = client
+        .get(peer.url(&format!("federation/data/{}", remote.uuid)))
+        .bearer_auth(encode_token(&token))
+        .send()
+        .await?
+        .error_for_status()?;
+    let progress = transfer_progress(state, &copy, "download", response.content_length());
+    let body =
+        tokio_util::io::StreamReader::new(response.bytes_stream().map_err(std::io::Error::other));
+    let data_ref = state
+        .inner
+        .store
+        .put_stream(copy, body, Some(progress))
+        .await?;
+    if let Some(expected) = remote.checksum.clone() {
+        let uuid = data_ref.uuid;
+        if let Err(e) =
+            with_store(state, move |store| store.verify_checksum(&uuid, &expected)).await
+        {
+            if let Err(e) = with_store(state, move |store| store.delete(&uuid)).await {
+                tracing::warn!(data_uuid = %uuid, "Failed to delete corrupt import: {e}");
+            }
+            return Err(e.into());
+        }
+    }
+
+    if let Err(e) = state.inner.catalog.register(&data_ref) {
+        tracing::warn!(data_uuid = %data_ref.uuid, "Failed to catalog data: {e}");
+    }
+    if !workflow_id.is_nil() {
+        state
+            .inner
+            .data
+            .write()
+            .await
+            .register(data_ref.clone(), Holder::Workflow { workflow_id });
+    }
+    let events = [
+        Event::DataTransferred {
+            data_uuid: data_ref.uuid,
+            from_server: remote.location.clone(),
+            to_server: data_ref.location.clone(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            timestamp: chrono::Utc::now(),
+        },
+        Event::DataCreated {
+            data_uuid: data_ref.uuid,
+            workflow_id,
+            location: data_ref.location.clone(),
+            size_bytes: data_ref.size_bytes,
+            tags: data_ref.tag_labels(),
+            timestamp: chrono::Utc::now(),
+        },
+    ];
+    for event in events {
+        if let Err(e) = state.inner.events.publish(event).await {
+            tracing::warn!(data_uuid = %data_ref.uuid, "Failed to record data import: {e}");
+        }
+    }
+    Ok(data_ref)
+}
+
+/// Failure to import federated data
+#[derive(Debug, thiserror::Error)]
+enum ImportError {
+    #[error(transparent)]
+    Federation(#[from] FederationError),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("Peer refused access: {0}")]
+    Refused(String),
+    #[error(transparent)]
+    Store(#[from] DataStoreError),
+}
+
+/// Response for federation endpoints when no federation is configured
+fn not_configured<T>() -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ApiResponse::error(
+            "NOT_CONFIGURED",
+            "Federation is not configured",
+        )),
+    )
+}
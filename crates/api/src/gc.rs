@@ -0,0 +1,204 @@
+//! Cluster-wide garbage collection
+//!
+//! Servers report every UUID they hold with `POST /api/servers/inventory`.
+//! [`data_gc`] runs the [`GcCoordinator`](swarmx_dataref::GcCoordinator)
+//! on its policy's interval, reconciling those inventories, and the API
+//! server's own store, with the data catalog: leaked copies are deleted
+//! from their servers, and missing copies are dropped from the catalog,
+//! recording a `data_deleted` event for data left with no copy at all.
+//! Every pass is recorded with a `data_gc_completed` event.
+//! `GET /api/admin/gc` reports the inventories and the last pass, and
+//! `POST /api/admin/gc` runs a pass right away, as a dry run with
+//! `?dry_run=true`.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+
+use swarmx_dataref::{
+    DataQuery, GcFailure, GcFinding, GcFindingKind, GcReport, GcStatus, ServerInventory,
+};
+use swarmx_protocol::{ApiResponse, InventoryReport};
+
+use crate::{delete_client, delete_copy, with_store, AppState, WireBody};
+
+/// Reconcile the catalog with server inventories periodically
+pub async fn data_gc(state: AppState) {
+    let client = match delete_client() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to create garbage collection HTTP client: {e}");
+            return;
+        }
+    };
+    let period = state.inner.gc.policy().interval;
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        collect_garbage(&state, &client, false).await;
+    }
+}
+
+/// Run one pass, acting on what is due unless it is a dry run, and record
+/// it
+async fn collect_garbage(state: &AppState, client: &reqwest::Client, dry_run: bool) -> GcReport {
+    let started = Instant::now();
+    let gc = &state.inner.gc;
+    let location = state.inner.store.location().to_string();
+    let reported_at = Utc::now();
+    match with_store(state, |store| Ok(store.list())).await {
+        Ok(refs) => gc.record_inventory(ServerInventory {
+            server: location,
+            data: refs.into_iter().map(|data_ref| data_ref.uuid).collect(),
+            reported_at,
+        }),
+        Err(e) => tracing::warn!("Failed to inventory the local data store: {e}"),
+    }
+
+    let catalog = state.inner.catalog.find(&DataQuery::new());
+    let plan = {
+        let registry = state.inner.data.read().await;
+        gc.plan(
+            &catalog,
+            |uuid| registry.get(uuid).is_some(),
+            dry_run,
+            Utc::now(),
+        )
+    };
+    let mut report = plan.report;
+    for (i, finding) in plan.due.into_iter().enumerate() {
+        if report.dry_run {
+            match finding.kind {
+                GcFindingKind::Leaked => report.deleted.push(finding),
+                GcFindingKind::Missing => report.uncataloged.push(finding),
+            }
+            continue;
+        }
+        if let Some(pause) = gc.policy().delete_interval().filter(|_| i > 0) {
+            tokio::time::sleep(pause).await;
+        }
+        let resolved = match finding.kind {
+            GcFindingKind::Leaked => delete_leaked(state, client, &finding).await,
+            GcFindingKind::Missing => uncatalog_missing(state, &finding).await,
+        };
+        match resolved {
+            Ok(()) => match finding.kind {
+                GcFindingKind::Leaked => report.deleted.push(finding),
+                GcFindingKind::Missing => report.uncataloged.push(finding),
+            },
+            Err(error) => {
+                tracing::warn!(
+                    data_uuid = %finding.data_uuid,
+                    server = %finding.server,
+                    "Failed to collect garbage, will retry: {error}"
+                );
+                report.failed.push(GcFailure { finding, error });
+            }
+        }
+    }
+    report.duration_ms = started.elapsed().as_millis() as u64;
+
+    tracing::info!(
+        dry_run = report.dry_run,
+        leaked = report.leaked.len(),
+        missing = report.missing.len(),
+        deleted = report.deleted.len(),
+        uncataloged = report.uncataloged.len(),
+        deferred = report.deferred,
+        "Garbage collection pass completed"
+    );
+    if let Err(e) = state.inner.events.publish(report.completed_event()).await {
+        tracing::warn!("Failed to record garbage collection pass: {e}");
+    }
+    gc.finish(report.clone());
+    report
+}
+
+/// Delete a copy the catalog does not know from its server
+async fn delete_leaked(
+    state: &AppState,
+    client: &reqwest::Client,
+    finding: &GcFinding,
+) -> Result<(), String> {
+    tracing::info!(
+        data_uuid = %finding.data_uuid,
+        server = %finding.server,
+        "Deleting leaked data"
+    );
+    delete_copy(state, client, &finding.server, finding.data_uuid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Drop a copy its server no longer holds from the catalog and registry
+async fn uncatalog_missing(state: &AppState, finding: &GcFinding) -> Result<(), String> {
+    let uuid = finding.data_uuid;
+    let Some(data_ref) = state.inner.catalog.get(&uuid) else {
+        return Ok(());
+    };
+    tracing::warn!(data_uuid = %uuid, server = %finding.server, "Dropping missing data copy");
+    let remaining = state
+        .inner
+        .catalog
+        .remove_location(&uuid, &finding.server)
+        .map_err(|e| e.to_string())?;
+    let mut registry = state.inner.data.write().await;
+    match remaining {
+        Some(data_ref) => {
+            registry.update(data_ref);
+        }
+        None => {
+            registry.remove(&uuid);
+            drop(registry);
+            tracing::error!(data_uuid = %uuid, "Data has no copy left");
+            if let Err(e) = state.inner.events.publish(data_ref.deleted_event()).await {
+                tracing::warn!(data_uuid = %uuid, "Failed to record data deletion: {e}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Record the data a server holds, reported as JSON, MessagePack, or CBOR
+pub async fn report_inventory(
+    State(state): State<AppState>,
+    WireBody(report): WireBody<InventoryReport>,
+) -> StatusCode {
+    state.inner.gc.record_inventory(report.into_inventory());
+    StatusCode::NO_CONTENT
+}
+
+/// Get the server inventories and the outcome of the last pass
+pub async fn get_gc_status(State(state): State<AppState>) -> Json<ApiResponse<GcStatus>> {
+    Json(ApiResponse::success(state.inner.gc.status()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GcParams {
+    /// Only report what the pass would do
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Run a garbage collection pass now
+pub async fn run_gc(
+    State(state): State<AppState>,
+    Query(params): Query<GcParams>,
+) -> (StatusCode, Json<ApiResponse<GcReport>>) {
+    match delete_client() {
+        Ok(client) => {
+            let report = collect_garbage(&state, &client, params.dry_run).await;
+            (StatusCode::OK, Json(ApiResponse::success(report)))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("INTERNAL_ERROR", &e.to_string())),
+        ),
+    }
+}
@@ -2,17 +2,32 @@
 //!
 //! Implements all REST endpoints for workflow management, execution, and data access.
 
+use std::collections::BTreeMap;
+
 use axum::{
+    body::Body,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use futures_util::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::AppState;
+use crate::{
+    access_result, cancel_tasks, send_cancel, status_reason, AcceptFormat, AppState, DataToken,
+    Negotiated, RequestTrace, WireBody,
+};
+use swarmx_core::{NodeState, StateError, WorkflowMetrics};
+use swarmx_dataref::{
+    parse_tags, ByteRange, DataQuery, DataRef, DataStoreError, DataType, Holder,
+    LocationInventory, ProgressFn, ReadLease, DEFAULT_LEASE_SECS,
+};
+use swarmx_events::Event;
 use swarmx_protocol::{
-    ApiResponse, ExecutionSummary, PaginatedResponse, WorkflowDefinition, WorkflowSummary,
+    ApiResponse, DataStoreRequest, ExecutionSummary, PaginatedResponse, ServerRegistration,
+    TaskStatus, WorkflowDefinition, WorkflowSummary,
 };
 
 // ============================================================================
@@ -90,9 +105,13 @@ pub struct ExecutionStarted {
 }
 
 /// Execute a workflow
+///
+/// The execution joins the caller's trace when the request carries a
+/// `traceparent` header; its events and dispatched tasks use that trace ID.
 pub async fn execute_workflow(
     State(_state): State<AppState>,
     Path(_id): Path<Uuid>,
+    RequestTrace(_trace): RequestTrace,
 ) -> (StatusCode, Json<ApiResponse<ExecutionStarted>>) {
     todo!("Implement execute_workflow")
 }
@@ -107,6 +126,7 @@ pub struct WorkflowStatus {
     pub nodes_completed: u32,
     pub nodes_total: u32,
     pub nodes: Vec<NodeStatus>,
+    pub metrics: WorkflowMetrics,
 }
 
 /// Individual node status
@@ -143,89 +163,1019 @@ pub async fn get_execution(
     todo!("Implement get_execution")
 }
 
+/// Cancel query parameters
+#[derive(Debug, Deserialize)]
+pub struct CancelParams {
+    /// Kill running tasks instead of asking them to stop
+    #[serde(default)]
+    pub force: bool,
+    pub reason: Option<String>,
+}
+
+impl CancelParams {
+    /// Get the request to send to the servers running the tasks
+    pub fn request(&self) -> swarmx_protocol::TaskCancelRequest {
+        if self.force {
+            swarmx_protocol::TaskCancelRequest::force(self.reason.clone())
+        } else {
+            swarmx_protocol::TaskCancelRequest::best_effort(self.reason.clone())
+        }
+    }
+}
+
 /// Cancel an execution
+///
+/// Nodes not yet executing are cancelled right away; the servers running
+/// the others are asked to stop them, and those nodes are cancelled when
+/// the servers report back. Answers `409` if the execution already
+/// finished.
 pub async fn cancel_execution(
-    State(_state): State<AppState>,
-    Path(_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<CancelParams>,
 ) -> StatusCode {
-    todo!("Implement cancel_execution")
+    let execution = {
+        let executions = state.inner.executions.read().await;
+        executions
+            .get(&id)
+            .map(|e| (e.workflow_id, e.context.clone()))
+    };
+    let Some((workflow_id, context)) = execution else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let reason = params
+        .reason
+        .clone()
+        .unwrap_or_else(|| "cancelled by user".to_string());
+    let Some(running) = context.cancel(&reason) else {
+        return StatusCode::CONFLICT;
+    };
+    let event = Event::WorkflowCancelled {
+        workflow_id,
+        reason: Some(reason),
+        timestamp: chrono::Utc::now(),
+    };
+    if let Err(e) = state.inner.progress.publish(event).await {
+        tracing::warn!("Failed to record workflow cancellation: {e}");
+    }
+
+    cancel_tasks(&state.inner.cancels, running, params.request());
+    StatusCode::ACCEPTED
+}
+
+/// Released node response
+#[derive(Debug, Serialize)]
+pub struct NodeReleased {
+    pub execution_id: Uuid,
+    pub node_id: Uuid,
+    pub state: NodeState,
+}
+
+/// Release a quarantined node and re-enter it into scheduling
+pub async fn release_node(
+    State(state): State<AppState>,
+    Path((execution_id, node_id)): Path<(Uuid, Uuid)>,
+) -> (StatusCode, Json<ApiResponse<NodeReleased>>) {
+    let context = {
+        let executions = state.inner.executions.read().await;
+        executions.get(&execution_id).map(|e| e.context.clone())
+    };
+    let Some(context) = context else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Execution not found")),
+        );
+    };
+
+    match context.release_quarantined(node_id, Some("released by user".to_string())) {
+        Ok(transition) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(NodeReleased {
+                execution_id,
+                node_id,
+                state: transition.to,
+            })),
+        ),
+        Err(StateError::NodeNotFound(_)) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Node not found")),
+        ),
+        Err(e) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error("NOT_QUARANTINED", &e.to_string())),
+        ),
+    }
 }
 
 // ============================================================================
 // Task Endpoints
 // ============================================================================
 
-/// Get task status
+/// Get task status, in the format the request accepts
+///
+/// The status is that of the node attempt the task ran; outputs are not
+/// kept by the client and are never included.
 pub async fn get_task_status(
-    State(_state): State<AppState>,
-    Path(_id): Path<Uuid>,
-) -> Result<Json<ApiResponse<swarmx_protocol::TaskStatusResponse>>, StatusCode> {
-    todo!("Implement get_task_status")
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    accept: AcceptFormat,
+) -> Result<Negotiated<ApiResponse<swarmx_protocol::TaskStatusResponse>>, StatusCode> {
+    let executions = state.inner.executions.read().await;
+    let (execution, node_id) = executions.find_task(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let attempt = execution
+        .context
+        .read(|ctx| {
+            ctx.get_node(&node_id)?
+                .attempts
+                .iter()
+                .find(|a| a.task_id == Some(id))
+                .cloned()
+        })
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let status = match attempt.outcome {
+        None if attempt.started_at.is_some() => TaskStatus::Running,
+        None => TaskStatus::Accepted,
+        Some(NodeState::Done) => TaskStatus::Complete,
+        Some(NodeState::Cancelled) => TaskStatus::Cancelled,
+        Some(_) => TaskStatus::Failed,
+    };
+    let response = swarmx_protocol::TaskStatusResponse {
+        task_id: id,
+        status,
+        progress: (status == TaskStatus::Complete).then_some(1.0),
+        outputs: None,
+        error: attempt.error,
+        started_at: attempt.started_at,
+        completed_at: attempt.completed_at,
+    };
+    Ok(Negotiated::new(accept, ApiResponse::success(response)))
 }
 
 /// Cancel a task
+///
+/// Sends the server running the task a cancel request and answers with the
+/// server's response. A task that is no longer running is reported as
+/// already finished without contacting its server. Answers `502` if the
+/// server cannot be reached.
 pub async fn cancel_task(
-    State(_state): State<AppState>,
-    Path(_id): Path<Uuid>,
-) -> StatusCode {
-    todo!("Implement cancel_task")
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<CancelParams>,
+) -> Result<Json<ApiResponse<swarmx_protocol::TaskCancelResponse>>, StatusCode> {
+    let context = {
+        let executions = state.inner.executions.read().await;
+        executions
+            .find_task(&id)
+            .map(|(execution, _)| execution.context.clone())
+            .ok_or(StatusCode::NOT_FOUND)?
+    };
+    let running = context.read(|ctx| ctx.running_tasks());
+    let Some(task) = running.iter().find(|t| t.task_id == id) else {
+        return Ok(Json(ApiResponse::success(
+            swarmx_protocol::TaskCancelResponse {
+                task_id: id,
+                outcome: swarmx_protocol::CancelOutcome::AlreadyFinished,
+            },
+        )));
+    };
+
+    match send_cancel(&state.inner.cancels, task, &params.request()).await {
+        Ok(response) => Ok(Json(ApiResponse::success(response))),
+        Err(e) => {
+            tracing::warn!(task_id = %id, server = %task.server, "Failed to cancel task: {e}");
+            Err(StatusCode::BAD_GATEWAY)
+        }
+    }
 }
 
 // ============================================================================
 // Data Endpoints
 // ============================================================================
 
+/// Data upload query parameters
+#[derive(Debug, Deserialize)]
+pub struct UploadDataParams {
+    /// Workflow the data belongs to; it is kept until the workflow
+    /// terminates, then collected
+    #[serde(default)]
+    pub workflow_id: Option<Uuid>,
+    /// Tags to find the data by, as `key=value` pairs separated by commas
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// Tenant whose storage quota the workflow's data counts against
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+/// Upload data
+///
+/// `POST /api/data` streams the request body into the local data store,
+/// publishing `data_transfer_progress` events as it goes. The
+/// `Content-Type` selects the data type: JSON, raw bytes for
+/// `application/octet-stream` or no type, and a file otherwise. Uploads for
+/// a workflow under a storage quota need a `Content-Length` to be admitted.
+pub async fn upload_data(
+    State(state): State<AppState>,
+    Query(params): Query<UploadDataParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> (StatusCode, Json<ApiResponse<DataRef>>) {
+    let mime_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(str::trim)
+        .unwrap_or_default();
+    let dtype = data_type_for(mime_type);
+    let tags = match params.tags.as_deref().map(parse_tags).transpose() {
+        Ok(tags) => tags.unwrap_or_default(),
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error("INVALID_TAGS", &e.to_string())),
+            )
+        }
+    };
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let workflow_id = params.workflow_id.unwrap_or_else(Uuid::nil);
+    if params.workflow_id.is_some() {
+        let mut registry = state.inner.data.write().await;
+        // Only a declared length can be checked against a quota before the
+        // body is stored
+        let Some(size_bytes) =
+            content_length.or(registry.quota_policy().is_unlimited().then_some(0))
+        else {
+            return (
+                StatusCode::LENGTH_REQUIRED,
+                Json(ApiResponse::error(
+                    "LENGTH_REQUIRED",
+                    "Uploads under a storage quota need a Content-Length",
+                )),
+            );
+        };
+        let request = DataStoreRequest {
+            workflow_id,
+            dtype: dtype.name().to_string(),
+            content_type: mime_type.to_string(),
+            size_bytes,
+            tenant: params.tenant.clone(),
+        };
+        if let Err(e) = request.admit(&mut registry) {
+            return (
+                StatusCode::INSUFFICIENT_STORAGE,
+                Json(ApiResponse::error("QUOTA_EXCEEDED", &e.to_string())),
+            );
+        }
+    }
+    let mut data_ref = DataRef::new(String::new(), 0, dtype, workflow_id);
+    data_ref.tags = tags;
+
+    let progress = transfer_progress(&state, &data_ref, "upload", content_length);
+    let body = tokio_util::io::StreamReader::new(
+        body.into_data_stream().map_err(std::io::Error::other),
+    );
+    let stored = state
+        .inner
+        .store
+        .put_stream(data_ref, body, Some(progress))
+        .await;
+    let data_ref = match stored {
+        Ok(data_ref) => data_ref,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error("STORAGE_ERROR", &e.to_string())),
+            )
+        }
+    };
+    record_upload(&state, &data_ref, params.workflow_id).await;
+    (StatusCode::CREATED, Json(ApiResponse::success(data_ref)))
+}
+
+/// Catalog uploaded data, register it with its workflow, if any, and
+/// record its creation
+pub async fn record_upload(state: &AppState, data_ref: &DataRef, workflow_id: Option<Uuid>) {
+    if let Err(e) = state.inner.catalog.register(data_ref) {
+        tracing::warn!(data_uuid = %data_ref.uuid, "Failed to catalog data: {e}");
+    }
+
+    if let Some(workflow_id) = workflow_id {
+        let warnings = {
+            let mut registry = state.inner.data.write().await;
+            registry.register(data_ref.clone(), Holder::Workflow { workflow_id });
+            registry.take_quota_warnings()
+        };
+        for warning in warnings {
+            if let Err(e) = state.inner.events.publish(warning).await {
+                tracing::warn!(%workflow_id, "Failed to record quota warning: {e}");
+            }
+        }
+    }
+    let created = Event::DataCreated {
+        data_uuid: data_ref.uuid,
+        workflow_id: workflow_id.unwrap_or_else(Uuid::nil),
+        location: data_ref.location.clone(),
+        size_bytes: data_ref.size_bytes,
+        tags: data_ref.tag_labels(),
+        timestamp: data_ref.created_at,
+    };
+    if let Err(e) = state.inner.events.publish(created).await {
+        tracing::warn!(data_uuid = %data_ref.uuid, "Failed to record data creation: {e}");
+    }
+}
+
+/// Data type of an upload with MIME type `mime_type`: JSON, raw bytes
+/// for `application/octet-stream` or no type, and a file otherwise
+pub fn data_type_for(mime_type: &str) -> DataType {
+    match mime_type {
+        "application/json" => DataType::Json,
+        "" | "application/octet-stream" => DataType::Bytes,
+        mime_type => DataType::File {
+            mime_type: mime_type.to_string(),
+        },
+    }
+}
+
+/// Find cataloged data by metadata
+///
+/// `GET /api/data` takes the [`DataQuery`] keys as query parameters, e.g.
+/// `?workflow=<id>&dtype=file&tag.stage=final&limit=1`, and returns the
+/// matching refs newest first.
+pub async fn find_data(
+    State(state): State<AppState>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> (StatusCode, Json<ApiResponse<Vec<DataRef>>>) {
+    let query = match DataQuery::from_pairs(pairs) {
+        Ok(query) => query,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error("INVALID_QUERY", &e.to_string())),
+            )
+        }
+    };
+    let found = state.inner.catalog.find(&query);
+    (StatusCode::OK, Json(ApiResponse::success(found)))
+}
+
+/// Get the DataRef of cataloged data, wherever it is stored
+pub async fn get_data_ref(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+) -> Result<Json<ApiResponse<DataRef>>, StatusCode> {
+    let data_ref = state.inner.catalog.get(&uuid).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(data_ref)))
+}
+
+/// List the cataloged data of a workflow, newest first
+pub async fn list_workflow_data(
+    State(state): State<AppState>,
+    Path(workflow_id): Path<Uuid>,
+) -> Json<ApiResponse<Vec<DataRef>>> {
+    Json(ApiResponse::success(state.inner.catalog.by_workflow(workflow_id)))
+}
+
+/// Count the cataloged data and bytes each server holds
+pub async fn data_inventory(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<BTreeMap<String, LocationInventory>>> {
+    Json(ApiResponse::success(state.inner.catalog.inventory()))
+}
+
+/// List cataloged data whose workflow is unknown or that has none
+pub async fn list_orphaned_data(State(state): State<AppState>) -> Json<ApiResponse<Vec<DataRef>>> {
+    let workflows = state.inner.workflows.read().await;
+    Json(ApiResponse::success(
+        state
+            .inner
+            .catalog
+            .orphans(|workflow_id| workflows.workflows.contains_key(workflow_id)),
+    ))
+}
+
 /// Get data by UUID
+///
+/// The payload is streamed from the local data store, publishing
+/// `data_transfer_progress` events as it goes. A `Range: bytes=...` header
+/// with a single range selects part of it. Every request is recorded in
+/// the data-access audit trail.
 pub async fn get_data(
-    State(_state): State<AppState>,
-    Path(_uuid): Path<Uuid>,
-) -> Result<Vec<u8>, StatusCode> {
-    todo!("Implement get_data")
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let served = serve_data(&state, uuid, &headers).await;
+    token
+        .read(uuid)
+        .record(&state, access_result(&served))
+        .await;
+    served
+}
+
+/// Stream data from the local store in response to a `GET`
+pub async fn serve_data(
+    state: &AppState,
+    uuid: Uuid,
+    headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    let data_ref = state
+        .inner
+        .store
+        .get_ref(&uuid)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let size = data_ref.size_bytes;
+    let range = match headers.get(header::RANGE) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| parse_byte_range(value, size))
+                .ok_or(StatusCode::RANGE_NOT_SATISFIABLE)?,
+        ),
+        None => None,
+    };
+    let len = range.unwrap_or_default().len_within(size);
+    let progress = transfer_progress(state, &data_ref, "download", Some(len));
+    let payload = state
+        .inner
+        .store
+        .get_stream(uuid, range.unwrap_or_default(), Some(progress))
+        .await
+        .map_err(|e| match e {
+            DataStoreError::NotFound(_) => StatusCode::NOT_FOUND,
+            DataStoreError::InvalidRange { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+    let content_type = match data_ref.dtype {
+        DataType::Json => "application/json".to_string(),
+        DataType::File { mime_type } => mime_type,
+        _ => "application/octet-stream".to_string(),
+    };
+
+    let payload = tokio_util::io::ReaderStream::new(payload);
+    let mut response = Body::from_stream(payload).into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&content_type)
+            .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(range) = range {
+        let content_range = format!("bytes {}-{}/{size}", range.offset, range.offset + len - 1);
+        response_headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).expect("valid header value"),
+        );
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    }
+    Ok(response)
+}
+
+/// Parse a single-range `Range` header, e.g. `bytes=0-1023`, `bytes=1024-`,
+/// or `bytes=-512`, against a payload of `size` bytes
+///
+/// Returns `None` for anything else, and for ranges that select no bytes.
+fn parse_byte_range(value: &str, size: u64) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        ByteRange::from_offset(size.saturating_sub(suffix))
+    } else {
+        let start: u64 = start.parse().ok()?;
+        if end.is_empty() {
+            ByteRange::from_offset(start)
+        } else {
+            let end: u64 = end.parse().ok()?;
+            if end < start {
+                return None;
+            }
+            ByteRange::new(start, end - start + 1)
+        }
+    };
+    (range.offset < size).then_some(range)
+}
+
+/// Derive new data from stored data without copying it
+///
+/// `POST /api/data/{uuid}/derive` registers data that shares the stored
+/// bytes of `uuid` until either is written, belonging to the same
+/// workflow. It is recorded in the data-access audit trail as a read of
+/// `uuid`.
+pub async fn derive_data(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+) -> (StatusCode, Json<ApiResponse<DataRef>>) {
+    let derived = with_store(&state, move |store| store.derive(&uuid)).await;
+    let data_ref = match derived {
+        Ok(data_ref) => data_ref,
+        Err(DataStoreError::NotFound(_)) => {
+            token
+                .read(uuid)
+                .record(&state, Err(status_reason(StatusCode::NOT_FOUND)))
+                .await;
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error("NOT_FOUND", "Data not found")),
+            );
+        }
+        Err(e) => {
+            token.read(uuid).record(&state, Err(e.to_string())).await;
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error("STORAGE_ERROR", &e.to_string())),
+            );
+        }
+    };
+    token.read(uuid).record(&state, Ok(())).await;
+    if let Err(e) = state.inner.catalog.register(&data_ref) {
+        tracing::warn!(data_uuid = %data_ref.uuid, "Failed to catalog data: {e}");
+    }
+    let workflow_id = data_ref.workflow_id;
+    if !workflow_id.is_nil() {
+        state
+            .inner
+            .data
+            .write()
+            .await
+            .register(data_ref.clone(), Holder::Workflow { workflow_id });
+    }
+    let created = Event::DataCreated {
+        data_uuid: data_ref.uuid,
+        workflow_id,
+        location: data_ref.location.clone(),
+        size_bytes: data_ref.size_bytes,
+        tags: data_ref.tag_labels(),
+        timestamp: data_ref.created_at,
+    };
+    if let Err(e) = state.inner.events.publish(created).await {
+        tracing::warn!(data_uuid = %data_ref.uuid, "Failed to record data creation: {e}");
+    }
+    (StatusCode::CREATED, Json(ApiResponse::success(data_ref)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WriteDataParams {
+    /// Byte offset to write at; defaults to the end of the data, appending
+    #[serde(default)]
+    pub offset: Option<u64>,
+}
+
+/// Overwrite part of stored data
+///
+/// `PATCH /api/data/{uuid}?offset=N` writes the request body at `offset`,
+/// extending the data if the write runs past its end. Data derived from
+/// the same bytes is unaffected. Versions of a data series are immutable
+/// and refused with `409`.
+pub async fn write_data(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+    Query(params): Query<WriteDataParams>,
+    body: axum::body::Bytes,
+) -> (StatusCode, Json<ApiResponse<DataRef>>) {
+    let versioned = state
+        .inner
+        .series
+        .read()
+        .await
+        .version_of(&uuid)
+        .map(|(series, version)| format!("Data is version {version} of series {series}"));
+    if let Some(message) = versioned {
+        token.write(uuid).record(&state, Err(message.clone())).await;
+        return (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error("IMMUTABLE_VERSION", &message)),
+        );
+    }
+    let written = with_store(&state, move |store| {
+        let size = store
+            .get_ref(&uuid)
+            .ok_or(DataStoreError::NotFound(uuid))?
+            .size_bytes;
+        store.write_at(&uuid, params.offset.unwrap_or(size), &body)
+    })
+    .await;
+    let access = token.write(uuid);
+    let result = written.as_ref().map(|_| ()).map_err(|e| e.to_string());
+    access.record(&state, result).await;
+    let data_ref = match written {
+        Ok(data_ref) => data_ref,
+        Err(DataStoreError::NotFound(_)) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error("NOT_FOUND", "Data not found")),
+            )
+        }
+        Err(e @ DataStoreError::InvalidRange { .. }) => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                Json(ApiResponse::error("INVALID_RANGE", &e.to_string())),
+            )
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error("STORAGE_ERROR", &e.to_string())),
+            )
+        }
+    };
+    if let Err(e) = state.inner.catalog.register(&data_ref) {
+        tracing::warn!(data_uuid = %uuid, "Failed to catalog data: {e}");
+    }
+    state.inner.data.write().await.update(data_ref.clone());
+    (StatusCode::OK, Json(ApiResponse::success(data_ref)))
 }
 
 /// Delete data by UUID
 pub async fn delete_data(
-    State(_state): State<AppState>,
-    Path(_uuid): Path<Uuid>,
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
 ) -> StatusCode {
-    todo!("Implement delete_data")
+    let access = token.delete(uuid);
+    if state.inner.data.read().await.is_leased(&uuid) {
+        access
+            .record(&state, Err("Data is leased for reading".to_string()))
+            .await;
+        return StatusCode::CONFLICT;
+    }
+    match with_store(&state, move |store| store.delete(&uuid)).await {
+        Ok(data_ref) => {
+            access
+                .with_workflow(data_ref.workflow_id)
+                .record(&state, Ok(()))
+                .await;
+            state.inner.data.write().await.remove(&uuid);
+            if let Err(e) = state.inner.catalog.remove(&uuid) {
+                tracing::warn!(data_uuid = %uuid, "Failed to remove catalog entry: {e}");
+            }
+            if let Err(e) = state.inner.events.publish(data_ref.deleted_event()).await {
+                tracing::warn!(data_uuid = %uuid, "Failed to record data deletion: {e}");
+            }
+            StatusCode::NO_CONTENT
+        }
+        Err(e) => {
+            access.record(&state, Err(e.to_string())).await;
+            if matches!(e, DataStoreError::NotFound(_)) {
+                return StatusCode::NOT_FOUND;
+            }
+            tracing::error!(data_uuid = %uuid, "Failed to delete data: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
 }
 
-// ============================================================================
-// Server Registry Endpoints
-// ============================================================================
+/// Pinned data and workflows
+#[derive(Debug, Serialize)]
+pub struct PinnedData {
+    /// Registered data that is pinned, on its own or through its workflow
+    pub data: Vec<DataRef>,
+    /// Workflows whose data is all pinned
+    pub workflows: Vec<Uuid>,
+    /// Total size of the pinned data in bytes
+    pub total_bytes: u64,
+}
+
+/// List pinned data with sizes
+pub async fn list_pinned_data(State(state): State<AppState>) -> Json<ApiResponse<PinnedData>> {
+    let registry = state.inner.data.read().await;
+    let data: Vec<DataRef> = registry.pinned().into_iter().cloned().collect();
+    let total_bytes = data.iter().map(|data_ref| data_ref.size_bytes).sum();
+    Json(ApiResponse::success(PinnedData {
+        data,
+        workflows: registry.pins().workflows.iter().copied().collect(),
+        total_bytes,
+    }))
+}
+
+/// Exempt data from cleanup until it is unpinned
+pub async fn pin_data(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+) -> StatusCode {
+    let pinned = match state.inner.data.write().await.pin(uuid) {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    };
+    token
+        .write(uuid)
+        .record(&state, access_result(&pinned))
+        .await;
+    pinned.unwrap_or_else(|status| status)
+}
+
+/// Unpin data
+pub async fn unpin_data(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+) -> StatusCode {
+    let unpinned = if state.inner.data.write().await.unpin(&uuid) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    };
+    token
+        .write(uuid)
+        .record(&state, access_result(&unpinned))
+        .await;
+    unpinned.unwrap_or_else(|status| status)
+}
 
-/// Server registration request
+/// Exempt all data of a workflow from cleanup until it is unpinned
+///
+/// Recorded in the data-access audit trail as a write of each piece of the
+/// workflow's cataloged data.
+pub async fn pin_workflow_data(
+    State(state): State<AppState>,
+    Path(workflow_id): Path<Uuid>,
+    token: DataToken,
+) -> StatusCode {
+    state.inner.data.write().await.pin_workflow(workflow_id);
+    record_workflow_write(&state, &token, workflow_id, Ok(())).await;
+    StatusCode::NO_CONTENT
+}
+
+/// Unpin the data of a workflow
+pub async fn unpin_workflow_data(
+    State(state): State<AppState>,
+    Path(workflow_id): Path<Uuid>,
+    token: DataToken,
+) -> StatusCode {
+    let unpinned = if state.inner.data.write().await.unpin_workflow(&workflow_id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    };
+    record_workflow_write(&state, &token, workflow_id, access_result(&unpinned)).await;
+    unpinned.unwrap_or_else(|status| status)
+}
+
+/// Record a write of each piece of a workflow's cataloged data
+async fn record_workflow_write(
+    state: &AppState,
+    token: &DataToken,
+    workflow_id: Uuid,
+    result: Result<(), String>,
+) {
+    for data_ref in state.inner.catalog.by_workflow(workflow_id) {
+        token
+            .write(data_ref.uuid)
+            .with_workflow(workflow_id)
+            .record(state, result.clone())
+            .await;
+    }
+}
+
+/// Request for a read lease
 #[derive(Debug, Deserialize)]
-pub struct RegisterServerRequest {
-    pub address: String,
-    pub capabilities: Vec<String>,
-    pub gpu_available: bool,
+pub struct LeaseRequest {
+    /// Server or client reading the data
+    pub holder: String,
+    /// Lifetime of the lease in seconds; one minute if unset
+    #[serde(default)]
+    pub ttl_secs: Option<i64>,
 }
 
-/// Server info response
-#[derive(Debug, Serialize)]
-pub struct ServerInfoResponse {
-    pub address: String,
-    pub healthy: bool,
-    pub current_load: f64,
-    pub gpu_available: bool,
-    pub capabilities: Vec<String>,
+/// Lease renewal query parameters
+#[derive(Debug, Deserialize)]
+pub struct RenewLeaseParams {
+    /// New lifetime of the lease in seconds, from now; one minute if unset
+    #[serde(default)]
+    pub ttl_secs: Option<i64>,
+}
+
+fn lease_ttl(ttl_secs: Option<i64>) -> chrono::Duration {
+    chrono::Duration::seconds(ttl_secs.unwrap_or(DEFAULT_LEASE_SECS))
+}
+
+/// Lease data for reading, keeping it from cleanup until the lease is
+/// released or expires
+pub async fn acquire_lease(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+    Json(request): Json<LeaseRequest>,
+) -> (StatusCode, Json<ApiResponse<ReadLease>>) {
+    let ttl = lease_ttl(request.ttl_secs);
+    let acquired = state
+        .inner
+        .data
+        .write()
+        .await
+        .acquire_lease(uuid, request.holder, ttl);
+    let access = token.write(uuid);
+    match acquired {
+        Ok(lease) => {
+            access.record(&state, Ok(())).await;
+            (StatusCode::CREATED, Json(ApiResponse::success(lease)))
+        }
+        Err(e) => {
+            access.record(&state, Err(e.to_string())).await;
+            (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error("NOT_FOUND", &e.to_string())),
+            )
+        }
+    }
+}
+
+/// List the active leases on data, soonest expiring first
+pub async fn list_leases(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    token: DataToken,
+) -> Json<ApiResponse<Vec<ReadLease>>> {
+    let leases = state
+        .inner
+        .data
+        .read()
+        .await
+        .leases(&uuid)
+        .into_iter()
+        .cloned()
+        .collect();
+    token.read(uuid).record(&state, Ok(())).await;
+    Json(ApiResponse::success(leases))
+}
+
+/// Extend a lease before it expires
+pub async fn renew_lease(
+    State(state): State<AppState>,
+    Path((uuid, lease_id)): Path<(Uuid, Uuid)>,
+    token: DataToken,
+    Query(params): Query<RenewLeaseParams>,
+) -> Result<Json<ApiResponse<ReadLease>>, StatusCode> {
+    let renewed = {
+        let mut registry = state.inner.data.write().await;
+        if registry
+            .leases(&uuid)
+            .iter()
+            .any(|lease| lease.lease_id == lease_id)
+        {
+            registry
+                .renew_lease(&lease_id, lease_ttl(params.ttl_secs))
+                .map_err(|_| StatusCode::NOT_FOUND)
+        } else {
+            Err(StatusCode::NOT_FOUND)
+        }
+    };
+    token
+        .write(uuid)
+        .record(&state, access_result(&renewed))
+        .await;
+    Ok(Json(ApiResponse::success(renewed?)))
+}
+
+/// Release a lease once the data has been read
+pub async fn release_lease(
+    State(state): State<AppState>,
+    Path((uuid, lease_id)): Path<(Uuid, Uuid)>,
+    token: DataToken,
+) -> StatusCode {
+    let released = {
+        let mut registry = state.inner.data.write().await;
+        if registry
+            .leases(&uuid)
+            .iter()
+            .any(|lease| lease.lease_id == lease_id)
+        {
+            registry.release_lease(&lease_id);
+            Ok(StatusCode::NO_CONTENT)
+        } else {
+            Err(StatusCode::NOT_FOUND)
+        }
+    };
+    token
+        .write(uuid)
+        .record(&state, access_result(&released))
+        .await;
+    released.unwrap_or_else(|status| status)
+}
+
+/// List every active lease, soonest expiring first
+pub async fn list_active_leases(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<Vec<ReadLease>>> {
+    let registry = state.inner.data.read().await;
+    Json(ApiResponse::success(
+        registry.active_leases().into_iter().cloned().collect(),
+    ))
+}
+
+/// Publish the progress of a data upload or download as
+/// `data_transfer_progress` events
+///
+/// Reports are forwarded in order by a task that ends with the transfer.
+pub fn transfer_progress(
+    state: &AppState,
+    data_ref: &DataRef,
+    direction: &'static str,
+    total_bytes: Option<u64>,
+) -> ProgressFn {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let events = state.inner.events.clone();
+    let data_uuid = data_ref.uuid;
+    let workflow_id = data_ref.workflow_id;
+    tokio::spawn(async move {
+        while let Some(bytes_transferred) = receiver.recv().await {
+            let progress = Event::DataTransferProgress {
+                data_uuid,
+                workflow_id,
+                direction: direction.to_string(),
+                bytes_transferred,
+                total_bytes,
+                timestamp: chrono::Utc::now(),
+            };
+            if let Err(e) = events.publish(progress).await {
+                tracing::warn!(%data_uuid, "Failed to record transfer progress: {e}");
+            }
+        }
+    });
+    Box::new(move |bytes_transferred| {
+        let _ = sender.send(bytes_transferred);
+    })
+}
+
+/// Run a data store operation on the blocking thread pool
+pub async fn with_store<T, F>(state: &AppState, operation: F) -> Result<T, DataStoreError>
+where
+    T: Send + 'static,
+    F: FnOnce(&swarmx_dataref::LocalDataStore) -> Result<T, DataStoreError> + Send + 'static,
+{
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || operation(&state.inner.store))
+        .await
+        .map_err(|e| DataStoreError::Io(std::io::Error::other(e)))?
 }
 
+// ============================================================================
+// Server Registry Endpoints
+// ============================================================================
+
 /// List registered servers
 pub async fn list_servers(
     State(_state): State<AppState>,
-) -> Json<ApiResponse<Vec<ServerInfoResponse>>> {
+) -> Json<ApiResponse<Vec<swarmx_core::ServerInfo>>> {
     todo!("Implement list_servers")
 }
 
 /// Register a new server
+///
+/// Registrations may be sent as JSON, MessagePack, or CBOR. A server
+/// registering again replaces its earlier registration; it is then
+/// expected to send heartbeats to stay schedulable.
 pub async fn register_server(
-    State(_state): State<AppState>,
-    Json(_request): Json<RegisterServerRequest>,
-) -> (StatusCode, Json<ApiResponse<ServerInfoResponse>>) {
-    todo!("Implement register_server")
+    State(state): State<AppState>,
+    WireBody(registration): WireBody<ServerRegistration>,
+) -> (StatusCode, Json<ApiResponse<swarmx_core::ServerInfo>>) {
+    if registration.address.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                "INVALID_ADDRESS",
+                "Server address is empty",
+            )),
+        );
+    }
+    let now = chrono::Utc::now();
+    let server = swarmx_core::ServerInfo::from_registration(&registration, now);
+    state
+        .inner
+        .servers
+        .write()
+        .await
+        .servers
+        .insert(server.address.clone(), server.clone());
+    tracing::info!(server = %server.address, "Server registered");
+
+    let registered = Event::ServerRegistered {
+        server_address: server.address.clone(),
+        capabilities: server.capabilities.clone(),
+        timestamp: now,
+    };
+    if let Err(e) = state.inner.events.publish(registered).await {
+        tracing::warn!(server = %server.address, "Failed to record server registration: {e}");
+    }
+    (StatusCode::CREATED, Json(ApiResponse::success(server)))
 }
 
 /// Unregister a server
@@ -235,3 +1185,49 @@ pub async fn unregister_server(
 ) -> StatusCode {
     todo!("Implement unregister_server")
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use swarmx_dataref::QuotaPolicy;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upload_under_quota_needs_length() {
+        let state = AppState::new();
+        {
+            let mut registry = state.inner.data.write().await;
+            let quotas = QuotaPolicy::new().with_workflow_quota(8);
+            *registry = std::mem::take(&mut *registry).with_quotas(quotas);
+        }
+        let app = Router::new()
+            .route("/api/data", post(upload_data))
+            .with_state(state);
+        let upload = |content_length: Option<usize>, payload: &'static [u8]| {
+            let mut request = Request::builder()
+                .method(Method::POST)
+                .uri(format!("/api/data?workflow_id={}", Uuid::new_v4()));
+            if let Some(content_length) = content_length {
+                request = request.header(header::CONTENT_LENGTH, content_length);
+            }
+            let request = request.body(Body::from(payload)).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(upload(None, b"data").await, StatusCode::LENGTH_REQUIRED);
+        assert_eq!(upload(Some(4), b"data").await, StatusCode::CREATED);
+        assert_eq!(
+            upload(Some(17), b"far too much data").await,
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Server health monitoring
+//!
+//! Registered servers send a [`ServerHeartbeat`] every
+//! [`SERVER_HEARTBEAT_INTERVAL_SECS`](swarmx_protocol::SERVER_HEARTBEAT_INTERVAL_SECS)
+//! with their free memory, GPUs, loaded models, queue depth, and load,
+//! which the registry takes as the server's current state. A server not
+//! heard from for [`SERVER_HEARTBEAT_TIMEOUT_SECS`] is marked unhealthy and
+//! no longer scheduled on until its next heartbeat. Both changes are
+//! recorded with a `server_health_check` event.
+
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{DateTime, Utc};
+use swarmx_events::Event;
+use swarmx_protocol::{ServerHeartbeat, SERVER_HEARTBEAT_TIMEOUT_SECS};
+
+use crate::{AppState, WireBody};
+
+/// How often servers are checked for missed heartbeats
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Record a heartbeat of a registered server
+///
+/// Answers `404` if the server is not registered, so it registers again.
+/// Heartbeats may be sent as JSON, MessagePack, or CBOR.
+pub async fn record_heartbeat(
+    State(state): State<AppState>,
+    WireBody(heartbeat): WireBody<ServerHeartbeat>,
+) -> StatusCode {
+    let recovered = {
+        let mut registry = state.inner.servers.write().await;
+        let Some(server) = registry.servers.get_mut(&heartbeat.address) else {
+            return StatusCode::NOT_FOUND;
+        };
+        let recovered = !server.healthy;
+        server.record_heartbeat(&heartbeat, chrono::Utc::now());
+        recovered.then(|| health_check(&heartbeat.address, true, server.current_load))
+    };
+    if let Some(event) = recovered {
+        tracing::info!(server = %heartbeat.address, "Server is healthy again");
+        if let Err(e) = state.inner.events.publish(event).await {
+            tracing::warn!("Failed to record server health: {e}");
+        }
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// Periodically mark servers that stopped sending heartbeats as unhealthy
+pub async fn server_health_monitor(state: AppState) {
+    let timeout = chrono::Duration::seconds(SERVER_HEARTBEAT_TIMEOUT_SECS as i64);
+    let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        mark_stale_servers(&state, chrono::Utc::now(), timeout).await;
+    }
+}
+
+/// Mark healthy servers not heard from for `timeout` before `now` as
+/// unhealthy
+async fn mark_stale_servers(state: &AppState, now: DateTime<Utc>, timeout: chrono::Duration) {
+    let expired: Vec<Event> = {
+        let mut registry = state.inner.servers.write().await;
+        registry
+            .servers
+            .values_mut()
+            .filter(|server| server.healthy && server.is_stale(now, timeout))
+            .map(|server| {
+                server.healthy = false;
+                health_check(&server.address, false, server.current_load)
+            })
+            .collect()
+    };
+    for event in expired {
+        if let Event::ServerHealthCheck { server_address, .. } = &event {
+            tracing::warn!(server = %server_address, "Server missed its heartbeats");
+        }
+        if let Err(e) = state.inner.events.publish(event).await {
+            tracing::warn!("Failed to record server health: {e}");
+        }
+    }
+}
+
+fn health_check(address: &str, healthy: bool, load: f64) -> Event {
+    Event::ServerHealthCheck {
+        server_address: address.to_string(),
+        healthy,
+        load,
+        timestamp: chrono::Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Method, Request},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use swarmx_events::EventFilter;
+    use swarmx_protocol::{ServerRegistration, ServerResources};
+
+    use super::*;
+    use crate::register_server;
+
+    #[tokio::test]
+    async fn test_heartbeats_keep_registered_servers_healthy() {
+        let state = AppState::new();
+        let mut events = state.inner.events.subscribe(EventFilter::new());
+        let app = Router::new()
+            .route("/api/servers", post(register_server))
+            .route("/api/servers/heartbeat", post(record_heartbeat))
+            .with_state(state.clone());
+        let address = "http://gpu-1:9090".to_string();
+        let post_json = |uri: &'static str, body: String| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        let resources = ServerResources {
+            load: 0.5,
+            ..Default::default()
+        };
+        let heartbeat = ServerHeartbeat::new(address.clone(), resources);
+        let heartbeat = serde_json::to_string(&heartbeat).unwrap();
+
+        // Servers must register before their heartbeats are taken
+        assert_eq!(
+            post_json("/api/servers/heartbeat", heartbeat.clone()).await,
+            StatusCode::NOT_FOUND
+        );
+        let registration = ServerRegistration::new(
+            address.clone(),
+            vec!["llm.".to_string()],
+            ServerResources::default(),
+        );
+        let registration = serde_json::to_string(&registration).unwrap();
+        assert_eq!(
+            post_json("/api/servers", registration).await,
+            StatusCode::CREATED
+        );
+        match events.recv().await.unwrap().event {
+            Event::ServerRegistered {
+                server_address,
+                capabilities,
+                ..
+            } => {
+                assert_eq!(server_address, address);
+                assert_eq!(capabilities, vec!["llm.".to_string()]);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        // A server that misses its heartbeats is marked unhealthy once
+        let timeout = chrono::Duration::seconds(SERVER_HEARTBEAT_TIMEOUT_SECS as i64);
+        mark_stale_servers(&state, Utc::now(), timeout).await;
+        assert!(state.inner.servers.read().await.servers[&address].healthy);
+        let later = Utc::now() + timeout;
+        mark_stale_servers(&state, later, timeout).await;
+        mark_stale_servers(&state, later, timeout).await;
+        assert!(!state.inner.servers.read().await.servers[&address].healthy);
+        assert!(matches!(
+            events.recv().await.unwrap().event,
+            Event::ServerHealthCheck { healthy: false, .. }
+        ));
+
+        // Its next heartbeat makes it healthy again
+        assert_eq!(
+            post_json("/api/servers/heartbeat", heartbeat).await,
+            StatusCode::NO_CONTENT
+        );
+        let server = state.inner.servers.read().await.servers[&address].clone();
+        assert!(server.healthy);
+        assert_eq!(server.current_load, 0.5);
+        assert!(matches!(
+            events.recv().await.unwrap().event,
+            Event::ServerHealthCheck { healthy: true, .. }
+        ));
+    }
+}
@@ -7,7 +7,7 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
-    routing::{get, post, delete},
+    routing::{get, post, put, delete},
     Router,
 };
 use tokio::sync::RwLock;
@@ -15,11 +15,59 @@ use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod access;
+mod admin;
+mod approval;
+mod auth;
 mod callback;
+mod cancel;
+mod federation;
+mod gc;
 mod handlers;
+mod health;
+mod metrics;
+mod negotiate;
+mod output;
+mod otel;
+mod reaper;
+mod scrubber;
+mod series;
+mod sse;
+mod trace;
+mod triggers;
+mod uploads;
+mod version;
+mod webhooks;
+mod ws;
 
 use handlers::*;
+use access::*;
+use admin::*;
+use approval::*;
+use auth::*;
 use callback::*;
+use cancel::*;
+use federation::*;
+use gc::*;
+use health::*;
+use metrics::*;
+use negotiate::*;
+use output::*;
+use otel::*;
+use reaper::*;
+use scrubber::*;
+use series::*;
+use sse::*;
+use trace::*;
+use triggers::*;
+use uploads::*;
+use version::*;
+use webhooks::*;
+use ws::*;
+
+/// Address data in the local store is fetched from, unless `SWARMX_DATA_URL`
+/// is set
+const DEFAULT_DATA_URL: &str = "http://localhost:3000/api";
 
 /// Application state shared across all handlers
 #[derive(Clone)]
@@ -35,6 +83,37 @@ pub struct AppStateInner {
     pub executions: RwLock<ExecutionStore>,
     /// Server registry
     pub servers: RwLock<ServerRegistry>,
+    /// Durable event bus feeding the event streams
+    pub events: swarmx_events::EventBus,
+    /// Publisher that coalesces node progress reports into `events`
+    ///
+    /// Node and workflow events go through it too, so a held progress
+    /// report is never recorded after the events that follow it.
+    pub progress: swarmx_events::CoalescingPublisher,
+    /// Output streamed by running tasks, put back in order
+    pub partial_outputs: RwLock<PartialOutputBuffer>,
+    /// Registered webhooks and their delivery history
+    pub webhooks: swarmx_events::WebhookDispatcher,
+    /// Holders of distributed data, for garbage collection
+    pub data: RwLock<swarmx_dataref::DataRefRegistry>,
+    /// Uploaded inputs and final outputs held by the API server
+    pub store: swarmx_dataref::AsyncDataStore,
+    /// Every DataRef created, for lookups, inventories, and orphan detection
+    pub catalog: swarmx_dataref::DataCatalog,
+    /// Named, versioned artifacts of workflows
+    pub series: RwLock<swarmx_dataref::DataSeriesIndex>,
+    /// Integrity checks of the data in `store`
+    pub scrubber: swarmx_dataref::Scrubber,
+    /// Reconciles the catalog with the data servers report holding
+    pub gc: swarmx_dataref::GcCoordinator,
+    /// Peer clusters data is shared with, if federation is configured
+    pub federation: Option<swarmx_dataref::Federation>,
+    /// Multipart uploads in progress into `store`, if enabled
+    pub uploads: Option<swarmx_dataref::MultipartUploads>,
+    /// Verifies the access tokens data endpoints require, if enforced
+    pub tokens: Option<swarmx_dataref::TokenManager>,
+    /// HTTP client asking servers to stop tasks
+    pub cancels: reqwest::Client,
 }
 
 /// In-memory workflow storage
@@ -67,6 +146,19 @@ impl ExecutionStore {
             executions: std::collections::HashMap::new(),
         }
     }
+
+    /// Get an execution by ID
+    pub fn get(&self, execution_id: &uuid::Uuid) -> Option<&ExecutionState> {
+        self.executions.get(execution_id)
+    }
+
+    /// Find the execution and node a server task was submitted for
+    pub fn find_task(&self, task_id: &uuid::Uuid) -> Option<(&ExecutionState, uuid::Uuid)> {
+        self.executions.values().find_map(|execution| {
+            let node_id = execution.context.read(|ctx| ctx.node_for_task(*task_id))?;
+            Some((execution, node_id))
+        })
+    }
 }
 
 impl Default for ExecutionStore {
@@ -76,11 +168,15 @@ impl Default for ExecutionStore {
 }
 
 /// Execution state for a running workflow
+///
+/// Node and workflow state lives in the shared context so handlers, the
+/// callback receiver, and the engine loop all see the same view without
+/// holding the store lock.
 pub struct ExecutionState {
     pub execution_id: uuid::Uuid,
     pub workflow_id: uuid::Uuid,
-    pub status: String,
-    pub progress: f64,
+    pub context: swarmx_core::SharedWorkflowContext,
+    pub dag: Arc<swarmx_core::WorkflowDag>,
     pub started_at: chrono::DateTime<chrono::Utc>,
 }
 
@@ -104,16 +200,100 @@ impl Default for ServerRegistry {
 }
 
 impl AppState {
-    /// Create a new application state
+    /// Create a new application state with an in-memory event log and a
+    /// data store in a temporary directory
     pub fn new() -> Self {
+        let wal = swarmx_events::WriteAheadLog::in_memory().expect("in-memory WAL");
+        let events = swarmx_events::EventBus::new(wal, Default::default())
+            .expect("failed to start event bus");
+        let webhooks = swarmx_events::WriteAheadLog::in_memory().expect("in-memory WAL");
+        Self::with_events(
+            events,
+            Default::default(),
+            swarmx_events::WebhookDispatcher::new(webhooks),
+            Default::default(),
+            swarmx_dataref::LocalDataStore::open(
+                std::env::temp_dir().join(format!("swarmx-data-{}", std::process::id())),
+                DEFAULT_DATA_URL,
+            )
+            .expect("temporary data store"),
+            swarmx_dataref::DataCatalog::new(),
+            Default::default(),
+        )
+    }
+
+    /// Create a new application state publishing to the given event bus
+    pub fn with_events(
+        events: swarmx_events::EventBus,
+        progress: swarmx_events::CoalesceConfig,
+        webhooks: swarmx_events::WebhookDispatcher,
+        data: swarmx_dataref::DataRefRegistry,
+        store: swarmx_dataref::LocalDataStore,
+        catalog: swarmx_dataref::DataCatalog,
+        scrubber: swarmx_dataref::Scrubber,
+    ) -> Self {
         Self {
             inner: Arc::new(AppStateInner {
                 workflows: RwLock::new(WorkflowStore::new()),
                 executions: RwLock::new(ExecutionStore::new()),
                 servers: RwLock::new(ServerRegistry::new()),
+                progress: swarmx_events::CoalescingPublisher::new(events.clone(), progress),
+                partial_outputs: RwLock::new(PartialOutputBuffer::new()),
+                events,
+                webhooks,
+                data: RwLock::new(data),
+                store: swarmx_dataref::AsyncDataStore::new(store),
+                catalog,
+                series: RwLock::new(swarmx_dataref::DataSeriesIndex::new()),
+                scrubber,
+                gc: Default::default(),
+                federation: None,
+                uploads: None,
+                tokens: None,
+                cancels: cancel_client().expect("HTTP client for cancel requests"),
             }),
         }
     }
+
+    /// Share data with the peer clusters of `federation`
+    ///
+    /// Must be called before the state is cloned.
+    pub fn with_federation(mut self, federation: swarmx_dataref::Federation) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("federation is configured before the state is shared")
+            .federation = Some(federation);
+        self
+    }
+
+    /// Collect garbage across the cluster with `gc`
+    ///
+    /// Must be called before the state is cloned.
+    pub fn with_gc(mut self, gc: swarmx_dataref::GcCoordinator) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("garbage collection is configured before the state is shared")
+            .gc = gc;
+        self
+    }
+
+    /// Accept multipart uploads staged in `uploads`
+    ///
+    /// Must be called before the state is cloned.
+    pub fn with_uploads(mut self, uploads: swarmx_dataref::MultipartUploads) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("uploads are configured before the state is shared")
+            .uploads = Some(uploads);
+        self
+    }
+
+    /// Require access tokens verified by `tokens` on the data endpoints
+    ///
+    /// Must be called before the state is cloned.
+    pub fn with_tokens(mut self, tokens: swarmx_dataref::TokenManager) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("tokens are configured before the state is shared")
+            .tokens = Some(tokens);
+        self
+    }
 }
 
 impl Default for AppState {
@@ -132,7 +312,151 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let state = AppState::new();
+    let wal_path = std::env::var("SWARMX_WAL_PATH").unwrap_or_else(|_| "swarmx-events.db".into());
+    let wal = swarmx_events::WriteAheadLog::open(&wal_path)?
+        .with_field_protection(field_protection_from_env()?);
+    // Surface damage from an unclean shutdown before the log is trusted
+    let integrity = wal.verify()?;
+    if let Err(e) = integrity.check() {
+        tracing::warn!(
+            gaps = integrity.gaps.len(),
+            corrupt = integrity.corrupt.len(),
+            "Event log failed verification: {e}"
+        );
+    }
+    // Record every progress report unchanged when debugging servers
+    let progress = swarmx_events::CoalesceConfig {
+        pass_through: std::env::var("SWARMX_RAW_PROGRESS").is_ok_and(|v| v == "1"),
+        ..Default::default()
+    };
+    // Webhooks are kept in the event log's database, on their own connection
+    let webhooks =
+        swarmx_events::WebhookDispatcher::new(swarmx_events::WriteAheadLog::open(&wal_path)?);
+    // Stream chatty events to clients without persisting them, e.g.
+    // SWARMX_PERSIST_LEVEL=info keeps progress reports out of the log
+    let bus_config = swarmx_events::EventBusConfig {
+        persist_level: severity_from_env("SWARMX_PERSIST_LEVEL")?,
+        stream_level: severity_from_env("SWARMX_STREAM_LEVEL")?,
+        ..Default::default()
+    };
+    // Delete a workflow's data a fixed time after it terminates, e.g.
+    // SWARMX_DATA_RETENTION_HOURS=24
+    let mut lifecycle = swarmx_dataref::LifecyclePolicy::new();
+    if let Ok(hours) = std::env::var("SWARMX_DATA_RETENTION_HOURS") {
+        lifecycle = lifecycle.with_workflow_retention(chrono::Duration::hours(hours.parse()?));
+    }
+    // Cap the bytes each workflow and tenant may store, e.g.
+    // SWARMX_WORKFLOW_QUOTA_BYTES=10737418240
+    let mut quotas = swarmx_dataref::QuotaPolicy::new();
+    if let Ok(bytes) = std::env::var("SWARMX_WORKFLOW_QUOTA_BYTES") {
+        quotas = quotas.with_workflow_quota(bytes.parse()?);
+    }
+    if let Ok(bytes) = std::env::var("SWARMX_TENANT_QUOTA_BYTES") {
+        quotas = quotas.with_tenant_quota(bytes.parse()?);
+    }
+    let data_dir = std::env::var("SWARMX_DATA_DIR").unwrap_or_else(|_| "swarmx-data".into());
+    let mut store = swarmx_dataref::LocalDataStore::open(
+        &data_dir,
+        &std::env::var("SWARMX_DATA_URL").unwrap_or_else(|_| DEFAULT_DATA_URL.into()),
+    )?;
+    // Store identical uploads once, e.g. SWARMX_DATA_CONTENT_ADDRESSED=1
+    if std::env::var("SWARMX_DATA_CONTENT_ADDRESSED").is_ok_and(|v| v == "1") {
+        store = store.with_content_addressing();
+    }
+    // Compress JSON and text uploads with zstd, e.g.
+    // SWARMX_DATA_COMPRESSION_LEVEL=3
+    if let Ok(level) = std::env::var("SWARMX_DATA_COMPRESSION_LEVEL") {
+        store = store.with_compression(level.parse()?);
+    }
+    // Encrypt stored data with per-workflow keys wrapped by a master key,
+    // e.g. SWARMX_DATA_MASTER_KEY=<base64 of 32 bytes>
+    if let Some(provider) = data_key_provider_from_env()? {
+        store = store.with_encryption(std::sync::Arc::new(provider))?;
+    }
+    // Verify a sample of stored data against its checksums periodically,
+    // e.g. SWARMX_SCRUB_INTERVAL_SECS=3600
+    let mut scrub = swarmx_dataref::ScrubPolicy::new();
+    if let Ok(secs) = std::env::var("SWARMX_SCRUB_INTERVAL_SECS") {
+        scrub = scrub.with_interval(std::time::Duration::from_secs(secs.parse()?));
+    }
+    if let Ok(sample_size) = std::env::var("SWARMX_SCRUB_SAMPLE_SIZE") {
+        scrub = scrub.with_sample_size(sample_size.parse()?);
+    }
+    // Reconcile the catalog with server inventories periodically, e.g.
+    // SWARMX_GC_INTERVAL_SECS=900
+    let mut gc = swarmx_dataref::GcPolicy::new()
+        .with_dry_run(std::env::var("SWARMX_GC_DRY_RUN").is_ok_and(|v| v == "1"));
+    if let Ok(secs) = std::env::var("SWARMX_GC_INTERVAL_SECS") {
+        gc = gc.with_interval(std::time::Duration::from_secs(secs.parse()?));
+    }
+    if let Ok(secs) = std::env::var("SWARMX_GC_GRACE_SECS") {
+        gc = gc.with_grace_period(chrono::Duration::seconds(secs.parse()?));
+    }
+    if let Ok(max_deletions) = std::env::var("SWARMX_GC_MAX_DELETIONS") {
+        gc = gc.with_max_deletions(max_deletions.parse()?);
+    }
+    if let Ok(per_second) = std::env::var("SWARMX_GC_DELETE_RATE") {
+        gc = gc.with_delete_rate(per_second.parse()?);
+    }
+    // The data catalog is kept in the event log's database as well
+    let catalog =
+        swarmx_dataref::DataCatalog::persistent(swarmx_events::WriteAheadLog::open(&wal_path)?)?;
+    let state = AppState::with_events(
+        swarmx_events::EventBus::new(wal, bus_config)?,
+        progress,
+        webhooks,
+        swarmx_dataref::DataRefRegistry::default()
+            .with_lifecycle(lifecycle)
+            .with_quotas(quotas),
+        store,
+        catalog,
+        swarmx_dataref::Scrubber::new(scrub),
+    )
+    .with_gc(swarmx_dataref::GcCoordinator::new(gc));
+    // Share data with peer clusters, e.g. SWARMX_CLUSTER_NAME=us-west and
+    // SWARMX_FEDERATION_PEERS=eu-central=https://eu.example.com/api
+    let state = match federation_from_env()? {
+        Some(federation) => state.with_federation(federation),
+        None => state,
+    };
+    // Stage multipart uploads next to the stored data; uploads not
+    // completed within SWARMX_UPLOAD_TTL_HOURS, e.g. 24, are discarded
+    let mut uploads =
+        swarmx_dataref::MultipartUploads::open(std::path::Path::new(&data_dir).join("uploads"))?;
+    if let Ok(hours) = std::env::var("SWARMX_UPLOAD_TTL_HOURS") {
+        uploads = uploads.with_ttl(chrono::Duration::hours(hours.parse()?));
+    }
+    let state = state.with_uploads(uploads);
+    // Require access tokens on the data endpoints, e.g.
+    // SWARMX_DATA_TOKEN_KEY=<base64 of the secret tokens are signed with>
+    let state = match data_tokens_from_env(&wal_path)? {
+        Some(tokens) => state.with_tokens(tokens),
+        None => state,
+    };
+
+    // Apply default decisions to timed-out approval gates
+    tokio::spawn(approval_sweeper(state.clone()));
+    // Record queue depth and server load in the event log
+    tokio::spawn(metric_sampler(state.clone()));
+    // Mark servers that stopped sending heartbeats as unhealthy
+    tokio::spawn(server_health_monitor(state.clone()));
+
+    // Publish progress reports held back by coalescing
+    tokio::spawn(progress_flusher(state.clone()));
+    // Notify registered webhooks of committed events
+    tokio::spawn(webhook_dispatcher(state.clone()));
+    // Fire workflow triggers on committed events
+    tokio::spawn(trigger_engine(state.clone()));
+    // Delete unreferenced and expired data from the servers holding it
+    tokio::spawn(data_reaper(state.clone()));
+    // Quarantine stored data that no longer matches its checksum
+    tokio::spawn(data_scrubber(state.clone()));
+    // Delete leaked copies and uncatalog lost ones across the cluster
+    tokio::spawn(data_gc(state.clone()));
+    // Export spans derived from events to an OpenTelemetry collector
+    if let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        tokio::spawn(otel_exporter(state.clone(), endpoint));
+    }
 
     // Build the router
     let app = Router::new()
@@ -151,20 +475,95 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/executions", get(list_executions))
         .route("/api/executions/{id}", get(get_execution))
         .route("/api/executions/{id}/cancel", post(cancel_execution))
+        .route("/api/executions/{id}/events", get(stream_execution_events))
+        .route(
+            "/api/executions/{id}/nodes/{node_id}/approval",
+            post(resolve_approval),
+        )
+        .route(
+            "/api/executions/{id}/nodes/{node_id}/release",
+            post(release_node),
+        )
         // Task endpoints
         .route("/api/tasks/{id}", get(get_task_status))
         .route("/api/tasks/{id}/cancel", post(cancel_task))
         // Callback endpoint (receives from servers)
         .route("/api/callback", post(handle_callback))
         // Data endpoints
-        .route("/api/data/{uuid}", get(get_data).delete(delete_data))
+        .route("/api/data", get(find_data).post(upload_data))
+        .route(
+            "/api/data/{uuid}",
+            get(get_data).patch(write_data).delete(delete_data),
+        )
+        .route("/api/data/uploads", post(create_upload))
+        .route(
+            "/api/data/uploads/{id}",
+            get(get_upload).delete(abort_upload),
+        )
+        .route(
+            "/api/data/uploads/{id}/parts/{part_number}",
+            put(upload_part),
+        )
+        .route("/api/data/uploads/{id}/complete", post(complete_upload))
+        .route("/api/data/{uuid}/derive", post(derive_data))
+        .route("/api/data/{uuid}/access", get(list_data_access))
+        .route("/api/data/pinned", get(list_pinned_data))
+        .route("/api/data/{uuid}/pin", post(pin_data).delete(unpin_data))
+        .route("/api/data/{uuid}/leases", get(list_leases).post(acquire_lease))
+        .route("/api/data/{uuid}/leases/{lease_id}", delete(release_lease))
+        .route("/api/data/{uuid}/leases/{lease_id}/renew", post(renew_lease))
+        .route("/api/catalog/leases", get(list_active_leases))
+        .route("/api/catalog/locations", get(data_inventory))
+        .route("/api/catalog/orphans", get(list_orphaned_data))
+        .route("/api/catalog/{uuid}", get(get_data_ref))
+        .route("/api/workflows/{id}/data", get(list_workflow_data))
+        .route(
+            "/api/workflows/{id}/data/pin",
+            post(pin_workflow_data).delete(unpin_workflow_data),
+        )
+        .route("/api/workflows/{id}/series", get(list_series))
+        .route("/api/workflows/{id}/series/{name}", get(get_series))
+        .route(
+            "/api/workflows/{id}/series/{name}/versions",
+            post(append_version),
+        )
+        .route(
+            "/api/workflows/{id}/series/{name}/versions/{version}",
+            get(get_version),
+        )
+        .route(
+            "/api/workflows/{id}/series/{name}/latest",
+            get(get_latest_version),
+        )
+        .route("/api/workflows/{id}/series/{name}/diff", get(diff_versions))
+        // Admin endpoints
+        .route("/api/admin/workflows/{id}/replay", get(replay_workflow))
+        .route("/api/admin/audit", get(list_audit_records))
+        .route("/api/admin/backup", post(backup_event_log))
+        .route("/api/admin/restore", post(restore_event_log))
+        .route("/api/admin/scrub", get(get_scrub_status).post(run_scrub))
+        .route("/api/admin/gc", get(get_gc_status).post(run_gc))
+        // Cross-cluster data federation
+        .route("/api/federation/clusters", get(list_clusters))
+        .route("/api/federation/token", post(exchange_token))
+        .route("/api/federation/data/{uuid}", get(get_federated_data))
+        .route("/api/federation/import", post(import_data))
+        // Webhooks
+        .route("/api/webhooks", get(list_webhooks).post(register_webhook))
+        .route("/api/webhooks/{id}", delete(delete_webhook))
+        .route("/api/webhooks/{id}/deliveries", get(list_webhook_deliveries))
         // Server registry
         .route("/api/servers", get(list_servers).post(register_server))
+        .route("/api/servers/heartbeat", post(record_heartbeat))
+        .route("/api/servers/inventory", post(report_inventory))
         .route("/api/servers/{address}", delete(unregister_server))
+        // Event streaming over WebSocket
+        .route("/ws", get(ws_handler))
         // Health check
         .route("/health", get(health_check))
         .route("/api/health", get(health_check))
         // Add middleware
+        .layer(axum::middleware::from_fn(negotiate_version))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
         .with_state(state);
@@ -179,6 +578,104 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Minimum event severity from an environment variable, `debug` if unset
+fn severity_from_env(name: &str) -> anyhow::Result<swarmx_events::Severity> {
+    match std::env::var(name) {
+        Ok(value) => Ok(value.parse()?),
+        Err(_) => Ok(swarmx_events::Severity::Debug),
+    }
+}
+
+/// Protection of sensitive event fields, if `SWARMX_FIELD_KEY` is set
+///
+/// The key is 32 bytes, base64-encoded. `SWARMX_PROTECTED_FIELDS` lists the
+/// fields to protect, see [`swarmx_events::FieldProtection::with_rules`].
+fn field_protection_from_env() -> anyhow::Result<Option<swarmx_events::FieldProtection>> {
+    use base64::Engine;
+
+    let Ok(key) = std::env::var("SWARMX_FIELD_KEY") else {
+        return Ok(None);
+    };
+    let key = base64::engine::general_purpose::STANDARD.decode(key.trim())?;
+    let key_id = std::env::var("SWARMX_FIELD_KEY_ID").unwrap_or_else(|_| "k1".into());
+    let rules = std::env::var("SWARMX_PROTECTED_FIELDS").unwrap_or_default();
+    Ok(Some(
+        swarmx_events::FieldProtection::new(&key_id, &key)?.with_rules(&rules)?,
+    ))
+}
+
+/// Master key for data encryption at rest, if `SWARMX_DATA_MASTER_KEY` is
+/// set
+///
+/// The key is 32 bytes, base64-encoded.
+fn data_key_provider_from_env() -> anyhow::Result<Option<swarmx_dataref::StaticKeyProvider>> {
+    use base64::Engine;
+
+    let Ok(key) = std::env::var("SWARMX_DATA_MASTER_KEY") else {
+        return Ok(None);
+    };
+    let key = base64::engine::general_purpose::STANDARD.decode(key.trim())?;
+    let key_id = std::env::var("SWARMX_DATA_MASTER_KEY_ID").unwrap_or_else(|_| "m1".into());
+    Ok(Some(swarmx_dataref::StaticKeyProvider::new(&key_id, &key)?))
+}
+
+/// Verifier of data access tokens, if `SWARMX_DATA_TOKEN_KEY` or
+/// `SWARMX_DATA_TOKEN_PUBLIC_KEY` is set
+///
+/// `SWARMX_DATA_TOKEN_KEY` is the base64-encoded HMAC secret clients sign
+/// tokens with, `SWARMX_DATA_TOKEN_PUBLIC_KEY` the base64-encoded public
+/// key of an Ed25519 signing key; `SWARMX_DATA_TOKEN_KEY_ID` names the key
+/// tokens carry. Revocations are kept in the WAL database at `wal_path`, so
+/// revoked tokens stay rejected across restarts.
+fn data_tokens_from_env(wal_path: &str) -> anyhow::Result<Option<swarmx_dataref::TokenManager>> {
+    use base64::Engine;
+
+    let key_id = std::env::var("SWARMX_DATA_TOKEN_KEY_ID").unwrap_or_else(|_| "k1".into());
+    let decode = |key: String| base64::engine::general_purpose::STANDARD.decode(key.trim());
+    let revocations = || -> anyhow::Result<_> {
+        let wal = swarmx_events::WriteAheadLog::open(wal_path)?;
+        Ok(swarmx_dataref::RevocationList::persistent(wal)?)
+    };
+    let tokens = swarmx_dataref::TokenManager::verifier("swarmx-api".to_string());
+    if let Ok(key) = std::env::var("SWARMX_DATA_TOKEN_KEY") {
+        let tokens = tokens.with_verification_key(&key_id, &decode(key)?)?;
+        return Ok(Some(tokens.with_revocation_list(revocations()?)));
+    }
+    if let Ok(key) = std::env::var("SWARMX_DATA_TOKEN_PUBLIC_KEY") {
+        let tokens = tokens.with_public_key(&key_id, &decode(key)?)?;
+        return Ok(Some(tokens.with_revocation_list(revocations()?)));
+    }
+    Ok(None)
+}
+
+/// Federation with peer clusters, if `SWARMX_CLUSTER_NAME` is set
+///
+/// `SWARMX_FEDERATION_KEY` is the base64-encoded secret federation tokens
+/// are signed with, shared by all federated clusters.
+/// `SWARMX_FEDERATION_PEERS` lists the peers as comma-separated
+/// `name=endpoint` pairs.
+fn federation_from_env() -> anyhow::Result<Option<swarmx_dataref::Federation>> {
+    use base64::Engine;
+
+    let Ok(cluster) = std::env::var("SWARMX_CLUSTER_NAME") else {
+        return Ok(None);
+    };
+    let key = std::env::var("SWARMX_FEDERATION_KEY").map_err(|_| {
+        anyhow::anyhow!("SWARMX_FEDERATION_KEY is required with SWARMX_CLUSTER_NAME")
+    })?;
+    let key = base64::engine::general_purpose::STANDARD.decode(key.trim())?;
+    let mut federation = swarmx_dataref::Federation::new(&cluster, &key)?;
+    let peers = std::env::var("SWARMX_FEDERATION_PEERS").unwrap_or_default();
+    for peer in peers.split(',').map(str::trim).filter(|peer| !peer.is_empty()) {
+        let (name, endpoint) = peer.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid federation peer {peer:?}, expected name=endpoint")
+        })?;
+        let peer = swarmx_dataref::RemoteCluster::new(name.trim(), endpoint.trim());
+        federation = federation.with_peer(peer, &key)?;
+    }
+    Ok(Some(federation))
+}
+
 /// Health check endpoint
 async fn health_check() -> &'static str {
     "OK"
@@ -0,0 +1,53 @@
+//! Periodic metric sampling
+//!
+//! A background task appends an `Event::MetricSample` to the event log at a
+//! fixed interval, so queue depth and server load can be charted and
+//! aggregated from the WAL like any other event.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use swarmx_core::NodeState;
+use swarmx_events::Event;
+
+use crate::AppState;
+
+/// How often a metric sample is recorded
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Record a metric sample every [`SAMPLE_INTERVAL`]
+pub async fn metric_sampler(state: AppState) {
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let (mut queue_depth, mut running_nodes) = (0, 0);
+        {
+            let executions = state.inner.executions.read().await;
+            for execution in executions.executions.values() {
+                let snapshot = execution.context.snapshot();
+                queue_depth += snapshot.count_in_state(NodeState::Scheduled)
+                    + snapshot.count_in_state(NodeState::Retrying);
+                running_nodes += snapshot.count_in_state(NodeState::Running);
+            }
+        }
+        let server_loads: BTreeMap<String, f64> = {
+            let servers = state.inner.servers.read().await;
+            servers
+                .servers
+                .iter()
+                .map(|(address, info)| (address.clone(), info.current_load))
+                .collect()
+        };
+
+        let sample = Event::MetricSample {
+            queue_depth: queue_depth as u64,
+            running_nodes: running_nodes as u64,
+            server_loads,
+            timestamp: chrono::Utc::now(),
+        };
+        if let Err(e) = state.inner.events.publish(sample).await {
+            tracing::warn!("Failed to record metric sample: {e}");
+        }
+    }
+}
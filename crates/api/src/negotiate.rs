@@ -0,0 +1,143 @@
+//! Content negotiation for protocol messages
+//!
+//! Endpoints that servers call with protocol messages take them as JSON,
+//! MessagePack, or CBOR, as named by the request's `Content-Type` (JSON if
+//! it is missing), with the [`WireBody`] extractor, and answer in the
+//! format the request's `Accept` prefers with [`AcceptFormat`] and
+//! [`Negotiated`]. A body in any other format is refused with `415`, and
+//! an `Accept` header that allows none of the formats with `406`. Data
+//! endpoints keep their payloads in the data's own content type and do not
+//! negotiate.
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use swarmx_protocol::{ApiResponse, WireFormat};
+
+/// Request body decoded from the format its `Content-Type` names
+#[derive(Debug, Clone)]
+pub struct WireBody<T>(pub T);
+
+impl<T, S> FromRequest<S> for WireBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = NegotiationRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = match req.headers().get(header::CONTENT_TYPE) {
+            Some(content_type) => content_type
+                .to_str()
+                .ok()
+                .and_then(WireFormat::from_content_type)
+                .ok_or_else(|| {
+                    NegotiationRejection::new(
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "UNSUPPORTED_CONTENT_TYPE",
+                        format!("Content type {content_type:?} is not JSON, MessagePack, or CBOR"),
+                    )
+                })?,
+            None => WireFormat::Json,
+        };
+        let body = Bytes::from_request(req, state).await.map_err(|e| {
+            NegotiationRejection::new(StatusCode::BAD_REQUEST, "INVALID_BODY", e.body_text())
+        })?;
+        format
+            .decode(&body)
+            .map(Self)
+            .map_err(|e| NegotiationRejection::new(StatusCode::BAD_REQUEST, "INVALID_BODY", e))
+    }
+}
+
+/// Format a response should be sent in, by the request's `Accept` header
+///
+/// JSON if the header is missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AcceptFormat(pub WireFormat);
+
+impl<S: Send + Sync> FromRequestParts<S> for AcceptFormat {
+    type Rejection = NegotiationRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(accept) = parts.headers.get(header::ACCEPT) else {
+            return Ok(Self::default());
+        };
+        accept
+            .to_str()
+            .ok()
+            .and_then(WireFormat::negotiate)
+            .map(Self)
+            .ok_or_else(|| {
+                NegotiationRejection::new(
+                    StatusCode::NOT_ACCEPTABLE,
+                    "NOT_ACCEPTABLE",
+                    "Responses are sent as JSON, MessagePack, or CBOR",
+                )
+            })
+    }
+}
+
+/// Response body encoded in the negotiated format
+#[derive(Debug, Clone)]
+pub struct Negotiated<T> {
+    pub format: WireFormat,
+    pub body: T,
+}
+
+impl<T> Negotiated<T> {
+    /// Send `body` in the format of `accept`
+    pub fn new(AcceptFormat(format): AcceptFormat, body: T) -> Self {
+        Self { format, body }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        match self.format.encode(&self.body) {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(self.format.content_type()),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(e) => {
+                NegotiationRejection::new(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", e)
+                    .into_response()
+            }
+        }
+    }
+}
+
+/// Refusal of a body or `Accept` header
+#[derive(Debug, Clone)]
+pub struct NegotiationRejection {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl NegotiationRejection {
+    fn new(status: StatusCode, code: &'static str, message: impl ToString) -> Self {
+        Self {
+            status,
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for NegotiationRejection {
+    fn into_response(self) -> Response {
+        let body = ApiResponse::<()>::error(self.code, &self.message);
+        (self.status, Json(body)).into_response()
+    }
+}
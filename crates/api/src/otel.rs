@@ -0,0 +1,85 @@
+//! OpenTelemetry trace export
+//!
+//! When `OTEL_EXPORTER_OTLP_ENDPOINT` is set, [`otel_exporter`] turns
+//! committed events into spans and POSTs them to the collector's
+//! OTLP/HTTP JSON endpoint, so workflow executions show up in Jaeger or
+//! Tempo with per-node queue, scheduling, and run phases.
+
+use std::time::Duration;
+
+use swarmx_events::{otlp_json, BusError, EventFilter, Span, SpanCollector};
+
+use crate::AppState;
+
+/// `service.name` reported with exported spans
+const SERVICE_NAME: &str = "swarmx-api";
+
+/// Spans are exported at least this often while any are pending
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Pending spans that trigger an export before the interval elapses
+const MAX_BATCH: usize = 512;
+
+/// Timeout for a single export request
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Export spans derived from committed events to an OTLP collector
+///
+/// `endpoint` is the collector's base URL, e.g. `http://localhost:4318`.
+/// Runs until the event bus closes. A failed export is logged and its
+/// spans dropped, so a collector outage never backs up the event stream.
+pub async fn otel_exporter(state: AppState, endpoint: String) {
+    let client = match reqwest::Client::builder().timeout(EXPORT_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to create OTLP HTTP client: {e}");
+            return;
+        }
+    };
+    let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+
+    let mut subscription = state.inner.events.subscribe(EventFilter::new());
+    let mut collector = SpanCollector::new();
+    let mut pending: Vec<Span> = Vec::new();
+    let mut ticker = tokio::time::interval(EXPORT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            received = subscription.recv() => match received {
+                Ok(envelope) => {
+                    pending.extend(collector.record(&envelope));
+                    if pending.len() < MAX_BATCH {
+                        continue;
+                    }
+                }
+                Err(BusError::Closed) => {
+                    export(&client, &url, &mut pending).await;
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!("OTLP exporter missed events: {e}");
+                    continue;
+                }
+            },
+            _ = ticker.tick() => {}
+        }
+        export(&client, &url, &mut pending).await;
+    }
+}
+
+/// POST pending spans to the collector, emptying `pending`
+async fn export(client: &reqwest::Client, url: &str, pending: &mut Vec<Span>) {
+    if pending.is_empty() {
+        return;
+    }
+    let spans = std::mem::take(pending);
+    let result = client
+        .post(url)
+        .json(&otlp_json(SERVICE_NAME, &spans))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        tracing::warn!(spans = spans.len(), "Failed to export spans: {e}");
+    }
+}
@@ -0,0 +1,132 @@
+//! Ordering of streamed task output
+//!
+//! Servers stream output with `partial_output` callbacks, which may arrive
+//! out of order or more than once. [`PartialOutputBuffer`] puts the chunks
+//! of each output back in sequence order so they are published as
+//! `node_output_chunk` events in the order they were produced. Only the
+//! position in each stream and the chunks received ahead of a gap are
+//! kept; the complete output arrives with the task's completion.
+
+use std::collections::{BTreeMap, HashMap};
+
+use uuid::Uuid;
+
+/// How far ahead of the next expected chunk a chunk may be
+///
+/// Bounds the chunks held per output while waiting for a lost one.
+pub const MAX_SEQUENCE_GAP: u64 = 256;
+
+/// Chunk too far ahead of the stream to be held
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("chunk {sequence} is {MAX_SEQUENCE_GAP} or more chunks ahead of chunk {next}")]
+pub struct SequenceGapError {
+    pub sequence: u64,
+    pub next: u64,
+}
+
+/// Puts chunks streamed by running tasks back in order
+#[derive(Debug, Default)]
+pub struct PartialOutputBuffer {
+    outputs: HashMap<(Uuid, String), StreamedOutput>,
+}
+
+/// Position in one output stream
+#[derive(Debug, Default)]
+struct StreamedOutput {
+    /// Sequence number of the next chunk to release
+    next: u64,
+    /// Chunks received ahead of a gap
+    held: BTreeMap<u64, String>,
+}
+
+impl PartialOutputBuffer {
+    /// Create an empty buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk of a task's output
+    ///
+    /// Returns the chunks the chunk made available, in order with their
+    /// sequence numbers: the chunk and any held chunks following it, or
+    /// none if it is early or repeated. A chunk [`MAX_SEQUENCE_GAP`] or
+    /// more ahead of the next expected one is refused.
+    pub fn push(
+        &mut self,
+        task_id: Uuid,
+        output: &str,
+        sequence: u64,
+        chunk: String,
+    ) -> Result<Vec<(u64, String)>, SequenceGapError> {
+        let streamed = self
+            .outputs
+            .entry((task_id, output.to_string()))
+            .or_default();
+        if sequence < streamed.next {
+            return Ok(Vec::new());
+        }
+        if sequence - streamed.next >= MAX_SEQUENCE_GAP {
+            return Err(SequenceGapError {
+                sequence,
+                next: streamed.next,
+            });
+        }
+        streamed.held.insert(sequence, chunk);
+        let mut released = Vec::new();
+        while let Some(chunk) = streamed.held.remove(&streamed.next) {
+            released.push((streamed.next, chunk));
+            streamed.next += 1;
+        }
+        Ok(released)
+    }
+
+    /// Forget every output of a task once it completes, fails, or is
+    /// cancelled
+    pub fn finish(&mut self, task_id: Uuid) {
+        self.outputs.retain(|(id, _), _| *id != task_id);
+    }
+
+    /// Number of outputs being streamed
+    pub fn open_streams(&self) -> usize {
+        self.outputs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_are_released_in_order() {
+        let task_id = Uuid::new_v4();
+        let mut buffer = PartialOutputBuffer::new();
+        let mut push = |output: &str, sequence: u64, chunk: &str| {
+            buffer.push(task_id, output, sequence, chunk.to_string())
+        };
+
+        // Out of order and repeated chunks are released once, in order
+        assert_eq!(push("text", 0, "Hel"), Ok(vec![(0, "Hel".to_string())]));
+        assert_eq!(push("text", 2, " wor"), Ok(Vec::new()));
+        assert_eq!(push("text", 0, "Hel"), Ok(Vec::new()));
+        assert_eq!(
+            push("text", 1, "lo"),
+            Ok(vec![(1, "lo".to_string()), (2, " wor".to_string())])
+        );
+        assert_eq!(push("reasoning", 0, "Hm"), Ok(vec![(0, "Hm".to_string())]));
+
+        // Chunks far ahead of a gap are not held
+        assert_eq!(
+            push("text", u64::MAX, "!"),
+            Err(SequenceGapError {
+                sequence: u64::MAX,
+                next: 3
+            })
+        );
+        assert!(push("text", 3 + MAX_SEQUENCE_GAP, "!").is_err());
+        assert_eq!(push("text", 2 + MAX_SEQUENCE_GAP, "!"), Ok(Vec::new()));
+
+        assert_eq!(buffer.open_streams(), 2);
+        buffer.finish(task_id);
+        assert_eq!(buffer.open_streams(), 0);
+    }
+}
@@ -0,0 +1,142 @@
+//! Data cleanup
+//!
+//! [`data_reaper`] keeps the data registry and catalog in step with the
+//! event stream, releasing data as nodes complete and workflows terminate,
+//! and periodically deletes data that is no longer referenced, whose TTL
+//! has passed, or whose workflow's retention has passed. Data is deleted on
+//! every server holding a copy and then recorded with a `data_deleted`
+//! event; data a server failed to delete is retried on the next pass.
+//! Data with an active read lease waits until the lease is released or
+//! expires, so cleanup does not pull data out from under a transfer.
+//! Expired multipart uploads are discarded on the same schedule.
+
+use std::time::Duration;
+
+use swarmx_dataref::{DataRef, DataStoreError};
+use swarmx_events::{BusError, EventFilter, EventKind};
+
+use crate::{expire_uploads, with_store, AppState};
+
+/// How often collectable data is deleted
+const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time allowed for a server to delete one piece of data
+const DELETE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Release and delete distributed data
+///
+/// Runs until the event bus closes.
+pub async fn data_reaper(state: AppState) {
+    let client = match delete_client() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to create data reaper HTTP client: {e}");
+            return;
+        }
+    };
+
+    let mut subscription = state.inner.events.subscribe(EventFilter::new().kinds([
+        EventKind::WorkflowStarted,
+        EventKind::WorkflowCompleted,
+        EventKind::WorkflowFailed,
+        EventKind::WorkflowCancelled,
+        EventKind::NodeCompleted,
+        EventKind::DataReplicated,
+        EventKind::DataReplicaRemoved,
+    ]));
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+    loop {
+        tokio::select! {
+            received = subscription.recv() => match received {
+                Ok(envelope) => {
+                    state.inner.data.write().await.apply(&envelope.event);
+                    if let Err(e) = state.inner.catalog.apply(&envelope.event) {
+                        tracing::warn!("Failed to update data catalog: {e}");
+                    }
+                }
+                Err(BusError::Closed) => return,
+                Err(e) => tracing::warn!("Data reaper missed events: {e}"),
+            },
+            _ = interval.tick() => reap(&state, &client).await,
+        }
+    }
+}
+
+/// Delete everything collectable and record the deletions
+async fn reap(state: &AppState, client: &reqwest::Client) {
+    if let Some(uploads) = &state.inner.uploads {
+        expire_uploads(uploads);
+    }
+    let collected = state.inner.data.write().await.collect(chrono::Utc::now());
+    for data_ref in collected {
+        match delete(state, client, &data_ref).await {
+            Ok(()) => {
+                if let Err(e) = state.inner.catalog.remove(&data_ref.uuid) {
+                    tracing::warn!(data_uuid = %data_ref.uuid, "Failed to remove catalog entry: {e}");
+                }
+                if let Err(e) = state.inner.events.publish(data_ref.deleted_event()).await {
+                    tracing::warn!(data_uuid = %data_ref.uuid, "Failed to record data deletion: {e}");
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    data_uuid = %data_ref.uuid,
+                    location = %data_ref.location,
+                    "Failed to delete data, will retry: {e}"
+                );
+                state.inner.data.write().await.requeue(data_ref);
+            }
+        }
+    }
+}
+
+/// HTTP client for asking servers to delete data
+pub fn delete_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(DELETE_TIMEOUT).build()
+}
+
+/// Ask every server holding a copy of data to delete it; copies already
+/// gone count as deleted
+async fn delete(
+    state: &AppState,
+    client: &reqwest::Client,
+    data_ref: &DataRef,
+) -> Result<(), DeleteError> {
+    for location in data_ref.locations() {
+        delete_copy(state, client, location, data_ref.uuid).await?;
+    }
+    Ok(())
+}
+
+/// Ask the server at `location` to delete its copy of data; a copy already
+/// gone counts as deleted
+///
+/// Copies in the API server's own store are deleted directly.
+pub async fn delete_copy(
+    state: &AppState,
+    client: &reqwest::Client,
+    location: &str,
+    uuid: uuid::Uuid,
+) -> Result<(), DeleteError> {
+    if location == state.inner.store.location() {
+        return match with_store(state, move |store| store.delete(&uuid)).await {
+            Ok(_) | Err(DataStoreError::NotFound(_)) => Ok(()),
+            Err(e) => Err(e.into()),
+        };
+    }
+    let url = format!("{}/data/{}", location.trim_end_matches('/'), uuid);
+    let response = client.delete(&url).send().await?;
+    if response.status() != reqwest::StatusCode::NOT_FOUND {
+        response.error_for_status()?;
+    }
+    Ok(())
+}
+
+/// Failure to delete a copy of data
+#[derive(Debug, thiserror::Error)]
+pub enum DeleteError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Store(#[from] DataStoreError),
+}
@@ -0,0 +1,83 @@
+//! Data integrity scrubbing
+//!
+//! [`data_scrubber`] runs the [`Scrubber`](swarmx_dataref::Scrubber) over
+//! the local data store on its policy's interval, recording a
+//! `data_corrupted` event for every piece of data it quarantines and a
+//! `data_scrub_completed` event per run. `GET /api/admin/scrub` reports
+//! the scrub status and `POST /api/admin/scrub` scrubs right away.
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Utc;
+
+use swarmx_dataref::{DataStoreError, ScrubReport, ScrubStatus};
+use swarmx_events::Event;
+use swarmx_protocol::ApiResponse;
+
+use crate::{with_store, AppState};
+
+/// Scrub the local data store periodically
+pub async fn data_scrubber(state: AppState) {
+    let period = state.inner.scrubber.policy().interval;
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = scrub(&state).await {
+            tracing::warn!("Data scrub failed: {e}");
+        }
+    }
+}
+
+/// Scrub the next sample of stored data and record the outcome
+async fn scrub(state: &AppState) -> Result<ScrubReport, DataStoreError> {
+    let scrubber = state.inner.scrubber.clone();
+    let report = with_store(state, move |store| Ok(scrubber.run(store))).await?;
+    let location = state.inner.store.location().to_string();
+    for corrupt in &report.corrupted {
+        let data_ref = &corrupt.data_ref;
+        tracing::error!(
+            data_uuid = %data_ref.uuid,
+            reason = %corrupt.reason,
+            "Quarantined corrupt data"
+        );
+        let corrupted = Event::DataCorrupted {
+            data_uuid: data_ref.uuid,
+            workflow_id: data_ref.workflow_id,
+            location: location.clone(),
+            expected_checksum: data_ref.checksum.clone(),
+            actual_checksum: corrupt.actual_checksum.clone(),
+            timestamp: corrupt.detected_at,
+        };
+        if let Err(e) = state.inner.events.publish(corrupted).await {
+            tracing::warn!(data_uuid = %data_ref.uuid, "Failed to record data corruption: {e}");
+        }
+    }
+    let completed = Event::DataScrubCompleted {
+        location,
+        checked: report.checked,
+        corrupted: report.corrupted.len() as u64,
+        duration_ms: report.duration_ms,
+        timestamp: Utc::now(),
+    };
+    if let Err(e) = state.inner.events.publish(completed).await {
+        tracing::warn!("Failed to record data scrub: {e}");
+    }
+    Ok(report)
+}
+
+/// Get the scrub status, including all data quarantined so far
+pub async fn get_scrub_status(State(state): State<AppState>) -> Json<ApiResponse<ScrubStatus>> {
+    Json(ApiResponse::success(state.inner.scrubber.status()))
+}
+
+/// Scrub the next sample of stored data now
+pub async fn run_scrub(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<ApiResponse<ScrubReport>>) {
+    match scrub(&state).await {
+        Ok(report) => (StatusCode::OK, Json(ApiResponse::success(report))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("STORAGE_ERROR", &e.to_string())),
+        ),
+    }
+}
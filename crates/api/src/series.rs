@@ -0,0 +1,190 @@
+//! Data series endpoints
+//!
+//! A workflow names an artifact it refines over time with a
+//! [`DataSeries`]. `POST /api/workflows/{id}/series/{name}/versions`
+//! appends cataloged data of the workflow as the next version, creating
+//! the series on its first version; the latest version, any earlier one,
+//! and the metadata changes between two versions are read back by name.
+//! Data that is a version can no longer be written; write a derived copy
+//! and append that instead.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_dataref::{DataSeries, SeriesError, SeriesVersion, VersionDiff};
+use swarmx_protocol::ApiResponse;
+
+use crate::AppState;
+
+/// A series without its history
+#[derive(Debug, Serialize)]
+pub struct SeriesSummary {
+    pub name: String,
+    pub workflow_id: Uuid,
+    /// Number of versions
+    pub versions: u32,
+    /// Newest version
+    pub latest: Option<SeriesVersion>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&DataSeries> for SeriesSummary {
+    fn from(series: &DataSeries) -> Self {
+        Self {
+            name: series.name.clone(),
+            workflow_id: series.workflow_id,
+            versions: series.versions.len() as u32,
+            latest: series.latest().cloned(),
+            created_at: series.created_at,
+        }
+    }
+}
+
+/// Request to append a version
+#[derive(Debug, Deserialize)]
+pub struct AppendVersionRequest {
+    /// Cataloged data of the series' workflow
+    pub data_uuid: Uuid,
+}
+
+/// Versions to compare; the latest and the one before by default
+#[derive(Debug, Deserialize)]
+pub struct DiffParams {
+    #[serde(default)]
+    pub from: Option<u32>,
+    #[serde(default)]
+    pub to: Option<u32>,
+}
+
+/// List the series of a workflow, by name
+pub async fn list_series(
+    State(state): State<AppState>,
+    Path(workflow_id): Path<Uuid>,
+) -> Json<ApiResponse<Vec<SeriesSummary>>> {
+    let series = state.inner.series.read().await;
+    Json(ApiResponse::success(
+        series
+            .by_workflow(workflow_id)
+            .into_iter()
+            .map(SeriesSummary::from)
+            .collect(),
+    ))
+}
+
+/// Get a series with every version
+pub async fn get_series(
+    State(state): State<AppState>,
+    Path((workflow_id, name)): Path<(Uuid, String)>,
+) -> (StatusCode, Json<ApiResponse<DataSeries>>) {
+    match state.inner.series.read().await.get(workflow_id, &name) {
+        Ok(series) => (StatusCode::OK, Json(ApiResponse::success(series.clone()))),
+        Err(e) => series_error(e),
+    }
+}
+
+/// Append cataloged data as the next version of a series
+pub async fn append_version(
+    State(state): State<AppState>,
+    Path((workflow_id, name)): Path<(Uuid, String)>,
+    Json(request): Json<AppendVersionRequest>,
+) -> (StatusCode, Json<ApiResponse<SeriesVersion>>) {
+    let Some(data_ref) = state.inner.catalog.get(&request.data_uuid) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Data not found")),
+        );
+    };
+    if data_ref.workflow_id != workflow_id {
+        return series_error(SeriesError::WrongWorkflow {
+            data_uuid: data_ref.uuid,
+            expected: workflow_id,
+            actual: data_ref.workflow_id,
+        });
+    }
+    match state.inner.series.write().await.append(&name, data_ref) {
+        Ok(version) => {
+            tracing::info!(
+                %workflow_id,
+                series = %name,
+                version = version.version,
+                data_uuid = %version.data_ref.uuid,
+                "Appended series version"
+            );
+            (
+                StatusCode::CREATED,
+                Json(ApiResponse::success(version.clone())),
+            )
+        }
+        Err(e) => series_error(e),
+    }
+}
+
+/// Get the newest version of a series
+pub async fn get_latest_version(
+    State(state): State<AppState>,
+    Path((workflow_id, name)): Path<(Uuid, String)>,
+) -> (StatusCode, Json<ApiResponse<SeriesVersion>>) {
+    let series = state.inner.series.read().await;
+    let latest = series.get(workflow_id, &name).and_then(|series| {
+        series.latest().ok_or(SeriesError::VersionNotFound {
+            series: name.clone(),
+            version: 1,
+        })
+    });
+    match latest {
+        Ok(version) => (StatusCode::OK, Json(ApiResponse::success(version.clone()))),
+        Err(e) => series_error(e),
+    }
+}
+
+/// Get one version of a series
+pub async fn get_version(
+    State(state): State<AppState>,
+    Path((workflow_id, name, version)): Path<(Uuid, String, u32)>,
+) -> (StatusCode, Json<ApiResponse<SeriesVersion>>) {
+    let series = state.inner.series.read().await;
+    match series
+        .get(workflow_id, &name)
+        .and_then(|series| series.version(version))
+    {
+        Ok(version) => (StatusCode::OK, Json(ApiResponse::success(version.clone()))),
+        Err(e) => series_error(e),
+    }
+}
+
+/// Compare the metadata of two versions of a series
+pub async fn diff_versions(
+    State(state): State<AppState>,
+    Path((workflow_id, name)): Path<(Uuid, String)>,
+    Query(params): Query<DiffParams>,
+) -> (StatusCode, Json<ApiResponse<VersionDiff>>) {
+    let series = state.inner.series.read().await;
+    let diff = series.get(workflow_id, &name).and_then(|series| {
+        let to = params.to.unwrap_or(series.versions.len() as u32);
+        let from = params.from.unwrap_or(to.saturating_sub(1).max(1));
+        series.diff(from, to)
+    });
+    match diff {
+        Ok(diff) => (StatusCode::OK, Json(ApiResponse::success(diff))),
+        Err(e) => series_error(e),
+    }
+}
+
+/// Response for a failed series request
+fn series_error<T>(e: SeriesError) -> (StatusCode, Json<ApiResponse<T>>) {
+    let (status, code) = match &e {
+        SeriesError::NotFound(_) | SeriesError::VersionNotFound { .. } => {
+            (StatusCode::NOT_FOUND, "NOT_FOUND")
+        }
+        SeriesError::InvalidName(_) => (StatusCode::BAD_REQUEST, "INVALID_NAME"),
+        SeriesError::WrongWorkflow { .. } => (StatusCode::BAD_REQUEST, "WRONG_WORKFLOW"),
+        SeriesError::AlreadyVersioned { .. } => (StatusCode::CONFLICT, "ALREADY_VERSIONED"),
+    };
+    (status, Json(ApiResponse::error(code, &e.to_string())))
+}
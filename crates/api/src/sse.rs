@@ -0,0 +1,282 @@
+//! Server-Sent Events stream of workflow events
+//!
+//! `GET /api/executions/{id}/events` pushes the execution's events as they
+//! are committed to the WAL, replacing status polling in the UI. Each SSE
+//! message carries the WAL sequence number as its ID, so a reconnecting
+//! client that sends `Last-Event-ID` resumes exactly where it left off.
+
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+    Json,
+};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::AppState;
+use swarmx_events::{EventEnvelope, EventFilter, EventKind, EventSubscription, UnknownEventKind};
+use swarmx_protocol::ApiResponse;
+
+/// Interval between keep-alive comments on idle streams
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Event stream query parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct EventStreamParams {
+    /// Only stream events for this node
+    pub node_id: Option<Uuid>,
+    /// Comma-separated event types, e.g. `node_completed,node_failed`
+    pub types: Option<String>,
+}
+
+impl EventStreamParams {
+    /// Build the subscription filter for a workflow
+    pub fn filter(&self, workflow_id: Uuid) -> Result<EventFilter, UnknownEventKind> {
+        let mut filter = EventFilter::new().workflow(workflow_id);
+        if let Some(node_id) = self.node_id {
+            filter = filter.node(node_id);
+        }
+        if let Some(types) = &self.types {
+            let kinds = types
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<EventKind>, _>>()?;
+            filter = filter.kinds(kinds);
+        }
+        Ok(filter)
+    }
+}
+
+/// Stream an execution's events as Server-Sent Events
+///
+/// The stream ends after the workflow's terminal event.
+pub async fn stream_execution_events(
+    State(state): State<AppState>,
+    Path(execution_id): Path<Uuid>,
+    Query(params): Query<EventStreamParams>,
+    headers: HeaderMap,
+) -> Result<
+    Sse<impl Stream<Item = Result<SseEvent, Infallible>>>,
+    (StatusCode, Json<ApiResponse<()>>),
+> {
+    let workflow_id = {
+        let executions = state.inner.executions.read().await;
+        executions.get(&execution_id).map(|e| e.workflow_id)
+    };
+    let Some(workflow_id) = workflow_id else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Execution not found")),
+        ));
+    };
+
+    let filter = params.filter(workflow_id).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("INVALID_EVENT_TYPE", &e.to_string())),
+        )
+    })?;
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+
+    let subscription = match last_event_id {
+        Some(sequence) => state
+            .inner
+            .events
+            .subscribe_from(filter, sequence.saturating_add(1))
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+                )
+            })?,
+        None => state.inner.events.subscribe(filter),
+    };
+
+    Ok(Sse::new(event_stream(subscription))
+        .keep_alive(KeepAlive::new().interval(KEEP_ALIVE_INTERVAL)))
+}
+
+/// Turn a subscription into an SSE stream that ends after a terminal event
+fn event_stream(
+    subscription: EventSubscription,
+) -> impl Stream<Item = Result<SseEvent, Infallible>> {
+    stream::unfold(Some(subscription), |subscription| async move {
+        let mut subscription = subscription?;
+        match subscription.recv().await {
+            Ok(envelope) => {
+                let done = envelope.event.is_workflow_terminal();
+                let event = to_sse_event(&envelope);
+                Some((Ok(event), (!done).then_some(subscription)))
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "event stream ended");
+                None
+            }
+        }
+    })
+}
+
+/// Encode an envelope as an SSE message
+pub fn to_sse_event(envelope: &EventEnvelope) -> SseEvent {
+    let mut event = SseEvent::default().event(envelope.event.event_type());
+    // Transient events have no place in the log to resume from
+    if !envelope.is_transient() {
+        event = event.id(envelope.sequence.to_string());
+    }
+    match serde_json::to_string(envelope) {
+        Ok(data) => event.data(data),
+        Err(e) => event.comment(format!("failed to encode event: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use futures_util::StreamExt;
+    use tower::ServiceExt;
+
+    use swarmx_core::{SharedWorkflowContext, WorkflowContext, WorkflowDag};
+    use swarmx_events::Event;
+
+    use super::*;
+    use crate::ExecutionState;
+
+    /// Request an execution's event stream, answering the type and ID of
+    /// each message
+    async fn stream(app: &Router, uri: String, last_event_id: Option<u64>) -> Vec<(String, u64)> {
+        let mut request = Request::get(uri);
+        if let Some(id) = last_event_id {
+            request = request.header("last-event-id", id.to_string());
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = tokio::time::timeout(
+            Duration::from_secs(1),
+            axum::body::to_bytes(response.into_body(), usize::MAX),
+        )
+        .await
+        .expect("stream did not end")
+        .unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .split("\n\n")
+            .filter_map(|message| {
+                let field = |name: &str| {
+                    message
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(str::trim)
+                };
+                Some((field("event:")?.to_string(), field("id:")?.parse().ok()?))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_replays_and_filters() {
+        let state = AppState::new();
+        let dag = WorkflowDag::new();
+        let workflow_id = dag.workflow_id();
+        let ctx = WorkflowContext::new(workflow_id, "test".to_string());
+        let execution_id = ctx.execution_id;
+        state.inner.executions.write().await.executions.insert(
+            execution_id,
+            ExecutionState {
+                execution_id,
+                workflow_id,
+                context: SharedWorkflowContext::new(ctx),
+                dag: Arc::new(dag),
+                started_at: chrono::Utc::now(),
+            },
+        );
+
+        let (node_id, timestamp) = (Uuid::new_v4(), chrono::Utc::now());
+        let mut sequences = Vec::new();
+        for event in [
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp,
+            },
+            Event::NodeCompleted {
+                workflow_id: Uuid::new_v4(),
+                node_id,
+                output_refs: Vec::new(),
+                duration_ms: 1,
+                timestamp,
+            },
+            Event::NodeCompleted {
+                workflow_id,
+                node_id,
+                output_refs: Vec::new(),
+                duration_ms: 1,
+                timestamp,
+            },
+            Event::WorkflowCompleted {
+                workflow_id,
+                timestamp,
+                duration_ms: 2,
+            },
+        ] {
+            sequences.push(state.inner.events.publish(event).await.unwrap().sequence);
+        }
+        let app = Router::new()
+            .route("/api/executions/{id}/events", get(stream_execution_events))
+            .with_state(state);
+        let uri = format!("/api/executions/{execution_id}/events");
+
+        // Resumes after the last event the client saw, skipping other workflows
+        assert_eq!(
+            stream(&app, uri.clone(), Some(sequences[0])).await,
+            [
+                ("node_completed".to_string(), sequences[2]),
+                ("workflow_completed".to_string(), sequences[3]),
+            ]
+        );
+        let filtered = format!("{uri}?types=node_started,workflow_completed");
+        assert_eq!(
+            stream(&app, filtered, Some(sequences[0] - 1)).await,
+            [
+                ("node_started".to_string(), sequences[0]),
+                ("workflow_completed".to_string(), sequences[3]),
+            ]
+        );
+
+        // An ID past every event replays nothing
+        let request = Request::get(uri.clone())
+            .header("last-event-id", u64::MAX.to_string())
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), body.next())
+                .await
+                .is_err()
+        );
+
+        let request = Request::get(format!("{uri}?types=node_exploded"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
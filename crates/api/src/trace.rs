@@ -0,0 +1,42 @@
+//! Trace context for incoming requests
+//!
+//! Clients that already trace their calls send a W3C `traceparent` header;
+//! the workflow execution then joins that trace, so client, servers, and
+//! WAL events can be stitched together under one trace ID. Requests without
+//! a valid header start a new trace.
+
+use std::convert::Infallible;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+use swarmx_events::TraceContext;
+use swarmx_protocol::CallbackTrace;
+
+/// Name of the W3C trace context header
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Extractor for the trace context of the current request
+#[derive(Debug, Clone)]
+pub struct RequestTrace(pub TraceContext);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestTrace {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let trace = parts
+            .headers
+            .get(TRACEPARENT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(TraceContext::from_traceparent)
+            .unwrap_or_else(TraceContext::new_root);
+        Ok(Self(trace))
+    }
+}
+
+/// Trace context reported by a server callback, if it carried one
+pub fn callback_trace(trace: &CallbackTrace) -> Option<TraceContext> {
+    Some(TraceContext {
+        trace_id: trace.trace_id.clone()?,
+        parent_span_id: trace.parent_span_id.clone(),
+    })
+}
@@ -0,0 +1,72 @@
+//! Event-driven workflow triggers
+//!
+//! [`trigger_engine`] evaluates every committed event against the triggers
+//! of the stored workflows. Each firing is published as a
+//! `workflow_triggered` audit event carrying the variables to start the
+//! execution with, keyed so that an event fires each trigger at most once.
+
+use swarmx_core::TriggerEngine;
+use swarmx_events::{AuditAction, BusError, Event, EventFilter};
+
+use crate::AppState;
+
+/// Fire workflow triggers on committed events
+///
+/// Follows workflow changes through their audit events, so triggers take
+/// effect as soon as a workflow is created or updated. Runs until the
+/// event bus closes.
+pub async fn trigger_engine(state: AppState) {
+    // Subscribe first so workflows stored while loading are not missed
+    let mut subscription = state.inner.events.subscribe(EventFilter::new());
+    let mut engine = TriggerEngine::new();
+    for workflow in state.inner.workflows.read().await.workflows.values() {
+        engine.register(workflow);
+    }
+
+    loop {
+        let envelope = match subscription.recv().await {
+            Ok(envelope) => envelope,
+            Err(BusError::Closed) => return,
+            Err(e) => {
+                tracing::warn!("Trigger engine missed events: {e}");
+                continue;
+            }
+        };
+
+        if let Event::Audit { action, .. } = &envelope.event {
+            match action {
+                AuditAction::WorkflowCreated { workflow_id, .. }
+                | AuditAction::WorkflowUpdated { workflow_id } => {
+                    let workflows = state.inner.workflows.read().await;
+                    match workflows.workflows.get(workflow_id) {
+                        Some(workflow) => engine.register(workflow),
+                        None => engine.unregister(*workflow_id),
+                    }
+                }
+                AuditAction::WorkflowDeleted { workflow_id } => engine.unregister(*workflow_id),
+                _ => {}
+            }
+        }
+
+        for firing in engine.evaluate(&envelope) {
+            let recorded = state
+                .inner
+                .events
+                .publish_idempotent(&firing.idempotency_key(), firing.audit_event())
+                .await;
+            match recorded {
+                Ok(_) => tracing::info!(
+                    workflow_id = %firing.workflow_id,
+                    trigger_id = %firing.trigger_id,
+                    source_sequence = firing.source_sequence,
+                    "Workflow trigger fired"
+                ),
+                Err(e) => tracing::warn!(
+                    workflow_id = %firing.workflow_id,
+                    trigger_id = %firing.trigger_id,
+                    "Failed to record trigger firing: {e}"
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Multipart uploads
+//!
+//! Payloads too large for one `POST /api/data` are uploaded in parts.
+//! `POST /api/data/uploads` starts an upload and returns a presigned URL
+//! per part; each part is sent with `PUT` to its URL, in any order and in
+//! parallel, and `POST /api/data/uploads/{id}/complete` stores the
+//! assembled payload as one piece of data. `GET /api/data/uploads/{id}`
+//! shows which parts have arrived, so an interrupted client only resends
+//! the rest, and `DELETE /api/data/uploads/{id}` abandons the upload.
+//! Uploads not completed in time are discarded by the data reaper.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use swarmx_dataref::{
+    parse_tags, DataRef, MultipartError, MultipartUpload, MultipartUploads, UploadedPart,
+};
+use swarmx_protocol::{
+    ApiResponse, CompleteMultipartUpload, MultipartUploadRequest, MultipartUploadResponse,
+};
+
+use crate::{data_type_for, record_upload, AppState};
+
+/// Start a multipart upload
+pub async fn create_upload(
+    State(state): State<AppState>,
+    Json(request): Json<MultipartUploadRequest>,
+) -> (StatusCode, Json<ApiResponse<MultipartUploadResponse>>) {
+    let Some(uploads) = &state.inner.uploads else {
+        return not_configured();
+    };
+    let dtype = data_type_for(request.content_type.trim());
+    let tags = match request.tags.as_deref().map(parse_tags).transpose() {
+        Ok(tags) => tags.unwrap_or_default(),
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error("INVALID_TAGS", &e.to_string())),
+            )
+        }
+    };
+    if let Some(store_request) = request.store_request(dtype.name()) {
+        if let Err(e) = store_request.admit(&mut *state.inner.data.write().await) {
+            return (
+                StatusCode::INSUFFICIENT_STORAGE,
+                Json(ApiResponse::error("QUOTA_EXCEEDED", &e.to_string())),
+            );
+        }
+    }
+    let workflow_id = request.workflow_id.unwrap_or_else(Uuid::nil);
+    let mut data_ref = DataRef::new(String::new(), 0, dtype, workflow_id);
+    data_ref.tags = tags;
+
+    let upload = match uploads.initiate(data_ref, request.size_bytes, request.part_size) {
+        Ok(upload) => upload,
+        Err(e @ MultipartError::TooManyParts(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error("TOO_MANY_PARTS", &e.to_string())),
+            )
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error("STORAGE_ERROR", &e.to_string())),
+            )
+        }
+    };
+    let base = state.inner.store.location().trim_end_matches('/');
+    let response = MultipartUploadResponse::new(&upload, |part_number| {
+        format!(
+            "{base}/data/uploads/{}/parts/{part_number}?signature={}",
+            upload.upload_id,
+            uploads.sign_part(&upload, part_number)
+        )
+    });
+    (StatusCode::CREATED, Json(ApiResponse::success(response)))
+}
+
+/// Get an upload in progress, with the parts received so far
+pub async fn get_upload(
+    State(state): State<AppState>,
+    Path(upload_id): Path<Uuid>,
+) -> (StatusCode, Json<ApiResponse<MultipartUpload>>) {
+    let Some(uploads) = &state.inner.uploads else {
+        return not_configured();
+    };
+    match uploads.get(&upload_id) {
+        Some(upload) => (StatusCode::OK, Json(ApiResponse::success(upload))),
+        None => upload_error(MultipartError::NotFound(upload_id)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadPartParams {
+    /// Signature from the part's presigned URL
+    pub signature: String,
+}
+
+/// Receive one part of an upload
+///
+/// The request body is streamed to disk; sending a part again replaces
+/// it.
+pub async fn upload_part(
+    State(state): State<AppState>,
+    Path((upload_id, part_number)): Path<(Uuid, u32)>,
+    Query(params): Query<UploadPartParams>,
+    body: Body,
+) -> (StatusCode, Json<ApiResponse<UploadedPart>>) {
+    let Some(uploads) = state.inner.uploads.clone() else {
+        return not_configured();
+    };
+    if let Err(e) = uploads.verify_part(&upload_id, part_number, &params.signature) {
+        return upload_error(e);
+    }
+    let body =
+        tokio_util::io::StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let received = tokio::task::spawn_blocking(move || {
+        let body = tokio_util::io::SyncIoBridge::new(body);
+        uploads.put_part(&upload_id, part_number, body)
+    })
+    .await
+    .unwrap_or_else(|e| Err(MultipartError::Io(std::io::Error::other(e))));
+    match received {
+        Ok(part) => (StatusCode::OK, Json(ApiResponse::success(part))),
+        Err(e) => upload_error(e),
+    }
+}
+
+/// Store the assembled payload of an upload
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    Path(upload_id): Path<Uuid>,
+    Json(request): Json<CompleteMultipartUpload>,
+) -> (StatusCode, Json<ApiResponse<DataRef>>) {
+    let Some(uploads) = state.inner.uploads.clone() else {
+        return not_configured();
+    };
+    let Some(upload) = uploads.get(&upload_id) else {
+        return upload_error(MultipartError::NotFound(upload_id));
+    };
+    let store = state.inner.store.clone();
+    let completed =
+        tokio::task::spawn_blocking(move || uploads.complete(&upload_id, &request.parts, &store))
+            .await
+            .unwrap_or_else(|e| Err(MultipartError::Io(std::io::Error::other(e))));
+    let data_ref = match completed {
+        Ok(data_ref) => data_ref,
+        Err(e) => return upload_error(e),
+    };
+    let workflow_id = Some(upload.data_ref.workflow_id).filter(|id| !id.is_nil());
+    record_upload(&state, &data_ref, workflow_id).await;
+    (StatusCode::CREATED, Json(ApiResponse::success(data_ref)))
+}
+
+/// Abandon an upload, deleting the parts received
+pub async fn abort_upload(
+    State(state): State<AppState>,
+    Path(upload_id): Path<Uuid>,
+) -> StatusCode {
+    let Some(uploads) = state.inner.uploads.clone() else {
+        return StatusCode::NOT_FOUND;
+    };
+    match tokio::task::spawn_blocking(move || uploads.abort(&upload_id)).await {
+        Ok(Ok(_)) => StatusCode::NO_CONTENT,
+        Ok(Err(MultipartError::NotFound(_))) => StatusCode::NOT_FOUND,
+        Ok(Err(e)) => {
+            tracing::error!(%upload_id, "Failed to abort upload: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        Err(e) => {
+            tracing::error!(%upload_id, "Failed to abort upload: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Discard expired uploads
+pub fn expire_uploads(uploads: &MultipartUploads) {
+    for upload in uploads.expire(chrono::Utc::now()) {
+        tracing::info!(
+            upload_id = %upload.upload_id,
+            data_uuid = %upload.data_ref.uuid,
+            "Discarded expired upload"
+        );
+    }
+}
+
+/// Response for a failed upload request
+fn upload_error<T>(e: MultipartError) -> (StatusCode, Json<ApiResponse<T>>) {
+    let (status, code) = match &e {
+        MultipartError::NotFound(_) => (StatusCode::NOT_FOUND, "NOT_FOUND"),
+        MultipartError::InvalidSignature => (StatusCode::FORBIDDEN, "FORBIDDEN"),
+        MultipartError::InvalidPart { .. }
+        | MultipartError::PartSize { .. }
+        | MultipartError::TooManyParts(_) => (StatusCode::BAD_REQUEST, "INVALID_PART"),
+        MultipartError::MissingPart(_) | MultipartError::PartChecksum(_) => {
+            (StatusCode::CONFLICT, "INCOMPLETE_UPLOAD")
+        }
+        MultipartError::Random | MultipartError::Io(_) | MultipartError::Store(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR")
+        }
+    };
+    (status, Json(ApiResponse::error(code, &e.to_string())))
+}
+
+/// Response for upload endpoints when uploads are not configured
+fn not_configured<T>() -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ApiResponse::error(
+            "NOT_CONFIGURED",
+            "Multipart uploads are not configured",
+        )),
+    )
+}
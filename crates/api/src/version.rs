@@ -0,0 +1,65 @@
+//! Protocol version negotiation
+//!
+//! Every response names the protocol version this client speaks in the
+//! `X-SwarmX-Protocol-Version` header. Requests naming theirs are checked
+//! against it: an unreadable version is refused with `400
+//! INVALID_PROTOCOL_VERSION` and a different major version with `400
+//! INCOMPATIBLE_PROTOCOL_VERSION`, so a server from an incompatible release
+//! learns why instead of failing on a body it cannot parse. Requests
+//! without the header come from peers that predate negotiation. Handlers
+//! take the version to speak with the caller with [`PeerVersion`].
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use swarmx_protocol::{
+    ApiResponse, ProtocolVersion, VersionError, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+};
+
+/// Version negotiated with the sender of a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerVersion(pub ProtocolVersion);
+
+impl<S: Send + Sync> FromRequestParts<S> for PeerVersion {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get().copied().unwrap_or_default())
+    }
+}
+
+/// Negotiate the protocol version of a request, and name ours in its
+/// response
+pub async fn negotiate_version(mut req: Request, next: Next) -> Response {
+    let peer = match req.headers().get(PROTOCOL_VERSION_HEADER) {
+        Some(version) => match version.to_str() {
+            Ok(version) => version.parse::<ProtocolVersion>(),
+            Err(_) => Err(VersionError::Invalid(format!("{version:?}"))),
+        },
+        None => Ok(ProtocolVersion::BASELINE),
+    };
+    let mut response = match peer.and_then(|peer| PROTOCOL_VERSION.negotiate(peer)) {
+        Ok(version) => {
+            req.extensions_mut().insert(PeerVersion(version));
+            next.run(req).await
+        }
+        Err(e) => {
+            let code = match e {
+                VersionError::Invalid(_) => "INVALID_PROTOCOL_VERSION",
+                VersionError::Incompatible { .. } => "INCOMPATIBLE_PROTOCOL_VERSION",
+            };
+            let body = ApiResponse::<()>::error(code, &e.to_string());
+            (StatusCode::BAD_REQUEST, Json(body)).into_response()
+        }
+    };
+    response.headers_mut().insert(
+        PROTOCOL_VERSION_HEADER,
+        HeaderValue::from_str(&PROTOCOL_VERSION.to_string()).expect("version is a valid header"),
+    );
+    response
+}
@@ -0,0 +1,174 @@
+//! Outbound webhook registration and delivery
+//!
+//! `POST /api/webhooks` registers a URL with an event filter; every
+//! committed event matching it is POSTed there as signed JSON by
+//! [`webhook_dispatcher`]. `GET /api/webhooks/{id}/deliveries` shows
+//! recent delivery outcomes, so a failing receiver can be spotted without
+//! reading server logs.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::AppState;
+use swarmx_events::{
+    BusError, EventFilter, Webhook, WebhookDelivery, WebhookFilter, WebhookRequest,
+};
+use swarmx_protocol::ApiResponse;
+
+/// Timeout for a single webhook request
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook registration request
+#[derive(Debug, Deserialize)]
+pub struct RegisterWebhookRequest {
+    /// `http` or `https` URL to POST events to
+    pub url: String,
+    /// Key for the payload signature; never returned by the API
+    pub secret: String,
+    /// Events to deliver; all events if omitted
+    #[serde(default)]
+    pub filter: WebhookFilter,
+}
+
+/// Register a webhook
+pub async fn register_webhook(
+    State(state): State<AppState>,
+    Json(request): Json<RegisterWebhookRequest>,
+) -> (StatusCode, Json<ApiResponse<Webhook>>) {
+    if !(request.url.starts_with("http://") || request.url.starts_with("https://")) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                "INVALID_URL",
+                "Webhook URL must use http or https",
+            )),
+        );
+    }
+    if request.secret.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                "INVALID_SECRET",
+                "Webhook secret must not be empty",
+            )),
+        );
+    }
+
+    match state
+        .inner
+        .webhooks
+        .register(&request.url, request.filter, &request.secret)
+    {
+        Ok(webhook) => (StatusCode::CREATED, Json(ApiResponse::success(webhook))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
+
+/// List registered webhooks
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<ApiResponse<Vec<Webhook>>>) {
+    match state.inner.webhooks.webhooks() {
+        Ok(webhooks) => (StatusCode::OK, Json(ApiResponse::success(webhooks))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
+
+/// Remove a webhook
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    match state.inner.webhooks.remove(id) {
+        Ok(true) => (StatusCode::OK, Json(ApiResponse::success(()))),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Webhook not found")),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
+
+/// Recent deliveries to a webhook, newest first
+pub async fn list_webhook_deliveries(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<ApiResponse<Vec<WebhookDelivery>>>) {
+    match state.inner.webhooks.deliveries(id) {
+        Ok(Some(deliveries)) => (StatusCode::OK, Json(ApiResponse::success(deliveries))),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("NOT_FOUND", "Webhook not found")),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error("WAL_ERROR", &e.to_string())),
+        ),
+    }
+}
+
+/// Deliver every committed event to the matching webhooks
+///
+/// Runs until the event bus closes. Events committed while a delivery is
+/// being retried are picked up afterwards, in order.
+pub async fn webhook_dispatcher(state: AppState) {
+    let client = match reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to create webhook HTTP client: {e}");
+            return;
+        }
+    };
+
+    let mut subscription = state.inner.events.subscribe(EventFilter::new());
+    loop {
+        let envelope = match subscription.recv().await {
+            Ok(envelope) => envelope,
+            Err(BusError::Closed) => return,
+            Err(e) => {
+                tracing::warn!("Webhook dispatcher missed events: {e}");
+                continue;
+            }
+        };
+        let result = state
+            .inner
+            .webhooks
+            .dispatch(&envelope, |request| send(&client, request))
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to record webhook delivery: {e}");
+        }
+    }
+}
+
+/// POST a signed webhook request, failing on non-success responses
+async fn send(client: &reqwest::Client, request: WebhookRequest) -> Result<(), reqwest::Error> {
+    let mut builder = client
+        .post(&request.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    for (name, value) in request.headers() {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(request.body)
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+}
@@ -0,0 +1,408 @@
+//! WebSocket event streaming
+//!
+//! `GET /ws` upgrades to a WebSocket over which a client can hold any
+//! number of event subscriptions at once. Clients send JSON control
+//! messages:
+//!
+//! ```json
+//! { "type": "subscribe", "id": "runs", "workflow_id": "...", "event_types": ["node_failed"] }
+//! { "type": "unsubscribe", "id": "runs" }
+//! ```
+//!
+//! and receive each matching envelope as an `event` frame tagged with the
+//! subscription ID. The server pings idle connections and closes those that
+//! stop responding. A client that reads too slowly first falls back to WAL
+//! catch-up inside its subscriptions; if it still cannot keep up within
+//! [`SLOW_CLIENT_TIMEOUT`], the connection is closed rather than buffering
+//! without bound.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, Utf8Bytes, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::AppState;
+use swarmx_events::{EventEnvelope, EventFilter, EventKind, EventSubscription};
+
+/// Interval between server pings
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a connection may stay silent before it is considered dead
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How long a frame may wait for room in the outbound queue
+pub const SLOW_CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Capacity of the per-connection outbound queue
+const OUTBOUND_CAPACITY: usize = 256;
+
+/// WebSocket close code for policy violations (RFC 6455)
+const CLOSE_POLICY: u16 = 1008;
+
+/// Messages sent by the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Start a subscription
+    Subscribe {
+        /// Client-chosen subscription ID, unique per connection
+        id: String,
+        #[serde(default)]
+        workflow_id: Option<Uuid>,
+        #[serde(default)]
+        node_id: Option<Uuid>,
+        #[serde(default)]
+        event_types: Option<Vec<EventKind>>,
+        /// Replay events from this sequence number before streaming live
+        #[serde(default)]
+        from_sequence: Option<u64>,
+    },
+    /// Stop a subscription
+    Unsubscribe { id: String },
+    /// Application-level keep-alive
+    Ping,
+}
+
+/// Messages sent by the server
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed {
+        id: String,
+    },
+    Unsubscribed {
+        id: String,
+    },
+    Event {
+        subscription: String,
+        envelope: Box<EventEnvelope>,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        message: String,
+    },
+    Pong,
+}
+
+/// Upgrade to a WebSocket event stream
+pub async fn ws_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, socket))
+}
+
+/// Per-connection state
+struct Connection {
+    state: AppState,
+    outbound: mpsc::Sender<ServerMessage>,
+    /// Signalled when a subscription could not enqueue a frame in time
+    too_slow: Arc<Notify>,
+    subscriptions: HashMap<String, JoinHandle<()>>,
+}
+
+async fn handle_socket(state: AppState, mut socket: WebSocket) {
+    let (outbound, mut outbound_rx) = mpsc::channel(OUTBOUND_CAPACITY);
+    let mut conn = Connection {
+        state,
+        outbound,
+        too_slow: Arc::new(Notify::new()),
+        subscriptions: HashMap::new(),
+    };
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut last_seen = Instant::now();
+    let too_slow = conn.too_slow.clone();
+
+    let close_reason = loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let Some(Ok(message)) = incoming else {
+                    break None;
+                };
+                last_seen = Instant::now();
+                match message {
+                    Message::Text(text) => {
+                        let reply = conn.handle_text(&text).await;
+                        if let Some(reply) = reply {
+                            if send_json(&mut socket, &reply).await.is_err() {
+                                break None;
+                            }
+                        }
+                    }
+                    Message::Close(_) => break None,
+                    // Pongs only refresh `last_seen`; pings are answered by axum
+                    _ => {}
+                }
+            }
+            Some(message) = outbound_rx.recv() => {
+                if send_json(&mut socket, &message).await.is_err() {
+                    break None;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    break Some("heartbeat timeout");
+                }
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break None;
+                }
+            }
+            _ = too_slow.notified() => break Some("client too slow"),
+        }
+    };
+
+    conn.unsubscribe_all();
+    if let Some(reason) = close_reason {
+        tracing::debug!(reason, "closing event WebSocket");
+        let _ = socket
+            .send(Message::Close(Some(CloseFrame {
+                code: CLOSE_POLICY,
+                reason: Utf8Bytes::from(reason),
+            })))
+            .await;
+    }
+}
+
+impl Connection {
+    /// Handle a text frame, returning an immediate reply if any
+    async fn handle_text(&mut self, text: &str) -> Option<ServerMessage> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                return Some(ServerMessage::Error {
+                    id: None,
+                    message: format!("invalid message: {e}"),
+                })
+            }
+        };
+
+        match message {
+            ClientMessage::Subscribe {
+                id,
+                workflow_id,
+                node_id,
+                event_types,
+                from_sequence,
+            } => {
+                if self.subscriptions.contains_key(&id) {
+                    return Some(ServerMessage::Error {
+                        id: Some(id),
+                        message: "subscription ID already in use".to_string(),
+                    });
+                }
+                let mut filter = EventFilter {
+                    workflow_id,
+                    node_id,
+                    ..Default::default()
+                };
+                if let Some(kinds) = event_types {
+                    filter = filter.kinds(kinds);
+                }
+                let events = &self.state.inner.events;
+                let subscription = match from_sequence {
+                    Some(sequence) => match events.subscribe_from(filter, sequence).await {
+                        Ok(subscription) => subscription,
+                        Err(e) => {
+                            return Some(ServerMessage::Error {
+                                id: Some(id),
+                                message: e.to_string(),
+                            })
+                        }
+                    },
+                    None => events.subscribe(filter),
+                };
+
+                // The reply is written before the outbound queue is drained
+                // again, so it always precedes the subscription's events
+                let task = tokio::spawn(forward(
+                    id.clone(),
+                    subscription,
+                    self.outbound.clone(),
+                    self.too_slow.clone(),
+                ));
+                self.subscriptions.insert(id.clone(), task);
+                Some(ServerMessage::Subscribed { id })
+            }
+            ClientMessage::Unsubscribe { id } => match self.subscriptions.remove(&id) {
+                Some(task) => {
+                    task.abort();
+                    Some(ServerMessage::Unsubscribed { id })
+                }
+                None => Some(ServerMessage::Error {
+                    id: Some(id),
+                    message: "unknown subscription".to_string(),
+                }),
+            },
+            ClientMessage::Ping => Some(ServerMessage::Pong),
+        }
+    }
+
+    fn unsubscribe_all(&mut self) {
+        for (_, task) in self.subscriptions.drain() {
+            task.abort();
+        }
+    }
+}
+
+/// Forward a subscription's events into the connection's outbound queue
+async fn forward(
+    id: String,
+    mut subscription: EventSubscription,
+    outbound: mpsc::Sender<ServerMessage>,
+    too_slow: Arc<Notify>,
+) {
+    loop {
+        let message = match subscription.recv().await {
+            Ok(envelope) => ServerMessage::Event {
+                subscription: id.clone(),
+                envelope: Box::new(envelope),
+            },
+            Err(e) => ServerMessage::Error {
+                id: Some(id.clone()),
+                message: e.to_string(),
+            },
+        };
+        let failed = matches!(message, ServerMessage::Error { .. });
+
+        match tokio::time::timeout(SLOW_CLIENT_TIMEOUT, outbound.send(message)).await {
+            Ok(Ok(())) if !failed => {}
+            Ok(_) => return,
+            Err(_) => {
+                too_slow.notify_one();
+                return;
+            }
+        }
+    }
+}
+
+async fn send_json(socket: &mut WebSocket, message: &ServerMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text.into())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Router};
+    use futures_util::Stream;
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio_tungstenite::{connect_async, tungstenite, tungstenite::Message as WsMessage};
+
+    use swarmx_events::Event;
+
+    use super::*;
+
+    /// Receive the next JSON frame, skipping heartbeat pings
+    async fn recv(
+        socket: &mut (impl Stream<Item = Result<WsMessage, tungstenite::Error>> + Unpin),
+    ) -> Value {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(1), socket.next())
+                .await
+                .expect("no frame")
+                .unwrap()
+                .unwrap();
+            if let WsMessage::Text(text) = frame {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// Send a control message and receive the next JSON frame
+    async fn send(
+        socket: &mut (impl Stream<Item = Result<WsMessage, tungstenite::Error>>
+                  + SinkExt<WsMessage, Error = tungstenite::Error>
+                  + Unpin),
+        message: Value,
+    ) -> Value {
+        socket
+            .send(WsMessage::text(message.to_string()))
+            .await
+            .unwrap();
+        recv(socket).await
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_filter_and_close() {
+        let state = AppState::new();
+        let app = Router::new()
+            .route("/ws", get(ws_handler))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await.unwrap();
+
+        let workflow_id = Uuid::new_v4();
+        let subscribe = json!({
+            "type": "subscribe",
+            "id": "failures",
+            "workflow_id": workflow_id,
+            "event_types": ["node_failed"],
+        });
+        assert_eq!(
+            send(&mut socket, subscribe.clone()).await,
+            json!({"type": "subscribed", "id": "failures"})
+        );
+        assert_eq!(send(&mut socket, subscribe).await["type"], "error");
+
+        let (node_id, timestamp) = (Uuid::new_v4(), chrono::Utc::now());
+        let failed = |workflow_id| Event::NodeFailed {
+            workflow_id,
+            node_id,
+            error: "boom".to_string(),
+            retry_count: 0,
+            timestamp,
+        };
+        for event in [
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp,
+            },
+            failed(Uuid::new_v4()),
+            failed(workflow_id),
+        ] {
+            state.inner.events.publish(event).await.unwrap();
+        }
+        // Only the matching event is forwarded
+        let reply = recv(&mut socket).await;
+        assert_eq!(reply["type"], "event");
+        assert_eq!(reply["subscription"], "failures");
+        assert_eq!(reply["envelope"]["event"]["type"], "node_failed");
+        assert_eq!(
+            reply["envelope"]["event"]["workflow_id"],
+            json!(workflow_id)
+        );
+        assert_eq!(
+            send(&mut socket, json!({"type": "ping"})).await,
+            json!({"type": "pong"})
+        );
+        assert_eq!(state.inner.events.subscriber_count(), 1);
+
+        // Closing the socket ends its subscriptions
+        socket.close(None).await.unwrap();
+        while let Some(Ok(frame)) = socket.next().await {
+            assert!(frame.is_close() || frame.is_ping());
+        }
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while state.inner.events.subscriber_count() > 0 {
+            assert!(
+                Instant::now() < deadline,
+                "subscription outlived its socket"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! Human-in-the-loop approval gates
+//!
+//! An approval node (type [`APPROVAL_NODE_TYPE`]) is never dispatched to a
+//! server. When it becomes ready the engine moves it to
+//! [`NodeState::WaitingForApproval`], and execution of its downstream halts
+//! until someone approves or rejects it through the API. Approving completes
+//! the node; rejecting cancels it (and, through [`cancel_downstream`], the
+//! nodes it guards). An optional timeout applies a default decision.
+//!
+//! Node configuration:
+//!
+//! ```json
+//! { "timeout_ms": 3600000, "default_action": "reject" }
+//! ```
+//!
+//! [`cancel_downstream`]: crate::cancel::cancel_downstream
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::dag::{WorkflowDag, WorkflowNode};
+use crate::shared::SharedWorkflowContext;
+use crate::state::{NodeState, StateError, StateTransition, WorkflowContext};
+
+/// Node type for approval gates
+pub const APPROVAL_NODE_TYPE: &str = "flow.approval";
+
+/// Check if a node is an approval gate
+pub fn is_approval_node(node: &WorkflowNode) -> bool {
+    node.node_type == APPROVAL_NODE_TYPE
+}
+
+/// Decision on an approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    /// Let execution continue past the gate
+    Approve,
+    /// Stop execution at the gate
+    #[default]
+    Reject,
+}
+
+/// Approval gate configuration, read from the node config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApprovalConfig {
+    /// How long to wait for a decision before applying the default
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Decision applied when the timeout elapses
+    #[serde(default)]
+    pub default_action: ApprovalDecision,
+}
+
+impl ApprovalConfig {
+    /// Read the approval configuration from a node
+    ///
+    /// A node without configuration uses the defaults.
+    pub fn from_node(node: &WorkflowNode) -> Result<Self, StateError> {
+        if node.config.is_null() {
+            return Ok(Self::default());
+        }
+        serde_json::from_value(node.config.clone()).map_err(|e| StateError::InvalidConfig {
+            node_id: node.id,
+            message: e.to_string(),
+        })
+    }
+}
+
+/// An outstanding approval request on a node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingApproval {
+    /// When approval was requested
+    pub requested_at: DateTime<Utc>,
+    /// When the default action applies, if a timeout is configured
+    pub deadline: Option<DateTime<Utc>>,
+    /// Decision applied at the deadline
+    pub default_action: ApprovalDecision,
+}
+
+impl WorkflowContext {
+    /// Halt every pending approval gate whose upstream nodes are all done
+    ///
+    /// Returns the outcome for each gate that was ready. A gate whose
+    /// configuration does not parse stays pending and is reported as
+    /// [`StateError::InvalidConfig`].
+    pub fn open_approval_gates(
+        &mut self,
+        dag: &WorkflowDag,
+    ) -> Vec<(Uuid, Result<StateTransition, StateError>)> {
+        let ready: Vec<Uuid> = dag
+            .node_ids()
+            .into_iter()
+            .filter(|id| dag.get_node(*id).is_some_and(is_approval_node))
+            .filter(|id| {
+                self.get_node(id)
+                    .is_some_and(|n| n.state == NodeState::Pending)
+            })
+            .filter(|id| {
+                dag.get_dependencies(*id).iter().all(|dep| {
+                    self.get_node(dep)
+                        .is_some_and(|n| n.state == NodeState::Done)
+                })
+            })
+            .collect();
+
+        ready
+            .into_iter()
+            .map(|id| {
+                let result = dag
+                    .get_node(id)
+                    .ok_or(StateError::NodeNotFound(id))
+                    .and_then(ApprovalConfig::from_node)
+                    .and_then(|config| self.request_approval(id, &config));
+                (id, result)
+            })
+            .collect()
+    }
+
+    /// Halt a ready approval node until a decision arrives
+    pub fn request_approval(
+        &mut self,
+        node_id: Uuid,
+        config: &ApprovalConfig,
+    ) -> Result<StateTransition, StateError> {
+        let node = self
+            .get_node_mut(&node_id)
+            .ok_or(StateError::NodeNotFound(node_id))?;
+        let transition = node.transition_with_reason(
+            NodeState::WaitingForApproval,
+            Some("awaiting approval".to_string()),
+        )?;
+
+        let requested_at = transition.timestamp;
+        node.approval = Some(PendingApproval {
+            requested_at,
+            deadline: config
+                .timeout_ms
+                .map(|ms| requested_at + Duration::milliseconds(ms as i64)),
+            default_action: config.default_action,
+        });
+        Ok(transition)
+    }
+
+    /// Apply an approval decision to a waiting node
+    ///
+    /// Approval completes the node. Rejection cancels it; when a DAG is
+    /// supplied the nodes downstream of the gate are cancelled as well.
+    pub fn resolve_approval(
+        &mut self,
+        node_id: Uuid,
+        decision: ApprovalDecision,
+        reason: Option<String>,
+        dag: Option<&WorkflowDag>,
+    ) -> Result<StateTransition, StateError> {
+        let node = self
+            .get_node_mut(&node_id)
+            .ok_or(StateError::NodeNotFound(node_id))?;
+        let to = match decision {
+            ApprovalDecision::Approve => NodeState::Done,
+            ApprovalDecision::Reject => NodeState::Cancelled,
+        };
+        if node.state != NodeState::WaitingForApproval {
+            return Err(StateError::InvalidTransition {
+                from: node.state,
+                to,
+            });
+        }
+
+        let reason = reason.unwrap_or_else(|| match decision {
+            ApprovalDecision::Approve => "approved".to_string(),
+            ApprovalDecision::Reject => "rejected".to_string(),
+        });
+        let transition = node.transition_with_reason(to, Some(reason))?;
+        node.approval = None;
+
+        if let (ApprovalDecision::Reject, Some(dag)) = (decision, dag) {
+            crate::cancel::cancel_downstream(node_id, dag, self);
+        }
+        Ok(transition)
+    }
+
+    /// Apply the default decision to approvals whose deadline has passed
+    ///
+    /// Returns the affected nodes and the decision applied to each.
+    pub fn expire_approvals(
+        &mut self,
+        now: DateTime<Utc>,
+        dag: Option<&WorkflowDag>,
+    ) -> Vec<(Uuid, ApprovalDecision)> {
+        let expired: Vec<(Uuid, ApprovalDecision)> = self
+            .nodes
+            .values()
+            .filter_map(|n| {
+                let approval = n.approval.as_ref()?;
+                let deadline = approval.deadline?;
+                (n.state == NodeState::WaitingForApproval && deadline <= now)
+                    .then_some((n.node_id, approval.default_action))
+            })
+            .collect();
+
+        expired
+            .into_iter()
+            .filter(|(id, decision)| {
+                self.resolve_approval(*id, *decision, Some("approval timed out".to_string()), dag)
+                    .is_ok()
+            })
+            .collect()
+    }
+}
+
+impl SharedWorkflowContext {
+    /// Halt every pending approval gate whose upstream nodes are all done
+    ///
+    /// See [`WorkflowContext::open_approval_gates`].
+    pub fn open_approval_gates(
+        &self,
+        dag: &WorkflowDag,
+    ) -> Vec<(Uuid, Result<StateTransition, StateError>)> {
+        self.update(|ctx| ctx.open_approval_gates(dag))
+    }
+
+    /// Apply an approval decision to a waiting node
+    ///
+    /// See [`WorkflowContext::resolve_approval`].
+    pub fn resolve_approval(
+        &self,
+        node_id: Uuid,
+        decision: ApprovalDecision,
+        reason: Option<String>,
+        dag: Option<&WorkflowDag>,
+    ) -> Result<StateTransition, StateError> {
+        self.update(|ctx| ctx.resolve_approval(node_id, decision, reason, dag))
+    }
+
+    /// Apply the default decision to approvals whose deadline has passed
+    pub fn expire_approvals(
+        &self,
+        now: DateTime<Utc>,
+        dag: Option<&WorkflowDag>,
+    ) -> Vec<(Uuid, ApprovalDecision)> {
+        self.update(|ctx| ctx.expire_approvals(now, dag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::{NodeBuilder, WorkflowEdge};
+
+    fn gated() -> (WorkflowDag, WorkflowContext, Uuid, Uuid) {
+        let mut dag = WorkflowDag::new();
+        let gate = NodeBuilder::new(APPROVAL_NODE_TYPE, "Review")
+            .config(serde_json::json!({ "timeout_ms": 1000, "default_action": "approve" }))
+            .build();
+        let send = NodeBuilder::new("http.request", "Send").build();
+        let (gate_id, send_id) = (gate.id, send.id);
+        dag.add_node(gate);
+        dag.add_node(send);
+        dag.add_edge(
+            gate_id,
+            send_id,
+            WorkflowEdge {
+                source_output: "out".to_string(),
+                target_input: "in".to_string(),
+                transform: None,
+            },
+        )
+        .unwrap();
+
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        ctx.add_node(gate_id);
+        ctx.add_node(send_id);
+        (dag, ctx, gate_id, send_id)
+    }
+
+    #[test]
+    fn test_approve_and_reject() {
+        let (dag, mut ctx, gate_id, send_id) = gated();
+        let config = ApprovalConfig::from_node(dag.get_node(gate_id).unwrap()).unwrap();
+        assert_eq!(config.timeout_ms, Some(1000));
+
+        ctx.request_approval(gate_id, &config).unwrap();
+        assert_eq!(
+            ctx.get_node(&gate_id).unwrap().state,
+            NodeState::WaitingForApproval
+        );
+
+        let mut rejected = ctx.clone();
+        rejected
+            .resolve_approval(gate_id, ApprovalDecision::Reject, None, Some(&dag))
+            .unwrap();
+        assert_eq!(
+            rejected.get_node(&send_id).unwrap().state,
+            NodeState::Cancelled
+        );
+
+        ctx.resolve_approval(gate_id, ApprovalDecision::Approve, None, Some(&dag))
+            .unwrap();
+        assert_eq!(ctx.get_node(&gate_id).unwrap().state, NodeState::Done);
+        assert!(ctx
+            .resolve_approval(gate_id, ApprovalDecision::Approve, None, None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_approval_timeout_applies_default() {
+        let (dag, mut ctx, gate_id, _) = gated();
+        let config = ApprovalConfig::from_node(dag.get_node(gate_id).unwrap()).unwrap();
+        ctx.request_approval(gate_id, &config).unwrap();
+
+        assert!(ctx.expire_approvals(Utc::now(), Some(&dag)).is_empty());
+
+        let later = Utc::now() + Duration::seconds(2);
+        let expired = ctx.expire_approvals(later, Some(&dag));
+        assert_eq!(expired, vec![(gate_id, ApprovalDecision::Approve)]);
+        assert_eq!(ctx.get_node(&gate_id).unwrap().state, NodeState::Done);
+    }
+
+    #[test]
+    fn test_open_approval_gates() {
+        let (mut dag, mut ctx, gate_id, _) = gated();
+        let fetch = NodeBuilder::new("http.request", "Fetch").build();
+        let fetch_id = fetch.id;
+        dag.add_node(fetch);
+        dag.add_edge(
+            fetch_id,
+            gate_id,
+            WorkflowEdge {
+                source_output: "out".to_string(),
+                target_input: "in".to_string(),
+                transform: None,
+            },
+        )
+        .unwrap();
+        ctx.add_node(fetch_id);
+
+        assert!(ctx.open_approval_gates(&dag).is_empty());
+
+        for state in [NodeState::Scheduled, NodeState::Running, NodeState::Done] {
+            ctx.get_node_mut(&fetch_id)
+                .unwrap()
+                .transition(state)
+                .unwrap();
+        }
+        let opened = ctx.open_approval_gates(&dag);
+        assert_eq!(opened.len(), 1);
+        assert_eq!(opened[0].0, gate_id);
+        assert!(opened[0].1.is_ok());
+        let gate = ctx.get_node(&gate_id).unwrap();
+        assert_eq!(gate.state, NodeState::WaitingForApproval);
+        assert!(gate.approval.as_ref().unwrap().deadline.is_some());
+
+        assert!(ctx.open_approval_gates(&dag).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_approval_config_is_reported() {
+        let mut dag = WorkflowDag::new();
+        let gate = NodeBuilder::new(APPROVAL_NODE_TYPE, "Review")
+            .config(serde_json::json!({ "default_action": "maybe" }))
+            .build();
+        let gate_id = gate.id;
+        dag.add_node(gate);
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        ctx.add_node(gate_id);
+
+        let opened = ctx.open_approval_gates(&dag);
+        assert!(matches!(
+            opened[0].1,
+            Err(StateError::InvalidConfig { node_id, .. }) if node_id == gate_id
+        ));
+        assert_eq!(ctx.get_node(&gate_id).unwrap().state, NodeState::Pending);
+    }
+}
@@ -0,0 +1,364 @@
+//! Cancellation propagation through the DAG
+//!
+//! When a node is cancelled, or fails terminally under fail-fast, nothing
+//! downstream of it can ever run. [`cancel_downstream`] walks every
+//! transitive dependent and cancels the ones still waiting to run, recording
+//! the causal node in the transition reason. The engine and the API's cancel
+//! endpoints share this single implementation.
+//!
+//! [`WorkflowContext::handle_node_failure`] applies the workflow's
+//! [`FailurePolicy`] on top of it when a node fails terminally, and
+//! [`WorkflowContext::cancel`] cancels a whole execution. Nodes already
+//! executing on a server are not cancelled directly: their servers are
+//! asked to stop the [`RunningTask`]s, and the nodes are cancelled when the
+//! servers report them stopped.
+
+use std::collections::{HashSet, VecDeque};
+
+use uuid::Uuid;
+
+use crate::dag::WorkflowDag;
+use crate::shared::SharedWorkflowContext;
+use crate::state::{FailurePolicy, NodeState, WorkflowContext, WorkflowState};
+
+/// Cancel all waiting nodes downstream of `node_id`
+///
+/// Only nodes that have not started executing (see [`NodeState::is_waiting`])
+/// are cancelled; running and terminal nodes are left untouched
+/// but their own dependents are still visited. Returns the IDs of the nodes
+/// that were cancelled, in breadth-first order.
+pub fn cancel_downstream(node_id: Uuid, dag: &WorkflowDag, ctx: &mut WorkflowContext) -> Vec<Uuid> {
+    let cause_state = ctx
+        .get_node(&node_id)
+        .map(|n| format!("{:?}", n.state).to_lowercase())
+        .unwrap_or_else(|| "cancelled".to_string());
+    let cause_name = dag
+        .get_node(node_id)
+        .map(|n| format!("'{}' ({})", n.name, node_id))
+        .unwrap_or_else(|| node_id.to_string());
+    let reason = format!("upstream node {cause_name} {cause_state}");
+
+    let mut cancelled = Vec::new();
+    let mut visited = HashSet::from([node_id]);
+    let mut queue: VecDeque<Uuid> = dag.get_dependents(node_id).into();
+
+    while let Some(id) = queue.pop_front() {
+        if !visited.insert(id) {
+            continue;
+        }
+
+        if let Some(node) = ctx.get_node_mut(&id) {
+            if node.state.is_waiting()
+                && node
+                    .transition_with_reason(NodeState::Cancelled, Some(reason.clone()))
+                    .is_ok()
+            {
+                cancelled.push(id);
+            }
+        }
+
+        queue.extend(dag.get_dependents(id));
+    }
+
+    cancelled
+}
+
+/// Task a node is executing on a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningTask {
+    pub node_id: Uuid,
+    /// Address of the server running the task
+    pub server: String,
+    pub task_id: Uuid,
+}
+
+impl WorkflowContext {
+    /// Get the tasks of the nodes currently executing on servers
+    pub fn running_tasks(&self) -> Vec<RunningTask> {
+        let mut tasks: Vec<RunningTask> = self
+            .nodes
+            .values()
+            .filter(|n| n.state.is_active())
+            .filter_map(|n| {
+                let attempt = n.current_attempt()?;
+                Some(RunningTask {
+                    node_id: n.node_id,
+                    server: attempt.server.clone()?,
+                    task_id: attempt.task_id?,
+                })
+            })
+            .collect();
+        tasks.sort_by_key(|t| t.node_id);
+        tasks
+    }
+
+    /// Cancel the workflow and every node that is not executing on a server
+    ///
+    /// Returns the tasks still running, which their servers must be asked to
+    /// stop, or `None` if the workflow had already finished.
+    pub fn cancel(&mut self, reason: &str) -> Option<Vec<RunningTask>> {
+        if self.state.is_terminal() {
+            return None;
+        }
+
+        let running = self.running_tasks();
+        for node in self.nodes.values_mut().filter(|n| n.state.is_waiting()) {
+            if !running.iter().any(|t| t.node_id == node.node_id) {
+                let _ = node.transition_with_reason(NodeState::Cancelled, Some(reason.to_string()));
+            }
+        }
+        self.state = WorkflowState::Cancelled;
+        if self.completed_at.is_none() {
+            self.completed_at = Some(chrono::Utc::now());
+        }
+        Some(running)
+    }
+
+    /// Apply the failure policy after a node has failed
+    ///
+    /// Does nothing while the node can still be retried. Once retries are
+    /// exhausted, `FailFast` cancels every waiting node and fails the
+    /// workflow, `Continue` and `Ignore` cancel only the nodes downstream of
+    /// the failure, and `Quarantine` parks the node without cancelling
+    /// anything. Returns the IDs of the cancelled nodes.
+    ///
+    /// Nodes executing on servers are not cancelled: under `FailFast` the
+    /// caller asks their servers to stop the [`running_tasks`], and the
+    /// nodes are cancelled when the servers report them stopped.
+    ///
+    /// [`running_tasks`]: Self::running_tasks
+    pub fn handle_node_failure(&mut self, node_id: Uuid, dag: &WorkflowDag) -> Vec<Uuid> {
+        match self.get_node(&node_id) {
+            Some(node) if node.state == NodeState::Failed && !node.can_retry() => {}
+            _ => return Vec::new(),
+        }
+
+        match self.failure_policy {
+            FailurePolicy::FailFast => {
+                let reason = format!("fail-fast: node {node_id} failed");
+                let mut cancelled: Vec<Uuid> = self
+                    .nodes
+                    .values_mut()
+                    .filter(|n| n.state.is_waiting())
+                    .filter_map(|n| {
+                        n.transition_with_reason(NodeState::Cancelled, Some(reason.clone()))
+                            .ok()
+                            .map(|_| n.node_id)
+                    })
+                    .collect();
+                cancelled.sort();
+                self.state = WorkflowState::Failed;
+                if self.completed_at.is_none() {
+                    self.completed_at = Some(chrono::Utc::now());
+                }
+                cancelled
+            }
+            FailurePolicy::Continue | FailurePolicy::Ignore => {
+                cancel_downstream(node_id, dag, self)
+            }
+            FailurePolicy::Quarantine => {
+                if let Some(node) = self.get_node_mut(&node_id) {
+                    let reason = format!("quarantined after {} retries", node.retry_count);
+                    let _ = node.transition_with_reason(NodeState::Quarantined, Some(reason));
+                }
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl SharedWorkflowContext {
+    /// Cancel the workflow and every node that is not executing on a server
+    ///
+    /// See [`WorkflowContext::cancel`].
+    pub fn cancel(&self, reason: &str) -> Option<Vec<RunningTask>> {
+        self.update(|ctx| ctx.cancel(reason))
+    }
+
+    /// Apply the failure policy after a node has failed
+    ///
+    /// See [`WorkflowContext::handle_node_failure`].
+    pub fn handle_node_failure(&self, node_id: Uuid, dag: &WorkflowDag) -> Vec<Uuid> {
+        self.update(|ctx| ctx.handle_node_failure(node_id, dag))
+    }
+
+    /// Move the workflow to its final state if it has finished
+    pub fn finalize(&self) -> Option<WorkflowState> {
+        self.update(|ctx| ctx.finalize())
+    }
+
+    /// Cancel all waiting nodes downstream of `node_id`
+    ///
+    /// See [`cancel_downstream`]. The whole propagation runs under a single
+    /// write lock so observers never see a half-cancelled subgraph.
+    pub fn cancel_downstream(&self, node_id: Uuid, dag: &WorkflowDag) -> Vec<Uuid> {
+        self.update(|ctx| cancel_downstream(node_id, dag, ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::{NodeBuilder, WorkflowEdge};
+
+    fn edge() -> WorkflowEdge {
+        WorkflowEdge {
+            source_output: "out".to_string(),
+            target_input: "in".to_string(),
+            transform: None,
+        }
+    }
+
+    #[test]
+    fn test_cancel_downstream_transitive() {
+        // a -> b -> c, a -> d (running)
+        let mut dag = WorkflowDag::new();
+        let nodes: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|n| NodeBuilder::new("test", n).build())
+            .collect();
+        let ids: Vec<Uuid> = nodes.iter().map(|n| n.id).collect();
+        for node in nodes {
+            dag.add_node(node);
+        }
+        dag.add_edge(ids[0], ids[1], edge()).unwrap();
+        dag.add_edge(ids[1], ids[2], edge()).unwrap();
+        dag.add_edge(ids[0], ids[3], edge()).unwrap();
+
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        for id in &ids {
+            ctx.add_node(*id);
+        }
+        let d = ctx.get_node_mut(&ids[3]).unwrap();
+        d.transition(NodeState::Scheduled).unwrap();
+        d.transition(NodeState::Running).unwrap();
+        ctx.get_node_mut(&ids[0])
+            .unwrap()
+            .transition(NodeState::Cancelled)
+            .unwrap();
+
+        let cancelled = cancel_downstream(ids[0], &dag, &mut ctx);
+        assert_eq!(cancelled, vec![ids[1], ids[2]]);
+        assert_eq!(ctx.get_node(&ids[3]).unwrap().state, NodeState::Running);
+
+        let c = ctx.get_node(&ids[2]).unwrap();
+        assert_eq!(c.state, NodeState::Cancelled);
+        let reason = c.transitions.last().unwrap().reason.as_deref().unwrap();
+        assert!(reason.contains(&ids[0].to_string()));
+    }
+
+    fn fail_terminally(ctx: &mut WorkflowContext, id: Uuid) {
+        let node = ctx.get_node_mut(&id).unwrap();
+        node.max_retries = 0;
+        node.transition(NodeState::Scheduled).unwrap();
+        node.transition(NodeState::Running).unwrap();
+        node.fail("boom".to_string()).unwrap();
+    }
+
+    /// a -> b, c independent
+    fn branching(policy: FailurePolicy) -> (WorkflowDag, WorkflowContext, Vec<Uuid>) {
+        let mut dag = WorkflowDag::new();
+        let nodes: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|n| NodeBuilder::new("test", n).build())
+            .collect();
+        let ids: Vec<Uuid> = nodes.iter().map(|n| n.id).collect();
+        for node in nodes {
+            dag.add_node(node);
+        }
+        dag.add_edge(ids[0], ids[1], edge()).unwrap();
+
+        let mut ctx =
+            WorkflowContext::new(dag.workflow_id(), "test".to_string()).with_failure_policy(policy);
+        for id in &ids {
+            ctx.add_node(*id);
+        }
+        ctx.state = WorkflowState::Running;
+        (dag, ctx, ids)
+    }
+
+    #[test]
+    fn test_fail_fast_cancels_everything() {
+        let (dag, mut ctx, ids) = branching(FailurePolicy::FailFast);
+        fail_terminally(&mut ctx, ids[0]);
+
+        let cancelled = ctx.handle_node_failure(ids[0], &dag);
+        assert_eq!(cancelled.len(), 2);
+        assert_eq!(ctx.state, WorkflowState::Failed);
+        assert_eq!(ctx.finalize(), Some(WorkflowState::Failed));
+    }
+
+    #[test]
+    fn test_continue_and_ignore_run_independent_branches() {
+        for (policy, expected) in [
+            (FailurePolicy::Continue, WorkflowState::Failed),
+            (FailurePolicy::Ignore, WorkflowState::CompletedWithErrors),
+        ] {
+            let (dag, mut ctx, ids) = branching(policy);
+            fail_terminally(&mut ctx, ids[0]);
+
+            assert_eq!(ctx.handle_node_failure(ids[0], &dag), vec![ids[1]]);
+            assert_eq!(ctx.get_node(&ids[2]).unwrap().state, NodeState::Pending);
+            assert_eq!(ctx.final_state(), None);
+
+            let c = ctx.get_node_mut(&ids[2]).unwrap();
+            c.transition(NodeState::Scheduled).unwrap();
+            c.transition(NodeState::Running).unwrap();
+            c.transition(NodeState::Done).unwrap();
+            assert_eq!(ctx.finalize(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_quarantine_parks_node_until_released() {
+        let (dag, mut ctx, ids) = branching(FailurePolicy::Quarantine);
+        fail_terminally(&mut ctx, ids[0]);
+
+        assert!(ctx.handle_node_failure(ids[0], &dag).is_empty());
+        assert_eq!(ctx.quarantined_nodes(), vec![ids[0]]);
+        assert_eq!(ctx.get_node(&ids[1]).unwrap().state, NodeState::Pending);
+        assert_eq!(ctx.final_state(), None);
+
+        ctx.release_quarantined(ids[0], None).unwrap();
+        let a = ctx.get_node(&ids[0]).unwrap();
+        assert_eq!(a.state, NodeState::Retrying);
+        assert_eq!(a.retry_count, 0);
+        assert!(ctx.release_quarantined(ids[0], None).is_err());
+    }
+
+    #[test]
+    fn test_cancel_leaves_running_tasks_to_their_servers() {
+        let (_, mut ctx, ids) = branching(FailurePolicy::FailFast);
+        let task_id = Uuid::new_v4();
+        let a = ctx.get_node_mut(&ids[0]).unwrap();
+        a.transition(NodeState::Scheduled).unwrap();
+        a.transition(NodeState::Running).unwrap();
+        a.assign("http://gpu-1:9000".to_string(), Some(task_id));
+
+        let running = ctx.cancel("cancelled by user").unwrap();
+        assert_eq!(
+            running,
+            vec![RunningTask {
+                node_id: ids[0],
+                server: "http://gpu-1:9000".to_string(),
+                task_id,
+            }]
+        );
+        assert_eq!(ctx.get_node(&ids[0]).unwrap().state, NodeState::Running);
+        assert_eq!(ctx.get_node(&ids[1]).unwrap().state, NodeState::Cancelled);
+        assert_eq!(ctx.get_node(&ids[2]).unwrap().state, NodeState::Cancelled);
+        assert_eq!(ctx.state, WorkflowState::Cancelled);
+        assert!(ctx.cancel("again").is_none());
+    }
+
+    #[test]
+    fn test_retryable_failure_is_not_propagated() {
+        let (dag, mut ctx, ids) = branching(FailurePolicy::FailFast);
+        let a = ctx.get_node_mut(&ids[0]).unwrap();
+        a.transition(NodeState::Scheduled).unwrap();
+        a.fail("transient".to_string()).unwrap();
+
+        assert!(ctx.handle_node_failure(ids[0], &dag).is_empty());
+        assert_eq!(ctx.state, WorkflowState::Running);
+    }
+}
@@ -0,0 +1,129 @@
+//! Snapshot-based WAL compaction
+//!
+//! The WAL grows without bound unless old events are removed, but events
+//! can only be dropped once the state they describe is captured elsewhere.
+//! [`compact_with_snapshots`] writes a snapshot of every given workflow
+//! (the serialized [`WorkflowContext`]) and then removes the events that
+//! snapshot covers for workflows that have finished. Live workflows keep
+//! their events; on recovery their snapshot is loaded and only the events
+//! after it are replayed.
+
+use serde::{Deserialize, Serialize};
+
+use swarmx_events::{WalError, WalSnapshot, WriteAheadLog};
+
+use crate::state::WorkflowContext;
+
+/// Outcome of a compaction pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Number of snapshots written
+    pub snapshots_written: usize,
+    /// Number of workflows whose events were removed
+    pub workflows_compacted: usize,
+    /// Number of events removed
+    pub events_removed: u64,
+}
+
+/// Snapshot workflows and remove the events covered for finished ones
+pub fn compact_with_snapshots<'a>(
+    wal: &mut WriteAheadLog,
+    workflows: impl IntoIterator<Item = &'a WorkflowContext>,
+) -> Result<CompactionReport, WalError> {
+    let mut report = CompactionReport::default();
+
+    for ctx in workflows {
+        let terminal = ctx.state.is_terminal();
+        let snapshot = ctx.write_snapshot(wal)?;
+        report.snapshots_written += 1;
+
+        if terminal {
+            let removed = wal.compact_workflow(ctx.workflow_id, snapshot.sequence + 1)?;
+            report.events_removed += removed;
+            if removed > 0 {
+                report.workflows_compacted += 1;
+            }
+        }
+    }
+
+    tracing::info!(
+        snapshots = report.snapshots_written,
+        events_removed = report.events_removed,
+        "WAL compaction finished"
+    );
+    Ok(report)
+}
+
+impl WorkflowContext {
+    /// Write a snapshot of this context to the WAL
+    pub fn write_snapshot(&self, wal: &mut WriteAheadLog) -> Result<WalSnapshot, WalError> {
+        let state_json = serde_json::to_string(self)?;
+        wal.write_snapshot(self.workflow_id, state_json, self.state.is_terminal())
+    }
+
+    /// Restore a context from a WAL snapshot
+    ///
+    /// Events with a sequence number above `snapshot.sequence` still need
+    /// to be replayed on top of the result.
+    pub fn from_snapshot(snapshot: &WalSnapshot) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(&snapshot.state_json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{NodeState, WorkflowState};
+    use chrono::Utc;
+    use swarmx_events::Event;
+    use uuid::Uuid;
+
+    fn started(workflow_id: Uuid) -> Event {
+        Event::WorkflowStarted {
+            workflow_id,
+            name: "test".to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_compaction_keeps_live_workflow_events() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+
+        let mut done = WorkflowContext::new(Uuid::new_v4(), "done".to_string());
+        done.state = WorkflowState::Completed;
+        let mut live = WorkflowContext::new(Uuid::new_v4(), "live".to_string());
+        live.state = WorkflowState::Running;
+        let node = Uuid::new_v4();
+        live.add_node(node);
+        live.get_node_mut(&node)
+            .unwrap()
+            .transition(NodeState::Scheduled)
+            .unwrap();
+
+        wal.append_batch(vec![
+            started(done.workflow_id),
+            started(live.workflow_id),
+            started(done.workflow_id),
+        ])
+        .unwrap();
+
+        let report = compact_with_snapshots(&mut wal, [&done, &live]).unwrap();
+        assert_eq!(report.snapshots_written, 2);
+        assert_eq!(report.workflows_compacted, 1);
+        assert_eq!(report.events_removed, 2);
+        assert!(wal
+            .events_for_workflow(done.workflow_id)
+            .unwrap()
+            .is_empty());
+        assert_eq!(wal.events_for_workflow(live.workflow_id).unwrap().len(), 1);
+
+        let snapshot = wal.latest_snapshot(live.workflow_id).unwrap().unwrap();
+        assert_eq!(snapshot.sequence, 3);
+        let restored = WorkflowContext::from_snapshot(&snapshot).unwrap();
+        assert_eq!(
+            restored.get_node(&node).unwrap().state,
+            NodeState::Scheduled
+        );
+    }
+}
@@ -297,7 +297,17 @@ impl WorkflowDag {
 
     /// Get edges to a node
     pub fn get_incoming_edges(&self, node_id: Uuid) -> Vec<(Uuid, &WorkflowEdge)> {
-        todo!("Implement incoming edges query")
+        let Some(idx) = self.node_indices.get(&node_id) else {
+            return Vec::new();
+        };
+
+        self.graph
+            .edges_directed(*idx, Direction::Incoming)
+            .filter_map(|edge| {
+                let source_node = self.graph.node_weight(edge.source())?;
+                Some((source_node.id, edge.weight()))
+            })
+            .collect()
     }
 }
 
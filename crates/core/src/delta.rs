@@ -0,0 +1,434 @@
+//! Incremental workflow status updates
+//!
+//! The frontend only needs to know what changed since its last view of an
+//! execution. A [`WorkflowStatusDelta`] carries just the changed workflow
+//! fields and changed nodes between two [`WorkflowSnapshot`]s, and can be
+//! computed either from two snapshots or from a snapshot plus the events
+//! that happened after it.
+//!
+//! Optional fields distinguish "unchanged" from "cleared": an unchanged
+//! field is left out of the serialized delta, while a cleared one, e.g. the
+//! completion time of a node that was scheduled again, is sent as `null`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::state::{NodeSnapshot, NodeState, WorkflowSnapshot, WorkflowState};
+
+/// Changes between two snapshots of the same workflow execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowStatusDelta {
+    /// Workflow identifier
+    pub workflow_id: Uuid,
+    /// Execution identifier
+    pub execution_id: Uuid,
+    /// When the base snapshot was taken
+    pub since: DateTime<Utc>,
+    /// When the target snapshot was taken
+    pub until: DateTime<Utc>,
+    /// New workflow state, if changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<WorkflowState>,
+    /// New overall progress, if changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    /// New completion time, if changed; `Some(None)` if cleared
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "cleared")]
+    pub completed_at: Option<Option<DateTime<Utc>>>,
+    /// Nodes with at least one changed field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<NodeDelta>,
+    /// Nodes present in the base snapshot but not in the target
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_nodes: Vec<Uuid>,
+}
+
+/// Changed fields of a single node
+///
+/// Only fields that differ from the base snapshot are set. Nodes that are
+/// new in the target snapshot have every known field set.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NodeDelta {
+    /// Node identifier
+    pub node_id: Uuid,
+    /// New state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<NodeState>,
+    /// New retry count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_count: Option<u32>,
+    /// New error message; `Some(None)` if cleared
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "cleared")]
+    pub last_error: Option<Option<String>>,
+    /// New execution server; `Some(None)` if cleared
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "cleared")]
+    pub server: Option<Option<String>>,
+    /// New execution start time; `Some(None)` if cleared
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "cleared")]
+    pub started_at: Option<Option<DateTime<Utc>>>,
+    /// New completion time; `Some(None)` if cleared
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "cleared")]
+    pub completed_at: Option<Option<DateTime<Utc>>>,
+}
+
+/// Serialization of optional fields of a delta, where a missing field is
+/// unchanged and `null` is cleared
+mod cleared {
+    use super::*;
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &Option<Option<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => value.serialize(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Option<T>>, D::Error> {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+}
+
+impl NodeDelta {
+    /// Compute the changed fields of a node, or `None` if nothing changed
+    pub fn between(old: Option<&NodeSnapshot>, new: &NodeSnapshot) -> Option<Self> {
+        fn changed<T: PartialEq + Clone>(old: Option<&T>, new: &T) -> Option<T> {
+            match old {
+                Some(old) if old == new => None,
+                _ => Some(new.clone()),
+            }
+        }
+        // Unset fields of new nodes are left out rather than cleared
+        fn changed_opt<T: PartialEq + Clone>(
+            old: Option<&Option<T>>,
+            new: &Option<T>,
+        ) -> Option<Option<T>> {
+            match old {
+                None if new.is_none() => None,
+                old => changed(old, new),
+            }
+        }
+
+        let delta = Self {
+            node_id: new.node_id,
+            state: changed(old.map(|o| &o.state), &new.state),
+            retry_count: changed(old.map(|o| &o.retry_count), &new.retry_count),
+            last_error: changed_opt(old.map(|o| &o.last_error), &new.last_error),
+            server: changed_opt(old.map(|o| &o.server), &new.server),
+            started_at: changed_opt(old.map(|o| &o.started_at), &new.started_at),
+            completed_at: changed_opt(old.map(|o| &o.completed_at), &new.completed_at),
+        };
+
+        if old.is_some() && delta.is_empty() {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    /// Check if no field changed
+    pub fn is_empty(&self) -> bool {
+        self.state.is_none()
+            && self.retry_count.is_none()
+            && self.last_error.is_none()
+            && self.server.is_none()
+            && self.started_at.is_none()
+            && self.completed_at.is_none()
+    }
+}
+
+impl WorkflowStatusDelta {
+    /// Compute the delta between two snapshots of the same execution
+    pub fn between(old: &WorkflowSnapshot, new: &WorkflowSnapshot) -> Self {
+        let mut nodes: Vec<NodeDelta> = new
+            .nodes
+            .values()
+            .filter_map(|n| NodeDelta::between(old.nodes.get(&n.node_id), n))
+            .collect();
+        nodes.sort_by_key(|n| n.node_id);
+
+        let mut removed_nodes: Vec<Uuid> = old
+            .nodes
+            .keys()
+            .filter(|id| !new.nodes.contains_key(id))
+            .copied()
+            .collect();
+        removed_nodes.sort();
+
+        Self {
+            workflow_id: new.workflow_id,
+            execution_id: new.execution_id,
+            since: old.taken_at,
+            until: new.taken_at,
+            state: (old.state != new.state).then_some(new.state),
+            progress: (old.progress != new.progress).then_some(new.progress),
+            completed_at: (old.completed_at != new.completed_at).then_some(new.completed_at),
+            nodes,
+            removed_nodes,
+        }
+    }
+
+    /// Compute the delta produced by applying events on top of a snapshot
+    ///
+    /// Events for other workflows are ignored.
+    pub fn from_events<'a>(
+        base: &WorkflowSnapshot,
+        events: impl IntoIterator<Item = &'a Event>,
+    ) -> Self {
+        let mut target = base.clone();
+        for event in events {
+            target.apply_event(event);
+        }
+        Self::between(base, &target)
+    }
+
+    /// Check if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.state.is_none()
+            && self.progress.is_none()
+            && self.completed_at.is_none()
+            && self.nodes.is_empty()
+            && self.removed_nodes.is_empty()
+    }
+}
+
+impl WorkflowSnapshot {
+    /// Apply a delta to bring this snapshot up to date
+    pub fn apply_delta(&mut self, delta: &WorkflowStatusDelta) {
+        if let Some(state) = delta.state {
+            self.state = state;
+        }
+        if let Some(progress) = delta.progress {
+            self.progress = progress;
+        }
+        if let Some(completed_at) = delta.completed_at {
+            self.completed_at = completed_at;
+        }
+        for id in &delta.removed_nodes {
+            self.nodes.remove(id);
+        }
+        for change in &delta.nodes {
+            let node = self
+                .nodes
+                .entry(change.node_id)
+                .or_insert_with(|| NodeSnapshot {
+                    node_id: change.node_id,
+                    state: NodeState::Pending,
+                    retry_count: 0,
+                    last_error: None,
+                    server: None,
+                    started_at: None,
+                    completed_at: None,
+                });
+            if let Some(state) = change.state {
+                node.state = state;
+            }
+            if let Some(retry_count) = change.retry_count {
+                node.retry_count = retry_count;
+            }
+            if let Some(last_error) = &change.last_error {
+                node.last_error = last_error.clone();
+            }
+            if let Some(server) = &change.server {
+                node.server = server.clone();
+            }
+            if let Some(started_at) = change.started_at {
+                node.started_at = started_at;
+            }
+            if let Some(completed_at) = change.completed_at {
+                node.completed_at = completed_at;
+            }
+        }
+        self.taken_at = delta.until;
+    }
+
+    /// Apply a single event to this snapshot
+    ///
+    /// Events for other workflows or unknown nodes are ignored.
+    pub fn apply_event(&mut self, event: &Event) {
+        if event.workflow_id() != Some(self.workflow_id) {
+            return;
+        }
+
+        match event {
+            Event::WorkflowStarted { .. } => self.state = WorkflowState::Running,
+            Event::WorkflowCompleted { timestamp, .. } => {
+                self.state = WorkflowState::Completed;
+                self.completed_at = Some(*timestamp);
+            }
+            Event::WorkflowFailed { timestamp, .. } => {
+                self.state = WorkflowState::Failed;
+                self.completed_at = Some(*timestamp);
+            }
+            Event::WorkflowCancelled { timestamp, .. } => {
+                self.state = WorkflowState::Cancelled;
+                self.completed_at = Some(*timestamp);
+            }
+            _ => {
+                let Some(node) = event.node_id().and_then(|id| self.nodes.get_mut(&id)) else {
+                    return;
+                };
+                match event {
+                    Event::NodeScheduled { server, .. } => {
+                        node.state = NodeState::Scheduled;
+                        node.server = Some(server.clone());
+                    }
+                    Event::NodeDispatchFailed { error, .. } => {
+                        // Back to waiting until the scheduler places it again
+                        node.state = NodeState::Pending;
+                        node.last_error = Some(error.clone());
+                    }
+                    Event::NodeStarted { timestamp, .. } => {
+                        node.state = NodeState::Running;
+                        node.started_at.get_or_insert(*timestamp);
+                    }
+                    Event::NodeCompleted { timestamp, .. } => {
+                        node.state = NodeState::Done;
+                        node.completed_at = Some(*timestamp);
+                    }
+                    Event::NodeFailed {
+                        error,
+                        retry_count,
+                        timestamp,
+                        ..
+                    } => {
+                        node.state = NodeState::Failed;
+                        node.last_error = Some(error.clone());
+                        node.retry_count = *retry_count;
+                        node.completed_at = Some(*timestamp);
+                    }
+                    Event::NodeRetrying { retry_count, .. } => {
+                        node.state = NodeState::Retrying;
+                        node.retry_count = *retry_count;
+                    }
+                    Event::NodeCancelled { timestamp, .. } => {
+                        node.state = NodeState::Cancelled;
+                        node.completed_at = Some(*timestamp);
+                    }
+                    _ => return,
+                }
+                self.progress = if self.nodes.is_empty() {
+                    0.0
+                } else {
+                    self.terminal_count() as f64 / self.nodes.len() as f64
+                };
+            }
+        }
+        self.taken_at = event.timestamp().max(self.taken_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::WorkflowContext;
+
+    fn context_with_nodes(n: usize) -> (WorkflowContext, Vec<Uuid>) {
+        let mut ctx = WorkflowContext::new(Uuid::new_v4(), "test".to_string());
+        let ids: Vec<Uuid> = (0..n).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            ctx.add_node(*id);
+        }
+        (ctx, ids)
+    }
+
+    #[test]
+    fn test_delta_only_changed_nodes() {
+        let (mut ctx, ids) = context_with_nodes(3);
+        let before = ctx.snapshot();
+
+        ctx.get_node_mut(&ids[1])
+            .unwrap()
+            .transition(NodeState::Scheduled)
+            .unwrap();
+        let after = ctx.snapshot();
+
+        let delta = WorkflowStatusDelta::between(&before, &after);
+        assert_eq!(delta.nodes.len(), 1);
+        assert_eq!(delta.nodes[0].node_id, ids[1]);
+        assert_eq!(delta.nodes[0].state, Some(NodeState::Scheduled));
+        assert!(delta.nodes[0].retry_count.is_none());
+        assert!(delta.state.is_none());
+
+        let unchanged = WorkflowStatusDelta::between(&after, &after);
+        assert!(unchanged.is_empty());
+    }
+
+    #[test]
+    fn test_apply_delta_round_trip() {
+        let (mut ctx, ids) = context_with_nodes(2);
+        let mut client_view = ctx.snapshot();
+
+        let node = ctx.get_node_mut(&ids[0]).unwrap();
+        node.transition(NodeState::Scheduled).unwrap();
+        node.transition(NodeState::Running).unwrap();
+        node.transition(NodeState::Done).unwrap();
+        let server_view = ctx.snapshot();
+
+        let delta = WorkflowStatusDelta::between(&client_view, &server_view);
+        assert_eq!(delta.progress, Some(0.5));
+
+        client_view.apply_delta(&delta);
+        assert_eq!(client_view.nodes, server_view.nodes);
+        assert_eq!(client_view.progress, server_view.progress);
+    }
+
+    #[test]
+    fn test_delta_from_events() {
+        let (ctx, ids) = context_with_nodes(2);
+        let base = ctx.snapshot();
+        let events = vec![
+            Event::NodeScheduled {
+                workflow_id: ctx.workflow_id,
+                node_id: ids[0],
+                server: "server-a".to_string(),
+                timestamp: Utc::now(),
+            },
+            Event::NodeStarted {
+                workflow_id: Uuid::new_v4(),
+                node_id: ids[1],
+                timestamp: Utc::now(),
+            },
+        ];
+
+        let delta = WorkflowStatusDelta::from_events(&base, &events);
+        assert_eq!(delta.nodes.len(), 1);
+        assert_eq!(delta.nodes[0].server, Some(Some("server-a".to_string())));
+    }
+
+    #[test]
+    fn test_delta_clears_fields_of_reset_node() {
+        let (mut ctx, ids) = context_with_nodes(1);
+        let node = ctx.get_node_mut(&ids[0]).unwrap();
+        node.transition(NodeState::Scheduled).unwrap();
+        node.transition(NodeState::Running).unwrap();
+        node.fail("boom".to_string()).unwrap();
+        let mut client_view = ctx.snapshot();
+        assert!(client_view.nodes[&ids[0]].completed_at.is_some());
+
+        // Scheduling the retry resets the timing of the previous attempt
+        let node = ctx.get_node_mut(&ids[0]).unwrap();
+        node.transition(NodeState::Retrying).unwrap();
+        node.transition(NodeState::Scheduled).unwrap();
+        let server_view = ctx.snapshot();
+
+        let delta = WorkflowStatusDelta::between(&client_view, &server_view);
+        assert_eq!(delta.nodes[0].started_at, Some(None));
+        assert_eq!(delta.nodes[0].completed_at, Some(None));
+        assert_eq!(delta.nodes[0].last_error, None);
+
+        let json = serde_json::to_value(&delta).unwrap();
+        assert!(json["nodes"][0]["completed_at"].is_null());
+        assert!(json["nodes"][0].get("last_error").is_none());
+        let delta: WorkflowStatusDelta = serde_json::from_value(json).unwrap();
+        client_view.apply_delta(&delta);
+        assert_eq!(client_view.nodes, server_view.nodes);
+    }
+}
@@ -0,0 +1,225 @@
+//! Execution diagnostics
+//!
+//! Answers "why is this node stuck in Pending?" with a structured list of
+//! everything currently preventing the node from being scheduled.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::dag::WorkflowDag;
+use crate::scheduler::Scheduler;
+use crate::state::{NodeState, StateError, WorkflowContext, WorkflowState};
+
+/// A single reason why a node cannot be scheduled yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum BlockingReason {
+    /// The node is not waiting to be scheduled at all
+    NotSchedulable { state: NodeState },
+    /// An upstream dependency has not completed
+    UpstreamUnfinished {
+        node_id: Uuid,
+        name: String,
+        state: NodeState,
+    },
+    /// A required input has no incoming edge and no default value
+    MissingInput { input: String },
+    /// The workflow is paused
+    WorkflowPaused,
+    /// The workflow has already reached a terminal state
+    WorkflowFinished { state: WorkflowState },
+    /// No healthy server is registered
+    NoHealthyServer,
+    /// Healthy servers exist but none supports the node type
+    NoCapableServer { node_type: String },
+}
+
+impl fmt::Display for BlockingReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSchedulable { state } => {
+                write!(f, "node is {state:?}, not waiting to be scheduled")
+            }
+            Self::UpstreamUnfinished { name, state, .. } => {
+                write!(f, "upstream node '{name}' is {state:?}")
+            }
+            Self::MissingInput { input } => write!(f, "required input '{input}' is not connected"),
+            Self::WorkflowPaused => write!(f, "workflow is paused"),
+            Self::WorkflowFinished { state } => write!(f, "workflow is {state:?}"),
+            Self::NoHealthyServer => write!(f, "no healthy server is available"),
+            Self::NoCapableServer { node_type } => {
+                write!(f, "no healthy server supports node type '{node_type}'")
+            }
+        }
+    }
+}
+
+impl WorkflowContext {
+    /// Explain why a node is not being scheduled
+    ///
+    /// Covers workflow state, upstream dependencies, and input wiring. Use
+    /// [`Scheduler::blocking_reasons`] to also include server availability.
+    /// An empty result means the node is ready to be scheduled.
+    pub fn blocking_reasons(
+        &self,
+        node_id: Uuid,
+        dag: &WorkflowDag,
+    ) -> Result<Vec<BlockingReason>, StateError> {
+        let node_ctx = self
+            .get_node(&node_id)
+            .ok_or(StateError::NodeNotFound(node_id))?;
+        let node = dag
+            .get_node(node_id)
+            .ok_or(StateError::NodeNotFound(node_id))?;
+
+        let mut reasons = Vec::new();
+
+        if !node_ctx.state.can_schedule() {
+            reasons.push(BlockingReason::NotSchedulable {
+                state: node_ctx.state,
+            });
+        }
+
+        match self.state {
+            WorkflowState::Paused => reasons.push(BlockingReason::WorkflowPaused),
+            state if state.is_terminal() => {
+                reasons.push(BlockingReason::WorkflowFinished { state })
+            }
+            _ => {}
+        }
+
+        for dep_id in dag.get_dependencies(node_id) {
+            let state = self
+                .get_node(&dep_id)
+                .or_else(|| dag.get_context(dep_id))
+                .map(|c| c.state)
+                .unwrap_or_default();
+            if state != NodeState::Done {
+                reasons.push(BlockingReason::UpstreamUnfinished {
+                    node_id: dep_id,
+                    name: dag
+                        .get_node(dep_id)
+                        .map(|n| n.name.clone())
+                        .unwrap_or_default(),
+                    state,
+                });
+            }
+        }
+
+        let incoming = dag.get_incoming_edges(node_id);
+        for input in node
+            .inputs
+            .iter()
+            .filter(|i| i.required && i.default.is_none())
+        {
+            if !incoming.iter().any(|(_, e)| e.target_input == input.name) {
+                reasons.push(BlockingReason::MissingInput {
+                    input: input.name.clone(),
+                });
+            }
+        }
+
+        Ok(reasons)
+    }
+}
+
+impl Scheduler {
+    /// Explain why a node is not being scheduled, including server availability
+    pub fn blocking_reasons(
+        &self,
+        ctx: &WorkflowContext,
+        node_id: Uuid,
+        dag: &WorkflowDag,
+    ) -> Result<Vec<BlockingReason>, StateError> {
+        let mut reasons = ctx.blocking_reasons(node_id, dag)?;
+        let node = dag
+            .get_node(node_id)
+            .ok_or(StateError::NodeNotFound(node_id))?;
+
+        if self.healthy_servers().next().is_none() {
+            reasons.push(BlockingReason::NoHealthyServer);
+        } else if !self.healthy_servers().any(|s| s.supports(&node.node_type)) {
+            reasons.push(BlockingReason::NoCapableServer {
+                node_type: node.node_type.clone(),
+            });
+        }
+
+        Ok(reasons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::{NodeBuilder, WorkflowEdge};
+    use crate::scheduler::ServerInfo;
+
+    fn two_node_dag() -> (WorkflowDag, WorkflowContext, Uuid, Uuid) {
+        let mut dag = WorkflowDag::new();
+        let a = NodeBuilder::new("test.a", "A")
+            .output("out", "string")
+            .build();
+        let b = NodeBuilder::new("ai.chat", "B")
+            .input("in", "string", true)
+            .input("extra", "string", true)
+            .build();
+        let (a_id, b_id) = (a.id, b.id);
+        dag.add_node(a);
+        dag.add_node(b);
+        dag.add_edge(
+            a_id,
+            b_id,
+            WorkflowEdge {
+                source_output: "out".to_string(),
+                target_input: "in".to_string(),
+                transform: None,
+            },
+        )
+        .unwrap();
+
+        let mut ctx = WorkflowContext::new(dag.workflow_id(), "test".to_string());
+        ctx.add_node(a_id);
+        ctx.add_node(b_id);
+        ctx.state = WorkflowState::Running;
+        (dag, ctx, a_id, b_id)
+    }
+
+    #[test]
+    fn test_blocking_reasons_upstream_and_inputs() {
+        let (dag, ctx, a_id, b_id) = two_node_dag();
+
+        let reasons = ctx.blocking_reasons(b_id, &dag).unwrap();
+        assert_eq!(reasons.len(), 2);
+        assert!(reasons.contains(&BlockingReason::UpstreamUnfinished {
+            node_id: a_id,
+            name: "A".to_string(),
+            state: NodeState::Pending,
+        }));
+        assert!(reasons.contains(&BlockingReason::MissingInput {
+            input: "extra".to_string(),
+        }));
+
+        assert!(ctx.blocking_reasons(a_id, &dag).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_blocking_reasons_paused_and_servers() {
+        let (dag, mut ctx, a_id, _) = two_node_dag();
+        ctx.state = WorkflowState::Paused;
+
+        let mut scheduler = Scheduler::default();
+        let reasons = scheduler.blocking_reasons(&ctx, a_id, &dag).unwrap();
+        assert!(reasons.contains(&BlockingReason::WorkflowPaused));
+        assert!(reasons.contains(&BlockingReason::NoHealthyServer));
+
+        let mut server = ServerInfo::new("server-a".to_string());
+        server.capabilities = vec!["code.".to_string()];
+        scheduler.register_server(server);
+        let reasons = scheduler.blocking_reasons(&ctx, a_id, &dag).unwrap();
+        assert!(reasons.contains(&BlockingReason::NoCapableServer {
+            node_type: "test.a".to_string(),
+        }));
+    }
+}
@@ -0,0 +1,159 @@
+//! Time-travel queries over execution history
+//!
+//! Every node keeps its full transition history and attempt records, which
+//! is enough to reconstruct what the execution looked like at any past
+//! instant. Incident reviews use this to answer questions like "what was
+//! running at 03:12 when the server died?".
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::shared::SharedWorkflowContext;
+use crate::state::{
+    NodeContext, NodeSnapshot, NodeState, WorkflowContext, WorkflowSnapshot, WorkflowState,
+};
+
+impl NodeContext {
+    /// Reconstruct the node's status as of `at`
+    ///
+    /// Only transitions and attempts recorded at or before `at` are taken
+    /// into account.
+    pub fn snapshot_at(&self, at: DateTime<Utc>) -> NodeSnapshot {
+        let history = self.transitions.iter().take_while(|t| t.timestamp <= at);
+
+        let mut state = NodeState::Pending;
+        let mut retry_count = 0;
+        let mut last_error = None;
+        for transition in history {
+            state = transition.to;
+            match transition.to {
+                NodeState::Retrying => retry_count += 1,
+                NodeState::Failed => last_error = transition.reason.clone(),
+                _ => {}
+            }
+            // Releasing a quarantined node resets its retry budget
+            if transition.from == NodeState::Quarantined && transition.to == NodeState::Retrying {
+                retry_count = 0;
+            }
+        }
+
+        let attempt = self.attempts.iter().rev().find(|a| a.scheduled_at <= at);
+        NodeSnapshot {
+            node_id: self.node_id,
+            state,
+            retry_count,
+            last_error,
+            server: attempt.and_then(|a| a.server.clone()),
+            started_at: attempt.and_then(|a| a.started_at).filter(|t| *t <= at),
+            completed_at: attempt.and_then(|a| a.completed_at).filter(|t| *t <= at),
+        }
+    }
+}
+
+impl WorkflowContext {
+    /// Reconstruct the execution status as of `at`
+    ///
+    /// Node states are replayed from their transition histories. The
+    /// workflow state is derived from the execution's start and completion
+    /// times, since workflow-level changes in between are not recorded.
+    pub fn state_at(&self, at: DateTime<Utc>) -> WorkflowSnapshot {
+        let nodes: std::collections::HashMap<Uuid, NodeSnapshot> = self
+            .nodes
+            .values()
+            .map(|n| (n.node_id, n.snapshot_at(at)))
+            .collect();
+
+        let state = match self.completed_at {
+            Some(completed) if completed <= at => self.state,
+            _ if at < self.started_at || self.state == WorkflowState::Pending => {
+                WorkflowState::Pending
+            }
+            _ => WorkflowState::Running,
+        };
+
+        let mut snapshot = WorkflowSnapshot {
+            workflow_id: self.workflow_id,
+            execution_id: self.execution_id,
+            name: self.name.clone(),
+            state,
+            progress: 0.0,
+            started_at: self.started_at,
+            completed_at: self.completed_at.filter(|t| *t <= at),
+            nodes,
+            taken_at: at,
+        };
+        if !snapshot.nodes.is_empty() {
+            snapshot.progress = snapshot.terminal_count() as f64 / snapshot.nodes.len() as f64;
+        }
+        snapshot
+    }
+
+    /// Get the nodes that were running at `at`
+    pub fn running_at(&self, at: DateTime<Utc>) -> Vec<Uuid> {
+        let mut running: Vec<Uuid> = self
+            .nodes
+            .values()
+            .filter(|n| n.snapshot_at(at).state == NodeState::Running)
+            .map(|n| n.node_id)
+            .collect();
+        running.sort();
+        running
+    }
+}
+
+impl SharedWorkflowContext {
+    /// Reconstruct the execution status as of `at`
+    pub fn state_at(&self, at: DateTime<Utc>) -> WorkflowSnapshot {
+        self.read(|ctx| ctx.state_at(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_state_at_replays_history() {
+        let mut ctx = WorkflowContext::new(Uuid::new_v4(), "test".to_string());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        ctx.add_node(a);
+        ctx.add_node(b);
+        ctx.state = WorkflowState::Running;
+
+        let node = ctx.get_node_mut(&a).unwrap();
+        node.transition(NodeState::Scheduled).unwrap();
+        node.assign("server-a".to_string(), None);
+        node.transition(NodeState::Running).unwrap();
+        node.transition(NodeState::Done).unwrap();
+
+        // Spread the history out so the query points are unambiguous
+        let t0 = Utc::now() - Duration::hours(1);
+        let node = ctx.get_node_mut(&a).unwrap();
+        for (i, transition) in node.transitions.iter_mut().enumerate() {
+            transition.timestamp = t0 + Duration::minutes(10 * i as i64);
+        }
+        let attempt = &mut node.attempts[0];
+        attempt.scheduled_at = t0;
+        attempt.started_at = Some(t0 + Duration::minutes(10));
+        attempt.completed_at = Some(t0 + Duration::minutes(20));
+        ctx.started_at = t0 - Duration::minutes(1);
+
+        let during = ctx.state_at(t0 + Duration::minutes(15));
+        assert_eq!(during.state, WorkflowState::Running);
+        assert_eq!(during.nodes[&a].state, NodeState::Running);
+        assert_eq!(during.nodes[&a].server.as_deref(), Some("server-a"));
+        assert!(during.nodes[&a].completed_at.is_none());
+        assert_eq!(during.nodes[&b].state, NodeState::Pending);
+        assert_eq!(ctx.running_at(t0 + Duration::minutes(15)), vec![a]);
+
+        let before = ctx.state_at(t0 - Duration::minutes(5));
+        assert_eq!(before.state, WorkflowState::Pending);
+        assert_eq!(before.nodes[&a].state, NodeState::Pending);
+
+        let after = ctx.state_at(Utc::now());
+        assert_eq!(after.nodes[&a].state, NodeState::Done);
+        assert_eq!(after.progress, 0.5);
+        assert!(ctx.running_at(Utc::now()).is_empty());
+    }
+}
@@ -4,11 +4,37 @@
 //! - DAG (Directed Acyclic Graph) representation and manipulation
 //! - Node state machine for tracking execution progress
 //! - Scheduler for assigning nodes to servers
+//! - Thread-safe shared execution state
+//! - Incremental status deltas for UI updates
+//! - Diagnostics explaining why nodes are blocked
+//! - Cancellation propagation to downstream nodes
+//! - Human-in-the-loop approval gates
+//! - Aggregated execution metrics
+//! - Time-travel queries over execution history
+//! - Snapshot-based WAL compaction
+//! - Event-driven workflow triggers
 
+pub mod approval;
+pub mod cancel;
+pub mod compaction;
 pub mod dag;
+pub mod delta;
+pub mod diagnostics;
+pub mod history;
+pub mod metrics;
 pub mod scheduler;
+pub mod shared;
 pub mod state;
+pub mod triggers;
 
+pub use approval::*;
+pub use cancel::*;
+pub use compaction::*;
 pub use dag::*;
+pub use delta::*;
+pub use diagnostics::*;
+pub use metrics::*;
 pub use scheduler::*;
+pub use shared::*;
 pub use state::*;
+pub use triggers::*;
@@ -0,0 +1,129 @@
+//! Aggregated execution metrics per workflow
+//!
+//! Summarises a [`WorkflowContext`] in one pass so the status API and other
+//! consumers do not each iterate the nodes themselves.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::shared::SharedWorkflowContext;
+use crate::state::{NodeState, WorkflowContext};
+
+/// Aggregated metrics for a workflow execution
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkflowMetrics {
+    /// Total number of nodes
+    pub total_nodes: usize,
+    /// Number of nodes in each state
+    pub counts: HashMap<NodeState, usize>,
+    /// Total retries across all nodes
+    pub total_retries: u32,
+    /// Total execution attempts across all nodes
+    pub total_attempts: usize,
+    /// Cumulative execution time across all attempts, in milliseconds
+    pub compute_time_ms: u64,
+    /// Cumulative time attempts spent queued before starting, in milliseconds
+    pub queue_wait_ms: u64,
+    /// Node with the longest single attempt
+    pub longest_node: Option<LongestNode>,
+    /// Wall-clock time since the workflow started (or until it completed)
+    pub wall_time_ms: u64,
+}
+
+/// The node with the longest single attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LongestNode {
+    pub node_id: Uuid,
+    pub duration_ms: u64,
+}
+
+impl WorkflowMetrics {
+    /// Get the number of nodes in a state
+    pub fn count(&self, state: NodeState) -> usize {
+        self.counts.get(&state).copied().unwrap_or(0)
+    }
+}
+
+impl WorkflowContext {
+    /// Compute aggregated metrics for this execution
+    pub fn metrics(&self) -> WorkflowMetrics {
+        let mut metrics = WorkflowMetrics {
+            total_nodes: self.nodes.len(),
+            ..Default::default()
+        };
+
+        for node in self.nodes.values() {
+            *metrics.counts.entry(node.state).or_default() += 1;
+            metrics.total_retries += node.retry_count;
+            metrics.total_attempts += node.attempts.len();
+
+            for attempt in &node.attempts {
+                metrics.queue_wait_ms += attempt.queue_wait_ms().unwrap_or(0);
+                if let Some(duration) = attempt.duration_ms() {
+                    metrics.compute_time_ms += duration;
+                    if metrics
+                        .longest_node
+                        .is_none_or(|longest| duration > longest.duration_ms)
+                    {
+                        metrics.longest_node = Some(LongestNode {
+                            node_id: node.node_id,
+                            duration_ms: duration,
+                        });
+                    }
+                }
+            }
+        }
+
+        let end = self.completed_at.unwrap_or_else(chrono::Utc::now);
+        metrics.wall_time_ms = (end - self.started_at).num_milliseconds().max(0) as u64;
+        metrics
+    }
+}
+
+impl SharedWorkflowContext {
+    /// Compute aggregated metrics for this execution
+    pub fn metrics(&self) -> WorkflowMetrics {
+        self.read(|ctx| ctx.metrics())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_metrics_aggregation() {
+        let mut ctx = WorkflowContext::new(Uuid::new_v4(), "test".to_string());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        ctx.add_node(a);
+        ctx.add_node(b);
+
+        let node = ctx.get_node_mut(&a).unwrap();
+        node.transition(NodeState::Scheduled).unwrap();
+        node.transition(NodeState::Running).unwrap();
+        node.transition(NodeState::Done).unwrap();
+        // Pin timings so the breakdown is deterministic
+        let attempt = &mut node.attempts[0];
+        let t0 = attempt.scheduled_at;
+        attempt.started_at = Some(t0 + Duration::milliseconds(100));
+        attempt.completed_at = Some(t0 + Duration::milliseconds(600));
+
+        let metrics = ctx.metrics();
+        assert_eq!(metrics.total_nodes, 2);
+        assert_eq!(metrics.count(NodeState::Done), 1);
+        assert_eq!(metrics.count(NodeState::Pending), 1);
+        assert_eq!(metrics.total_attempts, 1);
+        assert_eq!(metrics.queue_wait_ms, 100);
+        assert_eq!(metrics.compute_time_ms, 500);
+        assert_eq!(
+            metrics.longest_node,
+            Some(LongestNode {
+                node_id: a,
+                duration_ms: 500
+            })
+        );
+    }
+}
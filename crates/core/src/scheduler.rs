@@ -6,15 +6,29 @@
 //! - Server load and availability
 //! - LLM session affinity
 //! - Resource requirements
+//!
+//! [`Scheduler::schedule_near_inputs`] places a node by the locality of its
+//! inputs as scored by the [`LocalityOracle`]. A decision that places a
+//! node away from its inputs also yields [`PrefetchRequest`]s, so the
+//! inputs are copied while the node is queued.
 
 use std::collections::HashMap;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
+use crate::approval::is_approval_node;
 use crate::dag::WorkflowDag;
+use swarmx_dataref::{
+    DataRef, LlmSession, LlmSessionManager, LocalityOracle, NetworkTopology, PrefetchRequest,
+};
 use swarmx_events::Event;
+use swarmx_protocol::{
+    GpuInfo, ProtocolVersion, ServerHeartbeat, ServerRegistration, ServerResources, VersionError,
+    PROTOCOL_VERSION,
+};
 
 /// Server information for scheduling decisions
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,16 +37,32 @@ pub struct ServerInfo {
     pub address: String,
     /// Available memory in bytes
     pub available_memory: u64,
+    /// Total memory in bytes
+    #[serde(default)]
+    pub total_memory: u64,
     /// Whether GPU is available
     pub gpu_available: bool,
+    /// GPUs and their free memory
+    #[serde(default)]
+    pub gpus: Vec<GpuInfo>,
     /// Current load (0.0 to 1.0)
     pub current_load: f64,
     /// Supported node types
     pub capabilities: Vec<String>,
     /// Currently loaded models (for LLM affinity)
     pub loaded_models: Vec<String>,
+    /// Tasks accepted and not yet running
+    #[serde(default)]
+    pub queue_depth: u32,
     /// Whether the server is healthy
     pub healthy: bool,
+    /// When the server last registered or sent a heartbeat; servers added
+    /// without either are not monitored
+    #[serde(default)]
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// Protocol version the server speaks
+    #[serde(default)]
+    pub protocol_version: ProtocolVersion,
 }
 
 impl ServerInfo {
@@ -41,19 +71,67 @@ impl ServerInfo {
         Self {
             address,
             available_memory: 0,
+            total_memory: 0,
             gpu_available: false,
+            gpus: Vec::new(),
             current_load: 0.0,
             capabilities: Vec::new(),
             loaded_models: Vec::new(),
+            queue_depth: 0,
             healthy: true,
+            last_heartbeat: None,
+            protocol_version: ProtocolVersion::default(),
         }
     }
 
+    /// Create the server info of a registration received at `received_at`
+    pub fn from_registration(
+        registration: &ServerRegistration,
+        received_at: DateTime<Utc>,
+    ) -> Self {
+        let mut server = Self::new(registration.address.clone());
+        server.capabilities = registration.capabilities.clone();
+        server.protocol_version = registration.protocol_version;
+        server.apply_resources(&registration.resources);
+        server.gpu_available = registration.has_gpu();
+        server.last_heartbeat = Some(received_at);
+        server
+    }
+
+    /// Record a heartbeat received at `received_at`
+    ///
+    /// Takes the reported resources and marks the server healthy again.
+    pub fn record_heartbeat(&mut self, heartbeat: &ServerHeartbeat, received_at: DateTime<Utc>) {
+        self.apply_resources(&heartbeat.resources);
+        self.gpu_available |= !heartbeat.resources.gpus.is_empty();
+        self.healthy = true;
+        self.last_heartbeat = Some(received_at);
+    }
+
+    fn apply_resources(&mut self, resources: &ServerResources) {
+        self.total_memory = resources.memory_total;
+        self.available_memory = resources.memory_available;
+        self.gpus = resources.gpus.clone();
+        self.loaded_models = resources.loaded_models.clone();
+        self.queue_depth = resources.queue_depth;
+        self.current_load = resources.load;
+    }
+
+    /// Check if the server has not been heard from for `timeout`
+    pub fn is_stale(&self, now: DateTime<Utc>, timeout: chrono::Duration) -> bool {
+        self.last_heartbeat.is_some_and(|at| now - at >= timeout)
+    }
+
     /// Check if server can handle a specific node type
     pub fn supports(&self, node_type: &str) -> bool {
         self.capabilities.is_empty() || self.capabilities.iter().any(|c| node_type.starts_with(c))
     }
 
+    /// Get the protocol version to speak with the server
+    pub fn negotiated_version(&self) -> Result<ProtocolVersion, VersionError> {
+        PROTOCOL_VERSION.negotiate(self.protocol_version)
+    }
+
     /// Check if server has a model loaded
     pub fn has_model(&self, model_id: &str) -> bool {
         self.loaded_models.iter().any(|m| m == model_id)
@@ -75,6 +153,24 @@ pub struct SchedulingDecision {
     pub estimated_duration_ms: Option<u64>,
 }
 
+impl SchedulingDecision {
+    /// Prefetch hints for the node's inputs that live on other servers
+    pub fn prefetch_requests<'a>(
+        &self,
+        workflow_id: Uuid,
+        inputs: impl IntoIterator<Item = &'a DataRef>,
+        topology: &dyn NetworkTopology,
+    ) -> Vec<PrefetchRequest> {
+        PrefetchRequest::for_inputs(
+            workflow_id,
+            self.node_id,
+            &self.target_server,
+            inputs,
+            topology,
+        )
+    }
+}
+
 /// Retry policy configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RetryPolicy {
@@ -135,8 +231,10 @@ pub struct Scheduler {
     strategy: SchedulingStrategy,
     /// Round-robin index
     rr_index: usize,
-    /// LLM session affinities (session_id -> preferred_server)
+    /// LLM session affinities set by hand (session_id -> preferred_server)
     session_affinities: HashMap<Uuid, String>,
+    /// Tracked LLM sessions, preferring the servers holding their caches
+    sessions: LlmSessionManager,
 }
 
 impl Scheduler {
@@ -149,9 +247,16 @@ impl Scheduler {
             strategy: SchedulingStrategy::default(),
             rr_index: 0,
             session_affinities: HashMap::new(),
+            sessions: LlmSessionManager::default(),
         }
     }
 
+    /// Track LLM sessions with the given manager
+    pub fn with_sessions(mut self, sessions: LlmSessionManager) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
     /// Set the event sender
     pub fn with_event_sender(mut self, tx: mpsc::Sender<Event>) -> Self {
         self.event_tx = Some(tx);
@@ -175,8 +280,11 @@ impl Scheduler {
     }
 
     /// Remove a server from the scheduling pool
-    pub fn unregister_server(&mut self, address: &str) {
+    ///
+    /// LLM sessions on the server are evicted along with their caches.
+    pub fn unregister_server(&mut self, address: &str) -> Vec<LlmSession> {
         self.servers.remove(address);
+        self.sessions.evict_server(address)
     }
 
     /// Get server information
@@ -195,14 +303,15 @@ impl Scheduler {
     }
 
     /// Schedule the next ready node from the DAG
+    ///
+    /// Approval gates are never dispatched to servers and are skipped; they
+    /// are halted with [`WorkflowContext::open_approval_gates`] instead.
     pub fn schedule_next(&mut self, dag: &WorkflowDag) -> Option<SchedulingDecision> {
-        let ready_nodes = dag.get_ready_nodes();
-        if ready_nodes.is_empty() {
-            return None;
-        }
-
-        // Schedule the first ready node
-        let node_id = ready_nodes[0];
+        // Schedule the first ready node that runs on a server
+        let node_id = dag
+            .get_ready_nodes()
+            .into_iter()
+            .find(|id| dag.get_node(*id).is_some_and(|n| !is_approval_node(n)))?;
         self.schedule_node(node_id, dag)
     }
 
@@ -212,7 +321,10 @@ impl Scheduler {
         node_id: Uuid,
         dag: &WorkflowDag,
     ) -> Option<SchedulingDecision> {
-        let _node = dag.get_node(node_id)?;
+        let node = dag.get_node(node_id)?;
+        if is_approval_node(node) {
+            return None;
+        }
 
         // Collect healthy servers into owned data to avoid borrow issues
         let healthy_servers: Vec<ServerInfo> = self
@@ -234,9 +346,15 @@ impl Scheduler {
                 (healthy_servers[idx].address.clone(), None)
             }
             SchedulingStrategy::LeastLoaded => {
+                // Shorter queues break ties in load
                 let server = healthy_servers
                     .iter()
-                    .min_by(|a, b| a.current_load.partial_cmp(&b.current_load).unwrap())
+                    .min_by(|a, b| {
+                        a.current_load
+                            .partial_cmp(&b.current_load)
+                            .unwrap()
+                            .then(a.queue_depth.cmp(&b.queue_depth))
+                    })
                     .unwrap();
                 (server.address.clone(), Some("least loaded".to_string()))
             }
@@ -281,14 +399,77 @@ impl Scheduler {
         self.schedule_node(node_id, dag)
     }
 
+    /// Schedule a node on the server its inputs are staged to fastest
+    ///
+    /// Servers are ranked by the [`LocalityOracle`]; among servers with the
+    /// same staging time the least loaded wins. The decision's reason
+    /// records the share of input bytes local to the server and the
+    /// estimated staging time.
+    pub fn schedule_near_inputs(
+        &mut self,
+        node_id: Uuid,
+        dag: &WorkflowDag,
+        inputs: &[DataRef],
+        topology: &dyn NetworkTopology,
+    ) -> Option<SchedulingDecision> {
+        let node = dag.get_node(node_id)?;
+        if is_approval_node(node) {
+            return None;
+        }
+        let candidates = self
+            .servers
+            .values()
+            .filter(|s| s.healthy && s.supports(&node.node_type))
+            .map(|s| s.address.as_str());
+        let load = |address: &str| self.servers.get(address).map_or(0.0, |s| s.current_load);
+        let best = LocalityOracle::new(topology)
+            .rank(inputs, candidates)
+            .into_iter()
+            .min_by(|a, b| {
+                a.staging_ms
+                    .cmp(&b.staging_ms)
+                    .then(load(&a.server).total_cmp(&load(&b.server)))
+            })?;
+
+        Some(SchedulingDecision {
+            node_id,
+            priority: 0,
+            affinity_reason: Some(format!(
+                "data locality: {:.0}% of input bytes local, {}ms staging",
+                best.score * 100.0,
+                best.staging_ms
+            )),
+            estimated_duration_ms: None,
+            target_server: best.server,
+        })
+    }
+
     /// Set LLM session affinity
+    ///
+    /// Overrides the affinity of a [tracked](Self::sessions_mut) session;
+    /// sessions are usually left to follow their KV caches.
     pub fn set_session_affinity(&mut self, session_id: Uuid, server: String) {
         self.session_affinities.insert(session_id, server);
     }
 
     /// Get session affinity
+    ///
+    /// Affinities set by hand win over the server holding the session's
+    /// KV cache.
     pub fn get_session_affinity(&self, session_id: &Uuid) -> Option<&String> {
-        self.session_affinities.get(session_id)
+        self.session_affinities
+            .get(session_id)
+            .or_else(|| self.sessions.affinity(session_id))
+    }
+
+    /// Get the tracked LLM sessions
+    pub fn sessions(&self) -> &LlmSessionManager {
+        &self.sessions
+    }
+
+    /// Get the tracked LLM sessions, to open, update, and evict them
+    pub fn sessions_mut(&mut self) -> &mut LlmSessionManager {
+        &mut self.sessions
     }
 
     /// Calculate backoff delay for a retry
@@ -303,6 +484,37 @@ impl Scheduler {
         }
     }
 
+    /// Record a server heartbeat
+    ///
+    /// Returns false if the server is not registered.
+    pub fn record_heartbeat(&mut self, heartbeat: &ServerHeartbeat) -> bool {
+        match self.servers.get_mut(&heartbeat.address) {
+            Some(server) => {
+                server.record_heartbeat(heartbeat, Utc::now());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mark servers not heard from for `timeout` as unhealthy
+    ///
+    /// Returns the addresses of the servers that were healthy until now.
+    pub fn expire_heartbeats(
+        &mut self,
+        now: DateTime<Utc>,
+        timeout: chrono::Duration,
+    ) -> Vec<String> {
+        let mut expired = Vec::new();
+        for server in self.servers.values_mut() {
+            if server.healthy && server.is_stale(now, timeout) {
+                server.healthy = false;
+                expired.push(server.address.clone());
+            }
+        }
+        expired
+    }
+
     /// Mark server as unhealthy
     pub fn mark_unhealthy(&mut self, address: &str) {
         if let Some(server) = self.servers.get_mut(address) {
@@ -392,6 +604,63 @@ mod tests {
         assert!(scheduler.get_server("http://localhost:9090").is_some());
     }
 
+    #[test]
+    fn test_server_heartbeats() {
+        let mut scheduler = Scheduler::default();
+        let registration = ServerRegistration::new(
+            "http://gpu-1:9090".to_string(),
+            vec!["ml.".to_string()],
+            ServerResources {
+                memory_available: 1 << 30,
+                ..Default::default()
+            },
+        );
+        let registered_at = Utc::now();
+        scheduler.register_server(ServerInfo::from_registration(&registration, registered_at));
+        let server = scheduler.get_server("http://gpu-1:9090").unwrap();
+        assert_eq!(server.available_memory, 1 << 30);
+        assert_eq!(server.protocol_version, PROTOCOL_VERSION);
+        assert!(!server.gpu_available);
+
+        let timeout = chrono::Duration::seconds(30);
+        assert!(scheduler
+            .expire_heartbeats(registered_at, timeout)
+            .is_empty());
+        let later = registered_at + timeout;
+        assert_eq!(
+            scheduler.expire_heartbeats(later, timeout),
+            ["http://gpu-1:9090"]
+        );
+        assert!(scheduler.expire_heartbeats(later, timeout).is_empty());
+        assert_eq!(scheduler.healthy_servers().count(), 0);
+
+        let heartbeat = ServerHeartbeat::new(
+            "http://gpu-1:9090".to_string(),
+            ServerResources {
+                loaded_models: vec!["llama-3-8b".to_string()],
+                queue_depth: 4,
+                load: 0.75,
+                ..Default::default()
+            },
+        );
+        assert!(scheduler.record_heartbeat(&heartbeat));
+        let server = scheduler.get_server("http://gpu-1:9090").unwrap();
+        assert!(server.healthy);
+        assert!(server.has_model("llama-3-8b"));
+        assert_eq!(server.queue_depth, 4);
+        assert_eq!(server.current_load, 0.75);
+
+        // Servers added without registering are not monitored
+        scheduler.register_server(ServerInfo::new("http://cpu-1:9090".to_string()));
+        let far = Utc::now() + chrono::Duration::days(1);
+        assert_eq!(
+            scheduler.expire_heartbeats(far, timeout),
+            ["http://gpu-1:9090"]
+        );
+        let unknown = ServerHeartbeat::new("http://cpu-2:9090".to_string(), Default::default());
+        assert!(!scheduler.record_heartbeat(&unknown));
+    }
+
     #[test]
     fn test_server_capabilities() {
         let mut server = ServerInfo::new("test".to_string());
@@ -401,4 +670,75 @@ mod tests {
         assert!(server.supports("code.python"));
         assert!(!server.supports("http.request"));
     }
+
+    #[test]
+    fn test_session_affinity_follows_sessions() {
+        let mut scheduler = Scheduler::default();
+        let (session, _) = scheduler.sessions_mut().open(
+            "deepseek-coder".to_string(),
+            "http://gpu-1:9090".to_string(),
+            4096,
+        );
+        let session_id = session.session_id;
+        assert_eq!(
+            scheduler.get_session_affinity(&session_id).unwrap(),
+            "http://gpu-1:9090"
+        );
+
+        scheduler.set_session_affinity(session_id, "http://gpu-2:9090".to_string());
+        assert_eq!(
+            scheduler.get_session_affinity(&session_id).unwrap(),
+            "http://gpu-2:9090"
+        );
+
+        let evicted = scheduler.unregister_server("http://gpu-1:9090");
+        assert_eq!(evicted.len(), 1);
+        assert!(scheduler.sessions().is_empty());
+    }
+
+    #[test]
+    fn test_schedule_near_inputs() {
+        let mut dag = WorkflowDag::new();
+        let node = crate::dag::NodeBuilder::new("ai.embed", "Embed").build();
+        let node_id = node.id;
+        dag.add_node(node);
+        let mut scheduler = Scheduler::default();
+        for address in [
+            "http://gpu-1:9090",
+            "http://gpu-2:9090",
+            "http://gpu-3:9090",
+        ] {
+            scheduler.register_server(ServerInfo::new(address.to_string()));
+        }
+        scheduler.update_server_load("http://gpu-1:9090", 0.9);
+        let mut corpus = DataRef::new(
+            "http://gpu-1:9090".to_string(),
+            1 << 30,
+            swarmx_dataref::DataType::Bytes,
+            Uuid::new_v4(),
+        );
+        corpus.add_replica("http://gpu-2:9090");
+        let topology = swarmx_dataref::StaticTopology::default();
+
+        // Both holders stage nothing; the less loaded one wins
+        let decision = scheduler
+            .schedule_near_inputs(node_id, &dag, &[corpus.clone()], &topology)
+            .unwrap();
+        assert_eq!(decision.target_server, "http://gpu-2:9090");
+        assert_eq!(
+            decision.affinity_reason.as_deref(),
+            Some("data locality: 100% of input bytes local, 0ms staging")
+        );
+
+        scheduler.mark_unhealthy("http://gpu-1:9090");
+        scheduler.mark_unhealthy("http://gpu-2:9090");
+        let decision = scheduler
+            .schedule_near_inputs(node_id, &dag, &[corpus], &topology)
+            .unwrap();
+        assert_eq!(decision.target_server, "http://gpu-3:9090");
+        assert!(decision
+            .affinity_reason
+            .unwrap()
+            .starts_with("data locality: 0%"));
+    }
 }
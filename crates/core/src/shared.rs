@@ -0,0 +1,233 @@
+//! Thread-safe shared workflow execution state
+//!
+//! The API handlers, the callback handler, and the engine loop all need to
+//! observe and mutate the same [`WorkflowContext`]. [`SharedWorkflowContext`]
+//! wraps it in an `Arc<RwLock<..>>` and exposes a curated API so callers
+//! never hand-roll locking.
+//!
+//! Deadlock avoidance is built into the API:
+//! - Lock guards are never returned. Every operation acquires the lock,
+//!   does its work, and releases it before returning.
+//! - Closure-based access ([`SharedWorkflowContext::read`] and
+//!   [`SharedWorkflowContext::update`]) runs synchronously, so a guard can
+//!   never be held across an `.await` point.
+//! - The lock is not reentrant: closures must not call back into the same
+//!   `SharedWorkflowContext`, and should not perform I/O or blocking work.
+//!   Compute what you need, return it, then act on it outside the closure.
+//! - Long-lived reads should use [`SharedWorkflowContext::snapshot`], which
+//!   returns a detached copy.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use uuid::Uuid;
+
+use crate::state::{
+    NodeState, StateError, StateTransition, TransitionOutcome, WorkflowContext, WorkflowSnapshot,
+    WorkflowState,
+};
+
+/// Cheaply cloneable, thread-safe handle to a [`WorkflowContext`]
+#[derive(Debug, Clone)]
+pub struct SharedWorkflowContext {
+    inner: Arc<RwLock<WorkflowContext>>,
+}
+
+impl SharedWorkflowContext {
+    /// Wrap a workflow context for shared access
+    pub fn new(ctx: WorkflowContext) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ctx)),
+        }
+    }
+
+    /// Run a read-only closure against the context
+    ///
+    /// The read lock is held only for the duration of the closure.
+    pub fn read<R>(&self, f: impl FnOnce(&WorkflowContext) -> R) -> R {
+        // A poisoned lock only means another thread panicked mid-update; the
+        // state machine validates every transition, so the data is still usable.
+        let guard = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        f(&guard)
+    }
+
+    /// Run a mutating closure against the context
+    ///
+    /// The write lock is held only for the duration of the closure.
+    pub fn update<R>(&self, f: impl FnOnce(&mut WorkflowContext) -> R) -> R {
+        let mut guard = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        f(&mut guard)
+    }
+
+    /// Get the workflow ID
+    pub fn workflow_id(&self) -> Uuid {
+        self.read(|ctx| ctx.workflow_id)
+    }
+
+    /// Get the execution ID
+    pub fn execution_id(&self) -> Uuid {
+        self.read(|ctx| ctx.execution_id)
+    }
+
+    /// Get the overall workflow state
+    pub fn state(&self) -> WorkflowState {
+        self.read(|ctx| ctx.state)
+    }
+
+    /// Get the current state of a node
+    pub fn node_state(&self, node_id: &Uuid) -> Option<NodeState> {
+        self.read(|ctx| ctx.get_node(node_id).map(|n| n.state))
+    }
+
+    /// Atomically transition a node to a new state
+    pub fn transition(
+        &self,
+        node_id: Uuid,
+        to: NodeState,
+        reason: Option<String>,
+    ) -> Result<StateTransition, StateError> {
+        self.update(|ctx| {
+            ctx.get_node_mut(&node_id)
+                .ok_or(StateError::NodeNotFound(node_id))?
+                .transition_with_reason(to, reason)
+        })
+    }
+
+    /// Atomically transition a node at most once per task
+    ///
+    /// See [`NodeContext::apply_transition`](crate::state::NodeContext::apply_transition).
+    pub fn apply_transition(
+        &self,
+        node_id: Uuid,
+        to: NodeState,
+        task_id: Option<Uuid>,
+        reason: Option<String>,
+    ) -> Result<TransitionOutcome, StateError> {
+        self.update(|ctx| {
+            ctx.get_node_mut(&node_id)
+                .ok_or(StateError::NodeNotFound(node_id))?
+                .apply_transition(to, task_id, reason)
+        })
+    }
+
+    /// Atomically mark a node as failed with an error
+    pub fn fail_node(&self, node_id: Uuid, error: String) -> Result<StateTransition, StateError> {
+        self.update(|ctx| {
+            ctx.get_node_mut(&node_id)
+                .ok_or(StateError::NodeNotFound(node_id))?
+                .fail(error)
+        })
+    }
+
+    /// Release a quarantined node back into scheduling
+    pub fn release_quarantined(
+        &self,
+        node_id: Uuid,
+        reason: Option<String>,
+    ) -> Result<StateTransition, StateError> {
+        self.update(|ctx| ctx.release_quarantined(node_id, reason))
+    }
+
+    /// Set the overall workflow state
+    pub fn set_state(&self, state: WorkflowState) {
+        self.update(|ctx| {
+            ctx.state = state;
+            if state.is_terminal() && ctx.completed_at.is_none() {
+                ctx.completed_at = Some(chrono::Utc::now());
+            }
+        })
+    }
+
+    /// Calculate overall progress (0.0 to 1.0)
+    pub fn progress(&self) -> f64 {
+        self.read(|ctx| ctx.progress())
+    }
+
+    /// Check if every node has reached a terminal state
+    pub fn is_complete(&self) -> bool {
+        self.read(|ctx| ctx.is_complete())
+    }
+
+    /// Take a detached, read-only status snapshot
+    pub fn snapshot(&self) -> WorkflowSnapshot {
+        self.read(|ctx| ctx.snapshot())
+    }
+}
+
+impl From<WorkflowContext> for SharedWorkflowContext {
+    fn from(ctx: WorkflowContext) -> Self {
+        Self::new(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared_with_node() -> (SharedWorkflowContext, Uuid) {
+        let mut ctx = WorkflowContext::new(Uuid::new_v4(), "test".to_string());
+        let node_id = Uuid::new_v4();
+        ctx.add_node(node_id);
+        (SharedWorkflowContext::new(ctx), node_id)
+    }
+
+    #[test]
+    fn test_shared_transition() {
+        let (shared, node_id) = shared_with_node();
+        let clone = shared.clone();
+
+        clone
+            .transition(node_id, NodeState::Scheduled, None)
+            .unwrap();
+        assert_eq!(shared.node_state(&node_id), Some(NodeState::Scheduled));
+
+        assert!(shared.transition(node_id, NodeState::Done, None).is_err());
+        assert!(matches!(
+            shared.transition(Uuid::new_v4(), NodeState::Scheduled, None),
+            Err(StateError::NodeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_shared_snapshot_is_detached() {
+        let (shared, node_id) = shared_with_node();
+        let snapshot = shared.snapshot();
+
+        shared
+            .transition(node_id, NodeState::Scheduled, None)
+            .unwrap();
+        shared
+            .transition(node_id, NodeState::Running, None)
+            .unwrap();
+        shared.transition(node_id, NodeState::Done, None).unwrap();
+
+        assert_eq!(snapshot.nodes[&node_id].state, NodeState::Pending);
+        assert_eq!(shared.snapshot().nodes[&node_id].state, NodeState::Done);
+        assert_eq!(shared.progress(), 1.0);
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        let mut ctx = WorkflowContext::new(Uuid::new_v4(), "test".to_string());
+        let ids: Vec<Uuid> = (0..8).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            ctx.add_node(*id);
+        }
+        let shared = SharedWorkflowContext::new(ctx);
+
+        let handles: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                let shared = shared.clone();
+                let id = *id;
+                std::thread::spawn(move || {
+                    shared.transition(id, NodeState::Scheduled, None).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(shared.snapshot().count_in_state(NodeState::Scheduled), 8);
+    }
+}
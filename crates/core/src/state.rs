@@ -7,11 +7,19 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use swarmx_protocol::TaskRequest;
+
 /// Node execution states
 ///
 /// ```text
 /// Node State Machine:
 ///
+///   ┌────────────────────┐  approve
+///   │ WaitingForApproval │ ─────────► Done
+///   └────────────────────┘ ─────────► Cancelled
+///          ▲                 reject
+///          │ approval gate ready
+///          │
 ///     ┌─────────┐     schedule      ┌───────────┐
 ///     │ Pending │ ─────────────────►│ Scheduled │
 ///     └─────────┘                   └─────┬─────┘
@@ -28,10 +36,11 @@ use uuid::Uuid;
 ///                                   │   Done    │
 ///                                   └───────────┘
 /// ```
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum NodeState {
     /// Node is waiting for dependencies to complete
+    #[default]
     Pending,
     /// Node has been scheduled for execution on a server
     Scheduled,
@@ -45,12 +54,10 @@ pub enum NodeState {
     Cancelled,
     /// Node is being retried
     Retrying,
-}
-
-impl Default for NodeState {
-    fn default() -> Self {
-        Self::Pending
-    }
+    /// Node is halted until a human approves or rejects it
+    WaitingForApproval,
+    /// Node exhausted its retries and is parked until manually released
+    Quarantined,
 }
 
 impl NodeState {
@@ -64,6 +71,17 @@ impl NodeState {
         matches!(self, NodeState::Pending | NodeState::Retrying)
     }
 
+    /// Check if the node is waiting to run and has not started executing
+    pub fn is_waiting(&self) -> bool {
+        matches!(
+            self,
+            NodeState::Pending
+                | NodeState::Scheduled
+                | NodeState::Retrying
+                | NodeState::WaitingForApproval
+        )
+    }
+
     /// Check if the node is actively executing
     pub fn is_active(&self) -> bool {
         matches!(self, NodeState::Scheduled | NodeState::Running)
@@ -72,11 +90,21 @@ impl NodeState {
     /// Get valid transitions from this state
     pub fn valid_transitions(&self) -> Vec<NodeState> {
         match self {
-            NodeState::Pending => vec![NodeState::Scheduled, NodeState::Cancelled],
+            NodeState::Pending => vec![
+                NodeState::Scheduled,
+                NodeState::WaitingForApproval,
+                NodeState::Cancelled,
+            ],
             NodeState::Scheduled => vec![NodeState::Running, NodeState::Failed, NodeState::Cancelled],
             NodeState::Running => vec![NodeState::Done, NodeState::Failed, NodeState::Cancelled],
-            NodeState::Failed => vec![NodeState::Retrying, NodeState::Cancelled],
+            NodeState::Failed => vec![
+                NodeState::Retrying,
+                NodeState::Quarantined,
+                NodeState::Cancelled,
+            ],
+            NodeState::Quarantined => vec![NodeState::Retrying, NodeState::Cancelled],
             NodeState::Retrying => vec![NodeState::Scheduled, NodeState::Cancelled],
+            NodeState::WaitingForApproval => vec![NodeState::Done, NodeState::Cancelled],
             NodeState::Done => vec![],
             NodeState::Cancelled => vec![],
         }
@@ -94,6 +122,9 @@ pub struct StateTransition {
     pub timestamp: DateTime<Utc>,
     /// Optional reason for the transition
     pub reason: Option<String>,
+    /// Task that caused the transition, used to deduplicate callbacks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<Uuid>,
 }
 
 impl StateTransition {
@@ -104,6 +135,84 @@ impl StateTransition {
             to,
             timestamp: Utc::now(),
             reason,
+            task_id: None,
+        }
+    }
+}
+
+/// Result of an idempotent transition
+#[derive(Debug, Clone)]
+pub enum TransitionOutcome {
+    /// The transition was applied
+    Applied(StateTransition),
+    /// The same transition was already recorded for this task; nothing changed
+    AlreadyApplied(StateTransition),
+}
+
+impl TransitionOutcome {
+    /// Check if the transition was newly applied
+    pub fn is_applied(&self) -> bool {
+        matches!(self, Self::Applied(_))
+    }
+
+    /// Get the recorded transition
+    pub fn transition(&self) -> &StateTransition {
+        match self {
+            Self::Applied(t) | Self::AlreadyApplied(t) => t,
+        }
+    }
+}
+
+/// Record of a single execution attempt of a node
+///
+/// A new attempt begins each time the node is scheduled, so retries never
+/// overwrite what earlier attempts did.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    /// Attempt number, starting at 1
+    pub attempt: u32,
+    /// Server-assigned task ID
+    pub task_id: Option<Uuid>,
+    /// Server the attempt ran on
+    pub server: Option<String>,
+    /// When the attempt was scheduled
+    pub scheduled_at: DateTime<Utc>,
+    /// When execution started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the attempt finished
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Terminal state the attempt ended in
+    pub outcome: Option<NodeState>,
+    /// Error message if the attempt failed
+    pub error: Option<String>,
+}
+
+impl AttemptRecord {
+    /// Start a new attempt record
+    pub fn new(attempt: u32, scheduled_at: DateTime<Utc>) -> Self {
+        Self {
+            attempt,
+            task_id: None,
+            server: None,
+            scheduled_at,
+            started_at: None,
+            completed_at: None,
+            outcome: None,
+            error: None,
+        }
+    }
+
+    /// Get the time spent queued before execution started, in milliseconds
+    pub fn queue_wait_ms(&self) -> Option<u64> {
+        self.started_at
+            .map(|start| (start - self.scheduled_at).num_milliseconds().max(0) as u64)
+    }
+
+    /// Get the execution duration in milliseconds if the attempt finished
+    pub fn duration_ms(&self) -> Option<u64> {
+        match (self.started_at, self.completed_at) {
+            (Some(start), Some(end)) => Some((end - start).num_milliseconds() as u64),
+            _ => None,
         }
     }
 }
@@ -126,6 +235,9 @@ pub struct NodeContext {
     pub max_retries: u32,
     /// Last error message if failed
     pub last_error: Option<String>,
+    /// When the node was last scheduled
+    #[serde(default)]
+    pub scheduled_at: Option<DateTime<Utc>>,
     /// When execution started
     pub started_at: Option<DateTime<Utc>>,
     /// When execution completed
@@ -134,6 +246,12 @@ pub struct NodeContext {
     pub server: Option<String>,
     /// History of state transitions
     pub transitions: Vec<StateTransition>,
+    /// Record of every execution attempt, oldest first
+    #[serde(default)]
+    pub attempts: Vec<AttemptRecord>,
+    /// Outstanding approval request, while waiting for approval
+    #[serde(default)]
+    pub approval: Option<crate::approval::PendingApproval>,
 }
 
 impl NodeContext {
@@ -146,10 +264,13 @@ impl NodeContext {
             retry_count: 0,
             max_retries: 3,
             last_error: None,
+            scheduled_at: None,
             started_at: None,
             completed_at: None,
             server: None,
             transitions: Vec::new(),
+            attempts: Vec::new(),
+            approval: None,
         }
     }
 
@@ -182,15 +303,30 @@ impl NodeContext {
         self.transitions.push(transition.clone());
         self.state = to;
 
-        // Update timing information
+        // Update timing information; top-level fields track the latest attempt
+        let now = transition.timestamp;
         match to {
-            NodeState::Running => {
-                if self.started_at.is_none() {
-                    self.started_at = Some(Utc::now());
+            NodeState::Scheduled => {
+                self.scheduled_at = Some(now);
+                self.started_at = None;
+                self.completed_at = None;
+                self.attempts.push(AttemptRecord::new(self.attempts.len() as u32 + 1, now));
+            }
+            NodeState::Running if self.started_at.is_none() => {
+                self.started_at = Some(now);
+                if let Some(attempt) = self.open_attempt_mut() {
+                    attempt.started_at = Some(now);
                 }
             }
             NodeState::Done | NodeState::Failed | NodeState::Cancelled => {
-                self.completed_at = Some(Utc::now());
+                self.completed_at = Some(now);
+                if let Some(attempt) = self.open_attempt_mut() {
+                    attempt.completed_at = Some(now);
+                    attempt.outcome = Some(to);
+                    if to == NodeState::Failed {
+                        attempt.error = transition.reason.clone();
+                    }
+                }
             }
             NodeState::Retrying => {
                 self.retry_count += 1;
@@ -201,6 +337,74 @@ impl NodeContext {
         Ok(transition)
     }
 
+    /// Transition to a new state at most once per task
+    ///
+    /// Duplicate callbacks (e.g. network retries) carry the same task ID. If
+    /// a transition to `to` was already recorded for `task_id`, nothing
+    /// changes and `AlreadyApplied` is returned instead of an error. Without
+    /// a task ID this behaves like [`transition_with_reason`].
+    ///
+    /// [`transition_with_reason`]: Self::transition_with_reason
+    pub fn apply_transition(
+        &mut self,
+        to: NodeState,
+        task_id: Option<Uuid>,
+        reason: Option<String>,
+    ) -> Result<TransitionOutcome, StateError> {
+        if let Some(task_id) = task_id {
+            if let Some(existing) = self
+                .transitions
+                .iter()
+                .find(|t| t.task_id == Some(task_id) && t.to == to)
+            {
+                return Ok(TransitionOutcome::AlreadyApplied(existing.clone()));
+            }
+        }
+
+        let mut transition = self.transition_with_reason(to, reason)?;
+        if to == NodeState::Failed {
+            if let Some(error) = &transition.reason {
+                self.last_error = Some(error.clone());
+            }
+        }
+        if let Some(task_id) = task_id {
+            transition.task_id = Some(task_id);
+            if let Some(recorded) = self.transitions.last_mut() {
+                recorded.task_id = Some(task_id);
+            }
+            if let Some(attempt) = self.attempts.last_mut() {
+                attempt.task_id.get_or_insert(task_id);
+            }
+        }
+        Ok(TransitionOutcome::Applied(transition))
+    }
+
+    /// Record where the current attempt is executing
+    ///
+    /// Sets the top-level `server` and the server and task ID of the latest
+    /// attempt.
+    pub fn assign(&mut self, server: String, task_id: Option<Uuid>) {
+        if let Some(attempt) = self.attempts.last_mut() {
+            attempt.server = Some(server.clone());
+            if task_id.is_some() {
+                attempt.task_id = task_id;
+            }
+        }
+        self.server = Some(server);
+    }
+
+    /// Get the latest attempt, if the node was ever scheduled
+    pub fn current_attempt(&self) -> Option<&AttemptRecord> {
+        self.attempts.last()
+    }
+
+    /// Get the latest attempt if it has not finished yet
+    fn open_attempt_mut(&mut self) -> Option<&mut AttemptRecord> {
+        self.attempts
+            .last_mut()
+            .filter(|a| a.completed_at.is_none())
+    }
+
     /// Check if a transition to the given state is valid
     pub fn can_transition_to(&self, to: NodeState) -> bool {
         self.state.valid_transitions().contains(&to)
@@ -217,6 +421,22 @@ impl NodeContext {
         self.state == NodeState::Failed && self.retry_count < self.max_retries
     }
 
+    /// Release a quarantined node back into scheduling
+    ///
+    /// The node moves to `Retrying` with a fresh retry budget; earlier
+    /// attempts remain in `attempts`.
+    pub fn release(&mut self, reason: Option<String>) -> Result<StateTransition, StateError> {
+        if self.state != NodeState::Quarantined {
+            return Err(StateError::InvalidTransition {
+                from: self.state,
+                to: NodeState::Retrying,
+            });
+        }
+        let transition = self.transition_with_reason(NodeState::Retrying, reason)?;
+        self.retry_count = 0;
+        Ok(transition)
+    }
+
     /// Get the execution duration if completed
     pub fn duration(&self) -> Option<chrono::Duration> {
         match (self.started_at, self.completed_at) {
@@ -244,6 +464,9 @@ pub struct WorkflowContext {
     pub name: String,
     /// Overall workflow state
     pub state: WorkflowState,
+    /// Reaction to terminal node failures
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
     /// When execution started
     pub started_at: DateTime<Utc>,
     /// When execution completed
@@ -260,14 +483,51 @@ pub enum WorkflowState {
     Pending,
     /// Workflow is actively executing
     Running,
+    /// Workflow is paused; no new nodes are scheduled
+    Paused,
     /// Workflow completed successfully
     Completed,
+    /// Workflow finished, but some nodes failed and the failure policy ignored them
+    CompletedWithErrors,
     /// Workflow failed
     Failed,
     /// Workflow was cancelled
     Cancelled,
 }
 
+impl WorkflowState {
+    /// Check if this is a terminal state
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            WorkflowState::Completed
+                | WorkflowState::CompletedWithErrors
+                | WorkflowState::Failed
+                | WorkflowState::Cancelled
+        )
+    }
+}
+
+/// How a workflow reacts when a node fails terminally (retries exhausted)
+///
+/// Under `Quarantine` the failed node is parked in [`NodeState::Quarantined`]
+/// instead: its downstream waits, independent branches keep running, and the
+/// workflow cannot finish until the node is released or cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Cancel every waiting node and fail the workflow immediately
+    #[default]
+    FailFast,
+    /// Cancel only the failed node's downstream; independent branches keep
+    /// running and the workflow fails once everything has finished
+    Continue,
+    /// Like `Continue`, but the workflow finishes as completed-with-errors
+    Ignore,
+    /// Park the node for manual release instead of failing it
+    Quarantine,
+}
+
 impl WorkflowContext {
     /// Create a new workflow context
     pub fn new(workflow_id: Uuid, name: String) -> Self {
@@ -276,12 +536,19 @@ impl WorkflowContext {
             execution_id: Uuid::new_v4(),
             name,
             state: WorkflowState::Pending,
+            failure_policy: FailurePolicy::default(),
             started_at: Utc::now(),
             completed_at: None,
             nodes: std::collections::HashMap::new(),
         }
     }
 
+    /// Set the failure policy
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
     /// Add a node context
     pub fn add_node(&mut self, node_id: Uuid) {
         let ctx = NodeContext::new(node_id, self.workflow_id);
@@ -298,6 +565,54 @@ impl WorkflowContext {
         self.nodes.get_mut(node_id)
     }
 
+    /// Find the node a server task was submitted for, by any of its attempts
+    pub fn node_for_task(&self, task_id: Uuid) -> Option<Uuid> {
+        self.nodes
+            .values()
+            .find(|n| n.attempts.iter().any(|a| a.task_id == Some(task_id)))
+            .map(|n| n.node_id)
+    }
+
+    /// Get the number of a node's current attempt
+    ///
+    /// Resubmissions of the attempt reuse the number, so together with the
+    /// execution ID it keys the attempt's task requests idempotently.
+    /// `None` if the node was never scheduled.
+    pub fn attempt_number(&self, node_id: &Uuid) -> Option<u32> {
+        Some(self.nodes.get(node_id)?.current_attempt()?.attempt)
+    }
+
+    /// Prepare a node's task request for submission to its server
+    ///
+    /// Keys the request with the node's current attempt, so a server that
+    /// already accepted the attempt answers a resubmission with the same
+    /// task instead of running the node again. `None` if the node was never
+    /// scheduled.
+    pub fn keyed_task_request(&self, request: TaskRequest) -> Option<TaskRequest> {
+        let attempt = self.attempt_number(&request.node_id)?;
+        Some(request.with_idempotency_key(self.execution_id, attempt))
+    }
+
+    /// Release a quarantined node back into scheduling
+    pub fn release_quarantined(
+        &mut self,
+        node_id: Uuid,
+        reason: Option<String>,
+    ) -> Result<StateTransition, StateError> {
+        self.get_node_mut(&node_id)
+            .ok_or(StateError::NodeNotFound(node_id))?
+            .release(reason)
+    }
+
+    /// Get the IDs of all quarantined nodes
+    pub fn quarantined_nodes(&self) -> Vec<Uuid> {
+        self.nodes
+            .values()
+            .filter(|n| n.state == NodeState::Quarantined)
+            .map(|n| n.node_id)
+            .collect()
+    }
+
     /// Calculate overall progress (0.0 to 1.0)
     pub fn progress(&self) -> f64 {
         if self.nodes.is_empty() {
@@ -315,6 +630,135 @@ impl WorkflowContext {
     pub fn is_complete(&self) -> bool {
         self.nodes.values().all(|n| n.state.is_terminal())
     }
+
+    /// Compute the final workflow state once every node is terminal
+    ///
+    /// Returns `None` while nodes are still outstanding. Failed nodes are
+    /// resolved according to the failure policy; an explicitly cancelled
+    /// workflow stays cancelled.
+    pub fn final_state(&self) -> Option<WorkflowState> {
+        if self.state == WorkflowState::Cancelled {
+            return Some(WorkflowState::Cancelled);
+        }
+        if self.state == WorkflowState::Failed && self.failure_policy == FailurePolicy::FailFast {
+            return Some(WorkflowState::Failed);
+        }
+        if !self.is_complete() {
+            return None;
+        }
+
+        let any_failed = self.nodes.values().any(|n| n.state == NodeState::Failed);
+        let any_cancelled = self.nodes.values().any(|n| n.state == NodeState::Cancelled);
+
+        Some(match (any_failed, self.failure_policy) {
+            (true, FailurePolicy::Ignore) => WorkflowState::CompletedWithErrors,
+            (true, _) => WorkflowState::Failed,
+            (false, _) if any_cancelled => WorkflowState::Cancelled,
+            (false, _) => WorkflowState::Completed,
+        })
+    }
+
+    /// Move the workflow to its final state if it has finished
+    ///
+    /// Returns the final state when the workflow is (now) terminal.
+    pub fn finalize(&mut self) -> Option<WorkflowState> {
+        let state = self.final_state()?;
+        self.state = state;
+        if self.completed_at.is_none() {
+            self.completed_at = Some(Utc::now());
+        }
+        Some(state)
+    }
+
+    /// Take a point-in-time status snapshot of the workflow and its nodes
+    pub fn snapshot(&self) -> WorkflowSnapshot {
+        WorkflowSnapshot {
+            workflow_id: self.workflow_id,
+            execution_id: self.execution_id,
+            name: self.name.clone(),
+            state: self.state,
+            progress: self.progress(),
+            started_at: self.started_at,
+            completed_at: self.completed_at,
+            nodes: self
+                .nodes
+                .values()
+                .map(|n| (n.node_id, NodeSnapshot::from(n)))
+                .collect(),
+            taken_at: Utc::now(),
+        }
+    }
+}
+
+/// Read-only status snapshot of a workflow execution
+///
+/// Snapshots are detached copies: they can be held across await points,
+/// serialized, and compared without holding any lock on the live context.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowSnapshot {
+    /// Workflow identifier
+    pub workflow_id: Uuid,
+    /// Execution identifier
+    pub execution_id: Uuid,
+    /// Workflow name
+    pub name: String,
+    /// Overall workflow state
+    pub state: WorkflowState,
+    /// Overall progress (0.0 to 1.0)
+    pub progress: f64,
+    /// When execution started
+    pub started_at: DateTime<Utc>,
+    /// When execution completed
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Per-node status
+    pub nodes: std::collections::HashMap<Uuid, NodeSnapshot>,
+    /// When the snapshot was taken
+    pub taken_at: DateTime<Utc>,
+}
+
+impl WorkflowSnapshot {
+    /// Count nodes currently in the given state
+    pub fn count_in_state(&self, state: NodeState) -> usize {
+        self.nodes.values().filter(|n| n.state == state).count()
+    }
+
+    /// Count nodes that have reached a terminal state
+    pub fn terminal_count(&self) -> usize {
+        self.nodes.values().filter(|n| n.state.is_terminal()).count()
+    }
+}
+
+/// Read-only status of a single node within a [`WorkflowSnapshot`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    /// Node identifier
+    pub node_id: Uuid,
+    /// Current state
+    pub state: NodeState,
+    /// Number of retry attempts
+    pub retry_count: u32,
+    /// Last error message if failed
+    pub last_error: Option<String>,
+    /// Server where the node is/was executing
+    pub server: Option<String>,
+    /// When execution started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When execution completed
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl From<&NodeContext> for NodeSnapshot {
+    fn from(ctx: &NodeContext) -> Self {
+        Self {
+            node_id: ctx.node_id,
+            state: ctx.state,
+            retry_count: ctx.retry_count,
+            last_error: ctx.last_error.clone(),
+            server: ctx.server.clone(),
+            started_at: ctx.started_at,
+            completed_at: ctx.completed_at,
+        }
+    }
 }
 
 /// State machine errors
@@ -331,6 +775,9 @@ pub enum StateError {
 
     #[error("Workflow not found: {0}")]
     WorkflowNotFound(Uuid),
+
+    #[error("Invalid configuration on node {node_id}: {message}")]
+    InvalidConfig { node_id: Uuid, message: String },
 }
 
 #[cfg(test)]
@@ -365,10 +812,145 @@ mod tests {
         assert!(ctx.completed_at.is_some());
     }
 
+    #[test]
+    fn test_attempt_records_survive_retries() {
+        let mut ctx = NodeContext::new(Uuid::new_v4(), Uuid::new_v4());
+
+        ctx.transition(NodeState::Scheduled).unwrap();
+        ctx.assign("server-a".to_string(), Some(Uuid::new_v4()));
+        ctx.transition(NodeState::Running).unwrap();
+        ctx.fail("timeout".to_string()).unwrap();
+
+        ctx.transition(NodeState::Retrying).unwrap();
+        ctx.transition(NodeState::Scheduled).unwrap();
+        assert!(ctx.started_at.is_none());
+        ctx.assign("server-b".to_string(), None);
+        ctx.transition(NodeState::Running).unwrap();
+        ctx.transition(NodeState::Done).unwrap();
+
+        assert_eq!(ctx.attempts.len(), 2);
+        let first = &ctx.attempts[0];
+        assert_eq!(first.server.as_deref(), Some("server-a"));
+        assert_eq!(first.outcome, Some(NodeState::Failed));
+        assert_eq!(first.error.as_deref(), Some("timeout"));
+
+        let second = ctx.current_attempt().unwrap();
+        assert_eq!(second.attempt, 2);
+        assert_eq!(second.outcome, Some(NodeState::Done));
+        assert_eq!(ctx.server.as_deref(), Some("server-b"));
+        assert_eq!(ctx.started_at, second.started_at);
+    }
+
+    #[test]
+    fn test_attempt_number_follows_attempts() {
+        let node_id = Uuid::new_v4();
+        let mut workflow = WorkflowContext::new(Uuid::new_v4(), "retries".to_string());
+        workflow.add_node(node_id);
+        assert_eq!(workflow.attempt_number(&node_id), None);
+
+        let ctx = workflow.get_node_mut(&node_id).unwrap();
+        ctx.transition(NodeState::Scheduled).unwrap();
+        let first = workflow.attempt_number(&node_id).unwrap();
+        assert_eq!(workflow.attempt_number(&node_id).unwrap(), first);
+
+        let ctx = workflow.get_node_mut(&node_id).unwrap();
+        ctx.transition(NodeState::Running).unwrap();
+        ctx.fail("timeout".to_string()).unwrap();
+        ctx.transition(NodeState::Retrying).unwrap();
+        ctx.transition(NodeState::Scheduled).unwrap();
+        assert_ne!(workflow.attempt_number(&node_id).unwrap(), first);
+    }
+
+    #[test]
+    fn test_task_requests_are_keyed_by_attempt() {
+        let node_id = Uuid::new_v4();
+        let mut workflow = WorkflowContext::new(Uuid::new_v4(), "retries".to_string());
+        workflow.add_node(node_id);
+        let request = TaskRequest {
+            node_id,
+            node_type: "ai.openai.chat".to_string(),
+            inputs: Vec::new(),
+            config: serde_json::json!({}),
+            callback_url: "http://localhost:3000/callback".to_string(),
+            timeout_ms: None,
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
+        };
+        assert!(workflow.keyed_task_request(request.clone()).is_none());
+
+        let ctx = workflow.get_node_mut(&node_id).unwrap();
+        ctx.transition(NodeState::Scheduled).unwrap();
+        let first = workflow.keyed_task_request(request.clone()).unwrap();
+        let expected = TaskRequest::idempotency_key_for(workflow.execution_id, node_id, 1);
+        assert_eq!(first.idempotency_key, Some(expected));
+        let resent = workflow.keyed_task_request(request.clone()).unwrap();
+        assert_eq!(resent.idempotency_key, first.idempotency_key);
+
+        let ctx = workflow.get_node_mut(&node_id).unwrap();
+        ctx.transition(NodeState::Running).unwrap();
+        ctx.fail("timeout".to_string()).unwrap();
+        ctx.transition(NodeState::Retrying).unwrap();
+        ctx.transition(NodeState::Scheduled).unwrap();
+        let retry = workflow.keyed_task_request(request).unwrap();
+        assert_ne!(retry.idempotency_key, first.idempotency_key);
+    }
+
+    #[test]
+    fn test_duplicate_task_transitions_are_ignored() {
+        let mut ctx = NodeContext::new(Uuid::new_v4(), Uuid::new_v4());
+        let task = Uuid::new_v4();
+
+        ctx.transition(NodeState::Scheduled).unwrap();
+        ctx.transition(NodeState::Running).unwrap();
+        let first = ctx
+            .apply_transition(NodeState::Failed, Some(task), Some("boom".to_string()))
+            .unwrap();
+        assert!(first.is_applied());
+        assert_eq!(ctx.current_attempt().unwrap().task_id, Some(task));
+
+        ctx.transition(NodeState::Retrying).unwrap();
+        let duplicate = ctx
+            .apply_transition(NodeState::Failed, Some(task), Some("boom".to_string()))
+            .unwrap();
+        assert!(!duplicate.is_applied());
+        assert_eq!(duplicate.transition().timestamp, first.transition().timestamp);
+        assert_eq!(ctx.state, NodeState::Retrying);
+        assert_eq!(ctx.retry_count, 1);
+
+        // A different task is a genuine (here invalid) transition
+        assert!(ctx
+            .apply_transition(NodeState::Failed, Some(Uuid::new_v4()), None)
+            .is_err());
+    }
+
     #[test]
     fn test_invalid_transition() {
         let mut ctx = NodeContext::new(Uuid::new_v4(), Uuid::new_v4());
         let result = ctx.transition(NodeState::Done);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_rejected_failure_keeps_last_error() {
+        let mut ctx = NodeContext::new(Uuid::new_v4(), Uuid::new_v4());
+        ctx.transition(NodeState::Scheduled).unwrap();
+        ctx.transition(NodeState::Running).unwrap();
+        ctx.transition(NodeState::Done).unwrap();
+
+        let result = ctx.apply_transition(
+            NodeState::Failed,
+            Some(Uuid::new_v4()),
+            Some("late failure".to_string()),
+        );
+        assert!(matches!(
+            result,
+            Err(StateError::InvalidTransition {
+                from: NodeState::Done,
+                to: NodeState::Failed,
+            })
+        ));
+        assert_eq!(ctx.state, NodeState::Done);
+        assert_eq!(ctx.last_error, None);
+    }
 }
@@ -0,0 +1,288 @@
+//! Event-driven workflow triggers
+//!
+//! Workflows declare [`WorkflowTrigger`]s alongside their nodes, e.g. "when
+//! workflow X completes" or "when data tagged `raw` is created". The
+//! [`TriggerEngine`] indexes the triggers of every stored workflow by event
+//! type and evaluates each committed event against them, producing a
+//! [`TriggerFiring`] per match with the variables to start the workflow
+//! with.
+//!
+//! A firing is identified by the trigger and the sequence number of the
+//! event that fired it, so recording it with
+//! [`TriggerFiring::idempotency_key`] starts the workflow at most once even
+//! if the event is evaluated again after a restart. Transient events have
+//! no sequence number and never fire triggers, and a workflow's triggers
+//! ignore its own events so a trigger cannot restart its workflow forever.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use swarmx_events::{AuditAction, Event, EventEnvelope};
+use swarmx_protocol::{TriggerBinding, WorkflowDefinition, WorkflowTrigger};
+
+/// Actor recorded in the audit trail for trigger firings
+pub const TRIGGER_ACTOR: &str = "trigger";
+
+/// A trigger of a stored workflow
+#[derive(Debug, Clone)]
+struct Registered {
+    workflow_id: Uuid,
+    /// The workflow's own variables, which bindings add to
+    variables: Value,
+    trigger: WorkflowTrigger,
+}
+
+/// Evaluates committed events against workflow triggers
+#[derive(Debug, Clone, Default)]
+pub struct TriggerEngine {
+    /// Enabled triggers, by the event type they fire on
+    by_event_type: HashMap<String, Vec<Registered>>,
+}
+
+impl TriggerEngine {
+    /// Create an engine without triggers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a workflow's triggers, replacing any it had before
+    pub fn register(&mut self, workflow: &WorkflowDefinition) {
+        self.unregister(workflow.id);
+        for trigger in workflow.triggers.iter().filter(|t| t.enabled) {
+            self.by_event_type
+                .entry(trigger.on.event_type.clone())
+                .or_default()
+                .push(Registered {
+                    workflow_id: workflow.id,
+                    variables: workflow.variables.clone(),
+                    trigger: trigger.clone(),
+                });
+        }
+    }
+
+    /// Remove a workflow's triggers
+    pub fn unregister(&mut self, workflow_id: Uuid) {
+        self.by_event_type.retain(|_, triggers| {
+            triggers.retain(|t| t.workflow_id != workflow_id);
+            !triggers.is_empty()
+        });
+    }
+
+    /// Get the number of enabled triggers
+    pub fn len(&self) -> usize {
+        self.by_event_type.values().map(Vec::len).sum()
+    }
+
+    /// Check if no triggers are enabled
+    pub fn is_empty(&self) -> bool {
+        self.by_event_type.is_empty()
+    }
+
+    /// Find the triggers fired by a committed event
+    pub fn evaluate(&self, envelope: &EventEnvelope) -> Vec<TriggerFiring> {
+        if envelope.is_transient() {
+            return Vec::new();
+        }
+        let Some(candidates) = self.by_event_type.get(envelope.event.event_type()) else {
+            return Vec::new();
+        };
+        let source_workflow = envelope.event.workflow_id();
+        let Ok(event) = serde_json::to_value(&envelope.event) else {
+            return Vec::new();
+        };
+
+        candidates
+            .iter()
+            .filter(|r| source_workflow != Some(r.workflow_id))
+            .filter(|r| {
+                let condition = &r.trigger.on;
+                condition
+                    .workflow_id
+                    .is_none_or(|id| source_workflow == Some(id))
+                    && condition
+                        .fields
+                        .iter()
+                        .all(|(path, expected)| field_matches(&event, path, expected))
+            })
+            .filter_map(|r| {
+                let variables = bind(r, &event);
+                if variables.is_none() {
+                    tracing::warn!(
+                        workflow_id = %r.workflow_id,
+                        trigger_id = %r.trigger.id,
+                        sequence = envelope.sequence,
+                        "Trigger binding refers to a field the event does not have"
+                    );
+                }
+                Some(TriggerFiring {
+                    workflow_id: r.workflow_id,
+                    trigger_id: r.trigger.id.clone(),
+                    source_sequence: envelope.sequence,
+                    variables: variables?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A trigger fired by an event: start `workflow_id` with `variables`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriggerFiring {
+    pub workflow_id: Uuid,
+    pub trigger_id: String,
+    /// Sequence number of the event that fired the trigger
+    pub source_sequence: u64,
+    pub variables: Value,
+}
+
+impl TriggerFiring {
+    /// Idempotency key under which to record the firing
+    pub fn idempotency_key(&self) -> String {
+        format!(
+            "trigger:{}:{}:{}",
+            self.workflow_id, self.trigger_id, self.source_sequence
+        )
+    }
+
+    /// Audit event recording the firing
+    pub fn audit_event(&self) -> Event {
+        Event::audit(
+            TRIGGER_ACTOR,
+            AuditAction::WorkflowTriggered {
+                workflow_id: self.workflow_id,
+                trigger_id: self.trigger_id.clone(),
+                source_sequence: self.source_sequence,
+                variables: self.variables.clone(),
+            },
+        )
+    }
+}
+
+/// Find the value at a dotted path within nested JSON objects
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |value, key| value.as_object()?.get(key))
+}
+
+/// Check an event field against a condition; arrays match if they contain
+/// the expected value
+fn field_matches(event: &Value, path: &str, expected: &Value) -> bool {
+    match lookup(event, path) {
+        Some(Value::Array(items)) if !expected.is_array() => items.contains(expected),
+        Some(value) => value == expected,
+        None => false,
+    }
+}
+
+/// Variables for an execution started by a trigger, or `None` if a binding
+/// refers to a missing event field
+fn bind(registered: &Registered, event: &Value) -> Option<Value> {
+    let mut variables = match &registered.variables {
+        Value::Object(variables) => variables.clone(),
+        _ => serde_json::Map::new(),
+    };
+    for (name, binding) in &registered.trigger.bindings {
+        let value = match binding {
+            TriggerBinding::Value(value) => value.clone(),
+            TriggerBinding::Event(path) => lookup(event, path)?.clone(),
+        };
+        variables.insert(name.clone(), value);
+    }
+    Some(Value::Object(variables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::BTreeMap;
+    use swarmx_protocol::TriggerCondition;
+
+    fn trigger(id: &str, on: TriggerCondition) -> WorkflowTrigger {
+        WorkflowTrigger {
+            id: id.to_string(),
+            on,
+            bindings: BTreeMap::new(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_trigger_evaluation() {
+        let upstream = Uuid::new_v4();
+        let mut report = WorkflowDefinition::new("report");
+        report.variables = serde_json::json!({ "format": "pdf" });
+        report.triggers.push(trigger(
+            "after-etl",
+            TriggerCondition {
+                event_type: "workflow_completed".to_string(),
+                workflow_id: Some(upstream),
+                fields: BTreeMap::new(),
+            },
+        ));
+        let mut ingest = WorkflowDefinition::new("ingest");
+        let mut on_raw = trigger(
+            "raw-data",
+            TriggerCondition {
+                event_type: "data_created".to_string(),
+                workflow_id: None,
+                fields: BTreeMap::from([("tags".to_string(), "raw".into())]),
+            },
+        );
+        on_raw.bindings.insert(
+            "input".to_string(),
+            TriggerBinding::Event("data_uuid".to_string()),
+        );
+        ingest.triggers.push(on_raw);
+
+        let mut engine = TriggerEngine::new();
+        engine.register(&report);
+        engine.register(&ingest);
+        engine.register(&ingest);
+        assert_eq!(engine.len(), 2);
+
+        let completed = |workflow_id| Event::WorkflowCompleted {
+            workflow_id,
+            duration_ms: 10,
+            timestamp: Utc::now(),
+        };
+        let firings = engine.evaluate(&EventEnvelope::new(7, completed(upstream)));
+        assert_eq!(
+            firings,
+            vec![TriggerFiring {
+                workflow_id: report.id,
+                trigger_id: "after-etl".to_string(),
+                source_sequence: 7,
+                variables: serde_json::json!({ "format": "pdf" }),
+            }]
+        );
+        assert!(engine
+            .evaluate(&EventEnvelope::new(8, completed(Uuid::new_v4())))
+            .is_empty());
+        // Transient events never fire
+        assert!(engine
+            .evaluate(&EventEnvelope::new(0, completed(upstream)))
+            .is_empty());
+
+        let created = |tags: &[&str]| Event::DataCreated {
+            data_uuid: Uuid::nil(),
+            workflow_id: upstream,
+            location: "s3://bucket/raw.parquet".to_string(),
+            size_bytes: 1024,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            timestamp: Utc::now(),
+        };
+        let firings = engine.evaluate(&EventEnvelope::new(9, created(&["raw", "eu"])));
+        assert_eq!(firings.len(), 1);
+        assert_eq!(firings[0].variables["input"], Uuid::nil().to_string());
+        assert!(engine
+            .evaluate(&EventEnvelope::new(10, created(&["clean"])))
+            .is_empty());
+
+        engine.unregister(ingest.id);
+        assert_eq!(engine.len(), 1);
+    }
+}
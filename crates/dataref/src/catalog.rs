@@ -0,0 +1,325 @@
+//! Central catalog of DataRefs
+//!
+//! The [registry](crate::registry) only knows data something still holds,
+//! and each store only knows its own objects. A [`DataCatalog`] records
+//! every DataRef created in the system so data can be looked up by UUID,
+//! listed by workflow, and inventoried per server. When opened with
+//! [`DataCatalog::persistent`], entries are written through to the WAL
+//! database and survive restarts.
+//!
+//! The catalog follows replication and deletion events through
+//! [`DataCatalog::apply`]. Data whose workflow is gone, or that never had
+//! one, is reported by [`DataCatalog::orphans`] so it can be cleaned up.
+//! Copies servers no longer hold are dropped by the
+//! [garbage collection coordinator](crate::gc).
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::{CatalogRecord, Event, WalError, WriteAheadLog};
+
+use crate::pointer::{DataRef, DataRefError};
+use crate::query::DataQuery;
+
+/// Data held by one server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocationInventory {
+    /// Pieces of data the server holds, as primary or replica
+    pub data_count: usize,
+    /// Total size of that data at rest, in bytes
+    pub total_bytes: u64,
+}
+
+#[derive(Default)]
+struct CatalogState {
+    /// Cataloged data, by UUID
+    entries: HashMap<Uuid, DataRef>,
+    /// Where entries are persisted, if anywhere
+    wal: Option<WriteAheadLog>,
+}
+
+impl CatalogState {
+    fn insert(&mut self, data_ref: DataRef) -> Result<(), DataRefError> {
+        if let Some(wal) = self.wal.as_mut() {
+            wal.record_catalog_entry(&CatalogRecord {
+                data_uuid: data_ref.uuid,
+                workflow_id: data_ref.workflow_id,
+                document: serde_json::to_string(&data_ref).map_err(WalError::from)?,
+                updated_at: Utc::now(),
+            })?;
+        }
+        self.entries.insert(data_ref.uuid, data_ref);
+        Ok(())
+    }
+
+    fn remove(&mut self, uuid: &Uuid) -> Result<Option<DataRef>, DataRefError> {
+        if let Some(wal) = self.wal.as_mut() {
+            wal.remove_catalog_entry(uuid)?;
+        }
+        Ok(self.entries.remove(uuid))
+    }
+}
+
+/// Every DataRef created in the system
+///
+/// Cheaply cloneable; clones share the same catalog.
+#[derive(Clone, Default)]
+pub struct DataCatalog {
+    state: Arc<Mutex<CatalogState>>,
+}
+
+impl std::fmt::Debug for DataCatalog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataCatalog")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl DataCatalog {
+    /// Create an empty in-memory catalog
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the entries stored in `wal` and persist new ones there
+    pub fn persistent(wal: WriteAheadLog) -> Result<Self, DataRefError> {
+        let entries = wal
+            .catalog_entries()?
+            .into_iter()
+            .map(|record| {
+                let data_ref: DataRef =
+                    serde_json::from_str(&record.document).map_err(WalError::from)?;
+                Ok((data_ref.uuid, data_ref))
+            })
+            .collect::<Result<_, DataRefError>>()?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(CatalogState {
+                entries,
+                wal: Some(wal),
+            })),
+        })
+    }
+
+    /// Record created data, or replace the entry of known data
+    pub fn register(&self, data_ref: &DataRef) -> Result<(), DataRefError> {
+        self.lock().insert(data_ref.clone())
+    }
+
+    /// Drop the entry of deleted data
+    pub fn remove(&self, uuid: &Uuid) -> Result<Option<DataRef>, DataRefError> {
+        self.lock().remove(uuid)
+    }
+
+    /// Drop a server from the locations of data after its copy was lost
+    ///
+    /// A lost primary copy is replaced by the first replica. Returns the
+    /// updated ref, or `None` if the data has no copy left or was not
+    /// cataloged, in which case any entry is dropped.
+    pub fn remove_location(
+        &self,
+        uuid: &Uuid,
+        server: &str,
+    ) -> Result<Option<DataRef>, DataRefError> {
+        let mut state = self.lock();
+        let Some(mut data_ref) = state.entries.get(uuid).cloned() else {
+            return Ok(None);
+        };
+        if data_ref.location == server {
+            if data_ref.replicas.is_empty() {
+                state.remove(uuid)?;
+                return Ok(None);
+            }
+            data_ref.location = data_ref.replicas.remove(0);
+        } else {
+            data_ref.remove_replica(server);
+        }
+        state.insert(data_ref.clone())?;
+        Ok(Some(data_ref))
+    }
+
+    /// Look up data by UUID
+    pub fn get(&self, uuid: &Uuid) -> Option<DataRef> {
+        self.lock().entries.get(uuid).cloned()
+    }
+
+    /// List the data of a workflow, newest first
+    pub fn by_workflow(&self, workflow_id: Uuid) -> Vec<DataRef> {
+        self.find(&DataQuery::new().workflow(workflow_id))
+    }
+
+    /// Find data matching a query, newest first
+    pub fn find(&self, query: &DataQuery) -> Vec<DataRef> {
+        let state = self.lock();
+        query
+            .select(state.entries.values())
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    /// List the data a server holds, as primary or replica
+    pub fn at_location(&self, server: &str) -> Vec<DataRef> {
+        self.lock()
+            .entries
+            .values()
+            .filter(|data_ref| data_ref.is_local_to(server))
+            .cloned()
+            .collect()
+    }
+
+    /// Count the data and bytes held by each server
+    pub fn inventory(&self) -> BTreeMap<String, LocationInventory> {
+        let mut inventory: BTreeMap<String, LocationInventory> = BTreeMap::new();
+        for data_ref in self.lock().entries.values() {
+            for location in data_ref.locations() {
+                let held = inventory.entry(location.to_string()).or_default();
+                held.data_count += 1;
+                held.total_bytes += data_ref.stored_size();
+            }
+        }
+        inventory
+    }
+
+    /// List data without an owning workflow: data created outside any
+    /// workflow, or whose workflow `workflow_exists` no longer knows
+    pub fn orphans(&self, workflow_exists: impl Fn(&Uuid) -> bool) -> Vec<DataRef> {
+        self.lock()
+            .entries
+            .values()
+            .filter(|data_ref| {
+                data_ref.workflow_id.is_nil() || !workflow_exists(&data_ref.workflow_id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Keep locations current and drop deleted data
+    pub fn apply(&self, event: &Event) -> Result<(), DataRefError> {
+        let mut state = self.lock();
+        match event {
+            Event::DataReplicated {
+                data_uuid,
+                to_server,
+                ..
+            } => {
+                if let Some(mut data_ref) = state.entries.get(data_uuid).cloned() {
+                    data_ref.add_replica(to_server);
+                    state.insert(data_ref)?;
+                }
+            }
+            Event::DataReplicaRemoved {
+                data_uuid, server, ..
+            } => {
+                if let Some(mut data_ref) = state.entries.get(data_uuid).cloned() {
+                    data_ref.remove_replica(server);
+                    state.insert(data_ref)?;
+                }
+            }
+            Event::DataDeleted { data_uuid, .. } => {
+                state.remove(data_uuid)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Number of cataloged pieces of data
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether nothing is cataloged
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CatalogState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    fn data(location: &str, workflow_id: Uuid, size: u64) -> DataRef {
+        DataRef::new(location.to_string(), size, DataType::Bytes, workflow_id)
+    }
+
+    #[test]
+    fn test_catalog_lookups() {
+        let catalog = DataCatalog::new();
+        let workflow_id = Uuid::new_v4();
+        let first = data("gpu-1", workflow_id, 100);
+        let second = data("gpu-2", workflow_id, 50);
+        let stray = data("gpu-1", Uuid::nil(), 10);
+        for data_ref in [&first, &second, &stray] {
+            catalog.register(data_ref).unwrap();
+        }
+
+        assert_eq!(catalog.get(&first.uuid).unwrap().size_bytes, 100);
+        assert_eq!(catalog.by_workflow(workflow_id).len(), 2);
+        assert_eq!(catalog.at_location("gpu-1").len(), 2);
+
+        catalog
+            .apply(&Event::DataReplicated {
+                data_uuid: second.uuid,
+                from_server: "gpu-2".to_string(),
+                to_server: "gpu-1".to_string(),
+                duration_ms: 5,
+                timestamp: Utc::now(),
+            })
+            .unwrap();
+        let inventory = catalog.inventory();
+        assert_eq!(
+            inventory["gpu-1"],
+            LocationInventory {
+                data_count: 3,
+                total_bytes: 160
+            }
+        );
+        assert_eq!(inventory["gpu-2"].data_count, 1);
+
+        let orphans = catalog.orphans(|id| *id == workflow_id);
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].uuid, stray.uuid);
+        assert_eq!(catalog.orphans(|_| false).len(), 3);
+
+        catalog.apply(&first.deleted_event()).unwrap();
+        assert!(catalog.get(&first.uuid).is_none());
+
+        // Losing the primary copy promotes a replica; losing the last copy
+        // drops the entry
+        let promoted = catalog.remove_location(&second.uuid, "gpu-2").unwrap();
+        assert_eq!(promoted.unwrap().location, "gpu-1");
+        assert!(catalog
+            .remove_location(&second.uuid, "gpu-1")
+            .unwrap()
+            .is_none());
+        assert!(catalog.get(&second.uuid).is_none());
+    }
+
+    #[test]
+    fn test_persistent_catalog_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let kept = data("gpu-1", Uuid::new_v4(), 100).with_tag("stage", "final");
+        let deleted = data("gpu-1", Uuid::new_v4(), 100);
+
+        let catalog = DataCatalog::persistent(WriteAheadLog::open(&path).unwrap()).unwrap();
+        catalog.register(&kept).unwrap();
+        catalog.register(&deleted).unwrap();
+        catalog.remove(&deleted.uuid).unwrap();
+        drop(catalog);
+
+        let catalog = DataCatalog::persistent(WriteAheadLog::open(&path).unwrap()).unwrap();
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog.get(&kept.uuid).unwrap().tag("stage"), Some("final"));
+    }
+}
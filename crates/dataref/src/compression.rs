@@ -0,0 +1,97 @@
+//! Transparent compression of stored data
+//!
+//! A [`LocalDataStore`](crate::store::LocalDataStore) opened with
+//! [`with_compression`](crate::store::LocalDataStore::with_compression)
+//! stores compressible data with zstd: JSON, and files with a textual MIME
+//! type. Tensors, KV caches, and raw bytes are usually dense already and
+//! are stored as they are.
+//!
+//! A compressed ref records its
+//! [codec](crate::pointer::DataRef::compression) and its
+//! [size at rest](crate::pointer::DataRef::stored_bytes) next to the
+//! logical `size_bytes`. Quotas and transfer-cost estimates count the bytes
+//! at rest, since compressed data is kept and moved between servers as
+//! stored; readers always see the logical payload.
+
+use std::io::{self, Read};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pointer::DataType;
+
+/// Default zstd compression level, favoring speed
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Codec stored data is compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// Zstandard frames
+    Zstd,
+}
+
+impl Compression {
+    /// Wrap a reader of compressed bytes so it yields the logical payload
+    pub(crate) fn decoder(self, reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+        }
+    }
+}
+
+impl DataType {
+    /// Check whether data of this type is worth compressing: JSON, and
+    /// files with a [textual](is_textual_mime) MIME type
+    pub fn is_compressible(&self) -> bool {
+        match self {
+            DataType::Json => true,
+            DataType::File { mime_type } => is_textual_mime(mime_type),
+            DataType::Tensor { .. } | DataType::Bytes | DataType::KvCache { .. } => false,
+        }
+    }
+}
+
+/// Check whether a MIME type describes text, e.g. `text/csv` or
+/// `application/ld+json`
+pub fn is_textual_mime(mime_type: &str) -> bool {
+    let essence = mime_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/x-ndjson"
+                | "application/xml"
+                | "application/javascript"
+                | "application/yaml"
+                | "application/x-yaml"
+                | "application/toml"
+                | "application/sql"
+                | "image/svg+xml"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressible_types() {
+        assert!(DataType::Json.is_compressible());
+        assert!(!DataType::Bytes.is_compressible());
+        let file = |mime_type: &str| DataType::File {
+            mime_type: mime_type.to_string(),
+        };
+        assert!(file("text/csv").is_compressible());
+        assert!(file("Text/Plain; charset=utf-8").is_compressible());
+        assert!(file("application/ld+json").is_compressible());
+        assert!(!file("application/pdf").is_compressible());
+        assert!(!file("image/png").is_compressible());
+    }
+}
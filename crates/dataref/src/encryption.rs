@@ -0,0 +1,414 @@
+//! Envelope encryption of stored data
+//!
+//! Every workflow gets its own random data key, which encrypts the bytes of
+//! that workflow's stored objects. Data keys are never stored in the
+//! clear: a [`KeyProvider`] wraps them with a master key, e.g. one held by
+//! a KMS, and only the wrapped form is persisted, so a leaked store
+//! directory reveals nothing without the master key.
+//!
+//! Objects are encrypted with AES-256-GCM in frames of at most
+//! [`FRAME_SIZE`] bytes, so they can be written and read as streams. Each
+//! object uses its own key, derived from the data key and a random salt
+//! stored in the object header; frames are numbered and the last one is
+//! marked, so reordered or truncated objects fail to decrypt.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::token::{decode_hex, encode_hex};
+
+/// Length of data and master keys in bytes
+pub const DATA_KEY_LEN: usize = 32;
+
+/// Maximum plaintext bytes per encrypted frame
+pub const FRAME_SIZE: usize = 64 * 1024;
+
+/// Marks the start of an encrypted object
+const OBJECT_MAGIC: &[u8; 4] = b"SXE1";
+
+/// Length of the per-object key derivation salt
+const SALT_LEN: usize = 16;
+
+/// HKDF context for per-object keys
+const OBJECT_KEY_INFO: &[u8] = b"swarmx-data-object-v1";
+
+/// Length of the AES-GCM authentication tag
+const TAG_LEN: usize = 16;
+
+/// Bit of a frame's length word marking the last frame; also
+/// authenticated with the frame
+const LAST_FRAME_FLAG: u32 = 1 << 31;
+
+/// Encryption errors
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Unknown master key: {0}")]
+    UnknownMasterKey(String),
+
+    #[error("Failed to unwrap data key")]
+    Unwrap,
+
+    #[error("Unknown data key: {0}")]
+    UnknownDataKey(Uuid),
+
+    #[error("Random number generation failed")]
+    Random,
+}
+
+/// A data key wrapped by a master key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// Master key the data key is wrapped with
+    pub master_key_id: String,
+    /// Hex-encoded nonce and ciphertext of the data key
+    pub ciphertext: String,
+}
+
+/// Wraps and unwraps data keys with master keys
+///
+/// Implement this for a KMS to keep master keys out of the process.
+pub trait KeyProvider: Send + Sync {
+    /// Wrap a data key with the current master key
+    fn wrap(&self, data_key: &[u8]) -> Result<WrappedKey, EncryptionError>;
+
+    /// Recover a data key wrapped by [`wrap`](Self::wrap)
+    fn unwrap(&self, wrapped: &WrappedKey) -> Result<Vec<u8>, EncryptionError>;
+}
+
+/// Key provider holding master keys in memory
+///
+/// Keeps retired master keys for unwrapping data keys wrapped before a
+/// rotation.
+pub struct StaticKeyProvider {
+    /// ID of the master key new data keys are wrapped with
+    key_id: String,
+    keys: HashMap<String, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+impl std::fmt::Debug for StaticKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticKeyProvider")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StaticKeyProvider {
+    /// Wrap data keys with a 32-byte master key
+    pub fn new(key_id: &str, master_key: &[u8]) -> Result<Self, EncryptionError> {
+        let provider = Self {
+            key_id: key_id.to_string(),
+            keys: HashMap::new(),
+            rng: SystemRandom::new(),
+        };
+        provider.with_unwrapping_key(key_id, master_key)
+    }
+
+    /// Also unwrap data keys wrapped with `master_key` under `key_id`
+    pub fn with_unwrapping_key(
+        mut self,
+        key_id: &str,
+        master_key: &[u8],
+    ) -> Result<Self, EncryptionError> {
+        if key_id.is_empty() {
+            return Err(EncryptionError::InvalidKey("empty key ID".to_string()));
+        }
+        self.keys.insert(key_id.to_string(), aead_key(master_key)?);
+        Ok(self)
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn wrap(&self, data_key: &[u8]) -> Result<WrappedKey, EncryptionError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| EncryptionError::Random)?;
+        let mut sealed = data_key.to_vec();
+        self.keys[&self.key_id]
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.key_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| EncryptionError::InvalidKey("failed to wrap data key".to_string()))?;
+        let mut ciphertext = nonce.to_vec();
+        ciphertext.extend_from_slice(&sealed);
+        Ok(WrappedKey {
+            master_key_id: self.key_id.clone(),
+            ciphertext: encode_hex(&ciphertext),
+        })
+    }
+
+    fn unwrap(&self, wrapped: &WrappedKey) -> Result<Vec<u8>, EncryptionError> {
+        let key = self
+            .keys
+            .get(&wrapped.master_key_id)
+            .ok_or_else(|| EncryptionError::UnknownMasterKey(wrapped.master_key_id.clone()))?;
+        let ciphertext = decode_hex(&wrapped.ciphertext).ok_or(EncryptionError::Unwrap)?;
+        if ciphertext.len() < NONCE_LEN {
+            return Err(EncryptionError::Unwrap);
+        }
+        let (nonce, sealed) = ciphertext.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| EncryptionError::Unwrap)?;
+        let mut sealed = sealed.to_vec();
+        let data_key = key
+            .open_in_place(
+                nonce,
+                Aad::from(wrapped.master_key_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| EncryptionError::Unwrap)?;
+        Ok(data_key.to_vec())
+    }
+}
+
+/// Generate a random data key
+pub fn generate_data_key() -> Result<Vec<u8>, EncryptionError> {
+    let mut key = vec![0u8; DATA_KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| EncryptionError::Random)?;
+    Ok(key)
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, EncryptionError> {
+    if key.len() != DATA_KEY_LEN {
+        return Err(EncryptionError::InvalidKey(format!(
+            "expected {DATA_KEY_LEN} bytes, got {}",
+            key.len()
+        )));
+    }
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| EncryptionError::InvalidKey("rejected by AES-256-GCM".to_string()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Key for one object, derived from its workflow's data key
+fn object_key(data_key: &[u8], salt: &[u8]) -> LessSafeKey {
+    let prk = Salt::new(HKDF_SHA256, salt).extract(data_key);
+    let okm = prk
+        .expand(&[OBJECT_KEY_INFO], &AES_256_GCM)
+        .expect("AES-256 key length is a valid HKDF output length");
+    LessSafeKey::new(UnboundKey::from(okm))
+}
+
+/// Nonce of a frame: its index, which never repeats under one object key
+fn frame_nonce(index: u64) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 8..].copy_from_slice(&index.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn crypto_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Encrypts an object frame by frame
+pub(crate) struct ObjectSealer {
+    key: LessSafeKey,
+    next_frame: u64,
+}
+
+impl ObjectSealer {
+    /// Start an object, writing its header to `out`
+    pub(crate) fn start(data_key: &[u8], out: &mut impl Write) -> io::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| io::Error::other("random number generation failed"))?;
+        out.write_all(OBJECT_MAGIC)?;
+        out.write_all(&salt)?;
+        Ok(Self {
+            key: object_key(data_key, &salt),
+            next_frame: 0,
+        })
+    }
+
+    /// Encrypt and write the next frame; `last` must be set on exactly the
+    /// final frame, which may be empty
+    pub(crate) fn seal(
+        &mut self,
+        plaintext: &[u8],
+        last: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut frame = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                frame_nonce(self.next_frame),
+                Aad::from([u8::from(last)]),
+                &mut frame,
+            )
+            .map_err(|_| crypto_error("failed to encrypt frame"))?;
+        self.next_frame += 1;
+        let mut header = frame.len() as u32;
+        if last {
+            header |= LAST_FRAME_FLAG;
+        }
+        out.write_all(&header.to_be_bytes())?;
+        out.write_all(&frame)
+    }
+}
+
+/// Decrypts an object written by [`ObjectSealer`] while it is read
+pub struct DecryptingReader<R> {
+    inner: R,
+    data_key: Vec<u8>,
+    /// Derived once the header is read
+    key: Option<LessSafeKey>,
+    next_frame: u64,
+    plaintext: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Decrypt `inner` with the data key of its workflow
+    pub fn new(inner: R, data_key: Vec<u8>) -> Self {
+        Self {
+            inner,
+            data_key,
+            key: None,
+            next_frame: 0,
+            plaintext: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Read and decrypt the next frame into the buffer
+    fn next_frame(&mut self) -> io::Result<()> {
+        if self.key.is_none() {
+            let mut header = [0u8; OBJECT_MAGIC.len() + SALT_LEN];
+            self.inner.read_exact(&mut header)?;
+            if &header[..OBJECT_MAGIC.len()] != OBJECT_MAGIC {
+                return Err(crypto_error("not an encrypted object"));
+            }
+            self.key = Some(object_key(&self.data_key, &header[OBJECT_MAGIC.len()..]));
+        }
+        let mut len = [0u8; 4];
+        self.inner
+            .read_exact(&mut len)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => crypto_error("encrypted object is truncated"),
+                _ => e,
+            })?;
+        let header = u32::from_be_bytes(len);
+        let last = header & LAST_FRAME_FLAG != 0;
+        let len = (header & !LAST_FRAME_FLAG) as usize;
+        if !(TAG_LEN..=FRAME_SIZE + TAG_LEN).contains(&len) {
+            return Err(crypto_error("invalid encrypted frame length"));
+        }
+        let mut frame = vec![0u8; len];
+        self.inner.read_exact(&mut frame)?;
+        let key = self.key.as_ref().expect("key derived from header");
+        let plaintext_len = key
+            .open_in_place(
+                frame_nonce(self.next_frame),
+                Aad::from([u8::from(last)]),
+                &mut frame,
+            )
+            .map_err(|_| crypto_error("encrypted frame failed authentication"))?
+            .len();
+        frame.truncate(plaintext_len);
+        self.next_frame += 1;
+        self.finished = last;
+        self.plaintext = frame;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_frame()?;
+        }
+        let n = buf.len().min(self.plaintext.len() - self.position);
+        buf[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seal(data_key: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut sealer = ObjectSealer::start(data_key, &mut out).unwrap();
+        let mut frames = payload.chunks(FRAME_SIZE).peekable();
+        if frames.peek().is_none() {
+            sealer.seal(&[], true, &mut out).unwrap();
+        }
+        while let Some(frame) = frames.next() {
+            sealer
+                .seal(frame, frames.peek().is_none(), &mut out)
+                .unwrap();
+        }
+        out
+    }
+
+    fn open(data_key: &[u8], object: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        DecryptingReader::new(object, data_key.to_vec()).read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn test_key_wrapping() {
+        let provider = StaticKeyProvider::new("m1", &[1; DATA_KEY_LEN]).unwrap();
+        let data_key = generate_data_key().unwrap();
+        let wrapped = provider.wrap(&data_key).unwrap();
+        assert_eq!(wrapped.master_key_id, "m1");
+        assert!(!wrapped.ciphertext.contains(&encode_hex(&data_key)));
+        assert_eq!(provider.unwrap(&wrapped).unwrap(), data_key);
+
+        // A rotated provider still unwraps keys wrapped by the old master key
+        let rotated = StaticKeyProvider::new("m2", &[2; DATA_KEY_LEN])
+            .unwrap()
+            .with_unwrapping_key("m1", &[1; DATA_KEY_LEN])
+            .unwrap();
+        assert_eq!(rotated.unwrap(&wrapped).unwrap(), data_key);
+        let other = StaticKeyProvider::new("m1", &[3; DATA_KEY_LEN]).unwrap();
+        assert!(matches!(
+            other.unwrap(&wrapped),
+            Err(EncryptionError::Unwrap)
+        ));
+        assert!(StaticKeyProvider::new("m1", &[1; 16]).is_err());
+    }
+
+    #[test]
+    fn test_object_round_trip_and_tampering() {
+        let data_key = generate_data_key().unwrap();
+        for payload in [Vec::new(), b"hello".to_vec(), vec![7u8; 2 * FRAME_SIZE + 3]] {
+            let object = seal(&data_key, &payload);
+            assert_eq!(open(&data_key, &object).unwrap(), payload);
+        }
+
+        let payload = vec![7u8; 2 * FRAME_SIZE + 3];
+        let object = seal(&data_key, &payload);
+        let mut flipped = object.clone();
+        flipped[OBJECT_MAGIC.len() + SALT_LEN + 10] ^= 1;
+        assert!(open(&data_key, &flipped).is_err());
+        // Dropping the last frame is detected
+        let truncated = &object[..OBJECT_MAGIC.len() + SALT_LEN + 2 * (4 + FRAME_SIZE + TAG_LEN)];
+        assert!(open(&data_key, truncated).is_err());
+        assert!(open(&generate_data_key().unwrap(), &object).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Eviction policies
+//!
+//! When a tier fills past its high watermark, the
+//! [`TierManager`](crate::tier::TierManager) offloads data until usage is
+//! back under the low watermark. Which data goes first is up to the tier's
+//! [`EvictionPolicy`], which scores every candidate; the lowest scores are
+//! evicted first, ties going to the least recently used data. Built in are:
+//!
+//! - [`Lru`]: least recently read first (the default)
+//! - [`Lfu`]: least often read first
+//! - [`SizeWeighted`]: large data that has been idle longest first, freeing
+//!   the most space with the fewest migrations
+//! - [`TtlAware`]: data expiring soonest first, then least recently read
+//!
+//! Policies are chosen per tier, and per server where servers differ, e.g.
+//! LFU for a GPU's VRAM holding a few hot model weights and LRU for DRAM.
+//! Whatever the policy, [pinned](crate::pin) data is never evicted. Every
+//! eviction is recorded as a `DataEvicted` event with the policy's score,
+//! so policies can be compared against real workloads.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::pointer::DataRef;
+
+/// Data that may be evicted from a tier
+#[derive(Debug, Clone, Copy)]
+pub struct EvictionCandidate<'a> {
+    pub data_ref: &'a DataRef,
+    /// When the data was last read, or tracked if never read
+    pub last_access: DateTime<Utc>,
+    /// Reads of the data since it was tracked
+    pub access_count: u64,
+}
+
+impl EvictionCandidate<'_> {
+    /// Seconds since the data was last read, at least 1
+    pub fn idle_secs(&self, now: DateTime<Utc>) -> f64 {
+        ((now - self.last_access).num_milliseconds() as f64 / 1000.0).max(1.0)
+    }
+}
+
+/// Decides which data leaves a full tier first
+pub trait EvictionPolicy: Send + Sync + Debug {
+    /// Name of the policy, recorded in eviction events
+    fn name(&self) -> &str;
+
+    /// Score a candidate at `now`; lower scores are evicted first
+    fn score(&self, candidate: &EvictionCandidate<'_>, now: DateTime<Utc>) -> f64;
+}
+
+/// Evict the least recently read data first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lru;
+
+impl EvictionPolicy for Lru {
+    fn name(&self) -> &str {
+        "lru"
+    }
+
+    fn score(&self, candidate: &EvictionCandidate<'_>, _now: DateTime<Utc>) -> f64 {
+        candidate.last_access.timestamp_millis() as f64
+    }
+}
+
+/// Evict the least often read data first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lfu;
+
+impl EvictionPolicy for Lfu {
+    fn name(&self) -> &str {
+        "lfu"
+    }
+
+    fn score(&self, candidate: &EvictionCandidate<'_>, _now: DateTime<Utc>) -> f64 {
+        candidate.access_count as f64
+    }
+}
+
+/// Evict data with the most bytes times seconds idle first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeWeighted;
+
+impl EvictionPolicy for SizeWeighted {
+    fn name(&self) -> &str {
+        "size"
+    }
+
+    fn score(&self, candidate: &EvictionCandidate<'_>, now: DateTime<Utc>) -> f64 {
+        -(candidate.data_ref.size_bytes as f64 * candidate.idle_secs(now))
+    }
+}
+
+/// Evict data expiring soonest first
+///
+/// Data that is deleted soon anyway is the cheapest to lose from a fast
+/// tier. Data without an expiry scores [`f64::MAX`], so among it the
+/// least recently read goes first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TtlAware;
+
+impl EvictionPolicy for TtlAware {
+    fn name(&self) -> &str {
+        "ttl"
+    }
+
+    fn score(&self, candidate: &EvictionCandidate<'_>, now: DateTime<Utc>) -> f64 {
+        candidate
+            .data_ref
+            .expires_at
+            .map_or(f64::MAX, |expires_at| {
+                (expires_at - now).num_milliseconds() as f64 / 1000.0
+            })
+    }
+}
+
+/// Names of the built-in policies
+pub const EVICTION_POLICIES: [&str; 4] = ["lru", "lfu", "size", "ttl"];
+
+/// Look up a built-in policy by name, e.g. from configuration
+pub fn eviction_policy(name: &str) -> Option<Arc<dyn EvictionPolicy>> {
+    match name {
+        "lru" => Some(Arc::new(Lru)),
+        "lfu" => Some(Arc::new(Lfu)),
+        "size" => Some(Arc::new(SizeWeighted)),
+        "ttl" => Some(Arc::new(TtlAware)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_eviction_policies() {
+        let now = Utc::now();
+        let data = |size_bytes: u64, expires_in: Option<i64>| {
+            let mut data_ref = DataRef::new(
+                "gpu-1".to_string(),
+                size_bytes,
+                DataType::Bytes,
+                Uuid::new_v4(),
+            );
+            data_ref.expires_at = expires_in.map(|secs| now + Duration::seconds(secs));
+            data_ref
+        };
+        let (frequent, large_idle, expiring) =
+            (data(10, None), data(1000, None), data(100, Some(60)));
+        let candidates = [
+            EvictionCandidate {
+                data_ref: &frequent,
+                last_access: now - Duration::seconds(600),
+                access_count: 50,
+            },
+            EvictionCandidate {
+                data_ref: &large_idle,
+                last_access: now - Duration::seconds(300),
+                access_count: 2,
+            },
+            EvictionCandidate {
+                data_ref: &expiring,
+                last_access: now - Duration::seconds(10),
+                access_count: 5,
+            },
+        ];
+        let first = |name: &str| {
+            let policy = eviction_policy(name).unwrap();
+            assert_eq!(policy.name(), name);
+            candidates
+                .iter()
+                .min_by(|a, b| policy.score(a, now).total_cmp(&policy.score(b, now)))
+                .unwrap()
+                .data_ref
+                .uuid
+        };
+        assert_eq!(first("lru"), frequent.uuid);
+        assert_eq!(first("lfu"), large_idle.uuid);
+        assert_eq!(first("size"), large_idle.uuid);
+        assert_eq!(first("ttl"), expiring.uuid);
+        assert!(EVICTION_POLICIES
+            .iter()
+            .all(|name| eviction_policy(name).is_some()));
+        assert!(eviction_policy("random").is_none());
+    }
+}
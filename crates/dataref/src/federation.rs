@@ -0,0 +1,314 @@
+//! Cross-cluster data federation
+//!
+//! Separate SwarmX clusters, such as one GPU pool per region, each run
+//! their own API server and data stores. Data produced in one cluster is
+//! used in another through an ordinary [`DataRef`] whose `location` is the
+//! owning cluster's API endpoint. A [`Federation`] knows the peer clusters
+//! and brokers access between them:
+//!
+//! 1. The importing cluster signs a read-only token for the data with its
+//!    own federation key ([`Federation::request_token`]).
+//! 2. The owning cluster checks the token against the key it trusts for
+//!    that peer and exchanges it for a token of its own, with the same
+//!    scope and expiring no later ([`Federation::exchange`]).
+//! 3. The importing cluster fetches the payload with the exchanged token,
+//!    which the owning cluster [authorizes](Federation::authorize), and
+//!    stores a copy under the same UUID.
+//!
+//! Every cluster signs under its own name as key ID, so one peer cannot
+//! pass off tokens as another's, and tokens a cluster issued to peers are
+//! the only ones it serves data for.
+
+use std::collections::BTreeMap;
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::DataRef;
+use crate::token::{AccessToken, Permissions, TokenError, TokenManager};
+
+/// Default lifetime of federation tokens: 5 minutes
+pub const DEFAULT_FEDERATION_TOKEN_TTL_SECS: i64 = 300;
+
+/// Tag recording the cluster imported data was copied from
+pub const FEDERATED_FROM_TAG: &str = "federated_from";
+
+/// Another SwarmX cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteCluster {
+    /// Name the cluster signs its federation tokens under
+    pub name: String,
+    /// Base URL of the cluster's API, e.g. `https://eu.example.com/api`
+    pub endpoint: String,
+}
+
+impl RemoteCluster {
+    /// Create a peer cluster
+    pub fn new(name: &str, endpoint: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Check whether data at `location` is held by the cluster
+    pub fn hosts(&self, location: &str) -> bool {
+        location
+            .strip_prefix(self.endpoint.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// URL of an API path on the cluster, e.g. `federation/token`
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.endpoint, path.trim_start_matches('/'))
+    }
+}
+
+/// Federation errors
+#[derive(Debug, thiserror::Error)]
+pub enum FederationError {
+    #[error("Unknown cluster: {0}")]
+    UnknownCluster(String),
+
+    #[error("Data {0} is not held by a federated cluster")]
+    NotFederated(Uuid),
+
+    #[error("Token was not issued by cluster {0}")]
+    ForeignToken(String),
+
+    #[error("Token error: {0}")]
+    Token(#[from] TokenError),
+}
+
+/// This cluster's view of the clusters it shares data with
+#[derive(Debug, Clone)]
+pub struct Federation {
+    /// Name of this cluster
+    cluster: String,
+    /// Peer clusters by name
+    peers: BTreeMap<String, RemoteCluster>,
+    /// Signs this cluster's tokens and verifies those of peers
+    tokens: TokenManager,
+    token_ttl: Duration,
+}
+
+impl Federation {
+    /// Create a federation for the cluster `cluster`, signing with the
+    /// HMAC `secret_key`
+    pub fn new(cluster: &str, secret_key: &[u8]) -> Result<Self, FederationError> {
+        Ok(Self {
+            cluster: cluster.to_string(),
+            peers: BTreeMap::new(),
+            tokens: TokenManager::new(cluster.to_string(), cluster, secret_key)?,
+            token_ttl: Duration::seconds(DEFAULT_FEDERATION_TOKEN_TTL_SECS),
+        })
+    }
+
+    /// Share data with `peer`, trusting tokens signed with its HMAC
+    /// `secret_key`
+    pub fn with_peer(
+        mut self,
+        peer: RemoteCluster,
+        secret_key: &[u8],
+    ) -> Result<Self, FederationError> {
+        self.tokens = self.tokens.with_verification_key(&peer.name, secret_key)?;
+        self.peers.insert(peer.name.clone(), peer);
+        Ok(self)
+    }
+
+    /// Issue tokens valid for `ttl`
+    pub fn with_token_ttl(mut self, ttl: Duration) -> Self {
+        self.token_ttl = ttl;
+        self
+    }
+
+    /// Name of this cluster
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    /// Peer clusters, by name
+    pub fn peers(&self) -> impl Iterator<Item = &RemoteCluster> {
+        self.peers.values()
+    }
+
+    /// Look up a peer cluster by name
+    pub fn peer(&self, name: &str) -> Option<&RemoteCluster> {
+        self.peers.get(name)
+    }
+
+    /// Peer cluster holding data, if it is held by one
+    pub fn cluster_of(&self, data_ref: &DataRef) -> Option<&RemoteCluster> {
+        self.peers
+            .values()
+            .find(|peer| peer.hosts(&data_ref.location))
+    }
+
+    /// Sign a token asking the peer holding `data_ref` for read access
+    pub fn request_token(&self, data_ref: &DataRef) -> Result<AccessToken, FederationError> {
+        if self.cluster_of(data_ref).is_none() {
+            return Err(FederationError::NotFederated(data_ref.uuid));
+        }
+        let token = AccessToken::new(
+            data_ref.uuid,
+            self.cluster.clone(),
+            self.token_ttl,
+            Permissions::read_only(),
+        )
+        .with_workflow(data_ref.workflow_id);
+        Ok(self.tokens.sign(token)?)
+    }
+
+    /// Exchange a peer's token for one issued by this cluster
+    ///
+    /// The peer's token must be signed with the key trusted for the peer
+    /// it names as issuer. The exchanged token has the same scope, is
+    /// read-only, and expires with the peer's token at the latest.
+    pub fn exchange(&self, token: &AccessToken) -> Result<AccessToken, FederationError> {
+        if !self.peers.contains_key(&token.issued_by) {
+            return Err(FederationError::UnknownCluster(token.issued_by.clone()));
+        }
+        if token.key_id != token.issued_by {
+            return Err(FederationError::ForeignToken(token.issued_by.clone()));
+        }
+        self.tokens.authorize(
+            token,
+            std::iter::empty::<&DataRef>(),
+            Permissions::read_only(),
+        )?;
+        let mut exchanged = AccessToken::scoped(
+            token.scope.clone(),
+            self.cluster.clone(),
+            self.token_ttl,
+            Permissions::read_only(),
+        );
+        exchanged.expires_at = exchanged.expires_at.min(token.expires_at);
+        exchanged.workflow_id = token.workflow_id;
+        Ok(self.tokens.sign(exchanged)?)
+    }
+
+    /// Check that a token this cluster issued in
+    /// [exchange](Self::exchange) grants read access to local data
+    pub fn authorize(
+        &self,
+        token: &AccessToken,
+        data_ref: &DataRef,
+    ) -> Result<(), FederationError> {
+        if token.issued_by != self.cluster || token.key_id != self.cluster {
+            return Err(FederationError::ForeignToken(self.cluster.clone()));
+        }
+        self.tokens
+            .authorize(token, [data_ref], Permissions::read_only())?;
+        Ok(())
+    }
+
+    /// Ref of a local copy of federated data, before it is stored
+    ///
+    /// The copy keeps the data's UUID and metadata, belongs to
+    /// `workflow_id` in this cluster, and is tagged with the cluster it
+    /// came from.
+    pub fn local_copy(
+        &self,
+        data_ref: &DataRef,
+        workflow_id: Uuid,
+    ) -> Result<DataRef, FederationError> {
+        let peer = self
+            .cluster_of(data_ref)
+            .ok_or(FederationError::NotFederated(data_ref.uuid))?;
+        let mut copy = data_ref.clone().with_tag(FEDERATED_FROM_TAG, &peer.name);
+        copy.workflow_id = workflow_id;
+        copy.replicas.clear();
+        copy.shared_from = None;
+        copy.produced_by = None;
+        Ok(copy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    #[test]
+    fn test_token_exchange() {
+        let us = Federation::new("us-west", b"us secret")
+            .unwrap()
+            .with_peer(
+                RemoteCluster::new("eu-central", "https://eu.example.com/api/"),
+                b"eu secret",
+            )
+            .unwrap();
+        let eu = Federation::new("eu-central", b"eu secret")
+            .unwrap()
+            .with_peer(
+                RemoteCluster::new("us-west", "https://us.example.com/api"),
+                b"us secret",
+            )
+            .unwrap();
+        let remote = DataRef::new(
+            "https://eu.example.com/api".to_string(),
+            100,
+            DataType::Bytes,
+            Uuid::new_v4(),
+        );
+        assert_eq!(us.cluster_of(&remote).unwrap().name, "eu-central");
+        assert_eq!(
+            us.peer("eu-central").unwrap().url("federation/token"),
+            "https://eu.example.com/api/federation/token"
+        );
+
+        // The owning cluster exchanges the peer's token for its own
+        let requested = us.request_token(&remote).unwrap();
+        assert!(matches!(
+            eu.authorize(&requested, &remote),
+            Err(FederationError::ForeignToken(_))
+        ));
+        let exchanged = eu.exchange(&requested).unwrap();
+        assert_eq!(exchanged.issued_by, "eu-central");
+        assert!(exchanged.expires_at <= requested.expires_at);
+        eu.authorize(&exchanged, &remote).unwrap();
+        let other = DataRef::new(remote.location.clone(), 1, DataType::Bytes, Uuid::new_v4());
+        assert!(eu.authorize(&exchanged, &other).is_err());
+
+        // Tokens from unknown clusters or with forged signatures are refused
+        let stranger = Federation::new("ap-south", b"ap secret")
+            .unwrap()
+            .with_peer(
+                RemoteCluster::new("eu-central", "https://eu.example.com/api"),
+                b"eu secret",
+            )
+            .unwrap();
+        assert!(matches!(
+            eu.exchange(&stranger.request_token(&remote).unwrap()),
+            Err(FederationError::UnknownCluster(_))
+        ));
+        let forger = Federation::new("us-west", b"wrong secret")
+            .unwrap()
+            .with_peer(
+                RemoteCluster::new("eu-central", "https://eu.example.com/api"),
+                b"eu secret",
+            )
+            .unwrap();
+        assert!(eu
+            .exchange(&forger.request_token(&remote).unwrap())
+            .is_err());
+
+        // Local copies keep the UUID and record where they came from
+        let workflow_id = Uuid::new_v4();
+        let copy = us.local_copy(&remote, workflow_id).unwrap();
+        assert_eq!(copy.uuid, remote.uuid);
+        assert_eq!(copy.workflow_id, workflow_id);
+        assert_eq!(copy.tag(FEDERATED_FROM_TAG), Some("eu-central"));
+        let local = DataRef::new(
+            "https://us.example.com/api".to_string(),
+            1,
+            DataType::Bytes,
+            workflow_id,
+        );
+        assert!(matches!(
+            us.request_token(&local),
+            Err(FederationError::NotFederated(_))
+        ));
+    }
+}
@@ -0,0 +1,491 @@
+//! Cluster-wide garbage collection
+//!
+//! The [registry](crate::registry) frees data it knows to be unused, but
+//! copies can still slip through: a server keeps an object whose delete
+//! request was lost, or loses one the [catalog](crate::catalog) still
+//! lists. The [`GcCoordinator`] finds both by reconciling the catalog
+//! against the inventories servers report, every UUID they hold:
+//!
+//! - a copy a server holds that the catalog does not place there is
+//!   *leaked*, and is deleted from the server
+//! - a cataloged copy its server does not report is *missing*, and the
+//!   server is dropped from the data's locations; data left with no
+//!   location at all is dropped from the catalog
+//!
+//! Inventories and the catalog are never updated at the same instant, so a
+//! discrepancy is only acted on once it has persisted for the grace period;
+//! inventories older than that are ignored, and data created after an
+//! inventory was taken is never missing from it. Data the registry still
+//! tracks is never deleted. Each pass acts on at most
+//! [`GcPolicy::max_deletions`] findings, oldest first, and the rest wait
+//! for the next pass. In a dry run, passes only report what they would
+//! do.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::pointer::DataRef;
+
+/// Default time between garbage collection passes: 15 minutes
+pub const DEFAULT_GC_INTERVAL_SECS: u64 = 900;
+
+/// Default time a discrepancy persists before it is acted on: 1 hour
+pub const DEFAULT_GC_RECONCILE_GRACE_SECS: i64 = 3600;
+
+/// Default number of findings acted on per pass
+pub const DEFAULT_GC_MAX_DELETIONS: usize = 100;
+
+/// How often and how aggressively to collect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcPolicy {
+    /// Time between passes
+    pub interval: std::time::Duration,
+    /// Time a discrepancy persists before it is acted on, and the age
+    /// after which inventories are ignored
+    pub grace_period: Duration,
+    /// Report what would be done without doing it
+    pub dry_run: bool,
+    /// Findings acted on per pass
+    pub max_deletions: usize,
+    /// Findings acted on per second; unlimited if `None`
+    pub delete_rate: Option<u32>,
+}
+
+impl Default for GcPolicy {
+    fn default() -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(DEFAULT_GC_INTERVAL_SECS),
+            grace_period: Duration::seconds(DEFAULT_GC_RECONCILE_GRACE_SECS),
+            dry_run: false,
+            max_deletions: DEFAULT_GC_MAX_DELETIONS,
+            delete_rate: None,
+        }
+    }
+}
+
+impl GcPolicy {
+    /// Create the default policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run every `interval`, at most once a second
+    pub fn with_interval(mut self, interval: std::time::Duration) -> Self {
+        self.interval = interval.max(std::time::Duration::from_secs(1));
+        self
+    }
+
+    /// Act on discrepancies that persisted for `grace_period`
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Only report what passes would do
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Act on at most `max_deletions` findings per pass
+    pub fn with_max_deletions(mut self, max_deletions: usize) -> Self {
+        self.max_deletions = max_deletions;
+        self
+    }
+
+    /// Act on at most `per_second` findings per second
+    pub fn with_delete_rate(mut self, per_second: u32) -> Self {
+        self.delete_rate = Some(per_second.max(1));
+        self
+    }
+
+    /// Pause between two actions under the rate limit
+    pub fn delete_interval(&self) -> Option<std::time::Duration> {
+        self.delete_rate
+            .map(|per_second| std::time::Duration::from_secs(1) / per_second)
+    }
+}
+
+/// UUIDs of the data a server holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInventory {
+    pub server: String,
+    pub data: HashSet<Uuid>,
+    /// When the server listed its data
+    pub reported_at: DateTime<Utc>,
+}
+
+/// Which way the catalog and a server disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcFindingKind {
+    /// The server holds a copy the catalog does not place there
+    Leaked,
+    /// The catalog places a copy on the server, which does not report it
+    Missing,
+}
+
+/// A copy the catalog and its server disagree about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcFinding {
+    pub kind: GcFindingKind,
+    pub server: String,
+    pub data_uuid: Uuid,
+    /// Pass that first found the discrepancy
+    pub first_seen: DateTime<Utc>,
+}
+
+/// A finding that could not be acted on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcFailure {
+    pub finding: GcFinding,
+    pub error: String,
+}
+
+/// Outcome of one garbage collection pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    pub started_at: DateTime<Utc>,
+    pub dry_run: bool,
+    /// Servers whose inventories were reconciled
+    pub servers: u64,
+    /// Every leaked copy found, oldest first
+    pub leaked: Vec<GcFinding>,
+    /// Every missing copy found, oldest first
+    pub missing: Vec<GcFinding>,
+    /// Leaked copies deleted, or that would be in a dry run
+    pub deleted: Vec<GcFinding>,
+    /// Missing copies dropped from the catalog, or that would be in a dry
+    /// run
+    pub uncataloged: Vec<GcFinding>,
+    /// Findings past the grace period left for later passes by the limits
+    pub deferred: u64,
+    pub failed: Vec<GcFailure>,
+    pub duration_ms: u64,
+}
+
+impl GcReport {
+    /// Event recording the pass
+    pub fn completed_event(&self) -> Event {
+        Event::DataGcCompleted {
+            dry_run: self.dry_run,
+            servers: self.servers,
+            leaked: self.leaked.len() as u64,
+            missing: self.missing.len() as u64,
+            deleted: self.deleted.len() as u64,
+            uncataloged: self.uncataloged.len() as u64,
+            deferred: self.deferred,
+            duration_ms: self.duration_ms,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// What one pass found and should act on
+#[derive(Debug, Clone)]
+pub struct GcPlan {
+    /// The pass's report, to be completed as findings are acted on
+    pub report: GcReport,
+    /// Findings past the grace period to act on, oldest first
+    pub due: Vec<GcFinding>,
+}
+
+/// Garbage collection so far
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcStatus {
+    /// Completed passes
+    pub passes: u64,
+    /// Latest inventory of each server, without its UUIDs
+    pub inventories: Vec<InventorySummary>,
+    pub last_report: Option<GcReport>,
+}
+
+/// A server's latest inventory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventorySummary {
+    pub server: String,
+    pub data_count: usize,
+    pub reported_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct GcState {
+    /// Latest inventory of each server
+    inventories: HashMap<String, ServerInventory>,
+    /// When each open discrepancy was first found
+    first_seen: HashMap<(GcFindingKind, String, Uuid), DateTime<Utc>>,
+    passes: u64,
+    last_report: Option<GcReport>,
+}
+
+/// Reconciles the catalog with server inventories
+///
+/// Cheaply cloneable; clones share the same inventories and findings.
+#[derive(Debug, Clone, Default)]
+pub struct GcCoordinator {
+    policy: GcPolicy,
+    state: Arc<Mutex<GcState>>,
+}
+
+impl GcCoordinator {
+    /// Create a coordinator following `policy`
+    pub fn new(policy: GcPolicy) -> Self {
+        Self {
+            policy,
+            state: Arc::default(),
+        }
+    }
+
+    /// Policy the coordinator follows
+    pub fn policy(&self) -> &GcPolicy {
+        &self.policy
+    }
+
+    /// Record the data a server holds, replacing its previous inventory
+    pub fn record_inventory(&self, inventory: ServerInventory) {
+        self.lock()
+            .inventories
+            .insert(inventory.server.clone(), inventory);
+    }
+
+    /// Reconcile `catalog`, every cataloged ref, with the inventories
+    /// reported within the grace period
+    ///
+    /// Leaked copies of data `keep` returns `true` for are not reported.
+    /// A dry run is planned if the policy or `dry_run` asks for one.
+    pub fn plan(
+        &self,
+        catalog: &[DataRef],
+        keep: impl Fn(&Uuid) -> bool,
+        dry_run: bool,
+        now: DateTime<Utc>,
+    ) -> GcPlan {
+        let grace_period = self.policy.grace_period;
+        let mut state = self.lock();
+        let inventories: Vec<&ServerInventory> = state
+            .inventories
+            .values()
+            .filter(|inventory| now - inventory.reported_at <= grace_period)
+            .collect();
+
+        let mut found = Vec::new();
+        for inventory in &inventories {
+            let server = inventory.server.as_str();
+            let cataloged: HashSet<Uuid> = catalog
+                .iter()
+                .filter(|data_ref| data_ref.is_local_to(server))
+                .map(|data_ref| data_ref.uuid)
+                .collect();
+            found.extend(
+                inventory
+                    .data
+                    .iter()
+                    .filter(|uuid| !cataloged.contains(uuid) && !keep(uuid))
+                    .map(|uuid| (GcFindingKind::Leaked, server, *uuid)),
+            );
+            found.extend(
+                catalog
+                    .iter()
+                    .filter(|data_ref| {
+                        data_ref.is_local_to(server)
+                            && data_ref.created_at < inventory.reported_at
+                            && !inventory.data.contains(&data_ref.uuid)
+                    })
+                    .map(|data_ref| (GcFindingKind::Missing, server, data_ref.uuid)),
+            );
+        }
+        let servers = inventories.len() as u64;
+
+        let mut first_seen = HashMap::new();
+        let mut findings: Vec<GcFinding> = found
+            .into_iter()
+            .map(|(kind, server, data_uuid)| {
+                let key = (kind, server.to_string(), data_uuid);
+                let seen = state.first_seen.get(&key).copied().unwrap_or(now);
+                first_seen.insert(key, seen);
+                GcFinding {
+                    kind,
+                    server: server.to_string(),
+                    data_uuid,
+                    first_seen: seen,
+                }
+            })
+            .collect();
+        // Discrepancies not found again are resolved
+        state.first_seen = first_seen;
+        findings.sort_by(|a, b| {
+            (a.first_seen, &a.server, a.data_uuid).cmp(&(b.first_seen, &b.server, b.data_uuid))
+        });
+
+        let mut due: Vec<GcFinding> = findings
+            .iter()
+            .filter(|finding| now - finding.first_seen >= grace_period)
+            .cloned()
+            .collect();
+        let deferred = due.len().saturating_sub(self.policy.max_deletions) as u64;
+        due.truncate(self.policy.max_deletions);
+        let (leaked, missing) = findings
+            .into_iter()
+            .partition(|finding| finding.kind == GcFindingKind::Leaked);
+        GcPlan {
+            report: GcReport {
+                started_at: now,
+                dry_run: dry_run || self.policy.dry_run,
+                servers,
+                leaked,
+                missing,
+                deferred,
+                ..Default::default()
+            },
+            due,
+        }
+    }
+
+    /// Record a finished pass
+    ///
+    /// Findings acted on are forgotten; if they show up again, their grace
+    /// period starts over.
+    pub fn finish(&self, report: GcReport) {
+        let mut state = self.lock();
+        if !report.dry_run {
+            for finding in report.deleted.iter().chain(&report.uncataloged) {
+                state
+                    .first_seen
+                    .remove(&(finding.kind, finding.server.clone(), finding.data_uuid));
+            }
+        }
+        state.passes += 1;
+        state.last_report = Some(report);
+    }
+
+    /// Garbage collection so far
+    pub fn status(&self) -> GcStatus {
+        let state = self.lock();
+        let mut inventories: Vec<InventorySummary> = state
+            .inventories
+            .values()
+            .map(|inventory| InventorySummary {
+                server: inventory.server.clone(),
+                data_count: inventory.data.len(),
+                reported_at: inventory.reported_at,
+            })
+            .collect();
+        inventories.sort_by(|a, b| a.server.cmp(&b.server));
+        GcStatus {
+            passes: state.passes,
+            inventories,
+            last_report: state.last_report.clone(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GcState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    const GPU_1: &str = "http://gpu-1:9090";
+    const GPU_2: &str = "http://gpu-2:9090";
+
+    #[test]
+    fn test_gc_reconciliation() {
+        let start = Utc::now();
+        let created = start - Duration::minutes(10);
+        let data = |location: &str| {
+            let mut data_ref =
+                DataRef::new(location.to_string(), 100, DataType::Bytes, Uuid::new_v4());
+            data_ref.created_at = created;
+            data_ref
+        };
+        let held = data(GPU_1);
+        let mut replicated = data(GPU_1);
+        replicated.add_replica(GPU_2);
+        let lost = data(GPU_2);
+        let (stray, registered) = (Uuid::new_v4(), Uuid::new_v4());
+        let catalog = [held.clone(), replicated.clone(), lost.clone()];
+
+        let coordinator = GcCoordinator::new(
+            GcPolicy::new()
+                .with_grace_period(Duration::minutes(30))
+                .with_max_deletions(2),
+        );
+        let report = |server: &str, data: &[Uuid], at: DateTime<Utc>| ServerInventory {
+            server: server.to_string(),
+            data: data.iter().copied().collect(),
+            reported_at: at,
+        };
+        let keep = |uuid: &Uuid| *uuid == registered;
+
+        // GPU 1 holds a copy nobody cataloged; GPU 2 lost both its copies
+        coordinator.record_inventory(report(GPU_1, &[held.uuid, replicated.uuid, stray], start));
+        coordinator.record_inventory(report(GPU_2, &[registered], start));
+        let plan = coordinator.plan(&catalog, keep, false, start);
+        assert_eq!(plan.report.servers, 2);
+        assert_eq!(plan.report.leaked.len(), 1);
+        assert_eq!(plan.report.leaked[0].data_uuid, stray);
+        let missing: HashSet<Uuid> = plan.report.missing.iter().map(|f| f.data_uuid).collect();
+        assert_eq!(missing, HashSet::from([replicated.uuid, lost.uuid]));
+        // Nothing is due before the grace period
+        assert!(plan.due.is_empty());
+        coordinator.finish(plan.report);
+
+        // Data created after the inventory was taken is not missing
+        let mut fresh = data(GPU_1);
+        fresh.created_at = start + Duration::minutes(1);
+        let mut catalog = catalog.to_vec();
+        catalog.push(fresh);
+
+        // Past the grace period, the three findings are due, two at a time
+        let later = start + Duration::minutes(31);
+        coordinator.record_inventory(report(GPU_1, &[held.uuid, replicated.uuid, stray], start));
+        coordinator.record_inventory(report(GPU_2, &[registered], later));
+        let plan = coordinator.plan(&catalog, keep, false, later);
+        // GPU 1's inventory is too old to trust
+        assert_eq!(plan.report.servers, 1);
+        assert!(plan.report.leaked.is_empty());
+        assert_eq!(plan.report.missing.len(), 2);
+        assert_eq!(plan.due.len(), 2);
+        assert_eq!(plan.report.deferred, 0);
+        assert!(plan
+            .due
+            .iter()
+            .all(|finding| finding.first_seen == start && finding.server == GPU_2));
+
+        let mut done = plan.report;
+        done.uncataloged = plan.due;
+        assert!(matches!(
+            done.completed_event(),
+            Event::DataGcCompleted {
+                missing: 2,
+                uncataloged: 2,
+                ..
+            }
+        ));
+        coordinator.finish(done);
+        let status = coordinator.status();
+        assert_eq!(status.passes, 2);
+        assert_eq!(status.inventories.len(), 2);
+        assert_eq!(status.inventories[0].server, GPU_1);
+        assert_eq!(status.last_report.unwrap().uncataloged.len(), 2);
+
+        // Findings acted on start over if they come back
+        coordinator.record_inventory(report(GPU_1, &[stray], later));
+        let plan = coordinator.plan(&catalog, keep, true, later);
+        assert!(plan.report.dry_run);
+        assert_eq!(plan.report.leaked.len(), 1);
+        assert_eq!(plan.report.leaked[0].first_seen, later);
+        let again = coordinator.plan(&catalog, keep, false, later + Duration::minutes(30));
+        assert_eq!(again.due.len(), 2);
+        assert_eq!(again.report.deferred, 4);
+    }
+}
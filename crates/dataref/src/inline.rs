@@ -0,0 +1,97 @@
+//! Inline eligibility policy
+//!
+//! Small values travel inside task messages instead of as references the
+//! server has to fetch. [`InlinePolicy`] decides what counts as small: a
+//! deployment-wide byte limit, optionally overridden per data type, e.g. to
+//! never inline KV caches or to allow larger JSON documents.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pointer::{DataRef, DataType};
+
+/// Default largest inlined payload: 64KB
+pub const DEFAULT_INLINE_MAX_BYTES: u64 = 64 * 1024;
+
+/// Size limits for inlining data in task messages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InlinePolicy {
+    /// Largest payload inlined, in bytes, unless overridden for its type
+    pub max_bytes: u64,
+    /// Limits by data type name (see [`DataType::name`])
+    #[serde(default)]
+    pub per_type: HashMap<String, u64>,
+}
+
+impl Default for InlinePolicy {
+    /// 64KB for every type except KV caches, which stay on their server
+    fn default() -> Self {
+        Self::new(DEFAULT_INLINE_MAX_BYTES).with_type_limit("kv_cache", 0)
+    }
+}
+
+impl InlinePolicy {
+    /// Inline payloads up to `max_bytes` of any type
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            per_type: HashMap::new(),
+        }
+    }
+
+    /// Use a different limit for one data type; 0 never inlines it
+    pub fn with_type_limit(mut self, type_name: &str, max_bytes: u64) -> Self {
+        self.per_type.insert(type_name.to_string(), max_bytes);
+        self
+    }
+
+    /// Largest payload of a type that is inlined
+    pub fn limit_for(&self, dtype: &DataType) -> u64 {
+        self.per_type
+            .get(dtype.name())
+            .copied()
+            .unwrap_or(self.max_bytes)
+    }
+
+    /// Check if data is small enough to inline
+    pub fn allows(&self, data_ref: &DataRef) -> bool {
+        let limit = self.limit_for(&data_ref.dtype);
+        limit > 0 && data_ref.size_bytes <= limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_inline_policy() {
+        let workflow_id = Uuid::new_v4();
+        let json = |size| DataRef::new("server-a".to_string(), size, DataType::Json, workflow_id);
+        let kv = DataRef::new(
+            "server-a".to_string(),
+            16,
+            DataType::KvCache {
+                model_id: "deepseek-coder".to_string(),
+                seq_len: 4,
+            },
+            workflow_id,
+        );
+
+        assert!(json(DEFAULT_INLINE_MAX_BYTES).is_inline_eligible());
+        assert!(!json(DEFAULT_INLINE_MAX_BYTES + 1).is_inline_eligible());
+        assert!(!kv.is_inline_eligible());
+
+        let policy = InlinePolicy::new(1024).with_type_limit("json", 1 << 20);
+        assert!(policy.allows(&json(1 << 20)));
+        assert!(policy.allows(&kv));
+        assert!(!policy.allows(&DataRef::file(
+            "server-a".to_string(),
+            workflow_id,
+            2048,
+            "text/plain".to_string(),
+        )));
+    }
+}
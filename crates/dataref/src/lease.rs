@@ -0,0 +1,194 @@
+//! Read leases
+//!
+//! A server about to pull data takes a short-lived [`ReadLease`] on it
+//! first. While a lease is active, the [registry](crate::registry) does not
+//! collect the data, whether it is unreferenced, past its TTL, or past its
+//! workflow's retention, and explicit deletes are refused; collection
+//! resumes once every lease is released or has expired. Leases expire on
+//! their own so a server that crashes mid-transfer cannot keep data alive,
+//! and a long transfer renews its lease before it runs out.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Default lifetime of a read lease: 1 minute
+pub const DEFAULT_LEASE_SECS: i64 = 60;
+
+/// Longest lifetime a read lease is granted for: 10 minutes
+pub const MAX_LEASE_SECS: i64 = 600;
+
+/// Permission to read data without it being deleted underneath
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadLease {
+    pub lease_id: Uuid,
+    pub data_uuid: Uuid,
+    /// Server or client reading the data
+    pub holder: String,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ReadLease {
+    /// Check if the lease still holds at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        now < self.expires_at
+    }
+}
+
+/// Lifetime a lease is granted for: `ttl`, within 1 second and
+/// [`MAX_LEASE_SECS`]
+fn lease_ttl(ttl: Duration) -> Duration {
+    ttl.clamp(Duration::seconds(1), Duration::seconds(MAX_LEASE_SECS))
+}
+
+/// Read leases on data, by lease ID
+#[derive(Debug, Clone, Default)]
+pub struct LeaseTable {
+    leases: HashMap<Uuid, ReadLease>,
+}
+
+impl LeaseTable {
+    /// Create an empty lease table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grant `holder` a lease on data for `ttl`, capped at
+    /// [`MAX_LEASE_SECS`]
+    pub fn grant(
+        &mut self,
+        data_uuid: Uuid,
+        holder: String,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> ReadLease {
+        let lease = ReadLease {
+            lease_id: Uuid::new_v4(),
+            data_uuid,
+            holder,
+            acquired_at: now,
+            expires_at: now + lease_ttl(ttl),
+        };
+        self.leases.insert(lease.lease_id, lease.clone());
+        lease
+    }
+
+    /// Extend an active lease to `ttl` from `now`; `None` if it is unknown
+    /// or has expired
+    pub fn renew(
+        &mut self,
+        lease_id: &Uuid,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> Option<ReadLease> {
+        let lease = self
+            .leases
+            .get_mut(lease_id)
+            .filter(|lease| lease.is_active(now))?;
+        lease.expires_at = now + lease_ttl(ttl);
+        Some(lease.clone())
+    }
+
+    /// Give up a lease; `None` if it is unknown
+    pub fn release(&mut self, lease_id: &Uuid) -> Option<ReadLease> {
+        self.leases.remove(lease_id)
+    }
+
+    /// Check if data has an active lease at `now`
+    pub fn is_leased(&self, data_uuid: &Uuid, now: DateTime<Utc>) -> bool {
+        self.leases
+            .values()
+            .any(|lease| lease.data_uuid == *data_uuid && lease.is_active(now))
+    }
+
+    /// Get the active leases at `now`, soonest expiring first
+    pub fn active(&self, now: DateTime<Utc>) -> Vec<&ReadLease> {
+        let mut leases: Vec<&ReadLease> = self
+            .leases
+            .values()
+            .filter(|lease| lease.is_active(now))
+            .collect();
+        leases.sort_by_key(|lease| (lease.expires_at, lease.lease_id));
+        leases
+    }
+
+    /// Get the active leases on data at `now`, soonest expiring first
+    pub fn on(&self, data_uuid: &Uuid, now: DateTime<Utc>) -> Vec<&ReadLease> {
+        let mut leases = self.active(now);
+        leases.retain(|lease| lease.data_uuid == *data_uuid);
+        leases
+    }
+
+    /// Drop the leases that expired by `now`, returning them
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<ReadLease> {
+        let expired: Vec<Uuid> = self
+            .leases
+            .values()
+            .filter(|lease| !lease.is_active(now))
+            .map(|lease| lease.lease_id)
+            .collect();
+        expired
+            .iter()
+            .filter_map(|lease_id| self.leases.remove(lease_id))
+            .collect()
+    }
+
+    /// Drop every lease on data that no longer exists
+    pub fn remove_data(&mut self, data_uuid: &Uuid) {
+        self.leases.retain(|_, lease| lease.data_uuid != *data_uuid);
+    }
+
+    /// Check if there are no leases, active or expired
+    pub fn is_empty(&self) -> bool {
+        self.leases.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_table() {
+        let now = Utc::now();
+        let data_uuid = Uuid::new_v4();
+        let mut leases = LeaseTable::new();
+
+        let short = leases.grant(data_uuid, "gpu-1".to_string(), Duration::seconds(30), now);
+        let long = leases.grant(data_uuid, "gpu-2".to_string(), Duration::hours(1), now);
+        assert_eq!(long.expires_at, now + Duration::seconds(MAX_LEASE_SECS));
+        assert!(leases.is_leased(&data_uuid, now));
+        assert!(!leases.is_leased(&Uuid::new_v4(), now));
+        let holders: Vec<&str> = leases
+            .on(&data_uuid, now)
+            .iter()
+            .map(|lease| lease.holder.as_str())
+            .collect();
+        assert_eq!(holders, ["gpu-1", "gpu-2"]);
+
+        // A renewed lease outlives its first expiry; an expired one cannot
+        // be renewed
+        let later = now + Duration::seconds(20);
+        let renewed = leases
+            .renew(&short.lease_id, Duration::seconds(30), later)
+            .unwrap();
+        assert_eq!(renewed.expires_at, later + Duration::seconds(30));
+        assert!(leases
+            .renew(
+                &short.lease_id,
+                Duration::seconds(30),
+                now + Duration::seconds(51)
+            )
+            .is_none());
+
+        assert!(leases.release(&long.lease_id).is_some());
+        assert!(leases.release(&long.lease_id).is_none());
+        let expired = leases.expire(now + Duration::seconds(51));
+        assert_eq!(expired, [renewed]);
+        assert!(!leases.is_leased(&data_uuid, now));
+        assert!(leases.is_empty());
+    }
+}
@@ -4,8 +4,70 @@
 //! reference system for SwarmX-UI. DataRef provides location-aware, immutable
 //! references to data objects distributed across the SwarmX cluster.
 
+pub mod catalog;
+pub mod compression;
+pub mod encryption;
+pub mod eviction;
+pub mod federation;
+pub mod gc;
+pub mod inline;
+pub mod lease;
+pub mod lifecycle;
+pub mod lineage;
+pub mod locality;
+pub mod migration;
+pub mod multipart;
+pub mod object_store;
+pub mod pin;
 pub mod pointer;
+pub mod prefetch;
+pub mod query;
+pub mod quota;
+pub mod registry;
+pub mod replica;
+pub mod revocation;
+pub mod scrub;
+pub mod series;
+pub mod session;
+pub mod store;
+pub mod stream;
+pub mod tier;
 pub mod token;
+pub mod topology;
+pub mod transfer;
 
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "s3")]
+pub mod s3;
+
+pub use catalog::*;
+pub use compression::*;
+pub use encryption::*;
+pub use eviction::*;
+pub use federation::*;
+pub use gc::*;
+pub use inline::*;
+pub use lease::*;
+pub use lifecycle::*;
+pub use lineage::*;
+pub use locality::*;
+pub use migration::*;
+pub use multipart::*;
+pub use object_store::*;
+pub use pin::*;
 pub use pointer::*;
+pub use prefetch::*;
+pub use query::*;
+pub use quota::*;
+pub use registry::*;
+pub use revocation::*;
+pub use scrub::*;
+pub use series::*;
+pub use session::*;
+pub use store::*;
+pub use stream::*;
+pub use tier::*;
 pub use token::*;
+pub use topology::*;
+pub use transfer::*;
@@ -0,0 +1,119 @@
+//! Lifecycle rules for distributed data
+//!
+//! Reference counting frees data nobody needs, but some data should go
+//! sooner or regardless: a piece of data can carry its own TTL
+//! ([`DataRef::with_ttl`]), and a [`LifecyclePolicy`] can delete everything
+//! a workflow produced a fixed time after the workflow reached a terminal
+//! state. [`DataRefRegistry::collect`] applies both alongside reference
+//! counting.
+//!
+//! [`DataRef::with_ttl`]: crate::pointer::DataRef::with_ttl
+//! [`DataRefRegistry::collect`]: crate::registry::DataRefRegistry::collect
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Deployment-wide rules for deleting data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LifecyclePolicy {
+    /// How long a workflow's data is kept after it completes, fails, or is
+    /// cancelled; `None` keeps it as long as it is referenced
+    pub workflow_retention: Option<Duration>,
+}
+
+impl LifecyclePolicy {
+    /// Create a policy without lifecycle rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete a workflow's data `retention` after it terminates
+    pub fn with_workflow_retention(mut self, retention: Duration) -> Self {
+        self.workflow_retention = Some(retention);
+        self
+    }
+
+    /// Check if data of a workflow that terminated at `terminated_at` is due
+    /// for deletion
+    pub fn workflow_expired(&self, terminated_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.workflow_retention
+            .is_some_and(|retention| now - terminated_at >= retention)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{DataRef, DataType};
+    use crate::registry::{DataRefRegistry, Holder};
+    use swarmx_events::Event;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_lifecycle_rules() {
+        let workflow_id = Uuid::new_v4();
+        let data = |ttl: Option<i64>| {
+            let data = DataRef::new("server-a".to_string(), 1024, DataType::Bytes, workflow_id);
+            match ttl {
+                Some(secs) => data.with_ttl(Duration::seconds(secs)),
+                None => data,
+            }
+        };
+        let short_lived = data(Some(60));
+        let kept = data(None);
+        let now = Utc::now();
+        assert!(!short_lived.is_expired(now));
+        assert!(short_lived.is_expired(now + Duration::seconds(61)));
+        assert!(!kept.is_expired(now + Duration::days(365)));
+
+        let mut registry = DataRefRegistry::new(Duration::seconds(60))
+            .with_lifecycle(LifecyclePolicy::new().with_workflow_retention(Duration::hours(24)));
+        let holder = Holder::Workflow { workflow_id };
+        registry.register(short_lived.clone(), holder);
+        registry.register(kept.clone(), holder);
+        let other_workflow = Uuid::new_v4();
+        let other = DataRef::new(
+            "server-b".to_string(),
+            1024,
+            DataType::Bytes,
+            other_workflow,
+        );
+        registry.register(
+            other,
+            Holder::Workflow {
+                workflow_id: other_workflow,
+            },
+        );
+
+        // TTL applies while the data is still referenced
+        let collected = registry.collect(now + Duration::seconds(61));
+        assert_eq!(collected.len(), 1);
+        assert_eq!(collected[0].uuid, short_lived.uuid);
+
+        // A running workflow keeps its data
+        assert!(registry.collect(now + Duration::days(2)).is_empty());
+
+        let terminated_at = Utc::now();
+        registry.apply(&Event::WorkflowFailed {
+            workflow_id,
+            error: "boom".to_string(),
+            timestamp: terminated_at,
+        });
+        // The workflow's holder is gone; retention applies even if the data
+        // is acquired again
+        registry
+            .acquire(
+                kept.uuid,
+                Holder::Workflow {
+                    workflow_id: Uuid::new_v4(),
+                },
+            )
+            .unwrap();
+        assert!(registry
+            .collect(terminated_at + Duration::hours(23))
+            .is_empty());
+        let collected = registry.collect(terminated_at + Duration::hours(24));
+        assert_eq!(collected.len(), 1);
+        assert_eq!(collected[0].uuid, kept.uuid);
+        assert_eq!(registry.len(), 1);
+    }
+}
@@ -0,0 +1,278 @@
+//! Data lineage graph
+//!
+//! A [`DataRef`] produced by a node records the node and the inputs it read
+//! (see [`DataRef::derived_from`]). [`LineageGraph`] links those records
+//! into a graph that answers where data came from, through
+//! [`lineage`](LineageGraph::lineage), and what was computed from it,
+//! through [`descendants`](LineageGraph::descendants): when an input
+//! changes, every descendant is a cached result to invalidate. The graph
+//! can be exported in Graphviz DOT for inspection.
+//!
+//! The graph lives in memory and follows the event stream through
+//! [`LineageGraph::apply`]; the event log keeps the durable record of the
+//! same `DataDerivedFrom` events.
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::pointer::DataRef;
+
+/// Where one piece of data came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineageEntry {
+    pub data_uuid: Uuid,
+    pub workflow_id: Uuid,
+    /// Node that produced the data; `None` for external inputs
+    pub node_id: Option<Uuid>,
+    /// Data it was derived from
+    pub parents: Vec<Uuid>,
+}
+
+/// Derivations between data, queryable in both directions
+#[derive(Debug, Clone, Default)]
+pub struct LineageGraph {
+    entries: HashMap<Uuid, LineageEntry>,
+    /// Data derived from each piece of data
+    children: HashMap<Uuid, HashSet<Uuid>>,
+}
+
+impl LineageGraph {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record where data came from
+    ///
+    /// Recording known data replaces its parents.
+    pub fn record(&mut self, data_ref: &DataRef) {
+        self.insert(LineageEntry {
+            data_uuid: data_ref.uuid,
+            workflow_id: data_ref.workflow_id,
+            node_id: data_ref.produced_by,
+            parents: data_ref.parents.clone(),
+        });
+    }
+
+    /// Record derivations as they are announced
+    pub fn apply(&mut self, event: &Event) {
+        if let Event::DataDerivedFrom {
+            data_uuid,
+            parent_uuids,
+            workflow_id,
+            node_id,
+            ..
+        } = event
+        {
+            self.insert(LineageEntry {
+                data_uuid: *data_uuid,
+                workflow_id: *workflow_id,
+                node_id: Some(*node_id),
+                parents: parent_uuids.clone(),
+            });
+        }
+    }
+
+    /// Get the recorded origin of data
+    pub fn get(&self, uuid: &Uuid) -> Option<&LineageEntry> {
+        self.entries.get(uuid)
+    }
+
+    /// Everything data was derived from, directly or not
+    ///
+    /// Ancestors are returned breadth first, nearest first, each once.
+    /// Parents that were never recorded, such as external inputs, are
+    /// included and end the walk.
+    pub fn lineage(&self, uuid: Uuid) -> Vec<Uuid> {
+        self.walk(uuid, |current| {
+            self.entries
+                .get(current)
+                .map(|entry| entry.parents.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Everything derived from data, directly or not
+    ///
+    /// Descendants are returned breadth first, nearest first, each once.
+    pub fn descendants(&self, uuid: Uuid) -> Vec<Uuid> {
+        self.walk(uuid, |current| {
+            let mut children: Vec<Uuid> = self
+                .children
+                .get(current)
+                .map(|children| children.iter().copied().collect())
+                .unwrap_or_default();
+            children.sort();
+            children
+        })
+    }
+
+    /// Forget data, e.g. after it was deleted
+    ///
+    /// Its children keep listing it as a parent, so their lineage still
+    /// shows it was derived from something.
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<LineageEntry> {
+        let entry = self.entries.remove(uuid)?;
+        self.unlink(&entry);
+        Some(entry)
+    }
+
+    /// Get the number of recorded pieces of data
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if nothing is recorded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Export the whole graph in Graphviz DOT
+    pub fn to_dot(&self) -> String {
+        let mut uuids: BTreeSet<Uuid> = self.entries.keys().copied().collect();
+        for entry in self.entries.values() {
+            uuids.extend(entry.parents.iter().copied());
+        }
+        self.dot(&uuids)
+    }
+
+    /// Export the lineage and descendants of data in Graphviz DOT
+    pub fn dot_for(&self, uuid: Uuid) -> String {
+        let mut uuids: BTreeSet<Uuid> = self.lineage(uuid).into_iter().collect();
+        uuids.extend(self.descendants(uuid));
+        uuids.insert(uuid);
+        self.dot(&uuids)
+    }
+
+    fn insert(&mut self, entry: LineageEntry) {
+        if let Some(previous) = self.entries.remove(&entry.data_uuid) {
+            self.unlink(&previous);
+        }
+        for parent in &entry.parents {
+            self.children
+                .entry(*parent)
+                .or_default()
+                .insert(entry.data_uuid);
+        }
+        self.entries.insert(entry.data_uuid, entry);
+    }
+
+    fn unlink(&mut self, entry: &LineageEntry) {
+        for parent in &entry.parents {
+            if let Some(children) = self.children.get_mut(parent) {
+                children.remove(&entry.data_uuid);
+                if children.is_empty() {
+                    self.children.remove(parent);
+                }
+            }
+        }
+    }
+
+    /// Breadth-first walk from `start`, excluding it
+    fn walk(&self, start: Uuid, next: impl Fn(&Uuid) -> Vec<Uuid>) -> Vec<Uuid> {
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        let mut found = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            for neighbour in next(&current) {
+                if visited.insert(neighbour) {
+                    found.push(neighbour);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        found
+    }
+
+    /// DOT graph of the given data and the derivations among them
+    fn dot(&self, uuids: &BTreeSet<Uuid>) -> String {
+        let mut dot = String::from("digraph lineage {\n    rankdir=LR;\n");
+        for uuid in uuids {
+            let short = &uuid.to_string()[..8];
+            let label = match self.entries.get(uuid).and_then(|entry| entry.node_id) {
+                Some(node_id) => format!("{short}\\nnode {}", &node_id.to_string()[..8]),
+                None => short.to_string(),
+            };
+            let _ = writeln!(dot, "    \"{uuid}\" [label=\"{label}\"];");
+        }
+        for uuid in uuids {
+            let Some(entry) = self.entries.get(uuid) else {
+                continue;
+            };
+            for parent in entry.parents.iter().filter(|p| uuids.contains(p)) {
+                let _ = writeln!(dot, "    \"{parent}\" -> \"{uuid}\";");
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl DataRef {
+    /// Event recording where the data came from, if a node produced it
+    pub fn derived_event(&self) -> Option<Event> {
+        Some(Event::DataDerivedFrom {
+            data_uuid: self.uuid,
+            parent_uuids: self.parents.clone(),
+            workflow_id: self.workflow_id,
+            node_id: self.produced_by?,
+            timestamp: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    #[test]
+    fn test_lineage_graph() {
+        let workflow_id = Uuid::new_v4();
+        let data = |parents: Vec<Uuid>| {
+            DataRef::new("server-a".to_string(), 64, DataType::Json, workflow_id)
+                .derived_from(Uuid::new_v4(), parents)
+        };
+        // dataset -> features -> model -> report, and dataset -> stats
+        let dataset = DataRef::new("server-a".to_string(), 64, DataType::Bytes, workflow_id);
+        let features = data(vec![dataset.uuid]);
+        let model = data(vec![features.uuid]);
+        let stats = data(vec![dataset.uuid]);
+        let report = data(vec![model.uuid, stats.uuid]);
+
+        let mut graph = LineageGraph::new();
+        for data_ref in [&dataset, &features, &model, &stats] {
+            graph.record(data_ref);
+        }
+        assert!(dataset.derived_event().is_none());
+        graph.apply(&report.derived_event().unwrap());
+        assert_eq!(graph.len(), 5);
+
+        assert_eq!(graph.lineage(report.uuid)[..2], [model.uuid, stats.uuid]);
+        assert_eq!(graph.lineage(report.uuid).len(), 4);
+        assert!(graph.lineage(dataset.uuid).is_empty());
+
+        // Changing the dataset invalidates everything computed from it
+        let invalidated: HashSet<Uuid> = graph.descendants(dataset.uuid).into_iter().collect();
+        assert_eq!(
+            invalidated,
+            HashSet::from([features.uuid, model.uuid, stats.uuid, report.uuid])
+        );
+        assert_eq!(graph.descendants(stats.uuid), vec![report.uuid]);
+
+        let dot = graph.dot_for(stats.uuid);
+        assert!(dot.starts_with("digraph lineage {"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", dataset.uuid, stats.uuid)));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", stats.uuid, report.uuid)));
+        assert!(!dot.contains(&features.uuid.to_string()));
+
+        graph.remove(&stats.uuid);
+        assert_eq!(graph.descendants(dataset.uuid).len(), 3);
+    }
+}
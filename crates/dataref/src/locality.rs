@@ -0,0 +1,211 @@
+//! Data locality scoring
+//!
+//! Where a node should run depends on where its inputs are. Given the
+//! node's input [`DataRef`]s and the servers it could run on,
+//! [`LocalityOracle`] estimates for every server how long staging the
+//! inputs there would take: inputs the server holds, as primary or
+//! replica, cost nothing, and every other input is fetched from its
+//! [cheapest copy](DataRef::cheapest_source) at the speed the
+//! [transfer cost model](crate::topology) gives for the link and the tier
+//! the data is stored in. The scheduler ranks servers by these estimates
+//! rather than doing its own locality math.
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::{DataRef, StorageTier};
+use crate::topology::NetworkTopology;
+
+/// How one input gets to a server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputStaging {
+    pub data_uuid: Uuid,
+    /// Server the input is fetched from; `None` if the server holds it
+    pub source: Option<String>,
+    pub tier: StorageTier,
+    /// Bytes fetched, as stored
+    pub size_bytes: u64,
+    /// Estimated milliseconds to fetch the input; 0 if held locally
+    pub transfer_ms: u64,
+}
+
+/// Locality of a node's inputs on one server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalityScore {
+    pub server: String,
+    /// Share of the input bytes the server already holds, from 0.0 to 1.0;
+    /// 1.0 if the inputs are empty
+    pub score: f64,
+    /// Input bytes the server holds
+    pub local_bytes: u64,
+    /// Input bytes fetched from other servers
+    pub remote_bytes: u64,
+    /// Estimated milliseconds to fetch every remote input, one after
+    /// another
+    pub staging_ms: u64,
+    /// Staging of each input, in input order
+    pub inputs: Vec<InputStaging>,
+}
+
+impl LocalityScore {
+    /// Check if the server holds every input
+    pub fn is_local(&self) -> bool {
+        self.inputs.iter().all(|input| input.source.is_none())
+    }
+
+    /// Order scores best first: least staging time, then most local bytes
+    fn rank(&self, other: &Self) -> Ordering {
+        self.staging_ms
+            .cmp(&other.staging_ms)
+            .then(other.score.total_cmp(&self.score))
+            .then_with(|| self.server.cmp(&other.server))
+    }
+}
+
+/// Scores servers by the locality of a node's inputs
+#[derive(Clone, Copy)]
+pub struct LocalityOracle<'a> {
+    topology: &'a dyn NetworkTopology,
+}
+
+impl<'a> LocalityOracle<'a> {
+    /// Create an oracle estimating transfers with `topology`
+    pub fn new(topology: &'a dyn NetworkTopology) -> Self {
+        Self { topology }
+    }
+
+    /// Score how well `server` is placed to read `inputs`
+    pub fn score<'d>(
+        &self,
+        inputs: impl IntoIterator<Item = &'d DataRef>,
+        server: &str,
+    ) -> LocalityScore {
+        let inputs: Vec<InputStaging> = inputs
+            .into_iter()
+            .map(|data_ref| {
+                let local = data_ref.is_local_to(server);
+                InputStaging {
+                    data_uuid: data_ref.uuid,
+                    source: (!local)
+                        .then(|| data_ref.cheapest_source(server, self.topology).to_string()),
+                    tier: data_ref.storage_tier,
+                    size_bytes: data_ref.stored_size(),
+                    transfer_ms: if local {
+                        0
+                    } else {
+                        data_ref.transfer_cost(server, self.topology)
+                    },
+                }
+            })
+            .collect();
+        let (local, remote): (Vec<&InputStaging>, Vec<&InputStaging>) =
+            inputs.iter().partition(|input| input.source.is_none());
+        let local_bytes: u64 = local.iter().map(|input| input.size_bytes).sum();
+        let remote_bytes: u64 = remote.iter().map(|input| input.size_bytes).sum();
+        let total_bytes = local_bytes + remote_bytes;
+        LocalityScore {
+            server: server.to_string(),
+            score: if total_bytes == 0 {
+                1.0
+            } else {
+                local_bytes as f64 / total_bytes as f64
+            },
+            local_bytes,
+            remote_bytes,
+            staging_ms: remote
+                .iter()
+                .fold(0u64, |ms, input| ms.saturating_add(input.transfer_ms)),
+            inputs,
+        }
+    }
+
+    /// Score every server for `inputs`, best placed first
+    ///
+    /// Servers are ranked by staging time, then by the share of input
+    /// bytes they hold.
+    pub fn rank<'s>(
+        &self,
+        inputs: &[DataRef],
+        servers: impl IntoIterator<Item = &'s str>,
+    ) -> Vec<LocalityScore> {
+        let mut scores: Vec<LocalityScore> = servers
+            .into_iter()
+            .map(|server| self.score(inputs, server))
+            .collect();
+        scores.sort_by(LocalityScore::rank);
+        scores
+    }
+
+    /// Best placed server for `inputs`, if there are any servers
+    pub fn best<'s>(
+        &self,
+        inputs: &[DataRef],
+        servers: impl IntoIterator<Item = &'s str>,
+    ) -> Option<LocalityScore> {
+        self.rank(inputs, servers).into_iter().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+    use crate::topology::{Link, StaticTopology};
+
+    const GPU_1: &str = "http://gpu-1:9090";
+    const GPU_2: &str = "http://gpu-2:9090";
+    const CPU_1: &str = "http://cpu-1:9090";
+
+    fn data(location: &str, size_bytes: u64, tier: StorageTier) -> DataRef {
+        let mut data_ref = DataRef::new(
+            location.to_string(),
+            size_bytes,
+            DataType::Bytes,
+            Uuid::new_v4(),
+        );
+        data_ref.storage_tier = tier;
+        data_ref
+    }
+
+    #[test]
+    fn test_locality_oracle() {
+        // 1GB/s between the GPU servers, 100MB/s to the CPU server
+        let topology = StaticTopology::new(Link::new(100_000_000, 0.0)).with_link(
+            GPU_1,
+            GPU_2,
+            Link::new(1_000_000_000, 0.0),
+        );
+        let oracle = LocalityOracle::new(&topology);
+
+        let mut weights = data(GPU_1, 2_000_000_000, StorageTier::Dram);
+        let prompt = data(GPU_2, 100_000_000, StorageTier::Disk);
+        weights.add_replica(CPU_1);
+        let inputs = [weights.clone(), prompt.clone()];
+
+        let ranked = oracle.rank(&inputs, [CPU_1, GPU_2, GPU_1]);
+        let order: Vec<&str> = ranked.iter().map(|s| s.server.as_str()).collect();
+        assert_eq!(order, [GPU_1, CPU_1, GPU_2]);
+
+        // GPU 1 fetches the prompt from GPU 2
+        let gpu_1 = &ranked[0];
+        assert_eq!(gpu_1.local_bytes, 2_000_000_000);
+        assert_eq!(gpu_1.remote_bytes, 100_000_000);
+        assert_eq!(gpu_1.staging_ms, 100);
+        assert_eq!(gpu_1.inputs[1].source.as_deref(), Some(GPU_2));
+        assert!((gpu_1.score - 2.0 / 2.1).abs() < 1e-9);
+        // The CPU server holds a replica of the weights
+        assert_eq!(ranked[1].staging_ms, 1000);
+        assert!(ranked[1].inputs[0].source.is_none());
+        // GPU 2 fetches the weights from GPU 1, the cheaper copy
+        assert_eq!(ranked[2].inputs[0].source.as_deref(), Some(GPU_1));
+        assert_eq!(ranked[2].staging_ms, 2000);
+
+        let local = oracle.score([&weights], CPU_1);
+        assert!(local.is_local());
+        assert_eq!(local.score, 1.0);
+        assert_eq!(oracle.best(&[], [GPU_2]).unwrap().score, 1.0);
+        assert!(oracle.best(&inputs, []).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! KV-cache migration planning for LLM sessions
+//!
+//! When a session's next turn is scheduled on a server other than the one
+//! holding its KV cache, the target either receives a copy of the cache or
+//! rebuilds it by running prefill over the session's tokens again. Which is
+//! cheaper depends on the cache size, the link between the servers, and how
+//! fast the target prefills. A [`KvCacheMigrationPlan`] weighs both and
+//! records the estimates, so the scheduler can charge the chosen cost to
+//! the placement.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::LlmSession;
+use crate::topology::NetworkTopology;
+
+/// How a session's KV cache gets to a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KvCacheStrategy {
+    /// The server already holds the cache
+    Local,
+    /// Copy the cache to the server
+    Transfer,
+    /// Rebuild the cache on the server by running prefill again
+    Recompute,
+}
+
+/// Decision on moving a session's KV cache, with the cost estimates behind
+/// it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvCacheMigrationPlan {
+    /// Session being placed
+    pub session_id: Uuid,
+    /// Server the session would run on
+    pub target_server: String,
+    /// Chosen strategy
+    pub strategy: KvCacheStrategy,
+    /// Size of the cache; 0 if the session has none
+    pub cache_bytes: u64,
+    /// Tokens prefill would process to rebuild the cache
+    pub recompute_tokens: usize,
+    /// Estimated milliseconds to copy the cache; `None` if there is no
+    /// cache to copy
+    pub transfer_ms: Option<u64>,
+    /// Estimated milliseconds to rebuild the cache
+    pub recompute_ms: u64,
+}
+
+impl KvCacheMigrationPlan {
+    /// Plan getting a session's KV cache to `target_server`
+    ///
+    /// `prefill_tokens_per_sec` is the rate the target processes prompt
+    /// tokens for the session's model. Ties go to transferring, which
+    /// leaves the target's compute free.
+    pub fn new(
+        session: &LlmSession,
+        target_server: &str,
+        topology: &dyn NetworkTopology,
+        prefill_tokens_per_sec: f64,
+    ) -> Self {
+        let recompute_tokens = session.seq_length;
+        let recompute_ms = prefill_ms(recompute_tokens, prefill_tokens_per_sec);
+        let cache = session.kv_cache_ref.as_ref();
+        let transfer_ms = cache.map(|cache| cache.transfer_cost(target_server, topology));
+        let strategy = match (cache, transfer_ms) {
+            (Some(cache), _) if cache.is_local_to(target_server) => KvCacheStrategy::Local,
+            (Some(_), Some(transfer_ms)) if transfer_ms <= recompute_ms => {
+                KvCacheStrategy::Transfer
+            }
+            _ => KvCacheStrategy::Recompute,
+        };
+        Self {
+            session_id: session.session_id,
+            target_server: target_server.to_string(),
+            strategy,
+            cache_bytes: cache.map_or(0, |cache| cache.size_bytes),
+            recompute_tokens,
+            transfer_ms,
+            recompute_ms,
+        }
+    }
+
+    /// Estimated milliseconds the chosen strategy adds before the session
+    /// can run on the target
+    pub fn cost_ms(&self) -> u64 {
+        match self.strategy {
+            KvCacheStrategy::Local => 0,
+            KvCacheStrategy::Transfer => self.transfer_ms.unwrap_or(0),
+            KvCacheStrategy::Recompute => self.recompute_ms,
+        }
+    }
+}
+
+/// Milliseconds to prefill `tokens` at `tokens_per_sec`
+fn prefill_ms(tokens: usize, tokens_per_sec: f64) -> u64 {
+    if tokens == 0 {
+        return 0;
+    }
+    if tokens_per_sec <= 0.0 {
+        return u64::MAX;
+    }
+    (tokens as f64 * 1000.0 / tokens_per_sec).ceil() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{DataRef, DataType};
+    use crate::topology::{Link, StaticTopology};
+
+    fn session(cache_bytes: Option<u64>, seq_length: usize) -> LlmSession {
+        LlmSession {
+            session_id: Uuid::new_v4(),
+            model_id: "deepseek-coder".to_string(),
+            kv_cache_ref: cache_bytes.map(|size| {
+                DataRef::new(
+                    "http://gpu-1:9090".to_string(),
+                    size,
+                    DataType::KvCache {
+                        model_id: "deepseek-coder".to_string(),
+                        seq_len: seq_length,
+                    },
+                    Uuid::new_v4(),
+                )
+            }),
+            preferred_server: "http://gpu-1:9090".to_string(),
+            seq_length,
+            max_seq_length: 32_768,
+        }
+    }
+
+    #[test]
+    fn test_migration_plan() {
+        // 1GB/s between the GPU servers
+        let topology = StaticTopology::default().with_link(
+            "http://gpu-1:9090",
+            "http://gpu-2:9090",
+            Link::new(1_000_000_000, 0.0),
+        );
+
+        // 2GB cache: 2s to copy, 0.8s to prefill 8k tokens at 10k/s
+        let large = session(Some(2_000_000_000), 8_192);
+        let plan = KvCacheMigrationPlan::new(&large, "http://gpu-2:9090", &topology, 10_000.0);
+        assert_eq!(plan.strategy, KvCacheStrategy::Recompute);
+        assert_eq!(plan.transfer_ms, Some(2000));
+        assert_eq!(plan.recompute_ms, 820);
+        assert_eq!(plan.cost_ms(), 820);
+        assert!(!large.should_migrate("http://gpu-2:9090", &topology, 10_000.0));
+
+        // Slow prefill makes copying the cache worth it
+        let plan = KvCacheMigrationPlan::new(&large, "http://gpu-2:9090", &topology, 1_000.0);
+        assert_eq!(plan.strategy, KvCacheStrategy::Transfer);
+        assert_eq!(plan.cost_ms(), 2000);
+        assert!(large.should_migrate("http://gpu-2:9090", &topology, 1_000.0));
+
+        // Nothing to move to the server holding the cache
+        let plan = KvCacheMigrationPlan::new(&large, "http://gpu-1:9090", &topology, 1_000.0);
+        assert_eq!(plan.strategy, KvCacheStrategy::Local);
+        assert_eq!(plan.cost_ms(), 0);
+        assert!(!large.should_migrate("http://gpu-1:9090", &topology, 1_000.0));
+
+        // Without a cache the target can only recompute
+        let plan =
+            KvCacheMigrationPlan::new(&session(None, 100), "http://gpu-2:9090", &topology, 1_000.0);
+        assert_eq!(plan.strategy, KvCacheStrategy::Recompute);
+        assert_eq!(plan.transfer_ms, None);
+        assert_eq!(plan.recompute_ms, 100);
+    }
+}
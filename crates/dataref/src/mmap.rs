@@ -0,0 +1,131 @@
+//! Zero-copy reads of local data
+//!
+//! A task running on the same host as a [`LocalDataStore`] does not need
+//! its inputs serialized through HTTP: [`LocalDataStore::map`] maps a
+//! stored object into memory, so a multi-gigabyte tensor is paged in from
+//! the page cache as it is touched instead of being copied. Tasks in
+//! another process get the object's path instead, through
+//! [`LocalDataStore::local_path`] and a `local_file` task input.
+//!
+//! Only data stored as it is can be mapped; encrypted or compressed data
+//! has to be decoded through [`LocalDataStore::open_object`].
+
+use std::fs::File;
+use std::ops::Deref;
+
+use memmap2::Mmap;
+use uuid::Uuid;
+
+use crate::pointer::DataRef;
+use crate::store::{DataStoreError, LocalDataStore};
+
+/// Stored data mapped into memory
+///
+/// Dereferences to the payload bytes.
+#[derive(Debug)]
+pub struct MappedObject {
+    data_ref: DataRef,
+    map: Mmap,
+}
+
+impl MappedObject {
+    /// Ref of the mapped data
+    pub fn data_ref(&self) -> &DataRef {
+        &self.data_ref
+    }
+
+    /// Payload bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl Deref for MappedObject {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl AsRef<[u8]> for MappedObject {
+    fn as_ref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl LocalDataStore {
+    /// Map stored data into memory without copying it
+    ///
+    /// Encrypted or compressed data fails with
+    /// [`DataStoreError::Encoded`].
+    pub fn map(&self, uuid: &Uuid) -> Result<MappedObject, DataStoreError> {
+        let path = self.local_path(uuid)?;
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        let file = File::open(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DataStoreError::NotFound(*uuid),
+            _ => e.into(),
+        })?;
+        // SAFETY: the store never modifies object files in place; writes
+        // and deletions replace or unlink the path, which leaves existing
+        // mappings of the old file intact
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedObject { data_ref, map })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    #[test]
+    fn test_map_local_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalDataStore::open(dir.path(), "http://localhost:3000/api").unwrap();
+        let workflow_id = Uuid::new_v4();
+        let tensor = DataType::Tensor {
+            shape: vec![4],
+            dtype: crate::pointer::TensorDType::Float32,
+        };
+        let payload: Vec<u8> = (0..16).collect();
+        let stored = store
+            .put(
+                DataRef::new("client".to_string(), 0, tensor, workflow_id),
+                &payload,
+            )
+            .unwrap();
+
+        let mapped = store.map(&stored.uuid).unwrap();
+        assert_eq!(&mapped[..], payload.as_slice());
+        assert_eq!(mapped.data_ref().uuid, stored.uuid);
+
+        // Mappings survive the data being rewritten or deleted
+        store.write_at(&stored.uuid, 0, b"new").unwrap();
+        assert_eq!(&store.map(&stored.uuid).unwrap()[..3], b"new");
+        store.delete(&stored.uuid).unwrap();
+        assert_eq!(mapped.as_bytes(), payload.as_slice());
+
+        let empty = store
+            .put(
+                DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id),
+                b"",
+            )
+            .unwrap();
+        assert!(store.map(&empty.uuid).unwrap().is_empty());
+
+        let compressed = LocalDataStore::open(dir.path(), "http://localhost:3000/api")
+            .unwrap()
+            .with_compression(crate::compression::DEFAULT_COMPRESSION_LEVEL);
+        let json = compressed
+            .put(
+                DataRef::new("client".to_string(), 0, DataType::Json, workflow_id),
+                b"{}",
+            )
+            .unwrap();
+        assert!(matches!(
+            compressed.map(&json.uuid),
+            Err(DataStoreError::Encoded(_))
+        ));
+    }
+}
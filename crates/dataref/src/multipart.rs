@@ -0,0 +1,501 @@
+//! Multipart uploads
+//!
+//! A browser cannot stream a 5GB file through one request. A multipart
+//! upload splits the payload into fixed-size parts that are sent
+//! separately, in any order and in parallel, then assembled into one
+//! stored object:
+//!
+//! 1. [`MultipartUploads::initiate`] plans the parts of a payload of known
+//!    size.
+//! 2. Each part is sent to a URL presigned for it with
+//!    [`MultipartUploads::sign_part`], and staged in a file of its own by
+//!    [`MultipartUploads::put_part`], which returns the part's BLAKE3
+//!    checksum. Sending a part again replaces it.
+//! 3. [`MultipartUploads::complete`] checks that every part arrived, with
+//!    the checksums the client was given, and streams the parts in order
+//!    into a [`LocalDataStore`].
+//!
+//! Part signatures are HMAC-SHA256 over the upload ID, part number, and
+//! expiry, keyed with a secret generated when the uploads are opened, so
+//! presigned URLs need no other credentials and are only valid in this
+//! process. Uploads are kept in memory; parts staged by an earlier process
+//! are removed on open, and uploads not completed in time are discarded by
+//! [`MultipartUploads::expire`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::pointer::{format_blake3, DataRef};
+use crate::store::{ByteRange, DataStoreError, LocalDataStore, COPY_BUFFER_SIZE};
+use crate::token::{decode_hex, encode_hex};
+
+/// Default part size: 64MB
+pub const DEFAULT_PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Smallest part size: 1MB
+pub const MIN_PART_SIZE: u64 = 1024 * 1024;
+
+/// Most parts one upload may have
+pub const MAX_UPLOAD_PARTS: u64 = 10_000;
+
+/// Default time to complete an upload: 24 hours
+pub const DEFAULT_UPLOAD_TTL_SECS: i64 = 24 * 3600;
+
+/// Length of the part signing key in bytes
+const SIGNING_KEY_LEN: usize = 32;
+
+/// Multipart upload errors
+#[derive(Debug, thiserror::Error)]
+pub enum MultipartError {
+    #[error("Upload not found: {0}")]
+    NotFound(Uuid),
+
+    #[error("Upload {upload_id} has no part {part_number}")]
+    InvalidPart { upload_id: Uuid, part_number: u32 },
+
+    #[error("Part {part_number} must have {expected} bytes, got {actual}")]
+    PartSize {
+        part_number: u32,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Upload would need {0} parts, more than {MAX_UPLOAD_PARTS}")]
+    TooManyParts(u64),
+
+    #[error("Part {0} has not been uploaded")]
+    MissingPart(u32),
+
+    #[error("Part {0} does not match its checksum")]
+    PartChecksum(u32),
+
+    #[error("Invalid or expired part signature")]
+    InvalidSignature,
+
+    #[error("Random number generation failed")]
+    Random,
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Store error: {0}")]
+    Store(#[from] DataStoreError),
+}
+
+/// A part received for an upload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedPart {
+    /// Position of the part, numbered from 1
+    pub part_number: u32,
+    pub size: u64,
+    /// BLAKE3 checksum of the part's bytes
+    pub checksum: String,
+}
+
+/// A multipart upload in progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub upload_id: Uuid,
+    /// Ref the payload is stored under once complete
+    pub data_ref: DataRef,
+    /// Payload size in bytes
+    pub size_bytes: u64,
+    /// Size of every part but the last
+    pub part_size: u64,
+    pub created_at: DateTime<Utc>,
+    /// When the upload is discarded unless completed
+    pub expires_at: DateTime<Utc>,
+    /// Parts received so far, by part number
+    pub parts: BTreeMap<u32, UploadedPart>,
+}
+
+impl MultipartUpload {
+    /// Number of parts the payload is split into
+    pub fn part_count(&self) -> u32 {
+        self.size_bytes.div_ceil(self.part_size) as u32
+    }
+
+    /// Bytes of the payload a part holds, if the upload has the part
+    pub fn part_range(&self, part_number: u32) -> Option<ByteRange> {
+        if part_number == 0 || part_number > self.part_count() {
+            return None;
+        }
+        let offset = u64::from(part_number - 1) * self.part_size;
+        Some(ByteRange::new(
+            offset,
+            self.part_size.min(self.size_bytes - offset),
+        ))
+    }
+
+    /// Parts not received yet
+    pub fn missing_parts(&self) -> Vec<u32> {
+        (1..=self.part_count())
+            .filter(|part_number| !self.parts.contains_key(part_number))
+            .collect()
+    }
+
+    /// Check whether the upload has expired at `now`
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now > self.expires_at
+    }
+}
+
+/// Multipart uploads staged in a local directory
+///
+/// Cheaply cloneable; clones share the same uploads and signing key.
+#[derive(Clone)]
+pub struct MultipartUploads {
+    dir: PathBuf,
+    key: Arc<[u8; SIGNING_KEY_LEN]>,
+    ttl: Duration,
+    uploads: Arc<Mutex<HashMap<Uuid, MultipartUpload>>>,
+}
+
+impl std::fmt::Debug for MultipartUploads {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultipartUploads")
+            .field("dir", &self.dir)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MultipartUploads {
+    /// Stage uploads in `dir`, removing parts left there by an earlier
+    /// process
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, MultipartError> {
+        let dir = dir.as_ref().to_path_buf();
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        fs::create_dir_all(&dir)?;
+        let mut key = [0; SIGNING_KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| MultipartError::Random)?;
+        Ok(Self {
+            dir,
+            key: Arc::new(key),
+            ttl: Duration::seconds(DEFAULT_UPLOAD_TTL_SECS),
+            uploads: Arc::default(),
+        })
+    }
+
+    /// Discard uploads not completed within `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Start uploading a payload of `size_bytes` to be stored as
+    /// `data_ref`
+    ///
+    /// Parts are `part_size` bytes, by default [`DEFAULT_PART_SIZE`] and
+    /// at least [`MIN_PART_SIZE`], except the last.
+    pub fn initiate(
+        &self,
+        data_ref: DataRef,
+        size_bytes: u64,
+        part_size: Option<u64>,
+    ) -> Result<MultipartUpload, MultipartError> {
+        let part_size = part_size.unwrap_or(DEFAULT_PART_SIZE).max(MIN_PART_SIZE);
+        let parts = size_bytes.div_ceil(part_size);
+        if parts > MAX_UPLOAD_PARTS {
+            return Err(MultipartError::TooManyParts(parts));
+        }
+        let created_at = Utc::now();
+        let upload = MultipartUpload {
+            upload_id: Uuid::new_v4(),
+            data_ref,
+            size_bytes,
+            part_size,
+            created_at,
+            expires_at: created_at + self.ttl,
+            parts: BTreeMap::new(),
+        };
+        fs::create_dir_all(self.upload_dir(&upload.upload_id))?;
+        self.lock().insert(upload.upload_id, upload.clone());
+        Ok(upload)
+    }
+
+    /// Get an upload in progress
+    pub fn get(&self, upload_id: &Uuid) -> Option<MultipartUpload> {
+        self.lock().get(upload_id).cloned()
+    }
+
+    /// Signature authorizing one part of an upload until it expires
+    ///
+    /// Hex-encoded, for use in the part's presigned URL.
+    pub fn sign_part(&self, upload: &MultipartUpload, part_number: u32) -> String {
+        encode_hex(&self.part_mac(upload, part_number).finalize().into_bytes())
+    }
+
+    /// Check a part signature from [`sign_part`](Self::sign_part)
+    ///
+    /// Fails if the upload is unknown or expired, or the signature is for
+    /// another part.
+    pub fn verify_part(
+        &self,
+        upload_id: &Uuid,
+        part_number: u32,
+        signature: &str,
+    ) -> Result<(), MultipartError> {
+        let upload = self
+            .get(upload_id)
+            .ok_or(MultipartError::NotFound(*upload_id))?;
+        if upload.is_expired(Utc::now()) {
+            return Err(MultipartError::InvalidSignature);
+        }
+        let signature = decode_hex(signature).ok_or(MultipartError::InvalidSignature)?;
+        // Compares in constant time
+        self.part_mac(&upload, part_number)
+            .verify_slice(&signature)
+            .map_err(|_| MultipartError::InvalidSignature)
+    }
+
+    /// Stage a part read from `reader`, replacing any earlier copy of it
+    ///
+    /// The part must have exactly the size planned for it.
+    pub fn put_part(
+        &self,
+        upload_id: &Uuid,
+        part_number: u32,
+        reader: impl Read,
+    ) -> Result<UploadedPart, MultipartError> {
+        let upload = self
+            .get(upload_id)
+            .ok_or(MultipartError::NotFound(*upload_id))?;
+        let expected = upload
+            .part_range(part_number)
+            .and_then(|range| range.len)
+            .ok_or(MultipartError::InvalidPart {
+                upload_id: *upload_id,
+                part_number,
+            })?;
+
+        // Parts of one upload arrive in parallel, each into its own file
+        let path = self.part_path(upload_id, part_number);
+        let partial = path.with_extension(format!("{}.partial", Uuid::new_v4()));
+        let mut file = BufWriter::new(File::create(&partial)?);
+        let mut hasher = blake3::Hasher::new();
+        let mut size = 0u64;
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        // Read one byte past the planned size to detect oversized parts
+        let mut reader = reader.take(expected + 1);
+        let copied = loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e),
+            };
+            hasher.update(&buffer[..n]);
+            size += n as u64;
+            if let Err(e) = file.write_all(&buffer[..n]) {
+                break Err(e);
+            }
+        };
+        let flushed = copied.and_then(|_| file.flush());
+        drop(file);
+        if let Err(e) = flushed {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+        if size != expected {
+            let _ = fs::remove_file(&partial);
+            return Err(MultipartError::PartSize {
+                part_number,
+                expected,
+                actual: size,
+            });
+        }
+
+        let part = UploadedPart {
+            part_number,
+            size,
+            checksum: format_blake3(&hasher.finalize()),
+        };
+        let mut uploads = self.lock();
+        let Some(upload) = uploads.get_mut(upload_id) else {
+            // Completed or aborted while the part was being received
+            let _ = fs::remove_file(&partial);
+            return Err(MultipartError::NotFound(*upload_id));
+        };
+        fs::rename(&partial, &path)?;
+        upload.parts.insert(part_number, part.clone());
+        Ok(part)
+    }
+
+    /// Assemble the parts of an upload and store the payload in `store`
+    ///
+    /// Every part must have been uploaded. `parts` are the parts as the
+    /// client recorded them; each must match the part received, so a
+    /// client never completes an upload with parts it did not send. An
+    /// empty list skips the check. Returns the stored ref.
+    pub fn complete(
+        &self,
+        upload_id: &Uuid,
+        parts: &[UploadedPart],
+        store: &LocalDataStore,
+    ) -> Result<DataRef, MultipartError> {
+        let upload = self
+            .get(upload_id)
+            .ok_or(MultipartError::NotFound(*upload_id))?;
+        if let Some(missing) = upload.missing_parts().first() {
+            return Err(MultipartError::MissingPart(*missing));
+        }
+        for part in parts {
+            if upload.parts.get(&part.part_number) != Some(part) {
+                return Err(MultipartError::PartChecksum(part.part_number));
+            }
+        }
+
+        let mut payload: Box<dyn Read> = Box::new(io::empty());
+        for part_number in 1..=upload.part_count() {
+            let file = File::open(self.part_path(upload_id, part_number))?;
+            payload = Box::new(payload.chain(file));
+        }
+        let data_ref = store.put_stream(upload.data_ref, payload)?;
+        if self.lock().remove(upload_id).is_some() {
+            fs::remove_dir_all(self.upload_dir(upload_id))?;
+        }
+        Ok(data_ref)
+    }
+
+    /// Give up on an upload, deleting its parts
+    pub fn abort(&self, upload_id: &Uuid) -> Result<MultipartUpload, MultipartError> {
+        let upload = self
+            .lock()
+            .remove(upload_id)
+            .ok_or(MultipartError::NotFound(*upload_id))?;
+        fs::remove_dir_all(self.upload_dir(upload_id))?;
+        Ok(upload)
+    }
+
+    /// Discard uploads expired at `now`, returning them
+    pub fn expire(&self, now: DateTime<Utc>) -> Vec<MultipartUpload> {
+        let expired: Vec<MultipartUpload> = {
+            let mut uploads = self.lock();
+            let ids: Vec<Uuid> = uploads
+                .values()
+                .filter(|upload| upload.is_expired(now))
+                .map(|upload| upload.upload_id)
+                .collect();
+            ids.iter().filter_map(|id| uploads.remove(id)).collect()
+        };
+        for upload in &expired {
+            // Nothing refers to the parts any more; a failure only leaks them
+            let _ = fs::remove_dir_all(self.upload_dir(&upload.upload_id));
+        }
+        expired
+    }
+
+    fn part_mac(&self, upload: &MultipartUpload, part_number: u32) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.as_ref())
+            .expect("HMAC accepts keys of any length");
+        let payload = format!(
+            "{}:{part_number}:{}",
+            upload.upload_id,
+            upload.expires_at.timestamp()
+        );
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    fn upload_dir(&self, upload_id: &Uuid) -> PathBuf {
+        self.dir.join(upload_id.to_string())
+    }
+
+    fn part_path(&self, upload_id: &Uuid, part_number: u32) -> PathBuf {
+        self.upload_dir(upload_id).join(part_number.to_string())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, MultipartUpload>> {
+        self.uploads.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{blake3_checksum, DataType};
+
+    #[test]
+    fn test_multipart_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalDataStore::open(dir.path(), "http://localhost:3000/api").unwrap();
+        let uploads = MultipartUploads::open(dir.path().join("uploads")).unwrap();
+        let payload: Vec<u8> = (0..(2 * MIN_PART_SIZE + 100))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let data_ref = DataRef::new("client".to_string(), 0, DataType::Bytes, Uuid::new_v4());
+        let upload = uploads
+            .initiate(data_ref.clone(), payload.len() as u64, Some(1))
+            .unwrap();
+        assert_eq!(upload.part_size, MIN_PART_SIZE);
+        assert_eq!(upload.part_count(), 3);
+        assert_eq!(upload.part_range(3).unwrap().len, Some(100));
+        assert!(upload.part_range(4).is_none());
+
+        // Signatures are bound to the part
+        let id = upload.upload_id;
+        let signature = uploads.sign_part(&upload, 2);
+        uploads.verify_part(&id, 2, &signature).unwrap();
+        assert!(uploads.verify_part(&id, 1, &signature).is_err());
+        assert!(uploads.verify_part(&id, 2, "00").is_err());
+
+        // Parts arrive out of order and must have their planned size
+        let part = |n: usize| {
+            let start = (n - 1) * MIN_PART_SIZE as usize;
+            &payload[start..(start + MIN_PART_SIZE as usize).min(payload.len())]
+        };
+        let third = uploads.put_part(&id, 3, part(3)).unwrap();
+        assert_eq!(third.checksum, blake3_checksum(part(3)));
+        assert!(matches!(
+            uploads.put_part(&id, 1, part(3)),
+            Err(MultipartError::PartSize { .. })
+        ));
+        uploads.put_part(&id, 1, part(1)).unwrap();
+        assert!(matches!(
+            uploads.complete(&id, &[], &store),
+            Err(MultipartError::MissingPart(2))
+        ));
+        let second = uploads.put_part(&id, 2, part(2)).unwrap();
+        let forged = UploadedPart {
+            checksum: third.checksum.clone(),
+            ..second.clone()
+        };
+        assert!(matches!(
+            uploads.complete(&id, &[forged], &store),
+            Err(MultipartError::PartChecksum(2))
+        ));
+
+        let stored = uploads.complete(&id, &[second, third], &store).unwrap();
+        assert_eq!(stored.uuid, data_ref.uuid);
+        assert_eq!(stored.size_bytes, payload.len() as u64);
+        assert_eq!(store.get(&stored.uuid).unwrap(), payload);
+        assert!(uploads.get(&id).is_none());
+        assert!(!dir.path().join("uploads").join(id.to_string()).exists());
+
+        // Uncompleted uploads expire with their parts
+        let stale = uploads.initiate(data_ref, 10, None).unwrap();
+        uploads
+            .put_part(&stale.upload_id, 1, &b"0123456789"[..])
+            .unwrap();
+        assert!(uploads.expire(Utc::now()).is_empty());
+        let expired = uploads.expire(stale.expires_at + Duration::seconds(1));
+        assert_eq!(expired.len(), 1);
+        assert!(uploads.get(&stale.upload_id).is_none());
+    }
+}
@@ -0,0 +1,165 @@
+//! Object storage for large data
+//!
+//! Servers keep data in VRAM, DRAM, or local disk, but artifacts that
+//! outlive a workflow or are too large for any one server belong in object
+//! storage. [`ObjectStoreBackend`] is the interface to such a store; with
+//! the `s3` feature, `S3ObjectStore` implements it for S3 and
+//! S3-compatible stores such as MinIO. Stored data becomes a `Disk`-tier
+//! [`DataRef`] located at the object's URL, e.g. `s3://bucket/key`, and can
+//! be handed to clients as a presigned download URL instead of being
+//! proxied.
+
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::pointer::{DataRef, StorageTier};
+
+/// Object storage errors
+#[derive(Debug, thiserror::Error)]
+pub enum ObjectStoreError {
+    #[error("Object not found: {0}")]
+    NotFound(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Object store error: {0}")]
+    Backend(String),
+}
+
+/// An object store holding data by key
+///
+/// Keys are relative to the backend's bucket and prefix.
+pub trait ObjectStoreBackend: Send + Sync {
+    /// URL of an object, recorded as the location of data stored there
+    fn url(&self, key: &str) -> String;
+
+    /// Store a payload, replacing any object with the same key
+    fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+    ) -> impl Future<Output = Result<(), ObjectStoreError>> + Send;
+
+    /// Store a file, uploading it in parts if it is large
+    fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+    ) -> impl Future<Output = Result<(), ObjectStoreError>> + Send;
+
+    /// Load an object
+    fn get(&self, key: &str) -> impl Future<Output = Result<Vec<u8>, ObjectStoreError>> + Send;
+
+    /// Delete an object; deleting a missing object succeeds
+    fn delete(&self, key: &str) -> impl Future<Output = Result<(), ObjectStoreError>> + Send;
+
+    /// URL anyone can download an object from until `expires_in` passes
+    fn presigned_get(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> impl Future<Output = Result<String, ObjectStoreError>> + Send;
+}
+
+/// Key data is stored under: grouped by workflow, named by UUID
+pub fn object_key(data_ref: &DataRef) -> String {
+    format!("{}/{}", data_ref.workflow_id, data_ref.uuid)
+}
+
+/// Upload a file as the payload of `data_ref`
+///
+/// Returns the ref located in the object store, on the disk tier, with the
+/// file's size.
+pub async fn put_data_file<B: ObjectStoreBackend>(
+    backend: &B,
+    mut data_ref: DataRef,
+    path: &Path,
+) -> Result<DataRef, ObjectStoreError> {
+    let key = object_key(&data_ref);
+    backend.put_file(&key, path).await?;
+    data_ref.size_bytes = std::fs::metadata(path)?.len();
+    data_ref.location = backend.url(&key);
+    data_ref.storage_tier = StorageTier::Disk;
+    Ok(data_ref)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Backend keeping objects in memory
+    #[derive(Default)]
+    struct MemoryStore {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl ObjectStoreBackend for MemoryStore {
+        fn url(&self, key: &str) -> String {
+            format!("mem://bucket/{key}")
+        }
+
+        async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), ObjectStoreError> {
+            self.objects.lock().unwrap().insert(key.to_string(), data);
+            Ok(())
+        }
+
+        async fn put_file(&self, key: &str, path: &Path) -> Result<(), ObjectStoreError> {
+            self.put(key, std::fs::read(path)?).await
+        }
+
+        async fn get(&self, key: &str) -> Result<Vec<u8>, ObjectStoreError> {
+            self.objects
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| ObjectStoreError::NotFound(key.to_string()))
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), ObjectStoreError> {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        async fn presigned_get(
+            &self,
+            key: &str,
+            expires_in: Duration,
+        ) -> Result<String, ObjectStoreError> {
+            Ok(format!(
+                "{}?expires={}",
+                self.url(key),
+                expires_in.as_secs()
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_data_file() {
+        let path = std::env::temp_dir().join(format!("swarmx-object-{}", Uuid::new_v4()));
+        std::fs::write(&path, b"checkpoint").unwrap();
+        let store = MemoryStore::default();
+        let data = DataRef::new(
+            "gpu-1".to_string(),
+            0,
+            DataType::File {
+                mime_type: "application/octet-stream".to_string(),
+            },
+            Uuid::new_v4(),
+        );
+        let key = object_key(&data);
+
+        let stored = put_data_file(&store, data, &path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(stored.location, format!("mem://bucket/{key}"));
+        assert_eq!(stored.size_bytes, 10);
+        assert_eq!(stored.storage_tier, StorageTier::Disk);
+        assert_eq!(store.get(&key).await.unwrap(), b"checkpoint");
+    }
+}
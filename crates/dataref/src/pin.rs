@@ -0,0 +1,63 @@
+//! Data pinning
+//!
+//! Pinned data is exempt from automatic cleanup: garbage collection, TTL
+//! expiry, and workflow retention in the [registry](crate::registry), and
+//! offloading in the [tier manager](crate::tier). Data is pinned on its own
+//! or together with everything its workflow produces, e.g. to keep a
+//! training run's checkpoints. Pins last until removed; unpinned data is
+//! collected as usual on the next pass.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::DataRef;
+
+/// Data and workflows exempt from automatic cleanup
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinSet {
+    /// Pinned data, by UUID
+    pub data: HashSet<Uuid>,
+    /// Workflows whose data is all pinned
+    pub workflows: HashSet<Uuid>,
+}
+
+impl PinSet {
+    /// Create an empty pin set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin one piece of data
+    pub fn pin_data(&mut self, uuid: Uuid) {
+        self.data.insert(uuid);
+    }
+
+    /// Unpin one piece of data; returns `false` if it was not pinned
+    ///
+    /// Data of a pinned workflow stays pinned through the workflow.
+    pub fn unpin_data(&mut self, uuid: &Uuid) -> bool {
+        self.data.remove(uuid)
+    }
+
+    /// Pin all data of a workflow, including data it produces later
+    pub fn pin_workflow(&mut self, workflow_id: Uuid) {
+        self.workflows.insert(workflow_id);
+    }
+
+    /// Unpin a workflow; returns `false` if it was not pinned
+    pub fn unpin_workflow(&mut self, workflow_id: &Uuid) -> bool {
+        self.workflows.remove(workflow_id)
+    }
+
+    /// Check if data is pinned, on its own or through its workflow
+    pub fn covers(&self, data_ref: &DataRef) -> bool {
+        self.data.contains(&data_ref.uuid) || self.workflows.contains(&data_ref.workflow_id)
+    }
+
+    /// Check if nothing is pinned
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty() && self.workflows.is_empty()
+    }
+}
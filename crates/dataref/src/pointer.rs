@@ -3,12 +3,26 @@
 //! DataRef is the core abstraction for data management in SwarmX-UI,
 //! inspired by PGAS (Partitioned Global Address Space) systems.
 
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-/// Storage tier for data placement
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+use crate::compression::Compression;
+use crate::inline::InlinePolicy;
+use crate::migration::{KvCacheMigrationPlan, KvCacheStrategy};
+use crate::topology::NetworkTopology;
+
+/// Prefix of SHA-256 checksums
+const SHA256_PREFIX: &str = "sha256:";
+
+/// Prefix of BLAKE3 checksums, which double as content addresses
+pub(crate) const BLAKE3_PREFIX: &str = "blake3:";
+
+/// Storage tier for data placement, ordered fastest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum StorageTier {
     /// GPU Video RAM - fastest, most limited
@@ -25,8 +39,28 @@ impl Default for StorageTier {
     }
 }
 
+impl StorageTier {
+    /// Get the tier name, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StorageTier::Vram => "vram",
+            StorageTier::Dram => "dram",
+            StorageTier::Disk => "disk",
+        }
+    }
+
+    /// Next slower tier, which data is offloaded to under pressure
+    pub fn lower(self) -> Option<StorageTier> {
+        match self {
+            StorageTier::Vram => Some(StorageTier::Dram),
+            StorageTier::Dram => Some(StorageTier::Disk),
+            StorageTier::Disk => None,
+        }
+    }
+}
+
 /// Tensor data type specification
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TensorDType {
     Float16,
@@ -42,7 +76,7 @@ pub enum TensorDType {
 }
 
 /// Data type enumeration for DataRef
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum DataType {
     /// Tensor data with shape and element type
@@ -57,6 +91,31 @@ pub enum DataType {
     File { mime_type: String },
 }
 
+impl DataType {
+    /// Get the type name, as used in the serialized `type` tag
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataType::Tensor { .. } => "tensor",
+            DataType::Json => "json",
+            DataType::Bytes => "bytes",
+            DataType::KvCache { .. } => "kv_cache",
+            DataType::File { .. } => "file",
+        }
+    }
+
+    /// Tier new data of this type is placed in
+    ///
+    /// Tensors and KV caches are produced and consumed on the GPU, files
+    /// start out on disk, and everything else is kept in memory.
+    pub fn default_tier(&self) -> StorageTier {
+        match self {
+            DataType::Tensor { .. } | DataType::KvCache { .. } => StorageTier::Vram,
+            DataType::File { .. } => StorageTier::Disk,
+            DataType::Json | DataType::Bytes => StorageTier::Dram,
+        }
+    }
+}
+
 /// Global data reference - the core abstraction for distributed data
 ///
 /// DataRef represents an immutable reference to data stored somewhere
@@ -70,6 +129,9 @@ pub struct DataRef {
     pub uuid: Uuid,
     /// Current primary location (server address)
     pub location: String,
+    /// Other servers holding a copy, see [`add_replica`](Self::add_replica)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replicas: Vec<String>,
     /// Size hint for scheduling decisions
     pub size_bytes: u64,
     /// Type tag for the data
@@ -82,22 +144,80 @@ pub struct DataRef {
     pub workflow_id: Uuid,
     /// Optional checksum for integrity verification
     pub checksum: Option<String>,
+    /// When the data is deleted, however it is referenced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Data this was derived from, see [`derived_from`](Self::derived_from)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<Uuid>,
+    /// Node that produced the data; `None` for external inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub produced_by: Option<Uuid>,
+    /// Workflow data key the stored bytes are encrypted with, see
+    /// [`encryption`](crate::encryption)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_key: Option<Uuid>,
+    /// Key/value tags for finding the data, e.g. `stage=final`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Codec the stored bytes are compressed with, see
+    /// [`compression`](crate::compression)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Size at rest of compressed data; `size_bytes` stays the logical size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_bytes: Option<u64>,
+    /// Data whose stored object this data shares until it is written, see
+    /// [`derive`](Self::derive)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_from: Option<Uuid>,
 }
 
 impl DataRef {
     /// Create a new DataRef
+    ///
+    /// The data is placed in its type's [default tier](DataType::default_tier)
+    /// and has no checksum until one is attached with
+    /// [`with_checksum_of`](Self::with_checksum_of).
     pub fn new(
         location: String,
         size_bytes: u64,
         dtype: DataType,
         workflow_id: Uuid,
     ) -> Self {
-        todo!("Implement DataRef::new")
+        Self {
+            uuid: Uuid::new_v4(),
+            location,
+            replicas: Vec::new(),
+            size_bytes,
+            storage_tier: dtype.default_tier(),
+            dtype,
+            created_at: Utc::now(),
+            workflow_id,
+            checksum: None,
+            expires_at: None,
+            parents: Vec::new(),
+            produced_by: None,
+            encryption_key: None,
+            tags: BTreeMap::new(),
+            compression: None,
+            stored_bytes: None,
+            shared_from: None,
+        }
     }
 
     /// Create a DataRef for inline JSON data
+    ///
+    /// Size and checksum are computed from the compact JSON encoding.
     pub fn json(location: String, workflow_id: Uuid, data: &serde_json::Value) -> Self {
-        todo!("Implement DataRef::json")
+        let bytes = data.to_string().into_bytes();
+        Self::new(location, bytes.len() as u64, DataType::Json, workflow_id)
+            .with_checksum_of(&bytes)
+    }
+
+    /// Create a DataRef for raw bytes, with their checksum
+    pub fn bytes(location: String, workflow_id: Uuid, data: &[u8]) -> Self {
+        Self::new(location, data.len() as u64, DataType::Bytes, workflow_id).with_checksum_of(data)
     }
 
     /// Create a DataRef for a file
@@ -107,7 +227,12 @@ impl DataRef {
         size_bytes: u64,
         mime_type: String,
     ) -> Self {
-        todo!("Implement DataRef::file")
+        Self::new(
+            location,
+            size_bytes,
+            DataType::File { mime_type },
+            workflow_id,
+        )
     }
 
     /// Create a DataRef for tensor data
@@ -118,25 +243,176 @@ impl DataRef {
         shape: Vec<usize>,
         dtype: TensorDType,
     ) -> Self {
-        todo!("Implement DataRef::tensor")
+        Self::new(
+            location,
+            size_bytes,
+            DataType::Tensor { shape, dtype },
+            workflow_id,
+        )
+    }
+
+    /// Attach the SHA-256 checksum of the payload
+    pub fn with_checksum_of(mut self, payload: &[u8]) -> Self {
+        self.checksum = Some(sha256_checksum(payload));
+        self
+    }
+
+    /// Attach the BLAKE3 checksum of the payload, making the data
+    /// content-addressed
+    pub fn with_content_address_of(mut self, payload: &[u8]) -> Self {
+        self.checksum = Some(blake3_checksum(payload));
+        self
+    }
+
+    /// Content address of the data: its checksum, if it is a BLAKE3 one
+    ///
+    /// Data with the same content address has identical bytes, so one
+    /// stored object can back all of it. Encrypted data has none, since
+    /// its stored bytes differ per workflow key.
+    pub fn content_address(&self) -> Option<&str> {
+        self.checksum
+            .as_deref()
+            .filter(|checksum| checksum.starts_with(BLAKE3_PREFIX))
+            .filter(|_| self.encryption_key.is_none())
+    }
+
+    /// Set a tag, replacing any earlier value for the key
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Value of a tag
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    /// Tags as `key=value` labels, as carried by data events
+    pub fn tag_labels(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+
+    /// Check a payload against the recorded checksum
+    ///
+    /// Passes if no checksum was recorded.
+    pub fn verify_checksum(&self, payload: &[u8]) -> Result<(), DataRefError> {
+        let Some(checksum) = &self.checksum else {
+            return Ok(());
+        };
+        let actual = if checksum.starts_with(BLAKE3_PREFIX) {
+            blake3_checksum(payload)
+        } else {
+            sha256_checksum(payload)
+        };
+        if *checksum == actual {
+            Ok(())
+        } else {
+            Err(DataRefError::ChecksumMismatch)
+        }
+    }
+
+    /// Delete the data `ttl` after it was created, even if still referenced
+    pub fn with_ttl(mut self, ttl: chrono::Duration) -> Self {
+        self.expires_at = Some(self.created_at + ttl);
+        self
+    }
+
+    /// Record the node that produced the data and the inputs it read
+    pub fn derived_from(mut self, node_id: Uuid, parents: impl IntoIterator<Item = Uuid>) -> Self {
+        self.produced_by = Some(node_id);
+        self.parents = parents.into_iter().collect();
+        self
+    }
+
+    /// Create a new logical ref to the same bytes, copy-on-write
+    ///
+    /// The derived data has its own UUID and lists this data as its parent,
+    /// but shares its stored object until a store writes to it (see
+    /// [`LocalDataStore::write_at`](crate::store::LocalDataStore::write_at)),
+    /// so an agent loop tweaking a large context does not copy it each turn.
+    /// Replicas hold copies of this data's object, not the derived data's,
+    /// so the derived ref starts out at the primary location only.
+    pub fn derive(&self) -> DataRef {
+        DataRef {
+            uuid: Uuid::new_v4(),
+            replicas: Vec::new(),
+            created_at: Utc::now(),
+            expires_at: None,
+            parents: vec![self.uuid],
+            produced_by: None,
+            shared_from: Some(self.storage_uuid()),
+            ..self.clone()
+        }
+    }
+
+    /// UUID of the stored object holding the data: its own, or the one it
+    /// [shares](Self::derive)
+    pub fn storage_uuid(&self) -> Uuid {
+        self.shared_from.unwrap_or(self.uuid)
+    }
+
+    /// Check if the data's TTL has passed
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
     }
 
     /// Check if data is considered "small" (can be inlined in messages)
-    /// Default threshold: 64KB
+    /// under the [default policy](InlinePolicy::default)
     pub fn is_inline_eligible(&self) -> bool {
-        todo!("Implement inline eligibility check")
+        InlinePolicy::default().allows(self)
     }
 
     /// Estimate transfer cost to a target server
-    /// Returns estimated milliseconds for transfer
-    pub fn transfer_cost(&self, target: &str) -> u64 {
-        todo!("Implement transfer cost estimation")
+    /// Returns estimated milliseconds for transfer from the
+    /// [cheapest source](Self::cheapest_source); 0 if already there
+    pub fn transfer_cost(&self, target: &str, topology: &dyn NetworkTopology) -> u64 {
+        let source = self.cheapest_source(target, topology);
+        topology.transfer_ms(source, target, self.stored_size(), self.storage_tier)
+    }
+
+    /// Bytes the data occupies at rest and on the wire: the compressed
+    /// size if it is compressed, else its logical size
+    pub fn stored_size(&self) -> u64 {
+        self.stored_bytes.unwrap_or(self.size_bytes)
     }
 
-    /// Check if this DataRef is on the same server as the target
+    /// Check if the target server holds this data, as primary or replica
     pub fn is_local_to(&self, server: &str) -> bool {
-        self.location == server
+        self.locations().any(|location| location == server)
+    }
+}
+
+/// SHA-256 checksum of a payload, as stored in [`DataRef::checksum`]
+pub fn sha256_checksum(payload: &[u8]) -> String {
+    format_sha256(&Sha256::digest(payload))
+}
+
+/// BLAKE3 checksum of a payload, as stored in [`DataRef::checksum`] of
+/// content-addressed data
+pub fn blake3_checksum(payload: &[u8]) -> String {
+    format_blake3(&blake3::hash(payload))
+}
+
+/// Format a SHA-256 digest as a [`DataRef::checksum`]
+pub(crate) fn format_sha256(digest: &[u8]) -> String {
+    format_checksum(SHA256_PREFIX, digest)
+}
+
+/// Format a BLAKE3 hash as a [`DataRef::checksum`]
+pub(crate) fn format_blake3(hash: &blake3::Hash) -> String {
+    format_checksum(BLAKE3_PREFIX, hash.as_bytes())
+}
+
+fn format_checksum(prefix: &str, digest: &[u8]) -> String {
+    let mut checksum = String::with_capacity(prefix.len() + digest.len() * 2);
+    checksum.push_str(prefix);
+    for byte in digest {
+        checksum.push_str(&format!("{byte:02x}"));
     }
+    checksum
 }
 
 /// LLM Session with KV cache affinity
@@ -162,17 +438,50 @@ pub struct LlmSession {
 impl LlmSession {
     /// Create a new LLM session
     pub fn new(model_id: String, preferred_server: String, max_seq_length: usize) -> Self {
-        todo!("Implement LlmSession::new")
+        Self {
+            session_id: Uuid::new_v4(),
+            model_id,
+            kv_cache_ref: None,
+            preferred_server,
+            seq_length: 0,
+            max_seq_length,
+        }
     }
 
     /// Update the KV cache reference after generation
+    ///
+    /// The session follows its cache: the server holding the new cache
+    /// becomes the preferred server.
     pub fn update_kv_cache(&mut self, kv_cache_ref: DataRef, new_seq_length: usize) {
-        todo!("Implement KV cache update")
+        self.preferred_server = kv_cache_ref.location.clone();
+        self.kv_cache_ref = Some(kv_cache_ref);
+        self.seq_length = new_seq_length;
+    }
+
+    /// Determine if the KV cache should be copied to `target_server`
+    ///
+    /// True when the target lacks the cache and copying it is cheaper than
+    /// recomputing it at `prefill_tokens_per_sec`; see
+    /// [`KvCacheMigrationPlan`] for the estimates.
+    pub fn should_migrate(
+        &self,
+        target_server: &str,
+        topology: &dyn NetworkTopology,
+        prefill_tokens_per_sec: f64,
+    ) -> bool {
+        self.plan_migration(target_server, topology, prefill_tokens_per_sec)
+            .strategy
+            == KvCacheStrategy::Transfer
     }
 
-    /// Determine if session should migrate to a different server
-    pub fn should_migrate(&self, target_server: &str) -> bool {
-        todo!("Determine if session should migrate")
+    /// Plan getting the KV cache to `target_server`
+    pub fn plan_migration(
+        &self,
+        target_server: &str,
+        topology: &dyn NetworkTopology,
+        prefill_tokens_per_sec: f64,
+    ) -> KvCacheMigrationPlan {
+        KvCacheMigrationPlan::new(self, target_server, topology, prefill_tokens_per_sec)
     }
 
     /// Check if the session can accept more tokens
@@ -198,6 +507,26 @@ pub enum DataRefError {
 
     #[error("Checksum mismatch")]
     ChecksumMismatch,
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("LLM session not found: {0}")]
+    SessionNotFound(Uuid),
+
+    #[error("Read lease not found or expired: {0}")]
+    LeaseNotFound(Uuid),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] swarmx_events::WalError),
+
+    #[error("Storage quota of {scope} exceeded: {used} + {requested} bytes over {limit}")]
+    QuotaExceeded {
+        scope: String,
+        used: u64,
+        requested: u64,
+        limit: u64,
+    },
 }
 
 #[cfg(test)]
@@ -214,15 +543,61 @@ mod tests {
         let data_ref = DataRef {
             uuid: Uuid::new_v4(),
             location: "server-a".to_string(),
+            replicas: Vec::new(),
             size_bytes: 1024,
             dtype: DataType::Json,
             storage_tier: StorageTier::Dram,
             created_at: Utc::now(),
             workflow_id: Uuid::new_v4(),
             checksum: None,
+            expires_at: None,
+            parents: Vec::new(),
+            produced_by: None,
+            encryption_key: None,
+            tags: BTreeMap::new(),
+            compression: None,
+            stored_bytes: None,
+            shared_from: None,
         };
 
         assert!(data_ref.is_local_to("server-a"));
         assert!(!data_ref.is_local_to("server-b"));
     }
+
+    #[test]
+    fn test_constructors() {
+        let workflow_id = Uuid::new_v4();
+        let data = serde_json::json!({ "prompt": "hello" });
+        let json = DataRef::json("server-a".to_string(), workflow_id, &data);
+        assert_eq!(json.size_bytes, data.to_string().len() as u64);
+        assert_eq!(json.storage_tier, StorageTier::Dram);
+        assert!(json.verify_checksum(data.to_string().as_bytes()).is_ok());
+        assert!(matches!(
+            json.verify_checksum(b"{}"),
+            Err(DataRefError::ChecksumMismatch)
+        ));
+
+        let file = DataRef::file(
+            "server-a".to_string(),
+            workflow_id,
+            4096,
+            "image/png".to_string(),
+        );
+        assert_eq!(file.storage_tier, StorageTier::Disk);
+        assert!(file.checksum.is_none());
+        let kv = DataRef::new(
+            "server-a".to_string(),
+            1 << 20,
+            DataType::KvCache {
+                model_id: "deepseek-coder".to_string(),
+                seq_len: 512,
+            },
+            workflow_id,
+        );
+        assert_eq!(kv.storage_tier, StorageTier::Vram);
+        assert_eq!(
+            sha256_checksum(b""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
 }
@@ -0,0 +1,294 @@
+//! Input prefetching
+//!
+//! A node scheduled onto a server that does not hold its inputs would
+//! otherwise start by fetching them. Instead, as soon as the scheduler
+//! decides, each remote input gets a [`PrefetchRequest`] and the data layer
+//! copies it to the target server while the node is still queued; the copy
+//! is an ordinary replica, announced with a `DataReplicated` event.
+//!
+//! [`Prefetcher`] tracks the requests in flight. It follows the event
+//! stream through [`Prefetcher::apply`] and, once both the copy has arrived
+//! and the node has started, returns a `DataPrefetched` event recording how
+//! much of the transfer overlapped the node's queue time.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::pointer::DataRef;
+use crate::topology::NetworkTopology;
+
+/// Hint to copy a node's input to the server it was scheduled on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefetchRequest {
+    pub workflow_id: Uuid,
+    /// Node that reads the data
+    pub node_id: Uuid,
+    pub data_ref: DataRef,
+    /// Server to copy from, the cheapest holding a copy
+    pub from_server: String,
+    /// Server the node was scheduled on
+    pub to_server: String,
+    pub requested_at: DateTime<Utc>,
+}
+
+impl PrefetchRequest {
+    /// Requests for every input not already held by `target`
+    pub fn for_inputs<'a>(
+        workflow_id: Uuid,
+        node_id: Uuid,
+        target: &str,
+        inputs: impl IntoIterator<Item = &'a DataRef>,
+        topology: &dyn NetworkTopology,
+    ) -> Vec<PrefetchRequest> {
+        let requested_at = Utc::now();
+        inputs
+            .into_iter()
+            .filter(|data_ref| !data_ref.is_local_to(target))
+            .map(|data_ref| PrefetchRequest {
+                workflow_id,
+                node_id,
+                from_server: data_ref.cheapest_source(target, topology).to_string(),
+                to_server: target.to_string(),
+                data_ref: data_ref.clone(),
+                requested_at,
+            })
+            .collect()
+    }
+
+    /// Event recording that the prefetch started
+    pub fn started_event(&self) -> Event {
+        Event::DataPrefetchStarted {
+            data_uuid: self.data_ref.uuid,
+            workflow_id: self.workflow_id,
+            node_id: self.node_id,
+            from_server: self.from_server.clone(),
+            to_server: self.to_server.clone(),
+            timestamp: self.requested_at,
+        }
+    }
+}
+
+/// A prefetch in progress
+#[derive(Debug, Clone)]
+struct InFlight {
+    request: PrefetchRequest,
+    arrived_at: Option<DateTime<Utc>>,
+    node_started_at: Option<DateTime<Utc>>,
+}
+
+impl InFlight {
+    /// Event measuring the prefetch, once the copy arrived and the node
+    /// started
+    fn finished_event(&self) -> Option<Event> {
+        let (arrived_at, started_at) = (self.arrived_at?, self.node_started_at?);
+        let requested_at = self.request.requested_at;
+        let elapsed_ms = |until: DateTime<Utc>| (until - requested_at).num_milliseconds().max(0);
+        Some(Event::DataPrefetched {
+            data_uuid: self.request.data_ref.uuid,
+            workflow_id: self.request.workflow_id,
+            node_id: self.request.node_id,
+            to_server: self.request.to_server.clone(),
+            duration_ms: elapsed_ms(arrived_at) as u64,
+            overlap_ms: elapsed_ms(arrived_at.min(started_at)) as u64,
+            timestamp: arrived_at.max(started_at),
+        })
+    }
+}
+
+/// Tracks prefetches from request until their node starts
+#[derive(Debug, Clone, Default)]
+pub struct Prefetcher {
+    /// Prefetches by data and target server
+    in_flight: HashMap<(Uuid, String), InFlight>,
+}
+
+impl Prefetcher {
+    /// Create a prefetcher with nothing in flight
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a prefetch
+    ///
+    /// Returns `false` if the same data is already being copied to the same
+    /// server, in which case the caller need not start another transfer.
+    pub fn request(&mut self, request: PrefetchRequest) -> bool {
+        let key = (request.data_ref.uuid, request.to_server.clone());
+        if self.in_flight.contains_key(&key) {
+            return false;
+        }
+        self.in_flight.insert(
+            key,
+            InFlight {
+                request,
+                arrived_at: None,
+                node_started_at: None,
+            },
+        );
+        true
+    }
+
+    /// Drop a prefetch whose transfer failed; the node fetches the data
+    /// itself when it starts
+    pub fn fail(&mut self, data_uuid: Uuid, to_server: &str) -> Option<PrefetchRequest> {
+        self.in_flight
+            .remove(&(data_uuid, to_server.to_string()))
+            .map(|in_flight| in_flight.request)
+    }
+
+    /// Get the number of prefetches in flight
+    pub fn len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Check if nothing is in flight
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Record copies arriving and nodes starting
+    ///
+    /// Returns a `DataPrefetched` event for every prefetch that is now
+    /// finished. Nodes that fail or are cancelled before starting drop
+    /// their prefetches unmeasured.
+    pub fn apply(&mut self, event: &Event) -> Vec<Event> {
+        match event {
+            Event::DataReplicated {
+                data_uuid,
+                to_server,
+                timestamp,
+                ..
+            } => {
+                let key = (*data_uuid, to_server.clone());
+                match self.in_flight.get_mut(&key) {
+                    Some(in_flight) => {
+                        in_flight.arrived_at = Some(*timestamp);
+                        self.finish(|k, _| *k == key)
+                    }
+                    None => Vec::new(),
+                }
+            }
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp,
+                ..
+            } => {
+                let started = |in_flight: &InFlight| {
+                    in_flight.request.workflow_id == *workflow_id
+                        && in_flight.request.node_id == *node_id
+                };
+                for in_flight in self.in_flight.values_mut().filter(|f| started(f)) {
+                    in_flight.node_started_at = Some(*timestamp);
+                }
+                self.finish(|_, in_flight| started(in_flight))
+            }
+            Event::NodeFailed {
+                workflow_id,
+                node_id,
+                ..
+            } => {
+                self.in_flight.retain(|_, in_flight| {
+                    in_flight.request.workflow_id != *workflow_id
+                        || in_flight.request.node_id != *node_id
+                });
+                Vec::new()
+            }
+            Event::WorkflowCompleted { workflow_id, .. }
+            | Event::WorkflowFailed { workflow_id, .. }
+            | Event::WorkflowCancelled { workflow_id, .. } => {
+                self.in_flight
+                    .retain(|_, in_flight| in_flight.request.workflow_id != *workflow_id);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Remove finished prefetches among those selected, returning their
+    /// events
+    fn finish(&mut self, selected: impl Fn(&(Uuid, String), &InFlight) -> bool) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.in_flight.retain(|key, in_flight| {
+            if !selected(key, in_flight) {
+                return true;
+            }
+            match in_flight.finished_event() {
+                Some(event) => {
+                    events.push(event);
+                    false
+                }
+                None => true,
+            }
+        });
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+    use crate::topology::{Link, StaticTopology};
+    use chrono::Duration;
+
+    #[test]
+    fn test_prefetch_overlap() {
+        let workflow_id = Uuid::new_v4();
+        let node_id = Uuid::new_v4();
+        let remote = DataRef::new(
+            "server-a".to_string(),
+            1 << 30,
+            DataType::Bytes,
+            workflow_id,
+        );
+        let local = DataRef::new("server-b".to_string(), 1024, DataType::Bytes, workflow_id);
+        let topology = StaticTopology::new(Link::gigabit());
+
+        let requests = PrefetchRequest::for_inputs(
+            workflow_id,
+            node_id,
+            "server-b",
+            [&remote, &local],
+            &topology,
+        );
+        assert_eq!(requests.len(), 1);
+        let request = requests.into_iter().next().unwrap();
+        assert_eq!(request.from_server, "server-a");
+        let requested_at = request.requested_at;
+
+        let mut prefetcher = Prefetcher::new();
+        assert!(prefetcher.request(request.clone()));
+        assert!(!prefetcher.request(request.clone()));
+
+        // The copy arrives after 8s; the node started after 5s in the queue
+        let node_started = Event::NodeStarted {
+            workflow_id,
+            node_id,
+            timestamp: requested_at + Duration::seconds(5),
+        };
+        assert!(prefetcher.apply(&node_started).is_empty());
+        let arrived = Event::DataReplicated {
+            data_uuid: remote.uuid,
+            from_server: "server-a".to_string(),
+            to_server: "server-b".to_string(),
+            duration_ms: 8000,
+            timestamp: requested_at + Duration::seconds(8),
+        };
+        let events = prefetcher.apply(&arrived);
+        assert!(matches!(
+            events.as_slice(),
+            [Event::DataPrefetched {
+                duration_ms: 8000,
+                overlap_ms: 5000,
+                ..
+            }]
+        ));
+        assert!(prefetcher.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! Metadata queries over DataRefs
+//!
+//! A [`DataQuery`] selects data by workflow, type, tags, producer, and
+//! creation time, so data such as "the final report of last night's run"
+//! can be found without knowing its UUID. Queries are built in code or
+//! parsed from `key=value` pairs, e.g. URL query parameters:
+//!
+//! ```text
+//! workflow=<uuid>&dtype=file&tag.stage=final&created_after=2026-10-15T00:00:00Z&limit=1
+//! ```
+//!
+//! Matches are returned newest first.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::{DataRef, DataRefError};
+
+/// Prefix of query keys that match a tag
+const TAG_KEY_PREFIX: &str = "tag.";
+
+/// Criteria data must meet; unset criteria match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataQuery {
+    /// Only data of this workflow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow_id: Option<Uuid>,
+    /// Only data of this [type](crate::pointer::DataType::name), e.g. `file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dtype: Option<String>,
+    /// Only data carrying every one of these tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Only data produced by this node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub produced_by: Option<Uuid>,
+    /// Only data created at or after this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only data created before this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+    /// At most this many results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl DataQuery {
+    /// Match all data
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only data of a workflow
+    pub fn workflow(mut self, workflow_id: Uuid) -> Self {
+        self.workflow_id = Some(workflow_id);
+        self
+    }
+
+    /// Only data of a type, by name
+    pub fn dtype(mut self, name: &str) -> Self {
+        self.dtype = Some(name.to_string());
+        self
+    }
+
+    /// Only data with a tag set to `value`
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Only data created in `[after, before)`
+    pub fn created_between(mut self, after: DateTime<Utc>, before: DateTime<Utc>) -> Self {
+        self.created_after = Some(after);
+        self.created_before = Some(before);
+        self
+    }
+
+    /// At most `limit` results
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Parse a query from `key=value` pairs
+    ///
+    /// Keys are `workflow`, `dtype`, `produced_by`, `created_after`,
+    /// `created_before`, `limit`, and `tag.<name>` for each required tag.
+    pub fn from_pairs<K, V>(pairs: impl IntoIterator<Item = (K, V)>) -> Result<Self, DataRefError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut query = Self::new();
+        for (key, value) in pairs {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            match key {
+                "workflow" | "workflow_id" => query.workflow_id = Some(parse(key, value)?),
+                "dtype" => query.dtype = Some(value.to_string()),
+                "produced_by" => query.produced_by = Some(parse(key, value)?),
+                "created_after" => query.created_after = Some(parse(key, value)?),
+                "created_before" => query.created_before = Some(parse(key, value)?),
+                "limit" => query.limit = Some(parse(key, value)?),
+                _ => match key.strip_prefix(TAG_KEY_PREFIX) {
+                    Some(tag) if !tag.is_empty() => {
+                        query.tags.insert(tag.to_string(), value.to_string());
+                    }
+                    _ => return Err(DataRefError::InvalidQuery(format!("unknown key '{key}'"))),
+                },
+            }
+        }
+        Ok(query)
+    }
+
+    /// Check whether data meets every criterion; the limit is ignored
+    pub fn matches(&self, data_ref: &DataRef) -> bool {
+        self.workflow_id.is_none_or(|id| data_ref.workflow_id == id)
+            && self
+                .dtype
+                .as_deref()
+                .is_none_or(|name| data_ref.dtype.name() == name)
+            && self
+                .tags
+                .iter()
+                .all(|(key, value)| data_ref.tag(key) == Some(value.as_str()))
+            && self
+                .produced_by
+                .is_none_or(|node_id| data_ref.produced_by == Some(node_id))
+            && self
+                .created_after
+                .is_none_or(|after| data_ref.created_at >= after)
+            && self
+                .created_before
+                .is_none_or(|before| data_ref.created_at < before)
+    }
+
+    /// Select the matching refs, newest first, up to the limit
+    pub fn select<'a>(&self, data_refs: impl IntoIterator<Item = &'a DataRef>) -> Vec<&'a DataRef> {
+        let mut matches: Vec<&DataRef> = data_refs
+            .into_iter()
+            .filter(|data_ref| self.matches(data_ref))
+            .collect();
+        matches.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.uuid.cmp(&b.uuid)));
+        if let Some(limit) = self.limit {
+            matches.truncate(limit);
+        }
+        matches
+    }
+}
+
+fn parse<T>(key: &str, value: &str) -> Result<T, DataRefError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| DataRefError::InvalidQuery(format!("invalid {key} '{value}': {e}")))
+}
+
+/// Parse tags written as `key=value` pairs separated by commas
+pub fn parse_tags(spec: &str) -> Result<BTreeMap<String, String>, DataRefError> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| match tag.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+            _ => Err(DataRefError::InvalidQuery(format!("invalid tag '{tag}'"))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+    use chrono::Duration;
+
+    #[test]
+    fn test_query_matches() {
+        let workflow_id = Uuid::new_v4();
+        let report = DataRef::file(
+            "server-a".to_string(),
+            workflow_id,
+            10,
+            "application/pdf".to_string(),
+        )
+        .with_tag("stage", "final");
+        let draft = DataRef::file(
+            "server-a".to_string(),
+            workflow_id,
+            10,
+            "application/pdf".to_string(),
+        )
+        .with_tag("stage", "draft");
+        let json = DataRef::new("server-a".to_string(), 2, DataType::Json, workflow_id)
+            .with_tag("stage", "final");
+        let other = DataRef::file(
+            "server-a".to_string(),
+            Uuid::new_v4(),
+            10,
+            "application/pdf".to_string(),
+        )
+        .with_tag("stage", "final");
+        let all = [&report, &draft, &json, &other];
+
+        let query = DataQuery::new()
+            .workflow(workflow_id)
+            .dtype("file")
+            .tag("stage", "final");
+        let found = query.select(all);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].uuid, report.uuid);
+
+        let now = Utc::now();
+        let recent = DataQuery::new()
+            .tag("stage", "final")
+            .created_between(now - Duration::hours(1), now + Duration::hours(1))
+            .limit(2);
+        assert_eq!(recent.select(all).len(), 2);
+        assert!(DataQuery::new()
+            .created_between(now + Duration::hours(1), now + Duration::hours(2))
+            .select(all)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query_from_pairs() {
+        let workflow_id = Uuid::new_v4();
+        let query = DataQuery::from_pairs([
+            ("workflow", workflow_id.to_string().as_str()),
+            ("dtype", "file"),
+            ("tag.stage", "final"),
+            ("limit", "1"),
+        ])
+        .unwrap();
+        assert_eq!(
+            query,
+            DataQuery::new()
+                .workflow(workflow_id)
+                .dtype("file")
+                .tag("stage", "final")
+                .limit(1)
+        );
+        assert!(DataQuery::from_pairs([("workflow", "nope")]).is_err());
+        assert!(DataQuery::from_pairs([("color", "red")]).is_err());
+
+        let tags = parse_tags("stage=final, owner=ci").unwrap();
+        assert_eq!(tags["owner"], "ci");
+        assert!(parse_tags("stage").is_err());
+    }
+}
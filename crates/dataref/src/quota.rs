@@ -0,0 +1,341 @@
+//! Storage quotas per workflow and tenant
+//!
+//! The [registry](crate::registry::DataRefRegistry) accounts the bytes of
+//! every registered piece of data to its workflow and, if the workflow was
+//! [assigned](DataRefRegistry::assign_tenant) one, to its tenant. A
+//! [`QuotaPolicy`] caps both: a store that would go over a cap is refused
+//! by [`DataRefRegistry::check_quota`] before anything is written, so a
+//! runaway fan-out fails its own nodes instead of filling the disk for
+//! everyone.
+//!
+//! Crossing a warning threshold, e.g. 80% of a quota, queues a
+//! `DataQuotaWarning` event once per threshold; the caller publishes them
+//! from [`DataRefRegistry::take_quota_warnings`]. Usage that drops back
+//! below a threshold arms its warning again.
+//!
+//! [`DataRefRegistry::check_quota`]: crate::registry::DataRefRegistry::check_quota
+//! [`DataRefRegistry::take_quota_warnings`]: crate::registry::DataRefRegistry::take_quota_warnings
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::pointer::DataRefError;
+#[cfg(doc)]
+use crate::registry::DataRefRegistry;
+
+/// Default warning thresholds, in percent of a quota
+pub const DEFAULT_QUOTA_WARNING_THRESHOLDS: [u8; 2] = [80, 95];
+
+/// What a quota applies to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum QuotaScope {
+    /// All data of a workflow
+    Workflow(Uuid),
+    /// All data of the workflows assigned to a tenant
+    Tenant(String),
+}
+
+impl std::fmt::Display for QuotaScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaScope::Workflow(id) => write!(f, "workflow:{id}"),
+            QuotaScope::Tenant(name) => write!(f, "tenant:{name}"),
+        }
+    }
+}
+
+/// Deployment-wide caps on stored bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaPolicy {
+    /// Most bytes one workflow may store; `None` is unlimited
+    pub workflow_bytes: Option<u64>,
+    /// Most bytes one tenant may store; `None` is unlimited
+    pub tenant_bytes: Option<u64>,
+    /// Shares of a quota, in percent, whose crossing emits a warning
+    pub warning_thresholds: Vec<u8>,
+}
+
+impl Default for QuotaPolicy {
+    fn default() -> Self {
+        Self {
+            workflow_bytes: None,
+            tenant_bytes: None,
+            warning_thresholds: DEFAULT_QUOTA_WARNING_THRESHOLDS.to_vec(),
+        }
+    }
+}
+
+impl QuotaPolicy {
+    /// Create a policy without quotas
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap the bytes each workflow may store
+    pub fn with_workflow_quota(mut self, bytes: u64) -> Self {
+        self.workflow_bytes = Some(bytes);
+        self
+    }
+
+    /// Cap the bytes each tenant may store
+    pub fn with_tenant_quota(mut self, bytes: u64) -> Self {
+        self.tenant_bytes = Some(bytes);
+        self
+    }
+
+    /// Warn when usage crosses these shares of a quota, in percent
+    pub fn with_warning_thresholds(mut self, thresholds: Vec<u8>) -> Self {
+        self.warning_thresholds = thresholds;
+        self
+    }
+
+    /// Check if no workflow or tenant has a quota
+    pub fn is_unlimited(&self) -> bool {
+        self.workflow_bytes.is_none() && self.tenant_bytes.is_none()
+    }
+
+    /// Get the quota of a scope, if it has one
+    pub fn limit(&self, scope: &QuotaScope) -> Option<u64> {
+        match scope {
+            QuotaScope::Workflow(_) => self.workflow_bytes,
+            QuotaScope::Tenant(_) => self.tenant_bytes,
+        }
+    }
+}
+
+/// Bytes stored per workflow and tenant, checked against a policy
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QuotaTracker {
+    policy: QuotaPolicy,
+    /// Stored bytes, by scope
+    usage: HashMap<QuotaScope, u64>,
+    /// Tenant of each workflow that has one
+    tenants: HashMap<Uuid, String>,
+    /// Highest threshold already warned about, by scope
+    warned: HashMap<QuotaScope, u8>,
+    /// Warnings not yet taken
+    pending: Vec<Event>,
+}
+
+impl QuotaTracker {
+    pub(crate) fn new(policy: QuotaPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn policy(&self) -> &QuotaPolicy {
+        &self.policy
+    }
+
+    /// Assign a workflow to a tenant, moving its stored bytes over
+    pub(crate) fn assign_tenant(&mut self, workflow_id: Uuid, tenant: String) {
+        let used = self.usage(&QuotaScope::Workflow(workflow_id));
+        match self.tenants.insert(workflow_id, tenant.clone()) {
+            Some(previous) if previous == tenant => return,
+            Some(previous) => {
+                let previous = QuotaScope::Tenant(previous);
+                if let Some(bytes) = self.usage.get_mut(&previous) {
+                    *bytes = bytes.saturating_sub(used);
+                    if *bytes == 0 {
+                        self.usage.remove(&previous);
+                    }
+                }
+            }
+            None => {}
+        }
+        if used > 0 {
+            *self.usage.entry(QuotaScope::Tenant(tenant)).or_insert(0) += used;
+        }
+    }
+
+    pub(crate) fn tenant(&self, workflow_id: &Uuid) -> Option<&str> {
+        self.tenants.get(workflow_id).map(String::as_str)
+    }
+
+    pub(crate) fn usage(&self, scope: &QuotaScope) -> u64 {
+        self.usage.get(scope).copied().unwrap_or(0)
+    }
+
+    /// Check that storing `size_bytes` more for a workflow stays within
+    /// its quotas
+    pub(crate) fn check(&self, workflow_id: Uuid, size_bytes: u64) -> Result<(), DataRefError> {
+        for scope in self.scopes(workflow_id) {
+            let Some(limit) = self.policy.limit(&scope) else {
+                continue;
+            };
+            let used = self.usage(&scope);
+            if used.saturating_add(size_bytes) > limit {
+                return Err(DataRefError::QuotaExceeded {
+                    scope: scope.to_string(),
+                    used,
+                    requested: size_bytes,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Account stored bytes, queueing warnings for crossed thresholds
+    pub(crate) fn add(&mut self, workflow_id: Uuid, size_bytes: u64) {
+        for scope in self.scopes(workflow_id) {
+            let used = self.usage.entry(scope.clone()).or_insert(0);
+            *used = used.saturating_add(size_bytes);
+            let used = *used;
+            let Some(limit) = self.policy.limit(&scope) else {
+                continue;
+            };
+            let warned = self.warned.get(&scope).copied().unwrap_or(0);
+            let Some(threshold) = self.crossed(used, limit).filter(|t| *t > warned) else {
+                continue;
+            };
+            self.warned.insert(scope.clone(), threshold);
+            self.pending.push(Event::DataQuotaWarning {
+                scope: scope.to_string(),
+                workflow_id,
+                used_bytes: used,
+                limit_bytes: limit,
+                threshold_percent: threshold,
+                timestamp: Utc::now(),
+            });
+        }
+    }
+
+    /// Release stored bytes, re-arming warnings usage fell below
+    pub(crate) fn subtract(&mut self, workflow_id: Uuid, size_bytes: u64) {
+        for scope in self.scopes(workflow_id) {
+            let Some(used) = self.usage.get_mut(&scope) else {
+                continue;
+            };
+            *used = used.saturating_sub(size_bytes);
+            let used = *used;
+            if used == 0 {
+                self.usage.remove(&scope);
+            }
+            let crossed = self
+                .policy
+                .limit(&scope)
+                .and_then(|limit| self.crossed(used, limit));
+            match crossed {
+                Some(threshold) => {
+                    if let Some(warned) = self.warned.get_mut(&scope) {
+                        *warned = (*warned).min(threshold);
+                    }
+                }
+                None => {
+                    self.warned.remove(&scope);
+                }
+            }
+        }
+        if !self.usage.contains_key(&QuotaScope::Workflow(workflow_id)) {
+            self.tenants.remove(&workflow_id);
+        }
+    }
+
+    pub(crate) fn take_warnings(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Scopes the data of a workflow counts against
+    fn scopes(&self, workflow_id: Uuid) -> Vec<QuotaScope> {
+        let mut scopes = vec![QuotaScope::Workflow(workflow_id)];
+        if let Some(tenant) = self.tenants.get(&workflow_id) {
+            scopes.push(QuotaScope::Tenant(tenant.clone()));
+        }
+        scopes
+    }
+
+    /// Highest threshold `used` reaches, if any
+    fn crossed(&self, used: u64, limit: u64) -> Option<u8> {
+        self.policy
+            .warning_thresholds
+            .iter()
+            .copied()
+            .filter(|threshold| {
+                u128::from(used) * 100 >= u128::from(*threshold) * u128::from(limit)
+            })
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{DataRef, DataType};
+    use crate::registry::{DataRefRegistry, Holder};
+    use chrono::Duration;
+
+    #[test]
+    fn test_quota_enforcement_and_warnings() {
+        let workflow_id = Uuid::new_v4();
+        let holder = Holder::Workflow { workflow_id };
+        let data = |size| DataRef::new("server-a".to_string(), size, DataType::Bytes, workflow_id);
+        let mut registry = DataRefRegistry::new(Duration::seconds(60))
+            .with_quotas(QuotaPolicy::new().with_workflow_quota(1000));
+
+        registry.check_quota(workflow_id, 700).unwrap();
+        registry.register(data(700), holder);
+        assert!(registry.take_quota_warnings().is_empty());
+
+        let big = data(150);
+        registry.register(big.clone(), holder);
+        let warnings = registry.take_quota_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(
+            warnings[0],
+            Event::DataQuotaWarning {
+                threshold_percent: 80,
+                used_bytes: 850,
+                ..
+            }
+        ));
+        // Warned once per threshold
+        registry.register(data(10), holder);
+        assert!(registry.take_quota_warnings().is_empty());
+
+        let err = registry.check_quota(workflow_id, 200).unwrap_err();
+        assert!(matches!(
+            err,
+            DataRefError::QuotaExceeded {
+                used: 860,
+                limit: 1000,
+                ..
+            }
+        ));
+        // Other workflows have their own quota
+        registry.check_quota(Uuid::new_v4(), 200).unwrap();
+
+        // Dropping below a threshold re-arms its warning
+        registry.remove(&big.uuid);
+        assert_eq!(registry.usage(&QuotaScope::Workflow(workflow_id)), 710);
+        registry.register(data(100), holder);
+        assert_eq!(registry.take_quota_warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_tenant_quota() {
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        let mut registry = DataRefRegistry::new(Duration::seconds(60))
+            .with_quotas(QuotaPolicy::new().with_tenant_quota(1000));
+        registry.assign_tenant(first, "team-a".to_string());
+        registry.assign_tenant(second, "team-a".to_string());
+
+        registry.register(
+            DataRef::new("server-a".to_string(), 600, DataType::Bytes, first),
+            Holder::Workflow { workflow_id: first },
+        );
+        let tenant = QuotaScope::Tenant("team-a".to_string());
+        assert_eq!(registry.usage(&tenant), 600);
+        assert!(registry.check_quota(second, 500).is_err());
+        registry.check_quota(second, 400).unwrap();
+    }
+}
@@ -0,0 +1,739 @@
+//! Reference counting and garbage collection for distributed data
+//!
+//! Data produced by a node lives on its server until something frees it.
+//! [`DataRefRegistry`] records who still needs each piece of data: the nodes
+//! consuming it and the workflows keeping it as a result. Node holders are
+//! released when the node completes and every holder of a workflow is
+//! released when it terminates, so the registry can follow the event stream
+//! through [`DataRefRegistry::apply`].
+//!
+//! Data nobody holds is not freed immediately. It stays collectable for a
+//! grace period, during which it can be acquired again, e.g. by a retry or a
+//! workflow reading the previous run's output. [`DataRefRegistry::collect`]
+//! then removes it and returns the refs to delete, each with a
+//! `DataDeleted` event from [`DataRef::deleted_event`]. Collection also
+//! applies the [lifecycle rules](crate::lifecycle) for TTLs and workflow
+//! retention. [Pinned](crate::pin) data is never collected, and data
+//! with an active [read lease](crate::lease) is not collected until the
+//! lease is released or expires.
+//!
+//! Content-addressed data is also indexed by its
+//! [content address](DataRef::content_address), mapping identical content
+//! to every logical ref sharing it, so a producer can find an existing copy
+//! with [`DataRefRegistry::find_content`] instead of storing the bytes
+//! again. Each logical ref is still held and collected on its own; the
+//! stores keep a shared object until its last ref is deleted.
+//!
+//! The registry also accounts stored bytes per workflow and tenant against
+//! the [storage quotas](crate::quota).
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::lease::{LeaseTable, ReadLease};
+use crate::lifecycle::LifecyclePolicy;
+use crate::pin::PinSet;
+use crate::pointer::{DataRef, DataRefError};
+use crate::query::DataQuery;
+use crate::quota::{QuotaPolicy, QuotaScope, QuotaTracker};
+
+/// Default time unreferenced data is kept before collection: 5 minutes
+pub const DEFAULT_GC_GRACE_PERIOD_SECS: i64 = 300;
+
+/// Something that keeps data alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Holder {
+    /// A node that reads the data, until it completes
+    Node { workflow_id: Uuid, node_id: Uuid },
+    /// A workflow, until it terminates
+    Workflow { workflow_id: Uuid },
+}
+
+impl Holder {
+    /// Workflow the holder belongs to
+    pub fn workflow_id(&self) -> Uuid {
+        match self {
+            Holder::Node { workflow_id, .. } | Holder::Workflow { workflow_id } => *workflow_id,
+        }
+    }
+}
+
+/// A registered piece of data and its holders
+#[derive(Debug, Clone)]
+struct Entry {
+    data_ref: DataRef,
+    holders: HashSet<Holder>,
+    /// When the last holder released the data
+    unreferenced_since: Option<DateTime<Utc>>,
+}
+
+impl Entry {
+    fn release(&mut self, now: DateTime<Utc>, released: impl Fn(&Holder) -> bool) {
+        let before = self.holders.len();
+        self.holders.retain(|holder| !released(holder));
+        if before > 0 && self.holders.is_empty() {
+            self.unreferenced_since = Some(now);
+        }
+    }
+}
+
+/// Tracks the holders of each piece of data and collects what is unused
+#[derive(Debug, Clone)]
+pub struct DataRefRegistry {
+    entries: HashMap<Uuid, Entry>,
+    grace_period: Duration,
+    lifecycle: LifecyclePolicy,
+    /// When workflows with registered data reached a terminal state
+    terminated: HashMap<Uuid, DateTime<Utc>>,
+    /// Refs of content-addressed data, by content address
+    content: HashMap<String, HashSet<Uuid>>,
+    pins: PinSet,
+    leases: LeaseTable,
+    quotas: QuotaTracker,
+}
+
+impl Default for DataRefRegistry {
+    fn default() -> Self {
+        Self::new(Duration::seconds(DEFAULT_GC_GRACE_PERIOD_SECS))
+    }
+}
+
+impl DataRefRegistry {
+    /// Create a registry keeping unreferenced data for `grace_period`
+    pub fn new(grace_period: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            grace_period,
+            lifecycle: LifecyclePolicy::default(),
+            terminated: HashMap::new(),
+            content: HashMap::new(),
+            pins: PinSet::new(),
+            leases: LeaseTable::new(),
+            quotas: QuotaTracker::default(),
+        }
+    }
+
+    /// Apply lifecycle rules when collecting
+    pub fn with_lifecycle(mut self, lifecycle: LifecyclePolicy) -> Self {
+        self.lifecycle = lifecycle;
+        self
+    }
+
+    /// Enforce storage quotas
+    pub fn with_quotas(mut self, policy: QuotaPolicy) -> Self {
+        let mut quotas = QuotaTracker::new(policy);
+        for entry in self.entries.values() {
+            quotas.add(entry.data_ref.workflow_id, entry.data_ref.stored_size());
+        }
+        self.quotas = quotas;
+        self
+    }
+
+    /// Get the storage quotas
+    pub fn quota_policy(&self) -> &QuotaPolicy {
+        self.quotas.policy()
+    }
+
+    /// Count a workflow's data against a tenant's quota as well
+    ///
+    /// Bytes the workflow already stores move to the tenant. The assignment
+    /// is dropped once the workflow stores nothing.
+    pub fn assign_tenant(&mut self, workflow_id: Uuid, tenant: String) {
+        self.quotas.assign_tenant(workflow_id, tenant);
+    }
+
+    /// Get the tenant a workflow was assigned to
+    pub fn tenant(&self, workflow_id: &Uuid) -> Option<&str> {
+        self.quotas.tenant(workflow_id)
+    }
+
+    /// Get the bytes registered for a workflow or tenant
+    pub fn usage(&self, scope: &QuotaScope) -> u64 {
+        self.quotas.usage(scope)
+    }
+
+    /// Check that a workflow may store `size_bytes` more without going over
+    /// its own or its tenant's quota
+    pub fn check_quota(&self, workflow_id: Uuid, size_bytes: u64) -> Result<(), DataRefError> {
+        self.quotas.check(workflow_id, size_bytes)
+    }
+
+    /// Take the `DataQuotaWarning` events queued since the last call, for
+    /// the caller to publish
+    pub fn take_quota_warnings(&mut self) -> Vec<Event> {
+        self.quotas.take_warnings()
+    }
+
+    /// Register data with its first holder
+    ///
+    /// Registering known data adds the holder to it.
+    pub fn register(&mut self, data_ref: DataRef, holder: Holder) {
+        let uuid = data_ref.uuid;
+        if !self.entries.contains_key(&uuid) {
+            self.insert(Entry {
+                data_ref,
+                holders: HashSet::new(),
+                unreferenced_since: None,
+            });
+        }
+        let entry = self.entries.get_mut(&uuid).expect("entry registered");
+        entry.holders.insert(holder);
+        entry.unreferenced_since = None;
+    }
+
+    /// Add a holder to registered data, rescuing it from collection
+    pub fn acquire(&mut self, uuid: Uuid, holder: Holder) -> Result<(), DataRefError> {
+        let entry = self
+            .entries
+            .get_mut(&uuid)
+            .ok_or(DataRefError::NotFound(uuid))?;
+        entry.holders.insert(holder);
+        entry.unreferenced_since = None;
+        Ok(())
+    }
+
+    /// Remove a holder from data
+    pub fn release(&mut self, uuid: Uuid, holder: &Holder) {
+        if let Some(entry) = self.entries.get_mut(&uuid) {
+            entry.release(Utc::now(), |h| h == holder);
+        }
+    }
+
+    /// Replace the ref of registered data that was rewritten, keeping its
+    /// holders
+    ///
+    /// Returns whether the data was registered.
+    pub fn update(&mut self, data_ref: DataRef) -> bool {
+        let Some(entry) = self.entries.remove(&data_ref.uuid) else {
+            return false;
+        };
+        self.forget(&entry.data_ref);
+        self.insert(Entry { data_ref, ..entry });
+        true
+    }
+
+    /// Stop tracking data that was deleted by other means
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<DataRef> {
+        let data_ref = self.entries.remove(uuid)?.data_ref;
+        self.pins.unpin_data(uuid);
+        self.leases.remove_data(uuid);
+        self.forget(&data_ref);
+        Some(data_ref)
+    }
+
+    /// Release everything a completed node held
+    pub fn release_node(&mut self, workflow_id: Uuid, node_id: Uuid) {
+        let holder = Holder::Node {
+            workflow_id,
+            node_id,
+        };
+        let now = Utc::now();
+        for entry in self.entries.values_mut() {
+            entry.release(now, |h| *h == holder);
+        }
+    }
+
+    /// Release everything a terminated workflow or its nodes held
+    pub fn release_workflow(&mut self, workflow_id: Uuid) {
+        let now = Utc::now();
+        for entry in self.entries.values_mut() {
+            entry.release(now, |h| h.workflow_id() == workflow_id);
+        }
+    }
+
+    /// Exempt registered data from collection until unpinned
+    pub fn pin(&mut self, uuid: Uuid) -> Result<(), DataRefError> {
+        if !self.entries.contains_key(&uuid) {
+            return Err(DataRefError::NotFound(uuid));
+        }
+        self.pins.pin_data(uuid);
+        Ok(())
+    }
+
+    /// Unpin data; returns `false` if it was not pinned on its own
+    pub fn unpin(&mut self, uuid: &Uuid) -> bool {
+        self.pins.unpin_data(uuid)
+    }
+
+    /// Exempt all data of a workflow from collection until unpinned,
+    /// including data it registers later
+    pub fn pin_workflow(&mut self, workflow_id: Uuid) {
+        self.pins.pin_workflow(workflow_id);
+    }
+
+    /// Unpin a workflow; returns `false` if it was not pinned
+    pub fn unpin_workflow(&mut self, workflow_id: &Uuid) -> bool {
+        self.pins.unpin_workflow(workflow_id)
+    }
+
+    /// Check if registered data is pinned, on its own or through its
+    /// workflow
+    pub fn is_pinned(&self, uuid: &Uuid) -> bool {
+        self.get(uuid)
+            .is_some_and(|data_ref| self.pins.covers(data_ref))
+    }
+
+    /// Get the current pins
+    pub fn pins(&self) -> &PinSet {
+        &self.pins
+    }
+
+    /// Get all registered data that is pinned
+    pub fn pinned(&self) -> Vec<&DataRef> {
+        self.entries
+            .values()
+            .map(|entry| &entry.data_ref)
+            .filter(|data_ref| self.pins.covers(data_ref))
+            .collect()
+    }
+
+    /// Lease registered data for `holder` to read, keeping it from
+    /// collection for `ttl`
+    pub fn acquire_lease(
+        &mut self,
+        uuid: Uuid,
+        holder: String,
+        ttl: Duration,
+    ) -> Result<ReadLease, DataRefError> {
+        if !self.entries.contains_key(&uuid) {
+            return Err(DataRefError::NotFound(uuid));
+        }
+        Ok(self.leases.grant(uuid, holder, ttl, Utc::now()))
+    }
+
+    /// Extend an active lease to `ttl` from now
+    pub fn renew_lease(
+        &mut self,
+        lease_id: &Uuid,
+        ttl: Duration,
+    ) -> Result<ReadLease, DataRefError> {
+        self.leases
+            .renew(lease_id, ttl, Utc::now())
+            .ok_or(DataRefError::LeaseNotFound(*lease_id))
+    }
+
+    /// Give up a lease; returns `None` if it is unknown
+    pub fn release_lease(&mut self, lease_id: &Uuid) -> Option<ReadLease> {
+        self.leases.release(lease_id)
+    }
+
+    /// Check if data has an active lease
+    pub fn is_leased(&self, uuid: &Uuid) -> bool {
+        self.leases.is_leased(uuid, Utc::now())
+    }
+
+    /// Get the active leases on data, soonest expiring first
+    pub fn leases(&self, uuid: &Uuid) -> Vec<&ReadLease> {
+        self.leases.on(uuid, Utc::now())
+    }
+
+    /// Get every active lease, soonest expiring first
+    pub fn active_leases(&self) -> Vec<&ReadLease> {
+        self.leases.active(Utc::now())
+    }
+
+    /// Return collected data whose deletion failed, so the next collection
+    /// retries it
+    pub fn requeue(&mut self, data_ref: DataRef) {
+        let unreferenced_since = Utc::now() - self.grace_period;
+        self.insert(Entry {
+            data_ref,
+            holders: HashSet::new(),
+            unreferenced_since: Some(unreferenced_since),
+        });
+    }
+
+    /// Release holders as nodes complete and workflows terminate
+    ///
+    /// Failed nodes keep their data, since a retry reads the same inputs;
+    /// it is released when the workflow fails. Terminal events also start
+    /// the workflow's retention period, and replication events keep the
+    /// registered locations of data current.
+    pub fn apply(&mut self, event: &Event) {
+        match event {
+            Event::WorkflowStarted { workflow_id, .. } => {
+                self.terminated.remove(workflow_id);
+            }
+            Event::NodeCompleted {
+                workflow_id,
+                node_id,
+                ..
+            } => self.release_node(*workflow_id, *node_id),
+            Event::WorkflowCompleted {
+                workflow_id,
+                timestamp,
+                ..
+            }
+            | Event::WorkflowFailed {
+                workflow_id,
+                timestamp,
+                ..
+            }
+            | Event::WorkflowCancelled {
+                workflow_id,
+                timestamp,
+                ..
+            } => {
+                self.release_workflow(*workflow_id);
+                self.terminated.insert(*workflow_id, *timestamp);
+            }
+            Event::DataReplicated {
+                data_uuid,
+                to_server,
+                ..
+            } => {
+                if let Some(entry) = self.entries.get_mut(data_uuid) {
+                    entry.data_ref.add_replica(to_server);
+                }
+            }
+            Event::DataReplicaRemoved {
+                data_uuid, server, ..
+            } => {
+                if let Some(entry) = self.entries.get_mut(data_uuid) {
+                    entry.data_ref.remove_replica(server);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Get registered data
+    pub fn get(&self, uuid: &Uuid) -> Option<&DataRef> {
+        self.entries.get(uuid).map(|entry| &entry.data_ref)
+    }
+
+    /// Find registered data matching a query, newest first
+    pub fn find(&self, query: &DataQuery) -> Vec<&DataRef> {
+        query.select(self.entries.values().map(|entry| &entry.data_ref))
+    }
+
+    /// Find registered data with the given content address
+    ///
+    /// Any ref found has the same bytes, so its copy can back new data
+    /// instead of storing the content again.
+    pub fn find_content(&self, address: &str) -> Option<&DataRef> {
+        self.content
+            .get(address)?
+            .iter()
+            .find_map(|uuid| self.get(uuid))
+    }
+
+    /// Get the UUIDs of every ref sharing a content address
+    pub fn content_refs(&self, address: &str) -> Vec<Uuid> {
+        self.content
+            .get(address)
+            .map(|uuids| uuids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the number of holders of data, or `None` if it is not registered
+    pub fn ref_count(&self, uuid: &Uuid) -> Option<usize> {
+        self.entries.get(uuid).map(|entry| entry.holders.len())
+    }
+
+    /// Get the number of registered pieces of data
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no data is registered
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove data that has been unreferenced for the grace period, whose
+    /// TTL has passed, or whose workflow's retention has passed, unless it
+    /// is pinned or leased
+    ///
+    /// Leases that expired by `now` are dropped.
+    /// Returns the removed refs; the caller deletes them from their servers
+    /// and publishes their [deleted events](DataRef::deleted_event).
+    pub fn collect(&mut self, now: DateTime<Utc>) -> Vec<DataRef> {
+        let expired: Vec<Uuid> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| !self.pins.covers(&entry.data_ref))
+            .filter(|(uuid, _)| !self.leases.is_leased(uuid, now))
+            .filter(|(_, entry)| {
+                entry
+                    .unreferenced_since
+                    .is_some_and(|since| now - since >= self.grace_period)
+                    || entry.data_ref.is_expired(now)
+                    || self
+                        .terminated
+                        .get(&entry.data_ref.workflow_id)
+                        .is_some_and(|at| self.lifecycle.workflow_expired(*at, now))
+            })
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        let collected: Vec<DataRef> = expired
+            .into_iter()
+            .filter_map(|uuid| self.remove(&uuid))
+            .collect();
+
+        let live: HashSet<Uuid> = self
+            .entries
+            .values()
+            .map(|e| e.data_ref.workflow_id)
+            .collect();
+        self.terminated
+            .retain(|workflow_id, _| live.contains(workflow_id));
+        self.leases.expire(now);
+        collected
+    }
+
+    /// Add an entry, indexing it by content address and accounting its
+    /// bytes
+    fn insert(&mut self, entry: Entry) {
+        self.quotas
+            .add(entry.data_ref.workflow_id, entry.data_ref.stored_size());
+        if let Some(address) = entry.data_ref.content_address() {
+            self.content
+                .entry(address.to_string())
+                .or_default()
+                .insert(entry.data_ref.uuid);
+        }
+        self.entries.insert(entry.data_ref.uuid, entry);
+    }
+
+    /// Undo the accounting and indexing of a removed entry's ref
+    fn forget(&mut self, data_ref: &DataRef) {
+        self.quotas
+            .subtract(data_ref.workflow_id, data_ref.stored_size());
+        if let Some(address) = data_ref.content_address() {
+            if let Some(uuids) = self.content.get_mut(address) {
+                uuids.remove(&data_ref.uuid);
+                if uuids.is_empty() {
+                    self.content.remove(address);
+                }
+            }
+        }
+    }
+}
+
+impl DataRef {
+    /// Event recording that the data was freed
+    pub fn deleted_event(&self) -> Event {
+        Event::DataDeleted {
+            data_uuid: self.uuid,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    #[test]
+    fn test_reference_counting() {
+        let workflow_id = Uuid::new_v4();
+        let consumer = Uuid::new_v4();
+        let data = DataRef::new("server-a".to_string(), 1024, DataType::Bytes, workflow_id);
+        let uuid = data.uuid;
+        let node = Holder::Node {
+            workflow_id,
+            node_id: consumer,
+        };
+
+        let mut registry = DataRefRegistry::new(Duration::seconds(60));
+        registry.register(data, Holder::Workflow { workflow_id });
+        registry.acquire(uuid, node).unwrap();
+        assert_eq!(registry.ref_count(&uuid), Some(2));
+        assert!(registry.acquire(Uuid::new_v4(), node).is_err());
+
+        registry.apply(&Event::NodeCompleted {
+            workflow_id,
+            node_id: consumer,
+            output_refs: Vec::new(),
+            duration_ms: 10,
+            timestamp: Utc::now(),
+        });
+        assert_eq!(registry.ref_count(&uuid), Some(1));
+
+        registry.apply(&Event::WorkflowCompleted {
+            workflow_id,
+            duration_ms: 20,
+            timestamp: Utc::now(),
+        });
+        assert_eq!(registry.ref_count(&uuid), Some(0));
+
+        // Kept for the grace period
+        assert!(registry.collect(Utc::now()).is_empty());
+        let collected = registry.collect(Utc::now() + Duration::seconds(61));
+        assert_eq!(collected.len(), 1);
+        assert!(registry.is_empty());
+        assert!(matches!(
+            collected[0].deleted_event(),
+            Event::DataDeleted { data_uuid, .. } if data_uuid == uuid
+        ));
+
+        // Acquiring again during the grace period keeps the data
+        let data = DataRef::new("server-a".to_string(), 1024, DataType::Bytes, workflow_id);
+        let uuid = data.uuid;
+        registry.register(data, node);
+        registry.release(uuid, &node);
+        registry
+            .acquire(uuid, Holder::Workflow { workflow_id })
+            .unwrap();
+        assert!(registry
+            .collect(Utc::now() + Duration::seconds(61))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_content_index() {
+        let workflow_id = Uuid::new_v4();
+        let holder = Holder::Workflow { workflow_id };
+        let new_ref = |payload: &[u8]| {
+            DataRef::new("server-a".to_string(), 8, DataType::Bytes, workflow_id)
+                .with_content_address_of(payload)
+        };
+        let first = new_ref(b"artifact");
+        let second = new_ref(b"artifact");
+        let address = first.content_address().unwrap().to_string();
+
+        let mut registry = DataRefRegistry::new(Duration::seconds(60));
+        assert!(registry.find_content(&address).is_none());
+        registry.register(first.clone(), holder);
+        registry.register(second.clone(), holder);
+        registry.register(new_ref(b"other"), holder);
+        assert_eq!(registry.content_refs(&address).len(), 2);
+        assert_eq!(
+            registry.find_content(&address).unwrap().checksum.as_deref(),
+            Some(address.as_str())
+        );
+
+        registry.remove(&first.uuid);
+        assert_eq!(registry.content_refs(&address), vec![second.uuid]);
+
+        // Rewritten data moves to its new content address
+        let rewritten = second.clone().with_content_address_of(b"rewritten");
+        assert!(registry.update(rewritten.clone()));
+        assert!(registry.find_content(&address).is_none());
+        assert_eq!(
+            registry.content_refs(rewritten.content_address().unwrap()),
+            vec![second.uuid]
+        );
+        assert_eq!(registry.ref_count(&second.uuid), Some(1));
+        registry.release_workflow(workflow_id);
+        registry.collect(Utc::now() + Duration::seconds(61));
+        assert!(registry.find_content(&address).is_none());
+    }
+
+    #[test]
+    fn test_find() {
+        let workflow_id = Uuid::new_v4();
+        let holder = Holder::Workflow { workflow_id };
+        let report = DataRef::file(
+            "server-a".to_string(),
+            workflow_id,
+            8,
+            "text/plain".to_string(),
+        )
+        .with_tag("stage", "final");
+        let mut registry = DataRefRegistry::new(Duration::seconds(60));
+        registry.register(report.clone(), holder);
+        registry.register(
+            DataRef::file(
+                "server-a".to_string(),
+                workflow_id,
+                8,
+                "text/plain".to_string(),
+            )
+            .with_tag("stage", "draft"),
+            holder,
+        );
+
+        let query = DataQuery::new()
+            .workflow(workflow_id)
+            .dtype("file")
+            .tag("stage", "final");
+        let found = registry.find(&query);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].uuid, report.uuid);
+        assert_eq!(
+            registry.find(&DataQuery::new().workflow(workflow_id)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_pinning() {
+        let workflow_id = Uuid::new_v4();
+        let holder = Holder::Workflow { workflow_id };
+        let checkpoint = DataRef::new("gpu-1".to_string(), 1024, DataType::Bytes, workflow_id);
+        let expiring = DataRef::new("gpu-1".to_string(), 512, DataType::Bytes, workflow_id)
+            .with_ttl(Duration::seconds(10));
+        let (checkpoint_uuid, expiring_uuid) = (checkpoint.uuid, expiring.uuid);
+
+        let mut registry = DataRefRegistry::new(Duration::seconds(60));
+        registry.register(checkpoint, holder);
+        registry.register(expiring, holder);
+        assert!(registry.pin(Uuid::new_v4()).is_err());
+        registry.pin(checkpoint_uuid).unwrap();
+        registry.pin_workflow(workflow_id);
+        registry.release_workflow(workflow_id);
+
+        // Neither collection nor TTL expiry touches pinned data
+        let later = Utc::now() + Duration::seconds(61);
+        assert!(registry.collect(later).is_empty());
+        assert_eq!(registry.pinned().len(), 2);
+
+        // The checkpoint stays pinned on its own once the workflow is not
+        registry.unpin_workflow(&workflow_id);
+        let collected = registry.collect(later);
+        assert_eq!(collected.len(), 1);
+        assert_eq!(collected[0].uuid, expiring_uuid);
+        assert!(registry.is_pinned(&checkpoint_uuid));
+
+        assert!(registry.unpin(&checkpoint_uuid));
+        assert_eq!(registry.collect(later).len(), 1);
+        assert!(registry.pins().is_empty());
+    }
+
+    #[test]
+    fn test_read_leases() {
+        let workflow_id = Uuid::new_v4();
+        let data_ref = DataRef::new("gpu-1".to_string(), 1024, DataType::Bytes, workflow_id)
+            .with_ttl(Duration::seconds(10));
+        let uuid = data_ref.uuid;
+
+        let mut registry = DataRefRegistry::new(Duration::seconds(60));
+        registry.register(data_ref, Holder::Workflow { workflow_id });
+        registry.release_workflow(workflow_id);
+        assert!(registry
+            .acquire_lease(Uuid::new_v4(), "gpu-2".to_string(), Duration::seconds(30))
+            .is_err());
+        let lease = registry
+            .acquire_lease(uuid, "gpu-2".to_string(), Duration::seconds(120))
+            .unwrap();
+        assert!(registry.is_leased(&uuid));
+        assert_eq!(registry.leases(&uuid), [&lease]);
+
+        // Unreferenced and past its TTL, the data stays while it is read
+        let later = Utc::now() + Duration::seconds(61);
+        assert!(registry.collect(later).is_empty());
+        assert_eq!(registry.active_leases().len(), 1);
+
+        // Renewed leases still run out
+        let renewed = registry
+            .renew_lease(&lease.lease_id, Duration::seconds(30))
+            .unwrap();
+        assert!(renewed.expires_at < lease.expires_at);
+        assert_eq!(registry.collect(later).len(), 1);
+        assert!(registry.active_leases().is_empty());
+        assert!(registry.release_lease(&lease.lease_id).is_none());
+        assert!(matches!(
+            registry.renew_lease(&lease.lease_id, Duration::seconds(30)),
+            Err(DataRefError::LeaseNotFound(_))
+        ));
+    }
+}
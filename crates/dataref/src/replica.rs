@@ -0,0 +1,114 @@
+//! Replicated data
+//!
+//! Hot data such as shared model inputs is read by nodes on many servers.
+//! Rather than transferring it again for every reader, a [`DataRef`] can be
+//! held by several servers: its primary `location` plus a set of replicas.
+//! Readers fetch from whichever copy is cheapest to reach under the
+//! [transfer cost model](crate::topology), and replicas are announced with
+//! `DataReplicated` and `DataReplicaRemoved` events so every view of the
+//! data learns about them.
+
+use chrono::Utc;
+
+use swarmx_events::Event;
+
+use crate::pointer::DataRef;
+use crate::topology::NetworkTopology;
+
+impl DataRef {
+    /// Servers holding the data, primary first
+    pub fn locations(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.location.as_str()).chain(self.replicas.iter().map(String::as_str))
+    }
+
+    /// Record a copy of the data on another server
+    ///
+    /// Returns `false` if the server already holds the data.
+    pub fn add_replica(&mut self, server: &str) -> bool {
+        if self.is_local_to(server) {
+            return false;
+        }
+        self.replicas.push(server.to_string());
+        true
+    }
+
+    /// Forget a replica; the primary location cannot be removed
+    ///
+    /// Returns `false` if the server held no replica.
+    pub fn remove_replica(&mut self, server: &str) -> bool {
+        let before = self.replicas.len();
+        self.replicas.retain(|replica| replica != server);
+        self.replicas.len() != before
+    }
+
+    /// Server a reader on `target` should fetch the data from
+    ///
+    /// Picks the copy with the lowest transfer cost, preferring the primary
+    /// on ties.
+    pub fn cheapest_source(&self, target: &str, topology: &dyn NetworkTopology) -> &str {
+        self.locations()
+            .min_by_key(|source| {
+                topology.transfer_ms(source, target, self.stored_size(), self.storage_tier)
+            })
+            .unwrap_or(&self.location)
+    }
+
+    /// Event recording a new replica copied from `from_server`
+    pub fn replicated_event(&self, from_server: &str, to_server: &str, duration_ms: u64) -> Event {
+        Event::DataReplicated {
+            data_uuid: self.uuid,
+            from_server: from_server.to_string(),
+            to_server: to_server.to_string(),
+            duration_ms,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Event recording that a server dropped its replica
+    pub fn replica_removed_event(&self, server: &str) -> Event {
+        Event::DataReplicaRemoved {
+            data_uuid: self.uuid,
+            server: server.to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pointer::{DataRef, DataType};
+    use crate::topology::{Link, StaticTopology};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_replicas() {
+        let topology = StaticTopology::default()
+            .with_link("gpu-1:9090", "gpu-3:9090", Link::new(1_000_000_000, 0.5))
+            .with_link("gpu-2:9090", "gpu-3:9090", Link::new(10_000_000_000, 0.5));
+        let mut data = DataRef::new(
+            "gpu-1:9090".to_string(),
+            1_000_000_000,
+            DataType::Bytes,
+            Uuid::new_v4(),
+        );
+        assert_eq!(data.cheapest_source("gpu-3:9090", &topology), "gpu-1:9090");
+
+        assert!(data.add_replica("gpu-2:9090"));
+        assert!(!data.add_replica("gpu-2:9090"));
+        assert!(!data.add_replica("gpu-1:9090"));
+        assert_eq!(
+            data.locations().collect::<Vec<_>>(),
+            ["gpu-1:9090", "gpu-2:9090"]
+        );
+        assert!(data.is_local_to("gpu-2:9090"));
+        assert_eq!(data.transfer_cost("gpu-2:9090", &topology), 0);
+
+        // The replica has the faster link
+        assert_eq!(data.cheapest_source("gpu-3:9090", &topology), "gpu-2:9090");
+        assert_eq!(data.transfer_cost("gpu-3:9090", &topology), 101);
+
+        assert!(!data.remove_replica("gpu-1:9090"));
+        assert!(data.remove_replica("gpu-2:9090"));
+        assert_eq!(data.transfer_cost("gpu-3:9090", &topology), 1001);
+    }
+}
@@ -0,0 +1,212 @@
+//! Revocation list for access tokens
+//!
+//! A [`RevocationList`] holds revoked tokens in memory and, when opened
+//! with [`RevocationList::persistent`], writes every revocation through to
+//! the WAL database so it survives restarts. Tokens are revoked one at a
+//! time by their [ID](AccessToken::token_id), or in bulk for a workflow,
+//! which rejects every token of that workflow issued up to the revocation.
+//!
+//! Entries are kept until the tokens they cover have expired; call
+//! [`RevocationList::prune`] periodically to drop them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::{DateTime, Utc};
+use swarmx_events::{RevocationSubject, TokenRevocation, WriteAheadLog};
+use uuid::Uuid;
+
+use crate::token::{AccessToken, TokenError};
+
+#[derive(Default)]
+struct RevocationState {
+    /// Revocations by subject
+    entries: HashMap<RevocationSubject, TokenRevocation>,
+    /// Where revocations are persisted, if anywhere
+    wal: Option<WriteAheadLog>,
+}
+
+impl RevocationState {
+    fn insert(&mut self, revocation: TokenRevocation) -> Result<(), TokenError> {
+        if let Some(wal) = self.wal.as_mut() {
+            wal.record_token_revocation(&revocation)?;
+        }
+        self.entries.insert(revocation.subject.clone(), revocation);
+        Ok(())
+    }
+}
+
+/// Revoked access tokens
+///
+/// Cheaply cloneable; clones share the same list.
+#[derive(Clone, Default)]
+pub struct RevocationList {
+    state: Arc<Mutex<RevocationState>>,
+}
+
+impl std::fmt::Debug for RevocationList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RevocationList")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RevocationList {
+    /// Create an empty in-memory list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the revocations stored in `wal` and persist new ones there
+    ///
+    /// Entries that have already expired are pruned on load.
+    pub fn persistent(mut wal: WriteAheadLog) -> Result<Self, TokenError> {
+        wal.prune_token_revocations(Utc::now())?;
+        let entries = wal
+            .token_revocations()?
+            .into_iter()
+            .map(|revocation| (revocation.subject.clone(), revocation))
+            .collect();
+        Ok(Self {
+            state: Arc::new(Mutex::new(RevocationState {
+                entries,
+                wal: Some(wal),
+            })),
+        })
+    }
+
+    /// Revoke a single token until it expires
+    pub fn revoke(&self, token: &AccessToken) -> Result<(), TokenError> {
+        self.lock().insert(TokenRevocation {
+            subject: RevocationSubject::Token(token.token_id()),
+            revoked_at: Utc::now(),
+            expires_at: token.expires_at,
+        })
+    }
+
+    /// Revoke every token of a workflow issued up to now
+    ///
+    /// The entry is kept until `until`, which must not be earlier than the
+    /// expiry of the longest-lived token issued for the workflow. Tokens
+    /// issued afterwards are accepted again.
+    pub fn revoke_workflow(
+        &self,
+        workflow_id: Uuid,
+        until: DateTime<Utc>,
+    ) -> Result<(), TokenError> {
+        self.lock().insert(TokenRevocation {
+            subject: RevocationSubject::Workflow(workflow_id),
+            revoked_at: Utc::now(),
+            expires_at: until,
+        })
+    }
+
+    /// Check whether a token has been revoked, singly or by its workflow
+    pub fn is_revoked(&self, token: &AccessToken) -> bool {
+        let state = self.lock();
+        if state
+            .entries
+            .contains_key(&RevocationSubject::Token(token.token_id()))
+        {
+            return true;
+        }
+        token.workflow().is_some_and(|workflow_id| {
+            state
+                .entries
+                .get(&RevocationSubject::Workflow(workflow_id))
+                .is_some_and(|revocation| token.issued_at <= revocation.revoked_at)
+        })
+    }
+
+    /// Drop entries whose tokens have all expired by `now`
+    ///
+    /// Returns the number of entries removed.
+    pub fn prune(&self, now: DateTime<Utc>) -> Result<usize, TokenError> {
+        let mut state = self.lock();
+        if let Some(wal) = state.wal.as_mut() {
+            wal.prune_token_revocations(now)?;
+        }
+        let before = state.entries.len();
+        state
+            .entries
+            .retain(|_, revocation| revocation.expires_at >= now);
+        Ok(before - state.entries.len())
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether nothing is revoked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RevocationState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Permissions;
+    use chrono::Duration;
+
+    fn token(workflow_id: Option<Uuid>, ttl: Duration) -> AccessToken {
+        let mut token = AccessToken::new(
+            Uuid::new_v4(),
+            "client-1".to_string(),
+            ttl,
+            Permissions::read_only(),
+        );
+        token.workflow_id = workflow_id;
+        token.signature = Uuid::new_v4().simple().to_string();
+        token
+    }
+
+    #[test]
+    fn test_revoke_single_and_workflow() {
+        let list = RevocationList::new();
+        let workflow_id = Uuid::new_v4();
+        let single = token(None, Duration::hours(1));
+        let in_workflow = token(Some(workflow_id), Duration::hours(1));
+        let other = token(Some(Uuid::new_v4()), Duration::hours(1));
+
+        list.revoke(&single).unwrap();
+        list.revoke_workflow(workflow_id, Utc::now() + Duration::hours(1))
+            .unwrap();
+        assert!(list.is_revoked(&single));
+        assert!(list.is_revoked(&in_workflow));
+        assert!(!list.is_revoked(&other));
+
+        // Tokens issued after a workflow revocation are accepted
+        let mut later = token(Some(workflow_id), Duration::hours(1));
+        later.issued_at = Utc::now() + Duration::seconds(1);
+        assert!(!list.is_revoked(&later));
+
+        assert_eq!(list.prune(Utc::now() + Duration::hours(2)).unwrap(), 2);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_persistent_list_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let revoked = token(None, Duration::hours(1));
+        let expired = token(None, Duration::seconds(-1));
+
+        let list = RevocationList::persistent(WriteAheadLog::open(&path).unwrap()).unwrap();
+        list.revoke(&revoked).unwrap();
+        list.revoke(&expired).unwrap();
+        drop(list);
+
+        let list = RevocationList::persistent(WriteAheadLog::open(&path).unwrap()).unwrap();
+        assert!(list.is_revoked(&revoked));
+        // Expired entries are pruned on load
+        assert!(!list.is_revoked(&expired));
+        assert_eq!(list.len(), 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! S3 object store backend
+//!
+//! [`S3ObjectStore`] stores data in an S3 bucket, or any S3-compatible
+//! store such as MinIO: point the AWS SDK at it with `AWS_ENDPOINT_URL`
+//! and enable path-style addressing. Files above the part size are
+//! uploaded with multipart upload, one part at a time, so only one part of
+//! a large checkpoint is in memory at once; a failed upload is aborted so
+//! its parts do not linger in the bucket.
+
+use std::path::Path;
+use std::time::Duration;
+
+use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client;
+use tokio::io::AsyncReadExt;
+
+use crate::object_store::{ObjectStoreBackend, ObjectStoreError};
+
+/// Default size of multipart upload parts: 64MB
+pub const DEFAULT_PART_SIZE: usize = 64 * 1024 * 1024;
+
+/// S3 requires parts other than the last to be at least 5MB
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Object store backed by an S3 bucket
+#[derive(Debug, Clone)]
+pub struct S3ObjectStore {
+    client: Client,
+    bucket: String,
+    /// Prefix of every key, without a trailing slash
+    prefix: String,
+    part_size: usize,
+}
+
+impl S3ObjectStore {
+    /// Store objects in `bucket` through a configured client
+    pub fn new(client: Client, bucket: &str) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            prefix: String::new(),
+            part_size: DEFAULT_PART_SIZE,
+        }
+    }
+
+    /// Store objects in `bucket`, configured from the environment
+    ///
+    /// Credentials, region, and endpoint come from the standard AWS
+    /// settings. Path-style addressing is used when `AWS_ENDPOINT_URL` is
+    /// set, as MinIO and most other S3-compatible stores expect.
+    pub async fn from_env(bucket: &str) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        let s3_config = aws_sdk_s3::config::Builder::from(&config)
+            .force_path_style(std::env::var_os("AWS_ENDPOINT_URL").is_some())
+            .build();
+        Self::new(Client::from_conf(s3_config), bucket)
+    }
+
+    /// Keep objects under a key prefix, e.g. `swarmx/prod`
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_matches('/').to_string();
+        self
+    }
+
+    /// Upload files larger than `part_size` bytes in parts of that size
+    ///
+    /// Sizes below S3's 5MB minimum are raised to it.
+    pub fn with_part_size(mut self, part_size: usize) -> Self {
+        self.part_size = part_size.max(MIN_PART_SIZE);
+        self
+    }
+
+    fn full_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{key}", self.prefix)
+        }
+    }
+
+    /// Upload a file in parts, aborting the upload if a part fails
+    async fn put_multipart(&self, key: &str, path: &Path) -> Result<(), ObjectStoreError> {
+        let upload = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(backend_error)?;
+        let upload_id = upload
+            .upload_id()
+            .ok_or_else(|| ObjectStoreError::Backend("No upload ID returned".to_string()))?;
+
+        match self.upload_parts(key, upload_id, path).await {
+            Ok(parts) => {
+                self.client
+                    .complete_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .multipart_upload(
+                        CompletedMultipartUpload::builder()
+                            .set_parts(Some(parts))
+                            .build(),
+                    )
+                    .send()
+                    .await
+                    .map_err(backend_error)?;
+                Ok(())
+            }
+            Err(e) => {
+                let aborted = self
+                    .client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await;
+                if let Err(abort) = aborted {
+                    tracing::warn!(
+                        key,
+                        upload_id,
+                        "Failed to abort multipart upload: {}",
+                        DisplayErrorContext(abort)
+                    );
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        path: &Path,
+    ) -> Result<Vec<CompletedPart>, ObjectStoreError> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut parts = Vec::new();
+        for part_number in 1.. {
+            let mut part = Vec::with_capacity(self.part_size);
+            (&mut file)
+                .take(self.part_size as u64)
+                .read_to_end(&mut part)
+                .await?;
+            if part.is_empty() && part_number > 1 {
+                break;
+            }
+            let uploaded = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(ByteStream::from(part))
+                .send()
+                .await
+                .map_err(backend_error)?;
+            parts.push(
+                CompletedPart::builder()
+                    .part_number(part_number)
+                    .set_e_tag(uploaded.e_tag().map(str::to_string))
+                    .build(),
+            );
+        }
+        Ok(parts)
+    }
+}
+
+impl ObjectStoreBackend for S3ObjectStore {
+    fn url(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.bucket, self.full_key(key))
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), ObjectStoreError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.full_key(key))
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), ObjectStoreError> {
+        let key = self.full_key(key);
+        let size = tokio::fs::metadata(path).await?.len();
+        if size > self.part_size as u64 {
+            return self.put_multipart(&key, path).await;
+        }
+        let body = ByteStream::from_path(path)
+            .await
+            .map_err(|e| ObjectStoreError::Backend(e.to_string()))?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(body)
+            .send()
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, ObjectStoreError> {
+        let full_key = self.full_key(key);
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&full_key)
+            .send()
+            .await
+            .map_err(|e| match e {
+                SdkError::ServiceError(ref service)
+                    if matches!(service.err(), GetObjectError::NoSuchKey(_)) =>
+                {
+                    ObjectStoreError::NotFound(full_key.clone())
+                }
+                e => backend_error(e),
+            })?;
+        let data = object
+            .body
+            .collect()
+            .await
+            .map_err(|e| ObjectStoreError::Backend(e.to_string()))?;
+        Ok(data.into_bytes().to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ObjectStoreError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.full_key(key))
+            .send()
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn presigned_get(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<String, ObjectStoreError> {
+        let config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| ObjectStoreError::Backend(e.to_string()))?;
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.full_key(key))
+            .presigned(config)
+            .await
+            .map_err(backend_error)?;
+        Ok(request.uri().to_string())
+    }
+}
+
+fn backend_error<E, R>(e: SdkError<E, R>) -> ObjectStoreError
+where
+    E: std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    ObjectStoreError::Backend(DisplayErrorContext(e).to_string())
+}
@@ -0,0 +1,275 @@
+//! Background integrity scrubbing
+//!
+//! Disk-tier data can rot silently: a flipped bit is only noticed when a
+//! node reads the data and fails in some unrelated-looking way. A
+//! [`Scrubber`] re-checksums stored data ahead of that. Each
+//! [run](Scrubber::run) verifies the next sample of objects in UUID order,
+//! wrapping around, so every object is checked once per
+//! `objects / sample_size` runs however large the store grows.
+//!
+//! Data that no longer matches its checksum, fails to decrypt or
+//! decompress, or whose object is missing is
+//! [quarantined](LocalDataStore::quarantine) and reported, so the caller
+//! can emit integrity events and fall back to replicas.
+
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::DataRef;
+use crate::store::{DataStoreError, LocalDataStore};
+
+/// Default time between scrub runs: 1 hour
+pub const DEFAULT_SCRUB_INTERVAL_SECS: u64 = 3600;
+
+/// Default number of objects verified per run
+pub const DEFAULT_SCRUB_SAMPLE_SIZE: usize = 100;
+
+/// How often and how much to scrub
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrubPolicy {
+    /// Time between runs
+    pub interval: std::time::Duration,
+    /// Objects verified per run
+    pub sample_size: usize,
+}
+
+impl Default for ScrubPolicy {
+    fn default() -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(DEFAULT_SCRUB_INTERVAL_SECS),
+            sample_size: DEFAULT_SCRUB_SAMPLE_SIZE,
+        }
+    }
+}
+
+impl ScrubPolicy {
+    /// Create the default policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run every `interval`, at most once a second
+    pub fn with_interval(mut self, interval: std::time::Duration) -> Self {
+        self.interval = interval.max(std::time::Duration::from_secs(1));
+        self
+    }
+
+    /// Verify `sample_size` objects per run
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size.max(1);
+        self
+    }
+}
+
+/// Data found corrupt and moved out of the store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedData {
+    pub data_ref: DataRef,
+    /// Checksum of what was read; `None` if the data was unreadable
+    pub actual_checksum: Option<String>,
+    /// What was wrong with the data
+    pub reason: String,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// Outcome of one scrub run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrubReport {
+    /// Objects whose checksums were verified
+    pub checked: u64,
+    /// Objects that could not be checked, e.g. for lack of a data key
+    pub failed: u64,
+    /// Data quarantined by the run
+    pub corrupted: Vec<QuarantinedData>,
+    pub duration_ms: u64,
+}
+
+/// Scrubbing so far
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrubStatus {
+    /// Completed runs
+    pub runs: u64,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Outcome of the last run, without its quarantined data
+    pub last_checked: u64,
+    pub last_failed: u64,
+    pub last_duration_ms: u64,
+    /// Objects verified over all runs
+    pub total_checked: u64,
+    /// Data quarantined over all runs, oldest first
+    pub quarantined: Vec<QuarantinedData>,
+}
+
+#[derive(Debug, Default)]
+struct ScrubState {
+    /// Last object verified; the next run continues after it
+    cursor: Option<Uuid>,
+    status: ScrubStatus,
+}
+
+/// Periodically verifies a sample of stored objects
+///
+/// Cheaply cloneable; clones share the same position and status.
+#[derive(Debug, Clone, Default)]
+pub struct Scrubber {
+    policy: ScrubPolicy,
+    state: Arc<Mutex<ScrubState>>,
+}
+
+impl Scrubber {
+    /// Create a scrubber following `policy`
+    pub fn new(policy: ScrubPolicy) -> Self {
+        Self {
+            policy,
+            state: Arc::default(),
+        }
+    }
+
+    /// Policy the scrubber follows
+    pub fn policy(&self) -> &ScrubPolicy {
+        &self.policy
+    }
+
+    /// Verify the next sample of objects in `store`, quarantining corrupt
+    /// data
+    ///
+    /// Blocks on disk reads; run it off async threads.
+    pub fn run(&self, store: &LocalDataStore) -> ScrubReport {
+        let started = Instant::now();
+        let mut refs = store.list();
+        refs.sort_by_key(|data_ref| data_ref.uuid);
+        let cursor = self.lock().cursor;
+        let start = cursor.map_or(0, |cursor| {
+            refs.partition_point(|data_ref| data_ref.uuid <= cursor)
+        });
+        let sample: Vec<Uuid> = refs
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(self.policy.sample_size.min(refs.len()))
+            .map(|data_ref| data_ref.uuid)
+            .collect();
+
+        let mut report = ScrubReport::default();
+        for uuid in &sample {
+            let (actual_checksum, reason) = match store.verify(uuid) {
+                Ok(_) => {
+                    report.checked += 1;
+                    continue;
+                }
+                Err(DataStoreError::ChecksumMismatch { actual, .. }) => {
+                    (Some(actual), "checksum mismatch".to_string())
+                }
+                // The object is gone while its index entry remains
+                Err(DataStoreError::NotFound(_)) if store.get_ref(uuid).is_some() => {
+                    (None, "object missing".to_string())
+                }
+                // Deleted since the sample was taken
+                Err(DataStoreError::NotFound(_)) => continue,
+                Err(DataStoreError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                    ) =>
+                {
+                    (None, format!("unreadable: {e}"))
+                }
+                Err(_) => {
+                    report.failed += 1;
+                    continue;
+                }
+            };
+            report.checked += 1;
+            // Refs sharing a quarantined object are gone with it
+            let Ok(quarantined) = store.quarantine(uuid) else {
+                report.failed += 1;
+                continue;
+            };
+            let detected_at = Utc::now();
+            report
+                .corrupted
+                .extend(quarantined.into_iter().map(|data_ref| QuarantinedData {
+                    data_ref,
+                    actual_checksum: actual_checksum.clone(),
+                    reason: reason.clone(),
+                    detected_at,
+                }));
+        }
+        report.duration_ms = started.elapsed().as_millis() as u64;
+
+        let mut state = self.lock();
+        if let Some(last) = sample.last() {
+            state.cursor = Some(*last);
+        }
+        let status = &mut state.status;
+        status.runs += 1;
+        status.last_run_at = Some(Utc::now());
+        status.last_checked = report.checked;
+        status.last_failed = report.failed;
+        status.last_duration_ms = report.duration_ms;
+        status.total_checked += report.checked;
+        status.quarantined.extend(report.corrupted.iter().cloned());
+        report
+    }
+
+    /// Scrubbing so far
+    pub fn status(&self) -> ScrubStatus {
+        self.lock().status.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ScrubState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    #[test]
+    fn test_scrub_quarantines_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalDataStore::open(dir.path(), "http://localhost:3000/api").unwrap();
+        let workflow_id = Uuid::new_v4();
+        let stored: Vec<DataRef> = (0..5)
+            .map(|i| {
+                let data = DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id);
+                store.put(data, format!("payload {i}").as_bytes()).unwrap()
+            })
+            .collect();
+        let corrupt = &stored[3];
+        std::fs::write(dir.path().join(corrupt.uuid.to_string()), b"payload X").unwrap();
+
+        let scrubber = Scrubber::new(ScrubPolicy::new().with_sample_size(3));
+        let first = scrubber.run(&store);
+        let second = scrubber.run(&store);
+        assert_eq!(first.checked + second.checked, 6);
+        let corrupted: Vec<_> = first.corrupted.iter().chain(&second.corrupted).collect();
+        assert_eq!(corrupted.len(), 1);
+        assert_eq!(corrupted[0].data_ref.uuid, corrupt.uuid);
+        assert!(corrupted[0].actual_checksum.is_some());
+
+        // Quarantined data is no longer served but kept for inspection
+        assert!(store.get_ref(&corrupt.uuid).is_none());
+        assert!(dir
+            .path()
+            .join("quarantine")
+            .join(corrupt.uuid.to_string())
+            .exists());
+        let status = scrubber.status();
+        assert_eq!(status.runs, 2);
+        assert_eq!(status.total_checked, 6);
+        assert_eq!(status.quarantined.len(), 1);
+
+        // The remaining objects are intact
+        let third = scrubber.run(&store);
+        assert_eq!(third.checked, 3);
+        assert!(third.corrupted.is_empty());
+    }
+}
@@ -0,0 +1,369 @@
+//! Logical data versioning
+//!
+//! A [`DataSeries`] is a named handle, scoped to a workflow, for an
+//! artifact that is refined over time, e.g. a model checkpoint or a
+//! document rewritten on every pass. Each version is an immutable
+//! [`DataRef`], numbered from 1 in the order it was appended, so the name
+//! stays stable while the full history remains readable. A new version is
+//! usually [derived](DataRef::derive) from the latest one, written, and
+//! appended. A DataRef can be a version of only one series, and must not
+//! be written once it is one.
+//!
+//! [`VersionDiff`] compares the metadata of two versions: size, checksum,
+//! data type, and tags. The [`DataSeriesIndex`] holds every series, by
+//! workflow and name.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::{DataRef, DataType};
+
+/// Longest series name
+pub const MAX_SERIES_NAME_LEN: usize = 128;
+
+/// Data series errors
+#[derive(Debug, thiserror::Error)]
+pub enum SeriesError {
+    #[error("Series not found: {0}")]
+    NotFound(String),
+
+    #[error("Series {series} has no version {version}")]
+    VersionNotFound { series: String, version: u32 },
+
+    #[error("Invalid series name '{0}': use up to 128 letters, digits, '.', '_', or '-'")]
+    InvalidName(String),
+
+    #[error("Data {data_uuid} belongs to workflow {actual}, not the series' workflow {expected}")]
+    WrongWorkflow {
+        data_uuid: Uuid,
+        expected: Uuid,
+        actual: Uuid,
+    },
+
+    #[error("Data {data_uuid} is already version {version} of series {series}")]
+    AlreadyVersioned {
+        data_uuid: Uuid,
+        series: String,
+        version: u32,
+    },
+}
+
+/// Check that a series name is non-empty, at most [`MAX_SERIES_NAME_LEN`]
+/// long, and safe in a URL path
+pub fn validate_series_name(name: &str) -> Result<(), SeriesError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_SERIES_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SeriesError::InvalidName(name.to_string()))
+    }
+}
+
+/// One version of a series
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesVersion {
+    /// Version number, from 1
+    pub version: u32,
+    /// The data, as it was when appended
+    pub data_ref: DataRef,
+    pub appended_at: DateTime<Utc>,
+}
+
+/// A named, versioned artifact of a workflow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataSeries {
+    pub name: String,
+    pub workflow_id: Uuid,
+    /// Versions, oldest first
+    pub versions: Vec<SeriesVersion>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DataSeries {
+    /// Create a series with no versions
+    pub fn new(name: &str, workflow_id: Uuid) -> Result<Self, SeriesError> {
+        validate_series_name(name)?;
+        Ok(Self {
+            name: name.to_string(),
+            workflow_id,
+            versions: Vec::new(),
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Append data of the series' workflow as the next version
+    pub fn append(&mut self, data_ref: DataRef) -> Result<&SeriesVersion, SeriesError> {
+        if data_ref.workflow_id != self.workflow_id {
+            return Err(SeriesError::WrongWorkflow {
+                data_uuid: data_ref.uuid,
+                expected: self.workflow_id,
+                actual: data_ref.workflow_id,
+            });
+        }
+        if let Some(existing) = self.find(&data_ref.uuid) {
+            return Err(SeriesError::AlreadyVersioned {
+                data_uuid: data_ref.uuid,
+                series: self.name.clone(),
+                version: existing.version,
+            });
+        }
+        self.versions.push(SeriesVersion {
+            version: self.versions.len() as u32 + 1,
+            data_ref,
+            appended_at: Utc::now(),
+        });
+        Ok(self.versions.last().expect("version appended"))
+    }
+
+    /// Get the newest version, if any
+    pub fn latest(&self) -> Option<&SeriesVersion> {
+        self.versions.last()
+    }
+
+    /// Get a version by number
+    pub fn version(&self, version: u32) -> Result<&SeriesVersion, SeriesError> {
+        version
+            .checked_sub(1)
+            .and_then(|index| self.versions.get(index as usize))
+            .ok_or_else(|| SeriesError::VersionNotFound {
+                series: self.name.clone(),
+                version,
+            })
+    }
+
+    /// Find the version holding data
+    pub fn find(&self, data_uuid: &Uuid) -> Option<&SeriesVersion> {
+        self.versions
+            .iter()
+            .find(|version| version.data_ref.uuid == *data_uuid)
+    }
+
+    /// Compare the metadata of two versions
+    pub fn diff(&self, from: u32, to: u32) -> Result<VersionDiff, SeriesError> {
+        Ok(VersionDiff::between(self.version(from)?, self.version(to)?))
+    }
+}
+
+/// A tag whose value differs between two versions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagChange {
+    pub from: String,
+    pub to: String,
+}
+
+/// Metadata changes from one version of a series to another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionDiff {
+    pub from: u32,
+    pub to: u32,
+    pub from_uuid: Uuid,
+    pub to_uuid: Uuid,
+    /// Bytes `to` has more than `from`; negative if it shrank
+    pub size_delta: i64,
+    /// Whether the content differs, by checksum; `None` if either version
+    /// has no checksum
+    pub content_changed: Option<bool>,
+    /// Data type of `to`, if it differs from that of `from`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dtype: Option<DataType>,
+    /// Tags only `to` has
+    pub tags_added: BTreeMap<String, String>,
+    /// Tags only `from` has
+    pub tags_removed: BTreeMap<String, String>,
+    /// Tags both have, with different values
+    pub tags_changed: BTreeMap<String, TagChange>,
+    /// Seconds from the creation of `from` to that of `to`
+    pub elapsed_secs: i64,
+}
+
+impl VersionDiff {
+    /// Compare two versions
+    pub fn between(from: &SeriesVersion, to: &SeriesVersion) -> Self {
+        let (old, new) = (&from.data_ref, &to.data_ref);
+        let mut tags_removed = BTreeMap::new();
+        let mut tags_changed = BTreeMap::new();
+        for (key, value) in &old.tags {
+            match new.tags.get(key) {
+                None => {
+                    tags_removed.insert(key.clone(), value.clone());
+                }
+                Some(to) if to != value => {
+                    let change = TagChange {
+                        from: value.clone(),
+                        to: to.clone(),
+                    };
+                    tags_changed.insert(key.clone(), change);
+                }
+                Some(_) => {}
+            }
+        }
+        let tags_added = new
+            .tags
+            .iter()
+            .filter(|(key, _)| !old.tags.contains_key(*key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Self {
+            from: from.version,
+            to: to.version,
+            from_uuid: old.uuid,
+            to_uuid: new.uuid,
+            size_delta: new.size_bytes as i64 - old.size_bytes as i64,
+            content_changed: old
+                .checksum
+                .as_ref()
+                .zip(new.checksum.as_ref())
+                .map(|(old, new)| old != new),
+            dtype: (old.dtype != new.dtype).then(|| new.dtype.clone()),
+            tags_added,
+            tags_removed,
+            tags_changed,
+            elapsed_secs: (new.created_at - old.created_at).num_seconds(),
+        }
+    }
+}
+
+/// Every data series, by workflow and name
+#[derive(Debug, Clone, Default)]
+pub struct DataSeriesIndex {
+    series: HashMap<(Uuid, String), DataSeries>,
+    /// Series and version of each versioned piece of data
+    versions: HashMap<Uuid, (String, u32)>,
+}
+
+impl DataSeriesIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append data as the next version of a series of its workflow,
+    /// creating the series with its first version
+    pub fn append(&mut self, name: &str, data_ref: DataRef) -> Result<&SeriesVersion, SeriesError> {
+        if let Some((series, version)) = self.versions.get(&data_ref.uuid) {
+            return Err(SeriesError::AlreadyVersioned {
+                data_uuid: data_ref.uuid,
+                series: series.clone(),
+                version: *version,
+            });
+        }
+        let key = (data_ref.workflow_id, name.to_string());
+        if !self.series.contains_key(&key) {
+            let series = DataSeries::new(name, data_ref.workflow_id)?;
+            self.series.insert(key.clone(), series);
+        }
+        let data_uuid = data_ref.uuid;
+        let series = self.series.get_mut(&key).expect("series created");
+        let version = series.append(data_ref)?.version;
+        self.versions.insert(data_uuid, (name.to_string(), version));
+        Ok(self.series[&key].latest().expect("version appended"))
+    }
+
+    /// Get a series of a workflow
+    pub fn get(&self, workflow_id: Uuid, name: &str) -> Result<&DataSeries, SeriesError> {
+        self.series
+            .get(&(workflow_id, name.to_string()))
+            .ok_or_else(|| SeriesError::NotFound(name.to_string()))
+    }
+
+    /// Get the series of a workflow, by name
+    pub fn by_workflow(&self, workflow_id: Uuid) -> Vec<&DataSeries> {
+        let mut series: Vec<&DataSeries> = self
+            .series
+            .values()
+            .filter(|series| series.workflow_id == workflow_id)
+            .collect();
+        series.sort_by(|a, b| a.name.cmp(&b.name));
+        series
+    }
+
+    /// Get the series and version number of versioned data
+    pub fn version_of(&self, data_uuid: &Uuid) -> Option<(&str, u32)> {
+        self.versions
+            .get(data_uuid)
+            .map(|(series, version)| (series.as_str(), *version))
+    }
+
+    /// Get the number of series
+    pub fn len(&self) -> usize {
+        self.series.len()
+    }
+
+    /// Check if there are no series
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_series() {
+        let workflow_id = Uuid::new_v4();
+        let draft = DataRef::new("gpu-1".to_string(), 1000, DataType::Json, workflow_id)
+            .with_checksum_of(b"draft")
+            .with_tag("stage", "draft")
+            .with_tag("author", "planner");
+        let mut revised = draft.derive();
+        revised.size_bytes = 1200;
+        revised.checksum = DataRef::new(String::new(), 0, DataType::Json, workflow_id)
+            .with_checksum_of(b"revised")
+            .checksum;
+        revised.tags.remove("author");
+        revised
+            .tags
+            .insert("stage".to_string(), "final".to_string());
+        revised
+            .tags
+            .insert("reviewed".to_string(), "yes".to_string());
+        let (draft_uuid, revised_uuid) = (draft.uuid, revised.uuid);
+
+        let mut index = DataSeriesIndex::new();
+        assert!(matches!(
+            index.append("bad name", draft.clone()),
+            Err(SeriesError::InvalidName(_))
+        ));
+        assert_eq!(index.append("report", draft.clone()).unwrap().version, 1);
+        assert_eq!(index.append("report", revised).unwrap().version, 2);
+        assert!(matches!(
+            index.append("summary", draft.clone()),
+            Err(SeriesError::AlreadyVersioned { version: 1, .. })
+        ));
+        let other = DataRef::new("gpu-1".to_string(), 10, DataType::Json, Uuid::new_v4());
+        assert!(index.get(other.workflow_id, "report").is_err());
+        assert_eq!(index.version_of(&revised_uuid), Some(("report", 2)));
+
+        let series = index.get(workflow_id, "report").unwrap();
+        assert_eq!(series.latest().unwrap().data_ref.uuid, revised_uuid);
+        assert_eq!(series.version(1).unwrap().data_ref.uuid, draft_uuid);
+        assert!(series.version(0).is_err());
+        assert!(series.version(3).is_err());
+        assert_eq!(index.by_workflow(workflow_id).len(), 1);
+
+        let diff = series.diff(1, 2).unwrap();
+        assert_eq!((diff.from_uuid, diff.to_uuid), (draft_uuid, revised_uuid));
+        assert_eq!(diff.size_delta, 200);
+        assert_eq!(diff.content_changed, Some(true));
+        assert!(diff.dtype.is_none());
+        assert_eq!(diff.tags_added["reviewed"], "yes");
+        assert_eq!(diff.tags_removed["author"], "planner");
+        assert_eq!(diff.tags_changed["stage"].to, "final");
+        assert_eq!(series.diff(2, 2).unwrap().content_changed, Some(false));
+
+        // Versions stay within their workflow
+        let mut series = DataSeries::new("report", workflow_id).unwrap();
+        assert!(matches!(
+            series.append(other),
+            Err(SeriesError::WrongWorkflow { .. })
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+//! LLM session lifecycle
+//!
+//! An [`LlmSessionManager`] tracks the active LLM sessions, the server each
+//! one prefers, and the KV cache it has built there. The scheduler reads
+//! session affinities from it, so a session's next turn runs where its
+//! cache already is without affinities being set by hand.
+//!
+//! KV caches hold accelerator memory, so servers can be capped at a number
+//! of sessions. Opening or moving a session onto a full server evicts the
+//! server's least recently active session, and sessions idle longer than
+//! the idle timeout are evicted by [`LlmSessionManager::evict_idle`].
+//! Evicted sessions are returned so the caller can free their caches.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::pointer::{DataRef, DataRefError, LlmSession};
+
+/// Default time a session may go unused before eviction: 30 minutes
+pub const DEFAULT_SESSION_IDLE_TIMEOUT_SECS: i64 = 1800;
+
+/// A tracked session and its activity
+#[derive(Debug, Clone)]
+struct SessionEntry {
+    session: LlmSession,
+    /// When the session was last used
+    last_active: DateTime<Utc>,
+    /// Order of last use, breaking ties between equal timestamps
+    sequence: u64,
+}
+
+/// Tracks active LLM sessions and the servers holding their KV caches
+#[derive(Debug, Clone)]
+pub struct LlmSessionManager {
+    sessions: HashMap<Uuid, SessionEntry>,
+    idle_timeout: Duration,
+    /// Most sessions one server may hold; `None` is unlimited
+    max_sessions_per_server: Option<usize>,
+    next_sequence: u64,
+}
+
+impl Default for LlmSessionManager {
+    fn default() -> Self {
+        Self::new(Duration::seconds(DEFAULT_SESSION_IDLE_TIMEOUT_SECS))
+    }
+}
+
+impl LlmSessionManager {
+    /// Create a manager evicting sessions unused for `idle_timeout`
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            idle_timeout,
+            max_sessions_per_server: None,
+            next_sequence: 0,
+        }
+    }
+
+    /// Cap the number of sessions each server holds
+    pub fn with_server_limit(mut self, max_sessions: usize) -> Self {
+        self.max_sessions_per_server = Some(max_sessions);
+        self
+    }
+
+    /// Open a session on a server
+    ///
+    /// Returns the new session and any session evicted to make room.
+    pub fn open(
+        &mut self,
+        model_id: String,
+        server: String,
+        max_seq_length: usize,
+    ) -> (LlmSession, Vec<LlmSession>) {
+        let session = LlmSession::new(model_id, server, max_seq_length);
+        let evicted = self.make_room(&session.preferred_server, None);
+        let entry = self.entry(session.clone());
+        self.sessions.insert(session.session_id, entry);
+        (session, evicted)
+    }
+
+    /// Start tracking an existing session, e.g. after a restart
+    ///
+    /// Returns any session evicted to make room.
+    pub fn track(&mut self, session: LlmSession) -> Vec<LlmSession> {
+        let evicted = self.make_room(&session.preferred_server, Some(session.session_id));
+        let entry = self.entry(session);
+        self.sessions.insert(entry.session.session_id, entry);
+        evicted
+    }
+
+    /// Record the KV cache a turn left behind
+    ///
+    /// The session moves to the server holding the cache; if that is
+    /// another, full server, its least recently active session is evicted
+    /// and returned.
+    pub fn update_kv_cache(
+        &mut self,
+        session_id: Uuid,
+        kv_cache_ref: DataRef,
+        seq_length: usize,
+    ) -> Result<Vec<LlmSession>, DataRefError> {
+        if !self.sessions.contains_key(&session_id) {
+            return Err(DataRefError::SessionNotFound(session_id));
+        }
+        let evicted = self.make_room(&kv_cache_ref.location, Some(session_id));
+        self.touch(session_id)?;
+        let entry = self.sessions.get_mut(&session_id).expect("session tracked");
+        entry.session.update_kv_cache(kv_cache_ref, seq_length);
+        Ok(evicted)
+    }
+
+    /// Mark a session as used, postponing its eviction
+    pub fn touch(&mut self, session_id: Uuid) -> Result<(), DataRefError> {
+        let sequence = self.next_sequence();
+        let entry = self
+            .sessions
+            .get_mut(&session_id)
+            .ok_or(DataRefError::SessionNotFound(session_id))?;
+        entry.last_active = Utc::now();
+        entry.sequence = sequence;
+        Ok(())
+    }
+
+    /// Stop tracking a finished session
+    pub fn close(&mut self, session_id: &Uuid) -> Option<LlmSession> {
+        self.sessions.remove(session_id).map(|entry| entry.session)
+    }
+
+    /// Evict sessions unused for the idle timeout as of `now`
+    pub fn evict_idle(&mut self, now: DateTime<Utc>) -> Vec<LlmSession> {
+        let idle: Vec<Uuid> = self
+            .sessions
+            .values()
+            .filter(|entry| now - entry.last_active >= self.idle_timeout)
+            .map(|entry| entry.session.session_id)
+            .collect();
+        idle.iter().filter_map(|id| self.close(id)).collect()
+    }
+
+    /// Evict every session on a server, e.g. after it went away with its
+    /// caches
+    pub fn evict_server(&mut self, server: &str) -> Vec<LlmSession> {
+        let on_server: Vec<Uuid> = self
+            .sessions_on(server)
+            .map(|session| session.session_id)
+            .collect();
+        on_server.iter().filter_map(|id| self.close(id)).collect()
+    }
+
+    /// Get a session
+    pub fn get(&self, session_id: &Uuid) -> Option<&LlmSession> {
+        self.sessions.get(session_id).map(|entry| &entry.session)
+    }
+
+    /// Get the server a session prefers
+    pub fn affinity(&self, session_id: &Uuid) -> Option<&String> {
+        self.get(session_id)
+            .map(|session| &session.preferred_server)
+    }
+
+    /// Get the sessions on a server
+    pub fn sessions_on<'a>(&'a self, server: &'a str) -> impl Iterator<Item = &'a LlmSession> {
+        self.sessions
+            .values()
+            .map(|entry| &entry.session)
+            .filter(move |session| session.preferred_server == server)
+    }
+
+    /// Get all tracked sessions
+    pub fn sessions(&self) -> impl Iterator<Item = &LlmSession> {
+        self.sessions.values().map(|entry| &entry.session)
+    }
+
+    /// Get the number of tracked sessions
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Check if no session is tracked
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Evict least recently active sessions until `server` has room for one
+    /// more, not counting `arriving` if it is already there
+    fn make_room(&mut self, server: &str, arriving: Option<Uuid>) -> Vec<LlmSession> {
+        let Some(limit) = self.max_sessions_per_server else {
+            return Vec::new();
+        };
+        let mut on_server: Vec<&SessionEntry> = self
+            .sessions
+            .values()
+            .filter(|entry| entry.session.preferred_server == server)
+            .filter(|entry| Some(entry.session.session_id) != arriving)
+            .collect();
+        let excess = (on_server.len() + 1).saturating_sub(limit);
+        on_server.sort_by_key(|entry| (entry.last_active, entry.sequence));
+        let victims: Vec<Uuid> = on_server
+            .iter()
+            .take(excess)
+            .map(|entry| entry.session.session_id)
+            .collect();
+        victims.iter().filter_map(|id| self.close(id)).collect()
+    }
+
+    fn entry(&mut self, session: LlmSession) -> SessionEntry {
+        SessionEntry {
+            session,
+            last_active: Utc::now(),
+            sequence: self.next_sequence(),
+        }
+    }
+
+    fn next_sequence(&mut self) -> u64 {
+        self.next_sequence += 1;
+        self.next_sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+
+    fn kv_cache(server: &str, seq_len: usize) -> DataRef {
+        DataRef::new(
+            server.to_string(),
+            1 << 20,
+            DataType::KvCache {
+                model_id: "deepseek-coder".to_string(),
+                seq_len,
+            },
+            Uuid::new_v4(),
+        )
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let mut manager = LlmSessionManager::new(Duration::minutes(30));
+        let (session, evicted) =
+            manager.open("deepseek-coder".to_string(), "gpu-1".to_string(), 4096);
+        assert!(evicted.is_empty());
+        assert_eq!(session.seq_length, 0);
+        assert_eq!(manager.affinity(&session.session_id).unwrap(), "gpu-1");
+
+        // The session follows its cache
+        manager
+            .update_kv_cache(session.session_id, kv_cache("gpu-2", 512), 512)
+            .unwrap();
+        let tracked = manager.get(&session.session_id).unwrap();
+        assert_eq!(tracked.preferred_server, "gpu-2");
+        assert_eq!(tracked.seq_length, 512);
+        assert!(tracked.has_capacity(3584));
+        assert!(!tracked.has_capacity(3585));
+
+        assert!(manager
+            .evict_idle(Utc::now() + Duration::minutes(29))
+            .is_empty());
+        let evicted = manager.evict_idle(Utc::now() + Duration::minutes(31));
+        assert_eq!(evicted.len(), 1);
+        assert!(manager.is_empty());
+        assert!(matches!(
+            manager.update_kv_cache(session.session_id, kv_cache("gpu-2", 600), 600),
+            Err(DataRefError::SessionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_server_limit_evicts_least_recently_active() {
+        let mut manager = LlmSessionManager::default().with_server_limit(2);
+        let model = || "deepseek-coder".to_string();
+        let (first, _) = manager.open(model(), "gpu-1".to_string(), 4096);
+        let (second, _) = manager.open(model(), "gpu-1".to_string(), 4096);
+        manager.touch(first.session_id).unwrap();
+
+        let (third, evicted) = manager.open(model(), "gpu-1".to_string(), 4096);
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].session_id, second.session_id);
+        assert_eq!(manager.sessions_on("gpu-1").count(), 2);
+
+        // Moving onto a full server makes room there too
+        let (other, _) = manager.open(model(), "gpu-2".to_string(), 4096);
+        let evicted = manager
+            .update_kv_cache(other.session_id, kv_cache("gpu-1", 128), 128)
+            .unwrap();
+        assert_eq!(evicted[0].session_id, first.session_id);
+        assert!(manager.get(&third.session_id).is_some());
+        assert_eq!(manager.evict_server("gpu-1").len(), 2);
+    }
+}
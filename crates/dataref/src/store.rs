@@ -0,0 +1,1102 @@
+//! Local disk-backed data store
+//!
+//! [`LocalDataStore`] keeps data objects as files in a directory, one per
+//! UUID, next to an `index.json` mapping each UUID to its [`DataRef`]. The
+//! index is rewritten atomically on every change, so a crash leaves either
+//! the old or the new index, and objects without an index entry are
+//! ignored. Objects can be read and written whole or streamed through
+//! [`std::io::Read`], which keeps large uploads out of memory, and read
+//! from any [`ByteRange`]. With the `stream` feature, [`AsyncDataStore`]
+//! streams objects in and out of async code.
+//!
+//! [`AsyncDataStore`]: crate::stream::AsyncDataStore
+//!
+//! In content-addressing mode, payloads are checksummed with BLAKE3 and
+//! stored once per distinct content: every ref with the same
+//! [content address](DataRef::content_address) shares one object, which is
+//! deleted with the last of them.
+//!
+//! [Derived](LocalDataStore::derive) data shares its parent's object
+//! without copying it. Objects are only duplicated on a divergent write:
+//! data written while derived data still shares its object hands the old
+//! object over to the derived data first, so writes through
+//! [`LocalDataStore::write_at`] never affect other refs.
+//!
+//! With [`LocalDataStore::with_encryption`], objects are encrypted at rest
+//! with a per-workflow data key; see [`encryption`](crate::encryption).
+//! The wrapped keys are kept in `keys.json`, and reads decrypt
+//! transparently. Encrypted objects are never shared by content address.
+//!
+//! With [`LocalDataStore::with_compression`], compressible payloads are
+//! compressed with zstd before they are encrypted; see
+//! [`compression`](crate::compression). Checksums and `size_bytes` always
+//! describe the logical payload, and reads decompress transparently.
+//!
+//! [`LocalDataStore::verify`] re-reads stored data against its checksum,
+//! and [`LocalDataStore::quarantine`] moves corrupt objects aside into a
+//! `quarantine` directory; see [`scrub`](crate::scrub).
+//!
+//! Tasks on the store's host can read plain objects in place through
+//! [`LocalDataStore::local_path`], or, with the `mmap` feature, map them
+//! into memory with `LocalDataStore::map`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::compression::Compression;
+use crate::encryption::{
+    generate_data_key, DecryptingReader, EncryptionError, KeyProvider, ObjectSealer, WrappedKey,
+    FRAME_SIZE,
+};
+#[cfg(doc)]
+use crate::pointer::DataType;
+use crate::pointer::{format_blake3, format_sha256, DataRef, StorageTier, BLAKE3_PREFIX};
+use crate::token::{AccessToken, Permissions, TokenError, TokenManager};
+
+/// Name of the metadata index within the store directory
+const INDEX_FILE: &str = "index.json";
+
+/// Name of the wrapped data keys within the store directory
+const KEYRING_FILE: &str = "keys.json";
+
+/// Name of the directory corrupt objects are moved to
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Size of the buffer used when streaming objects in
+pub(crate) const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Data store errors
+#[derive(Debug, thiserror::Error)]
+pub enum DataStoreError {
+    #[error("Data not found: {0}")]
+    NotFound(Uuid),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Index error: {0}")]
+    Index(#[from] serde_json::Error),
+
+    #[error("Encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(#[from] TokenError),
+
+    #[error("Range starting at byte {offset} is outside the {size}-byte payload")]
+    InvalidRange { offset: u64, size: u64 },
+
+    #[error("Checksum mismatch for {uuid}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        uuid: Uuid,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Data {0} is encrypted or compressed at rest and cannot be read directly")]
+    Encoded(Uuid),
+}
+
+/// Part of a payload to read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ByteRange {
+    /// First byte to read
+    pub offset: u64,
+    /// Most bytes to read; `None` reads to the end
+    pub len: Option<u64>,
+}
+
+impl ByteRange {
+    /// The whole payload
+    pub fn full() -> Self {
+        Self::default()
+    }
+
+    /// `len` bytes starting at `offset`
+    pub fn new(offset: u64, len: u64) -> Self {
+        Self {
+            offset,
+            len: Some(len),
+        }
+    }
+
+    /// Everything from `offset` on
+    pub fn from_offset(offset: u64) -> Self {
+        Self { offset, len: None }
+    }
+
+    /// Bytes the range covers within a payload of `size` bytes
+    pub fn len_within(&self, size: u64) -> u64 {
+        let available = size.saturating_sub(self.offset);
+        self.len.map_or(available, |len| len.min(available))
+    }
+}
+
+/// Data objects stored as files in a local directory
+#[derive(Debug)]
+pub struct LocalDataStore {
+    dir: PathBuf,
+    /// Address stored data is reachable at, recorded as its location
+    location: String,
+    /// Whether new objects are stored by content address
+    content_addressed: bool,
+    /// Data keys of encrypted objects; new objects are encrypted if set
+    keyring: Option<DataKeyring>,
+    /// zstd level new compressible objects are compressed with, if any
+    compression_level: Option<i32>,
+    index: RwLock<HashMap<Uuid, DataRef>>,
+}
+
+impl LocalDataStore {
+    /// Open a store in `dir`, creating the directory if needed
+    ///
+    /// `location` is the address other components fetch stored data from,
+    /// e.g. the API's base URL.
+    pub fn open(dir: impl AsRef<Path>, location: &str) -> Result<Self, DataStoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let index = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            dir,
+            location: location.to_string(),
+            content_addressed: false,
+            keyring: None,
+            compression_level: None,
+            index: RwLock::new(index),
+        })
+    }
+
+    /// Store new payloads by BLAKE3 content address, keeping one object per
+    /// distinct content
+    ///
+    /// Objects stored before keep their SHA-256 checksums and files.
+    pub fn with_content_addressing(mut self) -> Self {
+        self.content_addressed = true;
+        self
+    }
+
+    /// Encrypt new objects with per-workflow data keys wrapped by
+    /// `provider`
+    ///
+    /// Objects stored before stay readable as they are. Data keys wrapped
+    /// earlier must still be unwrappable by `provider`.
+    pub fn with_encryption(
+        mut self,
+        provider: Arc<dyn KeyProvider>,
+    ) -> Result<Self, DataStoreError> {
+        self.keyring = Some(DataKeyring::open(self.dir.join(KEYRING_FILE), provider)?);
+        Ok(self)
+    }
+
+    /// Compress new payloads of [compressible](DataType::is_compressible)
+    /// types with zstd at `level`, e.g.
+    /// [`DEFAULT_COMPRESSION_LEVEL`](crate::compression::DEFAULT_COMPRESSION_LEVEL)
+    ///
+    /// Objects stored before stay readable as they are.
+    pub fn with_compression(mut self, level: i32) -> Self {
+        self.compression_level = Some(level);
+        self
+    }
+
+    /// Address stored data is reachable at
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Store a payload
+    ///
+    /// The stored ref is `data_ref` located in this store on disk, with the
+    /// payload's size and checksum. In content-addressing mode a payload
+    /// already in the store is not written again.
+    pub fn put(&self, data_ref: DataRef, payload: &[u8]) -> Result<DataRef, DataStoreError> {
+        self.put_stream(data_ref, payload)
+    }
+
+    /// Store a payload read from `reader`, without buffering it whole
+    pub fn put_stream(
+        &self,
+        mut data_ref: DataRef,
+        mut reader: impl Read,
+    ) -> Result<DataRef, DataStoreError> {
+        // Write to a temporary file so readers never see a partial object
+        let partial = self.dir.join(format!("{}.partial", data_ref.uuid));
+        let mut file = BufWriter::new(File::create(&partial)?);
+        let mut hasher = Hasher::new(self.content_addressed);
+        let mut size_bytes = 0u64;
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        let sealer = match &self.keyring {
+            Some(keyring) => {
+                let (key_id, data_key) = keyring.workflow_key(data_ref.workflow_id)?;
+                data_ref.encryption_key = Some(key_id);
+                Some(ObjectSealer::start(&data_key, &mut file)?)
+            }
+            None => None,
+        };
+        let sink = ObjectSink {
+            file,
+            sealer,
+            frame: Vec::new(),
+            written: 0,
+        };
+        let level = self
+            .compression_level
+            .filter(|_| data_ref.dtype.is_compressible());
+        let mut object = match level {
+            Some(level) => ObjectWriter::Zstd(zstd::stream::write::Encoder::new(sink, level)?),
+            None => ObjectWriter::Plain(sink),
+        };
+        loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let _ = fs::remove_file(&partial);
+                    return Err(e.into());
+                }
+            };
+            hasher.update(&buffer[..n]);
+            size_bytes += n as u64;
+            object.write_all(&buffer[..n])?;
+        }
+        let compressed = matches!(object, ObjectWriter::Zstd(_));
+        let stored_bytes = object.finish()?.finish()?;
+        if compressed {
+            data_ref.compression = Some(Compression::Zstd);
+            data_ref.stored_bytes = Some(stored_bytes);
+        } else {
+            data_ref.compression = None;
+            data_ref.stored_bytes = None;
+        }
+
+        data_ref.location = self.location.clone();
+        data_ref.storage_tier = StorageTier::Disk;
+        data_ref.size_bytes = size_bytes;
+        data_ref.checksum = Some(hasher.finalize());
+        // Written data gets an object of its own
+        data_ref.shared_from = None;
+
+        // Hold the index lock so a concurrent delete of the last ref to the
+        // same content cannot remove the object under us
+        let mut index = self.index.write().expect("index lock poisoned");
+        let path = self.object_path(&data_ref);
+        let previous = index.get(&data_ref.uuid).map(|old| self.object_path(old));
+        let existing = data_ref.content_address().and_then(|address| {
+            index
+                .values()
+                .find(|other| other.content_address() == Some(address))
+        });
+        if let Some(existing) = existing.filter(|_| path.exists()) {
+            // The shared object is kept as it was first stored
+            data_ref.compression = existing.compression;
+            data_ref.stored_bytes = existing.stored_bytes;
+            fs::remove_file(&partial)?;
+        } else {
+            if data_ref.content_address().is_none() {
+                self.hand_over_object(&mut index, data_ref.uuid, &path)?;
+            }
+            fs::rename(&partial, &path)?;
+        }
+        index.insert(data_ref.uuid, data_ref.clone());
+        self.save_index(&index)?;
+        // Rewritten content-addressed data may have moved to another object
+        if let Some(previous) = previous.filter(|previous| *previous != path) {
+            self.remove_unused_object(&index, &previous)?;
+        }
+        Ok(data_ref)
+    }
+
+    /// Derive new data from stored data, sharing its object
+    ///
+    /// No bytes are copied; see [`DataRef::derive`]. The derived data reads
+    /// its parent's object until either of them is written.
+    pub fn derive(&self, parent: &Uuid) -> Result<DataRef, DataStoreError> {
+        let mut index = self.index.write().expect("index lock poisoned");
+        let derived = index
+            .get(parent)
+            .ok_or(DataStoreError::NotFound(*parent))?
+            .derive();
+        index.insert(derived.uuid, derived.clone());
+        self.save_index(&index)?;
+        Ok(derived)
+    }
+
+    /// Overwrite part of stored data with `bytes` at `offset`, extending it
+    /// if the write runs past its end
+    ///
+    /// The payload is rewritten through [`put_stream`](Self::put_stream),
+    /// so the data gets an object of its own and a new checksum, and any
+    /// data sharing its old object keeps reading the old content. The
+    /// offset may be the end of the payload, appending, but not beyond it.
+    pub fn write_at(
+        &self,
+        uuid: &Uuid,
+        offset: u64,
+        bytes: &[u8],
+    ) -> Result<DataRef, DataStoreError> {
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        if offset > data_ref.size_bytes {
+            return Err(DataStoreError::InvalidRange {
+                offset,
+                size: data_ref.size_bytes,
+            });
+        }
+        let end = offset
+            .saturating_add(bytes.len() as u64)
+            .min(data_ref.size_bytes);
+        let head = self.open_range(uuid, ByteRange::new(0, offset))?;
+        let tail = self.open_range(uuid, ByteRange::from_offset(end))?;
+        self.put_stream(data_ref, head.chain(bytes).chain(tail))
+    }
+
+    /// Get the ref of stored data
+    pub fn get_ref(&self, uuid: &Uuid) -> Option<DataRef> {
+        self.index
+            .read()
+            .expect("index lock poisoned")
+            .get(uuid)
+            .cloned()
+    }
+
+    /// Read a stored payload whole
+    pub fn get(&self, uuid: &Uuid) -> Result<Vec<u8>, DataStoreError> {
+        let mut payload = Vec::new();
+        self.open_object(uuid)?.read_to_end(&mut payload)?;
+        Ok(payload)
+    }
+
+    /// Open a stored payload for streaming, decrypting and decompressing
+    /// it if needed
+    pub fn open_object(&self, uuid: &Uuid) -> Result<Box<dyn Read + Send>, DataStoreError> {
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        let file = self.open_file(&data_ref)?;
+        self.decode(&data_ref, file)
+    }
+
+    /// Open part of a stored payload for streaming
+    ///
+    /// Plain objects are read from the range's offset directly; encrypted
+    /// or compressed ones are decoded from the start and the bytes before
+    /// the offset skipped. A range may start at the end of the payload,
+    /// reading nothing, but not beyond it.
+    pub fn open_range(
+        &self,
+        uuid: &Uuid,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + Send>, DataStoreError> {
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        if range.offset > data_ref.size_bytes {
+            return Err(DataStoreError::InvalidRange {
+                offset: range.offset,
+                size: data_ref.size_bytes,
+            });
+        }
+        let mut file = self.open_file(&data_ref)?;
+        let reader = if data_ref.encryption_key.is_none() && data_ref.compression.is_none() {
+            file.seek(SeekFrom::Start(range.offset))?;
+            Box::new(file)
+        } else {
+            let mut reader = self.decode(&data_ref, file)?;
+            io::copy(&mut reader.by_ref().take(range.offset), &mut io::sink())?;
+            reader
+        };
+        match range.len {
+            Some(len) => Ok(Box::new(reader.take(len))),
+            None => Ok(reader),
+        }
+    }
+
+    /// Path of the file holding stored data, for tasks on the store's host
+    /// to read without copying it
+    ///
+    /// Only data stored as it is can be read this way; encrypted or
+    /// compressed data fails with [`DataStoreError::Encoded`]. Object files
+    /// are never modified in place: writes replace them with a new file,
+    /// so a reader that opened or mapped the path keeps seeing consistent
+    /// content. Readers must not write to the file.
+    pub fn local_path(&self, uuid: &Uuid) -> Result<PathBuf, DataStoreError> {
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        if data_ref.encryption_key.is_some() || data_ref.compression.is_some() {
+            return Err(DataStoreError::Encoded(*uuid));
+        }
+        Ok(self.object_path(&data_ref))
+    }
+
+    /// Re-read stored data and check it against its recorded checksum
+    ///
+    /// Returns the checksum of what was read, or `None` if the ref has no
+    /// checksum to check against. Objects that fail to decrypt or
+    /// decompress fail with an [`io::ErrorKind::InvalidData`] error.
+    pub fn verify(&self, uuid: &Uuid) -> Result<Option<String>, DataStoreError> {
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        match data_ref.checksum {
+            Some(expected) => self.verify_checksum(uuid, &expected).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Re-read stored data and check it against `expected`, which may use
+    /// another algorithm than this store, e.g. when data was copied from
+    /// a content-addressed store
+    ///
+    /// Returns the checksum of what was read.
+    pub fn verify_checksum(&self, uuid: &Uuid, expected: &str) -> Result<String, DataStoreError> {
+        let mut reader = self.open_object(uuid)?;
+        let mut hasher = Hasher::matching(expected);
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let actual = hasher.finalize();
+        if actual == expected {
+            Ok(actual)
+        } else {
+            Err(DataStoreError::ChecksumMismatch {
+                uuid: *uuid,
+                expected: expected.to_string(),
+                actual,
+            })
+        }
+    }
+
+    /// Move the object of stored data out of the store, returning the refs
+    /// that pointed at it
+    ///
+    /// The object is kept in the `quarantine` directory for inspection,
+    /// next to a JSON list of its refs, and is no longer served. Refs
+    /// sharing the object by content address are quarantined with it.
+    pub fn quarantine(&self, uuid: &Uuid) -> Result<Vec<DataRef>, DataStoreError> {
+        let mut index = self.index.write().expect("index lock poisoned");
+        let data_ref = index.get(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        let path = self.object_path(data_ref);
+        let quarantined: Vec<DataRef> = index
+            .values()
+            .filter(|other| self.object_path(other) == path)
+            .cloned()
+            .collect();
+        let dir = self.dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&dir)?;
+        let name = path.file_name().expect("object paths end in a file name");
+        match fs::rename(&path, dir.join(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut refs_name = name.to_os_string();
+        refs_name.push(".json");
+        fs::write(dir.join(refs_name), serde_json::to_vec(&quarantined)?)?;
+        for data_ref in &quarantined {
+            index.remove(&data_ref.uuid);
+        }
+        self.save_index(&index)?;
+        Ok(quarantined)
+    }
+
+    /// Read a stored payload whole on behalf of a token holder
+    ///
+    /// The token must be valid and grant read access to the data.
+    pub fn get_with_token(
+        &self,
+        uuid: &Uuid,
+        token: &AccessToken,
+        tokens: &TokenManager,
+    ) -> Result<Vec<u8>, DataStoreError> {
+        let data_ref = self.get_ref(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        tokens.authorize(token, [&data_ref], Permissions::read_only())?;
+        self.get(uuid)
+    }
+
+    /// Delete stored data, returning its ref
+    ///
+    /// An object is kept while other refs share it, by content address or
+    /// because they were derived from the data.
+    pub fn delete(&self, uuid: &Uuid) -> Result<DataRef, DataStoreError> {
+        let mut index = self.index.write().expect("index lock poisoned");
+        let data_ref = index.remove(uuid).ok_or(DataStoreError::NotFound(*uuid))?;
+        self.save_index(&index)?;
+        self.remove_unused_object(&index, &self.object_path(&data_ref))?;
+        Ok(data_ref)
+    }
+
+    /// List stored data
+    pub fn list(&self) -> Vec<DataRef> {
+        self.index
+            .read()
+            .expect("index lock poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Open the object file of a ref
+    fn open_file(&self, data_ref: &DataRef) -> Result<File, DataStoreError> {
+        File::open(self.object_path(data_ref)).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => DataStoreError::NotFound(data_ref.uuid),
+            _ => e.into(),
+        })
+    }
+
+    /// Decrypt and decompress an object file as its ref says
+    fn decode(
+        &self,
+        data_ref: &DataRef,
+        file: File,
+    ) -> Result<Box<dyn Read + Send>, DataStoreError> {
+        let reader: Box<dyn Read + Send> = match data_ref.encryption_key {
+            Some(key_id) => {
+                let data_key = self
+                    .keyring
+                    .as_ref()
+                    .ok_or(EncryptionError::UnknownDataKey(key_id))?
+                    .key(key_id)?;
+                Box::new(DecryptingReader::new(file, data_key.to_vec()))
+            }
+            None => Box::new(file),
+        };
+        match data_ref.compression {
+            Some(compression) => Ok(compression.decoder(reader)?),
+            None => Ok(reader),
+        }
+    }
+
+    /// Path of a ref's object: its content address, or else the UUID of
+    /// the data it shares its object with
+    fn object_path(&self, data_ref: &DataRef) -> PathBuf {
+        match data_ref.content_address() {
+            Some(address) => self.dir.join(address.replace(':', "-")),
+            None => self.dir.join(data_ref.storage_uuid().to_string()),
+        }
+    }
+
+    /// Hand the object at `path` over to the other refs sharing it, before
+    /// `writer` replaces it
+    ///
+    /// The sharer with the lowest UUID takes the object as its own and the
+    /// others share it from there.
+    fn hand_over_object(
+        &self,
+        index: &mut HashMap<Uuid, DataRef>,
+        writer: Uuid,
+        path: &Path,
+    ) -> Result<(), DataStoreError> {
+        let sharers: Vec<Uuid> = index
+            .values()
+            .filter(|other| other.uuid != writer && self.object_path(other) == path)
+            .map(|other| other.uuid)
+            .collect();
+        let Some(&heir) = sharers.iter().min() else {
+            return Ok(());
+        };
+        match fs::rename(path, self.dir.join(heir.to_string())) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        for uuid in sharers {
+            if let Some(sharer) = index.get_mut(&uuid) {
+                sharer.shared_from = (uuid != heir).then_some(heir);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the object at `path` unless a ref in `index` still uses it
+    fn remove_unused_object(
+        &self,
+        index: &HashMap<Uuid, DataRef>,
+        path: &Path,
+    ) -> Result<(), DataStoreError> {
+        if index.values().any(|other| self.object_path(other) == path) {
+            return Ok(());
+        }
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replace the index file with the given entries
+    fn save_index(&self, index: &HashMap<Uuid, DataRef>) -> Result<(), DataStoreError> {
+        let path = self.dir.join(INDEX_FILE);
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec(index)?)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
+
+/// Destination of an object's stored bytes: the partial file, through the
+/// sealer if the object is encrypted
+struct ObjectSink {
+    file: BufWriter<File>,
+    sealer: Option<ObjectSealer>,
+    /// Bytes not yet sealed; the last frame is only known at the end
+    frame: Vec<u8>,
+    /// Bytes written, before encryption
+    written: u64,
+}
+
+impl ObjectSink {
+    /// Seal the last frame and sync the file, returning the bytes written
+    fn finish(mut self) -> io::Result<u64> {
+        if let Some(sealer) = self.sealer.as_mut() {
+            sealer.seal(&self.frame, true, &mut self.file)?;
+        }
+        self.file
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        Ok(self.written)
+    }
+}
+
+impl Write for ObjectSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.sealer.as_mut() {
+            Some(sealer) => {
+                self.frame.extend_from_slice(buf);
+                while self.frame.len() > FRAME_SIZE {
+                    sealer.seal(&self.frame[..FRAME_SIZE], false, &mut self.file)?;
+                    self.frame.drain(..FRAME_SIZE);
+                }
+            }
+            None => self.file.write_all(buf)?,
+        }
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Payload being stored, compressed on its way to the sink if enabled
+enum ObjectWriter {
+    Plain(ObjectSink),
+    Zstd(zstd::stream::write::Encoder<'static, ObjectSink>),
+}
+
+impl ObjectWriter {
+    fn finish(self) -> io::Result<ObjectSink> {
+        match self {
+            ObjectWriter::Plain(sink) => Ok(sink),
+            ObjectWriter::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ObjectWriter::Plain(sink) => sink.write(buf),
+            ObjectWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ObjectWriter::Plain(sink) => sink.flush(),
+            ObjectWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// A data key as persisted: wrapped, and bound to its workflow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredDataKey {
+    workflow_id: Uuid,
+    wrapped: WrappedKey,
+}
+
+/// Per-workflow data keys of a store
+struct DataKeyring {
+    path: PathBuf,
+    provider: Arc<dyn KeyProvider>,
+    /// Wrapped keys by ID, as in the keyring file
+    wrapped: Mutex<HashMap<Uuid, StoredDataKey>>,
+    /// Keys unwrapped so far, so the provider is asked once per key
+    unwrapped: Mutex<HashMap<Uuid, Arc<[u8]>>>,
+}
+
+impl std::fmt::Debug for DataKeyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataKeyring")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DataKeyring {
+    fn open(path: PathBuf, provider: Arc<dyn KeyProvider>) -> Result<Self, DataStoreError> {
+        let wrapped = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            provider,
+            wrapped: Mutex::new(wrapped),
+            unwrapped: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// ID and key of a workflow's data key, creating it on first use
+    fn workflow_key(&self, workflow_id: Uuid) -> Result<(Uuid, Arc<[u8]>), DataStoreError> {
+        let mut wrapped = self.wrapped.lock().expect("keyring lock poisoned");
+        if let Some((&key_id, _)) = wrapped.iter().find(|(_, k)| k.workflow_id == workflow_id) {
+            drop(wrapped);
+            return Ok((key_id, self.key(key_id)?));
+        }
+        let data_key: Arc<[u8]> = generate_data_key()?.into();
+        let key_id = Uuid::new_v4();
+        wrapped.insert(
+            key_id,
+            StoredDataKey {
+                workflow_id,
+                wrapped: self.provider.wrap(&data_key)?,
+            },
+        );
+        // Persist before any object depends on the key
+        let partial = self.path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec(&*wrapped)?)?;
+        fs::rename(&partial, &self.path)?;
+        self.unwrapped
+            .lock()
+            .expect("keyring lock poisoned")
+            .insert(key_id, data_key.clone());
+        Ok((key_id, data_key))
+    }
+
+    /// A data key by ID
+    fn key(&self, key_id: Uuid) -> Result<Arc<[u8]>, DataStoreError> {
+        if let Some(key) = self
+            .unwrapped
+            .lock()
+            .expect("keyring lock poisoned")
+            .get(&key_id)
+        {
+            return Ok(key.clone());
+        }
+        let stored = self
+            .wrapped
+            .lock()
+            .expect("keyring lock poisoned")
+            .get(&key_id)
+            .cloned()
+            .ok_or(EncryptionError::UnknownDataKey(key_id))?;
+        let key: Arc<[u8]> = self.provider.unwrap(&stored.wrapped)?.into();
+        self.unwrapped
+            .lock()
+            .expect("keyring lock poisoned")
+            .insert(key_id, key.clone());
+        Ok(key)
+    }
+}
+
+/// Checksum of a payload being stored
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub(crate) fn new(content_addressed: bool) -> Self {
+        if content_addressed {
+            Hasher::Blake3(Box::new(blake3::Hasher::new()))
+        } else {
+            Hasher::Sha256(Sha256::new())
+        }
+    }
+
+    /// Hasher producing checksums comparable to `checksum`
+    pub(crate) fn matching(checksum: &str) -> Self {
+        Self::new(checksum.starts_with(BLAKE3_PREFIX))
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    pub(crate) fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format_sha256(&hasher.finalize()),
+            Hasher::Blake3(hasher) => format_blake3(&hasher.finalize()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{sha256_checksum, DataType};
+
+    #[test]
+    fn test_local_data_store() {
+        let dir = std::env::temp_dir().join(format!("swarmx-store-{}", Uuid::new_v4()));
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api").unwrap();
+        let workflow_id = Uuid::new_v4();
+
+        let data = DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id);
+        let stored = store.put(data, b"hello").unwrap();
+        assert_eq!(stored.location, "http://localhost:3000/api");
+        assert_eq!(stored.storage_tier, StorageTier::Disk);
+        assert_eq!(stored.size_bytes, 5);
+        stored.verify_checksum(b"hello").unwrap();
+        assert_eq!(store.get(&stored.uuid).unwrap(), b"hello");
+
+        let large = vec![7u8; 3 * COPY_BUFFER_SIZE + 1];
+        let data = DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id);
+        let streamed = store.put_stream(data, large.as_slice()).unwrap();
+        assert_eq!(streamed.checksum, Some(sha256_checksum(&large)));
+
+        // The index survives reopening
+        drop(store);
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api").unwrap();
+        assert_eq!(store.list().len(), 2);
+        let mut reader = store.open_object(&streamed.uuid).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, large);
+
+        assert_eq!(store.delete(&stored.uuid).unwrap().uuid, stored.uuid);
+        assert!(matches!(
+            store.get(&stored.uuid),
+            Err(DataStoreError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.delete(&stored.uuid),
+            Err(DataStoreError::NotFound(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_range_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalDataStore::open(dir.path(), "http://localhost:3000/api").unwrap();
+        let data = DataRef::new("client".to_string(), 0, DataType::Bytes, Uuid::new_v4());
+        let stored = store.put(data, b"0123456789").unwrap();
+        let read = |range| {
+            let mut read = Vec::new();
+            store
+                .open_range(&stored.uuid, range)
+                .unwrap()
+                .read_to_end(&mut read)
+                .unwrap();
+            read
+        };
+
+        assert_eq!(read(ByteRange::new(2, 3)), b"234");
+        assert_eq!(read(ByteRange::from_offset(7)), b"789");
+        assert_eq!(read(ByteRange::new(8, 100)), b"89");
+        assert!(read(ByteRange::from_offset(10)).is_empty());
+        assert_eq!(ByteRange::new(8, 100).len_within(10), 2);
+        assert!(matches!(
+            store.open_range(&stored.uuid, ByteRange::from_offset(11)),
+            Err(DataStoreError::InvalidRange {
+                offset: 11,
+                size: 10
+            })
+        ));
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalDataStore::open(dir.path(), "http://localhost:3000/api").unwrap();
+        let data = DataRef::new("client".to_string(), 0, DataType::Bytes, Uuid::new_v4());
+        let parent = store.put(data, b"0123456789").unwrap();
+
+        // Deriving copies no bytes
+        let derived = store.derive(&parent.uuid).unwrap();
+        assert_eq!(derived.parents, vec![parent.uuid]);
+        assert_eq!(derived.shared_from, Some(parent.uuid));
+        assert_eq!(store.get(&derived.uuid).unwrap(), b"0123456789");
+        assert!(!dir.path().join(derived.uuid.to_string()).exists());
+        let sibling = store.derive(&derived.uuid).unwrap();
+        assert_eq!(sibling.shared_from, Some(parent.uuid));
+
+        // A divergent write materializes the derived data's own object
+        let written = store.write_at(&derived.uuid, 8, b"XYZ").unwrap();
+        assert_eq!(written.shared_from, None);
+        assert_eq!(written.size_bytes, 11);
+        written.verify_checksum(b"01234567XYZ").unwrap();
+        assert_eq!(store.get(&derived.uuid).unwrap(), b"01234567XYZ");
+        assert_eq!(store.get(&parent.uuid).unwrap(), b"0123456789");
+        assert_eq!(store.get(&sibling.uuid).unwrap(), b"0123456789");
+
+        // Writing the parent hands its old object to the data sharing it
+        store.write_at(&parent.uuid, 0, b"ab").unwrap();
+        assert_eq!(store.get(&parent.uuid).unwrap(), b"ab23456789");
+        assert_eq!(store.get(&sibling.uuid).unwrap(), b"0123456789");
+        assert_eq!(store.get_ref(&sibling.uuid).unwrap().shared_from, None);
+
+        // Shared objects outlive the data they were derived from
+        let shared = store.derive(&parent.uuid).unwrap();
+        store.delete(&parent.uuid).unwrap();
+        assert_eq!(store.get(&shared.uuid).unwrap(), b"ab23456789");
+        store.delete(&shared.uuid).unwrap();
+        assert!(!dir.path().join(parent.uuid.to_string()).exists());
+
+        assert!(matches!(
+            store.write_at(&sibling.uuid, 11, b"!"),
+            Err(DataStoreError::InvalidRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_content_addressing() {
+        let dir = std::env::temp_dir().join(format!("swarmx-store-{}", Uuid::new_v4()));
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api")
+            .unwrap()
+            .with_content_addressing();
+        let workflow_id = Uuid::new_v4();
+        let new_ref = || DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id);
+
+        let first = store.put(new_ref(), b"artifact").unwrap();
+        let second = store.put(new_ref(), b"artifact").unwrap();
+        let other = store.put(new_ref(), b"other").unwrap();
+        assert_ne!(first.uuid, second.uuid);
+        assert_eq!(first.content_address(), second.content_address());
+        assert_ne!(first.content_address(), other.content_address());
+        second.verify_checksum(b"artifact").unwrap();
+
+        // One object per distinct content, plus the index
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+        // The shared object outlives the first of its refs
+        store.delete(&first.uuid).unwrap();
+        assert_eq!(store.get(&second.uuid).unwrap(), b"artifact");
+        store.delete(&second.uuid).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compression() {
+        let dir = std::env::temp_dir().join(format!("swarmx-store-{}", Uuid::new_v4()));
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api")
+            .unwrap()
+            .with_compression(crate::compression::DEFAULT_COMPRESSION_LEVEL);
+        let workflow_id = Uuid::new_v4();
+
+        let json = serde_json::to_vec(&vec![serde_json::json!({"token": "hello"}); 1000]).unwrap();
+        let data = DataRef::new("client".to_string(), 0, DataType::Json, workflow_id);
+        let stored = store.put(data, &json).unwrap();
+        assert_eq!(stored.compression, Some(Compression::Zstd));
+        assert_eq!(stored.size_bytes, json.len() as u64);
+        let stored_bytes = stored.stored_bytes.unwrap();
+        assert!(stored_bytes < stored.size_bytes);
+        assert_eq!(stored.stored_size(), stored_bytes);
+        assert_eq!(
+            fs::metadata(dir.join(stored.uuid.to_string()))
+                .unwrap()
+                .len(),
+            stored_bytes
+        );
+        stored.verify_checksum(&json).unwrap();
+        assert_eq!(store.get(&stored.uuid).unwrap(), json);
+
+        // Dense data is stored as it is
+        let data = DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id);
+        let raw = store.put(data, &json).unwrap();
+        assert_eq!(raw.compression, None);
+        assert_eq!(raw.stored_size(), raw.size_bytes);
+
+        // Reopened stores read compressed objects without the option
+        drop(store);
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api").unwrap();
+        assert_eq!(store.get(&stored.uuid).unwrap(), json);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encryption_at_rest() {
+        use crate::encryption::{StaticKeyProvider, DATA_KEY_LEN};
+        use crate::token::TokenScope;
+
+        let dir = std::env::temp_dir().join(format!("swarmx-store-{}", Uuid::new_v4()));
+        let provider = || Arc::new(StaticKeyProvider::new("m1", &[9; DATA_KEY_LEN]).unwrap());
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api")
+            .unwrap()
+            .with_content_addressing()
+            .with_encryption(provider())
+            .unwrap();
+        let workflow_id = Uuid::new_v4();
+        let new_ref =
+            |workflow_id| DataRef::new("client".to_string(), 0, DataType::Bytes, workflow_id);
+
+        let secret = b"quarterly numbers".repeat(10_000);
+        let first = store.put(new_ref(workflow_id), &secret).unwrap();
+        let second = store.put(new_ref(workflow_id), b"second").unwrap();
+        let other = store.put(new_ref(Uuid::new_v4()), b"other").unwrap();
+        // One key per workflow, and no sharing by content address
+        assert!(first.encryption_key.is_some());
+        assert_eq!(first.encryption_key, second.encryption_key);
+        assert_ne!(first.encryption_key, other.encryption_key);
+        assert!(first.content_address().is_none());
+        first.verify_checksum(&secret).unwrap();
+
+        let on_disk = fs::read(dir.join(first.uuid.to_string())).unwrap();
+        assert!(!on_disk.windows(9).any(|w| w == b"quarterly"));
+        assert_eq!(store.get(&first.uuid).unwrap(), secret);
+
+        // Reads after reopening unwrap the persisted key
+        drop(store);
+        let store = LocalDataStore::open(&dir, "http://localhost:3000/api")
+            .unwrap()
+            .with_encryption(provider())
+            .unwrap();
+        assert_eq!(store.get(&second.uuid).unwrap(), b"second");
+        let unkeyed = LocalDataStore::open(&dir, "http://localhost:3000/api").unwrap();
+        assert!(matches!(
+            unkeyed.get(&second.uuid),
+            Err(DataStoreError::Encryption(EncryptionError::UnknownDataKey(
+                _
+            )))
+        ));
+
+        let tokens = TokenManager::new("client-1".to_string(), "k1", b"secret").unwrap();
+        let token = tokens
+            .issue_scoped_token(
+                TokenScope::Workflow { workflow_id },
+                Permissions::read_only(),
+                chrono::Duration::hours(1),
+            )
+            .unwrap();
+        assert_eq!(
+            store.get_with_token(&second.uuid, &token, &tokens).unwrap(),
+            b"second"
+        );
+        assert!(matches!(
+            store.get_with_token(&other.uuid, &token, &tokens),
+            Err(DataStoreError::Unauthorized(TokenError::OutOfScope(_)))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+//! Streaming data in and out of stores
+//!
+//! Multi-gigabyte artifacts must never sit in memory whole. The
+//! [`LocalDataStore`] already streams through [`Read`]; a
+//! [`ProgressReader`] wraps such a stream and reports the bytes moved so far,
+//! so transfers can emit progress events.
+//!
+//! With the `stream` feature, [`AsyncDataStore`] bridges the store to async
+//! code: [`put_stream`](AsyncDataStore::put_stream) stores an `AsyncRead`
+//! and [`get_stream`](AsyncDataStore::get_stream) returns one for any
+//! [`ByteRange`] of a payload. File and crypto work runs on tokio's
+//! blocking pool, and payloads move in chunks through a bounded channel,
+//! so neither side holds more than a few chunks at a time.
+
+use std::io::{self, Read};
+
+#[cfg(feature = "stream")]
+pub use self::async_store::*;
+#[cfg(doc)]
+use crate::store::{ByteRange, LocalDataStore};
+
+/// Default bytes between progress reports: 8MB
+pub const DEFAULT_PROGRESS_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// Called with the bytes transferred so far
+pub type ProgressFn = Box<dyn FnMut(u64) + Send>;
+
+/// Reader reporting the bytes read through it
+///
+/// Progress is reported each time another `interval` bytes have been read,
+/// and once more at the end unless the last report already covered
+/// everything. Streams shorter than the interval report nothing.
+pub struct ProgressReader<R> {
+    inner: R,
+    interval: u64,
+    transferred: u64,
+    /// Bytes covered by the last report
+    reported: u64,
+    callback: ProgressFn,
+}
+
+impl<R> std::fmt::Debug for ProgressReader<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressReader")
+            .field("interval", &self.interval)
+            .field("transferred", &self.transferred)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read> ProgressReader<R> {
+    /// Report progress on `inner` to `callback` every `interval` bytes
+    pub fn new(inner: R, interval: u64, callback: ProgressFn) -> Self {
+        Self {
+            inner,
+            interval: interval.max(1),
+            transferred: 0,
+            reported: 0,
+            callback,
+        }
+    }
+
+    /// Bytes read so far
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    fn report(&mut self) {
+        self.reported = self.transferred;
+        (self.callback)(self.transferred);
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.transferred += n as u64;
+        if n == 0 {
+            if self.reported > 0 && self.reported < self.transferred {
+                self.report();
+            }
+        } else if self.transferred / self.interval > self.reported / self.interval {
+            self.report();
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "stream")]
+mod async_store {
+    use std::io::{self, Read};
+    use std::ops::Deref;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{ready, Context, Poll};
+
+    use tokio::io::{AsyncRead, ReadBuf};
+    use tokio::sync::mpsc;
+    use tokio_util::io::SyncIoBridge;
+    use uuid::Uuid;
+
+    use super::{ProgressFn, ProgressReader, DEFAULT_PROGRESS_INTERVAL};
+    use crate::pointer::DataRef;
+    use crate::store::{ByteRange, DataStoreError, LocalDataStore, COPY_BUFFER_SIZE};
+
+    /// Chunks buffered between the blocking reader and the async consumer
+    const STREAM_CHANNEL_CHUNKS: usize = 4;
+
+    /// A [`LocalDataStore`] used from async code
+    ///
+    /// Cheaply cloneable; clones share the same store. Dereferences to the
+    /// store for everything that does not move payloads.
+    #[derive(Debug, Clone)]
+    pub struct AsyncDataStore {
+        store: Arc<LocalDataStore>,
+    }
+
+    impl AsyncDataStore {
+        /// Wrap a store
+        pub fn new(store: LocalDataStore) -> Self {
+            Self {
+                store: Arc::new(store),
+            }
+        }
+
+        /// Store a payload read from `reader`, without buffering it whole
+        ///
+        /// `progress` is told the bytes stored so far every
+        /// [`DEFAULT_PROGRESS_INTERVAL`].
+        pub async fn put_stream<R>(
+            &self,
+            data_ref: DataRef,
+            reader: R,
+            progress: Option<ProgressFn>,
+        ) -> Result<DataRef, DataStoreError>
+        where
+            R: AsyncRead + Send + Unpin + 'static,
+        {
+            let reader = SyncIoBridge::new(reader);
+            let store = self.store.clone();
+            tokio::task::spawn_blocking(move || match progress {
+                Some(progress) => store.put_stream(
+                    data_ref,
+                    ProgressReader::new(reader, DEFAULT_PROGRESS_INTERVAL, progress),
+                ),
+                None => store.put_stream(data_ref, reader),
+            })
+            .await
+            .map_err(|e| DataStoreError::Io(io::Error::other(e)))?
+        }
+
+        /// Stream a stored payload, or part of it
+        ///
+        /// Missing data and invalid ranges fail here; read errors surface
+        /// from the returned stream. `progress` is told the bytes read so
+        /// far every [`DEFAULT_PROGRESS_INTERVAL`].
+        pub async fn get_stream(
+            &self,
+            uuid: Uuid,
+            range: ByteRange,
+            progress: Option<ProgressFn>,
+        ) -> Result<ObjectStream, DataStoreError> {
+            let store = self.store.clone();
+            let reader = tokio::task::spawn_blocking(move || store.open_range(&uuid, range))
+                .await
+                .map_err(|e| DataStoreError::Io(io::Error::other(e)))??;
+            let mut reader = match progress {
+                Some(progress) => Box::new(ProgressReader::new(
+                    reader,
+                    DEFAULT_PROGRESS_INTERVAL,
+                    progress,
+                )),
+                None => reader,
+            };
+            let (sender, receiver) = mpsc::channel(STREAM_CHANNEL_CHUNKS);
+            tokio::task::spawn_blocking(move || loop {
+                let mut chunk = vec![0; COPY_BUFFER_SIZE];
+                let item = match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        Ok(chunk)
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = item.is_err();
+                // Stop once the consumer is gone or the read failed
+                if sender.blocking_send(item).is_err() || failed {
+                    break;
+                }
+            });
+            Ok(ObjectStream {
+                receiver,
+                chunk: Vec::new(),
+                position: 0,
+            })
+        }
+    }
+
+    impl Deref for AsyncDataStore {
+        type Target = LocalDataStore;
+
+        fn deref(&self) -> &LocalDataStore {
+            &self.store
+        }
+    }
+
+    /// Payload streamed out of an [`AsyncDataStore`]
+    #[derive(Debug)]
+    pub struct ObjectStream {
+        receiver: mpsc::Receiver<io::Result<Vec<u8>>>,
+        /// Chunk being read
+        chunk: Vec<u8>,
+        position: usize,
+    }
+
+    impl AsyncRead for ObjectStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            while self.position == self.chunk.len() {
+                match ready!(self.receiver.poll_recv(cx)) {
+                    Some(Ok(chunk)) => {
+                        self.chunk = chunk;
+                        self.position = 0;
+                    }
+                    Some(Err(e)) => return Poll::Ready(Err(e)),
+                    None => return Poll::Ready(Ok(())),
+                }
+            }
+            let start = self.position;
+            let n = buf.remaining().min(self.chunk.len() - start);
+            buf.put_slice(&self.chunk[start..start + n]);
+            self.position += n;
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_progress_reader() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        let payload = vec![1u8; 250];
+        let mut reader = ProgressReader::new(
+            payload.as_slice(),
+            100,
+            Box::new(move |bytes| seen.lock().unwrap().push(bytes)),
+        );
+        let mut buffer = [0u8; 60];
+        while reader.read(&mut buffer).unwrap() > 0 {}
+        assert_eq!(reader.transferred(), 250);
+        assert_eq!(*reports.lock().unwrap(), vec![120, 240, 250]);
+
+        // Short streams report nothing
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        let mut reader = ProgressReader::new(
+            payload.as_slice(),
+            1000,
+            Box::new(move |bytes| seen.lock().unwrap().push(bytes)),
+        );
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert!(reports.lock().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_async_streaming() {
+        use crate::pointer::{DataRef, DataType};
+        use crate::store::{ByteRange, DataStoreError, LocalDataStore};
+        use tokio::io::AsyncReadExt;
+        use uuid::Uuid;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = AsyncDataStore::new(
+            LocalDataStore::open(dir.path(), "http://localhost:3000/api")
+                .unwrap()
+                .with_compression(crate::compression::DEFAULT_COMPRESSION_LEVEL),
+        );
+        let payload: Vec<u8> = (0..DEFAULT_PROGRESS_INTERVAL + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        let data = DataRef::new("client".to_string(), 0, DataType::Json, Uuid::new_v4());
+        let stored = store
+            .put_stream(
+                data,
+                std::io::Cursor::new(payload.clone()),
+                Some(Box::new(move |bytes| seen.lock().unwrap().push(bytes))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored.size_bytes, payload.len() as u64);
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![DEFAULT_PROGRESS_INTERVAL, payload.len() as u64]
+        );
+
+        let mut read = Vec::new();
+        store
+            .get_stream(stored.uuid, ByteRange::full(), None)
+            .await
+            .unwrap()
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert_eq!(read, payload);
+
+        // Ranges of compressed data skip the decoded prefix
+        let mut read = Vec::new();
+        store
+            .get_stream(stored.uuid, ByteRange::new(1000, 10), None)
+            .await
+            .unwrap()
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert_eq!(read, &payload[1000..1010]);
+
+        assert!(matches!(
+            store
+                .get_stream(stored.uuid, ByteRange::from_offset(u64::MAX), None)
+                .await,
+            Err(DataStoreError::InvalidRange { .. })
+        ));
+        assert!(matches!(
+            store
+                .get_stream(Uuid::new_v4(), ByteRange::full(), None)
+                .await,
+            Err(DataStoreError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,548 @@
+//! Tier migration
+//!
+//! New data goes to its type's [default tier](crate::pointer::DataType::default_tier),
+//! but VRAM and DRAM are small. [`TierManager`] tracks how much of each
+//! tier every server uses; when a tier fills past the policy's high
+//! watermark it offloads data one tier down until usage is back under the
+//! low watermark, in the order the tier's [eviction
+//! policy](crate::eviction) picks, least recently used first by default.
+//! Every eviction is queued as a `DataEvicted` event, taken with
+//! [`TierManager::take_eviction_events`]. Data read after being offloaded
+//! is promoted back to its default tier once that tier has room.
+//! [Pinned](crate::pin) data never moves.
+//!
+//! Decisions are [`TierMigration`] commands for the server holding the
+//! data. Planning assumes they succeed, so pending migrations count against
+//! their destination tier; once the server confirms one,
+//! [`TierManager::complete`] updates the ref's `storage_tier` and returns
+//! the `DataTierChanged` event to publish.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use swarmx_events::Event;
+
+use crate::eviction::{EvictionCandidate, EvictionPolicy, Lru};
+use crate::pin::PinSet;
+use crate::pointer::{DataRef, StorageTier};
+
+/// When tiers are offloaded, as fractions of their capacity
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TierPolicy {
+    /// Usage above which a tier is offloaded
+    pub high_watermark: f64,
+    /// Usage offloading brings a tier down to; promotions only fill a tier
+    /// up to this level
+    pub low_watermark: f64,
+}
+
+impl Default for TierPolicy {
+    fn default() -> Self {
+        Self {
+            high_watermark: 0.9,
+            low_watermark: 0.75,
+        }
+    }
+}
+
+/// Command for a server to move data between its tiers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierMigration {
+    pub data_uuid: Uuid,
+    /// Server holding the data
+    pub server: String,
+    pub from_tier: StorageTier,
+    pub to_tier: StorageTier,
+    pub size_bytes: u64,
+}
+
+impl TierMigration {
+    /// Check if the data moves to a slower tier
+    pub fn is_offload(&self) -> bool {
+        self.to_tier > self.from_tier
+    }
+
+    /// Event recording the completed migration
+    pub fn event(&self) -> Event {
+        Event::DataTierChanged {
+            data_uuid: self.data_uuid,
+            from_tier: self.from_tier.as_str().to_string(),
+            to_tier: self.to_tier.as_str().to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// Data held by a server
+#[derive(Debug, Clone)]
+struct Resident {
+    data_ref: DataRef,
+    last_access: DateTime<Utc>,
+    access_count: u64,
+    /// Destination of a migration in progress
+    migrating_to: Option<StorageTier>,
+}
+
+impl Resident {
+    /// Tier the data occupies once pending migrations complete
+    fn effective_tier(&self) -> StorageTier {
+        self.migrating_to.unwrap_or(self.data_ref.storage_tier)
+    }
+
+    fn candidate(&self) -> EvictionCandidate<'_> {
+        EvictionCandidate {
+            data_ref: &self.data_ref,
+            last_access: self.last_access,
+            access_count: self.access_count,
+        }
+    }
+}
+
+/// Tier capacities and contents of one server
+#[derive(Debug, Clone, Default)]
+struct ServerTiers {
+    /// Bytes available per tier; tiers without a capacity are unbounded
+    capacity: HashMap<StorageTier, u64>,
+    /// Eviction policies overriding the manager's for this server
+    policies: HashMap<StorageTier, Arc<dyn EvictionPolicy>>,
+    data: HashMap<Uuid, Resident>,
+}
+
+impl ServerTiers {
+    fn usage(&self, tier: StorageTier) -> u64 {
+        self.data
+            .values()
+            .filter(|resident| resident.effective_tier() == tier)
+            .map(|resident| resident.data_ref.size_bytes)
+            .sum()
+    }
+}
+
+/// Decides which data moves between storage tiers on each server
+#[derive(Debug, Clone, Default)]
+pub struct TierManager {
+    policy: TierPolicy,
+    /// Eviction policy per tier; tiers without one use [`Lru`]
+    eviction: HashMap<StorageTier, Arc<dyn EvictionPolicy>>,
+    servers: HashMap<String, ServerTiers>,
+    /// Server holding each piece of tracked data
+    locations: HashMap<Uuid, String>,
+    pins: PinSet,
+    /// `DataEvicted` events not yet taken
+    evictions: Vec<Event>,
+}
+
+impl TierManager {
+    /// Create a manager applying `policy`
+    pub fn new(policy: TierPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Set the bytes a server has available in a tier
+    pub fn set_capacity(&mut self, server: &str, tier: StorageTier, bytes: u64) {
+        self.servers
+            .entry(server.to_string())
+            .or_default()
+            .capacity
+            .insert(tier, bytes);
+    }
+
+    /// Choose what every server evicts from a tier first
+    pub fn set_eviction_policy(&mut self, tier: StorageTier, policy: Arc<dyn EvictionPolicy>) {
+        self.eviction.insert(tier, policy);
+    }
+
+    /// Choose what one server evicts from a tier first, overriding
+    /// [`set_eviction_policy`](Self::set_eviction_policy)
+    pub fn set_server_eviction_policy(
+        &mut self,
+        server: &str,
+        tier: StorageTier,
+        policy: Arc<dyn EvictionPolicy>,
+    ) {
+        self.servers
+            .entry(server.to_string())
+            .or_default()
+            .policies
+            .insert(tier, policy);
+    }
+
+    /// Eviction policy a server applies to a tier
+    pub fn eviction_policy(&self, server: &str, tier: StorageTier) -> &dyn EvictionPolicy {
+        self.servers
+            .get(server)
+            .and_then(|tiers| tiers.policies.get(&tier))
+            .or_else(|| self.eviction.get(&tier))
+            .map_or(&Lru, |policy| policy.as_ref())
+    }
+
+    /// Start tracking data in its current tier on its primary location
+    pub fn track(&mut self, data_ref: DataRef) {
+        let server = data_ref.location.clone();
+        self.untrack(&data_ref.uuid);
+        self.locations.insert(data_ref.uuid, server.clone());
+        self.servers.entry(server).or_default().data.insert(
+            data_ref.uuid,
+            Resident {
+                last_access: data_ref.created_at,
+                access_count: 0,
+                data_ref,
+                migrating_to: None,
+            },
+        );
+    }
+
+    /// Stop tracking data, e.g. after it was deleted
+    pub fn untrack(&mut self, uuid: &Uuid) -> Option<DataRef> {
+        self.pins.unpin_data(uuid);
+        let server = self.locations.remove(uuid)?;
+        let resident = self.servers.get_mut(&server)?.data.remove(uuid)?;
+        Some(resident.data_ref)
+    }
+
+    /// Get tracked data, with its current tier
+    pub fn get(&self, uuid: &Uuid) -> Option<&DataRef> {
+        self.resident(uuid).map(|resident| &resident.data_ref)
+    }
+
+    /// Keep data in its current tier until unpinned
+    ///
+    /// Returns `false` if the data is not tracked.
+    pub fn pin(&mut self, uuid: &Uuid) -> bool {
+        if self.resident(uuid).is_none() {
+            return false;
+        }
+        self.pins.pin_data(*uuid);
+        true
+    }
+
+    /// Allow data to move between tiers again
+    pub fn unpin(&mut self, uuid: &Uuid) -> bool {
+        self.pins.unpin_data(uuid)
+    }
+
+    /// Keep all data of a workflow in its current tier until unpinned
+    pub fn pin_workflow(&mut self, workflow_id: Uuid) {
+        self.pins.pin_workflow(workflow_id);
+    }
+
+    /// Allow a workflow's data to move between tiers again
+    pub fn unpin_workflow(&mut self, workflow_id: &Uuid) -> bool {
+        self.pins.unpin_workflow(workflow_id)
+    }
+
+    /// Replace all pins, e.g. with the [registry's](crate::registry::DataRefRegistry::pins)
+    pub fn set_pins(&mut self, pins: PinSet) {
+        self.pins = pins;
+    }
+
+    /// Bytes a server uses in a tier, counting pending migrations as done
+    pub fn usage(&self, server: &str, tier: StorageTier) -> u64 {
+        self.servers
+            .get(server)
+            .map_or(0, |tiers| tiers.usage(tier))
+    }
+
+    /// Record that data was read
+    ///
+    /// Returns a promotion back to the data's default tier if it was
+    /// offloaded and that tier has room.
+    pub fn touch(&mut self, uuid: &Uuid, now: DateTime<Utc>) -> Option<TierMigration> {
+        let low_watermark = self.policy.low_watermark;
+        let server = self.locations.get(uuid)?;
+        let tiers = self.servers.get_mut(server)?;
+        let resident = tiers.data.get_mut(uuid)?;
+        resident.last_access = now;
+        resident.access_count += 1;
+
+        let target = resident.data_ref.dtype.default_tier();
+        if self.pins.covers(&resident.data_ref)
+            || resident.migrating_to.is_some()
+            || target >= resident.data_ref.storage_tier
+        {
+            return None;
+        }
+        let size_bytes = resident.data_ref.size_bytes;
+        let from_tier = resident.data_ref.storage_tier;
+        if let Some(capacity) = tiers.capacity.get(&target) {
+            if (tiers.usage(target) + size_bytes) as f64 > *capacity as f64 * low_watermark {
+                return None;
+            }
+        }
+
+        tiers.data.get_mut(uuid)?.migrating_to = Some(target);
+        Some(TierMigration {
+            data_uuid: *uuid,
+            server: server.clone(),
+            from_tier,
+            to_tier: target,
+            size_bytes,
+        })
+    }
+
+    /// Offload data from every tier above its high watermark, in the order
+    /// of the tier's eviction policy
+    ///
+    /// Tiers are relieved fastest first, so data offloaded from VRAM can in
+    /// turn push DRAM over its watermark and DRAM data out to disk.
+    pub fn plan(&mut self) -> Vec<TierMigration> {
+        self.plan_at(Utc::now())
+    }
+
+    /// [`plan`](Self::plan) as of `now`, which time-based eviction policies
+    /// score against
+    pub fn plan_at(&mut self, now: DateTime<Utc>) -> Vec<TierMigration> {
+        let policy = self.policy;
+        let pins = &self.pins;
+        let mut migrations = Vec::new();
+        for (server, tiers) in &mut self.servers {
+            for tier in [StorageTier::Vram, StorageTier::Dram] {
+                let (Some(capacity), Some(lower)) = (tiers.capacity.get(&tier), tier.lower())
+                else {
+                    continue;
+                };
+                let capacity = *capacity as f64;
+                let mut used = tiers.usage(tier);
+                if used as f64 <= capacity * policy.high_watermark {
+                    continue;
+                }
+
+                let eviction: &dyn EvictionPolicy = tiers
+                    .policies
+                    .get(&tier)
+                    .or_else(|| self.eviction.get(&tier))
+                    .map_or(&Lru, |policy| policy.as_ref());
+                let mut candidates: Vec<(f64, &mut Resident)> = tiers
+                    .data
+                    .values_mut()
+                    .filter(|r| !pins.covers(&r.data_ref) && r.migrating_to.is_none())
+                    .filter(|r| r.data_ref.storage_tier == tier)
+                    .map(|r| (eviction.score(&r.candidate(), now), r))
+                    .collect();
+                candidates.sort_by(|(a, ra), (b, rb)| {
+                    a.total_cmp(b).then(ra.last_access.cmp(&rb.last_access))
+                });
+                for (score, resident) in candidates {
+                    if used as f64 <= capacity * policy.low_watermark {
+                        break;
+                    }
+                    resident.migrating_to = Some(lower);
+                    used = used.saturating_sub(resident.data_ref.size_bytes);
+                    self.evictions.push(Event::DataEvicted {
+                        data_uuid: resident.data_ref.uuid,
+                        workflow_id: resident.data_ref.workflow_id,
+                        server: server.clone(),
+                        from_tier: tier.as_str().to_string(),
+                        to_tier: lower.as_str().to_string(),
+                        policy: eviction.name().to_string(),
+                        score,
+                        size_bytes: resident.data_ref.size_bytes,
+                        last_access: resident.last_access,
+                        access_count: resident.access_count,
+                        timestamp: now,
+                    });
+                    migrations.push(TierMigration {
+                        data_uuid: resident.data_ref.uuid,
+                        server: server.clone(),
+                        from_tier: tier,
+                        to_tier: lower,
+                        size_bytes: resident.data_ref.size_bytes,
+                    });
+                }
+            }
+        }
+        migrations
+    }
+
+    /// Take the `DataEvicted` events queued by planning since the last
+    /// call, for the caller to publish
+    pub fn take_eviction_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.evictions)
+    }
+
+    /// Record that a server carried out a migration
+    ///
+    /// Returns the updated ref and the event to publish, or `None` if the
+    /// data is no longer tracked.
+    pub fn complete(&mut self, migration: &TierMigration) -> Option<(DataRef, Event)> {
+        let resident = self.resident_mut(&migration.data_uuid)?;
+        resident.migrating_to = None;
+        resident.data_ref.storage_tier = migration.to_tier;
+        Some((resident.data_ref.clone(), migration.event()))
+    }
+
+    /// Record that a server could not carry out a migration, so the data
+    /// stays where it was
+    pub fn fail(&mut self, migration: &TierMigration) {
+        if let Some(resident) = self.resident_mut(&migration.data_uuid) {
+            resident.migrating_to = None;
+        }
+    }
+
+    fn resident(&self, uuid: &Uuid) -> Option<&Resident> {
+        let server = self.locations.get(uuid)?;
+        self.servers.get(server)?.data.get(uuid)
+    }
+
+    fn resident_mut(&mut self, uuid: &Uuid) -> Option<&mut Resident> {
+        let server = self.locations.get(uuid)?;
+        self.servers.get_mut(server)?.data.get_mut(uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{DataType, TensorDType};
+    use chrono::Duration;
+
+    const GB: u64 = 1 << 30;
+
+    fn tensor(size_bytes: u64, created_at: DateTime<Utc>) -> DataRef {
+        let mut data = DataRef::new(
+            "gpu-1".to_string(),
+            size_bytes,
+            DataType::Tensor {
+                shape: vec![size_bytes as usize / 2],
+                dtype: TensorDType::Float16,
+            },
+            Uuid::new_v4(),
+        );
+        data.created_at = created_at;
+        data
+    }
+
+    #[test]
+    fn test_tier_migration() {
+        let start = Utc::now();
+        let mut manager = TierManager::default();
+        manager.set_capacity("gpu-1", StorageTier::Vram, 10 * GB);
+        manager.set_capacity("gpu-1", StorageTier::Dram, 4 * GB);
+
+        let oldest = tensor(3 * GB, start);
+        let pinned = tensor(3 * GB, start + Duration::seconds(1));
+        let older = tensor(2 * GB, start + Duration::seconds(2));
+        let newest = tensor(GB, start + Duration::seconds(3));
+        for data in [&oldest, &pinned, &older, &newest] {
+            manager.track(data.clone());
+        }
+        assert!(manager.pin(&pinned.uuid));
+        assert!(manager.plan().is_empty());
+
+        // Reading the oldest makes `older` the least recently used
+        manager.touch(&oldest.uuid, start + Duration::seconds(4));
+        manager.track(tensor(GB, start + Duration::seconds(5)));
+        let migrations = manager.plan();
+        // 10GB of VRAM is over 9GB: offload to under 7.5GB, skipping the
+        // pinned tensor
+        let offloaded: Vec<_> = migrations
+            .iter()
+            .map(|m| (m.data_uuid, m.to_tier))
+            .collect();
+        assert_eq!(
+            offloaded,
+            [
+                (older.uuid, StorageTier::Dram),
+                (newest.uuid, StorageTier::Dram),
+            ]
+        );
+        assert!(migrations.iter().all(TierMigration::is_offload));
+        assert_eq!(manager.usage("gpu-1", StorageTier::Vram), 7 * GB);
+        // Planning again does not repeat pending migrations
+        assert!(manager.plan().is_empty());
+
+        let (data, event) = manager.complete(&migrations[0]).unwrap();
+        assert_eq!(data.storage_tier, StorageTier::Dram);
+        assert!(matches!(
+            event,
+            Event::DataTierChanged { ref to_tier, .. } if to_tier == "dram"
+        ));
+        manager.fail(&migrations[1]);
+        assert_eq!(manager.usage("gpu-1", StorageTier::Dram), 2 * GB);
+
+        // Offloaded data is promoted on access once VRAM has room
+        assert!(manager
+            .touch(&older.uuid, start + Duration::seconds(6))
+            .is_none());
+        manager.untrack(&oldest.uuid);
+        let promotion = manager
+            .touch(&older.uuid, start + Duration::seconds(7))
+            .unwrap();
+        assert!(!promotion.is_offload());
+        assert_eq!(promotion.to_tier, StorageTier::Vram);
+    }
+
+    #[test]
+    fn test_eviction_policy() {
+        let start = Utc::now();
+        let mut manager = TierManager::default();
+        manager.set_eviction_policy(StorageTier::Vram, Arc::new(crate::eviction::Lfu));
+        for server in ["gpu-1", "gpu-2"] {
+            manager.set_capacity(server, StorageTier::Vram, 10 * GB);
+        }
+        let size = crate::eviction::eviction_policy("size").unwrap();
+        manager.set_server_eviction_policy("gpu-2", StorageTier::Vram, size);
+        assert_eq!(
+            manager.eviction_policy("gpu-1", StorageTier::Vram).name(),
+            "lfu"
+        );
+        assert_eq!(
+            manager.eviction_policy("gpu-2", StorageTier::Vram).name(),
+            "size"
+        );
+        assert_eq!(
+            manager.eviction_policy("gpu-1", StorageTier::Dram).name(),
+            "lru"
+        );
+
+        // On both servers: a 4GB tensor read twice, a 5GB tensor read once,
+        // and a 2GB tensor never read
+        let mut tracked = HashMap::new();
+        for server in ["gpu-1", "gpu-2"] {
+            let mut hot = tensor(4 * GB, start);
+            let mut large = tensor(5 * GB, start + Duration::seconds(1));
+            let mut small = tensor(2 * GB, start + Duration::seconds(2));
+            for data in [&mut hot, &mut large, &mut small] {
+                data.location = server.to_string();
+                manager.track(data.clone());
+            }
+            manager.touch(&large.uuid, start + Duration::seconds(3));
+            for secs in [4, 5] {
+                manager.touch(&hot.uuid, start + Duration::seconds(secs));
+            }
+            tracked.insert(server, (large.uuid, small.uuid));
+        }
+
+        let migrations = manager.plan_at(start + Duration::seconds(60));
+        let evicted = |server: &str| -> Vec<Uuid> {
+            migrations
+                .iter()
+                .filter(|m| m.server == server)
+                .map(|m| m.data_uuid)
+                .collect()
+        };
+        // LFU evicts the never-read tensor, then the one read once
+        let (large, small) = tracked["gpu-1"];
+        assert_eq!(evicted("gpu-1"), [small, large]);
+        // Size-weighted evicts the large, longest idle tensor, which is
+        // enough
+        let (large, _) = tracked["gpu-2"];
+        assert_eq!(evicted("gpu-2"), [large]);
+
+        let events = manager.take_eviction_events();
+        assert_eq!(events.len(), migrations.len());
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::DataEvicted { policy, access_count: 0, server, .. }
+                if policy == "lfu" && server == "gpu-1"
+        )));
+        assert!(manager.take_eviction_events().is_empty());
+    }
+}
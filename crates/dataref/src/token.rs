@@ -1,12 +1,43 @@
 //! Access token implementation for secure data access
 //!
-//! Data access is controlled via signed tokens. Servers trust each other
-//! and use UUID-based verification for data transfers.
+//! Data access is controlled via signed tokens. A [`TokenManager`] signs
+//! each token over a canonical serialization of its claims (data UUID,
+//! issuer, validity period, and permissions), and servers verify the
+//! signature before serving data. The [`SigningScheme`] is chosen per
+//! deployment: HMAC-SHA256 shares one secret with every verifying server
+//! and checks it in constant time, while Ed25519 keeps the private key on
+//! the client and distributes only the public key.
+//!
+//! Revoked tokens are rejected by [`TokenManager::verify_token`] even
+//! when their signature is valid; see [`RevocationList`].
+//!
+//! Keys are identified by an ID carried in every token. To rotate, sign
+//! with a new key and keep the old ones for verification with
+//! [`TokenManager::with_verification_key`] or
+//! [`TokenManager::with_public_key`] until tokens signed with them have
+//! expired.
+
+use std::collections::HashMap;
+use std::sync::Arc;
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use swarmx_events::WalError;
 use uuid::Uuid;
 
+use crate::pointer::DataRef;
+use crate::revocation::RevocationList;
+
+/// Version tag of the canonical claims serialization
+const SIGNING_CONTEXT: &str = "swarmx-access-token-v1";
+
+/// Length of an Ed25519 public key in bytes
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
 /// Permission flags for data access
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Permissions {
@@ -53,12 +84,67 @@ impl Permissions {
     }
 }
 
+impl Permissions {
+    /// Check that every permission in `required` is granted
+    pub fn contains(&self, required: Permissions) -> bool {
+        (self.read || !required.read)
+            && (self.write || !required.write)
+            && (self.delete || !required.delete)
+    }
+
+    /// Flags as `rwd`, with `-` for permissions not granted
+    fn flags(&self) -> String {
+        [(self.read, 'r'), (self.write, 'w'), (self.delete, 'd')]
+            .into_iter()
+            .map(|(granted, flag)| if granted { flag } else { '-' })
+            .collect()
+    }
+}
+
 impl Default for Permissions {
     fn default() -> Self {
         Self::read_only()
     }
 }
 
+/// Data a token grants access to
+///
+/// A single-item token needs one token per input; workflow and prefix
+/// scopes let one token cover a bounded set of data, e.g. every input a
+/// node consumes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TokenScope {
+    /// One data item
+    Data { data_uuid: Uuid },
+    /// Every data item of a workflow
+    Workflow { workflow_id: Uuid },
+    /// Every data item whose location starts with the prefix
+    ///
+    /// Matched as a plain string, so end the prefix with a separator.
+    LocationPrefix { prefix: String },
+}
+
+impl TokenScope {
+    /// Check whether the scope includes a data item
+    pub fn covers(&self, data_ref: &DataRef) -> bool {
+        match self {
+            TokenScope::Data { data_uuid } => data_ref.uuid == *data_uuid,
+            TokenScope::Workflow { workflow_id } => data_ref.workflow_id == *workflow_id,
+            TokenScope::LocationPrefix { prefix } => data_ref.location.starts_with(prefix.as_str()),
+        }
+    }
+
+    /// Canonical form used in the signing payload
+    fn canonical(&self) -> String {
+        match self {
+            TokenScope::Data { data_uuid } => format!("data:{data_uuid}"),
+            TokenScope::Workflow { workflow_id } => format!("workflow:{workflow_id}"),
+            TokenScope::LocationPrefix { prefix } => format!("prefix:{prefix}"),
+        }
+    }
+}
+
 /// Access token for secure data access between servers
 ///
 /// When Server B needs data from Server A:
@@ -67,8 +153,8 @@ impl Default for Permissions {
 /// 3. Data is transferred directly between servers (no client hop)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccessToken {
-    /// UUID of the data being accessed
-    pub data_uuid: Uuid,
+    /// Data the token grants access to
+    pub scope: TokenScope,
     /// Client ID that issued this token
     pub issued_by: String,
     /// Token issue timestamp
@@ -77,19 +163,48 @@ pub struct AccessToken {
     pub expires_at: DateTime<Utc>,
     /// Granted permissions
     pub permissions: Permissions,
-    /// Cryptographic signature (placeholder - will use proper signing)
+    /// Workflow the data belongs to, for bulk revocation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow_id: Option<Uuid>,
+    /// ID of the key the token was signed with
+    pub key_id: String,
+    /// Hex-encoded signature over the other fields; empty until signed
     pub signature: String,
 }
 
 impl AccessToken {
-    /// Create a new access token
+    /// Create an unsigned access token for one data item, valid from now
+    /// for `ttl`
+    ///
+    /// Servers reject unsigned tokens; sign it with
+    /// [`TokenManager::sign`], or use [`TokenManager::issue_token`].
     pub fn new(
         data_uuid: Uuid,
         issued_by: String,
         ttl: Duration,
         permissions: Permissions,
     ) -> Self {
-        todo!("Implement AccessToken::new with signing")
+        Self::scoped(TokenScope::Data { data_uuid }, issued_by, ttl, permissions)
+    }
+
+    /// Create an unsigned access token for a scope, valid from now for `ttl`
+    pub fn scoped(
+        scope: TokenScope,
+        issued_by: String,
+        ttl: Duration,
+        permissions: Permissions,
+    ) -> Self {
+        let issued_at = Utc::now();
+        Self {
+            scope,
+            issued_by,
+            issued_at,
+            expires_at: issued_at + ttl,
+            permissions,
+            workflow_id: None,
+            key_id: String::new(),
+            signature: String::new(),
+        }
     }
 
     /// Create a read-only token with default TTL (1 hour)
@@ -107,9 +222,73 @@ impl AccessToken {
         Self::new(data_uuid, issued_by, ttl, Permissions::full())
     }
 
-    /// Verify token validity (signature and expiration)
+    /// Tag the token with the workflow its data belongs to
+    ///
+    /// Must be set before signing; it lets
+    /// [`TokenManager::revoke_workflow`] revoke the token.
+    pub fn with_workflow(mut self, workflow_id: Uuid) -> Self {
+        self.workflow_id = Some(workflow_id);
+        self
+    }
+
+    /// Workflow the token is revoked with: its tag, or the workflow it is
+    /// scoped to
+    pub fn workflow(&self) -> Option<Uuid> {
+        match self.scope {
+            TokenScope::Workflow { workflow_id } => self.workflow_id.or(Some(workflow_id)),
+            _ => self.workflow_id,
+        }
+    }
+
+    /// ID used to revoke the token: its signature, which is unique per
+    /// token
+    pub fn token_id(&self) -> String {
+        self.signature.clone()
+    }
+
+    /// Check that the token is within its validity period
+    ///
+    /// The signature is checked by [`TokenManager::verify_token`], which
+    /// also calls this.
     pub fn verify(&self) -> Result<(), TokenError> {
-        todo!("Implement token verification")
+        let now = Utc::now();
+        if now < self.issued_at {
+            return Err(TokenError::NotYetValid);
+        }
+        if now > self.expires_at {
+            return Err(TokenError::Expired);
+        }
+        Ok(())
+    }
+
+    /// Canonical serialization of the signed claims
+    ///
+    /// Every field is length-prefixed, so no choice of issuer can make two
+    /// different tokens serialize alike.
+    fn signing_payload(&self) -> Vec<u8> {
+        let fields = [
+            SIGNING_CONTEXT.to_string(),
+            self.key_id.clone(),
+            self.scope.canonical(),
+            self.workflow_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            self.issued_by.clone(),
+            self.issued_at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            self.expires_at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            self.permissions.flags(),
+        ];
+        let mut payload = Vec::new();
+        for field in fields {
+            payload.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            payload.extend_from_slice(field.as_bytes());
+        }
+        payload
+    }
+
+    /// Check whether the token's scope includes a data item
+    pub fn covers(&self, data_ref: &DataRef) -> bool {
+        self.scope.covers(data_ref)
     }
 
     /// Check if token is expired
@@ -143,18 +322,174 @@ impl AccessToken {
     }
 }
 
+/// Algorithm access tokens are signed with
+///
+/// HMAC needs the secret on every server that verifies tokens; Ed25519
+/// lets the client keep its private key and hand servers only the public
+/// key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningScheme {
+    /// HMAC-SHA256 with a shared secret
+    #[default]
+    HmacSha256,
+    /// Ed25519 with a private signing key and public verification key
+    Ed25519,
+}
+
+/// Key new tokens are signed with
+enum SigningKey {
+    Hmac(Vec<u8>),
+    // Key pairs cannot be cloned
+    Ed25519(Arc<Ed25519KeyPair>),
+}
+
+impl SigningKey {
+    fn scheme(&self) -> SigningScheme {
+        match self {
+            SigningKey::Hmac(_) => SigningScheme::HmacSha256,
+            SigningKey::Ed25519(_) => SigningScheme::Ed25519,
+        }
+    }
+}
+
+/// Key tokens are verified with
+#[derive(Clone)]
+enum VerificationKey {
+    Hmac(Vec<u8>),
+    Ed25519(Vec<u8>),
+}
+
 /// Token manager for creating and verifying access tokens
+#[derive(Clone)]
 pub struct TokenManager {
     /// Client identifier for this manager
     client_id: String,
-    /// Secret key for signing (placeholder)
-    _secret_key: Vec<u8>,
+    /// ID and key new tokens are signed with; absent on verify-only managers
+    signer: Option<(String, Arc<SigningKey>)>,
+    /// Verification keys by ID, including retired keys still accepted
+    keys: HashMap<String, VerificationKey>,
+    /// Revoked tokens, rejected even with a valid signature
+    revocations: RevocationList,
+}
+
+impl std::fmt::Debug for TokenManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenManager")
+            .field("client_id", &self.client_id)
+            .field("key_id", &self.key_id())
+            .field("scheme", &self.scheme())
+            .finish_non_exhaustive()
+    }
 }
 
 impl TokenManager {
-    /// Create a new token manager
-    pub fn new(client_id: String) -> Self {
-        todo!("Implement TokenManager::new")
+    /// Create a token manager signing with the HMAC `secret_key`
+    ///
+    /// `key_id` is carried in every token so verifiers can pick the key.
+    pub fn new(client_id: String, key_id: &str, secret_key: &[u8]) -> Result<Self, TokenError> {
+        Self::with_scheme(client_id, SigningScheme::HmacSha256, key_id, secret_key)
+    }
+
+    /// Create a token manager signing with `key` under `scheme`
+    ///
+    /// For [`SigningScheme::HmacSha256`] the key is the shared secret; for
+    /// [`SigningScheme::Ed25519`] it is a PKCS#8 private key, such as one
+    /// from [`generate_ed25519_key`].
+    pub fn with_scheme(
+        client_id: String,
+        scheme: SigningScheme,
+        key_id: &str,
+        key: &[u8],
+    ) -> Result<Self, TokenError> {
+        let mut manager = Self::verifier(client_id);
+        manager.set_signer(scheme, key_id, key)?;
+        Ok(manager)
+    }
+
+    /// Create a manager that only verifies tokens
+    ///
+    /// Add keys with [`with_verification_key`](Self::with_verification_key)
+    /// or [`with_public_key`](Self::with_public_key). Signing fails with
+    /// [`TokenError::NoSigningKey`].
+    pub fn verifier(client_id: String) -> Self {
+        Self {
+            client_id,
+            signer: None,
+            keys: HashMap::new(),
+            revocations: RevocationList::new(),
+        }
+    }
+
+    /// Also accept tokens signed with the HMAC `secret_key` under `key_id`
+    pub fn with_verification_key(
+        mut self,
+        key_id: &str,
+        secret_key: &[u8],
+    ) -> Result<Self, TokenError> {
+        if secret_key.is_empty() {
+            return Err(TokenError::InvalidKey("empty secret key".to_string()));
+        }
+        self.add_key(key_id, VerificationKey::Hmac(secret_key.to_vec()))?;
+        Ok(self)
+    }
+
+    /// Also accept tokens signed by the Ed25519 `public_key` under `key_id`
+    pub fn with_public_key(mut self, key_id: &str, public_key: &[u8]) -> Result<Self, TokenError> {
+        if public_key.len() != ED25519_PUBLIC_KEY_LEN {
+            return Err(TokenError::InvalidKey(format!(
+                "Ed25519 public key must be {ED25519_PUBLIC_KEY_LEN} bytes, got {}",
+                public_key.len()
+            )));
+        }
+        self.add_key(key_id, VerificationKey::Ed25519(public_key.to_vec()))?;
+        Ok(self)
+    }
+
+    /// Check revocations against `revocations`, e.g. a persistent list
+    /// shared by every manager of the process
+    pub fn with_revocation_list(mut self, revocations: RevocationList) -> Self {
+        self.revocations = revocations;
+        self
+    }
+
+    /// Revocation list checked by [`verify_token`](Self::verify_token)
+    pub fn revocation_list(&self) -> &RevocationList {
+        &self.revocations
+    }
+
+    /// Sign new tokens with another key of the current scheme, still
+    /// accepting the old ones
+    pub fn rotate(&mut self, key_id: &str, key: &[u8]) -> Result<(), TokenError> {
+        let scheme = self.scheme().ok_or(TokenError::NoSigningKey)?;
+        self.set_signer(scheme, key_id, key)
+    }
+
+    /// Stop accepting tokens signed with a retired key
+    ///
+    /// The current signing key cannot be retired.
+    pub fn retire_key(&mut self, key_id: &str) -> bool {
+        self.key_id() != Some(key_id) && self.keys.remove(key_id).is_some()
+    }
+
+    /// ID of the key new tokens are signed with
+    pub fn key_id(&self) -> Option<&str> {
+        self.signer.as_ref().map(|(key_id, _)| key_id.as_str())
+    }
+
+    /// Scheme new tokens are signed with
+    pub fn scheme(&self) -> Option<SigningScheme> {
+        self.signer.as_ref().map(|(_, key)| key.scheme())
+    }
+
+    /// Ed25519 public key to distribute to verifying servers
+    ///
+    /// `None` unless signing with [`SigningScheme::Ed25519`].
+    pub fn public_key(&self) -> Option<&[u8]> {
+        match self.signer.as_ref().map(|(_, key)| key.as_ref()) {
+            Some(SigningKey::Ed25519(pair)) => Some(pair.public_key().as_ref()),
+            _ => None,
+        }
     }
 
     /// Issue a new token for data access
@@ -163,21 +498,171 @@ impl TokenManager {
         data_uuid: Uuid,
         permissions: Permissions,
         ttl: Duration,
-    ) -> AccessToken {
-        todo!("Implement token issuance")
+    ) -> Result<AccessToken, TokenError> {
+        self.sign(AccessToken::new(
+            data_uuid,
+            self.client_id.clone(),
+            ttl,
+            permissions,
+        ))
+    }
+
+    /// Issue a token for every data item in `scope`
+    pub fn issue_scoped_token(
+        &self,
+        scope: TokenScope,
+        permissions: Permissions,
+        ttl: Duration,
+    ) -> Result<AccessToken, TokenError> {
+        self.sign(AccessToken::scoped(
+            scope,
+            self.client_id.clone(),
+            ttl,
+            permissions,
+        ))
     }
 
-    /// Verify a token
+    /// Sign a token with the current key
+    pub fn sign(&self, mut token: AccessToken) -> Result<AccessToken, TokenError> {
+        let (key_id, key) = self.signer.as_ref().ok_or(TokenError::NoSigningKey)?;
+        token.key_id = key_id.clone();
+        let payload = token.signing_payload();
+        token.signature = match key.as_ref() {
+            SigningKey::Hmac(secret) => encode_hex(&hmac(secret, &payload).finalize().into_bytes()),
+            SigningKey::Ed25519(pair) => encode_hex(pair.sign(&payload).as_ref()),
+        };
+        Ok(token)
+    }
+
+    /// Verify a token's signature, revocation status, and validity period
     pub fn verify_token(&self, token: &AccessToken) -> Result<(), TokenError> {
-        todo!("Implement token verification")
+        let key = self
+            .keys
+            .get(&token.key_id)
+            .ok_or_else(|| TokenError::UnknownKey(token.key_id.clone()))?;
+        let signature = decode_hex(&token.signature).ok_or(TokenError::InvalidSignature)?;
+        let payload = token.signing_payload();
+        match key {
+            // Compares in constant time
+            VerificationKey::Hmac(secret) => hmac(secret, &payload)
+                .verify_slice(&signature)
+                .map_err(|_| TokenError::InvalidSignature)?,
+            VerificationKey::Ed25519(public_key) => UnparsedPublicKey::new(&ED25519, public_key)
+                .verify(&payload, &signature)
+                .map_err(|_| TokenError::InvalidSignature)?,
+        }
+        if self.revocations.is_revoked(token) {
+            return Err(TokenError::Revoked);
+        }
+        token.verify()
+    }
+
+    /// Check that a token is valid and grants `required` on every item of
+    /// `data_refs`
+    ///
+    /// The token is verified once, so a scoped token can authorize a whole
+    /// batch of requested data.
+    pub fn authorize<'a>(
+        &self,
+        token: &AccessToken,
+        data_refs: impl IntoIterator<Item = &'a DataRef>,
+        required: Permissions,
+    ) -> Result<(), TokenError> {
+        self.verify_token(token)?;
+        if !token.permissions.contains(required) {
+            return Err(TokenError::InsufficientPermissions {
+                required: required.flags(),
+                granted: token.permissions.flags(),
+            });
+        }
+        for data_ref in data_refs {
+            if !token.covers(data_ref) {
+                return Err(TokenError::OutOfScope(data_ref.uuid));
+            }
+        }
+        Ok(())
     }
 
-    /// Revoke a token (add to revocation list)
-    pub fn revoke_token(&mut self, _token: &AccessToken) {
-        todo!("Implement token revocation")
+    /// Revoke a token until it expires
+    pub fn revoke_token(&self, token: &AccessToken) -> Result<(), TokenError> {
+        self.revocations.revoke(token)
+    }
+
+    /// Revoke every token issued so far for a workflow's data
+    ///
+    /// See [`RevocationList::revoke_workflow`] for the choice of `until`.
+    pub fn revoke_workflow(
+        &self,
+        workflow_id: Uuid,
+        until: DateTime<Utc>,
+    ) -> Result<(), TokenError> {
+        self.revocations.revoke_workflow(workflow_id, until)
+    }
+
+    /// Sign with `key` from now on, also accepting it for verification
+    fn set_signer(
+        &mut self,
+        scheme: SigningScheme,
+        key_id: &str,
+        key: &[u8],
+    ) -> Result<(), TokenError> {
+        let signing_key = match scheme {
+            SigningScheme::HmacSha256 => {
+                *self = self.clone().with_verification_key(key_id, key)?;
+                SigningKey::Hmac(key.to_vec())
+            }
+            SigningScheme::Ed25519 => {
+                let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(key).map_err(|e| {
+                    TokenError::InvalidKey(format!("invalid Ed25519 PKCS#8 key: {e}"))
+                })?;
+                *self = self
+                    .clone()
+                    .with_public_key(key_id, pair.public_key().as_ref())?;
+                SigningKey::Ed25519(Arc::new(pair))
+            }
+        };
+        self.signer = Some((key_id.to_string(), Arc::new(signing_key)));
+        Ok(())
+    }
+
+    fn add_key(&mut self, key_id: &str, key: VerificationKey) -> Result<(), TokenError> {
+        if key_id.is_empty() {
+            return Err(TokenError::InvalidKey("empty key ID".to_string()));
+        }
+        self.keys.insert(key_id.to_string(), key);
+        Ok(())
     }
 }
 
+/// Generate a PKCS#8-encoded Ed25519 private key for
+/// [`SigningScheme::Ed25519`]
+pub fn generate_ed25519_key() -> Result<Vec<u8>, TokenError> {
+    Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map(|document| document.as_ref().to_vec())
+        .map_err(|_| TokenError::InvalidKey("failed to generate Ed25519 key".to_string()))
+}
+
+/// HMAC-SHA256 over a token's claims
+fn hmac(key: &[u8], payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload);
+    mac
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
 /// Token-related errors
 #[derive(Debug, thiserror::Error)]
 pub enum TokenError {
@@ -198,6 +683,21 @@ pub enum TokenError {
 
     #[error("Invalid token format: {0}")]
     InvalidFormat(String),
+
+    #[error("Unknown signing key: {0}")]
+    UnknownKey(String),
+
+    #[error("Invalid signing key: {0}")]
+    InvalidKey(String),
+
+    #[error("Data {0} is outside the token's scope")]
+    OutOfScope(Uuid),
+
+    #[error("No signing key configured")]
+    NoSigningKey,
+
+    #[error("Revocation storage error: {0}")]
+    Storage(#[from] WalError),
 }
 
 #[cfg(test)]
@@ -219,4 +719,225 @@ mod tests {
         assert!(perms.write);
         assert!(perms.delete);
     }
+
+    #[test]
+    fn test_token_signing() {
+        let mut manager = TokenManager::new("client-1".to_string(), "k1", b"secret-1").unwrap();
+        let data_uuid = Uuid::new_v4();
+        let token = manager
+            .issue_token(data_uuid, Permissions::read_only(), Duration::hours(1))
+            .unwrap();
+        assert_eq!(token.key_id, "k1");
+        manager.verify_token(&token).unwrap();
+
+        // Tokens survive serialization
+        let json = serde_json::to_string(&token).unwrap();
+        let parsed: AccessToken = serde_json::from_str(&json).unwrap();
+        manager.verify_token(&parsed).unwrap();
+
+        // Any change to the claims breaks the signature
+        let mut forged = token.clone();
+        forged.permissions = Permissions::full();
+        assert!(matches!(
+            manager.verify_token(&forged),
+            Err(TokenError::InvalidSignature)
+        ));
+        let unsigned = AccessToken::read_only(data_uuid, "client-1".to_string());
+        assert!(manager.verify_token(&unsigned).is_err());
+        let other = TokenManager::new("client-1".to_string(), "k1", b"secret-2").unwrap();
+        assert!(matches!(
+            other.verify_token(&token),
+            Err(TokenError::InvalidSignature)
+        ));
+
+        let expired = manager
+            .sign(AccessToken::new(
+                data_uuid,
+                "client-1".to_string(),
+                Duration::seconds(-1),
+                Permissions::read_only(),
+            ))
+            .unwrap();
+        assert!(matches!(
+            manager.verify_token(&expired),
+            Err(TokenError::Expired)
+        ));
+
+        // Rotation keeps old tokens valid until the old key is retired
+        manager.rotate("k2", b"secret-2").unwrap();
+        let rotated = manager
+            .issue_token(data_uuid, Permissions::read_only(), Duration::hours(1))
+            .unwrap();
+        assert_eq!(rotated.key_id, "k2");
+        manager.verify_token(&rotated).unwrap();
+        manager.verify_token(&token).unwrap();
+        assert!(!manager.retire_key("k2"));
+        assert!(manager.retire_key("k1"));
+        assert!(matches!(
+            manager.verify_token(&token),
+            Err(TokenError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_ed25519_signing() {
+        let private_key = generate_ed25519_key().unwrap();
+        let mut client = TokenManager::with_scheme(
+            "client-1".to_string(),
+            SigningScheme::Ed25519,
+            "e1",
+            &private_key,
+        )
+        .unwrap();
+        assert_eq!(client.scheme(), Some(SigningScheme::Ed25519));
+        let server = TokenManager::verifier("server-a".to_string())
+            .with_public_key("e1", client.public_key().unwrap())
+            .unwrap();
+
+        let data_uuid = Uuid::new_v4();
+        let token = client
+            .issue_token(data_uuid, Permissions::read_only(), Duration::hours(1))
+            .unwrap();
+        server.verify_token(&token).unwrap();
+        assert!(matches!(
+            server.issue_token(data_uuid, Permissions::read_only(), Duration::hours(1)),
+            Err(TokenError::NoSigningKey)
+        ));
+
+        let mut forged = token.clone();
+        forged.permissions = Permissions::full();
+        assert!(matches!(
+            server.verify_token(&forged),
+            Err(TokenError::InvalidSignature)
+        ));
+
+        // A token signed by a different key pair under the same ID fails
+        let impostor = TokenManager::with_scheme(
+            "client-1".to_string(),
+            SigningScheme::Ed25519,
+            "e1",
+            &generate_ed25519_key().unwrap(),
+        )
+        .unwrap();
+        let token = impostor
+            .issue_token(data_uuid, Permissions::full(), Duration::hours(1))
+            .unwrap();
+        assert!(matches!(
+            server.verify_token(&token),
+            Err(TokenError::InvalidSignature)
+        ));
+
+        client
+            .rotate("e2", &generate_ed25519_key().unwrap())
+            .unwrap();
+        assert_eq!(client.key_id(), Some("e2"));
+        assert!(TokenManager::verifier("server-a".to_string())
+            .with_public_key("e1", &[0; 8])
+            .is_err());
+    }
+
+    #[test]
+    fn test_revoked_tokens_rejected() {
+        let manager = TokenManager::new("client-1".to_string(), "k1", b"secret-1").unwrap();
+        let workflow_id = Uuid::new_v4();
+        let issue = || {
+            manager
+                .sign(
+                    AccessToken::read_only(Uuid::new_v4(), "client-1".to_string())
+                        .with_workflow(workflow_id),
+                )
+                .unwrap()
+        };
+        let first = issue();
+        let second = issue();
+        manager.verify_token(&first).unwrap();
+
+        manager.revoke_token(&first).unwrap();
+        assert!(matches!(
+            manager.verify_token(&first),
+            Err(TokenError::Revoked)
+        ));
+        manager.verify_token(&second).unwrap();
+
+        manager
+            .revoke_workflow(workflow_id, Utc::now() + Duration::hours(1))
+            .unwrap();
+        assert!(matches!(
+            manager.verify_token(&second),
+            Err(TokenError::Revoked)
+        ));
+
+        // The workflow ID is signed, so it cannot be stripped
+        let mut stripped = second.clone();
+        stripped.workflow_id = None;
+        assert!(matches!(
+            manager.verify_token(&stripped),
+            Err(TokenError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_scoped_token_authorizes_batch() {
+        let manager = TokenManager::new("client-1".to_string(), "k1", b"secret-1").unwrap();
+        let workflow_id = Uuid::new_v4();
+        let inputs: Vec<DataRef> = (0..50)
+            .map(|i| DataRef::bytes("server-a".to_string(), workflow_id, &[i]))
+            .collect();
+        let outsider = DataRef::bytes("server-a".to_string(), Uuid::new_v4(), b"x");
+
+        let token = manager
+            .issue_scoped_token(
+                TokenScope::Workflow { workflow_id },
+                Permissions::read_only(),
+                Duration::hours(1),
+            )
+            .unwrap();
+        manager
+            .authorize(&token, &inputs, Permissions::read_only())
+            .unwrap();
+        assert!(matches!(
+            manager.authorize(&token, inputs.iter().chain([&outsider]), Permissions::read_only()),
+            Err(TokenError::OutOfScope(uuid)) if uuid == outsider.uuid
+        ));
+        assert!(matches!(
+            manager.authorize(&token, &inputs, Permissions::read_write()),
+            Err(TokenError::InsufficientPermissions { .. })
+        ));
+
+        // The scope is signed
+        let mut widened = token.clone();
+        widened.scope = TokenScope::LocationPrefix {
+            prefix: String::new(),
+        };
+        assert!(matches!(
+            manager.authorize(&widened, [&outsider], Permissions::read_only()),
+            Err(TokenError::InvalidSignature)
+        ));
+
+        let prefix = manager
+            .issue_scoped_token(
+                TokenScope::LocationPrefix {
+                    prefix: "server-a".to_string(),
+                },
+                Permissions::read_only(),
+                Duration::hours(1),
+            )
+            .unwrap();
+        manager
+            .authorize(
+                &prefix,
+                inputs.iter().chain([&outsider]),
+                Permissions::read_only(),
+            )
+            .unwrap();
+
+        // Workflow-scoped tokens are revoked with their workflow
+        manager
+            .revoke_workflow(workflow_id, Utc::now() + Duration::hours(1))
+            .unwrap();
+        assert!(matches!(
+            manager.verify_token(&token),
+            Err(TokenError::Revoked)
+        ));
+    }
 }
@@ -0,0 +1,222 @@
+//! Network topology and transfer cost model
+//!
+//! [`DataRef::transfer_cost`] estimates how long moving data to another
+//! server takes: the link's latency plus the payload size over the slower
+//! of the link bandwidth and the rate the source tier can be read at.
+//! Links come from a [`NetworkTopology`]; [`StaticTopology`] holds
+//! configured pairwise links, refined by measurements of real transfers,
+//! and treats servers on the same host as connected by a fast loopback
+//! link.
+//!
+//! [`DataRef::transfer_cost`]: crate::pointer::DataRef::transfer_cost
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pointer::StorageTier;
+
+/// Weight of a new measurement when blending it into a known link
+const MEASUREMENT_WEIGHT: f64 = 0.3;
+
+/// Bandwidth and latency between two servers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    /// Sustained throughput in bytes per second
+    pub bandwidth_bytes_per_sec: u64,
+    /// One-way latency in milliseconds
+    pub latency_ms: f64,
+}
+
+impl Link {
+    /// Create a link
+    pub fn new(bandwidth_bytes_per_sec: u64, latency_ms: f64) -> Self {
+        Self {
+            bandwidth_bytes_per_sec,
+            latency_ms,
+        }
+    }
+
+    /// 1 Gbit/s Ethernet with 1ms latency
+    pub fn gigabit() -> Self {
+        Self::new(125_000_000, 1.0)
+    }
+
+    /// Loopback between servers on the same host
+    pub fn loopback() -> Self {
+        Self::new(10_000_000_000, 0.05)
+    }
+}
+
+/// Source of link characteristics for the transfer cost model
+pub trait NetworkTopology: Send + Sync {
+    /// Link used to send data from one server to another
+    fn link(&self, from: &str, to: &str) -> Link;
+
+    /// Rate data in a tier can be read at, in bytes per second
+    ///
+    /// Defaults approximate a PCIe 4.0 device-to-host copy for VRAM,
+    /// memory bandwidth for DRAM, and an NVMe drive for disk.
+    fn tier_read_bandwidth(&self, tier: StorageTier) -> u64 {
+        match tier {
+            StorageTier::Vram => 25_000_000_000,
+            StorageTier::Dram => 20_000_000_000,
+            StorageTier::Disk => 2_000_000_000,
+        }
+    }
+
+    /// Estimate milliseconds to move `size_bytes` stored in `tier` from one
+    /// server to another; 0 if they are the same server
+    fn transfer_ms(&self, from: &str, to: &str, size_bytes: u64, tier: StorageTier) -> u64 {
+        if from == to {
+            return 0;
+        }
+        let link = self.link(from, to);
+        let bandwidth = link
+            .bandwidth_bytes_per_sec
+            .min(self.tier_read_bandwidth(tier))
+            .max(1);
+        let ms = link.latency_ms + size_bytes as f64 * 1000.0 / bandwidth as f64;
+        ms.ceil() as u64
+    }
+}
+
+/// Configured and measured links between servers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticTopology {
+    /// Link between servers with nothing configured or measured
+    pub default_link: Link,
+    /// Link between servers on the same host
+    pub same_host_link: Link,
+    /// Known links, by source and destination address
+    links: HashMap<String, HashMap<String, Link>>,
+}
+
+impl Default for StaticTopology {
+    fn default() -> Self {
+        Self::new(Link::gigabit())
+    }
+}
+
+impl StaticTopology {
+    /// Create a topology where unknown links have the given characteristics
+    pub fn new(default_link: Link) -> Self {
+        Self {
+            default_link,
+            same_host_link: Link::loopback(),
+            links: HashMap::new(),
+        }
+    }
+
+    /// Configure the link between two servers, in both directions
+    pub fn with_link(mut self, a: &str, b: &str, link: Link) -> Self {
+        self.set_link(a, b, link);
+        self.set_link(b, a, link);
+        self
+    }
+
+    /// Configure the link from one server to another
+    pub fn set_link(&mut self, from: &str, to: &str, link: Link) {
+        self.links
+            .entry(from.to_string())
+            .or_default()
+            .insert(to.to_string(), link);
+    }
+
+    /// Record the characteristics observed for a transfer
+    ///
+    /// Measurements are blended into what is known about the link, so one
+    /// slow transfer does not make a link look permanently congested.
+    pub fn record_measurement(&mut self, from: &str, to: &str, measured: Link) {
+        let link = match self.links.get(from).and_then(|links| links.get(to)) {
+            Some(known) => Link {
+                bandwidth_bytes_per_sec: blend(
+                    known.bandwidth_bytes_per_sec as f64,
+                    measured.bandwidth_bytes_per_sec as f64,
+                ) as u64,
+                latency_ms: blend(known.latency_ms, measured.latency_ms),
+            },
+            None => measured,
+        };
+        self.set_link(from, to, link);
+    }
+}
+
+impl NetworkTopology for StaticTopology {
+    fn link(&self, from: &str, to: &str) -> Link {
+        if let Some(link) = self.links.get(from).and_then(|links| links.get(to)) {
+            return *link;
+        }
+        if same_host(from, to) {
+            self.same_host_link
+        } else {
+            self.default_link
+        }
+    }
+}
+
+fn blend(known: f64, measured: f64) -> f64 {
+    known * (1.0 - MEASUREMENT_WEIGHT) + measured * MEASUREMENT_WEIGHT
+}
+
+/// Check whether two server addresses, such as `http://gpu-1:9090/`, are
+/// on the same host
+pub fn same_host(a: &str, b: &str) -> bool {
+    host(a) == host(b)
+}
+
+/// Host part of a server address such as `http://gpu-1:9090/`
+fn host(address: &str) -> &str {
+    let address = address.split_once("://").map_or(address, |(_, rest)| rest);
+    let address = address.split('/').next().unwrap_or(address);
+    address.rsplit_once(':').map_or(address, |(host, _)| host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::{DataRef, DataType};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_transfer_cost() {
+        let topology = StaticTopology::default().with_link(
+            "http://gpu-1:9090",
+            "http://gpu-2:9090",
+            Link::new(1_000_000_000, 0.5),
+        );
+        let data = DataRef::new(
+            "http://gpu-1:9090".to_string(),
+            1_000_000_000,
+            DataType::Bytes,
+            Uuid::new_v4(),
+        );
+
+        assert_eq!(data.transfer_cost("http://gpu-1:9090", &topology), 0);
+        // 1GB over 1GB/s plus latency
+        assert_eq!(data.transfer_cost("http://gpu-2:9090", &topology), 1001);
+        // Unknown link: gigabit Ethernet
+        assert_eq!(data.transfer_cost("http://cpu-1:9090", &topology), 8001);
+        // Another server on the same host
+        assert_eq!(data.transfer_cost("http://gpu-1:9091", &topology), 101);
+        assert!(same_host("http://gpu-1:9090/api", "gpu-1:9091"));
+        assert!(!same_host("http://gpu-1:9090", "http://gpu-2:9090"));
+
+        // Reading from disk is slower than the link
+        let mut on_disk = data.clone();
+        on_disk.storage_tier = StorageTier::Disk;
+        let fast = StaticTopology::new(Link::new(10_000_000_000, 0.0));
+        assert_eq!(on_disk.transfer_cost("http://gpu-2:9090", &fast), 500);
+
+        let mut measured = topology.clone();
+        measured.record_measurement(
+            "http://gpu-1:9090",
+            "http://gpu-2:9090",
+            Link::new(500_000_000, 0.5),
+        );
+        assert_eq!(
+            measured.link("http://gpu-1:9090", "http://gpu-2:9090"),
+            Link::new(850_000_000, 0.5)
+        );
+    }
+}
@@ -0,0 +1,428 @@
+//! Chunked, resumable data transfer
+//!
+//! Large payloads such as model weights and tensors move between servers in
+//! fixed-size chunks, each carrying its own BLAKE3 checksum. The receiver
+//! appends verified chunks to a partial file and always knows how many
+//! bytes it holds, so when a connection drops the sender asks where to
+//! resume and carries on from that offset instead of starting over.
+//!
+//! [`TransferSender`] drives a transfer over any [`TransferTransport`],
+//! retrying from the receiver's offset after connection failures;
+//! [`TransferReceiver`] is the other end. The wire messages live in
+//! `swarmx-protocol`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::pointer::{blake3_checksum, DataRef};
+use crate::store::Hasher;
+
+/// Default chunk size: 8MB
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Default number of attempts before a transfer is abandoned
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Transfer errors
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Connection failed: {0}")]
+    Connection(String),
+
+    #[error("Chunk at offset {got} out of order, expected offset {expected}")]
+    OutOfOrder { expected: u64, got: u64 },
+
+    #[error("Chunk checksum mismatch at offset {0}")]
+    ChunkChecksumMismatch(u64),
+
+    #[error("Size mismatch: expected {expected} bytes, got {actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    #[error("Checksum mismatch")]
+    ChecksumMismatch,
+
+    #[error("Transfer abandoned after {attempts} attempts: {last}")]
+    RetriesExhausted { attempts: u32, last: String },
+}
+
+impl TransferError {
+    /// Whether resuming the transfer may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, TransferError::Connection(_))
+    }
+}
+
+/// What is being transferred and how it is split
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferPlan {
+    /// Identifies the transfer across reconnections
+    pub transfer_id: Uuid,
+    /// Data being transferred
+    pub data_ref: DataRef,
+    /// Payload size in bytes
+    pub total_size: u64,
+    /// Size of every chunk but the last
+    pub chunk_size: u64,
+}
+
+impl TransferPlan {
+    /// Plan a transfer of `data_ref`'s payload in chunks of `chunk_size`
+    pub fn new(data_ref: DataRef, chunk_size: u64) -> Self {
+        Self {
+            transfer_id: Uuid::new_v4(),
+            total_size: data_ref.size_bytes,
+            chunk_size: chunk_size.max(1),
+            data_ref,
+        }
+    }
+
+    /// Number of chunks the payload is split into
+    pub fn chunk_count(&self) -> u64 {
+        self.total_size.div_ceil(self.chunk_size)
+    }
+}
+
+/// A piece of a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Position of the chunk in the payload
+    pub offset: u64,
+    /// BLAKE3 checksum of `data`
+    pub checksum: String,
+    pub data: Vec<u8>,
+}
+
+impl Chunk {
+    /// Create a chunk, checksumming its data
+    pub fn new(offset: u64, data: Vec<u8>) -> Self {
+        Self {
+            offset,
+            checksum: blake3_checksum(&data),
+            data,
+        }
+    }
+
+    /// Check the data against the chunk's checksum
+    pub fn verify(&self) -> Result<(), TransferError> {
+        if blake3_checksum(&self.data) == self.checksum {
+            Ok(())
+        } else {
+            Err(TransferError::ChunkChecksumMismatch(self.offset))
+        }
+    }
+}
+
+/// Connection to the receiving end of transfers
+///
+/// Implementations report lost connections as
+/// [`TransferError::Connection`], which makes the sender resume.
+pub trait TransferTransport {
+    /// Start or resume a transfer, returning how many bytes the receiver
+    /// already holds
+    fn init(&mut self, plan: &TransferPlan) -> Result<u64, TransferError>;
+
+    /// Send the chunk at the receiver's current offset
+    fn send_chunk(&mut self, plan: &TransferPlan, chunk: &Chunk) -> Result<(), TransferError>;
+
+    /// Finish a transfer whose chunks have all been sent
+    fn complete(&mut self, plan: &TransferPlan) -> Result<(), TransferError>;
+}
+
+/// Outcome of a finished transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferReport {
+    /// Connections used, including the first
+    pub attempts: u32,
+    /// Bytes sent, counting chunks resent after a connection dropped
+    pub bytes_sent: u64,
+}
+
+/// Sends a payload in chunks, resuming after connection failures
+#[derive(Debug)]
+pub struct TransferSender<R> {
+    plan: TransferPlan,
+    source: R,
+    max_attempts: u32,
+}
+
+impl<R: Read + Seek> TransferSender<R> {
+    /// Send the payload read from `source`
+    pub fn new(plan: TransferPlan, source: R) -> Self {
+        Self {
+            plan,
+            source,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Give up after `max_attempts` connections
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Get the plan of the transfer
+    pub fn plan(&self) -> &TransferPlan {
+        &self.plan
+    }
+
+    /// Run the transfer to completion
+    pub fn run<T: TransferTransport>(
+        &mut self,
+        transport: &mut T,
+    ) -> Result<TransferReport, TransferError> {
+        let mut report = TransferReport {
+            attempts: 0,
+            bytes_sent: 0,
+        };
+        loop {
+            report.attempts += 1;
+            match self.attempt(transport, &mut report.bytes_sent) {
+                Ok(()) => return Ok(report),
+                Err(e) if e.is_retryable() && report.attempts < self.max_attempts => continue,
+                Err(e) if e.is_retryable() => {
+                    return Err(TransferError::RetriesExhausted {
+                        attempts: report.attempts,
+                        last: e.to_string(),
+                    })
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Send everything past the receiver's offset over one connection
+    fn attempt<T: TransferTransport>(
+        &mut self,
+        transport: &mut T,
+        bytes_sent: &mut u64,
+    ) -> Result<(), TransferError> {
+        let mut offset = transport.init(&self.plan)?;
+        if offset > self.plan.total_size {
+            return Err(TransferError::SizeMismatch {
+                expected: self.plan.total_size,
+                actual: offset,
+            });
+        }
+        self.source.seek(SeekFrom::Start(offset))?;
+        while offset < self.plan.total_size {
+            let len = self.plan.chunk_size.min(self.plan.total_size - offset);
+            let mut data = Vec::with_capacity(len as usize);
+            (&mut self.source).take(len).read_to_end(&mut data)?;
+            if data.len() as u64 != len {
+                return Err(TransferError::SizeMismatch {
+                    expected: self.plan.total_size,
+                    actual: offset + data.len() as u64,
+                });
+            }
+            transport.send_chunk(&self.plan, &Chunk::new(offset, data))?;
+            offset += len;
+            *bytes_sent += len;
+        }
+        transport.complete(&self.plan)
+    }
+}
+
+/// Receives a payload into a partial file that survives reconnections
+#[derive(Debug)]
+pub struct TransferReceiver {
+    plan: TransferPlan,
+    partial: PathBuf,
+    file: File,
+    received: u64,
+}
+
+impl TransferReceiver {
+    /// Start receiving into `dir`, or resume a transfer already begun there
+    ///
+    /// A chunk cut short by a crash is discarded, so the transfer resumes
+    /// from the last complete chunk.
+    pub fn open(dir: impl AsRef<Path>, plan: TransferPlan) -> Result<Self, TransferError> {
+        fs::create_dir_all(dir.as_ref())?;
+        let partial = dir.as_ref().join(format!("{}.transfer", plan.transfer_id));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&partial)?;
+        let len = file.metadata()?.len().min(plan.total_size);
+        let received = if len == plan.total_size {
+            len
+        } else {
+            len - len % plan.chunk_size
+        };
+        file.set_len(received)?;
+        Ok(Self {
+            plan,
+            partial,
+            file,
+            received,
+        })
+    }
+
+    /// Get the plan of the transfer
+    pub fn plan(&self) -> &TransferPlan {
+        &self.plan
+    }
+
+    /// Bytes received so far, where the sender resumes
+    pub fn resume_offset(&self) -> u64 {
+        self.received
+    }
+
+    /// Verify and append the next chunk
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), TransferError> {
+        if chunk.offset != self.received {
+            return Err(TransferError::OutOfOrder {
+                expected: self.received,
+                got: chunk.offset,
+            });
+        }
+        chunk.verify()?;
+        let end = self.received + chunk.data.len() as u64;
+        if end > self.plan.total_size {
+            return Err(TransferError::SizeMismatch {
+                expected: self.plan.total_size,
+                actual: end,
+            });
+        }
+        self.file.seek(SeekFrom::Start(self.received))?;
+        self.file.write_all(&chunk.data)?;
+        // Only acknowledge what is on disk, so a resume never skips data
+        self.file.sync_data()?;
+        self.received = end;
+        Ok(())
+    }
+
+    /// Check the received payload against the plan and return its file
+    ///
+    /// The whole payload is verified against the ref's checksum, if it has
+    /// one; the caller moves the file into its store.
+    pub fn finish(self) -> Result<PathBuf, TransferError> {
+        if self.received != self.plan.total_size {
+            return Err(TransferError::SizeMismatch {
+                expected: self.plan.total_size,
+                actual: self.received,
+            });
+        }
+        if let Some(expected) = &self.plan.data_ref.checksum {
+            let mut hasher = Hasher::matching(expected);
+            let mut file = File::open(&self.partial)?;
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                match file.read(&mut buffer)? {
+                    0 => break,
+                    n => hasher.update(&buffer[..n]),
+                }
+            }
+            if hasher.finalize() != *expected {
+                return Err(TransferError::ChecksumMismatch);
+            }
+        }
+        Ok(self.partial)
+    }
+
+    /// Give up on the transfer, deleting what was received
+    pub fn abort(self) -> Result<(), TransferError> {
+        drop(self.file);
+        fs::remove_file(&self.partial)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::DataType;
+    use std::io::Cursor;
+
+    /// Transport delivering to a local receiver, dropping the connection
+    /// after a number of chunks
+    struct FlakyTransport {
+        dir: PathBuf,
+        receiver: Option<TransferReceiver>,
+        drop_after: Vec<usize>,
+        sent: usize,
+        finished: Option<PathBuf>,
+    }
+
+    impl TransferTransport for FlakyTransport {
+        fn init(&mut self, plan: &TransferPlan) -> Result<u64, TransferError> {
+            // Reopen on every connection, as a restarted receiver would
+            self.receiver = None;
+            let receiver = TransferReceiver::open(&self.dir, plan.clone())?;
+            let offset = receiver.resume_offset();
+            self.receiver = Some(receiver);
+            Ok(offset)
+        }
+
+        fn send_chunk(&mut self, _: &TransferPlan, chunk: &Chunk) -> Result<(), TransferError> {
+            if self.drop_after.first() == Some(&self.sent) {
+                self.drop_after.remove(0);
+                return Err(TransferError::Connection("reset by peer".to_string()));
+            }
+            self.sent += 1;
+            self.receiver.as_mut().unwrap().write_chunk(chunk)
+        }
+
+        fn complete(&mut self, _: &TransferPlan) -> Result<(), TransferError> {
+            self.finished = Some(self.receiver.take().unwrap().finish()?);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_resumable_transfer() {
+        let payload: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let data = DataRef::new(
+            "gpu-1".to_string(),
+            payload.len() as u64,
+            DataType::Bytes,
+            Uuid::new_v4(),
+        )
+        .with_content_address_of(&payload);
+        let plan = TransferPlan::new(data, 1024);
+        assert_eq!(plan.chunk_count(), 10);
+
+        let dir = std::env::temp_dir().join(format!("swarmx-transfer-{}", Uuid::new_v4()));
+        let mut transport = FlakyTransport {
+            dir: dir.clone(),
+            receiver: None,
+            drop_after: vec![3, 7],
+            sent: 0,
+            finished: None,
+        };
+        let report = TransferSender::new(plan.clone(), Cursor::new(&payload))
+            .run(&mut transport)
+            .unwrap();
+        assert_eq!(report.attempts, 3);
+        // Resumed where the connection dropped instead of from zero
+        assert_eq!(report.bytes_sent, payload.len() as u64);
+        assert_eq!(fs::read(transport.finished.unwrap()).unwrap(), payload);
+
+        // A corrupted chunk is rejected
+        let mut receiver =
+            TransferReceiver::open(&dir, TransferPlan::new(plan.data_ref, 1024)).unwrap();
+        let mut chunk = Chunk::new(0, payload[..1024].to_vec());
+        chunk.data[0] ^= 1;
+        assert!(matches!(
+            receiver.write_chunk(&chunk),
+            Err(TransferError::ChunkChecksumMismatch(0))
+        ));
+        assert!(matches!(
+            receiver.write_chunk(&Chunk::new(1024, payload[1024..2048].to_vec())),
+            Err(TransferError::OutOfOrder { expected: 0, .. })
+        ));
+        receiver.abort().unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,536 @@
+//! AMQP integration for distributed event streaming
+//!
+//! Publishes events to a durable RabbitMQ topic exchange and consumes them
+//! from a durable queue with manual acknowledgement. Each consumer queue is
+//! paired with a dead-letter exchange and queue (`{queue}.dead`): rejected
+//! events and payloads that fail to deserialize are dead-lettered rather
+//! than requeued forever.
+//!
+//! Unlike Kafka, an AMQP queue cannot be rewound, so there is no `seek`.
+
+use std::time::Duration;
+
+use futures_util::{FutureExt, StreamExt};
+use lapin::message::Delivery;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+    ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind};
+use tokio::sync::Mutex;
+
+use crate::types::Event;
+
+/// Persistent delivery mode, so queued events survive a broker restart
+const PERSISTENT: u8 = 2;
+
+/// How the routing key of a published event is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmqpRoutingKey {
+    /// Route by event type, e.g. `node_completed`
+    #[default]
+    EventType,
+    /// Route by workflow ID, or `system` for events without one
+    WorkflowId,
+}
+
+impl AmqpRoutingKey {
+    /// Routing key for an event
+    pub fn key_for(&self, event: &Event) -> String {
+        match self {
+            Self::EventType => event.event_type().to_string(),
+            Self::WorkflowId => event
+                .workflow_id()
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "system".to_string()),
+        }
+    }
+}
+
+/// Name of the dead-letter exchange paired with a queue
+fn dead_letter_exchange(queue: &str) -> String {
+    format!("{queue}.dlx")
+}
+
+/// Name of the dead-letter queue paired with a queue
+fn dead_letter_queue(queue: &str) -> String {
+    format!("{queue}.dead")
+}
+
+async fn open_channel(uri: &str) -> Result<(Connection, Channel), AmqpError> {
+    let connection = Connection::connect(uri, ConnectionProperties::default())
+        .await
+        .map_err(|e| AmqpError::Connection(e.to_string()))?;
+    let channel = connection
+        .create_channel()
+        .await
+        .map_err(|e| AmqpError::Connection(e.to_string()))?;
+    Ok((connection, channel))
+}
+
+async fn declare_exchange(channel: &Channel, exchange: &str) -> Result<(), AmqpError> {
+    channel
+        .exchange_declare(
+            exchange,
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .map_err(|e| AmqpError::Configuration(e.to_string()))
+}
+
+/// AMQP producer for event publishing
+pub struct AmqpEventProducer {
+    /// Keeps the connection open for the channel's lifetime
+    _connection: Connection,
+    channel: Channel,
+    /// Exchange events are published to
+    exchange: String,
+    routing: AmqpRoutingKey,
+}
+
+impl AmqpEventProducer {
+    /// Connect and declare the exchange
+    ///
+    /// # Arguments
+    /// * `uri` - Broker URI, e.g. `amqp://localhost:5672/%2f`
+    /// * `exchange` - Topic exchange to publish events to
+    /// * `routing` - How routing keys are derived from events
+    pub async fn new(
+        uri: &str,
+        exchange: &str,
+        routing: AmqpRoutingKey,
+    ) -> Result<Self, AmqpError> {
+        let (connection, channel) = open_channel(uri).await?;
+        declare_exchange(&channel, exchange).await?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .map_err(|e| AmqpError::Configuration(e.to_string()))?;
+
+        Ok(Self {
+            _connection: connection,
+            channel,
+            exchange: exchange.to_string(),
+            routing,
+        })
+    }
+
+    /// Publish an event and wait for the broker to confirm it
+    pub async fn publish(&self, event: &Event) -> Result<(), AmqpError> {
+        self.publish_with_key(&self.routing.key_for(event), event)
+            .await
+    }
+
+    /// Publish an event with a specific routing key
+    pub async fn publish_with_key(&self, key: &str, event: &Event) -> Result<(), AmqpError> {
+        let confirm = self
+            .channel
+            .basic_publish(
+                &self.exchange,
+                key,
+                BasicPublishOptions::default(),
+                &serialize(event)?,
+                properties(),
+            )
+            .await
+            .map_err(|e| AmqpError::Publish(e.to_string()))?
+            .await
+            .map_err(|e| AmqpError::Publish(e.to_string()))?;
+        if confirm.is_nack() {
+            return Err(AmqpError::Publish("broker rejected event".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Publish multiple events as a batch
+    ///
+    /// All events are sent before any confirmation is awaited.
+    pub async fn publish_batch(&self, events: &[Event]) -> Result<(), AmqpError> {
+        let mut confirms = Vec::with_capacity(events.len());
+        for event in events {
+            let confirm = self
+                .channel
+                .basic_publish(
+                    &self.exchange,
+                    &self.routing.key_for(event),
+                    BasicPublishOptions::default(),
+                    &serialize(event)?,
+                    properties(),
+                )
+                .await
+                .map_err(|e| AmqpError::Publish(e.to_string()))?;
+            confirms.push(confirm);
+        }
+        for confirm in confirms {
+            let confirm = confirm
+                .await
+                .map_err(|e| AmqpError::Publish(e.to_string()))?;
+            if confirm.is_nack() {
+                return Err(AmqpError::Publish("broker rejected event".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until every published event is confirmed
+    pub async fn flush(&self) -> Result<(), AmqpError> {
+        self.channel
+            .wait_for_confirms()
+            .await
+            .map(|_| ())
+            .map_err(|e| AmqpError::Publish(e.to_string()))
+    }
+}
+
+fn serialize(event: &Event) -> Result<Vec<u8>, AmqpError> {
+    serde_json::to_vec(event).map_err(|e| AmqpError::Serialization(e.to_string()))
+}
+
+fn properties() -> BasicProperties {
+    BasicProperties::default()
+        .with_content_type("application/json".into())
+        .with_delivery_mode(PERSISTENT)
+}
+
+/// AMQP consumer for event subscription
+pub struct AmqpEventConsumer {
+    /// Keeps the connection open for the channel's lifetime
+    _connection: Connection,
+    channel: Channel,
+    /// Queue events are consumed from
+    queue: String,
+    /// Maximum unacknowledged deliveries
+    prefetch: u16,
+    /// How long a poll waits for the first event
+    poll_timeout: Duration,
+    consumer: Mutex<Option<lapin::Consumer>>,
+    /// Delivered events not yet acknowledged
+    pending: Mutex<Vec<Delivery>>,
+}
+
+impl AmqpEventConsumer {
+    /// Connect and declare the queue, its dead-letter queue, and the binding
+    ///
+    /// # Arguments
+    /// * `uri` - Broker URI
+    /// * `exchange` - Topic exchange events are published to
+    /// * `queue` - Durable queue to consume from
+    /// * `binding_key` - Routing pattern to bind with, e.g. `#` or `node_*`
+    pub async fn new(
+        uri: &str,
+        exchange: &str,
+        queue: &str,
+        binding_key: &str,
+    ) -> Result<Self, AmqpError> {
+        let (connection, channel) = open_channel(uri).await?;
+        declare_exchange(&channel, exchange).await?;
+
+        let dlx = dead_letter_exchange(queue);
+        let dlq = dead_letter_queue(queue);
+        let durable = QueueDeclareOptions {
+            durable: true,
+            ..Default::default()
+        };
+        let configure = |e: lapin::Error| AmqpError::Configuration(e.to_string());
+
+        channel
+            .exchange_declare(
+                &dlx,
+                ExchangeKind::Fanout,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(configure)?;
+        channel
+            .queue_declare(&dlq, durable, FieldTable::default())
+            .await
+            .map_err(configure)?;
+        channel
+            .queue_bind(
+                &dlq,
+                &dlx,
+                "",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(configure)?;
+
+        let mut arguments = FieldTable::default();
+        arguments.insert(
+            "x-dead-letter-exchange".into(),
+            AMQPValue::LongString(dlx.as_str().into()),
+        );
+        channel
+            .queue_declare(queue, durable, arguments)
+            .await
+            .map_err(configure)?;
+        channel
+            .queue_bind(
+                queue,
+                exchange,
+                binding_key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(configure)?;
+
+        Ok(Self {
+            _connection: connection,
+            channel,
+            queue: queue.to_string(),
+            prefetch: 256,
+            poll_timeout: Duration::from_secs(1),
+            consumer: Mutex::new(None),
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Set the maximum number of unacknowledged deliveries
+    pub fn with_prefetch(mut self, prefetch: u16) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    /// Set how long a poll waits for the first event
+    pub fn with_poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    /// Name of the dead-letter queue for this consumer
+    pub fn dead_letter_queue(&self) -> String {
+        dead_letter_queue(&self.queue)
+    }
+
+    /// Start consuming from the queue
+    pub async fn subscribe(&self) -> Result<(), AmqpError> {
+        self.channel
+            .basic_qos(self.prefetch, BasicQosOptions::default())
+            .await
+            .map_err(|e| AmqpError::Subscribe(e.to_string()))?;
+        let consumer = self
+            .channel
+            .basic_consume(
+                &self.queue,
+                "",
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(|e| AmqpError::Subscribe(e.to_string()))?;
+        *self.consumer.lock().await = Some(consumer);
+        Ok(())
+    }
+
+    /// Poll for the next event
+    pub async fn poll(&self) -> Result<Option<Event>, AmqpError> {
+        Ok(self.poll_batch(1).await?.pop())
+    }
+
+    /// Poll for the next batch of events
+    ///
+    /// Waits up to the poll timeout for the first event, then takes whatever
+    /// else is already buffered. Delivered events stay unacknowledged until
+    /// [`commit`](Self::commit) or [`reject`](Self::reject). Payloads that
+    /// are not valid events are dead-lettered immediately.
+    pub async fn poll_batch(&self, max_messages: usize) -> Result<Vec<Event>, AmqpError> {
+        let mut guard = self.consumer.lock().await;
+        let consumer = guard
+            .as_mut()
+            .ok_or_else(|| AmqpError::Subscribe("not subscribed".to_string()))?;
+
+        let mut events = Vec::new();
+        let mut pending = self.pending.lock().await;
+        while events.len() < max_messages {
+            let next = if events.is_empty() {
+                match tokio::time::timeout(self.poll_timeout, consumer.next()).await {
+                    Ok(next) => next,
+                    Err(_) => break,
+                }
+            } else {
+                match consumer.next().now_or_never() {
+                    Some(next) => next,
+                    None => break,
+                }
+            };
+            let Some(delivery) = next else {
+                return Err(AmqpError::Poll("consumer cancelled".to_string()));
+            };
+            let delivery = delivery.map_err(|e| AmqpError::Poll(e.to_string()))?;
+
+            match serde_json::from_slice::<Event>(&delivery.data) {
+                Ok(event) => {
+                    events.push(event);
+                    pending.push(delivery);
+                }
+                Err(e) => {
+                    tracing::warn!("dead-lettering malformed event from {}: {e}", self.queue);
+                    delivery
+                        .nack(BasicNackOptions {
+                            requeue: false,
+                            ..Default::default()
+                        })
+                        .await
+                        .map_err(|e| AmqpError::Commit(e.to_string()))?;
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Acknowledge every event delivered so far
+    pub async fn commit(&self) -> Result<(), AmqpError> {
+        let mut pending = self.pending.lock().await;
+        for delivery in pending.drain(..) {
+            delivery
+                .ack(BasicAckOptions::default())
+                .await
+                .map_err(|e| AmqpError::Commit(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Reject every event delivered so far
+    ///
+    /// With `requeue` the events are redelivered; otherwise they are routed
+    /// to the dead-letter queue.
+    pub async fn reject(&self, requeue: bool) -> Result<(), AmqpError> {
+        let mut pending = self.pending.lock().await;
+        for delivery in pending.drain(..) {
+            delivery
+                .nack(BasicNackOptions {
+                    requeue,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| AmqpError::Commit(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// AMQP configuration builder
+pub struct AmqpConfig {
+    uri: String,
+    exchange: String,
+    queue: Option<String>,
+    routing: AmqpRoutingKey,
+    binding_key: String,
+    prefetch: u16,
+}
+
+impl AmqpConfig {
+    /// Create a new AMQP configuration
+    pub fn new(uri: &str, exchange: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            exchange: exchange.to_string(),
+            queue: None,
+            routing: AmqpRoutingKey::default(),
+            binding_key: "#".to_string(),
+            prefetch: 256,
+        }
+    }
+
+    /// Set the consumer queue name
+    pub fn queue(mut self, queue: &str) -> Self {
+        self.queue = Some(queue.to_string());
+        self
+    }
+
+    /// Set how routing keys are derived from events
+    pub fn routing(mut self, routing: AmqpRoutingKey) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Set the routing pattern the consumer queue is bound with
+    pub fn binding_key(mut self, binding_key: &str) -> Self {
+        self.binding_key = binding_key.to_string();
+        self
+    }
+
+    /// Set the maximum number of unacknowledged deliveries
+    pub fn prefetch(mut self, prefetch: u16) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    /// Build a producer
+    pub async fn build_producer(self) -> Result<AmqpEventProducer, AmqpError> {
+        AmqpEventProducer::new(&self.uri, &self.exchange, self.routing).await
+    }
+
+    /// Build a consumer
+    pub async fn build_consumer(self) -> Result<AmqpEventConsumer, AmqpError> {
+        let queue = self.queue.unwrap_or_else(|| "swarmx-ui".to_string());
+        Ok(
+            AmqpEventConsumer::new(&self.uri, &self.exchange, &queue, &self.binding_key)
+                .await?
+                .with_prefetch(self.prefetch),
+        )
+    }
+}
+
+/// AMQP errors
+#[derive(Debug, thiserror::Error)]
+pub enum AmqpError {
+    #[error("Connection error: {0}")]
+    Connection(String),
+
+    #[error("Publish error: {0}")]
+    Publish(String),
+
+    #[error("Subscribe error: {0}")]
+    Subscribe(String),
+
+    #[error("Poll error: {0}")]
+    Poll(String),
+
+    #[error("Commit error: {0}")]
+    Commit(String),
+
+    #[error("Configuration error: {0}")]
+    Configuration(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_routing_keys() {
+        let workflow_id = Uuid::new_v4();
+        let event = Event::WorkflowStarted {
+            workflow_id,
+            name: "test".to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+
+        assert_eq!(
+            AmqpRoutingKey::EventType.key_for(&event),
+            "workflow_started"
+        );
+        assert_eq!(
+            AmqpRoutingKey::WorkflowId.key_for(&event),
+            workflow_id.to_string()
+        );
+        assert_eq!(dead_letter_queue("events"), "events.dead");
+    }
+}
@@ -0,0 +1,299 @@
+//! Cold archive of compacted events
+//!
+//! [`WriteAheadLog::archive_before`] compacts the log by time, but first
+//! writes every event it is about to remove to a gzip-compressed JSONL
+//! file, one [`EventEnvelope`] per line. Archives are named after the
+//! sequence range they cover, e.g.
+//! `events-00000000000000000001-00000000000000004096.jsonl.gz`, so they
+//! sort in log order.
+//!
+//! Archives live in an [`ArchiveStore`]: a local directory out of the box,
+//! or object storage through a custom implementation. For forensic replay,
+//! [`ColdArchive::read`] loads an archive's envelopes and
+//! [`WriteAheadLog::import_archive`] inserts them back into a log under
+//! their original sequence numbers.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::params;
+
+use crate::audit::AUDIT_EVENT_TYPE;
+use crate::types::EventEnvelope;
+use crate::wal::{EnvelopeRow, WalError, WriteAheadLog, ENVELOPE_COLUMNS};
+
+/// File name prefix of archives
+const ARCHIVE_PREFIX: &str = "events-";
+
+/// File name suffix of archives
+const ARCHIVE_SUFFIX: &str = ".jsonl.gz";
+
+/// Storage for archive files
+///
+/// Implement this for object storage; blocking calls are fine, archiving
+/// runs wherever the WAL is compacted.
+pub trait ArchiveStore: Send + Sync {
+    /// Store an archive, replacing any archive with the same name
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()>;
+
+    /// Load an archive
+    fn get(&self, name: &str) -> io::Result<Vec<u8>>;
+
+    /// Names of every stored object, in any order
+    fn list(&self) -> io::Result<Vec<String>>;
+}
+
+/// Archive store backed by a local directory
+#[derive(Debug, Clone)]
+pub struct LocalArchiveStore {
+    dir: PathBuf,
+}
+
+impl LocalArchiveStore {
+    /// Store archives in `dir`, which is created on first use
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Get the archive directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl ArchiveStore for LocalArchiveStore {
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first, so a crash never leaves a
+        // truncated archive behind
+        let tmp = self.dir.join(format!(".{name}.tmp"));
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, self.dir.join(name))
+    }
+
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.dir.join(name))
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+}
+
+/// An archive file and the sequence range it covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveInfo {
+    /// Name of the archive in its store
+    pub name: String,
+    /// Sequence number of the first archived event
+    pub first_sequence: u64,
+    /// Sequence number of the last archived event
+    pub last_sequence: u64,
+}
+
+impl ArchiveInfo {
+    fn new(first_sequence: u64, last_sequence: u64) -> Self {
+        Self {
+            name: format!(
+                "{ARCHIVE_PREFIX}{first_sequence:020}-{last_sequence:020}{ARCHIVE_SUFFIX}"
+            ),
+            first_sequence,
+            last_sequence,
+        }
+    }
+
+    /// Parse an archive name, or `None` for unrelated objects
+    fn parse(name: &str) -> Option<Self> {
+        let range = name
+            .strip_prefix(ARCHIVE_PREFIX)?
+            .strip_suffix(ARCHIVE_SUFFIX)?;
+        let (first, last) = range.split_once('-')?;
+        Some(Self {
+            name: name.to_string(),
+            first_sequence: first.parse().ok()?,
+            last_sequence: last.parse().ok()?,
+        })
+    }
+}
+
+/// Archives of compacted events in an [`ArchiveStore`]
+///
+/// Cheaply cloneable.
+#[derive(Clone)]
+pub struct ColdArchive {
+    store: Arc<dyn ArchiveStore>,
+}
+
+impl ColdArchive {
+    /// Keep archives in `store`
+    pub fn new<S: ArchiveStore + 'static>(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    /// Keep archives in a local directory
+    pub fn local<P: AsRef<Path>>(dir: P) -> Self {
+        Self::new(LocalArchiveStore::new(dir))
+    }
+
+    /// List archives, ordered by sequence range
+    pub fn list(&self) -> Result<Vec<ArchiveInfo>, WalError> {
+        let mut archives: Vec<ArchiveInfo> = self
+            .store
+            .list()?
+            .iter()
+            .filter_map(|name| ArchiveInfo::parse(name))
+            .collect();
+        archives.sort_by_key(|archive| archive.first_sequence);
+        Ok(archives)
+    }
+
+    /// Load the envelopes of an archive, in sequence order
+    pub fn read(&self, name: &str) -> Result<Vec<EventEnvelope>, WalError> {
+        let data = self.store.get(name)?;
+        let mut envelopes = Vec::new();
+        for line in BufReader::new(GzDecoder::new(data.as_slice())).lines() {
+            let line = line?;
+            if !line.is_empty() {
+                envelopes.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(envelopes)
+    }
+
+    /// Write envelopes, which must be in sequence order, to a new archive
+    fn write(&self, envelopes: &[EventEnvelope]) -> Result<Option<ArchiveInfo>, WalError> {
+        let (Some(first), Some(last)) = (envelopes.first(), envelopes.last()) else {
+            return Ok(None);
+        };
+        let info = ArchiveInfo::new(first.sequence, last.sequence);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        for envelope in envelopes {
+            serde_json::to_writer(&mut encoder, envelope)?;
+            encoder.write_all(b"\n")?;
+        }
+        self.store.put(&info.name, &encoder.finish()?)?;
+        Ok(Some(info))
+    }
+}
+
+impl WriteAheadLog {
+    /// Archive and then compact entries older than a timestamp
+    ///
+    /// Removes the same events as
+    /// [`compact_before`](Self::compact_before), after writing them to a
+    /// new archive. If archiving fails nothing is removed. Returns the new
+    /// archive, or `None` if there was nothing to compact.
+    pub fn archive_before(
+        &mut self,
+        before: DateTime<Utc>,
+        archive: &ColdArchive,
+    ) -> Result<Option<ArchiveInfo>, WalError> {
+        let before_sequence = self.compaction_bound(self.sequence_at(before)?)?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ENVELOPE_COLUMNS} FROM events
+             WHERE sequence < ?1 AND event_type != ?2
+             ORDER BY sequence ASC"
+        ))?;
+        let envelopes = stmt
+            .query_map(
+                params![before_sequence, AUDIT_EVENT_TYPE],
+                EnvelopeRow::read,
+            )?
+            .map(|row| row?.decode())
+            .collect::<Result<Vec<_>, WalError>>()?;
+        drop(stmt);
+
+        let Some(info) = archive.write(&envelopes)? else {
+            return Ok(None);
+        };
+        self.compact(before_sequence)?;
+        Ok(Some(info))
+    }
+
+    /// Insert the events of an archive back into this log
+    ///
+    /// See [`import_envelopes`](Self::import_envelopes). Returns the number
+    /// of events inserted.
+    pub fn import_archive(&mut self, archive: &ColdArchive, name: &str) -> Result<usize, WalError> {
+        let envelopes = archive.read(name)?;
+        self.import_envelopes(&envelopes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_archive_then_import_for_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = ColdArchive::local(dir.path().join("archive"));
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let workflow_id = Uuid::new_v4();
+        for _ in 0..3 {
+            wal.append(Event::WorkflowStarted {
+                workflow_id,
+                name: "nightly".to_string(),
+                timestamp: Utc::now(),
+            })
+            .unwrap();
+        }
+
+        // Nothing is older than an hour ago
+        let hour_ago = Utc::now() - Duration::hours(1);
+        assert!(wal.archive_before(hour_ago, &archive).unwrap().is_none());
+
+        let info = wal
+            .archive_before(Utc::now() + Duration::seconds(1), &archive)
+            .unwrap()
+            .unwrap();
+        assert_eq!((info.first_sequence, info.last_sequence), (1, 3));
+        assert_eq!(wal.count().unwrap(), 0);
+        assert_eq!(archive.list().unwrap(), vec![info.clone()]);
+
+        let mut scratch = WriteAheadLog::in_memory().unwrap();
+        assert_eq!(scratch.import_archive(&archive, &info.name).unwrap(), 3);
+        assert_eq!(scratch.import_archive(&archive, &info.name).unwrap(), 0);
+        let replayed = scratch.events_for_workflow(workflow_id).unwrap();
+        assert_eq!(
+            replayed.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            scratch
+                .append(Event::DataDeleted {
+                    data_uuid: Uuid::new_v4(),
+                    timestamp: Utc::now(),
+                })
+                .unwrap()
+                .sequence,
+            4
+        );
+    }
+}
@@ -0,0 +1,475 @@
+//! Non-blocking facade over the write-ahead log
+//!
+//! Every [`WalBackend`] performs blocking I/O, which must not run on the
+//! async runtime's worker threads. [`AsyncWal`] moves the log onto a
+//! dedicated writer thread fed by a bounded queue. Appends that arrive
+//! while a write is in progress are grouped into a single transaction
+//! (group commit), and each caller is woken once its events are committed.
+//!
+//! Dropping the handle or calling [`AsyncWal::shutdown`] drains every
+//! queued request before the writer thread exits.
+//!
+//! An optional notifier receives every committed envelope, in sequence
+//! order, right after its transaction commits; the
+//! [`EventBus`](crate::bus::EventBus) is built on this.
+//!
+//! Backends that offer a [`WalReader`] serve reads from its connection pool
+//! on the blocking thread pool, so readers neither queue behind appends nor
+//! delay them. Other backends serve reads on the writer thread.
+
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::backup::BackupInfo;
+use crate::reader::WalReader;
+use crate::types::{Event, EventEnvelope, EventFilter};
+use crate::wal::{AppendEntry, WalBackend, WalError, WriteAheadLog};
+
+/// Async WAL configuration
+#[derive(Debug, Clone)]
+pub struct AsyncWalConfig {
+    /// Maximum number of events committed in one transaction
+    pub max_batch_size: usize,
+    /// How long the writer waits for more appends before committing a
+    /// partial batch. Zero commits whatever is already queued.
+    pub max_batch_delay: Duration,
+    /// Capacity of the request queue; senders wait when it is full
+    pub queue_capacity: usize,
+}
+
+impl Default for AsyncWalConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 256,
+            max_batch_delay: Duration::ZERO,
+            queue_capacity: 1024,
+        }
+    }
+}
+
+type Reply<T> = oneshot::Sender<Result<T, WalError>>;
+
+/// Requests handled by the writer thread
+enum Command {
+    Append {
+        events: Vec<AppendEntry>,
+        reply: Reply<Vec<EventEnvelope>>,
+    },
+    Read {
+        filter: EventFilter,
+        reply: Reply<Vec<EventEnvelope>>,
+    },
+    Flush {
+        reply: Reply<()>,
+    },
+    Backup {
+        path: PathBuf,
+        reply: Reply<BackupInfo>,
+    },
+    Restore {
+        path: PathBuf,
+        reply: Reply<BackupInfo>,
+    },
+}
+
+/// Handle to a WAL owned by a dedicated writer thread
+///
+/// Cloning is not supported; share it behind an `Arc`.
+pub struct AsyncWal {
+    tx: Option<mpsc::Sender<Command>>,
+    writer: Option<JoinHandle<()>>,
+    reader: Option<WalReader>,
+}
+
+impl AsyncWal {
+    /// Open or create a SQLite WAL at the given path and start its writer thread
+    pub fn open<P: AsRef<Path>>(path: P, config: AsyncWalConfig) -> Result<Self, WalError> {
+        Self::new(WriteAheadLog::open(path)?, config)
+    }
+
+    /// Move an existing WAL backend onto a writer thread
+    pub fn new<W: WalBackend + 'static>(wal: W, config: AsyncWalConfig) -> Result<Self, WalError> {
+        Self::spawn(wal, config, None)
+    }
+
+    /// Move a WAL backend onto a writer thread that announces every
+    /// committed envelope on `notify`
+    pub fn with_notifier<W: WalBackend + 'static>(
+        wal: W,
+        config: AsyncWalConfig,
+        notify: broadcast::Sender<EventEnvelope>,
+    ) -> Result<Self, WalError> {
+        Self::spawn(wal, config, Some(notify))
+    }
+
+    fn spawn<W: WalBackend + 'static>(
+        wal: W,
+        config: AsyncWalConfig,
+        notify: Option<broadcast::Sender<EventEnvelope>>,
+    ) -> Result<Self, WalError> {
+        let reader = wal.reader();
+        let (tx, rx) = mpsc::channel(config.queue_capacity.max(1));
+        let writer = std::thread::Builder::new()
+            .name("swarmx-wal-writer".to_string())
+            .spawn(move || run_writer(wal, rx, config, notify))?;
+
+        Ok(Self {
+            tx: Some(tx),
+            writer: Some(writer),
+            reader,
+        })
+    }
+
+    /// Append an event, returning once it is committed
+    pub async fn append(&self, event: Event) -> Result<EventEnvelope, WalError> {
+        let mut envelopes = self.append_batch(vec![event]).await?;
+        Ok(envelopes.remove(0))
+    }
+
+    /// Append multiple events atomically, returning once they are committed
+    pub async fn append_batch(&self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError> {
+        self.append_entries(events.into_iter().map(AppendEntry::new).collect())
+            .await
+    }
+
+    /// Append an event unless one with the same idempotency key exists
+    ///
+    /// Returns the original envelope for a duplicate key.
+    pub async fn append_idempotent(
+        &self,
+        key: &str,
+        event: Event,
+    ) -> Result<EventEnvelope, WalError> {
+        let mut envelopes = self
+            .append_keyed_batch(vec![(event, Some(key.to_string()))])
+            .await?;
+        Ok(envelopes.remove(0))
+    }
+
+    /// Append events with optional idempotency keys atomically
+    ///
+    /// See [`WalBackend::append_keyed_batch`].
+    pub async fn append_keyed_batch(
+        &self,
+        events: Vec<(Event, Option<String>)>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        self.append_entries(events.into_iter().map(AppendEntry::from).collect())
+            .await
+    }
+
+    /// Append entries atomically, returning once they are committed
+    ///
+    /// See [`WalBackend::append_entries`].
+    pub async fn append_entries(
+        &self,
+        entries: Vec<AppendEntry>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.request(|reply| Command::Append {
+            events: entries,
+            reply,
+        })
+        .await
+    }
+
+    /// Read events from a given sequence number
+    pub async fn read_from(&self, sequence: u64) -> Result<Vec<EventEnvelope>, WalError> {
+        self.read_filtered(EventFilter::new().from_sequence(sequence))
+            .await
+    }
+
+    /// Read events matching a filter
+    ///
+    /// Reads see every append that has returned. Without a [`WalReader`]
+    /// they are also ordered after every append queued before them.
+    pub async fn read_filtered(&self, filter: EventFilter) -> Result<Vec<EventEnvelope>, WalError> {
+        match &self.reader {
+            Some(reader) => {
+                let reader = reader.clone();
+                tokio::task::spawn_blocking(move || reader.read_filtered(&filter))
+                    .await
+                    .map_err(|e| WalError::Io(std::io::Error::other(e)))?
+            }
+            None => self.request(|reply| Command::Read { filter, reply }).await,
+        }
+    }
+
+    /// Write a consistent copy of the log to `path`
+    ///
+    /// Runs on the reader's connection pool when the backend has one, so
+    /// appends carry on meanwhile.
+    pub async fn backup<P: AsRef<Path>>(&self, path: P) -> Result<BackupInfo, WalError> {
+        let path = path.as_ref().to_path_buf();
+        match &self.reader {
+            Some(reader) => {
+                let reader = reader.clone();
+                tokio::task::spawn_blocking(move || reader.backup(path))
+                    .await
+                    .map_err(|e| WalError::Io(std::io::Error::other(e)))?
+            }
+            None => self.request(|reply| Command::Backup { path, reply }).await,
+        }
+    }
+
+    /// Replace the log's contents with a backup
+    ///
+    /// Appends queued earlier are committed first. See
+    /// [`WriteAheadLog::restore`] for the checks applied to the backup.
+    pub async fn restore<P: AsRef<Path>>(&self, path: P) -> Result<BackupInfo, WalError> {
+        let path = path.as_ref().to_path_buf();
+        self.request(|reply| Command::Restore { path, reply }).await
+    }
+
+    /// Wait until every previously queued append is committed
+    pub async fn flush(&self) -> Result<(), WalError> {
+        self.request(|reply| Command::Flush { reply }).await
+    }
+
+    /// Stop accepting requests, drain the queue, and stop the writer thread
+    pub async fn shutdown(mut self) -> Result<(), WalError> {
+        self.flush().await?;
+        self.tx.take();
+        if let Some(writer) = self.writer.take() {
+            tokio::task::spawn_blocking(move || writer.join())
+                .await
+                .map_err(|_| WalError::WriterClosed)?
+                .map_err(|_| WalError::WriterClosed)?;
+        }
+        Ok(())
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Result<T, WalError> {
+        let tx = self.tx.as_ref().ok_or(WalError::WriterClosed)?;
+        let (reply, response) = oneshot::channel();
+        tx.send(command(reply))
+            .await
+            .map_err(|_| WalError::WriterClosed)?;
+        response.await.map_err(|_| WalError::WriterClosed)?
+    }
+}
+
+impl Drop for AsyncWal {
+    fn drop(&mut self) {
+        // Closing the queue lets the writer drain outstanding requests and exit
+        self.tx.take();
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                tracing::error!("WAL writer thread panicked");
+            }
+        }
+    }
+}
+
+/// Writer thread main loop
+fn run_writer<W: WalBackend>(
+    mut wal: W,
+    mut rx: mpsc::Receiver<Command>,
+    config: AsyncWalConfig,
+    notify: Option<broadcast::Sender<EventEnvelope>>,
+) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            tracing::error!(error = %e, "failed to start WAL writer runtime");
+            return;
+        }
+    };
+
+    runtime.block_on(async move {
+        let mut pending = None;
+        loop {
+            let command = match pending.take() {
+                Some(command) => command,
+                None => match rx.recv().await {
+                    Some(command) => command,
+                    None => break,
+                },
+            };
+
+            match command {
+                Command::Append { events, reply } => {
+                    let mut batch = vec![(events, reply)];
+                    pending = collect_batch(&mut rx, &mut batch, &config).await;
+                    commit_batch(&mut wal, batch, notify.as_ref());
+                }
+                Command::Read { filter, reply } => {
+                    let _ = reply.send(wal.read_filtered(&filter));
+                }
+                Command::Flush { reply } => {
+                    let _ = reply.send(Ok(()));
+                }
+                Command::Backup { path, reply } => {
+                    let _ = reply.send(wal.backup(&path));
+                }
+                Command::Restore { path, reply } => {
+                    let _ = reply.send(wal.restore(&path));
+                }
+            }
+        }
+    });
+}
+
+/// Gather queued appends into `batch` for a group commit
+///
+/// Returns the first non-append command encountered, which must be handled
+/// after the batch is committed to preserve ordering.
+async fn collect_batch(
+    rx: &mut mpsc::Receiver<Command>,
+    batch: &mut Vec<(Vec<AppendEntry>, Reply<Vec<EventEnvelope>>)>,
+    config: &AsyncWalConfig,
+) -> Option<Command> {
+    let deadline = Instant::now() + config.max_batch_delay;
+    let mut size: usize = batch.iter().map(|(events, _)| events.len()).sum();
+
+    while size < config.max_batch_size {
+        let next = match rx.try_recv() {
+            Ok(command) => Some(command),
+            Err(_) if config.max_batch_delay.is_zero() => None,
+            Err(_) => tokio::time::timeout_at(deadline, rx.recv())
+                .await
+                .ok()
+                .flatten(),
+        };
+        match next {
+            Some(Command::Append { events, reply }) => {
+                size += events.len();
+                batch.push((events, reply));
+            }
+            Some(other) => return Some(other),
+            None => break,
+        }
+    }
+    None
+}
+
+/// Commit a group of appends in one transaction and notify each caller
+///
+/// If the combined transaction fails, each request is retried on its own so
+/// that one bad request does not fail the others. Duplicates returned for a
+/// reused idempotency key are not announced again.
+fn commit_batch<W: WalBackend>(
+    wal: &mut W,
+    batch: Vec<(Vec<AppendEntry>, Reply<Vec<EventEnvelope>>)>,
+    notify: Option<&broadcast::Sender<EventEnvelope>>,
+) {
+    // New envelopes have increasing sequence numbers; anything at or below
+    // the last announced one is a duplicate returned for a reused key
+    let announced = Cell::new(wal.last_sequence());
+    let announce = |result: &Result<Vec<EventEnvelope>, WalError>| {
+        if let (Some(notify), Ok(envelopes)) = (notify, result) {
+            for envelope in envelopes {
+                if envelope.sequence <= announced.get() {
+                    continue;
+                }
+                announced.set(envelope.sequence);
+                // Sending only fails when nobody is subscribed
+                let _ = notify.send(envelope.clone());
+            }
+        }
+    };
+
+    if batch.len() == 1 {
+        let (events, reply) = batch.into_iter().next().unwrap();
+        let result = wal.append_entries(events);
+        announce(&result);
+        let _ = reply.send(result);
+        return;
+    }
+
+    let counts: Vec<usize> = batch.iter().map(|(events, _)| events.len()).collect();
+    let all: Vec<AppendEntry> = batch
+        .iter()
+        .flat_map(|(events, _)| events.clone())
+        .collect();
+
+    match wal.append_entries(all) {
+        Ok(mut envelopes) => {
+            for ((_, reply), count) in batch.into_iter().zip(counts) {
+                let rest = envelopes.split_off(count);
+                let result = Ok(std::mem::replace(&mut envelopes, rest));
+                announce(&result);
+                let _ = reply.send(result);
+            }
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "group commit failed, retrying requests individually");
+            for (events, reply) in batch {
+                let result = wal.append_entries(events);
+                announce(&result);
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn event(workflow_id: Uuid) -> Event {
+        Event::NodeStarted {
+            workflow_id,
+            node_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_appends_are_group_committed() {
+        let config = AsyncWalConfig {
+            max_batch_size: 16,
+            max_batch_delay: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let wal = Arc::new(AsyncWal::new(WriteAheadLog::in_memory().unwrap(), config).unwrap());
+        let workflow_id = Uuid::new_v4();
+
+        let handles: Vec<_> = (0..50)
+            .map(|_| {
+                let wal = wal.clone();
+                tokio::spawn(async move { wal.append(event(workflow_id)).await.unwrap() })
+            })
+            .collect();
+        let mut sequences = Vec::new();
+        for handle in handles {
+            sequences.push(handle.await.unwrap().sequence);
+        }
+        sequences.sort();
+        assert_eq!(sequences, (1..=50).collect::<Vec<u64>>());
+
+        let events = wal
+            .read_filtered(EventFilter::new().workflow(workflow_id))
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+
+        let wal = AsyncWal::open(&path, AsyncWalConfig::default()).unwrap();
+        wal.append_batch(vec![event(Uuid::new_v4()), event(Uuid::new_v4())])
+            .await
+            .unwrap();
+        wal.shutdown().await.unwrap();
+
+        let reopened = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(reopened.count().unwrap(), 2);
+    }
+}
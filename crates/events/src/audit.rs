@@ -0,0 +1,417 @@
+//! Audit trail of control-plane actions and data access
+//!
+//! [`Event::Audit`] events record who created, changed, or deleted
+//! workflows, cancelled executions, registered servers, and issued tokens,
+//! and who read, wrote, or deleted which data.
+//! They are ordinary events, so they flow through the same WAL, bus, and
+//! exporters as everything else, and [`AuditFilter`] narrows a read down to
+//! the trail.
+//!
+//! The SQLite WAL additionally makes the trail tamper-evident: each audit
+//! event is linked into a SHA-256 hash chain in the `audit_chain` table as
+//! it is appended, and audit events are never removed by compaction.
+//! [`WriteAheadLog::verify_audit_trail`] recomputes the chain and fails on
+//! the first record that was edited, removed, or inserted out of band.
+//! Keeping a copy of the reported head hash elsewhere also detects the
+//! chain being rewritten wholesale.
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::compression::{decode_payload, PayloadEncoding};
+use crate::types::{AuditAction, Event, EventEnvelope, EventFilter, EventKind};
+use crate::wal::{WalBackend, WalError, WriteAheadLog};
+
+/// Event type of audit events
+pub const AUDIT_EVENT_TYPE: &str = EventKind::Audit.as_str();
+
+/// A single entry of the audit trail
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Sequence of the audit event in the log
+    pub sequence: u64,
+    pub actor: String,
+    pub action: AuditAction,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AuditRecord {
+    /// Extract the audit record from an envelope, if it holds one
+    pub fn from_envelope(envelope: &EventEnvelope) -> Option<Self> {
+        match &envelope.event {
+            Event::Audit {
+                actor,
+                action,
+                timestamp,
+            } => Some(Self {
+                sequence: envelope.sequence,
+                actor: actor.clone(),
+                action: action.clone(),
+                timestamp: *timestamp,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Filter for querying the audit trail
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    /// Action name, see [`AuditAction::name`]
+    pub action: Option<String>,
+    pub workflow_id: Option<Uuid>,
+    /// Data acted on, see [`AuditAction::data_uuid`]
+    pub data_uuid: Option<Uuid>,
+    pub from_timestamp: Option<DateTime<Utc>>,
+    pub to_timestamp: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    /// Create a filter matching the whole trail
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter by actor
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// Filter by action name
+    pub fn action(mut self, action: impl Into<String>) -> Self {
+        self.action = Some(action.into());
+        self
+    }
+
+    /// Filter by the workflow acted on
+    pub fn workflow(mut self, workflow_id: Uuid) -> Self {
+        self.workflow_id = Some(workflow_id);
+        self
+    }
+
+    /// Filter by the data acted on
+    pub fn data(mut self, data_uuid: Uuid) -> Self {
+        self.data_uuid = Some(data_uuid);
+        self
+    }
+
+    /// Filter to actions logged within a time range (inclusive)
+    pub fn time_range(mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Self {
+        self.from_timestamp = from;
+        self.to_timestamp = to;
+        self
+    }
+
+    /// Limit the number of results
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The part of this filter the WAL can evaluate
+    ///
+    /// Actor, action, and data are checked by [`AuditFilter::apply`]
+    /// afterwards, so no limit is set here.
+    pub fn to_event_filter(&self) -> EventFilter {
+        let mut filter = EventFilter::new()
+            .kinds([EventKind::Audit])
+            .time_range(self.from_timestamp, self.to_timestamp);
+        filter.workflow_id = self.workflow_id;
+        filter
+    }
+
+    /// Check if an audit record matches the actor, action, and data
+    /// criteria
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.actor
+            .as_ref()
+            .is_none_or(|actor| &record.actor == actor)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| record.action.name() == action)
+            && self
+                .data_uuid
+                .is_none_or(|data_uuid| record.action.data_uuid() == Some(data_uuid))
+    }
+
+    /// Turn envelopes read with [`AuditFilter::to_event_filter`] into
+    /// matching records, honoring the limit
+    pub fn apply<'a>(
+        &self,
+        envelopes: impl IntoIterator<Item = &'a EventEnvelope>,
+    ) -> Vec<AuditRecord> {
+        envelopes
+            .into_iter()
+            .filter_map(AuditRecord::from_envelope)
+            .filter(|record| self.matches(record))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Read the audit trail from any WAL backend
+pub fn audit_trail<W: WalBackend + ?Sized>(
+    wal: &W,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditRecord>, WalError> {
+    let envelopes = wal.read_filtered(&filter.to_event_filter())?;
+    Ok(filter.apply(&envelopes))
+}
+
+/// Result of verifying the audit hash chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditVerification {
+    /// Number of audit events verified
+    pub records: u64,
+    /// Hash of the newest audit event; `None` for an empty trail
+    pub head: Option<String>,
+}
+
+/// Hash linking an audit event to its predecessor
+fn chain_hash(prev_hash: &str, sequence: u64, event_json: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    hasher.update(sequence.to_be_bytes());
+    hasher.update(event_json.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Link an audit event into the hash chain, if `envelope` holds one
+///
+/// Called inside the append transaction with the event JSON as stored.
+pub(crate) fn link_audit_event(
+    conn: &Connection,
+    envelope: &EventEnvelope,
+    event_json: &str,
+) -> Result<(), WalError> {
+    if !matches!(envelope.event, Event::Audit { .. }) {
+        return Ok(());
+    }
+    let prev_hash: String = conn
+        .query_row(
+            "SELECT hash FROM audit_chain ORDER BY sequence DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or_default();
+    conn.execute(
+        "INSERT INTO audit_chain (sequence, prev_hash, hash) VALUES (?1, ?2, ?3)",
+        params![
+            envelope.sequence,
+            prev_hash,
+            chain_hash(&prev_hash, envelope.sequence, event_json),
+        ],
+    )?;
+    Ok(())
+}
+
+impl WriteAheadLog {
+    /// Read the audit trail
+    pub fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, WalError> {
+        audit_trail(self, filter)
+    }
+
+    /// Recompute the audit hash chain
+    ///
+    /// Fails with [`WalError::Corrupt`] naming the first sequence whose
+    /// event was modified or removed, whose link was altered, or which is
+    /// an audit event missing from the chain.
+    pub fn verify_audit_trail(&self) -> Result<AuditVerification, WalError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.sequence, c.prev_hash, c.hash, e.event_json, e.encoding
+             FROM audit_chain c LEFT JOIN events e ON e.sequence = c.sequence
+             ORDER BY c.sequence",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<Value>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut head: Option<String> = None;
+        for (sequence, prev_hash, hash, payload, encoding) in &rows {
+            let expected_prev = head.as_deref().unwrap_or_default();
+            if prev_hash != expected_prev {
+                return Err(WalError::Corrupt(format!(
+                    "audit chain broken before sequence {sequence}"
+                )));
+            }
+            let (Some(payload), Some(encoding)) = (payload, encoding) else {
+                return Err(WalError::Corrupt(format!(
+                    "audit event {sequence} is missing"
+                )));
+            };
+            let event_json =
+                decode_payload(payload.clone(), PayloadEncoding::from_marker(*encoding)?)?;
+            if &chain_hash(prev_hash, *sequence, &event_json) != hash {
+                return Err(WalError::Corrupt(format!(
+                    "audit event {sequence} was modified"
+                )));
+            }
+            head = Some(hash.clone());
+        }
+
+        let unlinked: Option<u64> = self
+            .conn
+            .query_row(
+                "SELECT MIN(sequence) FROM events
+                 WHERE event_type = ?1
+                   AND sequence NOT IN (SELECT sequence FROM audit_chain)",
+                params![AUDIT_EVENT_TYPE],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        if let Some(sequence) = unlinked {
+            return Err(WalError::Corrupt(format!(
+                "audit event {sequence} is not in the audit chain"
+            )));
+        }
+
+        Ok(AuditVerification {
+            records: rows.len() as u64,
+            head,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wal_with_trail() -> (WriteAheadLog, Uuid) {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let workflow_id = Uuid::new_v4();
+        wal.append(Event::audit(
+            "alice",
+            AuditAction::WorkflowCreated {
+                workflow_id,
+                name: "etl".to_string(),
+            },
+        ))
+        .unwrap();
+        wal.append(Event::WorkflowStarted {
+            workflow_id,
+            name: "etl".to_string(),
+            timestamp: Utc::now(),
+        })
+        .unwrap();
+        wal.append(Event::audit(
+            "bob",
+            AuditAction::ServerRegistered {
+                server_address: "http://gpu-1:9090".to_string(),
+            },
+        ))
+        .unwrap();
+        wal.append(Event::audit(
+            "alice",
+            AuditAction::WorkflowDeleted { workflow_id },
+        ))
+        .unwrap();
+        (wal, workflow_id)
+    }
+
+    #[test]
+    fn test_audit_filter() {
+        let (wal, workflow_id) = wal_with_trail();
+
+        let all = wal.audit_trail(&AuditFilter::new()).unwrap();
+        assert_eq!(
+            all.iter().map(|r| r.sequence).collect::<Vec<_>>(),
+            vec![1, 3, 4]
+        );
+
+        let alice = wal.audit_trail(&AuditFilter::new().actor("alice")).unwrap();
+        assert_eq!(alice.len(), 2);
+        let deletes = wal
+            .audit_trail(&AuditFilter::new().action("workflow_deleted"))
+            .unwrap();
+        assert_eq!(deletes[0].sequence, 4);
+        let on_workflow = wal
+            .audit_trail(&AuditFilter::new().workflow(workflow_id).limit(1))
+            .unwrap();
+        assert_eq!(on_workflow.len(), 1);
+        assert_eq!(on_workflow[0].actor, "alice");
+    }
+
+    #[test]
+    fn test_data_access_trail() {
+        let (mut wal, workflow_id) = wal_with_trail();
+        let data_uuid = Uuid::new_v4();
+        let access = |granted: bool| AuditAction::DataAccessed {
+            data_uuid,
+            workflow_id: Some(workflow_id),
+            permission: "read".to_string(),
+            token_id: None,
+            granted,
+            reason: (!granted).then(|| "token expired".to_string()),
+        };
+        wal.append(Event::audit("gpu-1", access(true))).unwrap();
+        wal.append(Event::audit("gpu-2", access(false))).unwrap();
+        wal.append(Event::audit(
+            "gpu-1",
+            AuditAction::DataAccessed {
+                data_uuid: Uuid::new_v4(),
+                workflow_id: None,
+                permission: "delete".to_string(),
+                token_id: None,
+                granted: true,
+                reason: None,
+            },
+        ))
+        .unwrap();
+
+        let reads = wal
+            .audit_trail(&AuditFilter::new().data(data_uuid))
+            .unwrap();
+        assert_eq!(
+            reads.iter().map(|r| r.actor.as_str()).collect::<Vec<_>>(),
+            vec!["gpu-1", "gpu-2"]
+        );
+        let on_workflow = wal
+            .audit_trail(
+                &AuditFilter::new()
+                    .workflow(workflow_id)
+                    .action("data_accessed"),
+            )
+            .unwrap();
+        assert_eq!(on_workflow.len(), 2);
+        assert_eq!(wal.verify_audit_trail().unwrap().records, 6);
+    }
+
+    #[test]
+    fn test_audit_chain_detects_tampering() {
+        let (mut wal, _) = wal_with_trail();
+        wal.compact(wal.last_sequence() + 1).unwrap();
+
+        // Compaction keeps the trail intact
+        let verified = wal.verify_audit_trail().unwrap();
+        assert_eq!(verified.records, 3);
+        assert!(verified.head.is_some());
+
+        wal.conn
+            .execute(
+                "UPDATE events SET event_json = replace(event_json, 'bob', 'mallory')
+                 WHERE sequence = 3",
+                [],
+            )
+            .unwrap();
+        let err = wal.verify_audit_trail().unwrap_err();
+        assert!(err.to_string().contains("audit event 3 was modified"));
+    }
+}
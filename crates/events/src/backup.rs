@@ -0,0 +1,187 @@
+//! Online backup and restore of the SQLite WAL
+//!
+//! [`WriteAheadLog::backup`] copies the database with SQLite's online
+//! backup API, so it is safe while other connections keep appending: the
+//! copy is a consistent snapshot of one point in the log. It is written to
+//! a temporary file first and renamed into place once complete.
+//!
+//! [`WriteAheadLog::restore`] replaces a log's contents with a backup, but
+//! only after checking it: every row must pass
+//! [`verify`](WriteAheadLog::verify), sequence numbers must be contiguous
+//! past the compaction point, and the backup must not end before the log
+//! being restored into, since that would hand out sequence numbers that
+//! subscribers and outbox relays have already seen.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::reader::WalReader;
+use crate::wal::{query_count, query_high_water, WalError, WriteAheadLog};
+
+/// Pause before retrying a backup step that found the database locked
+const BUSY_PAUSE: Duration = Duration::from_millis(10);
+
+/// A backup file and the point in the log it captures
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// Location of the backup
+    pub path: PathBuf,
+    /// Highest sequence number covered by the backup
+    pub last_sequence: u64,
+    /// Number of events in the backup
+    pub events: u64,
+}
+
+impl BackupInfo {
+    fn read(path: &Path, conn: &Connection) -> Result<Self, WalError> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            last_sequence: query_high_water(conn)?,
+            events: query_count(conn)?,
+        })
+    }
+}
+
+/// Copy the database behind `source` into `destination`
+fn copy_database(source: &Connection, destination: &mut Connection) -> Result<(), WalError> {
+    // Copying every page in one step reads a single snapshot, so appends
+    // on other connections never force the copy to start over
+    Backup::new(source, destination)?.run_to_completion(i32::MAX, BUSY_PAUSE, None)?;
+    Ok(())
+}
+
+/// Back up the database behind `source` to a new file at `path`
+fn backup_to(source: &Connection, path: &Path) -> Result<BackupInfo, WalError> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| std::io::Error::other("backup path has no file name"))?;
+    let tmp = path.with_file_name(format!(".{file_name}.tmp"));
+    let _ = std::fs::remove_file(&tmp);
+
+    let info = {
+        let mut destination = Connection::open(&tmp)?;
+        copy_database(source, &mut destination)?;
+        BackupInfo::read(path, &destination)?
+    };
+    std::fs::rename(&tmp, path)?;
+    Ok(info)
+}
+
+impl WriteAheadLog {
+    /// Write a consistent copy of the log to `path`
+    ///
+    /// Replaces any file at `path`. In-memory logs can be backed up too.
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<BackupInfo, WalError> {
+        backup_to(&self.conn, path.as_ref())
+    }
+
+    /// Replace the log's contents with the backup at `path`
+    ///
+    /// The backup is loaded and verified before anything is changed; on
+    /// failure this log is left untouched. Fails with
+    /// [`WalError::BackupBehind`] if the backup ends before this log does,
+    /// so restore into an empty log when recovering from data loss.
+    pub fn restore<P: AsRef<Path>>(&mut self, path: P) -> Result<BackupInfo, WalError> {
+        let path = path.as_ref();
+        let source = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let mut staged = Connection::open_in_memory()?;
+        copy_database(&source, &mut staged)?;
+        drop(source);
+
+        // Bring backups taken by older versions up to the current schema
+        let staged = Self::initialize(staged)?;
+        staged.verify()?.check()?;
+        let info = BackupInfo::read(path, &staged.conn)?;
+        if info.last_sequence < self.last_sequence() {
+            return Err(WalError::BackupBehind {
+                backup: info.last_sequence,
+                current: self.last_sequence(),
+            });
+        }
+
+        copy_database(&staged.conn, &mut self.conn)?;
+        self.conn.flush_prepared_statement_cache();
+        self.next_sequence = staged.next_sequence;
+        self.committed.send_replace(info.last_sequence);
+        Ok(info)
+    }
+}
+
+impl WalReader {
+    /// Write a consistent copy of the log to `path`
+    ///
+    /// Unlike [`WriteAheadLog::backup`], this does not hold up appends.
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<BackupInfo, WalError> {
+        self.with_connection(|conn| backup_to(conn, path.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn started() -> Event {
+        Event::WorkflowStarted {
+            workflow_id: Uuid::new_v4(),
+            name: "nightly".to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.db");
+        let mut wal = WriteAheadLog::open(dir.path().join("events.db")).unwrap();
+        wal.append_batch(vec![started(), started(), started()])
+            .unwrap();
+        wal.compact(2).unwrap();
+
+        let info = wal.reader().unwrap().backup(&path).unwrap();
+        assert_eq!((info.last_sequence, info.events), (3, 2));
+        wal.append(started()).unwrap();
+
+        // Restoring would rewind the log
+        assert!(matches!(
+            wal.restore(&path),
+            Err(WalError::BackupBehind {
+                backup: 3,
+                current: 4
+            })
+        ));
+        assert_eq!(wal.count().unwrap(), 3);
+
+        let mut fresh = WriteAheadLog::open(dir.path().join("restored.db")).unwrap();
+        assert_eq!(fresh.restore(&path).unwrap(), info);
+        assert_eq!(
+            fresh
+                .read_from(1)
+                .unwrap()
+                .iter()
+                .map(|e| e.sequence)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(fresh.append(started()).unwrap().sequence, 4);
+
+        // A damaged backup is rejected before anything is replaced
+        let conn = Connection::open(&path).unwrap();
+        conn.execute("UPDATE events SET checksum = 0 WHERE sequence = 3", [])
+            .unwrap();
+        drop(conn);
+        let mut fresh = WriteAheadLog::in_memory().unwrap();
+        assert!(matches!(fresh.restore(&path), Err(WalError::Corrupt(_))));
+        assert_eq!(fresh.count().unwrap(), 0);
+    }
+}
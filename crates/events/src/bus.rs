@@ -0,0 +1,576 @@
+//! In-process event bus with push-based subscriptions
+//!
+//! [`EventBus::publish`] durably appends an event to the WAL and then fans
+//! it out to every live subscription over a `tokio::sync::broadcast`
+//! channel. Envelopes are broadcast by the WAL writer thread right after
+//! they commit, so subscribers see them in sequence order.
+//!
+//! Subscriptions never silently drop persisted events: a subscriber that
+//! falls so far behind that the broadcast channel overwrites its messages
+//! catches up by reading the missed range back from the WAL.
+//!
+//! Each event has a [`Severity`]. Events below the configured persistence
+//! level are streamed to subscribers as transient envelopes without being
+//! written to the WAL, so chatty progress reports can reach the UI without
+//! growing the log; a lagging subscriber may miss them. Events below the
+//! streaming level are not delivered at all.
+//!
+//! Every subscription sees every broadcast envelope, so its filter is
+//! evaluated once per published event. Filters are prepared when
+//! subscribing: event types and the minimum severity become a bit set of
+//! event kinds, leaving no string comparisons on the delivery path.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures_util::stream::{self, Stream};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::async_wal::{AsyncWal, AsyncWalConfig};
+use crate::types::{Event, EventEnvelope, EventFilter, EventKind, Severity, TRANSIENT_SEQUENCE};
+use crate::wal::{AppendEntry, WalBackend, WalError};
+
+/// Event bus configuration
+#[derive(Debug, Clone)]
+pub struct EventBusConfig {
+    /// Number of envelopes buffered per subscriber before it lags
+    pub channel_capacity: usize,
+    /// Configuration of the underlying WAL writer
+    pub wal: AsyncWalConfig,
+    /// Minimum severity of events written to the WAL
+    pub persist_level: Severity,
+    /// Minimum severity of events delivered to subscribers
+    pub stream_level: Severity,
+}
+
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        Self {
+            channel_capacity: 1024,
+            wal: AsyncWalConfig::default(),
+            persist_level: Severity::Debug,
+            stream_level: Severity::Debug,
+        }
+    }
+}
+
+/// Durable publish/subscribe hub for workflow events
+///
+/// Cheap to clone; clones share the same WAL and subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    wal: Arc<AsyncWal>,
+    sender: broadcast::Sender<EventEnvelope>,
+    /// Highest sequence number known to be committed
+    published: Arc<AtomicU64>,
+    persist_level: Severity,
+    stream_level: Severity,
+}
+
+impl EventBus {
+    /// Create a bus persisting to the given WAL backend
+    ///
+    /// Subscriptions only receive events appended after they were created,
+    /// unless started with [`EventBus::subscribe_from`].
+    pub fn new<W: WalBackend + 'static>(wal: W, config: EventBusConfig) -> Result<Self, WalError> {
+        let (sender, _) = broadcast::channel(config.channel_capacity.max(1));
+        let published = Arc::new(AtomicU64::new(wal.last_sequence()));
+        let wal = AsyncWal::with_notifier(wal, config.wal, sender.clone())?;
+        Ok(Self {
+            wal: Arc::new(wal),
+            sender,
+            published,
+            persist_level: config.persist_level,
+            stream_level: config.stream_level,
+        })
+    }
+
+    /// Persist an event and deliver it to subscribers
+    ///
+    /// Events below the persistence level are only streamed, and returned
+    /// as a transient envelope.
+    pub async fn publish(&self, event: Event) -> Result<EventEnvelope, WalError> {
+        if !self.persists(&event) {
+            return Ok(self.stream_transient(event));
+        }
+        let envelope = self.wal.append(event).await?;
+        self.published
+            .fetch_max(envelope.sequence, Ordering::Relaxed);
+        Ok(envelope)
+    }
+
+    /// Persist an event unless its idempotency key was already used
+    ///
+    /// A duplicate is not delivered again; the original envelope is
+    /// returned instead. Keyed events are persisted whatever their
+    /// severity.
+    pub async fn publish_idempotent(
+        &self,
+        key: &str,
+        event: Event,
+    ) -> Result<EventEnvelope, WalError> {
+        let envelope = self.wal.append_idempotent(key, event).await?;
+        self.published
+            .fetch_max(envelope.sequence, Ordering::Relaxed);
+        Ok(envelope)
+    }
+
+    /// Persist an event with its metadata and deliver it to subscribers
+    ///
+    /// Use this to record the trace an event belongs to; a reused
+    /// idempotency key behaves as in [`EventBus::publish_idempotent`].
+    /// Entries without a key follow the persistence level as in
+    /// [`EventBus::publish`].
+    pub async fn publish_entry(&self, entry: AppendEntry) -> Result<EventEnvelope, WalError> {
+        if entry.idempotency_key.is_none() && !self.persists(&entry.event) {
+            let envelope =
+                EventEnvelope::new(TRANSIENT_SEQUENCE, entry.event).with_trace(entry.trace);
+            return Ok(self.stream(envelope));
+        }
+        let envelope = self.wal.append_entries(vec![entry]).await?.remove(0);
+        self.published
+            .fetch_max(envelope.sequence, Ordering::Relaxed);
+        Ok(envelope)
+    }
+
+    /// Persist events atomically and deliver them to subscribers
+    ///
+    /// Events below the persistence level are streamed after the rest are
+    /// committed; envelopes are returned in the order of `events`.
+    pub async fn publish_batch(&self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError> {
+        let (persisted, transient): (Vec<_>, Vec<_>) = events
+            .into_iter()
+            .enumerate()
+            .partition(|(_, event)| self.persists(event));
+        let (positions, persisted): (Vec<_>, Vec<_>) = persisted.into_iter().unzip();
+
+        let committed = if persisted.is_empty() {
+            Vec::new()
+        } else {
+            self.wal.append_batch(persisted).await?
+        };
+        if let Some(last) = committed.last() {
+            self.published.fetch_max(last.sequence, Ordering::Relaxed);
+        }
+
+        let mut envelopes: Vec<(usize, EventEnvelope)> =
+            positions.into_iter().zip(committed).collect();
+        for (position, event) in transient {
+            envelopes.push((position, self.stream_transient(event)));
+        }
+        envelopes.sort_by_key(|(position, _)| *position);
+        Ok(envelopes
+            .into_iter()
+            .map(|(_, envelope)| envelope)
+            .collect())
+    }
+
+    /// Whether an event meets the persistence level
+    fn persists(&self, event: &Event) -> bool {
+        event.severity() >= self.persist_level
+    }
+
+    /// Deliver an event to subscribers without persisting it
+    fn stream_transient(&self, event: Event) -> EventEnvelope {
+        self.stream(EventEnvelope::new(TRANSIENT_SEQUENCE, event))
+    }
+
+    /// Deliver a transient envelope to subscribers
+    fn stream(&self, envelope: EventEnvelope) -> EventEnvelope {
+        if envelope.event.severity() >= self.stream_level {
+            // No subscribers is not an error
+            let _ = self.sender.send(envelope.clone());
+        }
+        envelope
+    }
+
+    /// Subscribe to events published from now on
+    ///
+    /// Events below the streaming level are never delivered, whatever the
+    /// filter asks for.
+    pub fn subscribe(&self, mut filter: EventFilter) -> EventSubscription {
+        filter.min_severity = filter.min_severity.max(Some(self.stream_level));
+        EventSubscription {
+            rx: self.sender.subscribe(),
+            wal: self.wal.clone(),
+            matcher: Matcher::new(&filter),
+            filter,
+            last_sequence: self.published.load(Ordering::Relaxed),
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Subscribe starting at `sequence`, replaying earlier events from the WAL
+    pub async fn subscribe_from(
+        &self,
+        filter: EventFilter,
+        sequence: u64,
+    ) -> Result<EventSubscription, WalError> {
+        // Subscribe before reading so nothing committed in between is missed
+        let mut subscription = self.subscribe(filter);
+        subscription.last_sequence = sequence.saturating_sub(1);
+        subscription.catch_up().await?;
+        Ok(subscription)
+    }
+
+    /// Stream events matching `filter` from `sequence` on, then live ones
+    ///
+    /// Equivalent to [`EventBus::subscribe_from`] followed by
+    /// [`EventSubscription::into_stream`].
+    pub async fn tail(
+        &self,
+        filter: EventFilter,
+        sequence: u64,
+    ) -> Result<impl Stream<Item = Result<EventEnvelope, BusError>> + Send + 'static, WalError>
+    {
+        Ok(self.subscribe_from(filter, sequence).await?.into_stream())
+    }
+
+    /// Stream events matching `filter` published from now on
+    ///
+    /// Equivalent to [`EventBus::subscribe`] followed by
+    /// [`EventSubscription::into_stream`].
+    pub fn subscribe_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> impl Stream<Item = Result<EventEnvelope, BusError>> + Send + 'static {
+        self.subscribe(filter).into_stream()
+    }
+
+    /// Stream node lifecycle events of a workflow, from queued to finished
+    pub fn subscribe_node_events(
+        &self,
+        workflow_id: Uuid,
+    ) -> impl Stream<Item = Result<EventEnvelope, BusError>> + Send + 'static {
+        self.subscribe_filtered(
+            EventFilter::new()
+                .workflow(workflow_id)
+                .kinds(EventKind::NODE),
+        )
+    }
+
+    /// Stream server registrations, health checks, and disconnects
+    pub fn subscribe_server_events(
+        &self,
+    ) -> impl Stream<Item = Result<EventEnvelope, BusError>> + Send + 'static {
+        self.subscribe_filtered(EventFilter::new().kinds(EventKind::SERVER))
+    }
+
+    /// Get the number of live subscriptions
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Get the underlying WAL for queries
+    pub fn wal(&self) -> &AsyncWal {
+        &self.wal
+    }
+}
+
+/// A filtered stream of events from an [`EventBus`]
+pub struct EventSubscription {
+    rx: broadcast::Receiver<EventEnvelope>,
+    wal: Arc<AsyncWal>,
+    filter: EventFilter,
+    /// `filter`, prepared for matching
+    matcher: Matcher,
+    /// Highest sequence number observed, matching or not
+    last_sequence: u64,
+    /// Matching events read from the WAL, not yet returned
+    backlog: VecDeque<EventEnvelope>,
+}
+
+impl EventSubscription {
+    /// Wait for the next matching event
+    pub async fn recv(&mut self) -> Result<EventEnvelope, BusError> {
+        loop {
+            if let Some(envelope) = self.backlog.pop_front() {
+                return Ok(envelope);
+            }
+
+            match self.rx.recv().await {
+                Ok(envelope) if envelope.is_transient() => {
+                    if self.matcher.matches(&envelope) {
+                        return Ok(envelope);
+                    }
+                }
+                Ok(envelope) => {
+                    if envelope.sequence <= self.last_sequence {
+                        continue;
+                    }
+                    self.last_sequence = envelope.sequence;
+                    if self.matcher.matches(&envelope) {
+                        return Ok(envelope);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::debug!(skipped, "event subscriber lagged, catching up from WAL");
+                    self.catch_up().await?;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Err(BusError::Closed),
+            }
+        }
+    }
+
+    /// Turn the subscription into a stream of matching events
+    ///
+    /// The stream ends after yielding [`BusError::Closed`].
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<EventEnvelope, BusError>> + Send + 'static {
+        stream::unfold(Some(self), |subscription| async move {
+            let mut subscription = subscription?;
+            let item = subscription.recv().await;
+            let closed = matches!(item, Err(BusError::Closed));
+            Some((item, (!closed).then_some(subscription)))
+        })
+    }
+
+    /// Get the highest sequence number this subscription has observed
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// Get the subscription filter
+    pub fn filter(&self) -> &EventFilter {
+        &self.filter
+    }
+
+    /// Read everything after `last_sequence` back from the WAL
+    async fn catch_up(&mut self) -> Result<(), WalError> {
+        for envelope in self.wal.read_from(self.last_sequence + 1).await? {
+            self.last_sequence = envelope.sequence;
+            if self.matcher.matches(&envelope) {
+                self.backlog.push_back(envelope);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An [`EventFilter`] prepared for matching every broadcast envelope
+struct Matcher {
+    /// Bit per [`EventKind`], in declaration order, for each kind allowed
+    /// by the filter's event types and minimum severity
+    kinds: u64,
+    /// The remaining criteria
+    rest: EventFilter,
+}
+
+impl Matcher {
+    fn new(filter: &EventFilter) -> Self {
+        let kinds = EventKind::ALL
+            .into_iter()
+            .filter(|kind| {
+                filter
+                    .event_types
+                    .as_ref()
+                    .is_none_or(|types| types.iter().any(|t| t == kind.as_str()))
+                    && filter.min_severity.is_none_or(|s| kind.severity() >= s)
+            })
+            .fold(0, |kinds, kind| kinds | kind_bit(kind));
+        let rest = EventFilter {
+            event_types: None,
+            min_severity: None,
+            ..filter.clone()
+        };
+        Self { kinds, rest }
+    }
+
+    fn matches(&self, envelope: &EventEnvelope) -> bool {
+        self.kinds & kind_bit(envelope.event.kind()) != 0 && self.rest.matches(envelope)
+    }
+}
+
+// Every kind needs a bit
+const _: () = assert!(EventKind::ALL.len() <= u64::BITS as usize);
+
+fn kind_bit(kind: EventKind) -> u64 {
+    1 << kind as u64
+}
+
+/// Event bus errors
+#[derive(Debug, thiserror::Error)]
+pub enum BusError {
+    #[error("Event bus closed")]
+    Closed,
+
+    #[error("WAL error: {0}")]
+    Wal(#[from] WalError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wal::WriteAheadLog;
+    use chrono::Utc;
+    use futures_util::StreamExt;
+    use uuid::Uuid;
+
+    fn started(workflow_id: Uuid) -> Event {
+        Event::WorkflowStarted {
+            workflow_id,
+            name: "test".to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn bus(channel_capacity: usize) -> EventBus {
+        let config = EventBusConfig {
+            channel_capacity,
+            ..Default::default()
+        };
+        EventBus::new(WriteAheadLog::in_memory().unwrap(), config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_filtered_delivery() {
+        let bus = bus(16);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut sub = bus.subscribe(EventFilter::new().workflow(b));
+
+        bus.publish(started(a)).await.unwrap();
+        bus.publish(started(b)).await.unwrap();
+
+        let envelope = sub.recv().await.unwrap();
+        assert_eq!(envelope.sequence, 2);
+        assert_eq!(envelope.event.workflow_id(), Some(b));
+    }
+
+    #[tokio::test]
+    async fn test_typed_subscriptions() {
+        let bus = bus(16);
+        let workflow_id = Uuid::new_v4();
+        let mut nodes = Box::pin(bus.subscribe_node_events(workflow_id));
+        let mut servers = Box::pin(bus.subscribe_server_events());
+        let queued = |workflow_id| Event::NodeQueued {
+            workflow_id,
+            node_id: Uuid::new_v4(),
+            node_type: "llm.chat".to_string(),
+            timestamp: Utc::now(),
+        };
+
+        bus.publish_batch(vec![
+            started(workflow_id),
+            queued(Uuid::new_v4()),
+            Event::ServerDisconnected {
+                server_address: "10.0.0.2:8080".to_string(),
+                reason: None,
+                timestamp: Utc::now(),
+            },
+            queued(workflow_id),
+        ])
+        .await
+        .unwrap();
+
+        assert_eq!(nodes.next().await.unwrap().unwrap().sequence, 4);
+        assert_eq!(servers.next().await.unwrap().unwrap().sequence, 3);
+    }
+
+    #[tokio::test]
+    async fn test_tail_replays_then_follows() {
+        let bus = bus(16);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        bus.publish(started(a)).await.unwrap();
+        bus.publish(started(b)).await.unwrap();
+
+        let tail = bus.tail(EventFilter::new().workflow(a), 1).await.unwrap();
+        let mut tail = Box::pin(tail);
+        bus.publish(started(b)).await.unwrap();
+        bus.publish(started(a)).await.unwrap();
+
+        assert_eq!(tail.next().await.unwrap().unwrap().sequence, 1);
+        assert_eq!(tail.next().await.unwrap().unwrap().sequence, 4);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_catches_up_from_wal() {
+        let bus = bus(2);
+        let workflow_id = Uuid::new_v4();
+        let mut sub = bus.subscribe(EventFilter::new());
+
+        for _ in 0..10 {
+            bus.publish(started(workflow_id)).await.unwrap();
+        }
+
+        let mut sequences = Vec::new();
+        for _ in 0..10 {
+            sequences.push(sub.recv().await.unwrap().sequence);
+        }
+        assert_eq!(sequences, (1..=10).collect::<Vec<u64>>());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_publish_is_not_redelivered() {
+        let bus = bus(16);
+        let workflow_id = Uuid::new_v4();
+        let mut sub = bus.subscribe(EventFilter::new());
+
+        let first = bus
+            .publish_idempotent("start", started(workflow_id))
+            .await
+            .unwrap();
+        let retried = bus
+            .publish_idempotent("start", started(workflow_id))
+            .await
+            .unwrap();
+        assert_eq!(retried.id, first.id);
+        bus.publish(started(workflow_id)).await.unwrap();
+
+        assert_eq!(sub.recv().await.unwrap().sequence, 1);
+        assert_eq!(sub.recv().await.unwrap().sequence, 2);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_from_replays_history() {
+        let bus = bus(16);
+        let workflow_id = Uuid::new_v4();
+        for _ in 0..3 {
+            bus.publish(started(workflow_id)).await.unwrap();
+        }
+
+        let mut sub = bus.subscribe_from(EventFilter::new(), 2).await.unwrap();
+        bus.publish(started(workflow_id)).await.unwrap();
+
+        for expected in 2..=4 {
+            assert_eq!(sub.recv().await.unwrap().sequence, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_severity_levels_split_streaming_from_persistence() {
+        let config = EventBusConfig {
+            persist_level: Severity::Info,
+            stream_level: Severity::Debug,
+            ..Default::default()
+        };
+        let bus = EventBus::new(WriteAheadLog::in_memory().unwrap(), config).unwrap();
+        let mut all = bus.subscribe(EventFilter::new());
+        let mut important = bus.subscribe(EventFilter::new().min_severity(Severity::Info));
+        let workflow_id = Uuid::new_v4();
+        let progress = Event::NodeProgress {
+            workflow_id,
+            node_id: Uuid::new_v4(),
+            progress: 0.5,
+            message: None,
+            timestamp: Utc::now(),
+        };
+
+        let envelopes = bus
+            .publish_batch(vec![progress, started(workflow_id)])
+            .await
+            .unwrap();
+        assert!(envelopes[0].is_transient());
+        assert_eq!(envelopes[1].sequence, 1);
+        assert_eq!(bus.wal().read_from(1).await.unwrap().len(), 1);
+
+        // Transient events are streamed after the committed ones
+        assert_eq!(all.recv().await.unwrap().sequence, 1);
+        assert!(all.recv().await.unwrap().is_transient());
+        assert_eq!(important.recv().await.unwrap().sequence, 1);
+        bus.publish(started(workflow_id)).await.unwrap();
+        assert_eq!(important.recv().await.unwrap().sequence, 2);
+    }
+}
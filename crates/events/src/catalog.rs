@@ -0,0 +1,143 @@
+//! Persistent data catalog entries
+//!
+//! The data catalog records every DataRef created in the system so data
+//! can be found after a restart. This crate does not know the DataRef
+//! type, so entries are stored as JSON documents keyed by data UUID, with
+//! the owning workflow alongside for listing.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::wal::{format_timestamp, parse_column, WalError, WriteAheadLog};
+
+/// A stored catalog entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogRecord {
+    /// Data the entry describes
+    pub data_uuid: Uuid,
+    /// Workflow owning the data
+    pub workflow_id: Uuid,
+    /// The DataRef, serialized as JSON
+    pub document: String,
+    /// When the entry was last written
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WriteAheadLog {
+    /// Store a catalog entry, replacing any earlier one for the same data
+    pub fn record_catalog_entry(&mut self, record: &CatalogRecord) -> Result<(), WalError> {
+        self.conn.execute(
+            "INSERT INTO data_catalog (data_uuid, workflow_id, document, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(data_uuid) DO UPDATE
+             SET workflow_id = excluded.workflow_id, document = excluded.document,
+                 updated_at = excluded.updated_at",
+            params![
+                record.data_uuid.to_string(),
+                record.workflow_id.to_string(),
+                record.document,
+                format_timestamp(record.updated_at),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remove the catalog entry of deleted data; returns `false` if there
+    /// was none
+    pub fn remove_catalog_entry(&mut self, data_uuid: &Uuid) -> Result<bool, WalError> {
+        let removed = self.conn.execute(
+            "DELETE FROM data_catalog WHERE data_uuid = ?1",
+            params![data_uuid.to_string()],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Get the catalog entry of data
+    pub fn catalog_entry(&self, data_uuid: &Uuid) -> Result<Option<CatalogRecord>, WalError> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT data_uuid, workflow_id, document, updated_at FROM data_catalog
+                 WHERE data_uuid = ?1",
+                params![data_uuid.to_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        row.map(parse_record).transpose()
+    }
+
+    /// List stored catalog entries, oldest first
+    pub fn catalog_entries(&self) -> Result<Vec<CatalogRecord>, WalError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT data_uuid, workflow_id, document, updated_at FROM data_catalog
+             ORDER BY updated_at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        rows.map(|row| parse_record(row?)).collect()
+    }
+}
+
+fn parse_record(
+    (data_uuid, workflow_id, document, updated_at): (String, String, String, String),
+) -> Result<CatalogRecord, WalError> {
+    Ok(CatalogRecord {
+        data_uuid: parse_column(&data_uuid)?,
+        workflow_id: parse_column(&workflow_id)?,
+        document,
+        updated_at: parse_column(&updated_at)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_entries_round_trip() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let record = CatalogRecord {
+            data_uuid: Uuid::new_v4(),
+            workflow_id: Uuid::new_v4(),
+            document: r#"{"size_bytes":1}"#.to_string(),
+            updated_at: Utc::now(),
+        };
+        wal.record_catalog_entry(&record).unwrap();
+        // Re-recording replaces the entry
+        let updated = CatalogRecord {
+            document: r#"{"size_bytes":2}"#.to_string(),
+            ..record.clone()
+        };
+        wal.record_catalog_entry(&updated).unwrap();
+
+        let stored = wal.catalog_entries().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].document, updated.document);
+        assert_eq!(
+            wal.catalog_entry(&record.data_uuid)
+                .unwrap()
+                .unwrap()
+                .workflow_id,
+            record.workflow_id
+        );
+
+        assert!(wal.remove_catalog_entry(&record.data_uuid).unwrap());
+        assert!(!wal.remove_catalog_entry(&record.data_uuid).unwrap());
+        assert!(wal.catalog_entry(&record.data_uuid).unwrap().is_none());
+    }
+}
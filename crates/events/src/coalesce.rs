@@ -0,0 +1,424 @@
+//! Coalescing of high-frequency progress events
+//!
+//! Servers may report `NodeProgress` many times a second. Persisting every
+//! report floods the WAL and every stream fed from it, while consumers only
+//! need a recent value. [`ProgressCoalescer`] keeps at most one progress
+//! event per node per interval, or per minimum change in progress, and
+//! holds back the rest. Only the latest held report is kept, and it is
+//! never lost: it is released once its interval elapses, or just before any
+//! later event of the same node or workflow, so completion always follows
+//! the final progress value.
+//!
+//! [`CoalescingPublisher`] applies a coalescer in front of an
+//! [`EventBus`]. The guarantee only covers events that go through it, so
+//! node and workflow events must all be published there rather than on
+//! the bus directly. Its owner calls [`CoalescingPublisher::flush_due`]
+//! periodically to release held reports of nodes that went quiet.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::bus::EventBus;
+use crate::types::{Event, EventEnvelope};
+use crate::wal::{AppendEntry, WalError};
+
+/// Progress coalescing configuration
+#[derive(Debug, Clone)]
+pub struct CoalesceConfig {
+    /// Minimum time between two progress events of a node
+    pub interval: Duration,
+    /// Change in progress that is published regardless of `interval`
+    pub min_delta: Option<f64>,
+    /// Publish every event unchanged, for debugging
+    pub pass_through: bool,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            min_delta: None,
+            pass_through: false,
+        }
+    }
+}
+
+/// Progress state of one node
+struct NodeProgressState {
+    workflow_id: Uuid,
+    /// When a progress event of the node was last released
+    emitted_at: Instant,
+    /// Progress value last released
+    emitted: f64,
+    /// Latest report held back
+    pending: Option<Event>,
+}
+
+/// Decides which progress events to publish
+///
+/// Time is passed in explicitly, so the coalescer itself never sleeps.
+pub struct ProgressCoalescer {
+    config: CoalesceConfig,
+    nodes: HashMap<Uuid, NodeProgressState>,
+}
+
+impl ProgressCoalescer {
+    /// Create a coalescer
+    pub fn new(config: CoalesceConfig) -> Self {
+        Self {
+            config,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &CoalesceConfig {
+        &self.config
+    }
+
+    /// Offer an event, returning the events to publish now, in order
+    ///
+    /// The offered event is last in the result if it is published now; an
+    /// empty result means it was held back.
+    pub fn offer(&mut self, event: Event, now: Instant) -> Vec<Event> {
+        if self.config.pass_through {
+            return vec![event];
+        }
+
+        if let Event::NodeProgress {
+            workflow_id,
+            node_id,
+            progress,
+            ..
+        } = &event
+        {
+            let (workflow_id, node_id, progress) = (*workflow_id, *node_id, *progress);
+            if let Some(state) = self.nodes.get_mut(&node_id) {
+                let interval_elapsed = now.duration_since(state.emitted_at) >= self.config.interval;
+                let delta_reached = self
+                    .config
+                    .min_delta
+                    .is_some_and(|delta| (progress - state.emitted).abs() >= delta);
+                if !(interval_elapsed || delta_reached || progress >= 1.0) {
+                    state.pending = Some(event);
+                    return Vec::new();
+                }
+            }
+            self.nodes.insert(
+                node_id,
+                NodeProgressState {
+                    workflow_id,
+                    emitted_at: now,
+                    emitted: progress,
+                    pending: None,
+                },
+            );
+            return vec![event];
+        }
+
+        // Release held reports that this event must follow
+        let mut released = Vec::new();
+        match (event.node_id(), event.workflow_id()) {
+            (Some(node_id), _) => {
+                if let Some(state) = self.nodes.get_mut(&node_id) {
+                    released.extend(state.pending.take());
+                }
+                if event.is_node_terminal() {
+                    self.nodes.remove(&node_id);
+                }
+            }
+            (None, Some(workflow_id)) => {
+                for state in self.nodes.values_mut() {
+                    if state.workflow_id == workflow_id {
+                        released.extend(state.pending.take());
+                    }
+                }
+                if event.is_workflow_terminal() {
+                    self.nodes
+                        .retain(|_, state| state.workflow_id != workflow_id);
+                }
+            }
+            (None, None) => {}
+        }
+        released.push(event);
+        released
+    }
+
+    /// Take held reports whose interval has elapsed
+    pub fn due(&mut self, now: Instant) -> Vec<Event> {
+        let interval = self.config.interval;
+        self.nodes
+            .values_mut()
+            .filter(|state| now.duration_since(state.emitted_at) >= interval)
+            .filter_map(|state| {
+                let event = state.pending.take()?;
+                if let Event::NodeProgress { progress, .. } = &event {
+                    state.emitted = *progress;
+                }
+                state.emitted_at = now;
+                Some(event)
+            })
+            .collect()
+    }
+
+    /// Take every held report
+    pub fn drain(&mut self) -> Vec<Event> {
+        self.nodes
+            .values_mut()
+            .filter_map(|state| state.pending.take())
+            .collect()
+    }
+
+    /// Number of reports held back
+    pub fn pending(&self) -> usize {
+        self.nodes.values().filter(|s| s.pending.is_some()).count()
+    }
+}
+
+/// Publishes events to a bus through a [`ProgressCoalescer`]
+///
+/// Publishes through one publisher are serialized, so a released progress
+/// report is always committed before the event that released it.
+pub struct CoalescingPublisher {
+    bus: EventBus,
+    coalescer: Mutex<ProgressCoalescer>,
+}
+
+impl CoalescingPublisher {
+    /// Create a publisher in front of `bus`
+    pub fn new(bus: EventBus, config: CoalesceConfig) -> Self {
+        Self {
+            bus,
+            coalescer: Mutex::new(ProgressCoalescer::new(config)),
+        }
+    }
+
+    /// Get the underlying bus
+    pub fn bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// Publish an event, unless it is a progress report held back
+    ///
+    /// Returns the envelope of the event if it was published now.
+    pub async fn publish(&self, event: Event) -> Result<Option<EventEnvelope>, WalError> {
+        let mut coalescer = self.coalescer.lock().await;
+        let events = coalescer.offer(event, tokio::time::Instant::now().into_std());
+        if events.is_empty() {
+            return Ok(None);
+        }
+        Ok(self.bus.publish_batch(events).await?.pop())
+    }
+
+    /// Publish an entry with its trace and idempotency key, unless it is a
+    /// progress report held back
+    ///
+    /// Held reports the entry must follow are published first.
+    pub async fn publish_entry(
+        &self,
+        entry: AppendEntry,
+    ) -> Result<Option<EventEnvelope>, WalError> {
+        let AppendEntry {
+            event,
+            idempotency_key,
+            trace,
+        } = entry;
+        let mut coalescer = self.coalescer.lock().await;
+        let mut events = coalescer.offer(event, tokio::time::Instant::now().into_std());
+        let Some(event) = events.pop() else {
+            return Ok(None);
+        };
+        self.publish_released(events).await?;
+        let entry = AppendEntry {
+            event,
+            idempotency_key,
+            trace,
+        };
+        Ok(Some(self.bus.publish_entry(entry).await?))
+    }
+
+    /// Publish held reports whose interval has elapsed
+    ///
+    /// Call about once per configured interval.
+    pub async fn flush_due(&self) -> Result<usize, WalError> {
+        let mut coalescer = self.coalescer.lock().await;
+        let events = coalescer.due(tokio::time::Instant::now().into_std());
+        self.publish_released(events).await
+    }
+
+    /// Publish every held report, e.g. before shutting down
+    pub async fn flush(&self) -> Result<usize, WalError> {
+        let mut coalescer = self.coalescer.lock().await;
+        let events = coalescer.drain();
+        self.publish_released(events).await
+    }
+
+    async fn publish_released(&self, events: Vec<Event>) -> Result<usize, WalError> {
+        if events.is_empty() {
+            return Ok(0);
+        }
+        Ok(self.bus.publish_batch(events).await?.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EventFilter, TraceContext};
+    use crate::wal::WriteAheadLog;
+    use chrono::Utc;
+
+    fn progress(workflow_id: Uuid, node_id: Uuid, progress: f64) -> Event {
+        Event::NodeProgress {
+            workflow_id,
+            node_id,
+            progress,
+            message: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn progress_values(events: &[Event]) -> Vec<f64> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::NodeProgress { progress, .. } => Some(*progress),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_coalescer_keeps_one_report_per_interval() {
+        let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut coalescer = ProgressCoalescer::new(CoalesceConfig {
+            interval: Duration::from_secs(1),
+            min_delta: Some(0.5),
+            pass_through: false,
+        });
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(
+            coalescer
+                .offer(progress(workflow_id, node_id, 0.1), at(0))
+                .len(),
+            1
+        );
+        assert!(coalescer
+            .offer(progress(workflow_id, node_id, 0.2), at(100))
+            .is_empty());
+        assert!(coalescer
+            .offer(progress(workflow_id, node_id, 0.3), at(200))
+            .is_empty());
+        assert_eq!(coalescer.pending(), 1);
+        assert!(coalescer.due(at(900)).is_empty());
+        assert_eq!(progress_values(&coalescer.due(at(1000))), vec![0.3]);
+
+        // A large jump is published at once
+        assert_eq!(
+            coalescer
+                .offer(progress(workflow_id, node_id, 0.9), at(1100))
+                .len(),
+            1
+        );
+
+        // Completion releases the final held value first
+        assert!(coalescer
+            .offer(progress(workflow_id, node_id, 0.95), at(1200))
+            .is_empty());
+        let completed = Event::NodeCompleted {
+            workflow_id,
+            node_id,
+            output_refs: Vec::new(),
+            duration_ms: 10,
+            timestamp: Utc::now(),
+        };
+        let released = coalescer.offer(completed, at(1300));
+        assert_eq!(progress_values(&released), vec![0.95]);
+        assert!(matches!(released[1], Event::NodeCompleted { .. }));
+        assert_eq!(coalescer.pending(), 0);
+
+        let mut raw = ProgressCoalescer::new(CoalesceConfig {
+            pass_through: true,
+            ..Default::default()
+        });
+        for i in 0..3 {
+            assert_eq!(
+                raw.offer(progress(workflow_id, node_id, 0.1), at(i)).len(),
+                1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publisher_flushes_held_reports() {
+        let bus = EventBus::new(WriteAheadLog::in_memory().unwrap(), Default::default()).unwrap();
+        let publisher = CoalescingPublisher::new(
+            bus,
+            CoalesceConfig {
+                interval: Duration::from_secs(3600),
+                ..Default::default()
+            },
+        );
+        let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        for i in 1..=5 {
+            let published = publisher
+                .publish(progress(workflow_id, node_id, i as f64 / 10.0))
+                .await
+                .unwrap();
+            assert_eq!(published.is_some(), i == 1);
+        }
+        assert_eq!(publisher.flush_due().await.unwrap(), 0);
+        assert_eq!(publisher.flush().await.unwrap(), 1);
+
+        let events: Vec<Event> = publisher
+            .bus()
+            .wal()
+            .read_filtered(EventFilter::new().node(node_id))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|envelope| envelope.event)
+            .collect();
+        assert_eq!(progress_values(&events), vec![0.1, 0.5]);
+    }
+
+    #[tokio::test]
+    async fn test_entry_follows_held_report() {
+        let bus = EventBus::new(WriteAheadLog::in_memory().unwrap(), Default::default()).unwrap();
+        let publisher = CoalescingPublisher::new(bus, Default::default());
+        let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        for value in [0.1, 0.2] {
+            publisher
+                .publish(progress(workflow_id, node_id, value))
+                .await
+                .unwrap();
+        }
+        let trace = TraceContext::new_root();
+        let cancelled = Event::NodeCancelled {
+            workflow_id,
+            node_id,
+            reason: None,
+            timestamp: Utc::now(),
+        };
+        let entry = AppendEntry::new(cancelled).with_trace(Some(trace.clone()));
+        let published = publisher.publish_entry(entry).await.unwrap().unwrap();
+        assert_eq!(published.trace(), Some(trace));
+
+        let envelopes = publisher
+            .bus()
+            .wal()
+            .read_filtered(EventFilter::new().node(node_id))
+            .await
+            .unwrap();
+        let events: Vec<Event> = envelopes.into_iter().map(|e| e.event).collect();
+        assert_eq!(progress_values(&events), vec![0.1, 0.2]);
+        assert!(matches!(events[2], Event::NodeCancelled { .. }));
+        // The cancelled node is forgotten
+        assert_eq!(publisher.coalescer.lock().await.nodes.len(), 0);
+    }
+}
@@ -0,0 +1,117 @@
+//! Event payload compression for the WAL
+//!
+//! Large payloads (progress and completion events carrying output metadata)
+//! are compressed with zstd before being stored. Every stored payload is
+//! tagged with a [`PayloadEncoding`] marker, so rows written before
+//! compression was enabled, or below the size threshold, are read back as
+//! plain JSON.
+
+use rusqlite::types::Value;
+
+use crate::wal::WalError;
+
+/// Compression settings for stored event payloads
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// Payloads at or above this size (in bytes) are compressed
+    pub threshold_bytes: usize,
+    /// zstd compression level
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            threshold_bytes: 4096,
+            level: 3,
+        }
+    }
+}
+
+/// How a stored payload is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadEncoding {
+    /// Plain JSON text
+    Json = 0,
+    /// zstd-compressed JSON
+    Zstd = 1,
+}
+
+impl PayloadEncoding {
+    /// Parse the marker stored alongside a payload
+    pub fn from_marker(marker: i64) -> Result<Self, WalError> {
+        match marker {
+            0 => Ok(Self::Json),
+            1 => Ok(Self::Zstd),
+            other => Err(WalError::Corrupt(format!(
+                "unknown payload encoding {other}"
+            ))),
+        }
+    }
+
+    /// Get the marker stored alongside a payload
+    pub fn marker(self) -> i64 {
+        self as i64
+    }
+}
+
+/// Encode a JSON payload for storage
+///
+/// Returns the value to store and its encoding. Payloads are compressed
+/// only when compression is enabled, the payload reaches the threshold, and
+/// compression actually saves space.
+pub fn encode_payload(
+    json: String,
+    config: Option<&CompressionConfig>,
+) -> Result<(Value, PayloadEncoding), WalError> {
+    if let Some(config) = config.filter(|c| json.len() >= c.threshold_bytes) {
+        let compressed = zstd::bulk::compress(json.as_bytes(), config.level)?;
+        if compressed.len() < json.len() {
+            return Ok((Value::Blob(compressed), PayloadEncoding::Zstd));
+        }
+    }
+    Ok((Value::Text(json), PayloadEncoding::Json))
+}
+
+/// Decode a stored payload back into JSON text
+pub fn decode_payload(value: Value, encoding: PayloadEncoding) -> Result<String, WalError> {
+    match (encoding, value) {
+        (PayloadEncoding::Json, Value::Text(json)) => Ok(json),
+        (PayloadEncoding::Json, Value::Blob(bytes)) => String::from_utf8(bytes)
+            .map_err(|e| WalError::Corrupt(format!("payload is not UTF-8: {e}"))),
+        (PayloadEncoding::Zstd, Value::Blob(bytes)) => {
+            let json = zstd::stream::decode_all(bytes.as_slice())?;
+            String::from_utf8(json)
+                .map_err(|e| WalError::Corrupt(format!("payload is not UTF-8: {e}")))
+        }
+        (encoding, _) => Err(WalError::Corrupt(format!(
+            "unexpected column type for {encoding:?} payload"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let config = CompressionConfig {
+            threshold_bytes: 64,
+            level: 3,
+        };
+        let small = r#"{"type":"node_started"}"#.to_string();
+        let large = format!(r#"{{"message":"{}"}}"#, "x".repeat(1000));
+
+        let (value, encoding) = encode_payload(small.clone(), Some(&config)).unwrap();
+        assert_eq!(encoding, PayloadEncoding::Json);
+        assert_eq!(decode_payload(value, encoding).unwrap(), small);
+
+        let (value, encoding) = encode_payload(large.clone(), Some(&config)).unwrap();
+        assert_eq!(encoding, PayloadEncoding::Zstd);
+        assert_eq!(decode_payload(value, encoding).unwrap(), large);
+
+        let (_, encoding) = encode_payload(large, None).unwrap();
+        assert_eq!(encoding, PayloadEncoding::Json);
+    }
+}
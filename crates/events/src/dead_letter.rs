@@ -0,0 +1,381 @@
+//! Dead-letter queue for failed event deliveries
+//!
+//! Forwarding events to an external destination (a Kafka topic, a webhook)
+//! can fail for longer than a retry loop should wait. Rather than dropping
+//! the event, [`DeadLetterQueue::deliver`] retries with backoff and then
+//! parks the envelope in the WAL's `dead_letters` table together with the
+//! failure reason. [`DeadLetterQueue::redeliver_dead_letters`] retries
+//! everything parked for a destination once it is reachable again.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+
+use crate::types::EventEnvelope;
+use crate::wal::{format_timestamp, parse_column, WalError, WriteAheadLog};
+
+/// An event whose delivery failed
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    /// Row ID in the dead-letter table
+    pub id: i64,
+    /// Where the event was being delivered, e.g. `kafka:events`
+    pub destination: String,
+    /// The undelivered event
+    pub envelope: EventEnvelope,
+    /// Error from the most recent attempt
+    pub reason: String,
+    /// Total delivery attempts so far
+    pub attempts: u32,
+    /// When the event was dead-lettered
+    pub created_at: DateTime<Utc>,
+    /// When delivery was last attempted
+    pub last_attempt_at: DateTime<Utc>,
+}
+
+/// How often delivery is retried before an event is dead-lettered
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts before giving up, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each further retry
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (starting at 1)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_backoff)
+    }
+}
+
+/// Outcome of a redelivery pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RedeliveryReport {
+    /// Dead letters delivered and removed from the queue
+    pub delivered: usize,
+    /// Dead letters that failed again and remain queued
+    pub failed: usize,
+}
+
+impl WriteAheadLog {
+    /// Park an undeliverable event in the dead-letter table
+    pub fn dead_letter(
+        &mut self,
+        destination: &str,
+        envelope: &EventEnvelope,
+        reason: &str,
+        attempts: u32,
+    ) -> Result<DeadLetter, WalError> {
+        let now = Utc::now();
+        self.conn.execute(
+            "INSERT INTO dead_letters
+                (destination, sequence, envelope_json, reason, attempts, created_at, last_attempt_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+            params![
+                destination,
+                envelope.sequence,
+                serde_json::to_string(envelope)?,
+                reason,
+                attempts,
+                format_timestamp(now),
+            ],
+        )?;
+        Ok(DeadLetter {
+            id: self.conn.last_insert_rowid(),
+            destination: destination.to_string(),
+            envelope: envelope.clone(),
+            reason: reason.to_string(),
+            attempts,
+            created_at: now,
+            last_attempt_at: now,
+        })
+    }
+
+    /// List dead letters, oldest first, optionally for one destination
+    pub fn dead_letters(&self, destination: Option<&str>) -> Result<Vec<DeadLetter>, WalError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, destination, envelope_json, reason, attempts, created_at, last_attempt_at
+             FROM dead_letters
+             WHERE ?1 IS NULL OR destination = ?1
+             ORDER BY id",
+        )?;
+        let rows = stmt
+            .query_map(params![destination], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, u32>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    id,
+                    destination,
+                    envelope_json,
+                    reason,
+                    attempts,
+                    created_at,
+                    last_attempt_at,
+                )| {
+                    Ok(DeadLetter {
+                        id,
+                        destination,
+                        envelope: serde_json::from_str(&envelope_json)?,
+                        reason,
+                        attempts,
+                        created_at: parse_column(&created_at)?,
+                        last_attempt_at: parse_column(&last_attempt_at)?,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Remove a dead letter, returning whether it existed
+    pub fn remove_dead_letter(&mut self, id: i64) -> Result<bool, WalError> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM dead_letters WHERE id = ?1", params![id])?;
+        Ok(removed > 0)
+    }
+
+    /// Record another failed delivery attempt for a dead letter
+    pub fn record_dead_letter_failure(&mut self, id: i64, reason: &str) -> Result<(), WalError> {
+        self.conn.execute(
+            "UPDATE dead_letters
+             SET attempts = attempts + 1, reason = ?2, last_attempt_at = ?3
+             WHERE id = ?1",
+            params![id, reason, format_timestamp(Utc::now())],
+        )?;
+        Ok(())
+    }
+}
+
+/// Retrying delivery with a dead-letter fallback
+///
+/// Cheaply cloneable; clones share the same WAL connection.
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    wal: Arc<Mutex<WriteAheadLog>>,
+    policy: RetryPolicy,
+}
+
+impl DeadLetterQueue {
+    /// Store dead letters in the given WAL
+    ///
+    /// The WAL may be a second connection to the same database file as the
+    /// main event log.
+    pub fn new(wal: WriteAheadLog) -> Self {
+        Self {
+            wal: Arc::new(Mutex::new(wal)),
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Set the retry policy used before dead-lettering
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Run a closure against the WAL
+    ///
+    /// The lock is never held across an `.await`.
+    fn with_wal<R>(&self, f: impl FnOnce(&mut WriteAheadLog) -> R) -> R {
+        let mut wal = self.wal.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut wal)
+    }
+
+    /// Deliver an event, dead-lettering it if every attempt fails
+    ///
+    /// Returns `true` if the event was delivered and `false` if it was
+    /// dead-lettered. Errors only if the dead letter itself cannot be stored.
+    pub async fn deliver<F, Fut, E>(
+        &self,
+        destination: &str,
+        envelope: &EventEnvelope,
+        mut send: F,
+    ) -> Result<bool, WalError>
+    where
+        F: FnMut(EventEnvelope) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut attempts = 0;
+        let reason = loop {
+            attempts += 1;
+            match send(envelope.clone()).await {
+                Ok(()) => return Ok(true),
+                Err(e) if attempts >= self.policy.max_attempts => break e.to_string(),
+                Err(e) => {
+                    tracing::debug!("delivery to {destination} failed (attempt {attempts}): {e}");
+                    tokio::time::sleep(self.policy.backoff(attempts)).await;
+                }
+            }
+        };
+
+        tracing::warn!(
+            "dead-lettering event {} for {destination} after {attempts} attempts: {reason}",
+            envelope.sequence
+        );
+        self.with_wal(|wal| wal.dead_letter(destination, envelope, &reason, attempts))?;
+        Ok(false)
+    }
+
+    /// Retry every dead letter queued for a destination, oldest first
+    ///
+    /// Each dead letter gets one attempt per pass. Delivered events are
+    /// removed from the queue; failures stay queued with the new reason.
+    pub async fn redeliver_dead_letters<F, Fut, E>(
+        &self,
+        destination: &str,
+        mut send: F,
+    ) -> Result<RedeliveryReport, WalError>
+    where
+        F: FnMut(EventEnvelope) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut report = RedeliveryReport::default();
+        for letter in self.dead_letters(Some(destination))? {
+            match send(letter.envelope).await {
+                Ok(()) => {
+                    self.with_wal(|wal| wal.remove_dead_letter(letter.id))?;
+                    report.delivered += 1;
+                }
+                Err(e) => {
+                    let reason = e.to_string();
+                    self.with_wal(|wal| wal.record_dead_letter_failure(letter.id, &reason))?;
+                    report.failed += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// List dead letters, optionally for one destination
+    pub fn dead_letters(&self, destination: Option<&str>) -> Result<Vec<DeadLetter>, WalError> {
+        self.with_wal(|wal| wal.dead_letters(destination))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn envelope() -> EventEnvelope {
+        EventEnvelope::new(
+            7,
+            Event::DataDeleted {
+                data_uuid: uuid::Uuid::new_v4(),
+                timestamp: Utc::now(),
+            },
+        )
+    }
+
+    fn queue() -> DeadLetterQueue {
+        DeadLetterQueue::new(WriteAheadLog::in_memory().unwrap()).with_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retries_then_dead_letters() {
+        let dlq = queue();
+        let calls = AtomicU32::new(0);
+
+        // Succeeds on the second attempt
+        let delivered = dlq
+            .deliver("kafka:events", &envelope(), |_| {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if n == 0 {
+                        Err("broker down")
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert!(delivered);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let delivered = dlq
+            .deliver("kafka:events", &envelope(), |_| async {
+                Err("broker down")
+            })
+            .await
+            .unwrap();
+        assert!(!delivered);
+
+        let letters = dlq.dead_letters(Some("kafka:events")).unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts, 3);
+        assert_eq!(letters[0].reason, "broker down");
+        assert_eq!(letters[0].envelope.sequence, 7);
+        assert!(dlq.dead_letters(Some("webhook:a")).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_redeliver_dead_letters() {
+        let dlq = queue();
+        for _ in 0..2 {
+            dlq.deliver("kafka:events", &envelope(), |_| async { Err("down") })
+                .await
+                .unwrap();
+        }
+
+        let report = dlq
+            .redeliver_dead_letters("kafka:events", |_| async { Err("still down") })
+            .await
+            .unwrap();
+        assert_eq!(
+            report,
+            RedeliveryReport {
+                delivered: 0,
+                failed: 2
+            }
+        );
+        let letters = dlq.dead_letters(None).unwrap();
+        assert_eq!(letters[0].attempts, 4);
+        assert_eq!(letters[0].reason, "still down");
+
+        let report = dlq
+            .redeliver_dead_letters("kafka:events", |_| async { Ok::<_, String>(()) })
+            .await
+            .unwrap();
+        assert_eq!(report.delivered, 2);
+        assert!(dlq.dead_letters(None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Event export for offline analytics
+//!
+//! Dumps the events matching a filter as newline-delimited JSON or, with
+//! the `parquet` feature, as a Parquet file with typed columns. Both
+//! formats share one row layout, so the output loads directly into tools
+//! like DuckDB:
+//!
+//! | Column | Type |
+//! |--------|------|
+//! | `sequence` | unsigned 64-bit integer |
+//! | `type` | string (see [`Event::event_type`]) |
+//! | `workflow_id` | string, nullable |
+//! | `node_id` | string, nullable |
+//! | `timestamp` | timestamp (UTC, microseconds) |
+//! | `payload` | the full event as JSON |
+
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::types::{Event, EventEnvelope, EventFilter};
+use crate::wal::{WalError, WriteAheadLog};
+
+/// Number of events read from the WAL per page
+const EXPORT_PAGE_SIZE: usize = 10_000;
+
+/// Output format for [`WriteAheadLog::export`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Parquet, one row group per page of events
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// A single exported event
+#[derive(Debug, Serialize)]
+struct ExportRow<'a> {
+    sequence: u64,
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    workflow_id: Option<Uuid>,
+    node_id: Option<Uuid>,
+    timestamp: DateTime<Utc>,
+    payload: &'a Event,
+}
+
+impl<'a> ExportRow<'a> {
+    fn new(envelope: &'a EventEnvelope) -> Self {
+        let event = &envelope.event;
+        Self {
+            sequence: envelope.sequence,
+            event_type: event.event_type(),
+            workflow_id: event.workflow_id(),
+            node_id: event.node_id(),
+            timestamp: event.timestamp(),
+            payload: event,
+        }
+    }
+}
+
+impl WriteAheadLog {
+    /// Export the events matching `filter` to `writer`
+    ///
+    /// Events are read in pages, so the whole log never has to fit in
+    /// memory. Returns the number of events written.
+    pub fn export<W: Write + Send>(
+        &self,
+        filter: &EventFilter,
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<u64, WalError> {
+        match format {
+            ExportFormat::Jsonl => {
+                let mut writer = std::io::BufWriter::new(writer);
+                let count = self.for_each_page(filter, |page| {
+                    for envelope in page {
+                        serde_json::to_writer(&mut writer, &ExportRow::new(envelope))?;
+                        writer.write_all(b"\n")?;
+                    }
+                    Ok(())
+                })?;
+                writer.flush()?;
+                Ok(count)
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => parquet_export::export(self, filter, writer),
+        }
+    }
+
+    /// Feed the events matching `filter` to `f` a page at a time
+    fn for_each_page(
+        &self,
+        filter: &EventFilter,
+        mut f: impl FnMut(&[EventEnvelope]) -> Result<(), WalError>,
+    ) -> Result<u64, WalError> {
+        let mut page_filter = filter.clone();
+        let mut remaining = filter.limit.unwrap_or(usize::MAX);
+        let mut count = 0;
+
+        while remaining > 0 {
+            page_filter.limit = Some(remaining.min(EXPORT_PAGE_SIZE));
+            let page = self.read_filtered(&page_filter)?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            page_filter.from_sequence = Some(last.sequence + 1);
+            remaining -= page.len();
+            count += page.len() as u64;
+            f(&page)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_export {
+    use std::io::Write;
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+
+    use super::ExportRow;
+    use crate::types::{EventEnvelope, EventFilter};
+    use crate::wal::{WalError, WriteAheadLog};
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("sequence", DataType::UInt64, false),
+            Field::new("type", DataType::Utf8, false),
+            Field::new("workflow_id", DataType::Utf8, true),
+            Field::new("node_id", DataType::Utf8, true),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new("payload", DataType::Utf8, false),
+        ]))
+    }
+
+    fn record_batch(schema: &SchemaRef, page: &[EventEnvelope]) -> Result<RecordBatch, WalError> {
+        let rows: Vec<ExportRow<'_>> = page.iter().map(ExportRow::new).collect();
+        let payloads = rows
+            .iter()
+            .map(|r| serde_json::to_string(r.payload))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|r| r.sequence),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| r.event_type),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.workflow_id.map(|id| id.to_string())),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.node_id.map(|id| id.to_string())),
+            )),
+            Arc::new(
+                TimestampMicrosecondArray::from_iter_values(
+                    rows.iter().map(|r| r.timestamp.timestamp_micros()),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(StringArray::from_iter_values(payloads)),
+        ];
+        RecordBatch::try_new(schema.clone(), columns).map_err(|e| WalError::Export(e.to_string()))
+    }
+
+    pub(super) fn export<W: Write + Send>(
+        wal: &WriteAheadLog,
+        filter: &EventFilter,
+        writer: W,
+    ) -> Result<u64, WalError> {
+        let schema = schema();
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))
+            .map_err(|e| WalError::Export(e.to_string()))?;
+
+        let count = wal.for_each_page(filter, |page| {
+            writer
+                .write(&record_batch(&schema, page)?)
+                .map_err(|e| WalError::Export(e.to_string()))
+        })?;
+        writer
+            .close()
+            .map_err(|e| WalError::Export(e.to_string()))?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wal_with_events(n: usize) -> (WriteAheadLog, Uuid) {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let workflow_id = Uuid::new_v4();
+        for _ in 0..n {
+            wal.append(Event::NodeStarted {
+                workflow_id,
+                node_id: Uuid::new_v4(),
+                timestamp: Utc::now(),
+            })
+            .unwrap();
+        }
+        wal.append(Event::DataDeleted {
+            data_uuid: Uuid::new_v4(),
+            timestamp: Utc::now(),
+        })
+        .unwrap();
+        (wal, workflow_id)
+    }
+
+    #[test]
+    fn test_export_jsonl() {
+        let (wal, workflow_id) = wal_with_events(3);
+
+        let mut out = Vec::new();
+        let count = wal
+            .export(&EventFilter::new(), ExportFormat::Jsonl, &mut out)
+            .unwrap();
+        assert_eq!(count, 4);
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["sequence"], 1);
+        assert_eq!(lines[0]["type"], "node_started");
+        assert_eq!(lines[0]["workflow_id"], workflow_id.to_string());
+        assert_eq!(lines[0]["payload"]["type"], "node_started");
+        assert!(lines[3]["workflow_id"].is_null());
+
+        let mut out = Vec::new();
+        let filter = EventFilter::new().workflow(workflow_id).limit(2);
+        assert_eq!(
+            wal.export(&filter, ExportFormat::Jsonl, &mut out).unwrap(),
+            2
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_export_parquet() {
+        use arrow_array::{Array, StringArray, UInt64Array};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let (wal, _) = wal_with_events(3);
+        let mut file = tempfile::tempfile().unwrap();
+        let count = wal
+            .export(&EventFilter::new(), ExportFormat::Parquet, &mut file)
+            .unwrap();
+        assert_eq!(count, 4);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batch = reader.into_iter().next().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 4);
+
+        let sequences = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert_eq!(sequences.value(3), 4);
+        let workflow_ids = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(workflow_ids.is_null(3));
+    }
+}
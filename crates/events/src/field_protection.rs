@@ -0,0 +1,475 @@
+//! Field-level protection of sensitive event payloads
+//!
+//! Prompts and error messages can carry user data. [`FieldProtection`]
+//! rewrites selected string fields of an event before it leaves the
+//! process: the SQLite WAL applies it before persisting (see
+//! [`WriteAheadLog::with_field_protection`]) and the Kafka producer before
+//! publishing. Each rule either
+//!
+//! - encrypts the field with AES-256-GCM, bound to the event type and
+//!   field path, producing `enc:v1:<key id>:<base64>`, or
+//! - replaces it with a keyed HMAC-SHA256 digest,
+//!   `hmac-sha256:<key id>:<hex>`, which still allows equality matching.
+//!
+//! Protected values are ordinary strings, so events keep their shape and
+//! deserialize as usual; readers holding the key call
+//! [`FieldProtection::reveal`] to decrypt. Values that are already
+//! protected are left alone, so events read from the WAL can be republished
+//! without being encrypted twice. Rules only apply to string fields.
+//!
+//! Keys are identified by an ID stored with each ciphertext. To rotate,
+//! encrypt with a new key and keep the old ones for decryption with
+//! [`FieldProtection::with_decryption_key`].
+//!
+//! [`WriteAheadLog::with_field_protection`]: crate::wal::WriteAheadLog::with_field_protection
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::types::{Event, EventEnvelope, EventKind};
+
+/// Prefix of encrypted field values
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Prefix of hashed field values
+const HASHED_PREFIX: &str = "hmac-sha256:";
+
+/// Length of field protection keys in bytes
+pub const FIELD_KEY_LEN: usize = 32;
+
+/// What happens to a protected field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldAction {
+    /// Encrypt, so authorized readers can recover the value
+    Encrypt,
+    /// Replace with a keyed digest; the value cannot be recovered
+    Hash,
+}
+
+/// A field to protect
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FieldRule {
+    /// Event kind the rule applies to; `None` for every kind
+    kind: Option<EventKind>,
+    /// Keys leading to the field within the event's JSON object
+    path: Vec<String>,
+    action: FieldAction,
+}
+
+impl FieldRule {
+    /// Path as written in rules and bound into ciphertexts
+    fn path_str(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+/// A named key
+#[derive(Clone)]
+struct FieldKey {
+    aead: LessSafeKey,
+    /// Raw key, also used for hashing
+    secret: Arc<[u8]>,
+}
+
+/// Encryption and hashing rules for event fields
+///
+/// Cheaply cloneable.
+#[derive(Clone)]
+pub struct FieldProtection {
+    /// ID of the key used to protect new values
+    key_id: String,
+    keys: HashMap<String, FieldKey>,
+    rules: Vec<FieldRule>,
+    rng: SystemRandom,
+}
+
+impl std::fmt::Debug for FieldProtection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldProtection")
+            .field("key_id", &self.key_id)
+            .field("rules", &self.rules)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FieldProtection {
+    /// Protect fields with a 32-byte key
+    ///
+    /// `key_id` is stored with every protected value and must not contain
+    /// `:`.
+    pub fn new(key_id: &str, key: &[u8]) -> Result<Self, FieldProtectionError> {
+        let protection = Self {
+            key_id: key_id.to_string(),
+            keys: HashMap::new(),
+            rules: Vec::new(),
+            rng: SystemRandom::new(),
+        };
+        protection.with_decryption_key(key_id, key)
+    }
+
+    /// Also accept `key` when decrypting values protected under `key_id`
+    pub fn with_decryption_key(
+        mut self,
+        key_id: &str,
+        key: &[u8],
+    ) -> Result<Self, FieldProtectionError> {
+        if key_id.is_empty() || key_id.contains(':') {
+            return Err(FieldProtectionError::InvalidKey(format!(
+                "invalid key ID '{key_id}'"
+            )));
+        }
+        if key.len() != FIELD_KEY_LEN {
+            return Err(FieldProtectionError::InvalidKey(format!(
+                "expected {FIELD_KEY_LEN} bytes, got {}",
+                key.len()
+            )));
+        }
+        let aead = UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| FieldProtectionError::InvalidKey("rejected by AES-256-GCM".into()))?;
+        self.keys.insert(
+            key_id.to_string(),
+            FieldKey {
+                aead: LessSafeKey::new(aead),
+                secret: key.into(),
+            },
+        );
+        Ok(self)
+    }
+
+    /// Encrypt the field at the dotted `path` of events of `kind`, or of
+    /// every event if `kind` is `None`
+    pub fn encrypt(self, kind: Option<EventKind>, path: &str) -> Self {
+        self.rule(kind, path, FieldAction::Encrypt)
+    }
+
+    /// Hash the field at the dotted `path` of events of `kind`, or of every
+    /// event if `kind` is `None`
+    pub fn hash(self, kind: Option<EventKind>, path: &str) -> Self {
+        self.rule(kind, path, FieldAction::Hash)
+    }
+
+    fn rule(mut self, kind: Option<EventKind>, path: &str, action: FieldAction) -> Self {
+        self.rules.push(FieldRule {
+            kind,
+            path: path.split('.').map(str::to_string).collect(),
+            action,
+        });
+        self
+    }
+
+    /// Add rules from a comma-separated specification
+    ///
+    /// Each rule is `kind:path`, optionally followed by `:hash`; `*`
+    /// matches every kind. For example
+    /// `node_failed:error,*:message,workflow_started:name:hash`.
+    pub fn with_rules(mut self, spec: &str) -> Result<Self, FieldProtectionError> {
+        for rule in spec.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let mut parts = rule.split(':');
+            let (Some(kind), Some(path)) = (parts.next(), parts.next()) else {
+                return Err(FieldProtectionError::InvalidRule(rule.to_string()));
+            };
+            let action = match parts.next() {
+                None | Some("encrypt") => FieldAction::Encrypt,
+                Some("hash") => FieldAction::Hash,
+                Some(_) => return Err(FieldProtectionError::InvalidRule(rule.to_string())),
+            };
+            let kind = match kind {
+                "*" => None,
+                kind => Some(
+                    kind.parse()
+                        .map_err(|_| FieldProtectionError::InvalidRule(rule.to_string()))?,
+                ),
+            };
+            if path.is_empty() || parts.next().is_some() {
+                return Err(FieldProtectionError::InvalidRule(rule.to_string()));
+            }
+            self = self.rule(kind, path, action);
+        }
+        Ok(self)
+    }
+
+    /// Protect the configured fields of an event
+    ///
+    /// Returns the event unchanged if no rule applies.
+    pub fn protect(&self, event: &Event) -> Result<Event, FieldProtectionError> {
+        let kind = event.kind();
+        let rules: Vec<&FieldRule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.kind.is_none_or(|k| k == kind))
+            .collect();
+        if rules.is_empty() {
+            return Ok(event.clone());
+        }
+
+        let mut value = serde_json::to_value(event)?;
+        for rule in rules {
+            let Some(Value::String(field)) = field_mut(&mut value, &rule.path) else {
+                continue;
+            };
+            if is_protected(field) {
+                continue;
+            }
+            *field = match rule.action {
+                FieldAction::Encrypt => self.seal(kind, &rule.path_str(), field)?,
+                FieldAction::Hash => self.digest(field),
+            };
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Decrypt every encrypted field of an event
+    ///
+    /// Hashed fields stay hashed. Fails if a value was encrypted under an
+    /// unknown key or does not authenticate.
+    pub fn reveal(&self, event: &Event) -> Result<Event, FieldProtectionError> {
+        let kind = event.kind();
+        let mut value = serde_json::to_value(event)?;
+        let mut changed = false;
+        let mut path = Vec::new();
+        self.reveal_value(kind, &mut value, &mut path, &mut changed)?;
+        if !changed {
+            return Ok(event.clone());
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Decrypt every encrypted field of an envelope's event
+    pub fn reveal_envelope(
+        &self,
+        envelope: &EventEnvelope,
+    ) -> Result<EventEnvelope, FieldProtectionError> {
+        let mut envelope = envelope.clone();
+        envelope.event = self.reveal(&envelope.event)?;
+        Ok(envelope)
+    }
+
+    fn reveal_value(
+        &self,
+        kind: EventKind,
+        value: &mut Value,
+        path: &mut Vec<String>,
+        changed: &mut bool,
+    ) -> Result<(), FieldProtectionError> {
+        match value {
+            Value::String(field) if field.starts_with(ENCRYPTED_PREFIX) => {
+                *field = self.open(kind, &path.join("."), field)?;
+                *changed = true;
+            }
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    path.push(key.clone());
+                    self.reveal_value(kind, value, path, changed)?;
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn seal(
+        &self,
+        kind: EventKind,
+        path: &str,
+        plaintext: &str,
+    ) -> Result<String, FieldProtectionError> {
+        let key = &self.keys[&self.key_id];
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| FieldProtectionError::Crypto("no randomness available".into()))?;
+
+        let mut sealed = plaintext.as_bytes().to_vec();
+        key.aead
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad(kind, path)),
+                &mut sealed,
+            )
+            .map_err(|_| FieldProtectionError::Crypto("encryption failed".into()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&sealed);
+        Ok(format!(
+            "{ENCRYPTED_PREFIX}{}:{}",
+            self.key_id,
+            BASE64.encode(payload)
+        ))
+    }
+
+    fn open(
+        &self,
+        kind: EventKind,
+        path: &str,
+        protected: &str,
+    ) -> Result<String, FieldProtectionError> {
+        let malformed = || FieldProtectionError::Crypto(format!("malformed value at '{path}'"));
+        let (key_id, payload) = protected
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(malformed)?;
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| FieldProtectionError::UnknownKey(key_id.to_string()))?;
+        let mut payload = BASE64.decode(payload).map_err(|_| malformed())?;
+        if payload.len() < NONCE_LEN {
+            return Err(malformed());
+        }
+        let mut sealed = payload.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&payload).map_err(|_| malformed())?;
+
+        let plaintext = key
+            .aead
+            .open_in_place(nonce, Aad::from(aad(kind, path)), &mut sealed)
+            .map_err(|_| {
+                FieldProtectionError::Crypto(format!("value at '{path}' failed to authenticate"))
+            })?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| malformed())
+    }
+
+    fn digest(&self, value: &str) -> String {
+        let key = &self.keys[&self.key_id];
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&key.secret).expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        let hex: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        format!("{HASHED_PREFIX}{}:{hex}", self.key_id)
+    }
+}
+
+/// Additional data binding a ciphertext to where it belongs, so it cannot
+/// be moved to another field or event type
+fn aad(kind: EventKind, path: &str) -> Vec<u8> {
+    format!("{}:{path}", kind.as_str()).into_bytes()
+}
+
+fn is_protected(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX) || value.starts_with(HASHED_PREFIX)
+}
+
+/// Find the value at `path` within nested JSON objects
+fn field_mut<'a>(value: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    path.iter()
+        .try_fold(value, |value, key| value.as_object_mut()?.get_mut(key))
+}
+
+/// Field protection errors
+#[derive(Debug, thiserror::Error)]
+pub enum FieldProtectionError {
+    #[error("Invalid field protection key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid field protection rule '{0}'")]
+    InvalidRule(String),
+
+    #[error("Unknown field protection key '{0}'")]
+    UnknownKey(String),
+
+    #[error("Field decryption failed: {0}")]
+    Crypto(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_protect_and_reveal() {
+        let protection = FieldProtection::new("k1", &[7; FIELD_KEY_LEN])
+            .unwrap()
+            .with_rules("node_failed:error,workflow_started:name:hash")
+            .unwrap();
+        let failed = Event::NodeFailed {
+            workflow_id: Uuid::new_v4(),
+            node_id: Uuid::new_v4(),
+            error: "prompt 'my SSN is ...' was rejected".to_string(),
+            retry_count: 0,
+            timestamp: Utc::now(),
+        };
+
+        let protected = protection.protect(&failed).unwrap();
+        let Event::NodeFailed { error, .. } = &protected else {
+            unreachable!()
+        };
+        assert!(error.starts_with("enc:v1:k1:"));
+        // Already protected values are not encrypted again
+        assert_eq!(
+            protection.protect(&protected).unwrap().to_json().unwrap(),
+            protected.to_json().unwrap()
+        );
+        assert_eq!(
+            protection.reveal(&protected).unwrap().to_json().unwrap(),
+            failed.to_json().unwrap()
+        );
+
+        // Without the key, or after rotation without the old key, nothing
+        // is revealed
+        let rotated = FieldProtection::new("k2", &[8; FIELD_KEY_LEN]).unwrap();
+        assert!(matches!(
+            rotated.reveal(&protected),
+            Err(FieldProtectionError::UnknownKey(id)) if id == "k1"
+        ));
+        let rotated = rotated
+            .with_decryption_key("k1", &[7; FIELD_KEY_LEN])
+            .unwrap();
+        assert!(rotated.reveal(&protected).is_ok());
+
+        let started = |name: &str| Event::WorkflowStarted {
+            workflow_id: Uuid::nil(),
+            name: name.to_string(),
+            timestamp: chrono::DateTime::UNIX_EPOCH,
+        };
+        let hashed = protection.protect(&started("payroll")).unwrap();
+        assert_eq!(
+            hashed.to_json().unwrap(),
+            protection
+                .protect(&started("payroll"))
+                .unwrap()
+                .to_json()
+                .unwrap()
+        );
+        assert!(hashed.to_json().unwrap().contains("hmac-sha256:k1:"));
+        assert!(protection.clone().with_rules("node_failed").is_err());
+
+        // The WAL persists and returns the protected event
+        let mut wal = crate::wal::WriteAheadLog::in_memory()
+            .unwrap()
+            .with_field_protection(Some(protection.clone()));
+        let appended = wal.append(failed.clone()).unwrap();
+        let stored = wal.read_from(1).unwrap().remove(0);
+        assert_eq!(
+            stored.event.to_json().unwrap(),
+            appended.event.to_json().unwrap()
+        );
+        assert!(stored.event.to_json().unwrap().contains("enc:v1:k1:"));
+        assert_eq!(
+            protection
+                .reveal_envelope(&stored)
+                .unwrap()
+                .event
+                .to_json()
+                .unwrap(),
+            failed.to_json().unwrap()
+        );
+    }
+}
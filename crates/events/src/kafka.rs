@@ -2,58 +2,470 @@
 //!
 //! Optional integration with Apache Kafka for stronger durability
 //! guarantees and distributed event streaming.
+//!
+//! Producers configured with a [`KafkaConfig::transactional_id`] publish
+//! every call in its own Kafka transaction: a batch becomes visible to
+//! `read_committed` consumers (the librdkafka default) all at once or not at
+//! all, and broker-side deduplication makes retried sends exactly-once.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::{KafkaError as RdKafkaError, KafkaResult, RDKafkaErrorCode};
+use rdkafka::message::{BorrowedMessage, Header, Message, OwnedHeaders};
+use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
+use rdkafka::{Offset, TopicPartitionList};
+
+use crate::dead_letter::{DeadLetterQueue, RedeliveryReport};
+use crate::field_protection::FieldProtection;
+use crate::outbox::{OutboxError, OutboxRelay};
+use crate::types::{Event, EventEnvelope};
+use crate::wal::WalError;
+
+/// Key used for events that do not belong to a workflow
+const SYSTEM_KEY: &str = "system";
+
+/// Consumer group used when none is configured
+const DEFAULT_GROUP_ID: &str = "swarmx-ui";
 
-use crate::types::Event;
+/// Header carrying the WAL sequence number of events relayed from the outbox
+pub const SEQUENCE_HEADER: &str = "swarmx-sequence";
+
+/// Timeout for blocking broker round trips (commit, seek, offset lookup)
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Attempts at committing a transaction that fails with a retriable error
+const TRANSACTION_COMMIT_ATTEMPTS: usize = 3;
+
+/// How the producer chooses each message's key
+///
+/// Kafka only orders messages within a partition, and messages with the
+/// same key always land on the same partition. The key strategy therefore
+/// decides which events consumers are guaranteed to see in order.
+#[derive(Clone, Default)]
+pub enum KeyStrategy {
+    /// Key by workflow ID, or `system` for events outside any workflow
+    ///
+    /// Every event of a workflow stays in order.
+    #[default]
+    Workflow,
+    /// Key by node ID, falling back to the workflow key
+    ///
+    /// Spreads large workflows over more partitions, but only orders the
+    /// events of each node; workflow-level events may overtake node events.
+    Node,
+    /// Key by event type (see [`Event::event_type`])
+    ///
+    /// Only events of the same type stay in order.
+    EventType,
+    /// Key computed by a custom function
+    Custom(Arc<dyn Fn(&Event) -> String + Send + Sync>),
+}
+
+impl KeyStrategy {
+    /// Key strategy computed by `f`
+    pub fn custom(f: impl Fn(&Event) -> String + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    /// Message key for an event
+    pub fn key(&self, event: &Event) -> String {
+        let workflow_key = || {
+            event
+                .workflow_id()
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| SYSTEM_KEY.to_string())
+        };
+        match self {
+            Self::Workflow => workflow_key(),
+            Self::Node => event
+                .node_id()
+                .map(|id| id.to_string())
+                .unwrap_or_else(workflow_key),
+            Self::EventType => event.event_type().to_string(),
+            Self::Custom(f) => f(event),
+        }
+    }
+
+    /// Whether every event of a workflow is guaranteed to share a partition
+    ///
+    /// Custom strategies are assumed not to.
+    pub fn preserves_workflow_order(&self) -> bool {
+        matches!(self, Self::Workflow)
+    }
+}
+
+impl fmt::Debug for KeyStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Workflow => f.write_str("Workflow"),
+            Self::Node => f.write_str("Node"),
+            Self::EventType => f.write_str("EventType"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Where a published event was stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryReport {
+    /// Partition the event was written to
+    pub partition: i32,
+    /// Offset of the event within the partition
+    pub offset: i64,
+}
 
 /// Kafka producer for event publishing
 pub struct KafkaEventProducer {
     /// Kafka topic for events
     topic: String,
-    /// Broker addresses
-    brokers: String,
-    // producer: FutureProducer,  // Uncomment when kafka feature enabled
+    producer: FutureProducer,
+    /// How message keys are chosen
+    key_strategy: KeyStrategy,
+    /// How long a send may wait for space in the local queue
+    queue_timeout: Duration,
+    /// How long `flush` waits for outstanding deliveries
+    flush_timeout: Duration,
+    /// Serializes transactions in transactional mode; holds whether
+    /// transactions were initialized with the broker
+    transaction: Option<tokio::sync::Mutex<bool>>,
+    /// Protection applied to events before they are published
+    field_protection: Option<FieldProtection>,
 }
 
 impl KafkaEventProducer {
-    /// Create a new Kafka producer
+    /// Create a new Kafka producer with default settings
     ///
     /// # Arguments
     /// * `brokers` - Comma-separated list of broker addresses
     /// * `topic` - Topic to publish events to
     pub fn new(brokers: &str, topic: &str) -> Result<Self, KafkaError> {
-        todo!("Implement Kafka producer initialization")
+        Self::from_config(&KafkaConfig::new(brokers, topic))
+    }
+
+    /// Create a producer from a configuration
+    ///
+    /// A transactional producer registers with the broker lazily, on its
+    /// first publish.
+    pub fn from_config(config: &KafkaConfig) -> Result<Self, KafkaError> {
+        let mut client = ClientConfig::new();
+        client
+            .set("bootstrap.servers", &config.brokers)
+            .set("acks", config.acks.as_str())
+            .set("compression.type", config.compression.as_str())
+            .set("linger.ms", config.linger_ms.to_string())
+            .set("message.timeout.ms", config.message_timeout_ms.to_string());
+        if let Some(transactional_id) = &config.transactional_id {
+            if config.acks != KafkaAcks::All {
+                return Err(KafkaError::Configuration(
+                    "transactional producers require acks = all".to_string(),
+                ));
+            }
+            client
+                .set("transactional.id", transactional_id)
+                .set("enable.idempotence", "true");
+        }
+        let producer: FutureProducer = client
+            .create()
+            .map_err(|e| KafkaError::Configuration(e.to_string()))?;
+
+        Ok(Self {
+            topic: config.topic.clone(),
+            producer,
+            key_strategy: config.key_strategy.clone(),
+            queue_timeout: Duration::from_millis(config.message_timeout_ms as u64),
+            flush_timeout: Duration::from_millis(config.message_timeout_ms as u64),
+            transaction: config
+                .transactional_id
+                .as_ref()
+                .map(|_| tokio::sync::Mutex::new(false)),
+            field_protection: config.field_protection.clone(),
+        })
     }
 
-    /// Publish an event to Kafka
-    pub async fn publish(&self, event: &Event) -> Result<(), KafkaError> {
-        todo!("Implement event publishing")
+    /// Whether every publish runs in its own transaction
+    pub fn is_transactional(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Publish an event to Kafka, keyed by the configured [`KeyStrategy`]
+    pub async fn publish(&self, event: &Event) -> Result<DeliveryReport, KafkaError> {
+        self.publish_with_key(&self.key_strategy.key(event), event)
+            .await
+    }
+
+    /// Get the strategy used to key published events
+    pub fn key_strategy(&self) -> &KeyStrategy {
+        &self.key_strategy
     }
 
     /// Publish an event with a specific key (for partitioning)
-    pub async fn publish_with_key(&self, key: &str, event: &Event) -> Result<(), KafkaError> {
-        todo!("Implement keyed event publishing")
+    ///
+    /// Resolves once the broker has acknowledged the event according to the
+    /// configured `acks`.
+    pub async fn publish_with_key(
+        &self,
+        key: &str,
+        event: &Event,
+    ) -> Result<DeliveryReport, KafkaError> {
+        let payload = self.serialize(event)?;
+        if self.is_transactional() {
+            let mut reports = self
+                .send_records(vec![(key.to_string(), payload, None)])
+                .await?;
+            return Ok(reports.remove(0));
+        }
+        let record = FutureRecord::to(&self.topic).key(key).payload(&payload);
+        let (partition, offset) = self
+            .producer
+            .send(record, self.queue_timeout)
+            .await
+            .map_err(|(e, _)| KafkaError::Publish(e.to_string()))?;
+        Ok(DeliveryReport { partition, offset })
     }
 
     /// Publish multiple events as a batch
-    pub async fn publish_batch(&self, events: &[Event]) -> Result<(), KafkaError> {
-        todo!("Implement batch publishing")
+    ///
+    /// Every event is enqueued before any delivery report is awaited, so the
+    /// batch shares linger and compression. Reports are returned in input
+    /// order; the first failed delivery is returned as an error.
+    ///
+    /// In transactional mode the batch is one transaction: if any event
+    /// fails, the transaction is aborted and consumers see none of them.
+    pub async fn publish_batch(&self, events: &[Event]) -> Result<Vec<DeliveryReport>, KafkaError> {
+        let records = events
+            .iter()
+            .map(|event| Ok((self.key_strategy.key(event), self.serialize(event)?, None)))
+            .collect::<Result<Vec<_>, KafkaError>>()?;
+        self.send_records(records).await
+    }
+
+    /// Publish WAL envelopes as a batch, in order
+    ///
+    /// Like [`KafkaProducer::publish_batch`], but every message carries the
+    /// envelope's WAL sequence number in the [`SEQUENCE_HEADER`] header, so
+    /// consumers can drop events redelivered by an outbox relay.
+    pub async fn publish_envelopes(
+        &self,
+        envelopes: &[EventEnvelope],
+    ) -> Result<Vec<DeliveryReport>, KafkaError> {
+        let records = envelopes
+            .iter()
+            .map(|envelope| {
+                Ok((
+                    self.key_strategy.key(&envelope.event),
+                    self.serialize(&envelope.event)?,
+                    Some(envelope.sequence),
+                ))
+            })
+            .collect::<Result<Vec<_>, KafkaError>>()?;
+        self.send_records(records).await
+    }
+
+    /// Serialize an event, protecting its sensitive fields
+    fn serialize(&self, event: &Event) -> Result<Vec<u8>, KafkaError> {
+        match &self.field_protection {
+            Some(protection) => serialize(
+                &protection
+                    .protect(event)
+                    .map_err(|e| KafkaError::Serialization(e.to_string()))?,
+            ),
+            None => serialize(event),
+        }
+    }
+
+    /// Send records, in a transaction if the producer is transactional
+    async fn send_records(
+        &self,
+        records: Vec<(String, Vec<u8>, Option<u64>)>,
+    ) -> Result<Vec<DeliveryReport>, KafkaError> {
+        let Some(transaction) = &self.transaction else {
+            return self.enqueue_records(records).await;
+        };
+
+        // Only one transaction may be open per producer
+        let mut initialized = transaction.lock().await;
+        if !*initialized {
+            self.blocking(|producer| producer.init_transactions(OPERATION_TIMEOUT))
+                .await?
+                .map_err(transaction_error)?;
+            *initialized = true;
+        }
+        self.blocking(|producer| producer.begin_transaction())
+            .await?
+            .map_err(transaction_error)?;
+
+        match self.enqueue_records(records).await {
+            Ok(reports) => {
+                self.blocking(commit_transaction)
+                    .await?
+                    .map_err(transaction_error)?;
+                Ok(reports)
+            }
+            Err(e) => {
+                self.blocking(|producer| producer.abort_transaction(OPERATION_TIMEOUT))
+                    .await?
+                    .map_err(transaction_error)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Run a blocking librdkafka call off the async runtime
+    async fn blocking<T, F>(&self, f: F) -> Result<KafkaResult<T>, KafkaError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&FutureProducer) -> KafkaResult<T> + Send + 'static,
+    {
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || f(&producer))
+            .await
+            .map_err(|e| KafkaError::Publish(e.to_string()))
+    }
+
+    async fn enqueue_records(
+        &self,
+        records: Vec<(String, Vec<u8>, Option<u64>)>,
+    ) -> Result<Vec<DeliveryReport>, KafkaError> {
+        let mut deliveries: VecDeque<DeliveryFuture> = VecDeque::with_capacity(records.len());
+        let mut reports = Vec::with_capacity(records.len());
+
+        for (key, payload, sequence) in records {
+            let sequence = sequence.map(|sequence| sequence.to_string());
+            loop {
+                let mut record = FutureRecord::to(&self.topic).key(&key).payload(&payload);
+                if let Some(sequence) = &sequence {
+                    record = record.headers(OwnedHeaders::new().insert(Header {
+                        key: SEQUENCE_HEADER,
+                        value: Some(sequence),
+                    }));
+                }
+                match self.producer.send_result(record) {
+                    Ok(delivery) => {
+                        deliveries.push_back(delivery);
+                        break;
+                    }
+                    // Local queue is full: wait for the oldest delivery to drain it
+                    Err((RdKafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _))
+                        if !deliveries.is_empty() =>
+                    {
+                        if let Some(delivery) = deliveries.pop_front() {
+                            reports.push(await_delivery(delivery).await?);
+                        }
+                    }
+                    Err((e, _)) => return Err(KafkaError::Publish(e.to_string())),
+                }
+            }
+        }
+
+        for delivery in deliveries {
+            reports.push(await_delivery(delivery).await?);
+        }
+        Ok(reports)
     }
 
     /// Flush pending messages
+    ///
+    /// Waits until every enqueued event has been delivered or the message
+    /// timeout elapses.
     pub async fn flush(&self) -> Result<(), KafkaError> {
-        todo!("Implement flush")
+        let producer = self.producer.clone();
+        let timeout = self.flush_timeout;
+        tokio::task::spawn_blocking(move || producer.flush(timeout))
+            .await
+            .map_err(|e| KafkaError::Publish(e.to_string()))?
+            .map_err(|e| match e {
+                RdKafkaError::Flush(RDKafkaErrorCode::OperationTimedOut) => KafkaError::Timeout,
+                e => KafkaError::Publish(e.to_string()),
+            })
+    }
+
+    /// Dead-letter destination name for this producer's topic
+    pub fn dead_letter_destination(&self) -> String {
+        format!("kafka:{}", self.topic)
+    }
+
+    /// Publish an event, retrying and then dead-lettering it on failure
+    ///
+    /// Returns `true` if the event was delivered and `false` if it was
+    /// parked in the dead-letter queue.
+    pub async fn publish_or_dead_letter(
+        &self,
+        envelope: &EventEnvelope,
+        dlq: &DeadLetterQueue,
+    ) -> Result<bool, KafkaError> {
+        dlq.deliver(&self.dead_letter_destination(), envelope, |envelope| async move {
+            self.publish(&envelope.event).await.map(|_| ())
+        })
+        .await
+        .map_err(|e| KafkaError::DeadLetter(e.to_string()))
     }
+
+    /// Relay one batch of undelivered WAL events to this producer's topic
+    ///
+    /// The relay's destination should be [`KafkaProducer::outbox_destination`].
+    /// Returns the number of events published.
+    pub async fn relay_outbox_once(&self, relay: &OutboxRelay) -> Result<usize, OutboxError> {
+        relay
+            .relay_once(|batch| async move { self.publish_envelopes(&batch).await.map(|_| ()) })
+            .await
+    }
+
+    /// Relay committed WAL events to this producer's topic until the WAL fails
+    pub async fn run_outbox(&self, relay: &OutboxRelay) -> Result<(), WalError> {
+        relay
+            .run(|batch| async move { self.publish_envelopes(&batch).await.map(|_| ()) })
+            .await
+    }
+
+    /// Outbox destination name for this producer's topic
+    pub fn outbox_destination(&self) -> String {
+        format!("kafka:{}", self.topic)
+    }
+
+    /// Retry every event dead-lettered for this producer's topic
+    pub async fn redeliver_dead_letters(
+        &self,
+        dlq: &DeadLetterQueue,
+    ) -> Result<RedeliveryReport, KafkaError> {
+        dlq.redeliver_dead_letters(&self.dead_letter_destination(), |envelope| async move {
+            self.publish(&envelope.event).await.map(|_| ())
+        })
+        .await
+        .map_err(|e| KafkaError::DeadLetter(e.to_string()))
+    }
+}
+
+fn serialize(event: &Event) -> Result<Vec<u8>, KafkaError> {
+    serde_json::to_vec(event).map_err(|e| KafkaError::Serialization(e.to_string()))
+}
+
+async fn await_delivery(delivery: DeliveryFuture) -> Result<DeliveryReport, KafkaError> {
+    let (partition, offset) = delivery
+        .await
+        .map_err(|_| KafkaError::Publish("producer dropped delivery report".to_string()))?
+        .map_err(|(e, _)| KafkaError::Publish(e.to_string()))?;
+    Ok(DeliveryReport { partition, offset })
 }
 
 /// Kafka consumer for event subscription
 pub struct KafkaEventConsumer {
     /// Kafka topic for events
     topic: String,
-    /// Consumer group ID
-    group_id: String,
-    /// Broker addresses
-    brokers: String,
-    // consumer: StreamConsumer,  // Uncomment when kafka feature enabled
+    consumer: Arc<StreamConsumer>,
+    /// Key strategy the topic's producers are assumed to use
+    key_strategy: KeyStrategy,
+    /// How long a poll waits for the first event
+    poll_timeout: Duration,
+    /// Next offset to commit for each partition
+    offsets: Mutex<HashMap<i32, i64>>,
+    /// Decrypts protected fields of received events
+    field_protection: Option<FieldProtection>,
 }
 
 impl KafkaEventConsumer {
@@ -63,38 +475,276 @@ impl KafkaEventConsumer {
     /// * `brokers` - Comma-separated list of broker addresses
     /// * `topic` - Topic to consume events from
     /// * `group_id` - Consumer group ID
+    ///
+    /// Must be called from within a Tokio runtime.
     pub fn new(brokers: &str, topic: &str, group_id: &str) -> Result<Self, KafkaError> {
-        todo!("Implement Kafka consumer initialization")
+        Self::from_config(&KafkaConfig::new(brokers, topic).group_id(group_id))
+    }
+
+    /// Create a consumer from a configuration
+    ///
+    /// A group without committed offsets starts from the earliest event.
+    /// Must be called from within a Tokio runtime.
+    pub fn from_config(config: &KafkaConfig) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .set(
+                "group.id",
+                config.group_id.as_deref().unwrap_or(DEFAULT_GROUP_ID),
+            )
+            .set("enable.auto.commit", config.auto_commit.to_string())
+            .set("session.timeout.ms", config.session_timeout_ms.to_string())
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(|e| KafkaError::Configuration(e.to_string()))?;
+
+        Ok(Self {
+            topic: config.topic.clone(),
+            consumer: Arc::new(consumer),
+            key_strategy: config.key_strategy.clone(),
+            poll_timeout: Duration::from_secs(1),
+            offsets: Mutex::new(HashMap::new()),
+            field_protection: config.field_protection.clone(),
+        })
+    }
+
+    /// Set how long a poll waits for the first event
+    pub fn with_poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    /// Get the key strategy the topic's producers are assumed to use
+    ///
+    /// Ordering guarantees of [`poll_batch`](Self::poll_batch) depend on
+    /// it; see [`KeyStrategy::preserves_workflow_order`].
+    pub fn key_strategy(&self) -> &KeyStrategy {
+        &self.key_strategy
     }
 
     /// Subscribe to the topic
     pub async fn subscribe(&self) -> Result<(), KafkaError> {
-        todo!("Implement subscription")
+        self.consumer
+            .subscribe(&[&self.topic])
+            .map_err(|e| KafkaError::Subscribe(e.to_string()))
     }
 
     /// Poll for the next event
+    ///
+    /// Returns `None` if nothing arrives within the poll timeout.
     pub async fn poll(&self) -> Result<Option<Event>, KafkaError> {
-        todo!("Implement polling")
+        match tokio::time::timeout(self.poll_timeout, self.consumer.recv()).await {
+            Ok(message) => {
+                let message = message.map_err(|e| KafkaError::Poll(e.to_string()))?;
+                self.decode(&message).map(Some)
+            }
+            Err(_) => Ok(None),
+        }
     }
 
     /// Poll for the next batch of events
+    ///
+    /// Waits up to the poll timeout for the first event, then takes whatever
+    /// else is already fetched.
+    ///
+    /// A batch may interleave events from several partitions, so it is only
+    /// ordered per message key: with [`KeyStrategy::Workflow`] the events of
+    /// each workflow appear in the order they were published, but events of
+    /// different workflows may not. Consumers that need a global order must
+    /// sort by WAL sequence number themselves.
     pub async fn poll_batch(&self, max_messages: usize) -> Result<Vec<Event>, KafkaError> {
-        todo!("Implement batch polling")
+        let mut events = Vec::new();
+        while events.len() < max_messages {
+            let wait = if events.is_empty() {
+                self.poll_timeout
+            } else {
+                Duration::ZERO
+            };
+            let Ok(message) = tokio::time::timeout(wait, self.consumer.recv()).await else {
+                break;
+            };
+            let message = message.map_err(|e| KafkaError::Poll(e.to_string()))?;
+            events.push(self.decode(&message)?);
+        }
+        Ok(events)
+    }
+
+    /// Record a message as processed and deserialize it
+    ///
+    /// The offset is recorded first so a malformed event is skipped, not
+    /// redelivered after the next commit.
+    fn decode(&self, message: &BorrowedMessage<'_>) -> Result<Event, KafkaError> {
+        self.offsets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(message.partition(), message.offset() + 1);
+        let payload = message
+            .payload()
+            .ok_or_else(|| KafkaError::Serialization("empty message".to_string()))?;
+        let event: Event = serde_json::from_slice(payload)
+            .map_err(|e| KafkaError::Serialization(e.to_string()))?;
+        match &self.field_protection {
+            Some(protection) => protection
+                .reveal(&event)
+                .map_err(|e| KafkaError::Serialization(e.to_string())),
+            None => Ok(event),
+        }
     }
 
     /// Commit offsets for processed messages
+    ///
+    /// Commits synchronously, so events polled before this call will not be
+    /// redelivered to the group once it returns.
     pub async fn commit(&self) -> Result<(), KafkaError> {
-        todo!("Implement commit")
+        let offsets =
+            std::mem::take(&mut *self.offsets.lock().unwrap_or_else(PoisonError::into_inner));
+        if offsets.is_empty() {
+            return Ok(());
+        }
+
+        let mut list = TopicPartitionList::new();
+        for (partition, offset) in offsets {
+            list.add_partition_offset(&self.topic, partition, Offset::Offset(offset))
+                .map_err(|e| KafkaError::Commit(e.to_string()))?;
+        }
+        self.blocking(move |consumer| consumer.commit(&list, CommitMode::Sync))
+            .await?
+            .map_err(|e| KafkaError::Commit(e.to_string()))
     }
 
-    /// Seek to a specific offset
-    pub async fn seek(&self, offset: i64) -> Result<(), KafkaError> {
-        todo!("Implement seek")
+    /// Seek a partition to a specific offset
+    ///
+    /// The partition must currently be assigned to this consumer.
+    pub async fn seek(&self, partition: i32, offset: i64) -> Result<(), KafkaError> {
+        let topic = self.topic.clone();
+        self.blocking(move |consumer| {
+            consumer.seek(&topic, partition, Offset::Offset(offset), OPERATION_TIMEOUT)
+        })
+        .await?
+        .map_err(|e| KafkaError::Poll(e.to_string()))?;
+        self.forget_offsets();
+        Ok(())
+    }
+
+    /// Seek every assigned partition to the first event at or after `at`
+    ///
+    /// Partitions with no such event are moved to their end.
+    pub async fn seek_to_timestamp(&self, at: DateTime<Utc>) -> Result<(), KafkaError> {
+        let timestamp = at.timestamp_millis();
+        self.blocking(move |consumer| {
+            let offsets = consumer.offsets_for_timestamp(timestamp, OPERATION_TIMEOUT)?;
+            consumer.seek_partitions(offsets, OPERATION_TIMEOUT)
+        })
+        .await?
+        .map_err(|e| KafkaError::Poll(e.to_string()))?;
+        self.forget_offsets();
+        Ok(())
+    }
+
+    /// Re-consume every event published since `from`
+    ///
+    /// Unlike [`seek_to_timestamp`](Self::seek_to_timestamp) this does not
+    /// need an existing assignment: the consumer is switched to a manual
+    /// assignment of every partition of the topic, positioned at `from`.
+    /// Poll until events pass the end of the window of interest, then
+    /// [`subscribe`](Self::subscribe) again to rejoin the group.
+    pub async fn replay_from(&self, from: DateTime<Utc>) -> Result<(), KafkaError> {
+        let topic = self.topic.clone();
+        let timestamp = from.timestamp_millis();
+        self.blocking(move |consumer| {
+            let metadata = consumer.fetch_metadata(Some(&topic), OPERATION_TIMEOUT)?;
+            let mut list = TopicPartitionList::new();
+            for partition in metadata
+                .topics()
+                .iter()
+                .filter(|t| t.name() == topic)
+                .flat_map(|t| t.partitions())
+            {
+                list.add_partition_offset(&topic, partition.id(), Offset::Offset(timestamp))?;
+            }
+            let offsets = consumer.offsets_for_times(list, OPERATION_TIMEOUT)?;
+            consumer.unsubscribe();
+            consumer.assign(&offsets)
+        })
+        .await?
+        .map_err(|e| KafkaError::Subscribe(e.to_string()))?;
+        self.forget_offsets();
+        Ok(())
     }
 
-    /// Get current offset
-    pub fn current_offset(&self) -> Result<i64, KafkaError> {
-        todo!("Implement get offset")
+    /// Get the next offset to be committed for a partition
+    pub fn current_offset(&self, partition: i32) -> Option<i64> {
+        self.offsets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&partition)
+            .copied()
+    }
+
+    /// Drop uncommitted offsets after the consumer position moved
+    fn forget_offsets(&self) {
+        self.offsets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Run a blocking librdkafka call off the async runtime
+    async fn blocking<T, F>(&self, f: F) -> Result<KafkaResult<T>, KafkaError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&StreamConsumer) -> KafkaResult<T> + Send + 'static,
+    {
+        let consumer = Arc::clone(&self.consumer);
+        tokio::task::spawn_blocking(move || f(&consumer))
+            .await
+            .map_err(|e| KafkaError::Poll(e.to_string()))
+    }
+}
+
+/// How many replicas must acknowledge a write before it counts as delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KafkaAcks {
+    /// Fire and forget
+    None,
+    /// The partition leader only
+    Leader,
+    /// Every in-sync replica
+    #[default]
+    All,
+}
+
+impl KafkaAcks {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "0",
+            Self::Leader => "1",
+            Self::All => "all",
+        }
+    }
+}
+
+/// Producer-side compression codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KafkaCompression {
+    #[default]
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    /// Requires librdkafka built with zstd support
+    Zstd,
+}
+
+impl KafkaCompression {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        }
     }
 }
 
@@ -105,6 +755,13 @@ pub struct KafkaConfig {
     group_id: Option<String>,
     auto_commit: bool,
     session_timeout_ms: u32,
+    acks: KafkaAcks,
+    compression: KafkaCompression,
+    linger_ms: u32,
+    message_timeout_ms: u32,
+    key_strategy: KeyStrategy,
+    transactional_id: Option<String>,
+    field_protection: Option<FieldProtection>,
 }
 
 impl KafkaConfig {
@@ -116,6 +773,13 @@ impl KafkaConfig {
             group_id: None,
             auto_commit: false,
             session_timeout_ms: 30000,
+            acks: KafkaAcks::default(),
+            compression: KafkaCompression::default(),
+            linger_ms: 5,
+            message_timeout_ms: 30000,
+            key_strategy: KeyStrategy::default(),
+            transactional_id: None,
+            field_protection: None,
         }
     }
 
@@ -137,18 +801,95 @@ impl KafkaConfig {
         self
     }
 
+    /// Set the producer acknowledgement level
+    pub fn acks(mut self, acks: KafkaAcks) -> Self {
+        self.acks = acks;
+        self
+    }
+
+    /// Set the producer compression codec
+    pub fn compression(mut self, compression: KafkaCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set how long the producer waits to fill a batch
+    pub fn linger_ms(mut self, linger: u32) -> Self {
+        self.linger_ms = linger;
+        self
+    }
+
+    /// Set how long the producer tries to deliver an event before failing
+    pub fn message_timeout_ms(mut self, timeout: u32) -> Self {
+        self.message_timeout_ms = timeout;
+        self
+    }
+
+    /// Set how message keys, and so partitions, are chosen
+    ///
+    /// Consumers should be configured with the same strategy as the
+    /// producers of their topic.
+    pub fn key_strategy(mut self, strategy: KeyStrategy) -> Self {
+        self.key_strategy = strategy;
+        self
+    }
+
+    /// Publish in transactions, for exactly-once delivery
+    ///
+    /// `id` must be stable across restarts and unique among running
+    /// producers: the broker fences off older producers with the same ID
+    /// and aborts their open transactions. Requires [`KafkaAcks::All`].
+    pub fn transactional_id(mut self, id: &str) -> Self {
+        self.transactional_id = Some(id.to_string());
+        self
+    }
+
+    /// Protect sensitive event fields
+    ///
+    /// Producers encrypt or hash the configured fields before publishing;
+    /// consumers decrypt encrypted fields of the events they receive, so
+    /// only consumers given the key can read them.
+    pub fn field_protection(mut self, protection: FieldProtection) -> Self {
+        self.field_protection = Some(protection);
+        self
+    }
+
     /// Build a producer
     pub fn build_producer(self) -> Result<KafkaEventProducer, KafkaError> {
-        KafkaEventProducer::new(&self.brokers, &self.topic)
+        KafkaEventProducer::from_config(&self)
     }
 
     /// Build a consumer
     pub fn build_consumer(self) -> Result<KafkaEventConsumer, KafkaError> {
-        let group_id = self.group_id.unwrap_or_else(|| "swarmx-ui".to_string());
-        KafkaEventConsumer::new(&self.brokers, &self.topic, &group_id)
+        KafkaEventConsumer::from_config(&self)
+    }
+}
+
+/// Commit the open transaction, aborting it if the broker requires
+///
+/// Retriable failures are retried a few times before giving up.
+fn commit_transaction(producer: &FutureProducer) -> KafkaResult<()> {
+    let mut attempt = 1;
+    loop {
+        match producer.commit_transaction(OPERATION_TIMEOUT) {
+            Err(RdKafkaError::Transaction(e))
+                if e.is_retriable() && attempt < TRANSACTION_COMMIT_ATTEMPTS =>
+            {
+                attempt += 1;
+            }
+            Err(RdKafkaError::Transaction(e)) if e.txn_requires_abort() => {
+                producer.abort_transaction(OPERATION_TIMEOUT)?;
+                return Err(RdKafkaError::Transaction(e));
+            }
+            result => return result,
+        }
     }
 }
 
+fn transaction_error(e: RdKafkaError) -> KafkaError {
+    KafkaError::Transaction(e.to_string())
+}
+
 /// Kafka errors
 #[derive(Debug, thiserror::Error)]
 pub enum KafkaError {
@@ -173,6 +914,12 @@ pub enum KafkaError {
     #[error("Serialization error: {0}")]
     Serialization(String),
 
+    #[error("Dead-letter error: {0}")]
+    DeadLetter(String),
+
+    #[error("Transaction error: {0}")]
+    Transaction(String),
+
     #[error("Timeout")]
     Timeout,
 }
@@ -190,4 +937,92 @@ mod tests {
         assert_eq!(config.brokers, "localhost:9092");
         assert_eq!(config.topic, "events");
     }
+
+    #[test]
+    fn test_producer_from_config() {
+        let config = KafkaConfig::new("localhost:9092", "events")
+            .acks(KafkaAcks::Leader)
+            .compression(KafkaCompression::Lz4)
+            .linger_ms(20);
+        assert_eq!(config.acks.as_str(), "1");
+        assert_eq!(config.compression.as_str(), "lz4");
+
+        // Creating a producer does not contact the brokers
+        let producer = config.build_producer().unwrap();
+        assert_eq!(producer.topic, "events");
+
+        let event = Event::WorkflowStarted {
+            workflow_id: uuid::Uuid::new_v4(),
+            name: "test".to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+        assert_eq!(
+            producer.key_strategy().key(&event),
+            event.workflow_id().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_transactional_producer_config() {
+        let config = || KafkaConfig::new("localhost:9092", "usage").transactional_id("billing-1");
+        assert!(matches!(
+            config().acks(KafkaAcks::Leader).build_producer(),
+            Err(KafkaError::Configuration(_))
+        ));
+
+        // Transactions are initialized on first publish, not here
+        let producer = config().build_producer().unwrap();
+        assert!(producer.is_transactional());
+        assert!(!KafkaConfig::new("localhost:9092", "usage")
+            .build_producer()
+            .unwrap()
+            .is_transactional());
+    }
+
+    #[test]
+    fn test_key_strategies() {
+        let workflow_id = uuid::Uuid::new_v4();
+        let node_id = uuid::Uuid::new_v4();
+        let node_event = Event::NodeStarted {
+            workflow_id,
+            node_id,
+            timestamp: chrono::Utc::now(),
+        };
+        let workflow_event = Event::WorkflowStarted {
+            workflow_id,
+            name: "test".to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+        let system_event = Event::DataDeleted {
+            data_uuid: uuid::Uuid::new_v4(),
+            timestamp: chrono::Utc::now(),
+        };
+
+        let strategy = KeyStrategy::default();
+        assert!(strategy.preserves_workflow_order());
+        assert_eq!(strategy.key(&node_event), workflow_id.to_string());
+        assert_eq!(strategy.key(&system_event), SYSTEM_KEY);
+
+        assert_eq!(KeyStrategy::Node.key(&node_event), node_id.to_string());
+        assert_eq!(
+            KeyStrategy::Node.key(&workflow_event),
+            workflow_id.to_string()
+        );
+        assert_eq!(KeyStrategy::EventType.key(&node_event), "node_started");
+
+        let custom = KeyStrategy::custom(|event| format!("tenant-a/{}", event.event_type()));
+        assert!(!custom.preserves_workflow_order());
+        assert_eq!(custom.key(&system_event), "tenant-a/data_deleted");
+        assert_eq!(format!("{custom:?}"), "Custom(..)");
+    }
+
+    #[tokio::test]
+    async fn test_consumer_from_config() {
+        let consumer = KafkaConfig::new("localhost:9092", "events")
+            .group_id("replay")
+            .build_consumer()
+            .unwrap();
+        assert_eq!(consumer.topic, "events");
+        assert_eq!(consumer.current_offset(0), None);
+    }
 }
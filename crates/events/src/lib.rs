@@ -2,14 +2,91 @@
 //!
 //! This crate provides the event system for SwarmX-UI, including:
 //! - Event type definitions for workflow and node lifecycle
-//! - Write-Ahead Log (WAL) for crash recovery
-//! - Optional Kafka integration for distributed event streaming
+//! - Write-Ahead Log (WAL) for crash recovery, backed by SQLite or segment files
+//! - Time-based compaction with a cold archive of gzip-compressed JSONL files
+//! - Transparent zstd compression of large WAL payloads
+//! - Field-level encryption or hashing of sensitive payload fields
+//! - In-process event bus with push-based subscriptions
+//! - Coalescing of high-frequency node progress events
+//! - Non-blocking WAL writer for use from async code
+//! - Online backup and validated restore of the SQLite WAL
+//! - Pooled read-only connections that read concurrently with appends
+//! - Tail-follow streams that yield existing events, then new ones
+//! - Following the log from another process that shares the database file
+//! - Per-row checksums with integrity verification and repair
+//! - Dead-letter queue for events that could not be delivered downstream
+//! - Transactional outbox relaying committed events to downstream brokers
+//! - Outbound webhooks with signed payloads on selected events
+//! - Persistent revocation list for data-access tokens
+//! - Persistent catalog of every DataRef created
+//! - Tamper-evident audit trail of control-plane actions and data access
+//! - Data lineage queries over derivation events
+//! - Periodic metric samples and aggregation queries over the log
+//! - OpenTelemetry spans derived from workflow and node events, encoded for OTLP
+//! - Replay of a workflow's events into a timeline with divergence detection
+//! - Export to newline-delimited JSON, or Parquet with the `parquet` feature
+//! - Optional Kafka, NATS JetStream, and AMQP integration for distributed event streaming
+//! - Optional PostgreSQL WAL backend with LISTEN/NOTIFY subscriptions (`postgres` feature)
 
+pub mod archive;
+pub mod async_wal;
+pub mod audit;
+pub mod backup;
+pub mod bus;
+pub mod catalog;
+pub mod coalesce;
+pub mod compression;
+pub mod dead_letter;
+pub mod export;
+pub mod field_protection;
+pub mod integrity;
+pub mod lineage;
+pub mod metrics;
+pub mod otel;
+pub mod outbox;
+pub mod reader;
+pub mod remote;
+pub mod replay;
+pub mod revocation;
+pub mod segment;
+pub mod tail;
 pub mod types;
 pub mod wal;
+pub mod webhook;
+
+#[cfg(feature = "amqp")]
+pub mod amqp;
 
 #[cfg(feature = "kafka")]
 pub mod kafka;
 
+#[cfg(feature = "nats")]
+pub mod nats;
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+pub use archive::*;
+pub use async_wal::*;
+pub use audit::*;
+pub use backup::*;
+pub use bus::*;
+pub use catalog::*;
+pub use coalesce::*;
+pub use compression::*;
+pub use dead_letter::*;
+pub use export::*;
+pub use field_protection::*;
+pub use integrity::*;
+pub use lineage::*;
+pub use metrics::*;
+pub use otel::*;
+pub use outbox::*;
+pub use reader::*;
+pub use remote::*;
+pub use replay::*;
+pub use revocation::*;
+pub use segment::*;
 pub use types::*;
 pub use wal::*;
+pub use webhook::*;
@@ -0,0 +1,187 @@
+//! Data lineage queries
+//!
+//! Every [`Event::DataDerivedFrom`] appended to the SQLite WAL is also
+//! indexed in a `data_lineage` table, one row per parent. [`WriteAheadLog::lineage`]
+//! walks that table to answer which inputs, prompts, and nodes an artifact
+//! ultimately came from. The index is kept when the events themselves are
+//! compacted, so provenance outlives the detailed log.
+
+use std::collections::{HashSet, VecDeque};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::types::{Event, EventEnvelope};
+use crate::wal::{format_timestamp, parse_column, WalError, WriteAheadLog};
+
+/// One step in the ancestry of a data object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Derivation {
+    /// The derived data object
+    pub data_uuid: Uuid,
+    /// Data objects it was derived from
+    pub parent_uuids: Vec<Uuid>,
+    /// Workflow that ran the deriving node
+    pub workflow_id: Uuid,
+    /// Node that produced the data
+    pub node_id: Uuid,
+    /// Sequence of the `DataDerivedFrom` event
+    pub sequence: u64,
+    /// When the data was derived
+    pub timestamp: DateTime<Utc>,
+    /// Distance from the queried object; 0 for the object itself
+    pub depth: u32,
+}
+
+/// Index a lineage event, if `envelope` is one
+///
+/// Called inside the append transaction so the index never disagrees with
+/// the log.
+pub(crate) fn index_lineage(conn: &Connection, envelope: &EventEnvelope) -> Result<(), WalError> {
+    let Event::DataDerivedFrom {
+        data_uuid,
+        parent_uuids,
+        workflow_id,
+        node_id,
+        timestamp,
+    } = &envelope.event
+    else {
+        return Ok(());
+    };
+
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO data_lineage (data_uuid, parent_uuid, workflow_id, node_id, sequence, derived_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    // Source data with no parents still gets a row, with a NULL parent
+    let parents: Vec<Option<String>> = if parent_uuids.is_empty() {
+        vec![None]
+    } else {
+        parent_uuids.iter().map(|p| Some(p.to_string())).collect()
+    };
+    for parent in parents {
+        stmt.execute(params![
+            data_uuid.to_string(),
+            parent,
+            workflow_id.to_string(),
+            node_id.to_string(),
+            envelope.sequence,
+            format_timestamp(*timestamp),
+        ])?;
+    }
+    Ok(())
+}
+
+impl WriteAheadLog {
+    /// Walk the ancestry of a data object
+    ///
+    /// Returns every derivation reachable from `data_uuid` through its
+    /// parents, breadth first: the object's own derivation(s) at depth 0,
+    /// those of its inputs at depth 1, and so on. Each data object is
+    /// visited once, so shared ancestors and cycles are reported once.
+    /// Objects that were never derived (external inputs) end the walk.
+    pub fn lineage(&self, data_uuid: Uuid) -> Result<Vec<Derivation>, WalError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT sequence, parent_uuid, workflow_id, node_id, derived_at
+             FROM data_lineage
+             WHERE data_uuid = ?1
+             ORDER BY sequence, rowid",
+        )?;
+
+        let mut derivations: Vec<Derivation> = Vec::new();
+        let mut visited = HashSet::from([data_uuid]);
+        let mut queue = VecDeque::from([(data_uuid, 0)]);
+
+        while let Some((current, depth)) = queue.pop_front() {
+            let rows = stmt
+                .query_map(params![current.to_string()], |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let first = derivations.len();
+            for (sequence, parent, workflow_id, node_id, derived_at) in rows {
+                if derivations[first..].last().map(|d| d.sequence) != Some(sequence) {
+                    derivations.push(Derivation {
+                        data_uuid: current,
+                        parent_uuids: Vec::new(),
+                        workflow_id: parse_column(&workflow_id)?,
+                        node_id: parse_column(&node_id)?,
+                        sequence,
+                        timestamp: parse_column(&derived_at)?,
+                        depth,
+                    });
+                }
+                if let Some(parent) = parent {
+                    let parent: Uuid = parse_column(&parent)?;
+                    if let Some(derivation) = derivations.last_mut() {
+                        derivation.parent_uuids.push(parent);
+                    }
+                    if visited.insert(parent) {
+                        queue.push_back((parent, depth + 1));
+                    }
+                }
+            }
+        }
+
+        Ok(derivations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derived(data_uuid: Uuid, parent_uuids: Vec<Uuid>, node_id: Uuid) -> Event {
+        Event::DataDerivedFrom {
+            data_uuid,
+            parent_uuids,
+            workflow_id: Uuid::new_v4(),
+            node_id,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_lineage_walks_ancestry() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let (prompt, document, summary, report) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let (summarize, render) = (Uuid::new_v4(), Uuid::new_v4());
+
+        wal.append(derived(summary, vec![prompt, document], summarize))
+            .unwrap();
+        // The report reads the document directly as well as via the summary
+        wal.append(derived(report, vec![summary, document], render))
+            .unwrap();
+        wal.append(derived(Uuid::new_v4(), vec![report], Uuid::new_v4()))
+            .unwrap();
+
+        let lineage = wal.lineage(report).unwrap();
+        assert_eq!(lineage.len(), 2);
+        assert_eq!(lineage[0].data_uuid, report);
+        assert_eq!(lineage[0].node_id, render);
+        assert_eq!(lineage[0].parent_uuids, vec![summary, document]);
+        assert_eq!(lineage[0].depth, 0);
+        assert_eq!(lineage[1].data_uuid, summary);
+        assert_eq!(lineage[1].parent_uuids, vec![prompt, document]);
+        assert_eq!(lineage[1].depth, 1);
+
+        // Lineage survives compaction of the events that recorded it
+        wal.compact(wal.last_sequence() + 1).unwrap();
+        assert_eq!(wal.lineage(report).unwrap(), lineage);
+        assert!(wal.lineage(prompt).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,640 @@
+//! Operational metrics derived from the event log
+//!
+//! The API server periodically appends [`Event::MetricSample`] events with
+//! queue depth, running nodes, and per-server load, so those gauges are
+//! kept alongside the lifecycle events they summarize. The aggregation
+//! queries here answer the usual follow-up questions from the log itself:
+//! how long each node type takes, how often each server fails, and how
+//! busy the log is. [`WriteAheadLog::aggregate`] answers ad-hoc variants,
+//! e.g. which node type failed most often this week, grouped by any
+//! combination of node type, server, and day.
+//!
+//! All queries take an optional time range over the events' `created_at`,
+//! inclusive at both ends like [`EventFilter::time_range`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::types::{Event, EventEnvelope, EventFilter, EventKind};
+use crate::wal::{format_timestamp, parse_column, WalError, WriteAheadLog};
+
+/// Node type reported for nodes whose `NodeQueued` event is not in the log
+pub const UNKNOWN_NODE_TYPE: &str = "unknown";
+
+/// Completed-node durations for one node type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeDurationStats {
+    pub node_type: String,
+    /// Number of completed executions
+    pub count: u64,
+    pub avg_ms: f64,
+    pub min_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Execution outcomes on one server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerFailureRate {
+    pub server: String,
+    /// Nodes that completed, failed, or could not be dispatched
+    pub attempts: u64,
+    /// Nodes that failed or could not be dispatched
+    pub failures: u64,
+    /// `failures / attempts`
+    pub rate: f64,
+}
+
+/// Event throughput over a time range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRate {
+    pub events: u64,
+    /// Length of the measured range; unbounded ends are taken from the
+    /// oldest and newest event
+    pub seconds: f64,
+    pub per_second: f64,
+}
+
+/// Dimension to group node outcomes by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateGroup {
+    /// Node type from the node's `NodeQueued` event
+    NodeType,
+    /// Server the node ran on, or failed to be dispatched to
+    Server,
+    /// UTC day the outcome was appended
+    Day,
+}
+
+impl AggregateGroup {
+    fn column(self) -> &'static str {
+        match self {
+            AggregateGroup::NodeType => "node_type",
+            AggregateGroup::Server => "server",
+            AggregateGroup::Day => "day",
+        }
+    }
+}
+
+/// Query for [`WriteAheadLog::aggregate`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AggregateQuery {
+    /// Dimensions to group by, in output order; none yields a single row
+    #[serde(default)]
+    pub group_by: Vec<AggregateGroup>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl AggregateQuery {
+    /// Aggregate every node outcome into one row
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a grouping dimension
+    pub fn group_by(mut self, group: AggregateGroup) -> Self {
+        if !self.group_by.contains(&group) {
+            self.group_by.push(group);
+        }
+        self
+    }
+
+    /// Only count outcomes appended in a time range
+    pub fn time_range(mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Self {
+        self.from = from;
+        self.to = to;
+        self
+    }
+}
+
+/// Node outcomes in one group
+///
+/// Dimensions the query did not group by are `None`. So is `server` for
+/// nodes that finished without a recorded schedule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateRow {
+    pub node_type: Option<String>,
+    pub server: Option<String>,
+    pub day: Option<NaiveDate>,
+    /// Nodes that completed, failed, or could not be dispatched
+    pub count: u64,
+    /// Nodes that failed or could not be dispatched
+    pub failures: u64,
+    /// Mean duration of completed nodes
+    pub avg_duration_ms: Option<f64>,
+    /// 95th percentile (nearest rank) duration of completed nodes
+    pub p95_duration_ms: Option<u64>,
+}
+
+impl WriteAheadLog {
+    /// Count node outcomes and their durations, grouped by `query.group_by`
+    ///
+    /// Node types and servers are attributed like in
+    /// [`node_duration_by_type`](Self::node_duration_by_type) and
+    /// [`failure_rate_by_server`](Self::failure_rate_by_server). Outcomes
+    /// are collected into a temporary table, since payloads may be
+    /// compressed, and grouped and ranked there in SQL; the table is
+    /// discarded again by rolling back. Rows are ordered by the grouping
+    /// dimensions.
+    pub fn aggregate(&self, query: &AggregateQuery) -> Result<Vec<AggregateRow>, WalError> {
+        let envelopes = self.read_filtered(
+            &EventFilter::new()
+                .kinds([
+                    EventKind::NodeQueued,
+                    EventKind::NodeScheduled,
+                    EventKind::NodeDispatchFailed,
+                    EventKind::NodeCompleted,
+                    EventKind::NodeFailed,
+                ])
+                .time_range(None, query.to),
+        )?;
+
+        // Never committed: dropping the transaction discards the table
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch(
+            "CREATE TEMP TABLE aggregate_outcomes (
+                node_type TEXT NOT NULL,
+                server TEXT,
+                day TEXT NOT NULL,
+                failed INTEGER NOT NULL,
+                duration_ms INTEGER
+            );",
+        )?;
+        self.collect_outcomes(&envelopes, query)?;
+        self.query_outcomes(&query.group_by)
+    }
+
+    fn collect_outcomes(
+        &self,
+        envelopes: &[EventEnvelope],
+        query: &AggregateQuery,
+    ) -> Result<(), WalError> {
+        let mut insert = self.conn.prepare_cached(
+            "INSERT INTO temp.aggregate_outcomes (node_type, server, day, failed, duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut node_types: HashMap<Uuid, &str> = HashMap::new();
+        let mut servers: HashMap<Uuid, &str> = HashMap::new();
+        for envelope in envelopes {
+            let (node_id, server, failed, duration_ms) = match &envelope.event {
+                Event::NodeQueued {
+                    node_id, node_type, ..
+                } => {
+                    node_types.insert(*node_id, node_type);
+                    continue;
+                }
+                Event::NodeScheduled {
+                    node_id, server, ..
+                } => {
+                    servers.insert(*node_id, server);
+                    continue;
+                }
+                Event::NodeDispatchFailed {
+                    node_id, server, ..
+                } => (node_id, Some(server.as_str()), true, None),
+                Event::NodeCompleted {
+                    node_id,
+                    duration_ms,
+                    ..
+                } => (
+                    node_id,
+                    servers.get(node_id).copied(),
+                    false,
+                    Some(*duration_ms),
+                ),
+                Event::NodeFailed { node_id, .. } => {
+                    (node_id, servers.get(node_id).copied(), true, None)
+                }
+                _ => continue,
+            };
+            if query.from.is_some_and(|from| envelope.created_at < from) {
+                continue;
+            }
+            let node_type = node_types
+                .get(node_id)
+                .copied()
+                .unwrap_or(UNKNOWN_NODE_TYPE);
+            insert.execute(params![
+                node_type,
+                server,
+                envelope.created_at.date_naive().to_string(),
+                failed,
+                duration_ms,
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn query_outcomes(&self, group_by: &[AggregateGroup]) -> Result<Vec<AggregateRow>, WalError> {
+        let columns = group_by
+            .iter()
+            .map(|group| group.column())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (partition, grouping) = if group_by.is_empty() {
+            (String::new(), String::new())
+        } else {
+            (
+                format!("PARTITION BY {columns}"),
+                format!("GROUP BY {columns} ORDER BY {columns}"),
+            )
+        };
+        let selected = |group| {
+            if group_by.contains(&group) {
+                group.column()
+            } else {
+                "NULL"
+            }
+        };
+
+        // Completed outcomes are ranked first by duration, so the nearest
+        // rank p95 is the largest duration ranked within ceil(0.95 * n)
+        let sql = format!(
+            "WITH ranked AS (
+                SELECT *,
+                    ROW_NUMBER() OVER (
+                        {partition} ORDER BY duration_ms IS NULL, duration_ms
+                    ) AS rank,
+                    COUNT(duration_ms) OVER ({partition}) AS completed
+                FROM temp.aggregate_outcomes
+            )
+            SELECT {node_type}, {server}, {day}, COUNT(*), SUM(failed), AVG(duration_ms),
+                MAX(CASE WHEN duration_ms IS NOT NULL AND rank <= (95 * completed + 99) / 100
+                    THEN duration_ms END)
+            FROM ranked
+            {grouping}",
+            node_type = selected(AggregateGroup::NodeType),
+            server = selected(AggregateGroup::Server),
+            day = selected(AggregateGroup::Day),
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, u64>(3)?,
+                row.get::<_, Option<u64>>(4)?,
+                row.get::<_, Option<f64>>(5)?,
+                row.get::<_, Option<u64>>(6)?,
+            ))
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (node_type, server, day, count, failures, avg_duration_ms, p95_duration_ms) = row?;
+            // Without grouping, an empty table still yields one row
+            if count == 0 {
+                continue;
+            }
+            result.push(AggregateRow {
+                node_type,
+                server,
+                day: day.as_deref().map(parse_column).transpose()?,
+                count,
+                failures: failures.unwrap_or(0),
+                avg_duration_ms,
+                p95_duration_ms,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Average, minimum, and maximum duration of completed nodes by type
+    ///
+    /// Counts `NodeCompleted` events in the range. The node type comes from
+    /// the node's `NodeQueued` event, which may predate the range; nodes
+    /// queued before compaction are reported as [`UNKNOWN_NODE_TYPE`].
+    pub fn node_duration_by_type(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<NodeDurationStats>, WalError> {
+        let envelopes = self.read_filtered(
+            &EventFilter::new()
+                .kinds([EventKind::NodeQueued, EventKind::NodeCompleted])
+                .time_range(None, to),
+        )?;
+
+        let mut node_types: HashMap<Uuid, &str> = HashMap::new();
+        let mut stats: BTreeMap<&str, NodeDurationStats> = BTreeMap::new();
+        for envelope in &envelopes {
+            match &envelope.event {
+                Event::NodeQueued {
+                    node_id, node_type, ..
+                } => {
+                    node_types.insert(*node_id, node_type);
+                }
+                Event::NodeCompleted {
+                    node_id,
+                    duration_ms,
+                    ..
+                } if from.is_none_or(|from| envelope.created_at >= from) => {
+                    let node_type = node_types
+                        .get(node_id)
+                        .copied()
+                        .unwrap_or(UNKNOWN_NODE_TYPE);
+                    let entry = stats.entry(node_type).or_insert_with(|| NodeDurationStats {
+                        node_type: node_type.to_string(),
+                        count: 0,
+                        avg_ms: 0.0,
+                        min_ms: u64::MAX,
+                        max_ms: 0,
+                    });
+                    entry.count += 1;
+                    // Running mean, so large sums cannot overflow
+                    entry.avg_ms += (*duration_ms as f64 - entry.avg_ms) / entry.count as f64;
+                    entry.min_ms = entry.min_ms.min(*duration_ms);
+                    entry.max_ms = entry.max_ms.max(*duration_ms);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(stats.into_values().collect())
+    }
+
+    /// Share of node executions that failed, by server
+    ///
+    /// Each `NodeCompleted` and `NodeFailed` in the range is attributed to
+    /// the server the node was last scheduled on; `NodeDispatchFailed`
+    /// counts as a failure of the server it names. Outcomes of nodes with
+    /// no recorded schedule are skipped.
+    pub fn failure_rate_by_server(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ServerFailureRate>, WalError> {
+        let envelopes = self.read_filtered(
+            &EventFilter::new()
+                .kinds([
+                    EventKind::NodeScheduled,
+                    EventKind::NodeDispatchFailed,
+                    EventKind::NodeCompleted,
+                    EventKind::NodeFailed,
+                ])
+                .time_range(None, to),
+        )?;
+
+        let mut servers: HashMap<Uuid, &str> = HashMap::new();
+        // server -> (attempts, failures)
+        let mut counts: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for envelope in &envelopes {
+            let in_range = from.is_none_or(|from| envelope.created_at >= from);
+            let (server, failed) = match &envelope.event {
+                Event::NodeScheduled {
+                    node_id, server, ..
+                } => {
+                    servers.insert(*node_id, server);
+                    continue;
+                }
+                Event::NodeDispatchFailed { server, .. } => (Some(server.as_str()), true),
+                Event::NodeCompleted { node_id, .. } => (servers.get(node_id).copied(), false),
+                Event::NodeFailed { node_id, .. } => (servers.get(node_id).copied(), true),
+                _ => continue,
+            };
+            if let (true, Some(server)) = (in_range, server) {
+                let (attempts, failures) = counts.entry(server).or_default();
+                *attempts += 1;
+                *failures += u64::from(failed);
+            }
+        }
+
+        Ok(counts
+            .into_iter()
+            .map(|(server, (attempts, failures))| ServerFailureRate {
+                server: server.to_string(),
+                attempts,
+                failures,
+                rate: failures as f64 / attempts as f64,
+            })
+            .collect())
+    }
+
+    /// Number of events appended per second
+    ///
+    /// Ranges shorter than a second are measured as one second.
+    pub fn event_rate(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<EventRate, WalError> {
+        let mut sql =
+            String::from("SELECT COUNT(*), MIN(created_at), MAX(created_at) FROM events WHERE 1=1");
+        let mut args: Vec<Value> = Vec::new();
+        if let Some(from) = from {
+            sql.push_str(" AND created_at >= ?");
+            args.push(Value::Text(format_timestamp(from)));
+        }
+        if let Some(to) = to {
+            sql.push_str(" AND created_at <= ?");
+            args.push(Value::Text(format_timestamp(to)));
+        }
+
+        let (events, oldest, newest) =
+            self.conn.query_row(&sql, params_from_iter(args), |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?;
+        let start = match (from, oldest) {
+            (Some(from), _) => Some(from),
+            (None, Some(oldest)) => Some(parse_column(&oldest)?),
+            (None, None) => None,
+        };
+        let end = match (to, newest) {
+            (Some(to), _) => Some(to),
+            (None, Some(newest)) => Some(parse_column(&newest)?),
+            (None, None) => None,
+        };
+
+        let seconds = match (start, end) {
+            (Some(start), Some(end)) => (end - start).num_milliseconds().max(0) as f64 / 1000.0,
+            _ => 0.0,
+        };
+        Ok(EventRate {
+            events,
+            seconds,
+            per_second: events as f64 / seconds.max(1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_node_duration_and_failure_rate() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let workflow_id = Uuid::new_v4();
+        let now = Utc::now();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        for (node_id, node_type) in [(a, "llm.chat"), (b, "llm.chat"), (c, "http.fetch")] {
+            wal.append(Event::NodeQueued {
+                workflow_id,
+                node_id,
+                node_type: node_type.to_string(),
+                timestamp: now,
+            })
+            .unwrap();
+        }
+        for (node_id, server) in [(a, "gpu-1"), (b, "gpu-2"), (c, "gpu-1")] {
+            wal.append(Event::NodeScheduled {
+                workflow_id,
+                node_id,
+                server: server.to_string(),
+                timestamp: now,
+            })
+            .unwrap();
+        }
+        wal.append(Event::NodeDispatchFailed {
+            workflow_id,
+            node_id: b,
+            server: "gpu-2".to_string(),
+            error: "connection refused".to_string(),
+            timestamp: now,
+        })
+        .unwrap();
+        for (node_id, duration_ms) in [(a, 100), (b, 300)] {
+            wal.append(Event::NodeCompleted {
+                workflow_id,
+                node_id,
+                output_refs: vec![],
+                duration_ms,
+                timestamp: now,
+            })
+            .unwrap();
+        }
+        wal.append(Event::NodeFailed {
+            workflow_id,
+            node_id: c,
+            error: "timeout".to_string(),
+            retry_count: 0,
+            timestamp: now,
+        })
+        .unwrap();
+
+        let durations = wal.node_duration_by_type(None, None).unwrap();
+        assert_eq!(durations.len(), 1);
+        assert_eq!(durations[0].node_type, "llm.chat");
+        assert_eq!(durations[0].count, 2);
+        assert_eq!(durations[0].avg_ms, 200.0);
+        assert_eq!((durations[0].min_ms, durations[0].max_ms), (100, 300));
+
+        let rates = wal.failure_rate_by_server(None, None).unwrap();
+        assert_eq!(
+            rates
+                .iter()
+                .map(|r| (r.server.as_str(), r.attempts, r.failures))
+                .collect::<Vec<_>>(),
+            vec![("gpu-1", 2, 1), ("gpu-2", 2, 1)]
+        );
+        assert_eq!(rates[0].rate, 0.5);
+
+        // Nothing was appended in the future
+        let later = Utc::now() + Duration::hours(1);
+        assert!(wal
+            .node_duration_by_type(Some(later), None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_by_node_type_and_day() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let workflow_id = Uuid::new_v4();
+        let now = Utc::now();
+        let query = AggregateQuery::new().group_by(AggregateGroup::NodeType);
+        assert!(wal.aggregate(&query).unwrap().is_empty());
+
+        // 20 chat nodes taking 1..=20 ms and one failed fetch
+        for duration_ms in 1..=20 {
+            let node_id = Uuid::new_v4();
+            wal.append(Event::NodeQueued {
+                workflow_id,
+                node_id,
+                node_type: "llm.chat".to_string(),
+                timestamp: now,
+            })
+            .unwrap();
+            wal.append(Event::NodeCompleted {
+                workflow_id,
+                node_id,
+                output_refs: vec![],
+                duration_ms,
+                timestamp: now,
+            })
+            .unwrap();
+        }
+        let fetch = Uuid::new_v4();
+        wal.append(Event::NodeQueued {
+            workflow_id,
+            node_id: fetch,
+            node_type: "http.fetch".to_string(),
+            timestamp: now,
+        })
+        .unwrap();
+        wal.append(Event::NodeFailed {
+            workflow_id,
+            node_id: fetch,
+            error: "timeout".to_string(),
+            retry_count: 0,
+            timestamp: now,
+        })
+        .unwrap();
+
+        let rows = wal
+            .aggregate(&query.clone().group_by(AggregateGroup::Day))
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        let (fetch, chat) = (&rows[0], &rows[1]);
+        assert_eq!(fetch.node_type.as_deref(), Some("http.fetch"));
+        assert_eq!((fetch.count, fetch.failures), (1, 1));
+        assert_eq!(fetch.p95_duration_ms, None);
+        assert_eq!(chat.day, Some(Utc::now().date_naive()));
+        assert_eq!(chat.server, None);
+        assert_eq!((chat.count, chat.failures), (20, 0));
+        assert_eq!(chat.avg_duration_ms, Some(10.5));
+        assert_eq!(chat.p95_duration_ms, Some(19));
+
+        let total = wal.aggregate(&AggregateQuery::new()).unwrap();
+        assert_eq!((total[0].count, total[0].failures), (21, 1));
+        assert_eq!(total[0].node_type, None);
+    }
+
+    #[test]
+    fn test_event_rate() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        assert_eq!(wal.event_rate(None, None).unwrap().events, 0);
+
+        for _ in 0..3 {
+            wal.append(Event::MetricSample {
+                queue_depth: 2,
+                running_nodes: 1,
+                server_loads: BTreeMap::from([("gpu-1".to_string(), 0.5)]),
+                timestamp: Utc::now(),
+            })
+            .unwrap();
+        }
+
+        let now = Utc::now();
+        let rate = wal
+            .event_rate(Some(now - Duration::seconds(10)), Some(now))
+            .unwrap();
+        assert_eq!(rate.events, 3);
+        assert_eq!(rate.seconds, 10.0);
+        assert!((rate.per_second - 0.3).abs() < 1e-9);
+    }
+}
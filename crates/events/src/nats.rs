@@ -0,0 +1,411 @@
+//! NATS JetStream integration for distributed event streaming
+//!
+//! Lighter-weight alternative to Kafka with the same publish/subscribe
+//! surface. Events are published to `{prefix}.{workflow_id}.{event_type}`
+//! subjects on a JetStream stream, and consumed through a durable pull
+//! consumer with explicit acknowledgement, so a restarted consumer resumes
+//! where it left off.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_nats::jetstream::{
+    self,
+    consumer::{pull, AckPolicy, DeliverPolicy, PullConsumer},
+    stream,
+};
+use futures_util::StreamExt;
+use tokio::sync::Mutex;
+
+use crate::types::Event;
+
+/// Subject token used for events that do not belong to a workflow
+const SYSTEM_KEY: &str = "system";
+
+/// Build the subject an event is published on
+fn event_subject(prefix: &str, key: &str, event: &Event) -> String {
+    format!("{prefix}.{key}.{}", event.event_type())
+}
+
+/// Partition key for an event: its workflow ID, or `system`
+fn event_key(event: &Event) -> String {
+    event
+        .workflow_id()
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| SYSTEM_KEY.to_string())
+}
+
+/// JetStream producer for event publishing
+pub struct NatsEventProducer {
+    client: async_nats::Client,
+    jetstream: jetstream::Context,
+    /// Subject prefix for events
+    subject_prefix: String,
+}
+
+impl NatsEventProducer {
+    /// Connect and make sure the stream exists
+    ///
+    /// # Arguments
+    /// * `url` - NATS server address
+    /// * `stream` - JetStream stream name
+    /// * `subject_prefix` - Prefix for event subjects; the stream captures `{prefix}.>`
+    pub async fn connect(url: &str, stream: &str, subject_prefix: &str) -> Result<Self, NatsError> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| NatsError::Connection(e.to_string()))?;
+        let jetstream = jetstream::new(client.clone());
+        jetstream
+            .get_or_create_stream(stream::Config {
+                name: stream.to_string(),
+                subjects: vec![format!("{subject_prefix}.>")],
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| NatsError::Configuration(e.to_string()))?;
+
+        Ok(Self {
+            client,
+            jetstream,
+            subject_prefix: subject_prefix.to_string(),
+        })
+    }
+
+    /// Publish an event, keyed by its workflow ID
+    pub async fn publish(&self, event: &Event) -> Result<(), NatsError> {
+        self.publish_with_key(&event_key(event), event).await
+    }
+
+    /// Publish an event under a specific subject key
+    ///
+    /// Waits for the JetStream acknowledgement, so the event is stored once
+    /// this returns.
+    pub async fn publish_with_key(&self, key: &str, event: &Event) -> Result<(), NatsError> {
+        let payload =
+            serde_json::to_vec(event).map_err(|e| NatsError::Serialization(e.to_string()))?;
+        self.jetstream
+            .publish(
+                event_subject(&self.subject_prefix, key, event),
+                payload.into(),
+            )
+            .await
+            .map_err(|e| NatsError::Publish(e.to_string()))?
+            .await
+            .map_err(|e| NatsError::Publish(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Publish multiple events as a batch
+    ///
+    /// All events are sent before any acknowledgement is awaited.
+    pub async fn publish_batch(&self, events: &[Event]) -> Result<(), NatsError> {
+        let mut acks = Vec::with_capacity(events.len());
+        for event in events {
+            let payload =
+                serde_json::to_vec(event).map_err(|e| NatsError::Serialization(e.to_string()))?;
+            let ack = self
+                .jetstream
+                .publish(
+                    event_subject(&self.subject_prefix, &event_key(event), event),
+                    payload.into(),
+                )
+                .await
+                .map_err(|e| NatsError::Publish(e.to_string()))?;
+            acks.push(ack);
+        }
+        for ack in acks {
+            ack.await.map_err(|e| NatsError::Publish(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Flush pending messages
+    pub async fn flush(&self) -> Result<(), NatsError> {
+        self.client
+            .flush()
+            .await
+            .map_err(|e| NatsError::Publish(e.to_string()))
+    }
+}
+
+/// JetStream consumer for event subscription
+pub struct NatsEventConsumer {
+    jetstream: jetstream::Context,
+    /// JetStream stream name
+    stream: String,
+    /// Durable consumer name
+    durable_name: String,
+    /// Subject filter, e.g. `swarmx.events.>`
+    filter_subject: String,
+    /// How long a poll waits for messages
+    poll_timeout: Duration,
+    consumer: Mutex<Option<PullConsumer>>,
+    /// Delivered messages not yet acknowledged
+    pending: Mutex<Vec<jetstream::Message>>,
+    /// Stream sequence of the last delivered message
+    offset: AtomicU64,
+}
+
+impl NatsEventConsumer {
+    /// Connect to the server
+    ///
+    /// # Arguments
+    /// * `url` - NATS server address
+    /// * `stream` - JetStream stream name
+    /// * `durable_name` - Durable consumer name
+    pub async fn connect(url: &str, stream: &str, durable_name: &str) -> Result<Self, NatsError> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| NatsError::Connection(e.to_string()))?;
+        Ok(Self {
+            jetstream: jetstream::new(client),
+            stream: stream.to_string(),
+            durable_name: durable_name.to_string(),
+            filter_subject: String::new(),
+            poll_timeout: Duration::from_secs(1),
+            consumer: Mutex::new(None),
+            pending: Mutex::new(Vec::new()),
+            offset: AtomicU64::new(0),
+        })
+    }
+
+    /// Only receive events on subjects matching `filter`
+    pub fn with_filter_subject(mut self, filter: &str) -> Self {
+        self.filter_subject = filter.to_string();
+        self
+    }
+
+    /// Set how long a poll waits for messages
+    pub fn with_poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    /// Bind to the durable consumer, creating it if needed
+    ///
+    /// An existing durable consumer resumes from its last acknowledged event.
+    pub async fn subscribe(&self) -> Result<(), NatsError> {
+        let consumer = self.create_consumer(DeliverPolicy::All).await?;
+        *self.consumer.lock().await = Some(consumer);
+        Ok(())
+    }
+
+    async fn create_consumer(
+        &self,
+        deliver_policy: DeliverPolicy,
+    ) -> Result<PullConsumer, NatsError> {
+        let stream = self
+            .jetstream
+            .get_stream(&self.stream)
+            .await
+            .map_err(|e| NatsError::Subscribe(e.to_string()))?;
+        stream
+            .get_or_create_consumer(
+                &self.durable_name,
+                pull::Config {
+                    durable_name: Some(self.durable_name.clone()),
+                    ack_policy: AckPolicy::Explicit,
+                    deliver_policy,
+                    filter_subject: self.filter_subject.clone(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| NatsError::Subscribe(e.to_string()))
+    }
+
+    /// Poll for the next event
+    pub async fn poll(&self) -> Result<Option<Event>, NatsError> {
+        Ok(self.poll_batch(1).await?.pop())
+    }
+
+    /// Poll for the next batch of events
+    ///
+    /// Waits up to the poll timeout. Delivered events stay unacknowledged
+    /// until [`commit`](Self::commit) is called.
+    pub async fn poll_batch(&self, max_messages: usize) -> Result<Vec<Event>, NatsError> {
+        let consumer = self
+            .consumer
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| NatsError::Subscribe("not subscribed".to_string()))?;
+
+        let mut batch = consumer
+            .batch()
+            .max_messages(max_messages)
+            .expires(self.poll_timeout)
+            .messages()
+            .await
+            .map_err(|e| NatsError::Poll(e.to_string()))?;
+
+        let mut events = Vec::new();
+        let mut pending = self.pending.lock().await;
+        while let Some(message) = batch.next().await {
+            let message = message.map_err(|e| NatsError::Poll(e.to_string()))?;
+            let event: Event = serde_json::from_slice(&message.payload)
+                .map_err(|e| NatsError::Serialization(e.to_string()))?;
+            if let Ok(info) = message.info() {
+                self.offset.store(info.stream_sequence, Ordering::SeqCst);
+            }
+            events.push(event);
+            pending.push(message);
+        }
+        Ok(events)
+    }
+
+    /// Acknowledge every event delivered so far
+    pub async fn commit(&self) -> Result<(), NatsError> {
+        let mut pending = self.pending.lock().await;
+        for message in pending.drain(..) {
+            message
+                .ack()
+                .await
+                .map_err(|e| NatsError::Commit(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Restart delivery at a stream sequence
+    ///
+    /// Recreates the durable consumer, discarding its acknowledgement state
+    /// and any unacknowledged deliveries.
+    pub async fn seek(&self, sequence: u64) -> Result<(), NatsError> {
+        let stream = self
+            .jetstream
+            .get_stream(&self.stream)
+            .await
+            .map_err(|e| NatsError::Subscribe(e.to_string()))?;
+        // The consumer may not exist yet; any other failure surfaces on create.
+        let _ = stream.delete_consumer(&self.durable_name).await;
+        self.pending.lock().await.clear();
+
+        let consumer = self
+            .create_consumer(DeliverPolicy::ByStartSequence {
+                start_sequence: sequence,
+            })
+            .await?;
+        *self.consumer.lock().await = Some(consumer);
+        self.offset
+            .store(sequence.saturating_sub(1), Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Get the stream sequence of the last delivered event
+    pub fn current_offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+}
+
+/// NATS configuration builder
+pub struct NatsConfig {
+    url: String,
+    stream: String,
+    subject_prefix: String,
+    durable_name: Option<String>,
+    poll_timeout: Duration,
+}
+
+impl NatsConfig {
+    /// Create a new NATS configuration
+    pub fn new(url: &str, stream: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            stream: stream.to_string(),
+            subject_prefix: "swarmx.events".to_string(),
+            durable_name: None,
+            poll_timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the subject prefix
+    pub fn subject_prefix(mut self, prefix: &str) -> Self {
+        self.subject_prefix = prefix.to_string();
+        self
+    }
+
+    /// Set the durable consumer name
+    pub fn durable_name(mut self, name: &str) -> Self {
+        self.durable_name = Some(name.to_string());
+        self
+    }
+
+    /// Set how long a poll waits for messages
+    pub fn poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    /// Build a producer
+    pub async fn build_producer(self) -> Result<NatsEventProducer, NatsError> {
+        NatsEventProducer::connect(&self.url, &self.stream, &self.subject_prefix).await
+    }
+
+    /// Build a consumer for every event under the subject prefix
+    pub async fn build_consumer(self) -> Result<NatsEventConsumer, NatsError> {
+        let durable_name = self.durable_name.unwrap_or_else(|| "swarmx-ui".to_string());
+        Ok(
+            NatsEventConsumer::connect(&self.url, &self.stream, &durable_name)
+                .await?
+                .with_filter_subject(&format!("{}.>", self.subject_prefix))
+                .with_poll_timeout(self.poll_timeout),
+        )
+    }
+}
+
+/// NATS errors
+#[derive(Debug, thiserror::Error)]
+pub enum NatsError {
+    #[error("Connection error: {0}")]
+    Connection(String),
+
+    #[error("Publish error: {0}")]
+    Publish(String),
+
+    #[error("Subscribe error: {0}")]
+    Subscribe(String),
+
+    #[error("Poll error: {0}")]
+    Poll(String),
+
+    #[error("Commit error: {0}")]
+    Commit(String),
+
+    #[error("Configuration error: {0}")]
+    Configuration(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_event_subject() {
+        let workflow_id = Uuid::new_v4();
+        let event = Event::WorkflowStarted {
+            workflow_id,
+            name: "test".to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+
+        assert_eq!(
+            event_subject("swarmx.events", &event_key(&event), &event),
+            format!("swarmx.events.{workflow_id}.workflow_started")
+        );
+    }
+
+    #[test]
+    fn test_nats_config() {
+        let config = NatsConfig::new("localhost:4222", "EVENTS")
+            .subject_prefix("test.events")
+            .durable_name("test-consumer");
+
+        assert_eq!(config.stream, "EVENTS");
+        assert_eq!(config.subject_prefix, "test.events");
+        assert_eq!(config.durable_name.as_deref(), Some("test-consumer"));
+    }
+}
@@ -0,0 +1,634 @@
+//! OpenTelemetry spans derived from the event log
+//!
+//! [`SpanCollector`] folds the event stream into spans, so executions show
+//! up in Jaeger or Tempo next to the services that triggered them:
+//!
+//! - one `workflow` span per execution, from start to terminal event
+//! - one `node` span per node, child of the workflow span; it stays open
+//!   across retries and ends when the node completes or the workflow ends
+//! - `queue`, `schedule`, and `run` phase spans under each node, one set per
+//!   attempt
+//!
+//! Failures set the span status to error. Scheduling decisions, dispatch
+//! failures, and retries are recorded as span events.
+//!
+//! A workflow joins the trace its `WorkflowStarted` event was recorded
+//! under; otherwise the trace ID is derived from the workflow ID. Span IDs
+//! are derived from workflow and node IDs, so re-exporting the same events
+//! yields the same spans. [`otlp_json`] encodes finished spans as an OTLP
+//! `ExportTraceServiceRequest` for the OTLP/HTTP JSON endpoint
+//! (`/v1/traces`).
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::types::{Event, EventEnvelope};
+
+/// Instrumentation scope name reported with exported spans
+const SCOPE_NAME: &str = "swarmx-events";
+
+/// OTLP `SPAN_KIND_INTERNAL`
+const SPAN_KIND_INTERNAL: u8 = 1;
+
+/// Attribute value of a span or span event
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<u64> for AttributeValue {
+    fn from(value: u64) -> Self {
+        AttributeValue::Int(value.min(i64::MAX as u64) as i64)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+/// Key-value attributes, in insertion order
+pub type Attributes = Vec<(String, AttributeValue)>;
+
+/// Timestamped annotation on a span
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanEvent {
+    pub name: String,
+    pub time: DateTime<Utc>,
+    pub attributes: Attributes,
+}
+
+/// Outcome of a span
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum SpanStatus {
+    #[default]
+    Unset,
+    Ok,
+    Error(String),
+}
+
+/// A finished span
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    /// 32 hex digits
+    pub trace_id: String,
+    /// 16 hex digits
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub attributes: Attributes,
+    pub events: Vec<SpanEvent>,
+    pub status: SpanStatus,
+}
+
+/// A span that has started but not ended
+struct OpenSpan {
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start: DateTime<Utc>,
+    attributes: Attributes,
+    events: Vec<SpanEvent>,
+    status: SpanStatus,
+}
+
+impl OpenSpan {
+    fn new(
+        span_id: String,
+        parent_span_id: Option<String>,
+        name: &str,
+        start: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            span_id,
+            parent_span_id,
+            name: name.to_string(),
+            start,
+            attributes: Vec::new(),
+            events: Vec::new(),
+            status: SpanStatus::Unset,
+        }
+    }
+
+    fn attribute(&mut self, key: &str, value: impl Into<AttributeValue>) {
+        self.attributes.retain(|(k, _)| k != key);
+        self.attributes.push((key.to_string(), value.into()));
+    }
+
+    fn event(&mut self, name: &str, time: DateTime<Utc>, attributes: Attributes) {
+        self.events.push(SpanEvent {
+            name: name.to_string(),
+            time,
+            attributes,
+        });
+    }
+
+    fn end(self, trace_id: &str, end: DateTime<Utc>) -> Span {
+        Span {
+            trace_id: trace_id.to_string(),
+            span_id: self.span_id,
+            parent_span_id: self.parent_span_id,
+            name: self.name,
+            start: self.start,
+            end: end.max(self.start),
+            attributes: self.attributes,
+            events: self.events,
+            status: self.status,
+        }
+    }
+}
+
+/// Open spans of one node
+struct NodeSpans {
+    span: OpenSpan,
+    /// Current phase span, if any
+    phase: Option<OpenSpan>,
+    /// Attempt number, starting at 1
+    attempt: u64,
+    /// Phase spans opened so far, used to derive phase span IDs
+    phases_opened: u64,
+}
+
+/// Open spans of one workflow
+struct WorkflowSpans {
+    trace_id: String,
+    span: OpenSpan,
+    nodes: HashMap<Uuid, NodeSpans>,
+}
+
+/// Converts events into spans as they are committed
+///
+/// Spans are returned once they end. Spans of workflows whose terminal
+/// event is never seen stay open.
+#[derive(Default)]
+pub struct SpanCollector {
+    workflows: HashMap<Uuid, WorkflowSpans>,
+}
+
+impl SpanCollector {
+    /// Create an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of workflows with open spans
+    pub fn open_workflows(&self) -> usize {
+        self.workflows.len()
+    }
+
+    /// Record an event, returning the spans it ended
+    ///
+    /// Events must be recorded in sequence order.
+    pub fn record(&mut self, envelope: &EventEnvelope) -> Vec<Span> {
+        let event = &envelope.event;
+        let Some(workflow_id) = event.workflow_id() else {
+            return Vec::new();
+        };
+        let time = event.timestamp();
+        let workflow = self
+            .workflows
+            .entry(workflow_id)
+            .or_insert_with(|| WorkflowSpans::new(workflow_id, envelope, time));
+
+        let mut ended = Vec::new();
+        if let Some(node_id) = event.node_id() {
+            workflow.record_node_event(node_id, event, time, &mut ended);
+            return ended;
+        }
+
+        match event {
+            Event::WorkflowStarted { name, .. } => {
+                workflow
+                    .span
+                    .attribute("swarmx.workflow.name", name.as_str());
+                return ended;
+            }
+            Event::WorkflowCompleted { .. } => workflow.span.status = SpanStatus::Ok,
+            Event::WorkflowFailed { error, .. } => {
+                workflow.span.status = SpanStatus::Error(error.clone());
+            }
+            Event::WorkflowCancelled { reason, .. } => {
+                let reason = reason.as_deref().unwrap_or("cancelled");
+                workflow.span.status = SpanStatus::Error(format!("cancelled: {reason}"));
+            }
+            _ => return ended,
+        }
+
+        // Terminal event: end every span of the workflow
+        if let Some(workflow) = self.workflows.remove(&workflow_id) {
+            workflow.end(time, &mut ended);
+        }
+        ended
+    }
+}
+
+impl WorkflowSpans {
+    fn new(workflow_id: Uuid, envelope: &EventEnvelope, start: DateTime<Utc>) -> Self {
+        // Join the caller's trace if the execution was started under one
+        let (trace_id, parent_span_id) = match (&envelope.event, &envelope.trace_id) {
+            (Event::WorkflowStarted { .. }, Some(trace_id)) => {
+                (trace_id.clone(), envelope.parent_span_id.clone())
+            }
+            _ => (workflow_id.simple().to_string(), None),
+        };
+        let mut span = OpenSpan::new(
+            derive_span_id(&[b"workflow", workflow_id.as_bytes()]),
+            parent_span_id,
+            "workflow",
+            start,
+        );
+        span.attribute("swarmx.workflow_id", workflow_id.to_string());
+        Self {
+            trace_id,
+            span,
+            nodes: HashMap::new(),
+        }
+    }
+
+    fn record_node_event(
+        &mut self,
+        node_id: Uuid,
+        event: &Event,
+        time: DateTime<Utc>,
+        ended: &mut Vec<Span>,
+    ) {
+        let parent = self.span.span_id.clone();
+        let node = self.nodes.entry(node_id).or_insert_with(|| {
+            let mut span = OpenSpan::new(
+                derive_span_id(&[b"node", node_id.as_bytes()]),
+                Some(parent),
+                "node",
+                time,
+            );
+            span.attribute("swarmx.node_id", node_id.to_string());
+            NodeSpans {
+                span,
+                phase: None,
+                attempt: 1,
+                phases_opened: 0,
+            }
+        });
+
+        match event {
+            Event::NodeQueued { node_type, .. } => {
+                node.span.attribute("swarmx.node.type", node_type.as_str());
+                node.start_phase(node_id, "queue", time, &self.trace_id, ended);
+            }
+            Event::SchedulingDecisionMade {
+                strategy,
+                chosen_server,
+                candidates,
+                reason,
+                ..
+            } => node.span.event(
+                "scheduling_decision",
+                time,
+                vec![
+                    ("swarmx.strategy".to_string(), strategy.as_str().into()),
+                    ("swarmx.server".to_string(), chosen_server.as_str().into()),
+                    (
+                        "swarmx.candidates".to_string(),
+                        (candidates.len() as u64).into(),
+                    ),
+                    ("swarmx.reason".to_string(), reason.as_str().into()),
+                ],
+            ),
+            Event::NodeScheduled { server, .. } => {
+                node.span.attribute("swarmx.server", server.as_str());
+                node.start_phase(node_id, "schedule", time, &self.trace_id, ended);
+            }
+            Event::NodeDispatchFailed { server, error, .. } => {
+                node.span.event(
+                    "dispatch_failed",
+                    time,
+                    vec![
+                        ("swarmx.server".to_string(), server.as_str().into()),
+                        ("exception.message".to_string(), error.as_str().into()),
+                    ],
+                );
+                node.end_phase(
+                    SpanStatus::Error(error.clone()),
+                    time,
+                    &self.trace_id,
+                    ended,
+                );
+            }
+            Event::NodeStarted { .. } => {
+                node.start_phase(node_id, "run", time, &self.trace_id, ended);
+            }
+            Event::NodeCompleted { .. } => {
+                node.end_phase(SpanStatus::Ok, time, &self.trace_id, ended);
+                node.span.status = SpanStatus::Ok;
+                if let Some(node) = self.nodes.remove(&node_id) {
+                    ended.push(node.span.end(&self.trace_id, time));
+                }
+            }
+            Event::NodeFailed { error, .. } => {
+                node.span.event(
+                    "exception",
+                    time,
+                    vec![("exception.message".to_string(), error.as_str().into())],
+                );
+                node.end_phase(
+                    SpanStatus::Error(error.clone()),
+                    time,
+                    &self.trace_id,
+                    ended,
+                );
+                node.span.status = SpanStatus::Error(error.clone());
+            }
+            Event::NodeCancelled { reason, .. } => {
+                let reason = reason.clone().unwrap_or_else(|| "cancelled".to_string());
+                node.end_phase(
+                    SpanStatus::Error(reason.clone()),
+                    time,
+                    &self.trace_id,
+                    ended,
+                );
+                node.span.status = SpanStatus::Error(reason);
+                if let Some(node) = self.nodes.remove(&node_id) {
+                    ended.push(node.span.end(&self.trace_id, time));
+                }
+            }
+            Event::NodeRetrying {
+                retry_count,
+                delay_ms,
+                ..
+            } => {
+                node.attempt += 1;
+                node.span.event(
+                    "retry",
+                    time,
+                    vec![
+                        (
+                            "swarmx.retry_count".to_string(),
+                            u64::from(*retry_count).into(),
+                        ),
+                        ("swarmx.delay_ms".to_string(), (*delay_ms).into()),
+                    ],
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn end(mut self, time: DateTime<Utc>, ended: &mut Vec<Span>) {
+        for (_, mut node) in self.nodes.drain() {
+            node.end_phase(SpanStatus::Unset, time, &self.trace_id, ended);
+            ended.push(node.span.end(&self.trace_id, time));
+        }
+        ended.push(self.span.end(&self.trace_id, time));
+    }
+}
+
+impl NodeSpans {
+    fn start_phase(
+        &mut self,
+        node_id: Uuid,
+        name: &str,
+        time: DateTime<Utc>,
+        trace_id: &str,
+        ended: &mut Vec<Span>,
+    ) {
+        self.end_phase(SpanStatus::Unset, time, trace_id, ended);
+        self.phases_opened += 1;
+        let mut phase = OpenSpan::new(
+            derive_span_id(&[
+                b"phase",
+                node_id.as_bytes(),
+                &self.phases_opened.to_be_bytes(),
+            ]),
+            Some(self.span.span_id.clone()),
+            name,
+            time,
+        );
+        phase.attribute("swarmx.attempt", self.attempt);
+        self.phase = Some(phase);
+    }
+
+    fn end_phase(
+        &mut self,
+        status: SpanStatus,
+        time: DateTime<Utc>,
+        trace_id: &str,
+        ended: &mut Vec<Span>,
+    ) {
+        if let Some(mut phase) = self.phase.take() {
+            phase.status = status;
+            ended.push(phase.end(trace_id, time));
+        }
+    }
+}
+
+/// Derive a stable, non-zero 64-bit span ID
+fn derive_span_id(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let digest = hasher.finalize();
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&digest[..8]);
+    // An all-zero span ID is invalid
+    if id == [0; 8] {
+        id[7] = 1;
+    }
+    id.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Encode spans as an OTLP/HTTP JSON `ExportTraceServiceRequest`
+pub fn otlp_json(service_name: &str, spans: &[Span]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": encode_attributes(&[(
+                    "service.name".to_string(),
+                    service_name.into(),
+                )]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": SCOPE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans.iter().map(encode_span).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+fn encode_span(span: &Span) -> Value {
+    let status = match &span.status {
+        SpanStatus::Unset => json!({}),
+        SpanStatus::Ok => json!({ "code": 1 }),
+        SpanStatus::Error(message) => json!({ "code": 2, "message": message }),
+    };
+    let mut encoded = json!({
+        "traceId": span.trace_id,
+        "spanId": span.span_id,
+        "name": span.name,
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": unix_nanos(span.start),
+        "endTimeUnixNano": unix_nanos(span.end),
+        "attributes": encode_attributes(&span.attributes),
+        "events": span.events.iter().map(|event| json!({
+            "name": event.name,
+            "timeUnixNano": unix_nanos(event.time),
+            "attributes": encode_attributes(&event.attributes),
+        })).collect::<Vec<_>>(),
+        "status": status,
+    });
+    if let Some(parent) = &span.parent_span_id {
+        encoded["parentSpanId"] = json!(parent);
+    }
+    encoded
+}
+
+fn encode_attributes(attributes: &[(String, AttributeValue)]) -> Vec<Value> {
+    attributes
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                AttributeValue::String(s) => json!({ "stringValue": s }),
+                // 64-bit integers are strings in OTLP JSON
+                AttributeValue::Int(i) => json!({ "intValue": i.to_string() }),
+                AttributeValue::Bool(b) => json!({ "boolValue": b }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect()
+}
+
+/// Nanoseconds since the Unix epoch, as an OTLP JSON string
+fn unix_nanos(time: DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_spans_for_retried_node() {
+        let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let t0 = Utc::now();
+        let at = |ms| t0 + Duration::milliseconds(ms);
+        let events = vec![
+            Event::WorkflowStarted {
+                workflow_id,
+                name: "nightly".to_string(),
+                timestamp: at(0),
+            },
+            Event::NodeQueued {
+                workflow_id,
+                node_id,
+                node_type: "llm.chat".to_string(),
+                timestamp: at(1),
+            },
+            Event::NodeScheduled {
+                workflow_id,
+                node_id,
+                server: "gpu-1".to_string(),
+                timestamp: at(5),
+            },
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp: at(10),
+            },
+            Event::NodeFailed {
+                workflow_id,
+                node_id,
+                error: "OOM".to_string(),
+                retry_count: 0,
+                timestamp: at(20),
+            },
+            Event::NodeRetrying {
+                workflow_id,
+                node_id,
+                retry_count: 1,
+                delay_ms: 100,
+                timestamp: at(21),
+            },
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp: at(130),
+            },
+            Event::NodeCompleted {
+                workflow_id,
+                node_id,
+                output_refs: vec![],
+                duration_ms: 20,
+                timestamp: at(150),
+            },
+            Event::WorkflowCompleted {
+                workflow_id,
+                duration_ms: 160,
+                timestamp: at(160),
+            },
+        ];
+
+        let mut collector = SpanCollector::new();
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let mut spans = Vec::new();
+        for (i, event) in events.into_iter().enumerate() {
+            let mut envelope = EventEnvelope::new(i as u64 + 1, event);
+            envelope.trace_id = Some(trace_id.to_string());
+            spans.extend(collector.record(&envelope));
+        }
+        assert_eq!(collector.open_workflows(), 0);
+
+        let names: Vec<&str> = spans.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["queue", "schedule", "run", "run", "node", "workflow"]
+        );
+        assert!(spans.iter().all(|s| s.trace_id == trace_id));
+
+        let workflow = &spans[5];
+        let node = &spans[4];
+        assert_eq!(workflow.parent_span_id, None);
+        assert_eq!(node.parent_span_id.as_ref(), Some(&workflow.span_id));
+        assert_eq!(spans[2].parent_span_id.as_ref(), Some(&node.span_id));
+        assert_eq!(spans[2].status, SpanStatus::Error("OOM".to_string()));
+        assert_eq!(
+            spans[3].attributes,
+            vec![("swarmx.attempt".to_string(), AttributeValue::Int(2))]
+        );
+        assert_eq!(node.status, SpanStatus::Ok);
+        assert_eq!(
+            node.events
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["exception", "retry"]
+        );
+        assert_eq!((node.start, node.end), (at(1), at(150)));
+
+        let body = otlp_json("swarmx-ui", &spans);
+        let encoded = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(encoded.as_array().unwrap().len(), 6);
+        assert_eq!(encoded[4]["parentSpanId"], json!(workflow.span_id));
+        assert_eq!(encoded[2]["status"]["code"], json!(2));
+    }
+}
@@ -0,0 +1,293 @@
+//! Transactional outbox relay from the WAL to external destinations
+//!
+//! Publishing to a broker straight from a request handler loses the event
+//! if the broker is down or the process crashes before delivery. With the
+//! outbox pattern every event is first committed to the local WAL, which is
+//! the source of truth; an [`OutboxRelay`] then forwards committed events
+//! to a destination in sequence order and records how far it got in the
+//! `outbox_cursors` table.
+//!
+//! The cursor only advances after the destination acknowledged a batch, so
+//! delivery is at-least-once: after a crash or a failed batch the relay
+//! resumes from the cursor and may resend events that were already
+//! delivered. Consumers should deduplicate by WAL sequence number.
+//! Compaction never removes events a destination has not received yet.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use chrono::Utc;
+use rusqlite::{params, OptionalExtension};
+
+use crate::dead_letter::RetryPolicy;
+use crate::types::{EventEnvelope, EventFilter};
+use crate::wal::{format_timestamp, WalError, WriteAheadLog};
+
+/// Outbox relay configuration
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    /// Maximum number of events sent to the destination at once
+    pub batch_size: usize,
+    /// Delay between checks for new events once caught up
+    pub poll_interval: Duration,
+    /// Backoff after a failed batch; only the delays are used, a relay
+    /// retries forever
+    pub retry: RetryPolicy,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 256,
+            poll_interval: Duration::from_millis(500),
+            retry: RetryPolicy {
+                max_attempts: u32::MAX,
+                initial_backoff: Duration::from_millis(500),
+                max_backoff: Duration::from_secs(30),
+            },
+        }
+    }
+}
+
+/// Outbox errors
+#[derive(Debug, thiserror::Error)]
+pub enum OutboxError {
+    #[error("WAL error: {0}")]
+    Wal(#[from] WalError),
+
+    #[error("Delivery to {destination} failed: {reason}")]
+    Delivery { destination: String, reason: String },
+}
+
+impl WriteAheadLog {
+    /// Last sequence number delivered to a destination, 0 if none
+    pub fn outbox_cursor(&self, destination: &str) -> Result<u64, WalError> {
+        let cursor = self
+            .conn
+            .query_row(
+                "SELECT sequence FROM outbox_cursors WHERE destination = ?1",
+                params![destination],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(cursor.unwrap_or(0))
+    }
+
+    /// Record that every event up to `sequence` reached a destination
+    ///
+    /// Cursors never move backwards.
+    pub fn advance_outbox_cursor(
+        &mut self,
+        destination: &str,
+        sequence: u64,
+    ) -> Result<(), WalError> {
+        self.conn.execute(
+            "INSERT INTO outbox_cursors (destination, sequence, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(destination) DO UPDATE
+             SET sequence = MAX(sequence, excluded.sequence), updated_at = excluded.updated_at",
+            params![destination, sequence, format_timestamp(Utc::now())],
+        )?;
+        Ok(())
+    }
+
+    /// Forget a destination, so compaction is no longer held back for it
+    ///
+    /// Returns whether the destination had a cursor.
+    pub fn remove_outbox_cursor(&mut self, destination: &str) -> Result<bool, WalError> {
+        let removed = self.conn.execute(
+            "DELETE FROM outbox_cursors WHERE destination = ?1",
+            params![destination],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Highest sequence number committed by any connection to the log
+    fn committed_sequence(&self) -> Result<u64, WalError> {
+        let sequence =
+            self.conn
+                .query_row("SELECT COALESCE(MAX(sequence), 0) FROM events", [], |row| {
+                    row.get(0)
+                })?;
+        Ok(sequence)
+    }
+}
+
+/// Forwards committed WAL events to one destination
+///
+/// Cheaply cloneable; clones share the same WAL connection and must not
+/// relay concurrently.
+#[derive(Clone)]
+pub struct OutboxRelay {
+    wal: Arc<Mutex<WriteAheadLog>>,
+    destination: String,
+    config: OutboxConfig,
+}
+
+impl OutboxRelay {
+    /// Relay events of `wal` to `destination`, e.g. `kafka:events`
+    ///
+    /// The WAL should be a second connection to the database file of the
+    /// main event log, so the relay sees events committed by the writer.
+    /// A destination seen for the first time starts at the beginning of
+    /// the log.
+    pub fn new(wal: WriteAheadLog, destination: &str) -> Self {
+        Self {
+            wal: Arc::new(Mutex::new(wal)),
+            destination: destination.to_string(),
+            config: OutboxConfig::default(),
+        }
+    }
+
+    /// Set the relay configuration
+    pub fn with_config(mut self, config: OutboxConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the destination name
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Run a closure against the WAL
+    ///
+    /// The lock is never held across an `.await`.
+    fn with_wal<R>(&self, f: impl FnOnce(&mut WriteAheadLog) -> R) -> R {
+        let mut wal = self.wal.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut wal)
+    }
+
+    /// Last sequence number delivered to the destination
+    pub fn cursor(&self) -> Result<u64, WalError> {
+        self.with_wal(|wal| wal.outbox_cursor(&self.destination))
+    }
+
+    /// Number of committed events not yet delivered
+    ///
+    /// Compacted events below the cursor are not counted, so this is an
+    /// upper bound.
+    pub fn lag(&self) -> Result<u64, WalError> {
+        self.with_wal(|wal| {
+            let cursor = wal.outbox_cursor(&self.destination)?;
+            Ok(wal.committed_sequence()?.saturating_sub(cursor))
+        })
+    }
+
+    /// Send the next batch of undelivered events
+    ///
+    /// `send` receives the events in sequence order and must only succeed
+    /// once the destination has accepted all of them. Returns the number of
+    /// events delivered; 0 means the relay is caught up. On failure the
+    /// cursor is left in place and the same events are sent again next
+    /// time.
+    pub async fn relay_once<F, Fut, E>(&self, send: F) -> Result<usize, OutboxError>
+    where
+        F: FnOnce(Vec<EventEnvelope>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let batch = self.with_wal(|wal| {
+            let cursor = wal.outbox_cursor(&self.destination)?;
+            wal.read_filtered(
+                &EventFilter::new()
+                    .from_sequence(cursor + 1)
+                    .limit(self.config.batch_size.max(1)),
+            )
+        })?;
+        let Some(last) = batch.last().map(|envelope| envelope.sequence) else {
+            return Ok(0);
+        };
+        let count = batch.len();
+
+        send(batch).await.map_err(|e| OutboxError::Delivery {
+            destination: self.destination.clone(),
+            reason: e.to_string(),
+        })?;
+        self.with_wal(|wal| wal.advance_outbox_cursor(&self.destination, last))?;
+        Ok(count)
+    }
+
+    /// Relay events forever, backing off while the destination fails
+    ///
+    /// Only returns if the WAL itself cannot be read or updated.
+    pub async fn run<F, Fut, E>(&self, mut send: F) -> Result<(), WalError>
+    where
+        F: FnMut(Vec<EventEnvelope>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut failures = 0;
+        loop {
+            match self.relay_once(&mut send).await {
+                Ok(0) => tokio::time::sleep(self.config.poll_interval).await,
+                Ok(_) => failures = 0,
+                Err(OutboxError::Wal(e)) => return Err(e),
+                Err(e) => {
+                    failures += 1;
+                    tracing::warn!("outbox relay failed (attempt {failures}): {e}");
+                    tokio::time::sleep(self.config.retry.backoff(failures)).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use uuid::Uuid;
+
+    fn deleted() -> Event {
+        Event::DataDeleted {
+            data_uuid: Uuid::new_v4(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_resumes_after_failure_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        for _ in 0..5 {
+            wal.append(deleted()).unwrap();
+        }
+
+        let config = OutboxConfig {
+            batch_size: 2,
+            ..Default::default()
+        };
+        let relay = OutboxRelay::new(WriteAheadLog::open(&path).unwrap(), "kafka:events")
+            .with_config(config.clone());
+        let mut delivered = Vec::new();
+        let mut sink = |batch: Vec<EventEnvelope>| {
+            delivered.extend(batch.iter().map(|e| e.sequence));
+            async { Ok::<_, String>(()) }
+        };
+        assert_eq!(relay.relay_once(&mut sink).await.unwrap(), 2);
+
+        // A failed batch is not acknowledged
+        let err = relay
+            .relay_once(|_| async { Err("broker down") })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, OutboxError::Delivery { .. }));
+        assert_eq!(relay.cursor().unwrap(), 2);
+        assert_eq!(relay.lag().unwrap(), 3);
+
+        // Compaction keeps events the destination has not received
+        assert_eq!(wal.compact(5).unwrap(), 2);
+        drop(relay);
+
+        // Appends made after the relay opened its connection are seen
+        wal.append(deleted()).unwrap();
+        let relay = OutboxRelay::new(WriteAheadLog::open(&path).unwrap(), "kafka:events")
+            .with_config(config);
+        while relay.relay_once(&mut sink).await.unwrap() > 0 {}
+        assert_eq!(delivered, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(relay.lag().unwrap(), 0);
+    }
+}
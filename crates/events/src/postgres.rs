@@ -0,0 +1,500 @@
+//! PostgreSQL WAL backend
+//!
+//! [`PostgresWal`] implements [`WalBackend`] over a shared Postgres table,
+//! so several API instances can append to and read from one durable event
+//! store instead of each keeping its own SQLite file. Appends take an
+//! exclusive table lock for the length of their transaction, which keeps
+//! sequence numbers gapless and in commit order across instances; reads are
+//! not blocked by it.
+//!
+//! Every committing append sends a `NOTIFY` on [`NOTIFY_CHANNEL`] carrying
+//! the last new sequence number. [`PostgresSubscriber`] `LISTEN`s on that
+//! channel and reads new events as soon as any instance commits them.
+//!
+//! [`WalBackend`] is synchronous, so `PostgresWal` drives its connection on
+//! a private runtime and waits for each statement on the calling thread.
+//! Use it from a blocking context, such as the writer thread of an
+//! [`AsyncWal`](crate::async_wal::AsyncWal) or an
+//! [`EventBus`](crate::bus::EventBus).
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{AsyncMessage, Client, NoTls, Row};
+
+use crate::types::{Event, EventEnvelope, EventFilter, EVENT_SCHEMA_VERSION};
+use crate::wal::{format_timestamp, parse_column, AppendEntry, WalBackend, WalError};
+
+/// Channel notified after every append that adds events
+pub const NOTIFY_CHANNEL: &str = "swarmx_events";
+
+/// Table holding the event log
+const EVENTS_TABLE: &str = "swarmx_events";
+
+/// Maximum number of events a subscriber reads at once
+const SUBSCRIBER_BATCH_SIZE: usize = 256;
+
+/// Columns selected to rebuild an [`EventEnvelope`]
+const ENVELOPE_COLUMNS: &str =
+    "id, sequence, event_json, schema_version, created_at, idempotency_key, trace_id, parent_span_id";
+
+type Param = Box<dyn ToSql + Sync + Send>;
+
+/// WAL stored in a PostgreSQL table
+pub struct PostgresWal {
+    client: Arc<Client>,
+    runtime: Runtime,
+    /// Highest sequence appended by this instance or present at connect;
+    /// other instances may have appended more since
+    last_sequence: u64,
+}
+
+impl PostgresWal {
+    /// Connect and create the events table if needed
+    ///
+    /// `config` is a libpq-style connection string, e.g.
+    /// `host=db user=swarmx dbname=swarmx`.
+    pub fn connect(config: &str) -> Result<Self, WalError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("swarmx-postgres")
+            .enable_all()
+            .build()?;
+
+        let config = config.to_string();
+        let client = run(&runtime, async move {
+            let (client, connection) = tokio_postgres::connect(&config, NoTls).await?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    tracing::error!(error = %e, "Postgres WAL connection failed");
+                }
+            });
+            client.batch_execute(&schema_sql()).await?;
+            Ok::<_, WalError>(client)
+        })?;
+        let client = Arc::new(client);
+
+        let mut wal = Self {
+            client,
+            runtime,
+            last_sequence: 0,
+        };
+        wal.last_sequence = wal.max_sequence()?;
+        Ok(wal)
+    }
+
+    /// Run a query against the client on the private runtime
+    fn run<T, F, Fut>(&self, f: F) -> Result<T, WalError>
+    where
+        F: FnOnce(Arc<Client>) -> Fut,
+        Fut: Future<Output = Result<T, WalError>> + Send + 'static,
+        T: Send + 'static,
+    {
+        run(&self.runtime, f(self.client.clone()))
+    }
+
+    fn max_sequence(&self) -> Result<u64, WalError> {
+        self.run(|client| async move {
+            let row = client
+                .query_one(
+                    &format!("SELECT COALESCE(MAX(sequence), 0) FROM {EVENTS_TABLE}"),
+                    &[],
+                )
+                .await?;
+            Ok(row.get::<_, i64>(0) as u64)
+        })
+    }
+}
+
+/// Run a future on `runtime` and wait for it on the current thread
+///
+/// Unlike `Runtime::block_on`, this also works from a thread that is
+/// itself inside another runtime's `block_on`.
+fn run<T: Send + 'static>(
+    runtime: &Runtime,
+    future: impl Future<Output = Result<T, WalError>> + Send + 'static,
+) -> Result<T, WalError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    runtime.spawn(async move {
+        let _ = tx.send(future.await);
+    });
+    rx.recv()
+        .map_err(|_| WalError::Io(std::io::Error::other("Postgres WAL runtime stopped")))?
+}
+
+fn schema_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {EVENTS_TABLE} (
+            sequence BIGINT PRIMARY KEY,
+            id TEXT NOT NULL,
+            event_type TEXT NOT NULL,
+            event_json TEXT NOT NULL,
+            schema_version INTEGER NOT NULL,
+            idempotency_key TEXT UNIQUE,
+            trace_id TEXT,
+            parent_span_id TEXT,
+            workflow_id TEXT,
+            node_id TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_{EVENTS_TABLE}_workflow ON {EVENTS_TABLE}(workflow_id);
+        CREATE INDEX IF NOT EXISTS idx_{EVENTS_TABLE}_node ON {EVENTS_TABLE}(node_id);
+        CREATE INDEX IF NOT EXISTS idx_{EVENTS_TABLE}_created_at ON {EVENTS_TABLE}(created_at);
+        CREATE INDEX IF NOT EXISTS idx_{EVENTS_TABLE}_type ON {EVENTS_TABLE}(event_type, sequence);
+        CREATE INDEX IF NOT EXISTS idx_{EVENTS_TABLE}_trace ON {EVENTS_TABLE}(trace_id, sequence);"
+    )
+}
+
+/// Rebuild an envelope from a row selected with [`ENVELOPE_COLUMNS`]
+fn decode_row(row: &Row) -> Result<EventEnvelope, WalError> {
+    let schema_version: i32 = row.get(3);
+    Ok(EventEnvelope {
+        id: parse_column(row.get::<_, &str>(0))?,
+        sequence: row.get::<_, i64>(1) as u64,
+        schema_version: EVENT_SCHEMA_VERSION,
+        event: Event::migrate(row.get(2), schema_version as u32)?,
+        created_at: parse_column(row.get::<_, &str>(4))?,
+        idempotency_key: row.get(5),
+        trace_id: row.get(6),
+        parent_span_id: row.get(7),
+    })
+}
+
+/// Build the query for events matching a filter
+fn filter_query(filter: &EventFilter) -> (String, Vec<Param>) {
+    let mut sql = format!("SELECT {ENVELOPE_COLUMNS} FROM {EVENTS_TABLE} WHERE TRUE");
+    let mut params: Vec<Param> = Vec::new();
+    let mut push = |sql: &mut String, clause: &str, param: Param| {
+        params.push(param);
+        sql.push_str(&clause.replace('?', &format!("${}", params.len())));
+    };
+
+    if let Some(workflow_id) = filter.workflow_id {
+        push(
+            &mut sql,
+            " AND workflow_id = ?",
+            Box::new(workflow_id.to_string()),
+        );
+    }
+    if let Some(node_id) = filter.node_id {
+        push(&mut sql, " AND node_id = ?", Box::new(node_id.to_string()));
+    }
+    if let Some(types) = &filter.event_types {
+        push(
+            &mut sql,
+            " AND event_type = ANY(?)",
+            Box::new(types.clone()),
+        );
+    }
+    if let Some(from) = filter.from_timestamp {
+        push(
+            &mut sql,
+            " AND created_at >= ?",
+            Box::new(format_timestamp(from)),
+        );
+    }
+    if let Some(to) = filter.to_timestamp {
+        push(
+            &mut sql,
+            " AND created_at <= ?",
+            Box::new(format_timestamp(to)),
+        );
+    }
+    if let Some(sequence) = filter.from_sequence {
+        push(&mut sql, " AND sequence >= ?", Box::new(sequence as i64));
+    }
+    if let Some(trace_id) = &filter.trace_id {
+        push(&mut sql, " AND trace_id = ?", Box::new(trace_id.clone()));
+    }
+    if let Some(types) = filter.severity_event_types() {
+        push(&mut sql, " AND event_type = ANY(?)", Box::new(types));
+    }
+    sql.push_str(" ORDER BY sequence ASC");
+    if let Some(limit) = filter.limit {
+        push(&mut sql, " LIMIT ?", Box::new(limit as i64));
+    }
+    (sql, params)
+}
+
+async fn query_events(
+    client: &Client,
+    filter: &EventFilter,
+) -> Result<Vec<EventEnvelope>, WalError> {
+    let (sql, params) = filter_query(filter);
+    let params: Vec<&(dyn ToSql + Sync)> = params
+        .iter()
+        .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+        .collect();
+    client
+        .query(&sql, &params)
+        .await?
+        .iter()
+        .map(decode_row)
+        .collect()
+}
+
+/// Append events in one transaction, returning the envelopes
+async fn append_entries(
+    client: &Client,
+    entries: Vec<AppendEntry>,
+) -> Result<Vec<EventEnvelope>, WalError> {
+    client.batch_execute("BEGIN").await?;
+    let result = append_in_transaction(client, entries).await;
+    match &result {
+        Ok(_) => client.batch_execute("COMMIT").await?,
+        Err(_) => {
+            if let Err(e) = client.batch_execute("ROLLBACK").await {
+                tracing::warn!(error = %e, "failed to roll back Postgres WAL append");
+            }
+        }
+    }
+    result
+}
+
+async fn append_in_transaction(
+    client: &Client,
+    entries: Vec<AppendEntry>,
+) -> Result<Vec<EventEnvelope>, WalError> {
+    // Serializes appends across instances; plain reads are unaffected
+    client
+        .batch_execute(&format!("LOCK TABLE {EVENTS_TABLE} IN EXCLUSIVE MODE"))
+        .await?;
+    let row = client
+        .query_one(
+            &format!("SELECT COALESCE(MAX(sequence), 0) FROM {EVENTS_TABLE}"),
+            &[],
+        )
+        .await?;
+    let mut next_sequence = row.get::<_, i64>(0) as u64 + 1;
+
+    let insert = client
+        .prepare(&format!(
+            "INSERT INTO {EVENTS_TABLE}
+                (sequence, id, event_type, event_json, schema_version, idempotency_key, trace_id, parent_span_id, workflow_id, node_id, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+        ))
+        .await?;
+    let lookup = client
+        .prepare(&format!(
+            "SELECT {ENVELOPE_COLUMNS} FROM {EVENTS_TABLE} WHERE idempotency_key = $1"
+        ))
+        .await?;
+
+    let mut envelopes: Vec<EventEnvelope> = Vec::new();
+    let mut batch_keys: HashMap<String, usize> = HashMap::new();
+    let mut appended = false;
+    for entry in entries {
+        if let Some(key) = &entry.idempotency_key {
+            if let Some(&index) = batch_keys.get(key) {
+                envelopes.push(envelopes[index].clone());
+                continue;
+            }
+            if let Some(row) = client.query_opt(&lookup, &[key]).await? {
+                envelopes.push(decode_row(&row)?);
+                continue;
+            }
+            batch_keys.insert(key.clone(), envelopes.len());
+        }
+
+        let envelope = entry.into_envelope(next_sequence);
+        client
+            .execute(
+                &insert,
+                &[
+                    &(envelope.sequence as i64),
+                    &envelope.id.to_string(),
+                    &envelope.event.event_type(),
+                    &envelope.event.to_json()?,
+                    &(envelope.schema_version as i32),
+                    &envelope.idempotency_key,
+                    &envelope.trace_id,
+                    &envelope.parent_span_id,
+                    &envelope.event.workflow_id().map(|id| id.to_string()),
+                    &envelope.event.node_id().map(|id| id.to_string()),
+                    &format_timestamp(envelope.created_at),
+                ],
+            )
+            .await?;
+        next_sequence += 1;
+        appended = true;
+        envelopes.push(envelope);
+    }
+
+    if appended {
+        // Delivered to listeners when the transaction commits
+        client
+            .execute(
+                "SELECT pg_notify($1, $2)",
+                &[&NOTIFY_CHANNEL, &(next_sequence - 1).to_string()],
+            )
+            .await?;
+    }
+    Ok(envelopes)
+}
+
+impl WalBackend for PostgresWal {
+    fn append_batch(&mut self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError> {
+        self.append_entries(events.into_iter().map(AppendEntry::new).collect())
+    }
+
+    fn append_entries(
+        &mut self,
+        entries: Vec<AppendEntry>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        let envelopes = self.run(|client| async move { append_entries(&client, entries).await })?;
+        if let Some(max) = envelopes.iter().map(|e| e.sequence).max() {
+            self.last_sequence = self.last_sequence.max(max);
+        }
+        Ok(envelopes)
+    }
+
+    fn read_filtered(&self, filter: &EventFilter) -> Result<Vec<EventEnvelope>, WalError> {
+        let filter = filter.clone();
+        self.run(|client| async move { query_events(&client, &filter).await })
+    }
+
+    fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    fn count(&self) -> Result<u64, WalError> {
+        self.run(|client| async move {
+            let row = client
+                .query_one(&format!("SELECT COUNT(*) FROM {EVENTS_TABLE}"), &[])
+                .await?;
+            Ok(row.get::<_, i64>(0) as u64)
+        })
+    }
+}
+
+/// Push subscription to a Postgres WAL shared by several writers
+///
+/// Holds a dedicated connection that `LISTEN`s on [`NOTIFY_CHANNEL`];
+/// events are read as soon as a notification arrives, with no polling.
+pub struct PostgresSubscriber {
+    client: Client,
+    notifications: mpsc::UnboundedReceiver<()>,
+    filter: EventFilter,
+    /// Next sequence number to read
+    next: u64,
+    /// Matching events read from the log, not yet returned
+    backlog: VecDeque<EventEnvelope>,
+}
+
+impl PostgresSubscriber {
+    /// Connect and follow the log from `from_sequence` on
+    ///
+    /// The filter's `from_sequence` and `limit` are ignored.
+    pub async fn connect(
+        config: &str,
+        filter: EventFilter,
+        from_sequence: u64,
+    ) -> Result<Self, WalError> {
+        let (client, mut connection) = tokio_postgres::connect(config, NoTls).await?;
+        let (tx, notifications) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(_)) => {
+                        if tx.send(()).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Postgres subscriber connection failed");
+                        break;
+                    }
+                }
+            }
+        });
+        // Listen before the first read so no commit in between is missed
+        client
+            .batch_execute(&format!("LISTEN {NOTIFY_CHANNEL}"))
+            .await?;
+
+        Ok(Self {
+            client,
+            notifications,
+            filter,
+            next: from_sequence.max(1),
+            backlog: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the next matching event
+    pub async fn recv(&mut self) -> Result<EventEnvelope, WalError> {
+        loop {
+            if let Some(envelope) = self.backlog.pop_front() {
+                return Ok(envelope);
+            }
+
+            // Several notifications may have queued up; one read covers them
+            while self.notifications.try_recv().is_ok() {}
+            let mut filter = self.filter.clone();
+            filter.from_sequence = Some(self.next);
+            filter.limit = Some(SUBSCRIBER_BATCH_SIZE);
+            let envelopes = query_events(&self.client, &filter).await?;
+            if let Some(last) = envelopes.last() {
+                self.next = last.sequence + 1;
+                self.backlog.extend(envelopes);
+                continue;
+            }
+
+            if self.notifications.recv().await.is_none() {
+                return Err(WalError::Io(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "Postgres subscriber connection closed",
+                )));
+            }
+        }
+    }
+
+    /// Turn the subscriber into a stream of matching events
+    ///
+    /// The stream ends after the connection is lost.
+    pub fn into_stream(self) -> impl Stream<Item = Result<EventEnvelope, WalError>> + Send {
+        stream::unfold(Some(self), |subscriber| async move {
+            let mut subscriber = subscriber?;
+            match subscriber.recv().await {
+                Ok(envelope) => Some((Ok(envelope), Some(subscriber))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EventKind;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_filter_query_numbers_parameters() {
+        let filter = EventFilter::new()
+            .workflow(Uuid::new_v4())
+            .kinds([EventKind::NodeFailed, EventKind::NodeRetrying])
+            .time_range(Some(Utc::now()), None)
+            .from_sequence(10)
+            .limit(5);
+        let (sql, params) = filter_query(&filter);
+        assert_eq!(params.len(), 5);
+        assert!(sql.contains("workflow_id = $1"));
+        assert!(sql.contains("event_type = ANY($2)"));
+        assert!(sql.contains("created_at >= $3"));
+        assert!(sql.contains("sequence >= $4"));
+        assert!(sql.ends_with("ORDER BY sequence ASC LIMIT $5"));
+
+        let (sql, params) = filter_query(&EventFilter::new());
+        assert!(params.is_empty());
+        assert!(!sql.contains('$'));
+    }
+}
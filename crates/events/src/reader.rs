@@ -0,0 +1,164 @@
+//! Concurrent readers for the SQLite WAL
+//!
+//! A [`WriteAheadLog`] owns the single connection that writes to the log.
+//! In SQLite's WAL journal mode readers never block that writer, or each
+//! other, as long as they use connections of their own. [`WalReader`] is a
+//! cheaply cloneable, thread-safe handle to a small pool of read-only
+//! connections to the same database file, so SSE streams and status
+//! queries can read while appends are in progress.
+//!
+//! Readers see every transaction committed before their query started.
+//! In-memory logs cannot be opened twice and have no reader.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use rusqlite::{Connection, OpenFlags};
+use uuid::Uuid;
+
+use crate::types::{EventEnvelope, EventFilter};
+use crate::wal::{query_count, query_events, query_page, WalError};
+
+/// Connections kept open between reads
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// How long a reader waits on a locked database before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Thread-safe read handle to a file-backed WAL
+///
+/// Obtained from [`WriteAheadLog::reader`](crate::wal::WriteAheadLog::reader).
+/// Connections are opened on demand; at most [`MAX_IDLE_CONNECTIONS`] are
+/// kept for reuse, so bursts of concurrent reads are never refused.
+#[derive(Clone)]
+pub struct WalReader {
+    pool: Arc<ReadPool>,
+}
+
+struct ReadPool {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl WalReader {
+    /// Create a reader for the WAL database at `path`
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            pool: Arc::new(ReadPool {
+                path: path.to_path_buf(),
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Run a query on a pooled connection
+    pub(crate) fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, WalError>,
+    ) -> Result<T, WalError> {
+        let idle = self
+            .pool
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => self.connect()?,
+        };
+
+        let result = f(&conn);
+
+        let mut idle = self
+            .pool
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(conn);
+        }
+        result
+    }
+
+    fn connect(&self) -> Result<Connection, WalError> {
+        let conn = Connection::open_with_flags(
+            &self.pool.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    }
+
+    /// Read events matching a filter, ordered by sequence number
+    pub fn read_filtered(&self, filter: &EventFilter) -> Result<Vec<EventEnvelope>, WalError> {
+        self.with_connection(|conn| query_events(conn, filter))
+    }
+
+    /// Read events from a given sequence number
+    pub fn read_from(&self, sequence: u64) -> Result<Vec<EventEnvelope>, WalError> {
+        self.read_filtered(&EventFilter::new().from_sequence(sequence))
+    }
+
+    /// Get events for a specific workflow
+    pub fn events_for_workflow(&self, workflow_id: Uuid) -> Result<Vec<EventEnvelope>, WalError> {
+        self.read_filtered(&EventFilter::new().workflow(workflow_id))
+    }
+
+    /// Read a page of history, newest first
+    ///
+    /// See [`WriteAheadLog::read_page`](crate::wal::WriteAheadLog::read_page).
+    pub fn read_page(
+        &self,
+        before_sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        self.with_connection(|conn| query_page(conn, before_sequence, limit))
+    }
+
+    /// Get total event count
+    pub fn count(&self) -> Result<u64, WalError> {
+        self.with_connection(query_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use crate::wal::WriteAheadLog;
+    use chrono::Utc;
+
+    #[test]
+    fn test_reads_do_not_wait_for_the_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wal = WriteAheadLog::open(dir.path().join("events.db")).unwrap();
+        let workflow_id = Uuid::new_v4();
+        wal.append(Event::WorkflowStarted {
+            workflow_id,
+            name: "etl".to_string(),
+            timestamp: Utc::now(),
+        })
+        .unwrap();
+        let reader = wal.reader().unwrap();
+        assert!(WriteAheadLog::in_memory().unwrap().reader().is_none());
+
+        // Hold the write lock with an uncommitted change
+        wal.conn
+            .execute_batch("BEGIN IMMEDIATE; DELETE FROM events;")
+            .unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let reader = reader.clone();
+                std::thread::spawn(move || reader.events_for_workflow(workflow_id).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().len(), 1);
+        }
+
+        wal.conn.execute_batch("COMMIT;").unwrap();
+        assert_eq!(reader.count().unwrap(), 0);
+    }
+}
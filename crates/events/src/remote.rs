@@ -0,0 +1,199 @@
+//! Following the WAL from another process
+//!
+//! The bus and tail streams are woken by the process that writes the log.
+//! A separate process reading the same database file, such as a standalone
+//! UI backend, has no such hook. [`RemoteSubscriber`] instead watches
+//! SQLite's `PRAGMA data_version`, which changes whenever another
+//! connection commits. Checking it is cheap (it does not touch any table),
+//! and the events table is only queried after it changes. While the log is
+//! idle the check interval backs off, so an idle follower costs almost
+//! nothing.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::types::{EventEnvelope, EventFilter};
+use crate::wal::{query_events, WalError};
+
+/// Remote subscriber configuration
+#[derive(Debug, Clone)]
+pub struct RemoteSubscriberConfig {
+    /// Delay between change checks right after a change
+    pub min_poll_interval: Duration,
+    /// Upper bound the delay backs off to while the log is idle
+    pub max_poll_interval: Duration,
+    /// Maximum number of events read at once
+    pub batch_size: usize,
+}
+
+impl Default for RemoteSubscriberConfig {
+    fn default() -> Self {
+        Self {
+            min_poll_interval: Duration::from_millis(50),
+            max_poll_interval: Duration::from_secs(1),
+            batch_size: 256,
+        }
+    }
+}
+
+/// Follows a WAL file written by another process
+pub struct RemoteSubscriber {
+    conn: Arc<Mutex<Connection>>,
+    filter: EventFilter,
+    config: RemoteSubscriberConfig,
+    /// Next sequence number to read
+    next: u64,
+    /// `data_version` when the log was last read; `None` before the first read
+    data_version: Option<i64>,
+    poll_interval: Duration,
+    /// Matching events read from the log, not yet returned
+    backlog: VecDeque<EventEnvelope>,
+}
+
+impl RemoteSubscriber {
+    /// Follow the WAL at `path` from `from_sequence` on
+    ///
+    /// The database is opened read-only, so the log must already exist.
+    /// The filter's `from_sequence` and `limit` are ignored.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        filter: EventFilter,
+        from_sequence: u64,
+        config: RemoteSubscriberConfig,
+    ) -> Result<Self, WalError> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            filter,
+            poll_interval: config.min_poll_interval,
+            config,
+            next: from_sequence.max(1),
+            data_version: None,
+            backlog: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the next matching event
+    pub async fn recv(&mut self) -> Result<EventEnvelope, WalError> {
+        loop {
+            if let Some(envelope) = self.backlog.pop_front() {
+                return Ok(envelope);
+            }
+
+            let data_version = self.blocking(data_version).await?;
+            if self.data_version == Some(data_version) {
+                tokio::time::sleep(self.poll_interval).await;
+                self.poll_interval = (self.poll_interval * 2).min(self.config.max_poll_interval);
+                continue;
+            }
+
+            // Record the version before reading, so a commit racing with
+            // the read is picked up by the next check
+            self.data_version = Some(data_version);
+            self.poll_interval = self.config.min_poll_interval;
+            let mut filter = self.filter.clone();
+            filter.from_sequence = Some(self.next);
+            filter.limit = Some(self.config.batch_size.max(1));
+            let envelopes = self
+                .blocking(move |conn| query_events(conn, &filter))
+                .await?;
+
+            if let Some(last) = envelopes.last() {
+                self.next = last.sequence + 1;
+                // A full batch may have more behind it
+                if envelopes.len() >= self.config.batch_size {
+                    self.data_version = None;
+                }
+            }
+            self.backlog.extend(envelopes);
+        }
+    }
+
+    /// Get the next sequence number the subscriber will read from
+    pub fn next_sequence(&self) -> u64 {
+        self.next
+    }
+
+    /// Turn the subscriber into a stream of matching events
+    ///
+    /// Read errors are yielded as items; the stream continues after them.
+    pub fn into_stream(self) -> impl Stream<Item = Result<EventEnvelope, WalError>> + Send {
+        stream::unfold(self, |mut subscriber| async move {
+            let item = subscriber.recv().await;
+            Some((item, subscriber))
+        })
+    }
+
+    /// Run a query on the connection without blocking the runtime
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, WalError> + Send + 'static,
+    ) -> Result<T, WalError> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&conn)
+        })
+        .await
+        .map_err(|e| WalError::Io(std::io::Error::other(e)))?
+    }
+}
+
+/// Read the connection's change counter
+fn data_version(conn: &Connection) -> Result<i64, WalError> {
+    Ok(conn.query_row("PRAGMA data_version", [], |row| row.get(0))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use crate::wal::WriteAheadLog;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn started(workflow_id: Uuid) -> Event {
+        Event::WorkflowStarted {
+            workflow_id,
+            name: "test".to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_subscriber_follows_other_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        wal.append(started(a)).unwrap();
+        wal.append(started(b)).unwrap();
+
+        let config = RemoteSubscriberConfig {
+            min_poll_interval: Duration::from_millis(1),
+            max_poll_interval: Duration::from_millis(10),
+            batch_size: 1,
+        };
+        let mut subscriber =
+            RemoteSubscriber::open(&path, EventFilter::new().workflow(a), 1, config).unwrap();
+        assert_eq!(subscriber.recv().await.unwrap().sequence, 1);
+
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            wal.append(started(b)).unwrap();
+            wal.append(started(a)).unwrap();
+        });
+        assert_eq!(subscriber.recv().await.unwrap().sequence, 4);
+        assert_eq!(subscriber.next_sequence(), 5);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,752 @@
+//! Execution-state replay from the event log
+//!
+//! Rebuilds the timeline of a single workflow purely from its events: the
+//! final state of every node, how long each ran, how often it was retried,
+//! and a list of divergences wherever the log is inconsistent with the node
+//! lifecycle (for example `NodeCompleted` without a preceding
+//! `NodeStarted`). Used to debug crashed runs, where the in-memory state is
+//! gone and the WAL is all that is left.
+//!
+//! Replay is best-effort: every event is applied even when it diverges, so
+//! the rebuilt state reflects the last thing the log says. A log compacted
+//! mid-run starts part-way through the lifecycle and will report
+//! divergences for its first events.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::types::{Event, EventEnvelope, EventFilter};
+use crate::wal::{WalBackend, WalError};
+
+/// Workflow state as recorded in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayedWorkflowState {
+    /// No `WorkflowStarted` event was seen
+    #[default]
+    NotStarted,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl ReplayedWorkflowState {
+    /// Check if the workflow has finished
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// Node state as recorded in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayedNodeState {
+    Queued,
+    Scheduled,
+    Running,
+    Completed,
+    Failed,
+    Retrying,
+    Cancelled,
+}
+
+/// Rebuilt timeline of a single node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTimeline {
+    pub node_id: Uuid,
+    /// State after the last event
+    pub state: ReplayedNodeState,
+    /// Server of the most recent scheduling
+    pub server: Option<String>,
+    /// First time the node was queued
+    pub queued_at: Option<DateTime<Utc>>,
+    /// Strategy and reason of the most recent scheduling decision
+    pub scheduling_reason: Option<String>,
+    /// Number of `NodeDispatchFailed` events
+    pub dispatch_failures: u32,
+    /// First time the node was scheduled
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// First time the node started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the node last completed, failed, or was cancelled
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Execution time of the final attempt
+    pub duration_ms: Option<u64>,
+    /// Retry count from the most recent failure or retry event
+    pub retries: u32,
+    /// Number of `NodeStarted` events
+    pub attempts: u32,
+    /// Error of the most recent failure
+    pub last_error: Option<String>,
+    /// Outputs recorded on completion
+    pub output_refs: Vec<Uuid>,
+    /// Most recently reported progress
+    pub progress: Option<f64>,
+    /// Sequence of the first event for this node
+    pub first_sequence: u64,
+    /// Sequence of the last event for this node
+    pub last_sequence: u64,
+    /// When the most recent attempt started, for duration fallback
+    #[serde(skip)]
+    attempt_started_at: Option<DateTime<Utc>>,
+    /// Timestamp of the last event, for ordering checks
+    #[serde(skip)]
+    last_timestamp: Option<DateTime<Utc>>,
+}
+
+impl NodeTimeline {
+    fn new(node_id: Uuid, state: ReplayedNodeState, sequence: u64) -> Self {
+        Self {
+            node_id,
+            state,
+            server: None,
+            queued_at: None,
+            scheduling_reason: None,
+            dispatch_failures: 0,
+            scheduled_at: None,
+            started_at: None,
+            finished_at: None,
+            duration_ms: None,
+            retries: 0,
+            attempts: 0,
+            last_error: None,
+            output_refs: Vec::new(),
+            progress: None,
+            first_sequence: sequence,
+            last_sequence: sequence,
+            attempt_started_at: None,
+            last_timestamp: None,
+        }
+    }
+}
+
+/// Kind of inconsistency found in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DivergenceKind {
+    /// The event requires a lifecycle step that never happened,
+    /// e.g. `NodeCompleted` without `NodeStarted`
+    MissingPredecessor,
+    /// The same lifecycle step was recorded twice
+    DuplicateEvent,
+    /// The event arrived after its node or workflow had finished
+    EventAfterTerminal,
+    /// Node events were recorded before `WorkflowStarted`
+    EventBeforeWorkflowStart,
+    /// The workflow completed while a node was still in flight
+    UnfinishedAtCompletion,
+    /// A retry count does not follow from the previous events
+    RetryCountMismatch,
+    /// Sequence numbers or per-node timestamps go backwards
+    OutOfOrder,
+}
+
+/// A single inconsistency found during replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Divergence {
+    /// Sequence of the offending event
+    pub sequence: u64,
+    /// Node the event belongs to, if any
+    pub node_id: Option<Uuid>,
+    pub kind: DivergenceKind,
+    /// Human-readable explanation
+    pub detail: String,
+}
+
+/// Everything rebuilt from a workflow's events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub workflow_id: Uuid,
+    /// Workflow name from `WorkflowStarted`
+    pub name: Option<String>,
+    pub state: ReplayedWorkflowState,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    /// Error from `WorkflowFailed` or reason from `WorkflowCancelled`
+    pub error: Option<String>,
+    /// Per-node timelines, in order of first appearance
+    pub nodes: Vec<NodeTimeline>,
+    /// Number of events replayed
+    pub event_count: usize,
+    pub first_sequence: Option<u64>,
+    pub last_sequence: Option<u64>,
+    /// Inconsistencies, in log order
+    pub divergences: Vec<Divergence>,
+}
+
+impl ReplayReport {
+    /// Check if the log replayed without divergences
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Get the timeline of a node
+    pub fn node(&self, node_id: Uuid) -> Option<&NodeTimeline> {
+        self.nodes.iter().find(|n| n.node_id == node_id)
+    }
+}
+
+/// Incremental replay of a single workflow's events
+#[derive(Debug)]
+pub struct Replayer {
+    report: ReplayReport,
+    index: HashMap<Uuid, usize>,
+    reported_early_start: bool,
+}
+
+impl Replayer {
+    /// Start replaying a workflow
+    pub fn new(workflow_id: Uuid) -> Self {
+        Self {
+            report: ReplayReport {
+                workflow_id,
+                name: None,
+                state: ReplayedWorkflowState::NotStarted,
+                started_at: None,
+                finished_at: None,
+                duration_ms: None,
+                error: None,
+                nodes: Vec::new(),
+                event_count: 0,
+                first_sequence: None,
+                last_sequence: None,
+                divergences: Vec::new(),
+            },
+            index: HashMap::new(),
+            reported_early_start: false,
+        }
+    }
+
+    /// Apply the next envelope from the log
+    ///
+    /// Envelopes for other workflows are ignored.
+    pub fn apply(&mut self, envelope: &EventEnvelope) {
+        let event = &envelope.event;
+        if event.workflow_id() != Some(self.report.workflow_id) {
+            return;
+        }
+
+        let sequence = envelope.sequence;
+        if let Some(last) = self.report.last_sequence {
+            if sequence <= last {
+                self.diverge(
+                    sequence,
+                    event.node_id(),
+                    DivergenceKind::OutOfOrder,
+                    format!("sequence {sequence} follows {last}"),
+                );
+            }
+        }
+        self.report.first_sequence.get_or_insert(sequence);
+        self.report.last_sequence = Some(sequence);
+        self.report.event_count += 1;
+
+        match event.node_id() {
+            Some(node_id) => self.apply_node_event(sequence, node_id, event),
+            None => self.apply_workflow_event(sequence, event),
+        }
+    }
+
+    /// Finish replay and return the report
+    pub fn finish(self) -> ReplayReport {
+        self.report
+    }
+
+    fn diverge(
+        &mut self,
+        sequence: u64,
+        node_id: Option<Uuid>,
+        kind: DivergenceKind,
+        detail: String,
+    ) {
+        self.report.divergences.push(Divergence {
+            sequence,
+            node_id,
+            kind,
+            detail,
+        });
+    }
+
+    fn apply_workflow_event(&mut self, sequence: u64, event: &Event) {
+        let previous = self.report.state;
+        let (to, name, error) = match event {
+            Event::WorkflowStarted { name, .. } => {
+                (ReplayedWorkflowState::Running, Some(name.clone()), None)
+            }
+            Event::WorkflowCompleted { .. } => (ReplayedWorkflowState::Completed, None, None),
+            Event::WorkflowFailed { error, .. } => {
+                (ReplayedWorkflowState::Failed, None, Some(error.clone()))
+            }
+            Event::WorkflowCancelled { reason, .. } => {
+                (ReplayedWorkflowState::Cancelled, None, reason.clone())
+            }
+            // Workflow-scoped events that do not affect the lifecycle
+            _ => return,
+        };
+
+        let event_type = event.event_type();
+        if previous.is_terminal() {
+            self.diverge(
+                sequence,
+                None,
+                if previous == to {
+                    DivergenceKind::DuplicateEvent
+                } else {
+                    DivergenceKind::EventAfterTerminal
+                },
+                format!("{event_type} after workflow was {previous:?}"),
+            );
+        } else if to == ReplayedWorkflowState::Running && previous == to {
+            self.diverge(
+                sequence,
+                None,
+                DivergenceKind::DuplicateEvent,
+                "workflow started twice".to_string(),
+            );
+        } else if to.is_terminal() && previous == ReplayedWorkflowState::NotStarted {
+            self.diverge(
+                sequence,
+                None,
+                DivergenceKind::MissingPredecessor,
+                format!("{event_type} without workflow_started"),
+            );
+        }
+
+        if to == ReplayedWorkflowState::Completed {
+            let unfinished: Vec<(Uuid, ReplayedNodeState)> = self
+                .report
+                .nodes
+                .iter()
+                .filter(|n| {
+                    matches!(
+                        n.state,
+                        ReplayedNodeState::Queued
+                            | ReplayedNodeState::Scheduled
+                            | ReplayedNodeState::Running
+                            | ReplayedNodeState::Retrying
+                    )
+                })
+                .map(|n| (n.node_id, n.state))
+                .collect();
+            for (node_id, state) in unfinished {
+                self.diverge(
+                    sequence,
+                    Some(node_id),
+                    DivergenceKind::UnfinishedAtCompletion,
+                    format!("workflow completed while node was {state:?}"),
+                );
+            }
+        }
+
+        let timestamp = event.timestamp();
+        self.report.state = to;
+        if to == ReplayedWorkflowState::Running {
+            self.report.name = name;
+            self.report.started_at.get_or_insert(timestamp);
+        } else {
+            self.report.finished_at = Some(timestamp);
+            self.report.error = error;
+            self.report.duration_ms = match event {
+                Event::WorkflowCompleted { duration_ms, .. } => Some(*duration_ms),
+                _ => self
+                    .report
+                    .started_at
+                    .map(|start| (timestamp - start).num_milliseconds().max(0) as u64),
+            };
+        }
+    }
+
+    fn apply_node_event(&mut self, sequence: u64, node_id: Uuid, event: &Event) {
+        use ReplayedNodeState::*;
+
+        let workflow_state = self.report.state;
+        if workflow_state.is_terminal() {
+            self.diverge(
+                sequence,
+                Some(node_id),
+                DivergenceKind::EventAfterTerminal,
+                format!(
+                    "{} after workflow was {workflow_state:?}",
+                    event.event_type()
+                ),
+            );
+        } else if workflow_state == ReplayedWorkflowState::NotStarted && !self.reported_early_start
+        {
+            self.reported_early_start = true;
+            self.diverge(
+                sequence,
+                Some(node_id),
+                DivergenceKind::EventBeforeWorkflowStart,
+                format!("{} before workflow_started", event.event_type()),
+            );
+        }
+
+        let previous = self
+            .index
+            .get(&node_id)
+            .map(|&i| self.report.nodes[i].state);
+
+        // Which states the event may follow, and the state it produces
+        let (allowed, to): (&[Option<ReplayedNodeState>], ReplayedNodeState) = match event {
+            Event::NodeQueued { .. } => (&[None, Some(Failed), Some(Retrying)], Queued),
+            // A decision annotates the scheduling that follows it
+            Event::SchedulingDecisionMade { .. } => (
+                &[None, Some(Queued), Some(Failed), Some(Retrying)],
+                previous.unwrap_or(Queued),
+            ),
+            Event::NodeScheduled { .. } => (
+                &[None, Some(Queued), Some(Failed), Some(Retrying)],
+                Scheduled,
+            ),
+            // The node goes back to the queue to be scheduled again
+            Event::NodeDispatchFailed { .. } => (&[Some(Scheduled)], Queued),
+            Event::NodeStarted { .. } => (&[Some(Scheduled)], Running),
+            Event::NodeProgress { .. } | Event::NodeOutputChunk { .. } => {
+                (&[Some(Running)], Running)
+            }
+            Event::NodeCompleted { .. } => (&[Some(Running)], Completed),
+            Event::NodeFailed { .. } => (&[Some(Scheduled), Some(Running)], Failed),
+            Event::NodeRetrying { .. } => (&[Some(Failed)], Retrying),
+            Event::NodeCancelled { .. } => (
+                &[
+                    None,
+                    Some(Queued),
+                    Some(Scheduled),
+                    Some(Running),
+                    Some(Failed),
+                    Some(Retrying),
+                ],
+                Cancelled,
+            ),
+            _ => return,
+        };
+
+        if !allowed.contains(&previous) {
+            let kind = match previous {
+                Some(Completed | Cancelled) => DivergenceKind::EventAfterTerminal,
+                Some(state) if state == to => DivergenceKind::DuplicateEvent,
+                _ => DivergenceKind::MissingPredecessor,
+            };
+            let detail = match previous {
+                Some(state) => format!("{} while node was {state:?}", event.event_type()),
+                None => format!("{} is the first event for the node", event.event_type()),
+            };
+            self.diverge(sequence, Some(node_id), kind, detail);
+        }
+
+        let i = *self.index.entry(node_id).or_insert_with(|| {
+            self.report
+                .nodes
+                .push(NodeTimeline::new(node_id, to, sequence));
+            self.report.nodes.len() - 1
+        });
+
+        let timestamp = event.timestamp();
+        let node = &mut self.report.nodes[i];
+        let mut divergence = None;
+        if node.last_timestamp.is_some_and(|last| timestamp < last) {
+            divergence = Some((
+                DivergenceKind::OutOfOrder,
+                format!(
+                    "{} is timestamped before the previous node event",
+                    event.event_type()
+                ),
+            ));
+        }
+        node.last_timestamp = Some(timestamp);
+        node.last_sequence = sequence;
+        node.state = to;
+
+        match event {
+            Event::NodeQueued { .. } => {
+                node.queued_at.get_or_insert(timestamp);
+            }
+            Event::SchedulingDecisionMade {
+                strategy, reason, ..
+            } => node.scheduling_reason = Some(format!("{strategy}: {reason}")),
+            Event::NodeScheduled { server, .. } => {
+                node.server = Some(server.clone());
+                node.scheduled_at.get_or_insert(timestamp);
+            }
+            Event::NodeDispatchFailed { error, .. } => {
+                node.dispatch_failures += 1;
+                node.last_error = Some(error.clone());
+            }
+            Event::NodeStarted { .. } => {
+                node.attempts += 1;
+                node.started_at.get_or_insert(timestamp);
+                node.attempt_started_at = Some(timestamp);
+            }
+            Event::NodeProgress { progress, .. } => node.progress = Some(*progress),
+            Event::NodeCompleted {
+                output_refs,
+                duration_ms,
+                ..
+            } => {
+                node.finished_at = Some(timestamp);
+                node.duration_ms = Some(*duration_ms);
+                node.output_refs = output_refs.clone();
+            }
+            Event::NodeFailed {
+                error, retry_count, ..
+            } => {
+                if *retry_count != node.retries && divergence.is_none() {
+                    divergence = Some((
+                        DivergenceKind::RetryCountMismatch,
+                        format!(
+                            "node_failed reports {retry_count} retries, expected {}",
+                            node.retries
+                        ),
+                    ));
+                }
+                node.retries = *retry_count;
+                node.finished_at = Some(timestamp);
+                node.last_error = Some(error.clone());
+                node.duration_ms = node
+                    .attempt_started_at
+                    .map(|start| (timestamp - start).num_milliseconds().max(0) as u64);
+            }
+            Event::NodeRetrying { retry_count, .. } => {
+                if *retry_count != node.retries + 1 && divergence.is_none() {
+                    divergence = Some((
+                        DivergenceKind::RetryCountMismatch,
+                        format!(
+                            "node_retrying reports retry {retry_count}, expected {}",
+                            node.retries + 1
+                        ),
+                    ));
+                }
+                node.retries = *retry_count;
+            }
+            Event::NodeCancelled { .. } => node.finished_at = Some(timestamp),
+            _ => {}
+        }
+
+        if let Some((kind, detail)) = divergence {
+            self.diverge(sequence, Some(node_id), kind, detail);
+        }
+    }
+}
+
+/// Rebuild a workflow's timeline from its envelopes, in log order
+pub fn replay<'a>(
+    workflow_id: Uuid,
+    envelopes: impl IntoIterator<Item = &'a EventEnvelope>,
+) -> ReplayReport {
+    let mut replayer = Replayer::new(workflow_id);
+    for envelope in envelopes {
+        replayer.apply(envelope);
+    }
+    replayer.finish()
+}
+
+/// Read a workflow's events from a WAL and rebuild its timeline
+pub fn replay_workflow<W: WalBackend + ?Sized>(
+    wal: &W,
+    workflow_id: Uuid,
+) -> Result<ReplayReport, WalError> {
+    let envelopes = wal.read_filtered(&EventFilter::new().workflow(workflow_id))?;
+    Ok(replay(workflow_id, &envelopes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn envelopes(events: Vec<Event>) -> Vec<EventEnvelope> {
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(i, e)| EventEnvelope::new(i as u64 + 1, e))
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_rebuilds_timeline() {
+        let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let t0 = Utc::now();
+        let at = |ms| t0 + Duration::milliseconds(ms);
+        let log = envelopes(vec![
+            Event::WorkflowStarted {
+                workflow_id,
+                name: "etl".to_string(),
+                timestamp: at(0),
+            },
+            Event::NodeScheduled {
+                workflow_id,
+                node_id,
+                server: "a".to_string(),
+                timestamp: at(1),
+            },
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp: at(2),
+            },
+            Event::NodeFailed {
+                workflow_id,
+                node_id,
+                error: "boom".to_string(),
+                retry_count: 0,
+                timestamp: at(10),
+            },
+            Event::NodeRetrying {
+                workflow_id,
+                node_id,
+                retry_count: 1,
+                delay_ms: 5,
+                timestamp: at(11),
+            },
+            Event::NodeScheduled {
+                workflow_id,
+                node_id,
+                server: "b".to_string(),
+                timestamp: at(16),
+            },
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp: at(17),
+            },
+            Event::NodeCompleted {
+                workflow_id,
+                node_id,
+                output_refs: vec![],
+                duration_ms: 20,
+                timestamp: at(37),
+            },
+            Event::WorkflowCompleted {
+                workflow_id,
+                duration_ms: 40,
+                timestamp: at(40),
+            },
+        ]);
+
+        let report = replay(workflow_id, &log);
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        assert_eq!(report.state, ReplayedWorkflowState::Completed);
+        assert_eq!(report.name.as_deref(), Some("etl"));
+
+        let node = report.node(node_id).unwrap();
+        assert_eq!(node.state, ReplayedNodeState::Completed);
+        assert_eq!(node.server.as_deref(), Some("b"));
+        assert_eq!((node.retries, node.attempts), (1, 2));
+        assert_eq!(node.duration_ms, Some(20));
+        assert_eq!(node.last_error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn test_replay_scheduling_story() {
+        let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Utc::now();
+        let log = envelopes(vec![
+            Event::WorkflowStarted {
+                workflow_id,
+                name: "etl".to_string(),
+                timestamp: now,
+            },
+            Event::NodeQueued {
+                workflow_id,
+                node_id,
+                node_type: "llm.chat".to_string(),
+                timestamp: now,
+            },
+            Event::SchedulingDecisionMade {
+                workflow_id,
+                node_id,
+                strategy: "least_loaded".to_string(),
+                chosen_server: "a".to_string(),
+                candidates: vec!["a".to_string(), "b".to_string()],
+                reason: "lowest load".to_string(),
+                timestamp: now,
+            },
+            Event::NodeScheduled {
+                workflow_id,
+                node_id,
+                server: "a".to_string(),
+                timestamp: now,
+            },
+            Event::NodeDispatchFailed {
+                workflow_id,
+                node_id,
+                server: "a".to_string(),
+                error: "connection refused".to_string(),
+                timestamp: now,
+            },
+            Event::NodeScheduled {
+                workflow_id,
+                node_id,
+                server: "b".to_string(),
+                timestamp: now,
+            },
+            Event::NodeStarted {
+                workflow_id,
+                node_id,
+                timestamp: now,
+            },
+        ]);
+
+        let report = replay(workflow_id, &log);
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        let node = report.node(node_id).unwrap();
+        assert_eq!(node.state, ReplayedNodeState::Running);
+        assert_eq!(node.queued_at, Some(now));
+        assert_eq!(node.server.as_deref(), Some("b"));
+        assert_eq!(node.dispatch_failures, 1);
+        assert_eq!(
+            node.scheduling_reason.as_deref(),
+            Some("least_loaded: lowest load")
+        );
+    }
+
+    #[test]
+    fn test_replay_detects_divergences() {
+        let (workflow_id, a, b) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let now = Utc::now();
+        let log = envelopes(vec![
+            Event::WorkflowStarted {
+                workflow_id,
+                name: "broken".to_string(),
+                timestamp: now,
+            },
+            Event::NodeCompleted {
+                workflow_id,
+                node_id: a,
+                output_refs: vec![],
+                duration_ms: 1,
+                timestamp: now,
+            },
+            Event::NodeScheduled {
+                workflow_id,
+                node_id: b,
+                server: "a".to_string(),
+                timestamp: now,
+            },
+            Event::WorkflowCompleted {
+                workflow_id,
+                duration_ms: 1,
+                timestamp: now,
+            },
+        ]);
+
+        let report = replay(workflow_id, &log);
+        let kinds: Vec<_> = report.divergences.iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DivergenceKind::MissingPredecessor,
+                DivergenceKind::UnfinishedAtCompletion
+            ]
+        );
+        assert_eq!(report.divergences[0].node_id, Some(a));
+        assert_eq!(report.divergences[1].node_id, Some(b));
+        // The rebuilt state still reflects what the log says
+        assert_eq!(report.node(a).unwrap().state, ReplayedNodeState::Completed);
+    }
+}
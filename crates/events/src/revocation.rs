@@ -0,0 +1,156 @@
+//! Persistent access-token revocations
+//!
+//! Revoked data-access tokens must stay rejected across restarts, so the
+//! revocation list is stored in the WAL database alongside the events.
+//! An entry revokes either one token or every token issued for a workflow
+//! up to a point in time. Entries are only needed while the tokens they
+//! cover can still be presented, so each carries an expiry after which
+//! [`WriteAheadLog::prune_token_revocations`] removes it.
+
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::wal::{format_timestamp, parse_column, WalError, WriteAheadLog};
+
+/// What a revocation covers
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum RevocationSubject {
+    /// A single token, by its ID
+    Token(String),
+    /// Every token of a workflow issued up to the revocation time
+    Workflow(Uuid),
+}
+
+impl RevocationSubject {
+    fn kind(&self) -> &'static str {
+        match self {
+            RevocationSubject::Token(_) => "token",
+            RevocationSubject::Workflow(_) => "workflow",
+        }
+    }
+
+    fn id(&self) -> String {
+        match self {
+            RevocationSubject::Token(id) => id.clone(),
+            RevocationSubject::Workflow(id) => id.to_string(),
+        }
+    }
+
+    fn parse(kind: &str, id: String) -> Result<Self, WalError> {
+        match kind {
+            "token" => Ok(RevocationSubject::Token(id)),
+            "workflow" => Ok(RevocationSubject::Workflow(parse_column(&id)?)),
+            other => Err(WalError::Corrupt(format!(
+                "unknown revocation kind '{other}'"
+            ))),
+        }
+    }
+}
+
+/// A stored revocation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenRevocation {
+    /// Token or workflow revoked
+    pub subject: RevocationSubject,
+    /// When the revocation was made
+    pub revoked_at: DateTime<Utc>,
+    /// When every covered token has expired and the entry can be pruned
+    pub expires_at: DateTime<Utc>,
+}
+
+impl WriteAheadLog {
+    /// Store a revocation, replacing any earlier one for the same subject
+    pub fn record_token_revocation(
+        &mut self,
+        revocation: &TokenRevocation,
+    ) -> Result<(), WalError> {
+        self.conn.execute(
+            "INSERT INTO token_revocations (kind, subject, revoked_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(kind, subject) DO UPDATE
+             SET revoked_at = excluded.revoked_at, expires_at = excluded.expires_at",
+            params![
+                revocation.subject.kind(),
+                revocation.subject.id(),
+                format_timestamp(revocation.revoked_at),
+                format_timestamp(revocation.expires_at),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// List stored revocations, oldest first
+    pub fn token_revocations(&self) -> Result<Vec<TokenRevocation>, WalError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT kind, subject, revoked_at, expires_at FROM token_revocations
+             ORDER BY revoked_at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (kind, subject, revoked_at, expires_at) = row?;
+            Ok(TokenRevocation {
+                subject: RevocationSubject::parse(&kind, subject)?,
+                revoked_at: parse_column(&revoked_at)?,
+                expires_at: parse_column(&expires_at)?,
+            })
+        })
+        .collect()
+    }
+
+    /// Remove revocations that expired before `now`
+    ///
+    /// Returns the number of entries removed.
+    pub fn prune_token_revocations(&mut self, now: DateTime<Utc>) -> Result<usize, WalError> {
+        let removed = self.conn.execute(
+            "DELETE FROM token_revocations WHERE expires_at < ?1",
+            params![format_timestamp(now)],
+        )?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_token_revocations_round_trip_and_prune() {
+        let mut wal = WriteAheadLog::in_memory().unwrap();
+        let now = Utc::now();
+        let workflow_id = Uuid::new_v4();
+        let token = TokenRevocation {
+            subject: RevocationSubject::Token("abc".to_string()),
+            revoked_at: now,
+            expires_at: now + Duration::hours(1),
+        };
+        let workflow = TokenRevocation {
+            subject: RevocationSubject::Workflow(workflow_id),
+            revoked_at: now,
+            expires_at: now - Duration::seconds(1),
+        };
+        wal.record_token_revocation(&token).unwrap();
+        wal.record_token_revocation(&workflow).unwrap();
+        // Re-revoking replaces the entry
+        wal.record_token_revocation(&token).unwrap();
+
+        let stored = wal.token_revocations().unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().any(|r| r.subject == workflow.subject));
+
+        assert_eq!(wal.prune_token_revocations(now).unwrap(), 1);
+        let stored = wal.token_revocations().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].subject, token.subject);
+    }
+}
@@ -0,0 +1,476 @@
+//! Segmented append-only file WAL backend
+//!
+//! Events are written to a directory of segment files, each named after
+//! the first sequence number it holds (`00000000000000000001.log`). Once
+//! the active segment grows past [`SegmentConfig::max_segment_bytes`] a new
+//! one is started; sealed segments are never modified again and can be
+//! shipped to object storage as-is.
+//!
+//! Each appended batch is one frame:
+//!
+//! ```text
+//! +----------------+----------------+---------------------------------+
+//! | len: u32 (LE)  | crc32: u32 (LE)| payload: JSON [EventEnvelope]   |
+//! +----------------+----------------+---------------------------------+
+//! ```
+//!
+//! A batch is therefore committed atomically: a frame torn by a crash fails
+//! its length or checksum check and is truncated when the log is reopened.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::types::{Event, EventEnvelope, EventFilter};
+use crate::wal::{AppendEntry, WalBackend, WalError};
+
+/// Size of the frame header in bytes
+const FRAME_HEADER_LEN: usize = 8;
+
+/// Segment file extension
+const SEGMENT_EXTENSION: &str = "log";
+
+/// Segmented WAL configuration
+#[derive(Debug, Clone)]
+pub struct SegmentConfig {
+    /// Size after which the active segment is sealed and a new one started
+    pub max_segment_bytes: u64,
+    /// Whether to fsync after every append
+    pub sync_on_append: bool,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_bytes: 64 * 1024 * 1024,
+            sync_on_append: true,
+        }
+    }
+}
+
+/// Where an appended event's envelope comes from
+enum Slot {
+    /// Index into the envelopes written by this batch
+    New(usize),
+    /// Sequence number of an earlier event with the same idempotency key
+    Existing(u64),
+}
+
+/// A segment file on disk
+#[derive(Debug, Clone)]
+struct Segment {
+    /// First sequence number stored in the segment
+    base_sequence: u64,
+    /// Path to the segment file
+    path: PathBuf,
+    /// Current size in bytes
+    size: u64,
+}
+
+/// Append-only WAL stored as rotating segment files
+pub struct SegmentedWal {
+    dir: PathBuf,
+    config: SegmentConfig,
+    /// Segments ordered by base sequence; the last one is active
+    segments: Vec<Segment>,
+    /// Open handle to the active segment
+    active: File,
+    /// Next sequence number to assign
+    next_sequence: u64,
+    /// Number of events stored
+    count: u64,
+    /// Sequence number of the event holding each idempotency key
+    keys: HashMap<String, u64>,
+}
+
+impl SegmentedWal {
+    /// Open or create a segmented WAL in the given directory
+    ///
+    /// A torn frame at the end of the newest segment is truncated; corruption
+    /// anywhere else is reported as [`WalError::Corrupt`].
+    pub fn open<P: AsRef<Path>>(dir: P, config: SegmentConfig) -> Result<Self, WalError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let Some(base_sequence) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+            else {
+                continue;
+            };
+            let size = fs::metadata(&path)?.len();
+            segments.push(Segment {
+                base_sequence,
+                path,
+                size,
+            });
+        }
+        segments.sort_by_key(|s| s.base_sequence);
+
+        let mut next_sequence = 1;
+        let mut count = 0;
+        let mut keys = HashMap::new();
+        let last = segments.len().saturating_sub(1);
+        for (i, segment) in segments.iter_mut().enumerate() {
+            let data = fs::read(&segment.path)?;
+            let (payloads, valid_len) = decode_frames(&data);
+            if valid_len < data.len() {
+                if i != last {
+                    return Err(WalError::Corrupt(format!(
+                        "invalid frame at offset {valid_len} in sealed segment {}",
+                        segment.path.display()
+                    )));
+                }
+                tracing::warn!(
+                    segment = %segment.path.display(),
+                    offset = valid_len,
+                    "truncating torn frame at end of WAL segment"
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(&segment.path)?
+                    .set_len(valid_len as u64)?;
+                segment.size = valid_len as u64;
+            }
+            for payload in payloads {
+                for envelope in parse_batch(payload)? {
+                    count += 1;
+                    next_sequence = next_sequence.max(envelope.sequence + 1);
+                    if let Some(key) = envelope.idempotency_key {
+                        keys.insert(key, envelope.sequence);
+                    }
+                }
+            }
+        }
+
+        if segments.is_empty() {
+            segments.push(Segment::new(&dir, next_sequence));
+        }
+        let active = open_for_append(&segments[segments.len() - 1].path)?;
+
+        Ok(Self {
+            dir,
+            config,
+            segments,
+            active,
+            next_sequence,
+            count,
+            keys,
+        })
+    }
+
+    /// Get the directory holding the segments
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the paths of all segments, oldest first
+    pub fn segment_paths(&self) -> Vec<PathBuf> {
+        self.segments.iter().map(|s| s.path.clone()).collect()
+    }
+
+    /// Get the paths of sealed segments, which will not be written again
+    pub fn sealed_segments(&self) -> Vec<PathBuf> {
+        let sealed = self.segments.len().saturating_sub(1);
+        self.segments[..sealed]
+            .iter()
+            .map(|s| s.path.clone())
+            .collect()
+    }
+
+    /// Seal the active segment and start a new one
+    fn roll(&mut self) -> Result<(), WalError> {
+        self.active.sync_all()?;
+        let segment = Segment::new(&self.dir, self.next_sequence);
+        self.active = open_for_append(&segment.path)?;
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    /// Write a batch of new envelopes as one frame
+    fn write_batch(&mut self, envelopes: &[EventEnvelope]) -> Result<(), WalError> {
+        let frame = encode_frame(envelopes)?;
+
+        let max_segment_bytes = self.config.max_segment_bytes;
+        let active_size = self.active_segment().size;
+        if active_size > 0 && active_size + frame.len() as u64 > max_segment_bytes {
+            self.roll()?;
+        }
+
+        let written = self.active.write_all(&frame).and_then(|_| {
+            if self.config.sync_on_append {
+                self.active.sync_data()
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = written {
+            // Drop any partial frame so the segment stays well-formed
+            let size = self.active_segment().size;
+            let _ = self.active.set_len(size);
+            return Err(e.into());
+        }
+
+        self.active_segment().size += frame.len() as u64;
+        self.next_sequence += envelopes.len() as u64;
+        self.count += envelopes.len() as u64;
+        for envelope in envelopes {
+            if let Some(key) = &envelope.idempotency_key {
+                self.keys.insert(key.clone(), envelope.sequence);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read back the envelope with the given sequence number
+    fn read_sequence(&self, sequence: u64) -> Result<EventEnvelope, WalError> {
+        self.read_filtered(&EventFilter::new().from_sequence(sequence).limit(1))?
+            .into_iter()
+            .next()
+            .filter(|envelope| envelope.sequence == sequence)
+            .ok_or_else(|| WalError::Corrupt(format!("missing event {sequence}")))
+    }
+
+    fn active_segment(&mut self) -> &mut Segment {
+        self.segments
+            .last_mut()
+            .expect("segmented WAL always has an active segment")
+    }
+}
+
+impl WalBackend for SegmentedWal {
+    fn append_batch(&mut self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError> {
+        self.append_entries(events.into_iter().map(AppendEntry::new).collect())
+    }
+
+    fn append_entries(
+        &mut self,
+        entries: Vec<AppendEntry>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        let mut slots = Vec::with_capacity(entries.len());
+        let mut envelopes: Vec<EventEnvelope> = Vec::new();
+        let mut batch_keys: HashMap<String, usize> = HashMap::new();
+
+        for entry in entries {
+            if let Some(key) = &entry.idempotency_key {
+                if let Some(&sequence) = self.keys.get(key) {
+                    slots.push(Slot::Existing(sequence));
+                    continue;
+                }
+                if let Some(&index) = batch_keys.get(key) {
+                    slots.push(Slot::New(index));
+                    continue;
+                }
+                batch_keys.insert(key.clone(), envelopes.len());
+            }
+            let sequence = self.next_sequence + envelopes.len() as u64;
+            slots.push(Slot::New(envelopes.len()));
+            envelopes.push(entry.into_envelope(sequence));
+        }
+
+        if !envelopes.is_empty() {
+            self.write_batch(&envelopes)?;
+        }
+        slots
+            .into_iter()
+            .map(|slot| match slot {
+                Slot::New(index) => Ok(envelopes[index].clone()),
+                Slot::Existing(sequence) => self.read_sequence(sequence),
+            })
+            .collect()
+    }
+
+    fn read_filtered(&self, filter: &EventFilter) -> Result<Vec<EventEnvelope>, WalError> {
+        let from_sequence = filter.from_sequence.unwrap_or(0);
+        let mut results = Vec::new();
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            // Skip segments that end before the requested sequence
+            if let Some(next) = self.segments.get(i + 1) {
+                if next.base_sequence <= from_sequence {
+                    continue;
+                }
+            }
+
+            let data = fs::read(&segment.path)?;
+            let (payloads, _) = decode_frames(&data);
+            for payload in payloads {
+                for envelope in parse_batch(payload)? {
+                    if !filter.matches(&envelope) {
+                        continue;
+                    }
+                    results.push(envelope);
+                    if filter.limit.is_some_and(|limit| results.len() >= limit) {
+                        return Ok(results);
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn last_sequence(&self) -> u64 {
+        self.next_sequence.saturating_sub(1)
+    }
+
+    fn count(&self) -> Result<u64, WalError> {
+        Ok(self.count)
+    }
+}
+
+impl Segment {
+    fn new(dir: &Path, base_sequence: u64) -> Self {
+        Self {
+            base_sequence,
+            path: dir.join(format!("{base_sequence:020}.{SEGMENT_EXTENSION}")),
+            size: 0,
+        }
+    }
+}
+
+fn open_for_append(path: &Path) -> Result<File, WalError> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Encode a batch of envelopes as a single frame
+fn encode_frame(envelopes: &[EventEnvelope]) -> Result<Vec<u8>, WalError> {
+    let payload = serde_json::to_vec(envelopes)?;
+    let len = u32::try_from(payload.len())
+        .map_err(|_| WalError::Corrupt(format!("batch too large: {} bytes", payload.len())))?;
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Split data into consecutive frames, stopping at the first invalid one
+///
+/// Returns the checksum-verified payloads and the length of the valid
+/// prefix.
+fn decode_frames(data: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut payloads = Vec::new();
+    let mut offset = 0;
+
+    while data.len() - offset >= FRAME_HEADER_LEN {
+        let header = &data[offset..offset + FRAME_HEADER_LEN];
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+
+        let start = offset + FRAME_HEADER_LEN;
+        let Some(payload) = data.get(start..start + len) else {
+            break;
+        };
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        payloads.push(payload);
+        offset = start + len;
+    }
+
+    (payloads, offset)
+}
+
+/// Parse a verified frame payload, migrating older event schemas
+fn parse_batch(payload: &[u8]) -> Result<Vec<EventEnvelope>, WalError> {
+    Ok(serde_json::from_slice(payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn event() -> Event {
+        Event::NodeStarted {
+            workflow_id: Uuid::new_v4(),
+            node_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn small_segments() -> SegmentConfig {
+        SegmentConfig {
+            max_segment_bytes: 512,
+            sync_on_append: false,
+        }
+    }
+
+    #[test]
+    fn test_rotation_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut wal = SegmentedWal::open(dir.path(), small_segments()).unwrap();
+            for _ in 0..20 {
+                wal.append(event()).unwrap();
+            }
+            assert!(wal.segment_paths().len() > 1);
+            assert_eq!(wal.sealed_segments().len(), wal.segment_paths().len() - 1);
+        }
+
+        let mut wal = SegmentedWal::open(dir.path(), small_segments()).unwrap();
+        assert_eq!(wal.last_sequence(), 20);
+        assert_eq!(wal.count().unwrap(), 20);
+        assert_eq!(wal.append(event()).unwrap().sequence, 21);
+
+        let tail = wal.read_from(15).unwrap();
+        assert_eq!(
+            tail.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+            (15..=21).collect::<Vec<_>>()
+        );
+        let page = wal
+            .read_filtered(&EventFilter::new().from_sequence(3).limit(2))
+            .unwrap();
+        assert_eq!(
+            page.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn test_idempotent_append_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = {
+            let mut wal = SegmentedWal::open(dir.path(), SegmentConfig::default()).unwrap();
+            wal.append(event()).unwrap();
+            wal.append_idempotent("key", event()).unwrap()
+        };
+
+        let mut wal = SegmentedWal::open(dir.path(), SegmentConfig::default()).unwrap();
+        let retried = wal.append_idempotent("key", event()).unwrap();
+        assert_eq!((retried.id, retried.sequence), (original.id, 2));
+        assert_eq!(wal.count().unwrap(), 2);
+        assert_eq!(wal.append(event()).unwrap().sequence, 3);
+    }
+
+    #[test]
+    fn test_torn_frame_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = {
+            let mut wal = SegmentedWal::open(dir.path(), SegmentConfig::default()).unwrap();
+            wal.append_batch(vec![event(), event()]).unwrap();
+            wal.segment_paths()[0].clone()
+        };
+
+        // Simulate a crash halfway through writing the next frame
+        let frame = encode_frame(&[EventEnvelope::new(3, event())]).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&frame[..frame.len() / 2]).unwrap();
+
+        let mut wal = SegmentedWal::open(dir.path(), SegmentConfig::default()).unwrap();
+        assert_eq!(wal.count().unwrap(), 2);
+        assert_eq!(wal.append(event()).unwrap().sequence, 3);
+        assert_eq!(wal.read_from(1).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,139 @@
+//! Tail-follow streams over the WAL
+//!
+//! [`WriteAheadLog::tail`] returns a stream that first yields every event
+//! already in the log from a given sequence number on, then waits for new
+//! ones. It reads through a [`WalReader`] and is woken by a watch channel
+//! the log updates after each commit, so it keeps following the log after
+//! the log itself has moved onto an [`AsyncWal`](crate::async_wal::AsyncWal)
+//! writer thread. The stream ends once the log is dropped and every
+//! committed event has been yielded.
+//!
+//! Consumers of an [`EventBus`](crate::bus::EventBus) get the same
+//! behavior, with filtering, from [`EventBus::tail`](crate::bus::EventBus::tail).
+
+use std::collections::VecDeque;
+
+use futures_util::stream::{self, Stream};
+use tokio::sync::watch;
+
+use crate::reader::WalReader;
+use crate::types::{EventEnvelope, EventFilter};
+use crate::wal::{WalError, WriteAheadLog};
+
+/// Maximum number of events read from the log at once
+const TAIL_BATCH_SIZE: usize = 256;
+
+/// State of a tail stream between items
+struct Tail {
+    reader: WalReader,
+    committed: watch::Receiver<u64>,
+    /// Next sequence number to read
+    next: u64,
+    buffer: VecDeque<EventEnvelope>,
+}
+
+impl Tail {
+    async fn next_envelope(&mut self) -> Option<Result<EventEnvelope, WalError>> {
+        loop {
+            if let Some(envelope) = self.buffer.pop_front() {
+                return Some(Ok(envelope));
+            }
+
+            let committed = *self.committed.borrow_and_update();
+            if committed < self.next {
+                // An error means the log was dropped after its last commit
+                self.committed.changed().await.ok()?;
+                continue;
+            }
+
+            let reader = self.reader.clone();
+            let filter = EventFilter::new()
+                .from_sequence(self.next)
+                .limit(TAIL_BATCH_SIZE);
+            let read = tokio::task::spawn_blocking(move || reader.read_filtered(&filter))
+                .await
+                .map_err(|e| WalError::Io(std::io::Error::other(e)));
+            let envelopes = match read {
+                Ok(Ok(envelopes)) => envelopes,
+                Ok(Err(e)) | Err(e) => return Some(Err(e)),
+            };
+
+            // Compaction may have removed the newest committed events
+            self.next = match envelopes.last() {
+                Some(last) => last.sequence + 1,
+                None => committed + 1,
+            };
+            self.buffer.extend(envelopes);
+        }
+    }
+}
+
+impl WriteAheadLog {
+    /// Follow the log from `from_sequence`, yielding existing then new events
+    ///
+    /// Returns `None` for in-memory logs, which have no
+    /// [`reader`](WriteAheadLog::reader). The stream does not borrow the
+    /// log. Read errors are yielded as items; the stream continues after
+    /// them.
+    pub fn tail(
+        &self,
+        from_sequence: u64,
+    ) -> Option<impl Stream<Item = Result<EventEnvelope, WalError>> + Send + 'static> {
+        let tail = Tail {
+            reader: self.reader()?,
+            committed: self.committed.subscribe(),
+            next: from_sequence.max(1),
+            buffer: VecDeque::new(),
+        };
+        Some(stream::unfold(tail, |mut tail| async move {
+            let item = tail.next_envelope().await?;
+            Some((item, tail))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_wal::{AsyncWal, AsyncWalConfig};
+    use crate::types::Event;
+    use chrono::Utc;
+    use futures_util::StreamExt;
+    use uuid::Uuid;
+
+    fn deleted() -> Event {
+        Event::DataDeleted {
+            data_uuid: Uuid::new_v4(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tail_follows_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wal = WriteAheadLog::open(dir.path().join("events.db")).unwrap();
+        for _ in 0..3 {
+            wal.append(deleted()).unwrap();
+        }
+        assert!(WriteAheadLog::in_memory().unwrap().tail(1).is_none());
+
+        let tail = wal.tail(2).unwrap();
+        let wal = AsyncWal::new(wal, AsyncWalConfig::default()).unwrap();
+        let mut tail = Box::pin(tail);
+
+        assert_eq!(tail.next().await.unwrap().unwrap().sequence, 2);
+        assert_eq!(tail.next().await.unwrap().unwrap().sequence, 3);
+
+        let appended = wal.append(deleted()).await.unwrap();
+        assert_eq!(
+            tail.next().await.unwrap().unwrap().sequence,
+            appended.sequence
+        );
+
+        // Dropping the log ends the stream once it has caught up
+        wal.append(deleted()).await.unwrap();
+        wal.shutdown().await.unwrap();
+        assert_eq!(tail.next().await.unwrap().unwrap().sequence, 5);
+        assert!(tail.next().await.is_none());
+    }
+}
@@ -3,6 +3,8 @@
 //! All events are persisted to a Write-Ahead Log (WAL) for crash recovery.
 //! Optional Kafka integration provides stronger durability guarantees.
 
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -45,6 +47,29 @@ pub enum Event {
     // ========================================================================
     // Node Events
     // ========================================================================
+    /// Node is ready to run and waiting for the scheduler
+    NodeQueued {
+        workflow_id: Uuid,
+        node_id: Uuid,
+        /// Node type, e.g. `llm.chat`
+        node_type: String,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Scheduler picked a server for a node
+    SchedulingDecisionMade {
+        workflow_id: Uuid,
+        node_id: Uuid,
+        /// Scheduling strategy that made the decision
+        strategy: String,
+        chosen_server: String,
+        /// Servers that were considered
+        candidates: Vec<String>,
+        /// Why `chosen_server` won
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Node scheduled for execution on a server
     NodeScheduled {
         workflow_id: Uuid,
@@ -53,6 +78,15 @@ pub enum Event {
         timestamp: DateTime<Utc>,
     },
 
+    /// Node could not be handed to its scheduled server
+    NodeDispatchFailed {
+        workflow_id: Uuid,
+        node_id: Uuid,
+        server: String,
+        error: String,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Node execution started
     NodeStarted {
         workflow_id: Uuid,
@@ -69,6 +103,20 @@ pub enum Event {
         timestamp: DateTime<Utc>,
     },
 
+    /// Chunk of output a running node streamed before completing, e.g.
+    /// tokens of an LLM response
+    ///
+    /// Chunks of an output are numbered from 0 and appended in order; the
+    /// complete output still arrives with `NodeCompleted`.
+    NodeOutputChunk {
+        workflow_id: Uuid,
+        node_id: Uuid,
+        output: String,
+        sequence: u64,
+        chunk: String,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Node execution completed successfully
     NodeCompleted {
         workflow_id: Uuid,
@@ -96,6 +144,14 @@ pub enum Event {
         timestamp: DateTime<Utc>,
     },
 
+    /// Node was cancelled, by the user or because it can no longer run
+    NodeCancelled {
+        workflow_id: Uuid,
+        node_id: Uuid,
+        reason: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+
     // ========================================================================
     // Data Events
     // ========================================================================
@@ -105,6 +161,19 @@ pub enum Event {
         workflow_id: Uuid,
         location: String,
         size_bytes: u64,
+        /// Labels for routing, e.g. `raw`; matched by workflow triggers
+        #[serde(default)]
+        tags: Vec<String>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Data object produced by a node from other data objects
+    DataDerivedFrom {
+        data_uuid: Uuid,
+        /// Inputs the node read, including prompts stored as data
+        parent_uuids: Vec<Uuid>,
+        workflow_id: Uuid,
+        node_id: Uuid,
         timestamp: DateTime<Utc>,
     },
 
@@ -117,6 +186,57 @@ pub enum Event {
         timestamp: DateTime<Utc>,
     },
 
+    /// Bytes of a data upload or download moved so far
+    DataTransferProgress {
+        data_uuid: Uuid,
+        workflow_id: Uuid,
+        /// `upload` or `download`
+        direction: String,
+        bytes_transferred: u64,
+        /// Bytes the transfer will move, if known
+        total_bytes: Option<u64>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Data copied to another server, which now holds a replica
+    DataReplicated {
+        data_uuid: Uuid,
+        from_server: String,
+        to_server: String,
+        duration_ms: u64,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Replica of data dropped from a server
+    DataReplicaRemoved {
+        data_uuid: Uuid,
+        server: String,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Input copied ahead of time to the server a queued node will run on
+    DataPrefetchStarted {
+        data_uuid: Uuid,
+        workflow_id: Uuid,
+        node_id: Uuid,
+        from_server: String,
+        to_server: String,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Prefetched input arrived and its node started
+    DataPrefetched {
+        data_uuid: Uuid,
+        workflow_id: Uuid,
+        node_id: Uuid,
+        to_server: String,
+        /// Time from the prefetch request to the copy arriving
+        duration_ms: u64,
+        /// Part of the transfer that ran while the node was still queued
+        overlap_ms: u64,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Data deleted
     DataDeleted {
         data_uuid: Uuid,
@@ -131,6 +251,83 @@ pub enum Event {
         timestamp: DateTime<Utc>,
     },
 
+    /// Eviction policy chose data to offload from a full tier
+    DataEvicted {
+        data_uuid: Uuid,
+        workflow_id: Uuid,
+        /// Server holding the data
+        server: String,
+        from_tier: String,
+        to_tier: String,
+        /// Eviction policy of the tier, e.g. `lru`
+        policy: String,
+        /// Policy's score for the data; lower scores are evicted first
+        score: f64,
+        size_bytes: u64,
+        last_access: DateTime<Utc>,
+        /// Reads of the data since it was tracked
+        access_count: u64,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Stored bytes of a workflow or tenant crossed a share of its quota
+    DataQuotaWarning {
+        /// What the quota applies to, e.g. `workflow:<id>` or `tenant:<name>`
+        scope: String,
+        /// Workflow whose data crossed the threshold
+        workflow_id: Uuid,
+        used_bytes: u64,
+        limit_bytes: u64,
+        /// Share of the quota crossed, in percent
+        threshold_percent: u8,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Stored data no longer matches its checksum and was quarantined
+    DataCorrupted {
+        data_uuid: Uuid,
+        workflow_id: Uuid,
+        /// Store the corrupt copy was found in
+        location: String,
+        expected_checksum: Option<String>,
+        /// Checksum of what was read; `None` if the data was unreadable
+        actual_checksum: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Integrity scrub of a data store finished
+    DataScrubCompleted {
+        location: String,
+        /// Pieces of data whose checksums were verified
+        checked: u64,
+        /// Pieces of data found corrupt and quarantined
+        corrupted: u64,
+        duration_ms: u64,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Garbage collection pass reconciling the catalog with the data the
+    /// servers report holding finished
+    DataGcCompleted {
+        /// Whether deletions were only reported, not carried out
+        dry_run: bool,
+        /// Servers whose inventories were reconciled
+        servers: u64,
+        /// Copies a server holds that the catalog does not know
+        leaked: u64,
+        /// Cataloged copies no server reports holding
+        missing: u64,
+        /// Leaked copies deleted, or that would be in a dry run
+        deleted: u64,
+        /// Cataloged copies dropped from the catalog, or that would be in a
+        /// dry run
+        uncataloged: u64,
+        /// Deletions postponed to a later pass by the rate limit
+        deferred: u64,
+        duration_ms: u64,
+        timestamp: DateTime<Utc>,
+    },
+
     // ========================================================================
     // Server Events
     // ========================================================================
@@ -155,6 +352,121 @@ pub enum Event {
         reason: Option<String>,
         timestamp: DateTime<Utc>,
     },
+
+    // ========================================================================
+    // Metric Events
+    // ========================================================================
+    /// Periodic sample of scheduler and server load
+    MetricSample {
+        /// Nodes scheduled or awaiting retry but not yet running
+        queue_depth: u64,
+        running_nodes: u64,
+        /// Load (0.0 to 1.0) of each registered server
+        server_loads: BTreeMap<String, f64>,
+        timestamp: DateTime<Utc>,
+    },
+
+    // ========================================================================
+    // Audit Events
+    // ========================================================================
+    /// Control-plane action taken by a user or service
+    Audit {
+        /// Who performed the action, e.g. a user name or token subject
+        actor: String,
+        action: AuditAction,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+/// Control-plane action recorded in the audit trail
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AuditAction {
+    WorkflowCreated {
+        workflow_id: Uuid,
+        name: String,
+    },
+    WorkflowUpdated {
+        workflow_id: Uuid,
+    },
+    WorkflowDeleted {
+        workflow_id: Uuid,
+    },
+    ExecutionCancelled {
+        workflow_id: Uuid,
+        execution_id: Uuid,
+        reason: Option<String>,
+    },
+    ServerRegistered {
+        server_address: String,
+    },
+    TokenIssued {
+        /// Identity the token was issued to
+        subject: String,
+        scopes: Vec<String>,
+    },
+    /// A trigger requested an execution of its workflow
+    WorkflowTriggered {
+        workflow_id: Uuid,
+        trigger_id: String,
+        /// Sequence number of the event that fired the trigger
+        source_sequence: u64,
+        /// Variables to start the execution with
+        variables: serde_json::Value,
+    },
+    /// Data was read, written, or deleted, or access to it was refused
+    DataAccessed {
+        data_uuid: Uuid,
+        /// Workflow the data belongs to, if it is known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workflow_id: Option<Uuid>,
+        /// Permission the access required: `read`, `write`, or `delete`
+        permission: String,
+        /// ID of the access token presented, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_id: Option<String>,
+        granted: bool,
+        /// Why access was refused or failed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+}
+
+impl AuditAction {
+    /// Get the action name, as used in the serialized `action` tag
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditAction::WorkflowCreated { .. } => "workflow_created",
+            AuditAction::WorkflowUpdated { .. } => "workflow_updated",
+            AuditAction::WorkflowDeleted { .. } => "workflow_deleted",
+            AuditAction::ExecutionCancelled { .. } => "execution_cancelled",
+            AuditAction::ServerRegistered { .. } => "server_registered",
+            AuditAction::TokenIssued { .. } => "token_issued",
+            AuditAction::WorkflowTriggered { .. } => "workflow_triggered",
+            AuditAction::DataAccessed { .. } => "data_accessed",
+        }
+    }
+
+    /// Get the workflow the action applies to, if any
+    pub fn workflow_id(&self) -> Option<Uuid> {
+        match self {
+            AuditAction::WorkflowCreated { workflow_id, .. }
+            | AuditAction::WorkflowUpdated { workflow_id }
+            | AuditAction::WorkflowDeleted { workflow_id }
+            | AuditAction::ExecutionCancelled { workflow_id, .. }
+            | AuditAction::WorkflowTriggered { workflow_id, .. } => Some(*workflow_id),
+            AuditAction::DataAccessed { workflow_id, .. } => *workflow_id,
+            AuditAction::ServerRegistered { .. } | AuditAction::TokenIssued { .. } => None,
+        }
+    }
+
+    /// Get the data the action applies to, if any
+    pub fn data_uuid(&self) -> Option<Uuid> {
+        match self {
+            AuditAction::DataAccessed { data_uuid, .. } => Some(*data_uuid),
+            _ => None,
+        }
+    }
 }
 
 impl Event {
@@ -165,22 +477,86 @@ impl Event {
             Event::WorkflowCompleted { timestamp, .. } => *timestamp,
             Event::WorkflowFailed { timestamp, .. } => *timestamp,
             Event::WorkflowCancelled { timestamp, .. } => *timestamp,
+            Event::NodeQueued { timestamp, .. } => *timestamp,
+            Event::SchedulingDecisionMade { timestamp, .. } => *timestamp,
             Event::NodeScheduled { timestamp, .. } => *timestamp,
+            Event::NodeDispatchFailed { timestamp, .. } => *timestamp,
             Event::NodeStarted { timestamp, .. } => *timestamp,
             Event::NodeProgress { timestamp, .. } => *timestamp,
+            Event::NodeOutputChunk { timestamp, .. } => *timestamp,
             Event::NodeCompleted { timestamp, .. } => *timestamp,
             Event::NodeFailed { timestamp, .. } => *timestamp,
             Event::NodeRetrying { timestamp, .. } => *timestamp,
+            Event::NodeCancelled { timestamp, .. } => *timestamp,
             Event::DataCreated { timestamp, .. } => *timestamp,
+            Event::DataDerivedFrom { timestamp, .. } => *timestamp,
             Event::DataTransferred { timestamp, .. } => *timestamp,
+            Event::DataTransferProgress { timestamp, .. } => *timestamp,
+            Event::DataReplicated { timestamp, .. } => *timestamp,
+            Event::DataReplicaRemoved { timestamp, .. } => *timestamp,
+            Event::DataPrefetchStarted { timestamp, .. } => *timestamp,
+            Event::DataPrefetched { timestamp, .. } => *timestamp,
             Event::DataDeleted { timestamp, .. } => *timestamp,
             Event::DataTierChanged { timestamp, .. } => *timestamp,
+            Event::DataEvicted { timestamp, .. } => *timestamp,
+            Event::DataQuotaWarning { timestamp, .. } => *timestamp,
+            Event::DataCorrupted { timestamp, .. } => *timestamp,
+            Event::DataScrubCompleted { timestamp, .. } => *timestamp,
+            Event::DataGcCompleted { timestamp, .. } => *timestamp,
             Event::ServerRegistered { timestamp, .. } => *timestamp,
             Event::ServerHealthCheck { timestamp, .. } => *timestamp,
             Event::ServerDisconnected { timestamp, .. } => *timestamp,
+            Event::MetricSample { timestamp, .. } => *timestamp,
+            Event::Audit { timestamp, .. } => *timestamp,
         }
     }
 
+    /// Get the kind of this event
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::WorkflowStarted { .. } => EventKind::WorkflowStarted,
+            Event::WorkflowCompleted { .. } => EventKind::WorkflowCompleted,
+            Event::WorkflowFailed { .. } => EventKind::WorkflowFailed,
+            Event::WorkflowCancelled { .. } => EventKind::WorkflowCancelled,
+            Event::NodeQueued { .. } => EventKind::NodeQueued,
+            Event::SchedulingDecisionMade { .. } => EventKind::SchedulingDecisionMade,
+            Event::NodeScheduled { .. } => EventKind::NodeScheduled,
+            Event::NodeDispatchFailed { .. } => EventKind::NodeDispatchFailed,
+            Event::NodeStarted { .. } => EventKind::NodeStarted,
+            Event::NodeProgress { .. } => EventKind::NodeProgress,
+            Event::NodeOutputChunk { .. } => EventKind::NodeOutputChunk,
+            Event::NodeCompleted { .. } => EventKind::NodeCompleted,
+            Event::NodeFailed { .. } => EventKind::NodeFailed,
+            Event::NodeRetrying { .. } => EventKind::NodeRetrying,
+            Event::NodeCancelled { .. } => EventKind::NodeCancelled,
+            Event::DataCreated { .. } => EventKind::DataCreated,
+            Event::DataDerivedFrom { .. } => EventKind::DataDerivedFrom,
+            Event::DataTransferred { .. } => EventKind::DataTransferred,
+            Event::DataTransferProgress { .. } => EventKind::DataTransferProgress,
+            Event::DataReplicated { .. } => EventKind::DataReplicated,
+            Event::DataReplicaRemoved { .. } => EventKind::DataReplicaRemoved,
+            Event::DataPrefetchStarted { .. } => EventKind::DataPrefetchStarted,
+            Event::DataPrefetched { .. } => EventKind::DataPrefetched,
+            Event::DataDeleted { .. } => EventKind::DataDeleted,
+            Event::DataTierChanged { .. } => EventKind::DataTierChanged,
+            Event::DataEvicted { .. } => EventKind::DataEvicted,
+            Event::DataQuotaWarning { .. } => EventKind::DataQuotaWarning,
+            Event::DataCorrupted { .. } => EventKind::DataCorrupted,
+            Event::DataScrubCompleted { .. } => EventKind::DataScrubCompleted,
+            Event::DataGcCompleted { .. } => EventKind::DataGcCompleted,
+            Event::ServerRegistered { .. } => EventKind::ServerRegistered,
+            Event::ServerHealthCheck { .. } => EventKind::ServerHealthCheck,
+            Event::ServerDisconnected { .. } => EventKind::ServerDisconnected,
+            Event::MetricSample { .. } => EventKind::MetricSample,
+            Event::Audit { .. } => EventKind::Audit,
+        }
+    }
+
+    /// Get the event type name, as used in the serialized `type` tag
+    pub fn event_type(&self) -> &'static str {
+        self.kind().as_str()
+    }
+
     /// Get the workflow ID if applicable
     pub fn workflow_id(&self) -> Option<Uuid> {
         match self {
@@ -188,13 +564,26 @@ impl Event {
             Event::WorkflowCompleted { workflow_id, .. } => Some(*workflow_id),
             Event::WorkflowFailed { workflow_id, .. } => Some(*workflow_id),
             Event::WorkflowCancelled { workflow_id, .. } => Some(*workflow_id),
+            Event::NodeQueued { workflow_id, .. } => Some(*workflow_id),
+            Event::SchedulingDecisionMade { workflow_id, .. } => Some(*workflow_id),
             Event::NodeScheduled { workflow_id, .. } => Some(*workflow_id),
+            Event::NodeDispatchFailed { workflow_id, .. } => Some(*workflow_id),
             Event::NodeStarted { workflow_id, .. } => Some(*workflow_id),
             Event::NodeProgress { workflow_id, .. } => Some(*workflow_id),
+            Event::NodeOutputChunk { workflow_id, .. } => Some(*workflow_id),
             Event::NodeCompleted { workflow_id, .. } => Some(*workflow_id),
             Event::NodeFailed { workflow_id, .. } => Some(*workflow_id),
             Event::NodeRetrying { workflow_id, .. } => Some(*workflow_id),
+            Event::NodeCancelled { workflow_id, .. } => Some(*workflow_id),
             Event::DataCreated { workflow_id, .. } => Some(*workflow_id),
+            Event::DataDerivedFrom { workflow_id, .. } => Some(*workflow_id),
+            Event::DataTransferProgress { workflow_id, .. } => Some(*workflow_id),
+            Event::DataPrefetchStarted { workflow_id, .. } => Some(*workflow_id),
+            Event::DataPrefetched { workflow_id, .. } => Some(*workflow_id),
+            Event::DataQuotaWarning { workflow_id, .. } => Some(*workflow_id),
+            Event::DataCorrupted { workflow_id, .. } => Some(*workflow_id),
+            Event::DataEvicted { workflow_id, .. } => Some(*workflow_id),
+            Event::Audit { action, .. } => action.workflow_id(),
             _ => None,
         }
     }
@@ -202,12 +591,20 @@ impl Event {
     /// Get the node ID if applicable
     pub fn node_id(&self) -> Option<Uuid> {
         match self {
+            Event::NodeQueued { node_id, .. } => Some(*node_id),
+            Event::SchedulingDecisionMade { node_id, .. } => Some(*node_id),
             Event::NodeScheduled { node_id, .. } => Some(*node_id),
+            Event::NodeDispatchFailed { node_id, .. } => Some(*node_id),
             Event::NodeStarted { node_id, .. } => Some(*node_id),
             Event::NodeProgress { node_id, .. } => Some(*node_id),
+            Event::NodeOutputChunk { node_id, .. } => Some(*node_id),
             Event::NodeCompleted { node_id, .. } => Some(*node_id),
             Event::NodeFailed { node_id, .. } => Some(*node_id),
             Event::NodeRetrying { node_id, .. } => Some(*node_id),
+            Event::NodeCancelled { node_id, .. } => Some(*node_id),
+            Event::DataDerivedFrom { node_id, .. } => Some(*node_id),
+            Event::DataPrefetchStarted { node_id, .. } => Some(*node_id),
+            Event::DataPrefetched { node_id, .. } => Some(*node_id),
             _ => None,
         }
     }
@@ -222,6 +619,15 @@ impl Event {
         serde_json::from_str(json)
     }
 
+    /// Create an audit event timestamped now
+    pub fn audit(actor: impl Into<String>, action: AuditAction) -> Self {
+        Event::Audit {
+            actor: actor.into(),
+            action,
+            timestamp: Utc::now(),
+        }
+    }
+
     /// Check if this is a terminal event for a workflow
     pub fn is_workflow_terminal(&self) -> bool {
         matches!(
@@ -232,26 +638,424 @@ impl Event {
         )
     }
 
+    /// Get the severity of this event
+    pub fn severity(&self) -> Severity {
+        self.kind().severity()
+    }
+
     /// Check if this is a terminal event for a node
     pub fn is_node_terminal(&self) -> bool {
         matches!(
             self,
-            Event::NodeCompleted { .. } | Event::NodeFailed { .. }
+            Event::NodeCompleted { .. } | Event::NodeFailed { .. } | Event::NodeCancelled { .. }
         )
     }
 }
 
+/// Kind of an [`Event`], without its payload
+///
+/// Serializes to the same name as the event's `type` tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    WorkflowStarted,
+    WorkflowCompleted,
+    WorkflowFailed,
+    WorkflowCancelled,
+    NodeQueued,
+    SchedulingDecisionMade,
+    NodeScheduled,
+    NodeDispatchFailed,
+    NodeStarted,
+    NodeProgress,
+    NodeOutputChunk,
+    NodeCompleted,
+    NodeFailed,
+    NodeRetrying,
+    NodeCancelled,
+    DataCreated,
+    DataDerivedFrom,
+    DataTransferred,
+    DataTransferProgress,
+    DataReplicated,
+    DataReplicaRemoved,
+    DataPrefetchStarted,
+    DataPrefetched,
+    DataDeleted,
+    DataTierChanged,
+    DataEvicted,
+    DataQuotaWarning,
+    DataCorrupted,
+    DataScrubCompleted,
+    DataGcCompleted,
+    ServerRegistered,
+    ServerHealthCheck,
+    ServerDisconnected,
+    MetricSample,
+    Audit,
+}
+
+impl EventKind {
+    /// Every event kind, in declaration order
+    pub const ALL: [EventKind; 35] = [
+        EventKind::WorkflowStarted,
+        EventKind::WorkflowCompleted,
+        EventKind::WorkflowFailed,
+        EventKind::WorkflowCancelled,
+        EventKind::NodeQueued,
+        EventKind::SchedulingDecisionMade,
+        EventKind::NodeScheduled,
+        EventKind::NodeDispatchFailed,
+        EventKind::NodeStarted,
+        EventKind::NodeProgress,
+        EventKind::NodeOutputChunk,
+        EventKind::NodeCompleted,
+        EventKind::NodeFailed,
+        EventKind::NodeRetrying,
+        EventKind::NodeCancelled,
+        EventKind::DataCreated,
+        EventKind::DataDerivedFrom,
+        EventKind::DataTransferred,
+        EventKind::DataTransferProgress,
+        EventKind::DataReplicated,
+        EventKind::DataReplicaRemoved,
+        EventKind::DataPrefetchStarted,
+        EventKind::DataPrefetched,
+        EventKind::DataDeleted,
+        EventKind::DataTierChanged,
+        EventKind::DataEvicted,
+        EventKind::DataQuotaWarning,
+        EventKind::DataCorrupted,
+        EventKind::DataScrubCompleted,
+        EventKind::DataGcCompleted,
+        EventKind::ServerRegistered,
+        EventKind::ServerHealthCheck,
+        EventKind::ServerDisconnected,
+        EventKind::MetricSample,
+        EventKind::Audit,
+    ];
+
+    /// Kinds of node lifecycle events
+    pub const NODE: [EventKind; 11] = [
+        EventKind::NodeQueued,
+        EventKind::SchedulingDecisionMade,
+        EventKind::NodeScheduled,
+        EventKind::NodeDispatchFailed,
+        EventKind::NodeStarted,
+        EventKind::NodeProgress,
+        EventKind::NodeOutputChunk,
+        EventKind::NodeCompleted,
+        EventKind::NodeFailed,
+        EventKind::NodeRetrying,
+        EventKind::NodeCancelled,
+    ];
+
+    /// Kinds of server registry events
+    pub const SERVER: [EventKind; 3] = [
+        EventKind::ServerRegistered,
+        EventKind::ServerHealthCheck,
+        EventKind::ServerDisconnected,
+    ];
+
+    /// Get the event type name, as used in the serialized `type` tag
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            EventKind::WorkflowStarted => "workflow_started",
+            EventKind::WorkflowCompleted => "workflow_completed",
+            EventKind::WorkflowFailed => "workflow_failed",
+            EventKind::WorkflowCancelled => "workflow_cancelled",
+            EventKind::NodeQueued => "node_queued",
+            EventKind::SchedulingDecisionMade => "scheduling_decision_made",
+            EventKind::NodeScheduled => "node_scheduled",
+            EventKind::NodeDispatchFailed => "node_dispatch_failed",
+            EventKind::NodeStarted => "node_started",
+            EventKind::NodeProgress => "node_progress",
+            EventKind::NodeOutputChunk => "node_output_chunk",
+            EventKind::NodeCompleted => "node_completed",
+            EventKind::NodeFailed => "node_failed",
+            EventKind::NodeRetrying => "node_retrying",
+            EventKind::NodeCancelled => "node_cancelled",
+            EventKind::DataCreated => "data_created",
+            EventKind::DataDerivedFrom => "data_derived_from",
+            EventKind::DataTransferred => "data_transferred",
+            EventKind::DataTransferProgress => "data_transfer_progress",
+            EventKind::DataReplicated => "data_replicated",
+            EventKind::DataReplicaRemoved => "data_replica_removed",
+            EventKind::DataPrefetchStarted => "data_prefetch_started",
+            EventKind::DataPrefetched => "data_prefetched",
+            EventKind::DataDeleted => "data_deleted",
+            EventKind::DataTierChanged => "data_tier_changed",
+            EventKind::DataEvicted => "data_evicted",
+            EventKind::DataQuotaWarning => "data_quota_warning",
+            EventKind::DataCorrupted => "data_corrupted",
+            EventKind::DataScrubCompleted => "data_scrub_completed",
+            EventKind::DataGcCompleted => "data_gc_completed",
+            EventKind::ServerRegistered => "server_registered",
+            EventKind::ServerHealthCheck => "server_health_check",
+            EventKind::ServerDisconnected => "server_disconnected",
+            EventKind::MetricSample => "metric_sample",
+            EventKind::Audit => "audit",
+        }
+    }
+
+    /// Get the severity of events of this kind
+    pub const fn severity(self) -> Severity {
+        match self {
+            EventKind::NodeProgress
+            | EventKind::NodeOutputChunk
+            | EventKind::DataTransferProgress
+            | EventKind::ServerHealthCheck => Severity::Debug,
+            EventKind::WorkflowCancelled
+            | EventKind::NodeDispatchFailed
+            | EventKind::NodeRetrying
+            | EventKind::NodeCancelled
+            | EventKind::DataQuotaWarning
+            | EventKind::ServerDisconnected => Severity::Warn,
+            EventKind::WorkflowFailed | EventKind::NodeFailed | EventKind::DataCorrupted => {
+                Severity::Error
+            }
+            _ => Severity::Info,
+        }
+    }
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for EventKind {
+    type Err = UnknownEventKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| UnknownEventKind(s.to_string()))
+    }
+}
+
+/// Error parsing an [`EventKind`] from an unknown name
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown event type '{0}'")]
+pub struct UnknownEventKind(pub String);
+
+/// How important an event is, from chatty diagnostics to failures
+///
+/// Severities are ordered, so a minimum level selects that level and
+/// everything above it.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// High-frequency diagnostics, e.g. progress reports and health checks
+    Debug,
+    /// Normal lifecycle events
+    #[default]
+    Info,
+    /// Setbacks that may resolve on their own, e.g. retries
+    Warn,
+    /// Failures
+    Error,
+}
+
+impl Severity {
+    /// Every severity, lowest first
+    pub const ALL: [Severity; 4] = [
+        Severity::Debug,
+        Severity::Info,
+        Severity::Warn,
+        Severity::Error,
+    ];
+
+    /// Get the severity name
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        }
+    }
+
+    /// Event kinds of this severity or higher
+    pub fn kinds_at_least(self) -> impl Iterator<Item = EventKind> {
+        EventKind::ALL
+            .into_iter()
+            .filter(move |kind| kind.severity() >= self)
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = UnknownSeverity;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Severity::ALL
+            .into_iter()
+            .find(|severity| severity.as_str() == s)
+            .ok_or_else(|| UnknownSeverity(s.to_string()))
+    }
+}
+
+/// Error parsing a [`Severity`] from an unknown name
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown severity '{0}'")]
+pub struct UnknownSeverity(pub String);
+
+/// Upgrades from each schema version to the next
+///
+/// `EVENT_MIGRATIONS[i]` rewrites the JSON of an event written with schema
+/// version `i + 1` into version `i + 2`. To change an event's shape, append
+/// a migration here; [`EVENT_SCHEMA_VERSION`] follows automatically.
+const EVENT_MIGRATIONS: &[fn(&mut serde_json::Value)] = &[];
+
+/// Current event schema version
+pub const EVENT_SCHEMA_VERSION: u32 = EVENT_MIGRATIONS.len() as u32 + 1;
+
+/// Schema version assumed for data written before versioning existed
+fn initial_schema_version() -> u32 {
+    1
+}
+
+impl Event {
+    /// Deserialize event JSON written with an older schema version
+    ///
+    /// Applies every migration from `from_version` up to
+    /// [`EVENT_SCHEMA_VERSION`] before deserializing. Fails for versions
+    /// newer than this release understands.
+    pub fn migrate(old_json: &str, from_version: u32) -> Result<Self, serde_json::Error> {
+        Self::migrate_value(serde_json::from_str(old_json)?, from_version)
+    }
+
+    /// Deserialize an event JSON value written with an older schema version
+    pub fn migrate_value(
+        mut value: serde_json::Value,
+        from_version: u32,
+    ) -> Result<Self, serde_json::Error> {
+        if from_version == 0 || from_version > EVENT_SCHEMA_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported event schema version {from_version} (current is {EVENT_SCHEMA_VERSION})"
+            )));
+        }
+        for migration in &EVENT_MIGRATIONS[from_version as usize - 1..] {
+            migration(&mut value);
+        }
+        serde_json::from_value(value)
+    }
+}
+
+/// Distributed trace context carried by events and task messages
+///
+/// Identifiers use the W3C Trace Context format: a 32-digit lowercase hex
+/// trace ID shared by a whole workflow execution, and the 16-digit hex ID
+/// of the span that caused an event.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TraceContext {
+    pub trace_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
+}
+
+impl TraceContext {
+    /// Start a new trace with no parent span
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: Uuid::new_v4().simple().to_string(),
+            parent_span_id: None,
+        }
+    }
+
+    /// Parse a W3C `traceparent` header value
+    ///
+    /// Returns `None` for malformed values and for the all-zero IDs the
+    /// specification marks invalid.
+    pub fn from_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (version, trace_id, span_id, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let is_hex = |s: &str, len: usize| {
+            s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        let is_zero = |s: &str| s.bytes().all(|b| b == b'0');
+        if !is_hex(version, 2) || version == "ff" || !is_hex(flags, 2) {
+            return None;
+        }
+        // Version 00 has exactly four fields; later versions may add more
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || !is_hex(span_id, 16) || is_zero(trace_id) || is_zero(span_id) {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_span_id: Some(span_id.to_string()),
+        })
+    }
+
+    /// Continue the trace under a new span, returning the context to hand
+    /// to work caused by that span
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id.clone(),
+            parent_span_id: Some(new_span_id()),
+        }
+    }
+
+    /// Format as a W3C `traceparent` header value
+    ///
+    /// A context without a parent span gets a fresh span ID.
+    pub fn to_traceparent(&self) -> String {
+        let span_id = self.parent_span_id.clone().unwrap_or_else(new_span_id);
+        format!("00-{}-{}-01", self.trace_id, span_id)
+    }
+}
+
+/// Generate a random 16-digit hex span ID
+fn new_span_id() -> String {
+    let mut id = Uuid::new_v4().simple().to_string();
+    id.truncate(16);
+    id
+}
+
+/// Sequence number of envelopes that are streamed but not persisted
+pub const TRANSIENT_SEQUENCE: u64 = 0;
+
 /// Event envelope with metadata for storage and transmission
+///
+/// Deserializing an envelope migrates its event to the current schema.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawEventEnvelope")]
 pub struct EventEnvelope {
     /// Unique event identifier
     pub id: Uuid,
-    /// Monotonically increasing sequence number
+    /// Monotonically increasing sequence number, or
+    /// [`TRANSIENT_SEQUENCE`] if the event was not persisted
     pub sequence: u64,
+    /// Schema version of `event`
+    pub schema_version: u32,
     /// The actual event
     pub event: Event,
     /// When this envelope was created
     pub created_at: DateTime<Utc>,
+    /// Producer-supplied key; appending another event with the same key is
+    /// a no-op that returns this envelope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Trace the event was recorded under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Span that caused the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
 }
 
 impl EventEnvelope {
@@ -260,10 +1064,79 @@ impl EventEnvelope {
         Self {
             id: Uuid::new_v4(),
             sequence,
+            schema_version: EVENT_SCHEMA_VERSION,
             event,
             created_at: Utc::now(),
+            idempotency_key: None,
+            trace_id: None,
+            parent_span_id: None,
         }
     }
+
+    /// Set the idempotency key
+    pub fn with_idempotency_key(mut self, key: Option<String>) -> Self {
+        self.idempotency_key = key;
+        self
+    }
+
+    /// Set the trace context
+    pub fn with_trace(mut self, trace: Option<TraceContext>) -> Self {
+        (self.trace_id, self.parent_span_id) = match trace {
+            Some(trace) => (Some(trace.trace_id), trace.parent_span_id),
+            None => (None, None),
+        };
+        self
+    }
+
+    /// Check if the envelope was streamed without being persisted
+    ///
+    /// Transient envelopes carry sequence number
+    /// [`TRANSIENT_SEQUENCE`], which the log never assigns.
+    pub fn is_transient(&self) -> bool {
+        self.sequence == TRANSIENT_SEQUENCE
+    }
+
+    /// Get the trace context, if the event was recorded under one
+    pub fn trace(&self) -> Option<TraceContext> {
+        Some(TraceContext {
+            trace_id: self.trace_id.clone()?,
+            parent_span_id: self.parent_span_id.clone(),
+        })
+    }
+}
+
+/// Envelope as stored, before its event is migrated
+#[derive(Deserialize)]
+struct RawEventEnvelope {
+    id: Uuid,
+    sequence: u64,
+    #[serde(default = "initial_schema_version")]
+    schema_version: u32,
+    event: serde_json::Value,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    #[serde(default)]
+    trace_id: Option<String>,
+    #[serde(default)]
+    parent_span_id: Option<String>,
+}
+
+impl TryFrom<RawEventEnvelope> for EventEnvelope {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawEventEnvelope) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: raw.id,
+            sequence: raw.sequence,
+            schema_version: EVENT_SCHEMA_VERSION,
+            event: Event::migrate_value(raw.event, raw.schema_version)?,
+            created_at: raw.created_at,
+            idempotency_key: raw.idempotency_key,
+            trace_id: raw.trace_id,
+            parent_span_id: raw.parent_span_id,
+        })
+    }
 }
 
 /// Event filter for querying events
@@ -275,6 +1148,8 @@ pub struct EventFilter {
     pub from_timestamp: Option<DateTime<Utc>>,
     pub to_timestamp: Option<DateTime<Utc>>,
     pub from_sequence: Option<u64>,
+    pub trace_id: Option<String>,
+    pub min_severity: Option<Severity>,
     pub limit: Option<usize>,
 }
 
@@ -302,11 +1177,85 @@ impl EventFilter {
         self
     }
 
+    /// Filter to events recorded under a trace
+    pub fn trace(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Filter by event type names (see [`Event::event_type`])
+    pub fn event_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.event_types = Some(types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Filter by event kinds
+    pub fn kinds(self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.event_types(kinds.into_iter().map(EventKind::as_str))
+    }
+
+    /// Filter to events of at least the given severity
+    pub fn min_severity(mut self, severity: Severity) -> Self {
+        self.min_severity = Some(severity);
+        self
+    }
+
+    /// Event type names allowed by the minimum severity, if one is set
+    ///
+    /// For backends that filter by event type in their queries.
+    pub fn severity_event_types(&self) -> Option<Vec<String>> {
+        self.min_severity.map(|severity| {
+            severity
+                .kinds_at_least()
+                .map(|kind| kind.as_str().to_string())
+                .collect()
+        })
+    }
+
+    /// Filter to events created within a time range (inclusive)
+    pub fn time_range(mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Self {
+        self.from_timestamp = from;
+        self.to_timestamp = to;
+        self
+    }
+
     /// Limit the number of results
     pub fn limit(mut self, limit: usize) -> Self {
         self.limit = Some(limit);
         self
     }
+
+    /// Check if an envelope matches every criterion except `limit`
+    ///
+    /// The time range applies to when the envelope was created.
+    pub fn matches(&self, envelope: &EventEnvelope) -> bool {
+        let event = &envelope.event;
+        self.workflow_id
+            .is_none_or(|id| event.workflow_id() == Some(id))
+            && self.node_id.is_none_or(|id| event.node_id() == Some(id))
+            && self
+                .event_types
+                .as_ref()
+                .is_none_or(|types| types.iter().any(|t| t == event.event_type()))
+            && self
+                .from_timestamp
+                .is_none_or(|from| envelope.created_at >= from)
+            && self.to_timestamp.is_none_or(|to| envelope.created_at <= to)
+            && self
+                .from_sequence
+                .is_none_or(|seq| envelope.sequence >= seq)
+            && self
+                .trace_id
+                .as_ref()
+                .is_none_or(|id| envelope.trace_id.as_ref() == Some(id))
+            && self
+                .min_severity
+                .is_none_or(|severity| event.severity() >= severity)
+    }
 }
 
 #[cfg(test)]
@@ -324,9 +1273,40 @@ mod tests {
         let json = event.to_json().unwrap();
         let parsed = Event::from_json(&json).unwrap();
 
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["type"], event.event_type());
+
         assert!(matches!(parsed, Event::WorkflowStarted { .. }));
     }
 
+    #[test]
+    fn test_event_kind_names() {
+        for kind in EventKind::ALL {
+            assert_eq!(kind.as_str().parse::<EventKind>(), Ok(kind));
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                serde_json::Value::from(kind.as_str())
+            );
+        }
+        assert!("node_exploded".parse::<EventKind>().is_err());
+
+        let event = Event::NodeFailed {
+            workflow_id: Uuid::new_v4(),
+            node_id: Uuid::new_v4(),
+            error: "boom".to_string(),
+            retry_count: 0,
+            timestamp: Utc::now(),
+        };
+        assert_eq!(event.kind(), EventKind::NodeFailed);
+        let envelope = EventEnvelope::new(1, event);
+        assert!(EventFilter::new()
+            .kinds([EventKind::NodeFailed, EventKind::NodeRetrying])
+            .matches(&envelope));
+        assert!(!EventFilter::new()
+            .kinds([EventKind::NodeRetrying])
+            .matches(&envelope));
+    }
+
     #[test]
     fn test_event_envelope() {
         let event = Event::NodeStarted {
@@ -337,5 +1317,55 @@ mod tests {
 
         let envelope = EventEnvelope::new(1, event);
         assert_eq!(envelope.sequence, 1);
+        assert_eq!(envelope.schema_version, EVENT_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_traceparent_round_trip() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let trace = TraceContext::from_traceparent(header).unwrap();
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.parent_span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_eq!(trace.to_traceparent(), header);
+
+        let child = trace.child();
+        assert_eq!(child.trace_id, trace.trace_id);
+        assert_ne!(child.parent_span_id, trace.parent_span_id);
+        assert!(TraceContext::from_traceparent(&child.to_traceparent()).is_some());
+        let root = TraceContext::new_root();
+        assert!(TraceContext::from_traceparent(&root.to_traceparent()).is_some());
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert!(
+                TraceContext::from_traceparent(invalid).is_none(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_migration() {
+        // Envelopes written before versioning have no schema_version field
+        let legacy = serde_json::json!({
+            "id": Uuid::new_v4(),
+            "sequence": 7,
+            "event": { "type": "data_deleted", "data_uuid": Uuid::new_v4(), "timestamp": Utc::now() },
+            "created_at": Utc::now(),
+        });
+        let envelope: EventEnvelope = serde_json::from_value(legacy).unwrap();
+        assert_eq!(envelope.schema_version, EVENT_SCHEMA_VERSION);
+        assert!(matches!(envelope.event, Event::DataDeleted { .. }));
+
+        let json = envelope.event.to_json().unwrap();
+        assert!(Event::migrate(&json, EVENT_SCHEMA_VERSION).is_ok());
+        assert!(Event::migrate(&json, EVENT_SCHEMA_VERSION + 1).is_err());
     }
 }
@@ -3,25 +3,209 @@
 //! Events are persisted to SQLite with WAL mode for crash recovery.
 //! This provides durability for workflow state across restarts.
 
+use std::collections::HashMap;
 use std::path::Path;
 
-use rusqlite::Connection;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, CachedStatement, Connection, OptionalExtension};
+use tokio::sync::watch;
+use uuid::Uuid;
 
-use crate::types::{Event, EventEnvelope, EventFilter};
+use crate::audit::{link_audit_event, AUDIT_EVENT_TYPE};
+use crate::backup::BackupInfo;
+use crate::compression::{decode_payload, encode_payload, CompressionConfig, PayloadEncoding};
+use crate::field_protection::{FieldProtection, FieldProtectionError};
+use crate::integrity::row_checksum;
+use crate::lineage::index_lineage;
+use crate::reader::WalReader;
+use crate::types::{Event, EventEnvelope, EventFilter, TraceContext, EVENT_SCHEMA_VERSION};
+
+/// Workflow state snapshot stored alongside the event log
+#[derive(Debug, Clone)]
+pub struct WalSnapshot {
+    /// Workflow the snapshot belongs to
+    pub workflow_id: Uuid,
+    /// Last sequence number covered by the snapshot
+    pub sequence: u64,
+    /// Whether the workflow had finished when the snapshot was taken
+    pub terminal: bool,
+    /// Serialized workflow state
+    pub state_json: String,
+    /// When the snapshot was taken
+    pub created_at: DateTime<Utc>,
+}
+
+/// An event to append, with producer-supplied metadata
+#[derive(Debug, Clone)]
+pub struct AppendEntry {
+    pub event: Event,
+    /// Key that makes the append a no-op if already used
+    pub idempotency_key: Option<String>,
+    /// Trace the event is recorded under
+    pub trace: Option<TraceContext>,
+}
+
+impl AppendEntry {
+    /// Create an entry with no metadata
+    pub fn new(event: Event) -> Self {
+        Self {
+            event,
+            idempotency_key: None,
+            trace: None,
+        }
+    }
+
+    /// Set the idempotency key
+    pub fn with_idempotency_key(mut self, key: Option<String>) -> Self {
+        self.idempotency_key = key;
+        self
+    }
+
+    /// Set the trace context
+    pub fn with_trace(mut self, trace: Option<TraceContext>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Build the envelope this entry is stored as
+    pub(crate) fn into_envelope(self, sequence: u64) -> EventEnvelope {
+        EventEnvelope::new(sequence, self.event)
+            .with_idempotency_key(self.idempotency_key)
+            .with_trace(self.trace)
+    }
+}
+
+impl From<Event> for AppendEntry {
+    fn from(event: Event) -> Self {
+        Self::new(event)
+    }
+}
+
+impl From<(Event, Option<String>)> for AppendEntry {
+    fn from((event, key): (Event, Option<String>)) -> Self {
+        Self::new(event).with_idempotency_key(key)
+    }
+}
+
+/// Storage backend for the event log
+///
+/// [`WriteAheadLog`] (SQLite) and [`SegmentedWal`](crate::segment::SegmentedWal)
+/// (append-only segment files) both implement this, so callers such as
+/// [`AsyncWal`](crate::async_wal::AsyncWal) can use either.
+pub trait WalBackend: Send {
+    /// Append multiple events atomically
+    fn append_batch(&mut self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError>;
+
+    /// Append multiple entries atomically
+    ///
+    /// An entry whose idempotency key was already used, earlier in the log
+    /// or earlier in the same batch, is not appended; the original envelope
+    /// is returned in its place.
+    fn append_entries(&mut self, entries: Vec<AppendEntry>)
+        -> Result<Vec<EventEnvelope>, WalError>;
+
+    /// Read events matching a filter, ordered by sequence number
+    fn read_filtered(&self, filter: &EventFilter) -> Result<Vec<EventEnvelope>, WalError>;
+
+    /// Get the last sequence number
+    fn last_sequence(&self) -> u64;
+
+    /// Get total event count
+    fn count(&self) -> Result<u64, WalError>;
+
+    /// Append an event to the log
+    fn append(&mut self, event: Event) -> Result<EventEnvelope, WalError> {
+        let mut envelopes = self.append_batch(vec![event])?;
+        Ok(envelopes.remove(0))
+    }
+
+    /// Append multiple events atomically, each with an optional idempotency key
+    ///
+    /// See [`WalBackend::append_entries`].
+    fn append_keyed_batch(
+        &mut self,
+        events: Vec<(Event, Option<String>)>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        self.append_entries(events.into_iter().map(AppendEntry::from).collect())
+    }
+
+    /// Append an event unless one with the same idempotency key exists
+    ///
+    /// Returns the original envelope for a duplicate key.
+    fn append_idempotent(&mut self, key: &str, event: Event) -> Result<EventEnvelope, WalError> {
+        let mut envelopes = self.append_keyed_batch(vec![(event, Some(key.to_string()))])?;
+        Ok(envelopes.remove(0))
+    }
+
+    /// Read events from a given sequence number
+    fn read_from(&self, sequence: u64) -> Result<Vec<EventEnvelope>, WalError> {
+        self.read_filtered(&EventFilter::new().from_sequence(sequence))
+    }
+
+    /// Handle for reading concurrently with appends, if supported
+    fn reader(&self) -> Option<WalReader> {
+        None
+    }
+
+    /// Write a consistent copy of the log to `path`
+    fn backup(&self, _path: &Path) -> Result<BackupInfo, WalError> {
+        Err(WalError::Unsupported("backup"))
+    }
+
+    /// Replace the log's contents with a backup
+    fn restore(&mut self, _path: &Path) -> Result<BackupInfo, WalError> {
+        Err(WalError::Unsupported("restore"))
+    }
+}
+
+/// Storage statistics for the WAL
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WalStats {
+    /// Total number of events
+    pub event_count: u64,
+    /// Number of events stored compressed
+    pub compressed_count: u64,
+    /// Size of all payloads before compression, in bytes
+    pub raw_bytes: u64,
+    /// Size of all payloads as stored, in bytes
+    pub stored_bytes: u64,
+}
+
+impl WalStats {
+    /// Ratio of stored to raw payload size (1.0 means no savings)
+    pub fn compression_ratio(&self) -> f64 {
+        if self.raw_bytes == 0 {
+            1.0
+        } else {
+            self.stored_bytes as f64 / self.raw_bytes as f64
+        }
+    }
+}
 
 /// Write-Ahead Log for event persistence
 pub struct WriteAheadLog {
     /// SQLite connection
-    conn: Connection,
+    pub(crate) conn: Connection,
     /// Next sequence number to assign
-    next_sequence: u64,
+    pub(crate) next_sequence: u64,
+    /// Payload compression, if enabled
+    compression: Option<CompressionConfig>,
+    /// Protection of sensitive fields, if configured
+    field_protection: Option<FieldProtection>,
+    /// Read-only connections to the same file; `None` when in memory
+    reader: Option<WalReader>,
+    /// Last committed sequence number, watched by tail streams
+    pub(crate) committed: watch::Sender<u64>,
 }
 
 impl WriteAheadLog {
     /// Open or create a WAL at the given path
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, WalError> {
-        let conn = Connection::open(path)?;
-        Self::initialize(conn)
+        let conn = Connection::open(&path)?;
+        let mut wal = Self::initialize(conn)?;
+        wal.reader = Some(WalReader::new(path.as_ref()));
+        Ok(wal)
     }
 
     /// Create an in-memory WAL (for testing)
@@ -31,7 +215,7 @@ impl WriteAheadLog {
     }
 
     /// Initialize the WAL with schema
-    fn initialize(conn: Connection) -> Result<Self, WalError> {
+    pub(crate) fn initialize(conn: Connection) -> Result<Self, WalError> {
         // Enable WAL mode for better concurrency
         conn.execute_batch(
             "
@@ -43,6 +227,13 @@ impl WriteAheadLog {
                 sequence INTEGER UNIQUE NOT NULL,
                 event_type TEXT NOT NULL,
                 event_json TEXT NOT NULL,
+                encoding INTEGER NOT NULL DEFAULT 0,
+                raw_size INTEGER,
+                schema_version INTEGER NOT NULL DEFAULT 1,
+                idempotency_key TEXT,
+                checksum INTEGER,
+                trace_id TEXT,
+                parent_span_id TEXT,
                 workflow_id TEXT,
                 node_id TEXT,
                 created_at TEXT NOT NULL
@@ -52,42 +243,356 @@ impl WriteAheadLog {
             CREATE INDEX IF NOT EXISTS idx_events_workflow ON events(workflow_id);
             CREATE INDEX IF NOT EXISTS idx_events_node ON events(node_id);
             CREATE INDEX IF NOT EXISTS idx_events_created_at ON events(created_at);
+            CREATE INDEX IF NOT EXISTS idx_events_type ON events(event_type, sequence);
+
+            CREATE TABLE IF NOT EXISTS snapshots (
+                workflow_id TEXT PRIMARY KEY,
+                sequence INTEGER NOT NULL,
+                terminal INTEGER NOT NULL,
+                state_json TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS wal_meta (
+                key TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS dead_letters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                destination TEXT NOT NULL,
+                sequence INTEGER NOT NULL,
+                envelope_json TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                last_attempt_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_dead_letters_destination ON dead_letters(destination);
+
+            CREATE TABLE IF NOT EXISTS data_lineage (
+                data_uuid TEXT NOT NULL,
+                parent_uuid TEXT,
+                workflow_id TEXT NOT NULL,
+                node_id TEXT NOT NULL,
+                sequence INTEGER NOT NULL,
+                derived_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_data_lineage_data ON data_lineage(data_uuid);
+
+            CREATE TABLE IF NOT EXISTS audit_chain (
+                sequence INTEGER PRIMARY KEY,
+                prev_hash TEXT NOT NULL,
+                hash TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS quarantined_events (
+                sequence INTEGER PRIMARY KEY,
+                id TEXT NOT NULL,
+                event_type TEXT NOT NULL,
+                event_json TEXT NOT NULL,
+                encoding INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                quarantined_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS webhooks (
+                id TEXT PRIMARY KEY,
+                url TEXT NOT NULL,
+                filter_json TEXT NOT NULL,
+                secret TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                webhook_id TEXT NOT NULL,
+                sequence INTEGER NOT NULL,
+                event_type TEXT NOT NULL,
+                delivered INTEGER NOT NULL,
+                attempts INTEGER NOT NULL,
+                error TEXT,
+                attempted_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook
+                ON webhook_deliveries(webhook_id, id);
+
+            CREATE TABLE IF NOT EXISTS outbox_cursors (
+                destination TEXT PRIMARY KEY,
+                sequence INTEGER NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS token_revocations (
+                kind TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                revoked_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                PRIMARY KEY (kind, subject)
+            );
+
+            CREATE TABLE IF NOT EXISTS data_catalog (
+                data_uuid TEXT PRIMARY KEY,
+                workflow_id TEXT NOT NULL,
+                document TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_data_catalog_workflow
+                ON data_catalog(workflow_id);
             ",
         )?;
 
-        // Get the next sequence number
-        let next_sequence: u64 = conn
-            .query_row(
-                "SELECT COALESCE(MAX(sequence), 0) + 1 FROM events",
-                [],
-                |row| row.get(0),
-            )
-            .unwrap_or(1);
+        Self::migrate(&conn)?;
+        conn.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_events_idempotency_key
+                ON events(idempotency_key) WHERE idempotency_key IS NOT NULL;
+             CREATE INDEX IF NOT EXISTS idx_events_trace
+                ON events(trace_id, sequence) WHERE trace_id IS NOT NULL;",
+        )?;
+
+        let next_sequence = query_high_water(&conn).unwrap_or(0) + 1;
 
         Ok(Self {
             conn,
             next_sequence,
+            compression: Some(CompressionConfig::default()),
+            field_protection: None,
+            reader: None,
+            committed: watch::Sender::new(next_sequence - 1),
         })
     }
 
+    /// Bring databases created by older versions up to the current schema
+    fn migrate(conn: &Connection) -> Result<(), WalError> {
+        let columns: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_table_info('events')")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        // Rows written before compression existed are plain JSON (encoding 0)
+        if !columns.iter().any(|c| c == "encoding") {
+            conn.execute_batch(
+                "ALTER TABLE events ADD COLUMN encoding INTEGER NOT NULL DEFAULT 0;
+                 ALTER TABLE events ADD COLUMN raw_size INTEGER;",
+            )?;
+        }
+        // Rows written before event versioning use the initial schema
+        if !columns.iter().any(|c| c == "schema_version") {
+            conn.execute_batch(
+                "ALTER TABLE events ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1;",
+            )?;
+        }
+        // Rows written before deduplication have no idempotency key
+        if !columns.iter().any(|c| c == "idempotency_key") {
+            conn.execute_batch("ALTER TABLE events ADD COLUMN idempotency_key TEXT;")?;
+        }
+        // Rows written before checksums are verified by decoding only
+        if !columns.iter().any(|c| c == "checksum") {
+            conn.execute_batch("ALTER TABLE events ADD COLUMN checksum INTEGER;")?;
+        }
+        // Rows written before tracing belong to no trace
+        if !columns.iter().any(|c| c == "trace_id") {
+            conn.execute_batch(
+                "ALTER TABLE events ADD COLUMN trace_id TEXT;
+                 ALTER TABLE events ADD COLUMN parent_span_id TEXT;",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Set payload compression; `None` stores every payload as plain JSON
+    ///
+    /// Only affects newly appended events; existing rows stay readable
+    /// regardless of the setting.
+    pub fn with_compression(mut self, compression: Option<CompressionConfig>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypt or hash sensitive fields of events before they are stored
+    ///
+    /// Appends return the protected events, so subscribers see what was
+    /// persisted; use [`FieldProtection::reveal`] to read protected fields.
+    /// Imported envelopes are stored as they are.
+    pub fn with_field_protection(mut self, protection: Option<FieldProtection>) -> Self {
+        self.field_protection = protection;
+        self
+    }
+
+    /// Get payload storage statistics
+    pub fn stats(&self) -> Result<WalStats, WalError> {
+        let stats = self.conn.query_row(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(encoding != 0), 0),
+                    COALESCE(SUM(COALESCE(raw_size, length(CAST(event_json AS BLOB)))), 0),
+                    COALESCE(SUM(length(CAST(event_json AS BLOB))), 0)
+             FROM events",
+            [],
+            |row| {
+                Ok(WalStats {
+                    event_count: row.get(0)?,
+                    compressed_count: row.get(1)?,
+                    raw_bytes: row.get(2)?,
+                    stored_bytes: row.get(3)?,
+                })
+            },
+        )?;
+        Ok(stats)
+    }
+
     /// Append an event to the log
     pub fn append(&mut self, event: Event) -> Result<EventEnvelope, WalError> {
-        todo!("Implement event append")
+        let mut envelopes = self.append_batch(vec![event])?;
+        Ok(envelopes.remove(0))
     }
 
     /// Append multiple events atomically
+    ///
+    /// Either every event is persisted with consecutive sequence numbers, or
+    /// none is and the sequence counter is left untouched.
     pub fn append_batch(&mut self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError> {
-        todo!("Implement batch append")
+        self.append_entries(events.into_iter().map(AppendEntry::new).collect())
+    }
+
+    /// Append an event unless one with the same idempotency key exists
+    ///
+    /// Retried producers (e.g. a node callback delivered twice) can pass the
+    /// same key on every attempt; only the first is recorded and later
+    /// attempts get the original envelope back. Keys are forgotten once
+    /// their event is compacted away.
+    pub fn append_idempotent(
+        &mut self,
+        key: &str,
+        event: Event,
+    ) -> Result<EventEnvelope, WalError> {
+        let mut envelopes = self.append_keyed_batch(vec![(event, Some(key.to_string()))])?;
+        Ok(envelopes.remove(0))
+    }
+
+    /// Append multiple events atomically, each with an optional idempotency key
+    ///
+    /// Events whose key is already taken are skipped and replaced by the
+    /// original envelope in the result; the rest get consecutive sequence
+    /// numbers.
+    pub fn append_keyed_batch(
+        &mut self,
+        events: Vec<(Event, Option<String>)>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        self.append_entries(events.into_iter().map(AppendEntry::from).collect())
+    }
+
+    /// Append multiple entries atomically
+    ///
+    /// Like [`append_keyed_batch`](Self::append_keyed_batch), but each
+    /// entry may also carry a trace context.
+    pub fn append_entries(
+        &mut self,
+        entries: Vec<AppendEntry>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        let mut envelopes: Vec<EventEnvelope> = Vec::with_capacity(entries.len());
+        let mut batch_keys: HashMap<String, usize> = HashMap::new();
+        let mut next_sequence = self.next_sequence;
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(INSERT_EVENT)?;
+            let mut lookup = tx.prepare_cached(&format!(
+                "SELECT {ENVELOPE_COLUMNS} FROM events WHERE idempotency_key = ?1"
+            ))?;
+
+            for entry in entries {
+                if let Some(key) = &entry.idempotency_key {
+                    if let Some(&index) = batch_keys.get(key) {
+                        envelopes.push(envelopes[index].clone());
+                        continue;
+                    }
+                    let original = lookup
+                        .query_row(params![key], EnvelopeRow::read)
+                        .optional()?;
+                    if let Some(original) = original {
+                        envelopes.push(original.decode()?);
+                        continue;
+                    }
+                    batch_keys.insert(key.clone(), envelopes.len());
+                }
+
+                let mut envelope = entry.into_envelope(next_sequence);
+                if let Some(protection) = &self.field_protection {
+                    envelope.event = protection.protect(&envelope.event)?;
+                }
+                let json = envelope.event.to_json()?;
+                link_audit_event(&tx, &envelope, &json)?;
+                insert_event(&mut stmt, &envelope, json, self.compression.as_ref())?;
+                index_lineage(&tx, &envelope)?;
+                next_sequence += 1;
+                envelopes.push(envelope);
+            }
+        }
+        tx.commit()?;
+
+        if next_sequence != self.next_sequence {
+            self.committed.send_replace(next_sequence - 1);
+        }
+        self.next_sequence = next_sequence;
+        Ok(envelopes)
+    }
+
+    /// Insert envelopes under their original sequence numbers
+    ///
+    /// Used to load archived events back into a log, typically a scratch
+    /// log for forensic replay. Envelopes whose sequence number is already
+    /// taken are skipped, and later appends continue after the highest
+    /// imported sequence number. Returns the number of envelopes inserted.
+    pub fn import_envelopes(&mut self, envelopes: &[EventEnvelope]) -> Result<usize, WalError> {
+        let mut imported = 0;
+        let mut last_sequence = self.last_sequence();
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(INSERT_EVENT)?;
+            let mut exists = tx.prepare_cached("SELECT 1 FROM events WHERE sequence = ?1")?;
+            for envelope in envelopes {
+                if exists.exists(params![envelope.sequence])? {
+                    continue;
+                }
+                let json = envelope.event.to_json()?;
+                insert_event(&mut stmt, envelope, json, self.compression.as_ref())?;
+                index_lineage(&tx, envelope)?;
+                last_sequence = last_sequence.max(envelope.sequence);
+                imported += 1;
+            }
+        }
+        tx.commit()?;
+
+        if last_sequence >= self.next_sequence {
+            self.next_sequence = last_sequence + 1;
+            self.committed.send_replace(last_sequence);
+        }
+        Ok(imported)
     }
 
     /// Read events from a given sequence number
     pub fn read_from(&self, sequence: u64) -> Result<Vec<EventEnvelope>, WalError> {
-        todo!("Implement read from sequence")
+        self.read_filtered(&EventFilter::new().from_sequence(sequence))
     }
 
     /// Read events matching a filter
+    ///
+    /// Results are ordered by sequence number. The time range applies to
+    /// when events were appended to the log.
     pub fn read_filtered(&self, filter: &EventFilter) -> Result<Vec<EventEnvelope>, WalError> {
-        todo!("Implement filtered read")
+        query_events(&self.conn, filter)
+    }
+
+    /// Get a thread-safe handle for reading while this log is appended to
+    ///
+    /// Returns `None` for in-memory logs.
+    pub fn reader(&self) -> Option<WalReader> {
+        self.reader.clone()
     }
 
     /// Get the last sequence number
@@ -101,17 +606,153 @@ impl WriteAheadLog {
     }
 
     /// Compact the log (remove old entries)
-    /// Returns the number of entries removed
+    ///
+    /// Removes every event with a sequence number below `before_sequence`,
+    /// except audit events, which are kept for the audit trail, and events
+    /// an outbox relay has not delivered yet. Sequence numbers are never
+    /// reused, even if the newest events are removed. Returns the number of
+    /// entries removed.
     pub fn compact(&mut self, before_sequence: u64) -> Result<u64, WalError> {
-        todo!("Implement log compaction")
+        self.delete_before(None, before_sequence)
     }
 
-    /// Compact entries older than a timestamp
-    pub fn compact_before(
+    /// Remove a single workflow's events below `before_sequence`
+    ///
+    /// Returns the number of entries removed.
+    pub fn compact_workflow(
+        &mut self,
+        workflow_id: Uuid,
+        before_sequence: u64,
+    ) -> Result<u64, WalError> {
+        self.delete_before(Some(workflow_id), before_sequence)
+    }
+
+    fn delete_before(
         &mut self,
-        _before: chrono::DateTime<chrono::Utc>,
+        workflow_id: Option<Uuid>,
+        before_sequence: u64,
     ) -> Result<u64, WalError> {
-        todo!("Implement time-based compaction")
+        let before_sequence = self.compaction_bound(before_sequence)?;
+        let compacted_through = before_sequence.saturating_sub(1).min(self.last_sequence());
+
+        let tx = self.conn.transaction()?;
+        let removed = match workflow_id {
+            Some(id) => tx.execute(
+                "DELETE FROM events WHERE sequence < ?1 AND workflow_id = ?2 AND event_type != ?3",
+                params![before_sequence, id.to_string(), AUDIT_EVENT_TYPE],
+            )?,
+            None => tx.execute(
+                "DELETE FROM events WHERE sequence < ?1 AND event_type != ?2",
+                params![before_sequence, AUDIT_EVENT_TYPE],
+            )?,
+        };
+        tx.execute(
+            "INSERT INTO wal_meta (key, value) VALUES ('compacted_through', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = MAX(value, excluded.value)",
+            params![compacted_through],
+        )?;
+        tx.commit()?;
+
+        Ok(removed as u64)
+    }
+
+    /// Lower `before_sequence` so compaction keeps events an outbox relay
+    /// still has to deliver
+    pub(crate) fn compaction_bound(&self, before_sequence: u64) -> Result<u64, WalError> {
+        let undelivered: Option<u64> =
+            self.conn
+                .query_row("SELECT MIN(sequence) + 1 FROM outbox_cursors", [], |row| {
+                    row.get(0)
+                })?;
+        // SQLite integers are signed
+        Ok(before_sequence
+            .min(undelivered.unwrap_or(u64::MAX))
+            .min(i64::MAX as u64))
+    }
+
+    /// First sequence number appended at or after `time`
+    ///
+    /// Returns the next sequence number if every event is older.
+    pub(crate) fn sequence_at(&self, time: DateTime<Utc>) -> Result<u64, WalError> {
+        let sequence: Option<u64> = self.conn.query_row(
+            "SELECT MIN(sequence) FROM events WHERE created_at >= ?1",
+            params![format_timestamp(time)],
+            |row| row.get(0),
+        )?;
+        Ok(sequence.unwrap_or(self.next_sequence))
+    }
+
+    /// Record a state snapshot for a workflow at the current sequence
+    ///
+    /// `state_json` is the serialized workflow state; the WAL treats it as
+    /// opaque. A snapshot replaces any earlier snapshot for the workflow,
+    /// and covers every event up to and including its sequence number.
+    pub fn write_snapshot(
+        &mut self,
+        workflow_id: Uuid,
+        state_json: String,
+        terminal: bool,
+    ) -> Result<WalSnapshot, WalError> {
+        let snapshot = WalSnapshot {
+            workflow_id,
+            sequence: self.last_sequence(),
+            terminal,
+            state_json,
+            created_at: Utc::now(),
+        };
+        self.conn.execute(
+            "INSERT OR REPLACE INTO snapshots (workflow_id, sequence, terminal, state_json, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                snapshot.workflow_id.to_string(),
+                snapshot.sequence,
+                snapshot.terminal,
+                snapshot.state_json,
+                format_timestamp(snapshot.created_at),
+            ],
+        )?;
+        Ok(snapshot)
+    }
+
+    /// Get the latest snapshot for a workflow
+    pub fn latest_snapshot(&self, workflow_id: Uuid) -> Result<Option<WalSnapshot>, WalError> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT sequence, terminal, state_json, created_at FROM snapshots WHERE workflow_id = ?1",
+                params![workflow_id.to_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, bool>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        row.map(|(sequence, terminal, state_json, created_at)| {
+            Ok(WalSnapshot {
+                workflow_id,
+                sequence,
+                terminal,
+                state_json,
+                created_at: parse_column(&created_at)?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Compact entries older than a timestamp
+    ///
+    /// Removes events up to the first one appended at or after `before`,
+    /// with the same exceptions as [`compact`](Self::compact). Use
+    /// [`archive_before`](Self::archive_before) to keep a cold copy of the
+    /// removed events. Returns the number of entries removed.
+    pub fn compact_before(&mut self, before: DateTime<Utc>) -> Result<u64, WalError> {
+        let before_sequence = self.sequence_at(before)?;
+        self.delete_before(None, before_sequence)
     }
 
     /// Create a checkpoint for crash recovery
@@ -122,24 +763,273 @@ impl WriteAheadLog {
 
     /// Get total event count
     pub fn count(&self) -> Result<u64, WalError> {
-        let count: u64 = self
-            .conn
-            .query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))?;
-        Ok(count)
+        query_count(&self.conn)
     }
 
     /// Get events for a specific workflow
-    pub fn events_for_workflow(&self, workflow_id: uuid::Uuid) -> Result<Vec<EventEnvelope>, WalError> {
+    pub fn events_for_workflow(&self, workflow_id: Uuid) -> Result<Vec<EventEnvelope>, WalError> {
         self.read_filtered(&EventFilter::new().workflow(workflow_id))
     }
 
-    /// Get the latest N events
+    /// Get the latest N events, ordered by sequence number
     pub fn latest(&self, n: usize) -> Result<Vec<EventEnvelope>, WalError> {
-        todo!("Implement latest events query")
+        let mut events = self.read_page(u64::MAX, n)?;
+        events.reverse();
+        Ok(events)
+    }
+
+    /// Read a page of history, newest first
+    ///
+    /// Returns up to `limit` events with a sequence number below
+    /// `before_sequence`. Pass `u64::MAX` for the first page, then the
+    /// sequence number of the last event returned for the next one; an
+    /// empty page means the start of the log was reached.
+    pub fn read_page(
+        &self,
+        before_sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        query_page(&self.conn, before_sequence, limit)
     }
 }
 
+impl WalBackend for WriteAheadLog {
+    fn append_batch(&mut self, events: Vec<Event>) -> Result<Vec<EventEnvelope>, WalError> {
+        WriteAheadLog::append_batch(self, events)
+    }
+
+    fn append_entries(
+        &mut self,
+        entries: Vec<AppendEntry>,
+    ) -> Result<Vec<EventEnvelope>, WalError> {
+        WriteAheadLog::append_entries(self, entries)
+    }
+
+    fn read_filtered(&self, filter: &EventFilter) -> Result<Vec<EventEnvelope>, WalError> {
+        WriteAheadLog::read_filtered(self, filter)
+    }
+
+    fn last_sequence(&self) -> u64 {
+        WriteAheadLog::last_sequence(self)
+    }
+
+    fn count(&self) -> Result<u64, WalError> {
+        WriteAheadLog::count(self)
+    }
+
+    fn reader(&self) -> Option<WalReader> {
+        WriteAheadLog::reader(self)
+    }
+
+    fn backup(&self, path: &Path) -> Result<BackupInfo, WalError> {
+        WriteAheadLog::backup(self, path)
+    }
+
+    fn restore(&mut self, path: &Path) -> Result<BackupInfo, WalError> {
+        WriteAheadLog::restore(self, path)
+    }
+}
+
+/// Columns selected to rebuild an [`EventEnvelope`]
+pub(crate) const ENVELOPE_COLUMNS: &str =
+    "id, sequence, event_json, encoding, schema_version, created_at, idempotency_key, trace_id, parent_span_id";
+
+/// An event row as stored, before its payload is decoded
+pub(crate) struct EnvelopeRow {
+    id: String,
+    sequence: u64,
+    payload: Value,
+    encoding: i64,
+    schema_version: u32,
+    created_at: String,
+    idempotency_key: Option<String>,
+    trace_id: Option<String>,
+    parent_span_id: Option<String>,
+}
+
+impl EnvelopeRow {
+    /// Read a row selected with [`ENVELOPE_COLUMNS`]
+    pub(crate) fn read(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            sequence: row.get(1)?,
+            payload: row.get(2)?,
+            encoding: row.get(3)?,
+            schema_version: row.get(4)?,
+            created_at: row.get(5)?,
+            idempotency_key: row.get(6)?,
+            trace_id: row.get(7)?,
+            parent_span_id: row.get(8)?,
+        })
+    }
+
+    pub(crate) fn decode(self) -> Result<EventEnvelope, WalError> {
+        let event_json =
+            decode_payload(self.payload, PayloadEncoding::from_marker(self.encoding)?)?;
+        Ok(EventEnvelope {
+            id: parse_column(&self.id)?,
+            sequence: self.sequence,
+            schema_version: EVENT_SCHEMA_VERSION,
+            event: Event::migrate(&event_json, self.schema_version)?,
+            created_at: parse_column(&self.created_at)?,
+            idempotency_key: self.idempotency_key,
+            trace_id: self.trace_id,
+            parent_span_id: self.parent_span_id,
+        })
+    }
+}
+
+/// Read events matching a filter on the given connection
+pub(crate) fn query_events(
+    conn: &Connection,
+    filter: &EventFilter,
+) -> Result<Vec<EventEnvelope>, WalError> {
+    let mut sql = format!("SELECT {ENVELOPE_COLUMNS} FROM events WHERE 1 = 1");
+    let mut args: Vec<Value> = Vec::new();
+
+    if let Some(workflow_id) = filter.workflow_id {
+        sql.push_str(" AND workflow_id = ?");
+        args.push(Value::Text(workflow_id.to_string()));
+    }
+    if let Some(node_id) = filter.node_id {
+        sql.push_str(" AND node_id = ?");
+        args.push(Value::Text(node_id.to_string()));
+    }
+    if let Some(types) = &filter.event_types {
+        let placeholders = vec!["?"; types.len()].join(", ");
+        sql.push_str(&format!(" AND event_type IN ({placeholders})"));
+        args.extend(types.iter().map(|t| Value::Text(t.clone())));
+    }
+    if let Some(from) = filter.from_timestamp {
+        sql.push_str(" AND created_at >= ?");
+        args.push(Value::Text(format_timestamp(from)));
+    }
+    if let Some(to) = filter.to_timestamp {
+        sql.push_str(" AND created_at <= ?");
+        args.push(Value::Text(format_timestamp(to)));
+    }
+    if let Some(sequence) = filter.from_sequence {
+        sql.push_str(" AND sequence >= ?");
+        args.push(Value::Integer(sequence.min(i64::MAX as u64) as i64));
+    }
+    if let Some(trace_id) = &filter.trace_id {
+        sql.push_str(" AND trace_id = ?");
+        args.push(Value::Text(trace_id.clone()));
+    }
+    if let Some(types) = filter.severity_event_types() {
+        let placeholders = vec!["?"; types.len()].join(", ");
+        sql.push_str(&format!(" AND event_type IN ({placeholders})"));
+        args.extend(types.into_iter().map(Value::Text));
+    }
+    sql.push_str(" ORDER BY sequence ASC");
+    if let Some(limit) = filter.limit {
+        sql.push_str(" LIMIT ?");
+        args.push(Value::Integer(limit as i64));
+    }
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(args), EnvelopeRow::read)?;
+    rows.map(|row| row?.decode()).collect()
+}
+
+/// Read up to `limit` events below `before_sequence`, newest first
+pub(crate) fn query_page(
+    conn: &Connection,
+    before_sequence: u64,
+    limit: usize,
+) -> Result<Vec<EventEnvelope>, WalError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {ENVELOPE_COLUMNS} FROM events
+         WHERE sequence < ?1
+         ORDER BY sequence DESC
+         LIMIT ?2"
+    ))?;
+    let rows = stmt.query_map(
+        params![
+            before_sequence.min(i64::MAX as u64) as i64,
+            limit.min(i64::MAX as usize) as i64
+        ],
+        EnvelopeRow::read,
+    )?;
+    rows.map(|row| row?.decode()).collect()
+}
+
+/// Highest sequence number ever assigned on the given connection
+///
+/// Compaction or repair may have removed the newest events, so this also
+/// honors the recorded high-water mark and quarantined rows.
+pub(crate) fn query_high_water(conn: &Connection) -> Result<u64, WalError> {
+    let sequence = conn.query_row(
+        "SELECT MAX(
+            COALESCE((SELECT MAX(sequence) FROM events), 0),
+            COALESCE((SELECT value FROM wal_meta WHERE key = 'compacted_through'), 0),
+            COALESCE((SELECT MAX(sequence) FROM quarantined_events), 0)
+         )",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(sequence)
+}
+
+/// Count events on the given connection
+pub(crate) fn query_count(conn: &Connection) -> Result<u64, WalError> {
+    let count: u64 = conn.query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))?;
+    Ok(count)
+}
+
+/// Insert statement matching [`insert_event`]
+const INSERT_EVENT: &str = "INSERT INTO events (id, sequence, event_type, event_json, encoding, raw_size, schema_version, idempotency_key, checksum, trace_id, parent_span_id, workflow_id, node_id, created_at)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)";
+
+/// Insert one envelope row, given its serialized event
+fn insert_event(
+    stmt: &mut CachedStatement<'_>,
+    envelope: &EventEnvelope,
+    json: String,
+    compression: Option<&CompressionConfig>,
+) -> Result<(), WalError> {
+    let raw_size = json.len() as u64;
+    let checksum = row_checksum(envelope.sequence, &json);
+    let (payload, encoding) = encode_payload(json, compression)?;
+    stmt.execute(params![
+        envelope.id.to_string(),
+        envelope.sequence,
+        envelope.event.event_type(),
+        payload,
+        encoding.marker(),
+        raw_size,
+        envelope.schema_version,
+        envelope.idempotency_key,
+        checksum,
+        envelope.trace_id,
+        envelope.parent_span_id,
+        envelope.event.workflow_id().map(|id| id.to_string()),
+        envelope.event.node_id().map(|id| id.to_string()),
+        format_timestamp(envelope.created_at),
+    ])?;
+    Ok(())
+}
+
+/// Format a timestamp so that lexical order matches chronological order
+pub(crate) fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Parse a stored text column
+pub(crate) fn parse_column<T>(value: &str) -> Result<T, WalError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e: T::Err| WalError::Corrupt(format!("invalid column value '{value}': {e}")))
+}
+
 /// Event subscriber for real-time event streaming
+///
+/// Must be polled by hand; long-lived consumers should prefer
+/// [`WriteAheadLog::tail`] or [`EventBus::tail`](crate::bus::EventBus::tail).
 pub struct EventSubscriber {
     /// Last seen sequence number
     last_sequence: u64,
@@ -159,7 +1049,7 @@ impl EventSubscriber {
     }
 
     /// Poll for new events
-    pub fn poll(&mut self, wal: &WriteAheadLog) -> Result<Vec<EventEnvelope>, WalError> {
+    pub fn poll<W: WalBackend + ?Sized>(&mut self, wal: &W) -> Result<Vec<EventEnvelope>, WalError> {
         let events = wal.read_from(self.last_sequence + 1)?;
         if let Some(last) = events.last() {
             self.last_sequence = last.sequence;
@@ -191,6 +1081,28 @@ pub enum WalError {
 
     #[error("Sequence gap detected: expected {expected}, got {got}")]
     SequenceGap { expected: u64, got: u64 },
+
+    #[error("Corrupt log entry: {0}")]
+    Corrupt(String),
+
+    #[error("WAL writer has shut down")]
+    WriterClosed,
+
+    #[error("Export error: {0}")]
+    Export(String),
+
+    #[error("Operation not supported by this WAL backend: {0}")]
+    Unsupported(&'static str),
+
+    #[error("Backup ends at sequence {backup}, behind the log at {current}")]
+    BackupBehind { backup: u64, current: u64 },
+
+    #[error("Field protection error: {0}")]
+    FieldProtection(#[from] FieldProtectionError),
+
+    #[cfg(feature = "postgres")]
+    #[error("Postgres error: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
 }
 
 #[cfg(test)]
@@ -0,0 +1,550 @@
+//! Outbound webhooks on selected events
+//!
+//! A [`Webhook`] pairs a URL with a [`WebhookFilter`], e.g. only
+//! workflow-terminal events, so chat and incident tooling is notified
+//! without polling. Registrations and a short delivery history per webhook
+//! are stored in the WAL database.
+//!
+//! [`WebhookDispatcher::dispatch`] is called for every committed event. It
+//! sends a signed JSON payload to each matching webhook, retrying with
+//! exponential backoff per [`RetryPolicy`], and records the outcome. Like
+//! dead-letter delivery, the transport is supplied by the caller.
+//!
+//! Each request carries the Unix time in [`WEBHOOK_TIMESTAMP_HEADER`] and
+//! `sha256=<hex>` in [`WEBHOOK_SIGNATURE_HEADER`]: the HMAC-SHA256 of
+//! `{timestamp}.{body}` keyed with the webhook's secret. Receivers
+//! recompute it to authenticate the request, and should reject stale
+//! timestamps to prevent replays.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::dead_letter::RetryPolicy;
+use crate::types::{EventEnvelope, EventKind};
+use crate::wal::{format_timestamp, parse_column, WalError, WriteAheadLog};
+
+/// Header carrying the request signature
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-swarmx-signature";
+
+/// Header carrying the Unix time the request was signed at
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "x-swarmx-timestamp";
+
+/// Delivery records kept per webhook
+const DELIVERY_HISTORY: u32 = 100;
+
+/// Which events a webhook receives
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookFilter {
+    /// Only events of this workflow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow_id: Option<Uuid>,
+    /// Only events of these types; all types if `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_types: Option<Vec<EventKind>>,
+}
+
+impl WebhookFilter {
+    /// Match every event
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match workflow completion, failure, and cancellation
+    pub fn workflow_terminal() -> Self {
+        Self::new().kinds([
+            EventKind::WorkflowCompleted,
+            EventKind::WorkflowFailed,
+            EventKind::WorkflowCancelled,
+        ])
+    }
+
+    /// Only match events of one workflow
+    pub fn workflow(mut self, workflow_id: Uuid) -> Self {
+        self.workflow_id = Some(workflow_id);
+        self
+    }
+
+    /// Only match events of the given kinds
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.event_types = Some(kinds.into_iter().collect());
+        self
+    }
+
+    /// Check whether an event matches this filter
+    pub fn matches(&self, envelope: &EventEnvelope) -> bool {
+        let event = &envelope.event;
+        self.workflow_id
+            .is_none_or(|id| event.workflow_id() == Some(id))
+            && self
+                .event_types
+                .as_ref()
+                .is_none_or(|kinds| kinds.contains(&event.kind()))
+    }
+}
+
+/// A registered webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub filter: WebhookFilter,
+    /// Signing key; never serialized
+    #[serde(skip)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of delivering one event to one webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    /// Row ID in the delivery table
+    pub id: i64,
+    pub webhook_id: Uuid,
+    /// Sequence number of the delivered event
+    pub sequence: u64,
+    pub event_type: String,
+    /// Whether any attempt succeeded
+    pub delivered: bool,
+    /// Attempts made, including the successful one
+    pub attempts: u32,
+    /// Error from the last failed attempt
+    pub error: Option<String>,
+    /// When the last attempt finished
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// A signed request to send to a webhook
+#[derive(Debug, Clone)]
+pub struct WebhookRequest {
+    pub webhook_id: Uuid,
+    pub url: String,
+    /// JSON payload
+    pub body: String,
+    /// Unix time the request was signed at
+    pub timestamp: i64,
+    /// Value of the [`WEBHOOK_SIGNATURE_HEADER`]
+    pub signature: String,
+}
+
+impl WebhookRequest {
+    fn new(webhook: &Webhook, body: &str) -> Self {
+        let timestamp = Utc::now().timestamp();
+        Self {
+            webhook_id: webhook.id,
+            url: webhook.url.clone(),
+            body: body.to_string(),
+            timestamp,
+            signature: sign_webhook_payload(&webhook.secret, timestamp, body),
+        }
+    }
+
+    /// Headers to send along with the body
+    pub fn headers(&self) -> [(&'static str, String); 2] {
+        [
+            (WEBHOOK_TIMESTAMP_HEADER, self.timestamp.to_string()),
+            (WEBHOOK_SIGNATURE_HEADER, self.signature.clone()),
+        ]
+    }
+}
+
+/// JSON body of a webhook request
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    webhook_id: Uuid,
+    envelope: &'a EventEnvelope,
+}
+
+/// Compute the [`WEBHOOK_SIGNATURE_HEADER`] value for a payload
+pub fn sign_webhook_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+impl WriteAheadLog {
+    /// Register a webhook
+    pub fn register_webhook(
+        &mut self,
+        url: &str,
+        filter: WebhookFilter,
+        secret: &str,
+    ) -> Result<Webhook, WalError> {
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            filter,
+            secret: secret.to_string(),
+            created_at: Utc::now(),
+        };
+        self.conn.execute(
+            "INSERT INTO webhooks (id, url, filter_json, secret, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                webhook.id.to_string(),
+                webhook.url,
+                serde_json::to_string(&webhook.filter)?,
+                webhook.secret,
+                format_timestamp(webhook.created_at),
+            ],
+        )?;
+        Ok(webhook)
+    }
+
+    /// List webhooks, oldest first
+    pub fn webhooks(&self) -> Result<Vec<Webhook>, WalError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, url, filter_json, secret, created_at FROM webhooks ORDER BY created_at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (id, url, filter_json, secret, created_at) = row?;
+            Ok(Webhook {
+                id: parse_column(&id)?,
+                url,
+                filter: serde_json::from_str(&filter_json)?,
+                secret,
+                created_at: parse_column(&created_at)?,
+            })
+        })
+        .collect()
+    }
+
+    /// Remove a webhook and its delivery history
+    ///
+    /// Returns whether the webhook existed.
+    pub fn remove_webhook(&mut self, id: Uuid) -> Result<bool, WalError> {
+        let tx = self.conn.transaction()?;
+        let removed = tx.execute(
+            "DELETE FROM webhooks WHERE id = ?1",
+            params![id.to_string()],
+        )?;
+        tx.execute(
+            "DELETE FROM webhook_deliveries WHERE webhook_id = ?1",
+            params![id.to_string()],
+        )?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    /// Record the outcome of a delivery, pruning old records
+    pub fn record_webhook_delivery(
+        &mut self,
+        webhook_id: Uuid,
+        envelope: &EventEnvelope,
+        attempts: u32,
+        error: Option<&str>,
+    ) -> Result<WebhookDelivery, WalError> {
+        let now = Utc::now();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO webhook_deliveries
+                (webhook_id, sequence, event_type, delivered, attempts, error, attempted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                webhook_id.to_string(),
+                envelope.sequence,
+                envelope.event.event_type(),
+                error.is_none(),
+                attempts,
+                error,
+                format_timestamp(now),
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.execute(
+            "DELETE FROM webhook_deliveries
+             WHERE webhook_id = ?1 AND id NOT IN (
+                 SELECT id FROM webhook_deliveries WHERE webhook_id = ?1 ORDER BY id DESC LIMIT ?2
+             )",
+            params![webhook_id.to_string(), DELIVERY_HISTORY],
+        )?;
+        tx.commit()?;
+
+        Ok(WebhookDelivery {
+            id,
+            webhook_id,
+            sequence: envelope.sequence,
+            event_type: envelope.event.event_type().to_string(),
+            delivered: error.is_none(),
+            attempts,
+            error: error.map(str::to_string),
+            attempted_at: now,
+        })
+    }
+
+    /// Recent deliveries to a webhook, newest first
+    pub fn webhook_deliveries(&self, webhook_id: Uuid) -> Result<Vec<WebhookDelivery>, WalError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, sequence, event_type, delivered, attempts, error, attempted_at
+             FROM webhook_deliveries
+             WHERE webhook_id = ?1
+             ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map(params![webhook_id.to_string()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, u32>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, String>(6)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (id, sequence, event_type, delivered, attempts, error, attempted_at) = row?;
+            Ok(WebhookDelivery {
+                id,
+                webhook_id,
+                sequence,
+                event_type,
+                delivered,
+                attempts,
+                error,
+                attempted_at: parse_column(&attempted_at)?,
+            })
+        })
+        .collect()
+    }
+
+    /// Check whether a webhook is registered
+    pub fn has_webhook(&self, id: Uuid) -> Result<bool, WalError> {
+        let found = self
+            .conn
+            .query_row(
+                "SELECT 1 FROM webhooks WHERE id = ?1",
+                params![id.to_string()],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+}
+
+/// Delivers committed events to registered webhooks
+///
+/// Cheaply cloneable; clones share the same WAL connection.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    wal: Arc<Mutex<WriteAheadLog>>,
+    policy: RetryPolicy,
+}
+
+impl WebhookDispatcher {
+    /// Store webhooks in the given WAL
+    ///
+    /// The WAL may be a second connection to the same database file as the
+    /// main event log.
+    pub fn new(wal: WriteAheadLog) -> Self {
+        Self {
+            wal: Arc::new(Mutex::new(wal)),
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Set the retry policy for each delivery
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Run a closure against the WAL
+    ///
+    /// The lock is never held across an `.await`.
+    fn with_wal<R>(&self, f: impl FnOnce(&mut WriteAheadLog) -> R) -> R {
+        let mut wal = self.wal.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut wal)
+    }
+
+    /// Register a webhook
+    pub fn register(
+        &self,
+        url: &str,
+        filter: WebhookFilter,
+        secret: &str,
+    ) -> Result<Webhook, WalError> {
+        self.with_wal(|wal| wal.register_webhook(url, filter, secret))
+    }
+
+    /// List webhooks, oldest first
+    pub fn webhooks(&self) -> Result<Vec<Webhook>, WalError> {
+        self.with_wal(|wal| wal.webhooks())
+    }
+
+    /// Remove a webhook and its delivery history
+    pub fn remove(&self, id: Uuid) -> Result<bool, WalError> {
+        self.with_wal(|wal| wal.remove_webhook(id))
+    }
+
+    /// Recent deliveries to a webhook, newest first, or `None` if it is
+    /// not registered
+    pub fn deliveries(&self, id: Uuid) -> Result<Option<Vec<WebhookDelivery>>, WalError> {
+        self.with_wal(|wal| {
+            if !wal.has_webhook(id)? {
+                return Ok(None);
+            }
+            wal.webhook_deliveries(id).map(Some)
+        })
+    }
+
+    /// Deliver an event to every matching webhook, one after another
+    ///
+    /// Each request is re-signed per attempt. Returns the outcome per
+    /// matching webhook; errors only if an outcome cannot be recorded.
+    pub async fn dispatch<F, Fut, E>(
+        &self,
+        envelope: &EventEnvelope,
+        mut send: F,
+    ) -> Result<Vec<WebhookDelivery>, WalError>
+    where
+        F: FnMut(WebhookRequest) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut outcomes = Vec::new();
+        for webhook in self.webhooks()? {
+            if !webhook.filter.matches(envelope) {
+                continue;
+            }
+            let body = serde_json::to_string(&WebhookPayload {
+                webhook_id: webhook.id,
+                envelope,
+            })?;
+
+            let mut attempts = 0;
+            let error = loop {
+                attempts += 1;
+                match send(WebhookRequest::new(&webhook, &body)).await {
+                    Ok(()) => break None,
+                    Err(e) if attempts >= self.policy.max_attempts => break Some(e.to_string()),
+                    Err(e) => {
+                        tracing::debug!(
+                            "webhook {} delivery failed (attempt {attempts}): {e}",
+                            webhook.id
+                        );
+                        tokio::time::sleep(self.policy.backoff(attempts)).await;
+                    }
+                }
+            };
+            if let Some(error) = &error {
+                tracing::warn!(
+                    "giving up on event {} for webhook {} after {attempts} attempts: {error}",
+                    envelope.sequence,
+                    webhook.id
+                );
+            }
+
+            outcomes.push(self.with_wal(|wal| {
+                wal.record_webhook_delivery(webhook.id, envelope, attempts, error.as_deref())
+            })?);
+        }
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_dispatch_signs_retries_and_records() {
+        let dispatcher =
+            WebhookDispatcher::new(WriteAheadLog::in_memory().unwrap()).with_policy(RetryPolicy {
+                max_attempts: 2,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(1),
+            });
+        let terminal = dispatcher
+            .register(
+                "https://hooks.example/a",
+                WebhookFilter::workflow_terminal(),
+                "s3cret",
+            )
+            .unwrap();
+        let down = dispatcher
+            .register("https://hooks.example/b", WebhookFilter::new(), "other")
+            .unwrap();
+
+        let workflow_id = Uuid::new_v4();
+        let started = EventEnvelope::new(
+            1,
+            Event::WorkflowStarted {
+                workflow_id,
+                name: "nightly".to_string(),
+                timestamp: Utc::now(),
+            },
+        );
+        let completed = EventEnvelope::new(
+            2,
+            Event::WorkflowCompleted {
+                workflow_id,
+                duration_ms: 10,
+                timestamp: Utc::now(),
+            },
+        );
+
+        let mut requests = Vec::new();
+        for envelope in [&started, &completed] {
+            dispatcher
+                .dispatch(envelope, |request| {
+                    requests.push(request.clone());
+                    async move {
+                        if request.webhook_id == down.id {
+                            Err("503 Service Unavailable")
+                        } else {
+                            Ok(())
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+        }
+
+        // Only the completion reached the terminal-only webhook
+        let sent: Vec<&WebhookRequest> = requests
+            .iter()
+            .filter(|r| r.webhook_id == terminal.id)
+            .collect();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].signature,
+            sign_webhook_payload("s3cret", sent[0].timestamp, &sent[0].body)
+        );
+        assert!(sent[0].body.contains("workflow_completed"));
+
+        let deliveries = dispatcher.deliveries(down.id).unwrap().unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert!(!deliveries[0].delivered);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].sequence, 2);
+        assert_eq!(
+            deliveries[0].error.as_deref(),
+            Some("503 Service Unavailable")
+        );
+        assert!(dispatcher.deliveries(terminal.id).unwrap().unwrap()[0].delivered);
+
+        assert!(dispatcher.remove(down.id).unwrap());
+        assert!(dispatcher.deliveries(down.id).unwrap().is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! Integration tests for the SQLite write-ahead log
+
+use chrono::{Duration, Utc};
+use swarmx_events::{AppendEntry, Event, EventEnvelope, EventFilter, TraceContext, WriteAheadLog};
+use uuid::Uuid;
+
+fn node_started(workflow_id: Uuid, node_id: Uuid) -> Event {
+    Event::NodeStarted {
+        workflow_id,
+        node_id,
+        timestamp: Utc::now(),
+    }
+}
+
+fn workflow_started(workflow_id: Uuid) -> Event {
+    Event::WorkflowStarted {
+        workflow_id,
+        name: "test".to_string(),
+        timestamp: Utc::now(),
+    }
+}
+
+#[test]
+fn append_assigns_sequences_and_survives_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.db");
+    let workflow_id = Uuid::new_v4();
+
+    {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        let first = wal.append(workflow_started(workflow_id)).unwrap();
+        let second = wal
+            .append(node_started(workflow_id, Uuid::new_v4()))
+            .unwrap();
+        assert_eq!((first.sequence, second.sequence), (1, 2));
+    }
+
+    let mut wal = WriteAheadLog::open(&path).unwrap();
+    assert_eq!(wal.last_sequence(), 2);
+    assert_eq!(wal.count().unwrap(), 2);
+    assert_eq!(
+        wal.append(workflow_started(workflow_id)).unwrap().sequence,
+        3
+    );
+
+    let events = wal.read_from(2).unwrap();
+    assert_eq!(
+        events.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert!(matches!(events[0].event, Event::NodeStarted { .. }));
+}
+
+#[test]
+fn append_batch_is_atomic() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = WriteAheadLog::open(dir.path().join("events.db")).unwrap();
+    let workflow_id = Uuid::new_v4();
+
+    let batch = wal
+        .append_batch(vec![
+            workflow_started(workflow_id),
+            node_started(workflow_id, Uuid::new_v4()),
+            node_started(workflow_id, Uuid::new_v4()),
+        ])
+        .unwrap();
+    assert_eq!(
+        batch.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(wal.last_sequence(), 3);
+    assert!(wal.append_batch(Vec::new()).unwrap().is_empty());
+    assert_eq!(wal.peek_next_sequence(), 4);
+}
+
+#[test]
+fn duplicate_idempotency_keys_return_the_original_envelope() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.db");
+    let (workflow_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let original = {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        let original = wal
+            .append_idempotent("callback-1", node_started(workflow_id, node_id))
+            .unwrap();
+        let retried = wal
+            .append_idempotent("callback-1", node_started(workflow_id, node_id))
+            .unwrap();
+        assert_eq!(retried.id, original.id);
+        assert_eq!(retried.idempotency_key.as_deref(), Some("callback-1"));
+        original
+    };
+
+    let mut wal = WriteAheadLog::open(&path).unwrap();
+    let batch = wal
+        .append_keyed_batch(vec![
+            (
+                node_started(workflow_id, node_id),
+                Some("callback-1".into()),
+            ),
+            (workflow_started(workflow_id), Some("callback-2".into())),
+            (workflow_started(workflow_id), Some("callback-2".into())),
+            (workflow_started(workflow_id), None),
+        ])
+        .unwrap();
+    assert_eq!(batch[0].id, original.id);
+    assert_eq!(
+        batch.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        vec![1, 2, 2, 3]
+    );
+    assert_eq!(wal.count().unwrap(), 3);
+    assert_eq!(
+        wal.read_from(1).unwrap()[1].idempotency_key.as_deref(),
+        Some("callback-2")
+    );
+}
+
+#[test]
+fn read_filtered_honors_every_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = WriteAheadLog::open(dir.path().join("events.db")).unwrap();
+    let (wf_a, wf_b) = (Uuid::new_v4(), Uuid::new_v4());
+    let node = Uuid::new_v4();
+
+    let start = Utc::now() - Duration::seconds(1);
+    wal.append_batch(vec![
+        workflow_started(wf_a),
+        node_started(wf_a, node),
+        node_started(wf_a, Uuid::new_v4()),
+        workflow_started(wf_b),
+        node_started(wf_b, Uuid::new_v4()),
+    ])
+    .unwrap();
+    let end = Utc::now() + Duration::seconds(1);
+
+    assert_eq!(wal.events_for_workflow(wf_a).unwrap().len(), 3);
+    assert_eq!(
+        wal.read_filtered(&EventFilter::new().node(node)).unwrap()[0].sequence,
+        2
+    );
+
+    let started = wal
+        .read_filtered(&EventFilter::new().event_types(["workflow_started"]))
+        .unwrap();
+    assert_eq!(
+        started.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        vec![1, 4]
+    );
+
+    let page = wal
+        .read_filtered(&EventFilter::new().workflow(wf_a).from_sequence(2).limit(1))
+        .unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].sequence, 2);
+
+    let in_range = EventFilter::new().time_range(Some(start), Some(end));
+    assert_eq!(wal.read_filtered(&in_range).unwrap().len(), 5);
+    let future = EventFilter::new().time_range(Some(end), None);
+    assert!(wal.read_filtered(&future).unwrap().is_empty());
+}
+
+#[test]
+fn latest_and_pages_walk_history_backwards() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = WriteAheadLog::open(dir.path().join("events.db")).unwrap();
+    let workflow_id = Uuid::new_v4();
+    for _ in 0..5 {
+        wal.append(workflow_started(workflow_id)).unwrap();
+    }
+    let sequences =
+        |events: &[EventEnvelope]| events.iter().map(|e| e.sequence).collect::<Vec<_>>();
+
+    assert_eq!(sequences(&wal.latest(2).unwrap()), vec![4, 5]);
+    assert_eq!(sequences(&wal.latest(10).unwrap()), vec![1, 2, 3, 4, 5]);
+
+    let first = wal.read_page(u64::MAX, 2).unwrap();
+    assert_eq!(sequences(&first), vec![5, 4]);
+    let second = wal.read_page(first[1].sequence, 2).unwrap();
+    assert_eq!(sequences(&second), vec![3, 2]);
+    let reader = wal.reader().unwrap();
+    assert_eq!(
+        sequences(&reader.read_page(second[1].sequence, 2).unwrap()),
+        vec![1]
+    );
+    assert!(wal.read_page(1, 2).unwrap().is_empty());
+}
+
+#[test]
+fn compact_never_reuses_sequences() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.db");
+    let workflow_id = Uuid::new_v4();
+
+    {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append_batch(vec![
+            workflow_started(workflow_id),
+            node_started(workflow_id, Uuid::new_v4()),
+            node_started(Uuid::new_v4(), Uuid::new_v4()),
+        ])
+        .unwrap();
+
+        assert_eq!(wal.compact_workflow(workflow_id, 3).unwrap(), 2);
+        assert_eq!(wal.count().unwrap(), 1);
+        // Holes left by compaction are not gaps
+        assert!(wal.verify().unwrap().is_intact());
+        assert_eq!(wal.compact(u64::MAX).unwrap(), 1);
+        assert_eq!(wal.count().unwrap(), 0);
+    }
+
+    let mut wal = WriteAheadLog::open(&path).unwrap();
+    assert_eq!(wal.append(workflow_started(workflow_id)).unwrap().sequence, 4);
+}
+
+#[test]
+fn large_payloads_are_compressed_transparently() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.db");
+    let workflow_id = Uuid::new_v4();
+    let large = Event::NodeProgress {
+        workflow_id,
+        node_id: Uuid::new_v4(),
+        progress: 0.5,
+        message: Some("output ".repeat(2000)),
+        timestamp: Utc::now(),
+    };
+
+    {
+        // Rows written without compression must stay readable afterwards
+        let mut wal = WriteAheadLog::open(&path).unwrap().with_compression(None);
+        wal.append(large.clone()).unwrap();
+    }
+
+    let mut wal = WriteAheadLog::open(&path).unwrap();
+    wal.append(large).unwrap();
+    wal.append(workflow_started(workflow_id)).unwrap();
+
+    let stats = wal.stats().unwrap();
+    assert_eq!(stats.event_count, 3);
+    assert_eq!(stats.compressed_count, 1);
+    assert!(stats.stored_bytes < stats.raw_bytes);
+    assert!(stats.compression_ratio() < 1.0);
+
+    let events = wal.events_for_workflow(workflow_id).unwrap();
+    assert_eq!(events.len(), 3);
+    for envelope in &events[..2] {
+        match &envelope.event {
+            Event::NodeProgress { message, .. } => {
+                assert_eq!(message.as_deref().map(str::len), Some(14000))
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    assert!(wal.verify().unwrap().is_intact());
+}
+
+#[test]
+fn trace_context_is_stored_and_filterable() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.db");
+    let workflow_id = Uuid::new_v4();
+    let trace = TraceContext::new_root().child();
+
+    {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(workflow_started(workflow_id)).unwrap();
+        let envelopes = wal
+            .append_entries(vec![
+                AppendEntry::new(node_started(workflow_id, Uuid::new_v4()))
+                    .with_trace(Some(trace.clone())),
+                AppendEntry::new(node_started(workflow_id, Uuid::new_v4()))
+                    .with_idempotency_key(Some("callback-1".into()))
+                    .with_trace(Some(trace.clone())),
+            ])
+            .unwrap();
+        assert_eq!(envelopes[0].trace(), Some(trace.clone()));
+    }
+
+    let wal = WriteAheadLog::open(&path).unwrap();
+    let traced = wal
+        .read_filtered(&EventFilter::new().trace(trace.trace_id.clone()))
+        .unwrap();
+    assert_eq!(
+        traced.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert_eq!(traced[1].trace(), Some(trace.clone()));
+    assert_eq!(traced[1].idempotency_key.as_deref(), Some("callback-1"));
+    assert!(EventFilter::new().trace(trace.trace_id).matches(&traced[0]));
+    assert_eq!(wal.read_from(1).unwrap()[0].trace(), None);
+}
@@ -0,0 +1,17 @@
+//! Generates the gRPC services from `proto/` when the `grpc` feature is on
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto");
+    #[cfg(feature = "grpc")]
+    {
+        // Use the vendored protoc unless one is configured
+        if std::env::var_os("PROTOC").is_none() {
+            let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+            std::env::set_var("PROTOC", protoc);
+        }
+        tonic_prost_build::configure()
+            .compile_protos(&["proto/swarmx.proto"], &["proto"])
+            .expect("failed to compile protos");
+    }
+}
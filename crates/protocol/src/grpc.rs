@@ -0,0 +1,809 @@
+//! gRPC services alongside HTTP
+//!
+//! Executor servers that speak gRPC natively serve [`TaskService`] and
+//! [`DataService`] instead of the HTTP task and data endpoints, and report
+//! callbacks to the client's [`CallbackService`] or stream them from
+//! `TaskService::watch`. The services are generated from
+//! `proto/swarmx.proto`, whose messages mirror [`messages`](crate::messages)
+//! one for one; convert between the two with `From` and `TryFrom`, e.g.
+//! `TaskRequest::try_from(request.into_inner())?` in a service method.
+//! Messages that do not convert are rejected with
+//! [`InvalidMessage`], which becomes an `INVALID_ARGUMENT` status.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use crate::messages::{
+    CallbackMessage, CallbackTrace, CancelMode, CancelOutcome, DataFetchRequest, DataStoreRequest,
+    DataStoreResponse, TaskBatchRequest, TaskBatchResponse, TaskBatchResult, TaskCancelRequest,
+    TaskCancelResponse, TaskInput, TaskOutput, TaskRequest, TaskResponse, TaskStatus,
+    TaskStatusResponse,
+};
+
+/// Types and services generated from `proto/swarmx.proto`
+pub mod proto {
+    tonic::include_proto!("swarmx.v1");
+}
+
+pub use proto::callback_service_client::CallbackServiceClient;
+pub use proto::callback_service_server::{CallbackService, CallbackServiceServer};
+pub use proto::data_service_client::DataServiceClient;
+pub use proto::data_service_server::{DataService, DataServiceServer};
+pub use proto::task_service_client::TaskServiceClient;
+pub use proto::task_service_server::{TaskService, TaskServiceServer};
+
+/// gRPC message that has no HTTP counterpart, e.g. with a malformed UUID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidMessage {
+    /// Field at fault
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl InvalidMessage {
+    fn new(field: &'static str, reason: impl ToString) -> Self {
+        Self {
+            field,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn missing(field: &'static str) -> Self {
+        Self::new(field, "missing")
+    }
+}
+
+impl std::fmt::Display for InvalidMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid {}: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidMessage {}
+
+impl From<InvalidMessage> for tonic::Status {
+    fn from(e: InvalidMessage) -> Self {
+        tonic::Status::invalid_argument(e.to_string())
+    }
+}
+
+fn parse_uuid(field: &'static str, value: &str) -> Result<Uuid, InvalidMessage> {
+    value.parse().map_err(|e| InvalidMessage::new(field, e))
+}
+
+fn parse_timestamp(field: &'static str, value: &str) -> Result<DateTime<Utc>, InvalidMessage> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(|e| InvalidMessage::new(field, e))
+}
+
+fn parse_json<T: DeserializeOwned>(field: &'static str, value: &str) -> Result<T, InvalidMessage> {
+    serde_json::from_str(value).map_err(|e| InvalidMessage::new(field, e))
+}
+
+/// Format a timestamp as the HTTP API does
+fn timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("protocol messages serialize to JSON")
+}
+
+fn try_map<T, U>(items: Vec<T>) -> Result<Vec<U>, InvalidMessage>
+where
+    U: TryFrom<T, Error = InvalidMessage>,
+{
+    items.into_iter().map(U::try_from).collect()
+}
+
+// ============================================================================
+// Task Submission
+// ============================================================================
+
+impl From<TaskStatus> for proto::TaskStatus {
+    fn from(status: TaskStatus) -> Self {
+        match status {
+            TaskStatus::Accepted => Self::Accepted,
+            TaskStatus::Running => Self::Running,
+            TaskStatus::Complete => Self::Complete,
+            TaskStatus::Failed => Self::Failed,
+            TaskStatus::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+impl TryFrom<proto::TaskStatus> for TaskStatus {
+    type Error = InvalidMessage;
+
+    fn try_from(status: proto::TaskStatus) -> Result<Self, Self::Error> {
+        match status {
+            proto::TaskStatus::Unspecified => Err(InvalidMessage::missing("status")),
+            proto::TaskStatus::Accepted => Ok(Self::Accepted),
+            proto::TaskStatus::Running => Ok(Self::Running),
+            proto::TaskStatus::Complete => Ok(Self::Complete),
+            proto::TaskStatus::Failed => Ok(Self::Failed),
+            proto::TaskStatus::Cancelled => Ok(Self::Cancelled),
+        }
+    }
+}
+
+fn task_status(status: i32) -> Result<TaskStatus, InvalidMessage> {
+    proto::TaskStatus::try_from(status)
+        .map_err(|e| InvalidMessage::new("status", e))?
+        .try_into()
+}
+
+impl From<TaskRequest> for proto::TaskRequest {
+    fn from(request: TaskRequest) -> Self {
+        Self {
+            node_id: request.node_id.to_string(),
+            node_type: request.node_type,
+            inputs: request.inputs.into_iter().map(Into::into).collect(),
+            config_json: json(&request.config),
+            callback_url: request.callback_url,
+            timeout_ms: request.timeout_ms,
+            trace_id: request.trace_id,
+            parent_span_id: request.parent_span_id,
+            idempotency_key: request.idempotency_key,
+        }
+    }
+}
+
+impl TryFrom<proto::TaskRequest> for TaskRequest {
+    type Error = InvalidMessage;
+
+    fn try_from(request: proto::TaskRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            node_id: parse_uuid("node_id", &request.node_id)?,
+            node_type: request.node_type,
+            inputs: try_map(request.inputs)?,
+            config: parse_json("config_json", &request.config_json)?,
+            callback_url: request.callback_url,
+            timeout_ms: request.timeout_ms,
+            trace_id: request.trace_id,
+            parent_span_id: request.parent_span_id,
+            idempotency_key: request.idempotency_key,
+        })
+    }
+}
+
+impl From<TaskInput> for proto::TaskInput {
+    fn from(input: TaskInput) -> Self {
+        use proto::task_input::Source;
+
+        let (name, source) = match input {
+            TaskInput::Inline { name, value } => (name, Source::InlineJson(json(&value))),
+            TaskInput::LocalFile {
+                name,
+                data_ref,
+                path,
+            } => (
+                name,
+                Source::LocalFile(proto::LocalFile {
+                    data_ref_json: json(&data_ref),
+                    path: path.to_string_lossy().into_owned(),
+                }),
+            ),
+            TaskInput::Reference { name, data_ref } => (name, Source::DataRefJson(json(&data_ref))),
+        };
+        Self {
+            name,
+            source: Some(source),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskInput> for TaskInput {
+    type Error = InvalidMessage;
+
+    fn try_from(input: proto::TaskInput) -> Result<Self, Self::Error> {
+        use proto::task_input::Source;
+
+        let name = input.name;
+        Ok(
+            match input.source.ok_or(InvalidMessage::missing("source"))? {
+                Source::InlineJson(value) => Self::Inline {
+                    name,
+                    value: parse_json("inline_json", &value)?,
+                },
+                Source::LocalFile(file) => Self::LocalFile {
+                    name,
+                    data_ref: parse_json("data_ref_json", &file.data_ref_json)?,
+                    path: file.path.into(),
+                },
+                Source::DataRefJson(data_ref) => Self::Reference {
+                    name,
+                    data_ref: parse_json("data_ref_json", &data_ref)?,
+                },
+            },
+        )
+    }
+}
+
+impl From<TaskResponse> for proto::TaskResponse {
+    fn from(response: TaskResponse) -> Self {
+        Self {
+            task_id: response.task_id.to_string(),
+            status: proto::TaskStatus::from(response.status).into(),
+            accepted_at: timestamp(response.accepted_at),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskResponse> for TaskResponse {
+    type Error = InvalidMessage;
+
+    fn try_from(response: proto::TaskResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            task_id: parse_uuid("task_id", &response.task_id)?,
+            status: task_status(response.status)?,
+            accepted_at: parse_timestamp("accepted_at", &response.accepted_at)?,
+        })
+    }
+}
+
+impl From<TaskBatchRequest> for proto::TaskBatchRequest {
+    fn from(request: TaskBatchRequest) -> Self {
+        Self {
+            tasks: request.tasks.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskBatchRequest> for TaskBatchRequest {
+    type Error = InvalidMessage;
+
+    fn try_from(request: proto::TaskBatchRequest) -> Result<Self, Self::Error> {
+        Ok(Self::new(try_map(request.tasks)?))
+    }
+}
+
+impl From<TaskBatchResult> for proto::TaskBatchResult {
+    fn from(result: TaskBatchResult) -> Self {
+        use proto::task_batch_result::Outcome;
+
+        let node_id = result.node_id().to_string();
+        let outcome = match result {
+            TaskBatchResult::Accepted {
+                task_id,
+                accepted_at,
+                ..
+            } => Outcome::Accepted(proto::TaskAccepted {
+                task_id: task_id.to_string(),
+                accepted_at: timestamp(accepted_at),
+            }),
+            TaskBatchResult::Rejected {
+                error, error_code, ..
+            } => Outcome::Rejected(proto::TaskRejected { error, error_code }),
+        };
+        Self {
+            node_id,
+            outcome: Some(outcome),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskBatchResult> for TaskBatchResult {
+    type Error = InvalidMessage;
+
+    fn try_from(result: proto::TaskBatchResult) -> Result<Self, Self::Error> {
+        use proto::task_batch_result::Outcome;
+
+        let node_id = parse_uuid("node_id", &result.node_id)?;
+        Ok(
+            match result.outcome.ok_or(InvalidMessage::missing("outcome"))? {
+                Outcome::Accepted(accepted) => Self::Accepted {
+                    node_id,
+                    task_id: parse_uuid("task_id", &accepted.task_id)?,
+                    accepted_at: parse_timestamp("accepted_at", &accepted.accepted_at)?,
+                },
+                Outcome::Rejected(rejected) => {
+                    Self::rejected(node_id, rejected.error, rejected.error_code)
+                }
+            },
+        )
+    }
+}
+
+impl From<TaskBatchResponse> for proto::TaskBatchResponse {
+    fn from(response: TaskBatchResponse) -> Self {
+        Self {
+            results: response.results.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskBatchResponse> for TaskBatchResponse {
+    type Error = InvalidMessage;
+
+    fn try_from(response: proto::TaskBatchResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            results: try_map(response.results)?,
+        })
+    }
+}
+
+// ============================================================================
+// Task Status Query
+// ============================================================================
+
+impl From<TaskStatusResponse> for proto::TaskStatusResponse {
+    fn from(response: TaskStatusResponse) -> Self {
+        Self {
+            task_id: response.task_id.to_string(),
+            status: proto::TaskStatus::from(response.status).into(),
+            progress: response.progress,
+            outputs: response.outputs.map(|outputs| proto::TaskOutputs {
+                outputs: outputs.into_iter().map(Into::into).collect(),
+            }),
+            error: response.error,
+            started_at: response.started_at.map(timestamp),
+            completed_at: response.completed_at.map(timestamp),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskStatusResponse> for TaskStatusResponse {
+    type Error = InvalidMessage;
+
+    fn try_from(response: proto::TaskStatusResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            task_id: parse_uuid("task_id", &response.task_id)?,
+            status: task_status(response.status)?,
+            progress: response.progress,
+            outputs: response
+                .outputs
+                .map(|outputs| try_map(outputs.outputs))
+                .transpose()?,
+            error: response.error,
+            started_at: response
+                .started_at
+                .map(|at| parse_timestamp("started_at", &at))
+                .transpose()?,
+            completed_at: response
+                .completed_at
+                .map(|at| parse_timestamp("completed_at", &at))
+                .transpose()?,
+        })
+    }
+}
+
+impl From<TaskOutput> for proto::TaskOutput {
+    fn from(output: TaskOutput) -> Self {
+        use proto::task_output::Value;
+
+        let (name, value) = match output {
+            TaskOutput::Inline { name, value } => (name, Value::InlineJson(json(&value))),
+            TaskOutput::Reference { name, data_ref } => (name, Value::DataRefJson(json(&data_ref))),
+        };
+        Self {
+            name,
+            value: Some(value),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskOutput> for TaskOutput {
+    type Error = InvalidMessage;
+
+    fn try_from(output: proto::TaskOutput) -> Result<Self, Self::Error> {
+        use proto::task_output::Value;
+
+        let name = output.name;
+        Ok(
+            match output.value.ok_or(InvalidMessage::missing("value"))? {
+                Value::InlineJson(value) => Self::Inline {
+                    name,
+                    value: parse_json("inline_json", &value)?,
+                },
+                Value::DataRefJson(data_ref) => Self::Reference {
+                    name,
+                    data_ref: parse_json("data_ref_json", &data_ref)?,
+                },
+            },
+        )
+    }
+}
+
+// ============================================================================
+// Task Cancellation
+// ============================================================================
+
+impl From<CancelMode> for proto::CancelMode {
+    fn from(mode: CancelMode) -> Self {
+        match mode {
+            CancelMode::BestEffort => Self::BestEffort,
+            CancelMode::Force => Self::Force,
+        }
+    }
+}
+
+impl From<proto::CancelMode> for CancelMode {
+    fn from(mode: proto::CancelMode) -> Self {
+        match mode {
+            proto::CancelMode::BestEffort => Self::BestEffort,
+            proto::CancelMode::Force => Self::Force,
+        }
+    }
+}
+
+/// The task ID travels in the path over HTTP and in the message over gRPC
+impl From<(Uuid, TaskCancelRequest)> for proto::TaskCancelRequest {
+    fn from((task_id, request): (Uuid, TaskCancelRequest)) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            mode: proto::CancelMode::from(request.mode).into(),
+            reason: request.reason,
+        }
+    }
+}
+
+impl TryFrom<proto::TaskCancelRequest> for (Uuid, TaskCancelRequest) {
+    type Error = InvalidMessage;
+
+    fn try_from(request: proto::TaskCancelRequest) -> Result<Self, Self::Error> {
+        let mode = proto::CancelMode::try_from(request.mode)
+            .map_err(|e| InvalidMessage::new("mode", e))?;
+        Ok((
+            parse_uuid("task_id", &request.task_id)?,
+            TaskCancelRequest {
+                mode: mode.into(),
+                reason: request.reason,
+            },
+        ))
+    }
+}
+
+impl From<CancelOutcome> for proto::CancelOutcome {
+    fn from(outcome: CancelOutcome) -> Self {
+        match outcome {
+            CancelOutcome::Cancelling => Self::Cancelling,
+            CancelOutcome::Cancelled => Self::Cancelled,
+            CancelOutcome::AlreadyFinished => Self::AlreadyFinished,
+        }
+    }
+}
+
+impl From<TaskCancelResponse> for proto::TaskCancelResponse {
+    fn from(response: TaskCancelResponse) -> Self {
+        Self {
+            task_id: response.task_id.to_string(),
+            outcome: proto::CancelOutcome::from(response.outcome).into(),
+        }
+    }
+}
+
+impl TryFrom<proto::TaskCancelResponse> for TaskCancelResponse {
+    type Error = InvalidMessage;
+
+    fn try_from(response: proto::TaskCancelResponse) -> Result<Self, Self::Error> {
+        let outcome = match proto::CancelOutcome::try_from(response.outcome)
+            .map_err(|e| InvalidMessage::new("outcome", e))?
+        {
+            proto::CancelOutcome::Unspecified => return Err(InvalidMessage::missing("outcome")),
+            proto::CancelOutcome::Cancelling => CancelOutcome::Cancelling,
+            proto::CancelOutcome::Cancelled => CancelOutcome::Cancelled,
+            proto::CancelOutcome::AlreadyFinished => CancelOutcome::AlreadyFinished,
+        };
+        Ok(Self {
+            task_id: parse_uuid("task_id", &response.task_id)?,
+            outcome,
+        })
+    }
+}
+
+// ============================================================================
+// Callbacks
+// ============================================================================
+
+impl From<CallbackMessage> for proto::CallbackMessage {
+    fn from(message: CallbackMessage) -> Self {
+        use proto::callback_message::Kind;
+
+        let task_id = message.task_id().to_string();
+        let trace = message.trace().clone();
+        let (kind, at) = match message {
+            CallbackMessage::Progress {
+                progress,
+                message,
+                timestamp,
+                ..
+            } => (
+                Kind::Progress(proto::Progress { progress, message }),
+                timestamp,
+            ),
+            CallbackMessage::PartialOutput {
+                output,
+                sequence,
+                chunk,
+                timestamp,
+                ..
+            } => (
+                Kind::PartialOutput(proto::PartialOutput {
+                    output,
+                    sequence,
+                    chunk,
+                }),
+                timestamp,
+            ),
+            CallbackMessage::Complete {
+                outputs,
+                duration_ms,
+                timestamp,
+                ..
+            } => (
+                Kind::Complete(proto::Complete {
+                    outputs: outputs.into_iter().map(Into::into).collect(),
+                    duration_ms,
+                }),
+                timestamp,
+            ),
+            CallbackMessage::Failed {
+                error,
+                error_code,
+                timestamp,
+                ..
+            } => (Kind::Failed(proto::Failed { error, error_code }), timestamp),
+            CallbackMessage::Cancelled {
+                reason,
+                forced,
+                timestamp,
+                ..
+            } => (
+                Kind::Cancelled(proto::Cancelled { reason, forced }),
+                timestamp,
+            ),
+        };
+        Self {
+            task_id,
+            timestamp: timestamp(at),
+            trace_id: trace.trace_id,
+            parent_span_id: trace.parent_span_id,
+            kind: Some(kind),
+        }
+    }
+}
+
+impl TryFrom<proto::CallbackMessage> for CallbackMessage {
+    type Error = InvalidMessage;
+
+    fn try_from(message: proto::CallbackMessage) -> Result<Self, Self::Error> {
+        use proto::callback_message::Kind;
+
+        let task_id = parse_uuid("task_id", &message.task_id)?;
+        let timestamp = parse_timestamp("timestamp", &message.timestamp)?;
+        let trace = CallbackTrace {
+            trace_id: message.trace_id,
+            parent_span_id: message.parent_span_id,
+        };
+        Ok(match message.kind.ok_or(InvalidMessage::missing("kind"))? {
+            Kind::Progress(progress) => Self::Progress {
+                task_id,
+                progress: progress.progress,
+                message: progress.message,
+                timestamp,
+                trace,
+            },
+            Kind::PartialOutput(partial) => Self::PartialOutput {
+                task_id,
+                output: partial.output,
+                sequence: partial.sequence,
+                chunk: partial.chunk,
+                timestamp,
+                trace,
+            },
+            Kind::Complete(complete) => Self::Complete {
+                task_id,
+                outputs: try_map(complete.outputs)?,
+                duration_ms: complete.duration_ms,
+                timestamp,
+                trace,
+            },
+            Kind::Failed(failed) => Self::Failed {
+                task_id,
+                error: failed.error,
+                error_code: failed.error_code,
+                timestamp,
+                trace,
+            },
+            Kind::Cancelled(cancelled) => Self::Cancelled {
+                task_id,
+                reason: cancelled.reason,
+                forced: cancelled.forced,
+                timestamp,
+                trace,
+            },
+        })
+    }
+}
+
+// ============================================================================
+// Data Operations
+// ============================================================================
+
+impl From<DataFetchRequest> for proto::DataFetchRequest {
+    fn from(request: DataFetchRequest) -> Self {
+        Self {
+            data_uuids: request.data_uuids.iter().map(Uuid::to_string).collect(),
+            access_token_json: json(&request.access_token),
+        }
+    }
+}
+
+impl TryFrom<proto::DataFetchRequest> for DataFetchRequest {
+    type Error = InvalidMessage;
+
+    fn try_from(request: proto::DataFetchRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            data_uuids: request
+                .data_uuids
+                .iter()
+                .map(|uuid| parse_uuid("data_uuids", uuid))
+                .collect::<Result<_, _>>()?,
+            access_token: parse_json("access_token_json", &request.access_token_json)?,
+        })
+    }
+}
+
+impl From<DataStoreRequest> for proto::DataStoreRequest {
+    fn from(request: DataStoreRequest) -> Self {
+        Self {
+            workflow_id: request.workflow_id.to_string(),
+            dtype: request.dtype,
+            content_type: request.content_type,
+            size_bytes: request.size_bytes,
+            tenant: request.tenant,
+        }
+    }
+}
+
+impl TryFrom<proto::DataStoreRequest> for DataStoreRequest {
+    type Error = InvalidMessage;
+
+    fn try_from(request: proto::DataStoreRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            workflow_id: parse_uuid("workflow_id", &request.workflow_id)?,
+            dtype: request.dtype,
+            content_type: request.content_type,
+            size_bytes: request.size_bytes,
+            tenant: request.tenant,
+        })
+    }
+}
+
+impl From<DataStoreResponse> for proto::DataStoreResponse {
+    fn from(response: DataStoreResponse) -> Self {
+        Self {
+            data_ref_json: json(&response.data_ref),
+        }
+    }
+}
+
+impl TryFrom<proto::DataStoreResponse> for DataStoreResponse {
+    type Error = InvalidMessage;
+
+    fn try_from(response: proto::DataStoreResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            data_ref: parse_json("data_ref_json", &response.data_ref_json)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use swarmx_dataref::{AccessToken, DataRef, Permissions};
+
+    use super::*;
+
+    #[test]
+    fn test_grpc_conversions() {
+        let data_ref = DataRef::bytes("http://gpu-1:3000/api".to_string(), Uuid::new_v4(), b"x");
+        let request = TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: "ai.openai.chat".to_string(),
+            inputs: vec![
+                TaskInput::inline("prompt", serde_json::json!("Hello")),
+                TaskInput::local_file("weights", data_ref.clone(), PathBuf::from("/data/w")),
+                TaskInput::reference("table", data_ref.clone()),
+            ],
+            config: serde_json::json!({"model": "gpt-4"}),
+            callback_url: "http://localhost:3000/callback".to_string(),
+            timeout_ms: Some(60000),
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            parent_span_id: None,
+            idempotency_key: Some("retry-safe".to_string()),
+        };
+        let parsed = TaskRequest::try_from(proto::TaskRequest::from(request.clone())).unwrap();
+        assert_eq!(json(&parsed), json(&request));
+
+        let task_id = Uuid::new_v4();
+        let callbacks = [
+            CallbackMessage::partial_output(task_id, "text", 2, "lo".to_string()),
+            CallbackMessage::complete(task_id, vec![TaskOutput::reference("out", data_ref)], 9)
+                .with_trace(CallbackTrace {
+                    trace_id: request.trace_id.clone(),
+                    parent_span_id: Some("00f067aa0ba902b7".to_string()),
+                }),
+            CallbackMessage::cancelled(task_id, Some("user".to_string()), true),
+        ];
+        for callback in callbacks {
+            let parsed =
+                CallbackMessage::try_from(proto::CallbackMessage::from(callback.clone())).unwrap();
+            assert_eq!(json(&parsed), json(&callback));
+        }
+
+        let response = TaskBatchResponse {
+            results: vec![
+                TaskBatchResult::Accepted {
+                    node_id: request.node_id,
+                    task_id,
+                    accepted_at: Utc::now(),
+                },
+                TaskBatchResult::rejected(Uuid::new_v4(), "Unknown node type".to_string(), None),
+            ],
+        };
+        let parsed =
+            TaskBatchResponse::try_from(proto::TaskBatchResponse::from(response.clone())).unwrap();
+        assert_eq!(parsed.results, response.results);
+
+        let cancel = TaskCancelRequest::force(Some("user".to_string()));
+        let message = proto::TaskCancelRequest::from((task_id, cancel.clone()));
+        let parsed = <(Uuid, TaskCancelRequest)>::try_from(message).unwrap();
+        assert_eq!(parsed, (task_id, cancel));
+        let response = TaskCancelResponse {
+            task_id,
+            outcome: CancelOutcome::Cancelling,
+        };
+        let parsed =
+            TaskCancelResponse::try_from(proto::TaskCancelResponse::from(response.clone()))
+                .unwrap();
+        assert_eq!(parsed, response);
+
+        let fetch = DataFetchRequest::single(
+            Uuid::new_v4(),
+            AccessToken::new(
+                Uuid::new_v4(),
+                "client".to_string(),
+                chrono::Duration::minutes(5),
+                Permissions::read_only(),
+            ),
+        );
+        let parsed =
+            DataFetchRequest::try_from(proto::DataFetchRequest::from(fetch.clone())).unwrap();
+        assert_eq!(json(&parsed), json(&fetch));
+    }
+
+    #[test]
+    fn test_invalid_grpc_message() {
+        let mut request = proto::TaskRequest::from(TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: "util.map".to_string(),
+            inputs: Vec::new(),
+            config: serde_json::json!({}),
+            callback_url: String::new(),
+            timeout_ms: None,
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
+        });
+        request.node_id = "node-1".to_string();
+        let e = TaskRequest::try_from(request).unwrap_err();
+        assert_eq!(e.field, "node_id");
+        let status = tonic::Status::from(e);
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let response = proto::TaskResponse {
+            task_id: Uuid::new_v4().to_string(),
+            status: proto::TaskStatus::Unspecified.into(),
+            accepted_at: timestamp(Utc::now()),
+        };
+        assert_eq!(
+            TaskResponse::try_from(response).unwrap_err(),
+            InvalidMessage::missing("status")
+        );
+    }
+}
@@ -4,5 +4,12 @@
 //! between the SwarmX-UI client and SwarmX servers.
 
 pub mod messages;
+pub mod version;
+pub mod wire;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
 
 pub use messages::*;
+pub use version::*;
+pub use wire::*;
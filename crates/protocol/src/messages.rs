@@ -2,11 +2,19 @@
 //!
 //! Defines all message types for the HTTP API between client and servers.
 
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use swarmx_dataref::DataRef;
+use swarmx_dataref::{
+    same_host, AccessToken, Chunk, DataRef, DataRefError, DataRefRegistry, InlinePolicy,
+    MultipartUpload, ServerInventory, TransferPlan, UploadedPart,
+};
+
+use crate::version::ProtocolVersion;
 
 // ============================================================================
 // Task Submission
@@ -27,16 +35,65 @@ pub struct TaskRequest {
     pub callback_url: String,
     /// Execution timeout in milliseconds
     pub timeout_ms: Option<u64>,
+    /// Trace of the workflow execution dispatching the task
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Span that dispatched the task; servers echo the trace ID in their
+    /// callbacks and report their own task span as the callback's parent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
+    /// Key identifying the submission across retries; a server that
+    /// already accepted a task with the same key returns that task's
+    /// response instead of running the node again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+impl TaskRequest {
+    /// Get the idempotency key of an attempt of a node in an execution
+    ///
+    /// Every resubmission of the same attempt, e.g. after a timed out
+    /// request, carries the same key, while a retry of the node is a new
+    /// attempt with a new key.
+    pub fn idempotency_key_for(execution_id: Uuid, node_id: Uuid, attempt: u32) -> String {
+        format!("{execution_id}:{node_id}:{attempt}")
+    }
+
+    /// Set the idempotency key to that of an attempt of the node
+    pub fn with_idempotency_key(mut self, execution_id: Uuid, attempt: u32) -> Self {
+        self.idempotency_key = Some(Self::idempotency_key_for(
+            execution_id,
+            self.node_id,
+            attempt,
+        ));
+        self
+    }
 }
 
-/// Task input - either inline data or a DataRef
+/// Task input - inline data, a local file, or a DataRef
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum TaskInput {
     /// Inline data (for small values)
-    Inline { name: String, value: serde_json::Value },
+    Inline {
+        name: String,
+        value: serde_json::Value,
+    },
+    /// Data in a store on the server's own host, read from its file
+    /// instead of over HTTP
+    ///
+    /// The file must only be read, e.g. memory-mapped; it holds the
+    /// payload exactly as `data_ref` describes it.
+    LocalFile {
+        name: String,
+        data_ref: Box<DataRef>,
+        path: PathBuf,
+    },
     /// Reference to remote data
-    Reference { name: String, data_ref: DataRef },
+    Reference {
+        name: String,
+        data_ref: Box<DataRef>,
+    },
 }
 
 impl TaskInput {
@@ -52,7 +109,46 @@ impl TaskInput {
     pub fn reference(name: &str, data_ref: DataRef) -> Self {
         Self::Reference {
             name: name.to_string(),
-            data_ref,
+            data_ref: Box::new(data_ref),
+        }
+    }
+
+    /// Create an input for data that may be small enough to inline
+    ///
+    /// Inlines `value` if it is available and the policy allows the data's
+    /// size and type; otherwise the server fetches the data by reference.
+    pub fn for_data(
+        name: &str,
+        data_ref: DataRef,
+        value: Option<serde_json::Value>,
+        policy: &InlinePolicy,
+    ) -> Self {
+        match value {
+            Some(value) if policy.allows(&data_ref) => Self::inline(name, value),
+            _ => Self::reference(name, data_ref),
+        }
+    }
+
+    /// Create an input read from a file on the server's host
+    pub fn local_file(name: &str, data_ref: DataRef, path: PathBuf) -> Self {
+        Self::LocalFile {
+            name: name.to_string(),
+            data_ref: Box::new(data_ref),
+            path,
+        }
+    }
+
+    /// Create an input for data that `server` may read from its own host
+    ///
+    /// Hands over `path`, the data's file in its store, if the store is on
+    /// the same host as `server`; otherwise the server fetches the data by
+    /// reference.
+    pub fn for_server(name: &str, data_ref: DataRef, path: Option<PathBuf>, server: &str) -> Self {
+        match path {
+            Some(path) if same_host(&data_ref.location, server) => {
+                Self::local_file(name, data_ref, path)
+            }
+            _ => Self::reference(name, data_ref),
         }
     }
 
@@ -60,6 +156,7 @@ impl TaskInput {
     pub fn name(&self) -> &str {
         match self {
             Self::Inline { name, .. } => name,
+            Self::LocalFile { name, .. } => name,
             Self::Reference { name, .. } => name,
         }
     }
@@ -76,6 +173,83 @@ pub struct TaskResponse {
     pub accepted_at: DateTime<Utc>,
 }
 
+/// How long servers remember the response to an idempotency key, in
+/// seconds
+pub const IDEMPOTENCY_KEY_RETENTION_SECS: i64 = 24 * 60 * 60;
+
+/// Responses a server gave to submissions with idempotency keys
+///
+/// Servers look up each keyed submission before accepting it: a key seen
+/// within the retention period gets the original [`TaskResponse`] back, so
+/// a client retrying a submission whose response was lost never runs the
+/// node twice. Only accepted submissions are remembered; a refused one can
+/// be retried with the same key.
+#[derive(Debug, Clone)]
+pub struct IdempotencyCache {
+    responses: HashMap<String, TaskResponse>,
+    retention: chrono::Duration,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(chrono::Duration::seconds(IDEMPOTENCY_KEY_RETENTION_SECS))
+    }
+}
+
+impl IdempotencyCache {
+    /// Create a cache remembering responses for `retention`
+    pub fn new(retention: chrono::Duration) -> Self {
+        Self {
+            responses: HashMap::new(),
+            retention,
+        }
+    }
+
+    /// Get the response to an earlier submission of a request
+    pub fn get(&self, request: &TaskRequest) -> Option<&TaskResponse> {
+        let key = request.idempotency_key.as_ref()?;
+        self.responses
+            .get(key)
+            .filter(|response| Utc::now() - response.accepted_at < self.retention)
+    }
+
+    /// Answer a submission, with the original response if its key was
+    /// seen before and with `accept` otherwise
+    ///
+    /// Requests without a key are always accepted anew.
+    pub fn submit(
+        &mut self,
+        request: &TaskRequest,
+        accept: impl FnOnce() -> TaskResponse,
+    ) -> TaskResponse {
+        if let Some(response) = self.get(request) {
+            return response.clone();
+        }
+        let response = accept();
+        if let Some(key) = &request.idempotency_key {
+            self.responses.insert(key.clone(), response.clone());
+        }
+        response
+    }
+
+    /// Forget responses older than the retention period
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let retention = self.retention;
+        self.responses
+            .retain(|_, response| now - response.accepted_at < retention);
+    }
+
+    /// Get the number of remembered responses
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Check if no responses are remembered
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+}
+
 /// Task status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -92,6 +266,160 @@ pub enum TaskStatus {
     Cancelled,
 }
 
+/// Most tasks a server accepts in one [`TaskBatchRequest`]
+pub const MAX_TASK_BATCH_SIZE: usize = 500;
+
+/// Many tasks submitted to one server in a single call
+///
+/// Sent as `POST /tasks/batch`, so a wide fan-out, e.g. a 200-way map,
+/// costs one round trip instead of one per node. The server accepts or
+/// rejects each task on its own and answers with a [`TaskBatchResponse`];
+/// accepted tasks report through their callbacks exactly as if they were
+/// submitted one by one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskBatchRequest {
+    /// Tasks to run, at most [`MAX_TASK_BATCH_SIZE`]
+    pub tasks: Vec<TaskRequest>,
+}
+
+impl TaskBatchRequest {
+    /// Create a batch of tasks
+    pub fn new(tasks: Vec<TaskRequest>) -> Self {
+        Self { tasks }
+    }
+
+    /// Split tasks into batches of at most `max_size`, in order
+    pub fn batches(tasks: Vec<TaskRequest>, max_size: usize) -> Vec<Self> {
+        let max_size = max_size.clamp(1, MAX_TASK_BATCH_SIZE);
+        let mut batches = Vec::with_capacity(tasks.len().div_ceil(max_size));
+        let mut tasks = tasks.into_iter().peekable();
+        while tasks.peek().is_some() {
+            batches.push(Self::new(tasks.by_ref().take(max_size).collect()));
+        }
+        batches
+    }
+
+    /// Get the number of tasks
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Check if the batch has no tasks
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+/// Outcome of one task of a [`TaskBatchRequest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TaskBatchResult {
+    /// Task accepted and queued
+    Accepted {
+        node_id: Uuid,
+        task_id: Uuid,
+        accepted_at: DateTime<Utc>,
+    },
+    /// Task refused, e.g. for an unknown node type; it never runs and
+    /// sends no callbacks
+    Rejected {
+        node_id: Uuid,
+        error: String,
+        error_code: Option<String>,
+    },
+}
+
+impl TaskBatchResult {
+    /// Create the result of an accepted task
+    pub fn accepted(node_id: Uuid, response: &TaskResponse) -> Self {
+        Self::Accepted {
+            node_id,
+            task_id: response.task_id,
+            accepted_at: response.accepted_at,
+        }
+    }
+
+    /// Create the result of a rejected task
+    pub fn rejected(node_id: Uuid, error: String, error_code: Option<String>) -> Self {
+        Self::Rejected {
+            node_id,
+            error,
+            error_code,
+        }
+    }
+
+    /// Get the node ID of the task
+    pub fn node_id(&self) -> Uuid {
+        match self {
+            Self::Accepted { node_id, .. } => *node_id,
+            Self::Rejected { node_id, .. } => *node_id,
+        }
+    }
+
+    /// Get the response a single submission would have received, if the
+    /// task was accepted
+    pub fn response(&self) -> Option<TaskResponse> {
+        match self {
+            Self::Accepted {
+                task_id,
+                accepted_at,
+                ..
+            } => Some(TaskResponse {
+                task_id: *task_id,
+                status: TaskStatus::Accepted,
+                accepted_at: *accepted_at,
+            }),
+            Self::Rejected { .. } => None,
+        }
+    }
+}
+
+/// Batch submission response from server
+///
+/// Holds one result per task, in request order; the batch as a whole
+/// succeeds even if some of its tasks are rejected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskBatchResponse {
+    pub results: Vec<TaskBatchResult>,
+}
+
+impl TaskBatchResponse {
+    /// Get the result for a node's task
+    pub fn result(&self, node_id: Uuid) -> Option<&TaskBatchResult> {
+        self.results
+            .iter()
+            .find(|result| result.node_id() == node_id)
+    }
+
+    /// Get the accepted tasks, by node ID
+    pub fn accepted(&self) -> Vec<(Uuid, TaskResponse)> {
+        self.results
+            .iter()
+            .filter_map(|result| Some((result.node_id(), result.response()?)))
+            .collect()
+    }
+
+    /// Get the rejected tasks
+    pub fn rejected(&self) -> Vec<&TaskBatchResult> {
+        self.results
+            .iter()
+            .filter(|result| matches!(result, TaskBatchResult::Rejected { .. }))
+            .collect()
+    }
+
+    /// Get the nodes of `request` the server sent no result for
+    ///
+    /// Their outcome is unknown; poll or resubmit them.
+    pub fn unanswered(&self, request: &TaskBatchRequest) -> Vec<Uuid> {
+        request
+            .tasks
+            .iter()
+            .map(|task| task.node_id)
+            .filter(|node_id| self.result(*node_id).is_none())
+            .collect()
+    }
+}
+
 // ============================================================================
 // Callbacks
 // ============================================================================
@@ -106,6 +434,24 @@ pub enum CallbackMessage {
         progress: f64,
         message: Option<String>,
         timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        trace: CallbackTrace,
+    },
+    /// Chunk of an output streamed while the task runs, e.g. tokens of an
+    /// LLM response
+    ///
+    /// Chunks of each output are numbered from 0 and appended in order.
+    /// They only preview the output: `Complete` still carries the whole
+    /// of it.
+    PartialOutput {
+        task_id: Uuid,
+        /// Output port the chunk belongs to
+        output: String,
+        sequence: u64,
+        chunk: String,
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        trace: CallbackTrace,
     },
     /// Task completed successfully
     Complete {
@@ -113,6 +459,8 @@ pub enum CallbackMessage {
         outputs: Vec<TaskOutput>,
         duration_ms: u64,
         timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        trace: CallbackTrace,
     },
     /// Task failed
     Failed {
@@ -120,7 +468,33 @@ pub enum CallbackMessage {
         error: String,
         error_code: Option<String>,
         timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        trace: CallbackTrace,
     },
+    /// Task was stopped by a [`TaskCancelRequest`]
+    Cancelled {
+        task_id: Uuid,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+        /// Whether the task was killed instead of stopping on its own
+        #[serde(default)]
+        forced: bool,
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        trace: CallbackTrace,
+    },
+}
+
+/// Trace identifiers carried by a callback
+///
+/// Servers copy `trace_id` from the [`TaskRequest`] and set
+/// `parent_span_id` to the span that executed the task.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallbackTrace {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
 }
 
 impl CallbackMessage {
@@ -128,11 +502,36 @@ impl CallbackMessage {
     pub fn task_id(&self) -> Uuid {
         match self {
             Self::Progress { task_id, .. } => *task_id,
+            Self::PartialOutput { task_id, .. } => *task_id,
             Self::Complete { task_id, .. } => *task_id,
             Self::Failed { task_id, .. } => *task_id,
+            Self::Cancelled { task_id, .. } => *task_id,
         }
     }
 
+    /// Get the trace identifiers
+    pub fn trace(&self) -> &CallbackTrace {
+        match self {
+            Self::Progress { trace, .. } => trace,
+            Self::PartialOutput { trace, .. } => trace,
+            Self::Complete { trace, .. } => trace,
+            Self::Failed { trace, .. } => trace,
+            Self::Cancelled { trace, .. } => trace,
+        }
+    }
+
+    /// Set the trace identifiers
+    pub fn with_trace(mut self, trace: CallbackTrace) -> Self {
+        match &mut self {
+            Self::Progress { trace: t, .. } => *t = trace,
+            Self::PartialOutput { trace: t, .. } => *t = trace,
+            Self::Complete { trace: t, .. } => *t = trace,
+            Self::Failed { trace: t, .. } => *t = trace,
+            Self::Cancelled { trace: t, .. } => *t = trace,
+        }
+        self
+    }
+
     /// Create a progress callback
     pub fn progress(task_id: Uuid, progress: f64, message: Option<String>) -> Self {
         Self::Progress {
@@ -140,6 +539,19 @@ impl CallbackMessage {
             progress,
             message,
             timestamp: Utc::now(),
+            trace: CallbackTrace::default(),
+        }
+    }
+
+    /// Create a partial output callback
+    pub fn partial_output(task_id: Uuid, output: &str, sequence: u64, chunk: String) -> Self {
+        Self::PartialOutput {
+            task_id,
+            output: output.to_string(),
+            sequence,
+            chunk,
+            timestamp: Utc::now(),
+            trace: CallbackTrace::default(),
         }
     }
 
@@ -150,6 +562,7 @@ impl CallbackMessage {
             outputs,
             duration_ms,
             timestamp: Utc::now(),
+            trace: CallbackTrace::default(),
         }
     }
 
@@ -160,6 +573,18 @@ impl CallbackMessage {
             error,
             error_code,
             timestamp: Utc::now(),
+            trace: CallbackTrace::default(),
+        }
+    }
+
+    /// Create a cancellation callback
+    pub fn cancelled(task_id: Uuid, reason: Option<String>, forced: bool) -> Self {
+        Self::Cancelled {
+            task_id,
+            reason,
+            forced,
+            timestamp: Utc::now(),
+            trace: CallbackTrace::default(),
         }
     }
 }
@@ -169,9 +594,15 @@ impl CallbackMessage {
 #[serde(untagged)]
 pub enum TaskOutput {
     /// Inline data (for small values)
-    Inline { name: String, value: serde_json::Value },
+    Inline {
+        name: String,
+        value: serde_json::Value,
+    },
     /// Reference to remote data
-    Reference { name: String, data_ref: DataRef },
+    Reference {
+        name: String,
+        data_ref: Box<DataRef>,
+    },
 }
 
 impl TaskOutput {
@@ -187,7 +618,7 @@ impl TaskOutput {
     pub fn reference(name: &str, data_ref: DataRef) -> Self {
         Self::Reference {
             name: name.to_string(),
-            data_ref,
+            data_ref: Box::new(data_ref),
         }
     }
 }
@@ -215,17 +646,330 @@ pub struct TaskStatusResponse {
     pub completed_at: Option<DateTime<Utc>>,
 }
 
+// ============================================================================
+// Task Cancellation
+// ============================================================================
+
+/// How a server stops a task
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelMode {
+    /// Ask the task to stop at its next opportunity, letting it clean up
+    #[default]
+    BestEffort,
+    /// Kill the task right away, e.g. its process, discarding its work
+    Force,
+}
+
+/// Request to stop a task, sent to the server as `POST /tasks/{id}/cancel`
+///
+/// A task the server stops reports a [`CallbackMessage::Cancelled`], which
+/// is what moves its node to cancelled; the [`TaskCancelResponse`] only
+/// says whether one will follow.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskCancelRequest {
+    #[serde(default)]
+    pub mode: CancelMode,
+    /// Why the task is cancelled, e.g. "execution cancelled by user"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl TaskCancelRequest {
+    /// Create a request to stop a task at its next opportunity
+    pub fn best_effort(reason: Option<String>) -> Self {
+        Self {
+            mode: CancelMode::BestEffort,
+            reason,
+        }
+    }
+
+    /// Create a request to kill a task
+    pub fn force(reason: Option<String>) -> Self {
+        Self {
+            mode: CancelMode::Force,
+            reason,
+        }
+    }
+}
+
+/// What a server did with a [`TaskCancelRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelOutcome {
+    /// The task was asked to stop and has not yet
+    Cancelling,
+    /// The task was stopped
+    Cancelled,
+    /// The task had already completed, failed, or been cancelled; its
+    /// callback stands
+    AlreadyFinished,
+}
+
+/// Task cancellation response from server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskCancelResponse {
+    pub task_id: Uuid,
+    pub outcome: CancelOutcome,
+}
+
+impl TaskCancelResponse {
+    /// Check if a [`CallbackMessage::Cancelled`] follows
+    pub fn callback_follows(&self) -> bool {
+        self.outcome != CancelOutcome::AlreadyFinished
+    }
+}
+
+// ============================================================================
+// Task Channel
+// ============================================================================
+
+/// Path servers accept task channel WebSockets on
+pub const TASK_CHANNEL_PATH: &str = "/tasks/ws";
+
+/// Interval between heartbeats on an idle task channel: 15 seconds
+///
+/// A side that hears nothing from its peer for three intervals closes the
+/// channel.
+pub const TASK_CHANNEL_HEARTBEAT_SECS: u64 = 15;
+
+/// Message on a task channel, a WebSocket between a client and a server
+///
+/// Interactive nodes that cannot afford a request per task and a callback
+/// per update run over one long-lived connection instead: the client
+/// submits and cancels tasks, and the server answers with the same
+/// results and callbacks it would otherwise send over HTTP, so a task's
+/// `callback_url` is not used. Each message is one JSON text frame tagged
+/// with its `type`:
+///
+/// ```json
+/// { "type": "submit", "task": { "node_id": "...", ... } }
+/// { "type": "submitted", "status": "accepted", "node_id": "...", "task_id": "...", ... }
+/// { "type": "callback", "status": "partial_output", "task_id": "...", "chunk": "Hel", ... }
+/// { "type": "cancel", "task_id": "..." }
+/// { "type": "heartbeat", "sent_at": "..." }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskChannelMessage {
+    /// Client to server: run a task
+    Submit { task: TaskRequest },
+    /// Server to client: the task was accepted or rejected
+    Submitted(TaskBatchResult),
+    /// Server to client: progress, partial output, completion, or failure
+    /// of an accepted task
+    Callback(CallbackMessage),
+    /// Client to server: stop a task
+    Cancel {
+        task_id: Uuid,
+        #[serde(flatten)]
+        request: TaskCancelRequest,
+    },
+    /// Server to client: the task was stopped and sends nothing further
+    Cancelled { task_id: Uuid },
+    /// Either side: the connection is alive
+    Heartbeat { sent_at: DateTime<Utc> },
+    /// Either side: a message could not be handled, e.g. it was malformed
+    /// or named an unknown task
+    Error {
+        code: String,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        task_id: Option<Uuid>,
+    },
+}
+
+impl TaskChannelMessage {
+    /// Create a submission
+    pub fn submit(task: TaskRequest) -> Self {
+        Self::Submit { task }
+    }
+
+    /// Create a cancellation request
+    pub fn cancel(task_id: Uuid, request: TaskCancelRequest) -> Self {
+        Self::Cancel { task_id, request }
+    }
+
+    /// Create a heartbeat sent now
+    pub fn heartbeat() -> Self {
+        Self::Heartbeat {
+            sent_at: Utc::now(),
+        }
+    }
+
+    /// Create an error report
+    pub fn error(code: &str, message: &str, task_id: Option<Uuid>) -> Self {
+        Self::Error {
+            code: code.to_string(),
+            message: message.to_string(),
+            task_id,
+        }
+    }
+
+    /// Check if only the client sends this message
+    pub fn is_from_client(&self) -> bool {
+        matches!(self, Self::Submit { .. } | Self::Cancel { .. })
+    }
+
+    /// Check if only the server sends this message
+    pub fn is_from_server(&self) -> bool {
+        matches!(
+            self,
+            Self::Submitted(_) | Self::Callback(_) | Self::Cancelled { .. }
+        )
+    }
+
+    /// Get the ID of the task the message is about, once the server
+    /// assigned one
+    pub fn task_id(&self) -> Option<Uuid> {
+        match self {
+            Self::Submitted(result) => result.response().map(|response| response.task_id),
+            Self::Callback(message) => Some(message.task_id()),
+            Self::Cancel { task_id, .. } => Some(*task_id),
+            Self::Cancelled { task_id } => Some(*task_id),
+            Self::Error { task_id, .. } => *task_id,
+            Self::Submit { .. } | Self::Heartbeat { .. } => None,
+        }
+    }
+}
+
+// ============================================================================
+// Server Registry
+// ============================================================================
+
+/// Interval between heartbeats of a registered server: 10 seconds
+pub const SERVER_HEARTBEAT_INTERVAL_SECS: u64 = 10;
+
+/// Time without a heartbeat after which a server is taken as unhealthy:
+/// 30 seconds, three missed heartbeats
+pub const SERVER_HEARTBEAT_TIMEOUT_SECS: u64 = 30;
+
+/// GPU on a server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuInfo {
+    /// Device name, e.g. "NVIDIA A100-SXM4-80GB"
+    pub name: String,
+    /// Device memory in bytes
+    pub memory_total: u64,
+    /// Free device memory in bytes
+    pub memory_available: u64,
+    /// Utilization (0.0 to 1.0)
+    #[serde(default)]
+    pub utilization: f64,
+}
+
+/// Resources of a server, reported on registration and with every
+/// heartbeat
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerResources {
+    /// Memory in bytes
+    #[serde(default)]
+    pub memory_total: u64,
+    /// Free memory in bytes
+    #[serde(default)]
+    pub memory_available: u64,
+    #[serde(default)]
+    pub gpus: Vec<GpuInfo>,
+    /// Models loaded and ready to serve, e.g. for LLM affinity
+    #[serde(default)]
+    pub loaded_models: Vec<String>,
+    /// Tasks accepted and not yet running
+    #[serde(default)]
+    pub queue_depth: u32,
+    /// Current load (0.0 to 1.0)
+    #[serde(default)]
+    pub load: f64,
+}
+
+/// Server registration, sent as `POST /servers` when a server starts
+///
+/// A server that restarts registers again, replacing what was known about
+/// it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerRegistration {
+    /// Server address (e.g., "http://localhost:9090")
+    pub address: String,
+    /// Prefixes of the node types the server runs; any if empty
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Protocol version the server speaks, 1.0 if left out
+    #[serde(default)]
+    pub protocol_version: ProtocolVersion,
+    /// Whether the server has a GPU, for servers that do not list them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gpu_available: bool,
+    #[serde(flatten)]
+    pub resources: ServerResources,
+}
+
+impl ServerRegistration {
+    /// Create a registration of a server speaking this crate's version
+    pub fn new(address: String, capabilities: Vec<String>, resources: ServerResources) -> Self {
+        Self {
+            address,
+            capabilities,
+            protocol_version: crate::version::PROTOCOL_VERSION,
+            gpu_available: false,
+            resources,
+        }
+    }
+
+    /// Check if the server has a GPU
+    pub fn has_gpu(&self) -> bool {
+        self.gpu_available || !self.resources.gpus.is_empty()
+    }
+}
+
+/// Heartbeat of a registered server, sent as `POST /servers/heartbeat`
+/// every [`SERVER_HEARTBEAT_INTERVAL_SECS`]
+///
+/// A heartbeat from a server that is not registered, e.g. after the client
+/// restarted, is refused with `404`, and the server registers again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerHeartbeat {
+    /// Server address, as registered
+    pub address: String,
+    pub sent_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub resources: ServerResources,
+}
+
+impl ServerHeartbeat {
+    /// Create a heartbeat sent now
+    pub fn new(address: String, resources: ServerResources) -> Self {
+        Self {
+            address,
+            sent_at: Utc::now(),
+            resources,
+        }
+    }
+}
+
 // ============================================================================
 // Data Operations
 // ============================================================================
 
 /// Data fetch request
+///
+/// One [scoped](swarmx_dataref::TokenScope) token can authorize every
+/// requested item; see
+/// [`TokenManager::authorize`](swarmx_dataref::TokenManager::authorize).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataFetchRequest {
-    /// UUID of data to fetch
-    pub data_uuid: Uuid,
-    /// Access token for authorization
-    pub access_token: String,
+    /// UUIDs of data to fetch
+    pub data_uuids: Vec<Uuid>,
+    /// Access token covering every requested item
+    pub access_token: AccessToken,
+}
+
+impl DataFetchRequest {
+    /// Fetch a single data item
+    pub fn single(data_uuid: Uuid, access_token: AccessToken) -> Self {
+        Self {
+            data_uuids: vec![data_uuid],
+            access_token,
+        }
+    }
 }
 
 /// Data store request
@@ -239,6 +983,23 @@ pub struct DataStoreRequest {
     pub content_type: String,
     /// Size in bytes
     pub size_bytes: u64,
+    /// Tenant whose quota the workflow's data counts against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl DataStoreRequest {
+    /// Admit the request under the registry's storage quotas
+    ///
+    /// Assigns the request's tenant to its workflow, then checks that
+    /// storing `size_bytes` more stays within the workflow's and tenant's
+    /// quotas.
+    pub fn admit(&self, registry: &mut DataRefRegistry) -> Result<(), DataRefError> {
+        if let Some(tenant) = &self.tenant {
+            registry.assign_tenant(self.workflow_id, tenant.clone());
+        }
+        registry.check_quota(self.workflow_id, self.size_bytes)
+    }
 }
 
 /// Data store response
@@ -248,6 +1009,230 @@ pub struct DataStoreResponse {
     pub data_ref: DataRef,
 }
 
+// ============================================================================
+// Multipart Upload
+// ============================================================================
+
+/// Starts a multipart upload
+///
+/// The payload is then sent in parts, each to its own presigned URL, and
+/// stored once the upload is completed with [`CompleteMultipartUpload`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUploadRequest {
+    /// Workflow the data belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow_id: Option<Uuid>,
+    /// Content type (MIME), selecting the data type as for a single upload
+    #[serde(default)]
+    pub content_type: String,
+    /// Payload size in bytes
+    pub size_bytes: u64,
+    /// Size of every part but the last; the receiver's default if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part_size: Option<u64>,
+    /// Tags, as `key=value` pairs separated by commas
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+    /// Tenant whose quota the workflow's data counts against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl MultipartUploadRequest {
+    /// Request to store the whole payload, for quota admission
+    pub fn store_request(&self, dtype: &str) -> Option<DataStoreRequest> {
+        Some(DataStoreRequest {
+            workflow_id: self.workflow_id?,
+            dtype: dtype.to_string(),
+            content_type: self.content_type.clone(),
+            size_bytes: self.size_bytes,
+            tenant: self.tenant.clone(),
+        })
+    }
+}
+
+/// Where to send one part of a multipart upload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadPartUrl {
+    /// Position of the part, numbered from 1
+    pub part_number: u32,
+    /// Position of the part's first byte in the payload
+    pub offset: u64,
+    /// Part length in bytes
+    pub size: u64,
+    /// Presigned URL to `PUT` the part's bytes to
+    pub url: String,
+}
+
+/// Receiver's answer to [`MultipartUploadRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUploadResponse {
+    pub upload_id: Uuid,
+    /// UUID the data is stored under once the upload completes
+    pub data_uuid: Uuid,
+    /// Size of every part but the last
+    pub part_size: u64,
+    /// Every part of the payload, in order
+    pub parts: Vec<UploadPartUrl>,
+    /// When the upload and its URLs expire unless completed
+    pub expires_at: DateTime<Utc>,
+}
+
+impl MultipartUploadResponse {
+    /// Describe an upload, with `url` giving the presigned URL of each
+    /// part number
+    pub fn new(upload: &MultipartUpload, url: impl Fn(u32) -> String) -> Self {
+        let parts = (1..=upload.part_count())
+            .filter_map(|part_number| {
+                let range = upload.part_range(part_number)?;
+                Some(UploadPartUrl {
+                    part_number,
+                    offset: range.offset,
+                    size: range.len.unwrap_or_default(),
+                    url: url(part_number),
+                })
+            })
+            .collect();
+        Self {
+            upload_id: upload.upload_id,
+            data_uuid: upload.data_ref.uuid,
+            part_size: upload.part_size,
+            parts,
+            expires_at: upload.expires_at,
+        }
+    }
+}
+
+/// Completes a multipart upload, storing the payload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompleteMultipartUpload {
+    /// Parts as acknowledged when they were sent; each must match the part
+    /// received. Leave empty to skip the check.
+    #[serde(default)]
+    pub parts: Vec<UploadedPart>,
+}
+
+// ============================================================================
+// Data Inventory
+// ============================================================================
+
+/// Data a server holds, reported for cluster-wide garbage collection
+///
+/// Servers list every UUID in their stores, not just changes; the
+/// coordinator reconciles the list with the catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventoryReport {
+    /// Server address, as in DataRef locations
+    pub server: String,
+    /// UUIDs of the data the server holds
+    pub data: Vec<Uuid>,
+    /// When the server listed its data; receipt time if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reported_at: Option<DateTime<Utc>>,
+}
+
+impl InventoryReport {
+    /// Inventory as recorded by the coordinator
+    pub fn into_inventory(self) -> ServerInventory {
+        ServerInventory {
+            server: self.server,
+            data: self.data.into_iter().collect(),
+            reported_at: self.reported_at.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+// ============================================================================
+// Chunked Transfer
+// ============================================================================
+
+/// Starts a chunked transfer, or resumes it after a dropped connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferInit {
+    /// Same ID on every attempt of a transfer
+    pub transfer_id: Uuid,
+    /// Data being transferred
+    pub data_ref: DataRef,
+    /// Payload size in bytes
+    pub total_size: u64,
+    /// Size of every chunk but the last
+    pub chunk_size: u64,
+}
+
+impl From<TransferPlan> for TransferInit {
+    fn from(plan: TransferPlan) -> Self {
+        Self {
+            transfer_id: plan.transfer_id,
+            data_ref: plan.data_ref,
+            total_size: plan.total_size,
+            chunk_size: plan.chunk_size,
+        }
+    }
+}
+
+impl From<TransferInit> for TransferPlan {
+    fn from(init: TransferInit) -> Self {
+        Self {
+            transfer_id: init.transfer_id,
+            data_ref: init.data_ref,
+            total_size: init.total_size,
+            chunk_size: init.chunk_size,
+        }
+    }
+}
+
+/// Receiver's answer to [`TransferInit`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferInitResponse {
+    pub transfer_id: Uuid,
+    /// Bytes already received; the sender continues from here
+    pub resume_offset: u64,
+}
+
+/// Header of a transfer chunk; the chunk's bytes are the request body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferChunk {
+    pub transfer_id: Uuid,
+    /// Position of the chunk in the payload
+    pub offset: u64,
+    /// Chunk length in bytes
+    pub length: u64,
+    /// BLAKE3 checksum of the chunk's bytes
+    pub checksum: String,
+}
+
+impl TransferChunk {
+    /// Create the header of a chunk
+    pub fn new(transfer_id: Uuid, chunk: &Chunk) -> Self {
+        Self {
+            transfer_id,
+            offset: chunk.offset,
+            length: chunk.data.len() as u64,
+            checksum: chunk.checksum.clone(),
+        }
+    }
+
+    /// Combine the header with the received bytes
+    pub fn into_chunk(self, data: Vec<u8>) -> Chunk {
+        Chunk {
+            offset: self.offset,
+            checksum: self.checksum,
+            data,
+        }
+    }
+}
+
+/// Finishes a transfer once every chunk is sent
+///
+/// The receiver verifies the whole payload against the data's checksum and
+/// responds with the stored [`DataRef`] in a [`DataStoreResponse`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferComplete {
+    pub transfer_id: Uuid,
+    /// Bytes sent in total
+    pub total_size: u64,
+}
+
 // ============================================================================
 // Workflow DSL Types
 // ============================================================================
@@ -273,6 +1258,9 @@ pub struct WorkflowDefinition {
     /// Metadata
     #[serde(default)]
     pub metadata: WorkflowMetadata,
+    /// Events that start this workflow
+    #[serde(default)]
+    pub triggers: Vec<WorkflowTrigger>,
 }
 
 impl WorkflowDefinition {
@@ -287,6 +1275,7 @@ impl WorkflowDefinition {
             edges: Vec::new(),
             execution: ExecutionConfig::default(),
             metadata: WorkflowMetadata::default(),
+            triggers: Vec::new(),
         }
     }
 
@@ -382,6 +1371,9 @@ pub struct ExecutionConfig {
     /// Retry policy
     #[serde(default)]
     pub retry_policy: Option<RetryPolicyConfig>,
+    /// Reaction to terminal node failures
+    #[serde(default)]
+    pub failure_policy: FailurePolicyConfig,
 }
 
 impl Default for ExecutionConfig {
@@ -391,6 +1383,7 @@ impl Default for ExecutionConfig {
             server: None,
             timeout_ms: Some(300000), // 5 minutes
             retry_policy: Some(RetryPolicyConfig::default()),
+            failure_policy: FailurePolicyConfig::default(),
         }
     }
 }
@@ -426,6 +1419,21 @@ impl Default for RetryPolicyConfig {
     }
 }
 
+/// Failure policy configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicyConfig {
+    /// Cancel everything on the first terminal node failure
+    #[default]
+    FailFast,
+    /// Keep running independent branches; the workflow fails at the end
+    Continue,
+    /// Keep running independent branches; the workflow completes with errors
+    Ignore,
+    /// Park exhausted nodes for manual release; independent branches continue
+    Quarantine,
+}
+
 /// Workflow metadata
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct WorkflowMetadata {
@@ -446,6 +1454,57 @@ pub struct WorkflowMetadata {
     pub description: Option<String>,
 }
 
+/// Starts a workflow when a matching event is committed
+///
+/// ```json
+/// {
+///   "id": "ingest-raw",
+///   "on": { "event_type": "data_created", "fields": { "tags": "raw" } },
+///   "bindings": { "input": { "event": "data_uuid" }, "mode": { "value": "full" } }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowTrigger {
+    /// Identifies the trigger within its workflow
+    pub id: String,
+    /// Events that fire the trigger
+    pub on: TriggerCondition,
+    /// Variables to set on the started execution, on top of the workflow's
+    #[serde(default)]
+    pub bindings: BTreeMap<String, TriggerBinding>,
+    /// Disabled triggers are kept but never fire
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Events a trigger fires on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerCondition {
+    /// Event type, e.g. `workflow_completed`
+    pub event_type: String,
+    /// Only events of this workflow
+    #[serde(default)]
+    pub workflow_id: Option<Uuid>,
+    /// Values event fields must have, by dotted path; an array field
+    /// matches if it contains the value
+    #[serde(default)]
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+/// Source of a variable set by a trigger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerBinding {
+    /// A fixed value
+    Value(serde_json::Value),
+    /// The field of the triggering event at a dotted path, e.g. `data_uuid`
+    Event(String),
+}
+
 // ============================================================================
 // API Responses
 // ============================================================================
@@ -580,6 +1639,9 @@ mod tests {
             config: serde_json::json!({"model": "gpt-4"}),
             callback_url: "http://localhost:3000/callback".to_string(),
             timeout_ms: Some(60000),
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
         };
 
         let json = serde_json::to_string(&request).unwrap();
@@ -587,11 +1649,381 @@ mod tests {
         assert_eq!(parsed.node_type, "ai.openai.chat");
     }
 
+    #[test]
+    fn test_task_batch() {
+        let task = |node_type: &str| TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: node_type.to_string(),
+            inputs: vec![TaskInput::inline("item", serde_json::json!(1))],
+            config: serde_json::json!({}),
+            callback_url: "http://localhost:3000/callback".to_string(),
+            timeout_ms: None,
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
+        };
+        let tasks: Vec<TaskRequest> = (0..200).map(|_| task("util.map")).collect();
+        let batches = TaskBatchRequest::batches(tasks.clone(), 64);
+        let sizes: Vec<usize> = batches.iter().map(TaskBatchRequest::len).collect();
+        assert_eq!(sizes, [64, 64, 64, 8]);
+        assert_eq!(batches[3].tasks[7].node_id, tasks[199].node_id);
+        assert!(TaskBatchRequest::batches(Vec::new(), 64).is_empty());
+
+        let batch = TaskBatchRequest::new(tasks[..3].to_vec());
+        let accepted = TaskResponse {
+            task_id: Uuid::new_v4(),
+            status: TaskStatus::Accepted,
+            accepted_at: Utc::now(),
+        };
+        let response = TaskBatchResponse {
+            results: vec![
+                TaskBatchResult::accepted(batch.tasks[0].node_id, &accepted),
+                TaskBatchResult::rejected(
+                    batch.tasks[1].node_id,
+                    "Unknown node type".to_string(),
+                    Some("UNKNOWN_NODE_TYPE".to_string()),
+                ),
+            ],
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(json.contains(r#""status":"rejected""#));
+        let parsed: TaskBatchResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.results, response.results);
+
+        let accepted_tasks = parsed.accepted();
+        assert_eq!(accepted_tasks.len(), 1);
+        assert_eq!(accepted_tasks[0].0, batch.tasks[0].node_id);
+        assert_eq!(accepted_tasks[0].1.task_id, accepted.task_id);
+        assert_eq!(parsed.rejected()[0].node_id(), batch.tasks[1].node_id);
+        assert_eq!(parsed.unanswered(&batch), [batch.tasks[2].node_id]);
+    }
+
+    #[test]
+    fn test_idempotency_keys() {
+        let execution_id = Uuid::new_v4();
+        let request = TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: "ml.train".to_string(),
+            inputs: Vec::new(),
+            config: serde_json::json!({}),
+            callback_url: "http://localhost:3000/callback".to_string(),
+            timeout_ms: None,
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("idempotency_key"));
+
+        let first = request.clone().with_idempotency_key(execution_id, 1);
+        let resent = request.clone().with_idempotency_key(execution_id, 1);
+        let retry = request.clone().with_idempotency_key(execution_id, 2);
+        assert_eq!(first.idempotency_key, resent.idempotency_key);
+        assert_ne!(first.idempotency_key, retry.idempotency_key);
+
+        let mut cache = IdempotencyCache::default();
+        let accept = || TaskResponse {
+            task_id: Uuid::new_v4(),
+            status: TaskStatus::Accepted,
+            accepted_at: Utc::now(),
+        };
+        let original = cache.submit(&first, accept);
+        assert_eq!(cache.submit(&resent, accept).task_id, original.task_id);
+        assert_ne!(cache.submit(&retry, accept).task_id, original.task_id);
+        assert_ne!(cache.submit(&request, accept).task_id, original.task_id);
+        assert_eq!(cache.len(), 2);
+
+        cache.prune(Utc::now() + chrono::Duration::days(2));
+        assert!(cache.is_empty());
+        assert_ne!(cache.submit(&resent, accept).task_id, original.task_id);
+    }
+
+    #[test]
+    fn test_task_input_for_data() {
+        let value = serde_json::json!({ "rows": [1, 2, 3] });
+        let data_ref = DataRef::json("server-a".to_string(), Uuid::new_v4(), &value);
+
+        let input = TaskInput::for_data(
+            "table",
+            data_ref.clone(),
+            Some(value.clone()),
+            &InlinePolicy::default(),
+        );
+        assert!(matches!(input, TaskInput::Inline { .. }));
+        let input = TaskInput::for_data("table", data_ref.clone(), None, &InlinePolicy::default());
+        assert!(matches!(input, TaskInput::Reference { .. }));
+        let input = TaskInput::for_data("table", data_ref, Some(value), &InlinePolicy::new(8));
+        assert!(matches!(input, TaskInput::Reference { .. }));
+    }
+
+    #[test]
+    fn test_local_file_input() {
+        let data_ref = DataRef::bytes("http://gpu-1:3000/api".to_string(), Uuid::new_v4(), b"");
+        let path = PathBuf::from("/var/lib/swarmx/data/tensor");
+
+        let input = TaskInput::for_server(
+            "weights",
+            data_ref.clone(),
+            Some(path.clone()),
+            "http://gpu-1:9090",
+        );
+        let json = serde_json::to_string(&input).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            TaskInput::LocalFile {
+                path: parsed,
+                data_ref: parsed_ref,
+                ..
+            } => {
+                assert_eq!(parsed, path);
+                assert_eq!(parsed_ref.uuid, data_ref.uuid);
+            }
+            other => panic!("expected a local file input, got {other:?}"),
+        }
+
+        let input =
+            TaskInput::for_server("weights", data_ref.clone(), Some(path), "http://gpu-2:9090");
+        assert!(matches!(input, TaskInput::Reference { .. }));
+        let input = TaskInput::for_server("weights", data_ref, None, "http://gpu-1:9090");
+        assert!(matches!(input, TaskInput::Reference { .. }));
+    }
+
     #[test]
     fn test_callback_message_serialization() {
         let msg = CallbackMessage::progress(Uuid::new_v4(), 0.5, Some("Processing".to_string()));
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("progress"));
+        assert!(!json.contains("trace_id"));
+
+        let trace = CallbackTrace {
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            parent_span_id: Some("00f067aa0ba902b7".to_string()),
+        };
+        let msg = CallbackMessage::failed(Uuid::new_v4(), "boom".to_string(), None)
+            .with_trace(trace.clone());
+        let parsed: CallbackMessage =
+            serde_json::from_str(&serde_json::to_string(&msg).unwrap()).unwrap();
+        assert_eq!(parsed.trace(), &trace);
+    }
+
+    #[test]
+    fn test_partial_output() {
+        let task_id = Uuid::new_v4();
+        let msg = CallbackMessage::partial_output(task_id, "text", 0, "Hel".to_string());
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""status":"partial_output""#));
+        let parsed: CallbackMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.task_id(), task_id);
+    }
+
+    #[test]
+    fn test_task_cancellation() {
+        let task_id = Uuid::new_v4();
+
+        // Mode defaults to best effort
+        let request: TaskCancelRequest = serde_json::from_str("{}").unwrap();
+        assert_eq!(request, TaskCancelRequest::best_effort(None));
+        let request = TaskCancelRequest::force(Some("execution cancelled".to_string()));
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(json, r#"{"mode":"force","reason":"execution cancelled"}"#);
+
+        let response = TaskCancelResponse {
+            task_id,
+            outcome: CancelOutcome::AlreadyFinished,
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(json.contains(r#""outcome":"already_finished""#));
+        assert!(!response.callback_follows());
+
+        let msg = CallbackMessage::cancelled(task_id, request.reason, true);
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""status":"cancelled""#));
+        match serde_json::from_str(&json).unwrap() {
+            CallbackMessage::Cancelled {
+                task_id: id,
+                forced,
+                ..
+            } => {
+                assert_eq!(id, task_id);
+                assert!(forced);
+            }
+            other => panic!("expected a cancellation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_task_channel_messages() {
+        let roundtrip = |message: &TaskChannelMessage| -> (serde_json::Value, TaskChannelMessage) {
+            let json = serde_json::to_string(message).unwrap();
+            (
+                serde_json::from_str(&json).unwrap(),
+                serde_json::from_str(&json).unwrap(),
+            )
+        };
+        let task_id = Uuid::new_v4();
+
+        let submit = TaskChannelMessage::submit(TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: "ai.openai.chat".to_string(),
+            inputs: Vec::new(),
+            config: serde_json::json!({}),
+            callback_url: String::new(),
+            timeout_ms: None,
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
+        });
+        let (json, parsed) = roundtrip(&submit);
+        assert_eq!(json["type"], "submit");
+        assert_eq!(json["task"]["node_type"], "ai.openai.chat");
+        assert!(parsed.is_from_client());
+        assert_eq!(parsed.task_id(), None);
+
+        let accepted = TaskChannelMessage::Submitted(TaskBatchResult::Accepted {
+            node_id: Uuid::new_v4(),
+            task_id,
+            accepted_at: Utc::now(),
+        });
+        let (json, parsed) = roundtrip(&accepted);
+        assert_eq!(json["type"], "submitted");
+        assert_eq!(json["status"], "accepted");
+        assert_eq!(parsed.task_id(), Some(task_id));
+
+        let chunk = TaskChannelMessage::Callback(
+            CallbackMessage::partial_output(task_id, "text", 3, "lo".to_string()).with_trace(
+                CallbackTrace {
+                    trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+                    parent_span_id: None,
+                },
+            ),
+        );
+        let (json, parsed) = roundtrip(&chunk);
+        assert_eq!(json["type"], "callback");
+        assert_eq!(json["status"], "partial_output");
+        match parsed {
+            TaskChannelMessage::Callback(CallbackMessage::PartialOutput {
+                sequence,
+                trace,
+                ..
+            }) => {
+                assert_eq!(sequence, 3);
+                assert!(trace.trace_id.is_some());
+            }
+            other => panic!("expected a partial output, got {other:?}"),
+        }
+
+        let (json, parsed) = roundtrip(&TaskChannelMessage::cancel(
+            task_id,
+            TaskCancelRequest::force(None),
+        ));
+        assert_eq!(
+            json,
+            serde_json::json!({"type": "cancel", "task_id": task_id, "mode": "force"})
+        );
+        assert_eq!(parsed.task_id(), Some(task_id));
+        let (_, parsed) = roundtrip(&TaskChannelMessage::heartbeat());
+        assert!(!parsed.is_from_client() && !parsed.is_from_server());
+    }
+
+    #[test]
+    fn test_server_registration() {
+        // Registrations of servers that predate resource reporting
+        let legacy: ServerRegistration = serde_json::from_str(
+            r#"{"address": "http://gpu-1:9090", "capabilities": ["ml."], "gpu_available": true}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.protocol_version, ProtocolVersion::BASELINE);
+        assert_eq!(legacy.resources, ServerResources::default());
+        assert!(legacy.has_gpu());
+
+        let resources = ServerResources {
+            memory_total: 64 << 30,
+            memory_available: 48 << 30,
+            gpus: vec![GpuInfo {
+                name: "NVIDIA A100-SXM4-80GB".to_string(),
+                memory_total: 80 << 30,
+                memory_available: 60 << 30,
+                utilization: 0.25,
+            }],
+            loaded_models: vec!["llama-3-8b".to_string()],
+            queue_depth: 3,
+            load: 0.5,
+        };
+        let registration = ServerRegistration::new(
+            "http://gpu-1:9090".to_string(),
+            vec!["ml.".to_string()],
+            resources.clone(),
+        );
+        let json = serde_json::to_value(&registration).unwrap();
+        assert_eq!(json["queue_depth"], 3);
+        assert!(json.get("gpu_available").is_none());
+        let parsed: ServerRegistration = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, registration);
+        assert!(parsed.has_gpu());
+
+        let heartbeat = ServerHeartbeat::new("http://gpu-1:9090".to_string(), resources);
+        let json = serde_json::to_string(&heartbeat).unwrap();
+        let parsed: ServerHeartbeat = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, heartbeat);
+    }
+
+    #[test]
+    fn test_transfer_messages() {
+        let data_ref = DataRef::new(
+            "gpu-1".to_string(),
+            40 << 30,
+            swarmx_dataref::DataType::Bytes,
+            Uuid::new_v4(),
+        );
+        let plan = TransferPlan::new(data_ref, swarmx_dataref::DEFAULT_CHUNK_SIZE);
+        let init = TransferInit::from(plan.clone());
+        let parsed: TransferInit =
+            serde_json::from_str(&serde_json::to_string(&init).unwrap()).unwrap();
+        let resumed = TransferPlan::from(parsed);
+        assert_eq!(resumed.transfer_id, plan.transfer_id);
+        assert_eq!(resumed.chunk_count(), 5 * 1024);
+
+        let chunk = Chunk::new(8, b"tensor".to_vec());
+        let header = TransferChunk::new(plan.transfer_id, &chunk);
+        assert_eq!(header.length, 6);
+        assert_eq!(header.into_chunk(chunk.data.clone()), chunk);
+    }
+
+    #[test]
+    fn test_multipart_upload_response() {
+        let data_ref = DataRef::new(
+            "client".to_string(),
+            0,
+            swarmx_dataref::DataType::Bytes,
+            Uuid::new_v4(),
+        );
+        let upload = MultipartUpload {
+            upload_id: Uuid::new_v4(),
+            data_ref: data_ref.clone(),
+            size_bytes: 250,
+            part_size: 100,
+            created_at: Utc::now(),
+            expires_at: Utc::now(),
+            parts: BTreeMap::new(),
+        };
+        let response = MultipartUploadResponse::new(&upload, |n| format!("/parts/{n}"));
+        assert_eq!(response.data_uuid, data_ref.uuid);
+        let parts: Vec<_> = response
+            .parts
+            .iter()
+            .map(|part| (part.part_number, part.offset, part.size, part.url.as_str()))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                (1, 0, 100, "/parts/1"),
+                (2, 100, 100, "/parts/2"),
+                (3, 200, 50, "/parts/3"),
+            ]
+        );
+
+        let request: MultipartUploadRequest =
+            serde_json::from_str(r#"{"size_bytes": 250, "content_type": "video/mp4"}"#).unwrap();
+        assert!(request.store_request("file").is_none());
     }
 
     #[test]
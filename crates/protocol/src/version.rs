@@ -0,0 +1,283 @@
+//! Protocol versions
+//!
+//! Every request and response between the client and servers carries the
+//! version of the protocol its sender speaks in the
+//! [`PROTOCOL_VERSION_HEADER`], and servers report theirs when they
+//! register. Versions are `major.minor`: peers with the same major version
+//! understand each other, speaking the lower of their two versions, while
+//! different major versions cannot talk at all and are refused up front
+//! instead of failing on the first message that does not deserialize.
+//! Peers that send no version predate negotiation and speak
+//! [`ProtocolVersion::BASELINE`].
+//!
+//! Each minor version adds [features](ProtocolFeature). Before sending a
+//! message to an older peer, [`Downgrade`] it to the negotiated version,
+//! which rewrites what the peer would not understand, e.g. a local file
+//! input into a plain reference.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{TaskBatchRequest, TaskInput, TaskRequest};
+use crate::wire::WireFormat;
+
+/// Header naming the protocol version of a request or response
+pub const PROTOCOL_VERSION_HEADER: &str = "X-SwarmX-Protocol-Version";
+
+/// Version of the protocol this crate speaks
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::new(1, 8);
+
+/// Protocol version, `major.minor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    /// Version of peers that predate version negotiation
+    pub const BASELINE: ProtocolVersion = ProtocolVersion::new(1, 0);
+
+    /// Create a version
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Check if a peer speaking `other` can talk with this version
+    pub fn is_compatible(self, other: ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+
+    /// Pick the version to speak with a peer speaking `peer`: the lower of
+    /// the two
+    pub fn negotiate(self, peer: ProtocolVersion) -> Result<ProtocolVersion, VersionError> {
+        if !self.is_compatible(peer) {
+            return Err(VersionError::Incompatible { local: self, peer });
+        }
+        Ok(self.min(peer))
+    }
+
+    /// Check if the version has a feature
+    pub fn supports(self, feature: ProtocolFeature) -> bool {
+        let since = feature.since();
+        self.is_compatible(since) && self >= since
+    }
+
+    /// Get the body format to send at this version, `preferred` if the
+    /// version has binary encodings and JSON otherwise
+    pub fn wire_format(self, preferred: WireFormat) -> WireFormat {
+        if self.supports(ProtocolFeature::BinaryEncodings) {
+            preferred
+        } else {
+            WireFormat::Json
+        }
+    }
+}
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        Self::BASELINE
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || VersionError::Invalid(s.to_string());
+        let (major, minor) = s.trim().split_once('.').ok_or_else(invalid)?;
+        Ok(Self::new(
+            major.parse().map_err(|_| invalid())?,
+            minor.parse().map_err(|_| invalid())?,
+        ))
+    }
+}
+
+impl TryFrom<String> for ProtocolVersion {
+    type Error = VersionError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ProtocolVersion> for String {
+    fn from(version: ProtocolVersion) -> Self {
+        version.to_string()
+    }
+}
+
+/// Error reading or negotiating a protocol version
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum VersionError {
+    #[error("invalid protocol version '{0}', expected major.minor")]
+    Invalid(String),
+    #[error("protocol version {peer} is incompatible with {local}; both sides must speak {}.x", local.major)]
+    Incompatible {
+        local: ProtocolVersion,
+        peer: ProtocolVersion,
+    },
+}
+
+/// Protocol addition, and the minor version that introduced it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolFeature {
+    /// [`TaskInput::LocalFile`] inputs
+    LocalFileInputs,
+    /// `POST /tasks/batch`
+    BatchSubmission,
+    /// [`CallbackMessage::PartialOutput`](crate::CallbackMessage::PartialOutput)
+    /// callbacks
+    PartialOutput,
+    /// Task channel WebSockets
+    TaskChannel,
+    /// MessagePack and CBOR bodies
+    BinaryEncodings,
+    /// [`TaskRequest::idempotency_key`]; older servers ignore the key and
+    /// run resubmissions again
+    IdempotencyKeys,
+    /// [`TaskCancelRequest`](crate::TaskCancelRequest) and
+    /// [`CallbackMessage::Cancelled`](crate::CallbackMessage::Cancelled)
+    TaskCancellation,
+    /// [`ServerRegistration`](crate::ServerRegistration) with resources,
+    /// and [`ServerHeartbeat`](crate::ServerHeartbeat)s
+    ServerHeartbeats,
+}
+
+impl ProtocolFeature {
+    /// Get the version that introduced the feature
+    pub const fn since(self) -> ProtocolVersion {
+        match self {
+            ProtocolFeature::LocalFileInputs => ProtocolVersion::new(1, 1),
+            ProtocolFeature::BatchSubmission => ProtocolVersion::new(1, 2),
+            ProtocolFeature::PartialOutput => ProtocolVersion::new(1, 3),
+            ProtocolFeature::TaskChannel => ProtocolVersion::new(1, 4),
+            ProtocolFeature::BinaryEncodings => ProtocolVersion::new(1, 5),
+            ProtocolFeature::IdempotencyKeys => ProtocolVersion::new(1, 6),
+            ProtocolFeature::TaskCancellation => ProtocolVersion::new(1, 7),
+            ProtocolFeature::ServerHeartbeats => ProtocolVersion::new(1, 8),
+        }
+    }
+}
+
+/// Message that can be rewritten for a peer speaking an older version
+pub trait Downgrade {
+    /// Rewrite the message so a peer speaking `version` understands it
+    fn downgrade(self, version: ProtocolVersion) -> Self;
+}
+
+impl Downgrade for TaskInput {
+    /// Local file inputs become references the server fetches over HTTP
+    fn downgrade(self, version: ProtocolVersion) -> Self {
+        match self {
+            TaskInput::LocalFile { name, data_ref, .. }
+                if !version.supports(ProtocolFeature::LocalFileInputs) =>
+            {
+                TaskInput::Reference { name, data_ref }
+            }
+            input => input,
+        }
+    }
+}
+
+impl Downgrade for TaskRequest {
+    fn downgrade(mut self, version: ProtocolVersion) -> Self {
+        self.inputs = self
+            .inputs
+            .into_iter()
+            .map(|input| input.downgrade(version))
+            .collect();
+        self
+    }
+}
+
+impl Downgrade for TaskBatchRequest {
+    /// Downgrades each task; peers without batch submission need each task
+    /// submitted on its own
+    fn downgrade(mut self, version: ProtocolVersion) -> Self {
+        self.tasks = self
+            .tasks
+            .into_iter()
+            .map(|task| task.downgrade(version))
+            .collect();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use swarmx_dataref::DataRef;
+
+    use super::*;
+
+    #[test]
+    fn test_protocol_version() {
+        let v1_2: ProtocolVersion = "1.2".parse().unwrap();
+        assert_eq!(v1_2, ProtocolVersion::new(1, 2));
+        assert_eq!(v1_2.to_string(), "1.2");
+        assert_eq!(serde_json::to_string(&v1_2).unwrap(), r#""1.2""#);
+        assert!("1".parse::<ProtocolVersion>().is_err());
+        assert!(serde_json::from_str::<ProtocolVersion>(r#""1.x""#).is_err());
+
+        assert_eq!(PROTOCOL_VERSION.negotiate(v1_2), Ok(v1_2));
+        assert_eq!(v1_2.negotiate(PROTOCOL_VERSION), Ok(v1_2));
+        let v2 = ProtocolVersion::new(2, 0);
+        let e = PROTOCOL_VERSION.negotiate(v2).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("protocol version 2.0 is incompatible with {PROTOCOL_VERSION}; both sides must speak 1.x")
+        );
+
+        assert!(v1_2.supports(ProtocolFeature::BatchSubmission));
+        assert!(!v1_2.supports(ProtocolFeature::PartialOutput));
+        assert!(!v2.supports(ProtocolFeature::LocalFileInputs));
+        assert_eq!(v1_2.wire_format(WireFormat::Cbor), WireFormat::Json);
+        assert_eq!(
+            PROTOCOL_VERSION.wire_format(WireFormat::Cbor),
+            WireFormat::Cbor
+        );
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let data_ref = DataRef::bytes("http://gpu-1:3000/api".to_string(), Uuid::new_v4(), b"");
+        let request = TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: "ml.infer".to_string(),
+            inputs: vec![
+                TaskInput::local_file("weights", data_ref.clone(), PathBuf::from("/data/w")),
+                TaskInput::inline("batch", serde_json::json!([1, 2])),
+            ],
+            config: serde_json::json!({}),
+            callback_url: "http://localhost:3000/api/callback".to_string(),
+            timeout_ms: None,
+            trace_id: None,
+            parent_span_id: None,
+            idempotency_key: None,
+        };
+
+        let current = request.clone().downgrade(PROTOCOL_VERSION);
+        assert!(matches!(current.inputs[0], TaskInput::LocalFile { .. }));
+        let batch = TaskBatchRequest::new(vec![request]).downgrade(ProtocolVersion::BASELINE);
+        match &batch.tasks[0].inputs[0] {
+            TaskInput::Reference { name, data_ref: r } => {
+                assert_eq!(name, "weights");
+                assert_eq!(r.uuid, data_ref.uuid);
+            }
+            other => panic!("expected a reference input, got {other:?}"),
+        }
+        assert!(matches!(batch.tasks[0].inputs[1], TaskInput::Inline { .. }));
+    }
+}
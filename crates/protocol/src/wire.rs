@@ -0,0 +1,285 @@
+//! Wire formats for protocol messages
+//!
+//! Messages travel as JSON unless a peer asks otherwise. Tensor-heavy
+//! metadata and large node configurations are smaller and faster to parse
+//! as MessagePack or CBOR, so any message can be encoded in either: the
+//! sender names the format of a body with `Content-Type`, and the receiver
+//! of a response names the formats it takes with `Accept`. Every message
+//! is a [`WireMessage`]:
+//!
+//! ```
+//! use swarmx_protocol::{TaskStatus, WireFormat, WireMessage};
+//!
+//! let body = TaskStatus::Running.encode(WireFormat::MessagePack).unwrap();
+//! let status = TaskStatus::decode(&body, "application/msgpack").unwrap();
+//! assert_eq!(status, TaskStatus::Running);
+//! ```
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Encoding of a message body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireFormat {
+    #[default]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    Cbor,
+}
+
+impl WireFormat {
+    /// Every format, JSON first
+    pub const ALL: [WireFormat; 3] = [WireFormat::Json, WireFormat::MessagePack, WireFormat::Cbor];
+
+    /// Get the name of the format
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+            WireFormat::Cbor => "cbor",
+        }
+    }
+
+    /// Get the `Content-Type` of bodies in the format
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            WireFormat::MessagePack => "application/msgpack",
+            WireFormat::Cbor => "application/cbor",
+        }
+    }
+
+    /// Get the format of a `Content-Type`, ignoring its parameters
+    ///
+    /// `application/x-msgpack` and `application/vnd.msgpack` are taken as
+    /// MessagePack.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(WireFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(WireFormat::MessagePack)
+            }
+            "application/cbor" => Some(WireFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Pick the format to answer an `Accept` header with
+    ///
+    /// Takes the acceptable format with the highest quality, the earliest
+    /// listed among equals; wildcards stand for JSON. `None` if the header
+    /// accepts none of the formats.
+    pub fn negotiate(accept: &str) -> Option<Self> {
+        let mut best: Option<(WireFormat, f32)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type.as_str() {
+                "*/*" | "application/*" => Some(WireFormat::Json),
+                media_type => WireFormat::from_content_type(media_type),
+            };
+            if let Some(format) = format.filter(|_| quality > 0.0) {
+                if best.is_none_or(|(_, best)| quality > best) {
+                    best = Some((format, quality));
+                }
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    /// Get an `Accept` header preferring this format, with JSON as the
+    /// fallback
+    pub fn accept(self) -> String {
+        match self {
+            WireFormat::Json => WireFormat::Json.content_type().to_string(),
+            format => format!("{}, application/json;q=0.5", format.content_type()),
+        }
+    }
+
+    /// Encode a message in the format
+    ///
+    /// MessagePack encodes structs as maps, keeping field names, so that
+    /// optional and flattened fields decode as they do from JSON.
+    pub fn encode<T: Serialize + ?Sized>(self, message: &T) -> Result<Vec<u8>, WireError> {
+        let encoded = match self {
+            WireFormat::Json => serde_json::to_vec(message).map_err(|e| e.to_string()),
+            WireFormat::MessagePack => rmp_serde::to_vec_named(message).map_err(|e| e.to_string()),
+            WireFormat::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(message, &mut body)
+                    .map(|_| body)
+                    .map_err(|e| e.to_string())
+            }
+        };
+        encoded.map_err(|message| WireError::Encode {
+            format: self,
+            message,
+        })
+    }
+
+    /// Decode a message in the format
+    pub fn decode<T: DeserializeOwned>(self, body: &[u8]) -> Result<T, WireError> {
+        let decoded = match self {
+            WireFormat::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            WireFormat::MessagePack => rmp_serde::from_slice(body).map_err(|e| e.to_string()),
+            WireFormat::Cbor => ciborium::from_reader(body).map_err(|e| e.to_string()),
+        };
+        decoded.map_err(|message| WireError::Decode {
+            format: self,
+            message,
+        })
+    }
+}
+
+impl std::fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error encoding or decoding a message
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum WireError {
+    #[error("unsupported content type '{0}'")]
+    UnsupportedContentType(String),
+    #[error("failed to encode {format} message: {message}")]
+    Encode { format: WireFormat, message: String },
+    #[error("failed to decode {format} message: {message}")]
+    Decode { format: WireFormat, message: String },
+}
+
+/// Message that travels in any [`WireFormat`]
+pub trait WireMessage: Serialize + DeserializeOwned {
+    /// Encode the message in `format`
+    fn encode(&self, format: WireFormat) -> Result<Vec<u8>, WireError> {
+        format.encode(self)
+    }
+
+    /// Decode a message sent with `content_type`
+    fn decode(body: &[u8], content_type: &str) -> Result<Self, WireError> {
+        WireFormat::from_content_type(content_type)
+            .ok_or_else(|| WireError::UnsupportedContentType(content_type.to_string()))?
+            .decode(body)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> WireMessage for T {}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use swarmx_dataref::DataRef;
+
+    use super::*;
+    use crate::messages::*;
+
+    #[test]
+    fn test_negotiate() {
+        let negotiate = WireFormat::negotiate;
+        assert_eq!(
+            negotiate("application/msgpack"),
+            Some(WireFormat::MessagePack)
+        );
+        assert_eq!(
+            negotiate("application/json;q=0.5, application/cbor"),
+            Some(WireFormat::Cbor)
+        );
+        assert_eq!(
+            negotiate("application/cbor, application/msgpack"),
+            Some(WireFormat::Cbor)
+        );
+        assert_eq!(negotiate("text/html, */*;q=0.1"), Some(WireFormat::Json));
+        assert_eq!(negotiate("application/cbor;q=0, text/html"), None);
+        assert_eq!(
+            WireFormat::negotiate(&WireFormat::MessagePack.accept()),
+            Some(WireFormat::MessagePack)
+        );
+        assert_eq!(
+            WireFormat::from_content_type("Application/X-MsgPack; charset=binary"),
+            Some(WireFormat::MessagePack)
+        );
+    }
+
+    #[test]
+    fn test_wire_roundtrip() {
+        let task_id = Uuid::new_v4();
+        let data_ref = DataRef::json(
+            "http://gpu-1:3000/api".to_string(),
+            Uuid::new_v4(),
+            &serde_json::json!({"shape": [1024, 1024]}),
+        );
+        let request = TaskRequest {
+            node_id: Uuid::new_v4(),
+            node_type: "ml.infer".to_string(),
+            inputs: vec![
+                TaskInput::inline("batch", serde_json::json!([1.5, 2, null, {"k": "v"}])),
+                TaskInput::local_file("weights", data_ref.clone(), PathBuf::from("/data/w")),
+                TaskInput::reference("table", data_ref.clone()),
+            ],
+            config: serde_json::json!({"layers": [64, 64], "dropout": 0.1}),
+            callback_url: "http://localhost:3000/api/callback".to_string(),
+            timeout_ms: None,
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            parent_span_id: None,
+            idempotency_key: None,
+        };
+        let callback =
+            CallbackMessage::complete(task_id, vec![TaskOutput::reference("out", data_ref)], 12)
+                .with_trace(CallbackTrace {
+                    trace_id: request.trace_id.clone(),
+                    parent_span_id: Some("00f067aa0ba902b7".to_string()),
+                });
+        let channel = TaskChannelMessage::Submitted(TaskBatchResult::Accepted {
+            node_id: request.node_id,
+            task_id,
+            accepted_at: Utc::now(),
+        });
+
+        for format in WireFormat::ALL {
+            let decoded: TaskRequest =
+                WireMessage::decode(&request.encode(format).unwrap(), format.content_type())
+                    .unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&request).unwrap(),
+                "{format}"
+            );
+            let decoded: CallbackMessage =
+                format.decode(&format.encode(&callback).unwrap()).unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&callback).unwrap(),
+                "{format}"
+            );
+            let decoded: TaskChannelMessage =
+                format.decode(&format.encode(&channel).unwrap()).unwrap();
+            assert_eq!(decoded.task_id(), Some(task_id), "{format}");
+        }
+
+        assert!(matches!(
+            TaskStatus::decode(b"\"running\"", "text/plain"),
+            Err(WireError::UnsupportedContentType(_))
+        ));
+        assert!(matches!(
+            WireFormat::Cbor.decode::<TaskStatus>(b"\"running\""),
+            Err(WireError::Decode {
+                format: WireFormat::Cbor,
+                ..
+            })
+        ));
+    }
+}
@@ -49,6 +49,7 @@ const NODE_CATEGORIES: NodeCategory[] = [
       { type: 'flow.ai_branch', label: 'AI Branch', description: 'AI-powered if/else' },
       { type: 'flow.loop', label: 'Loop', description: 'Iterate array' },
       { type: 'flow.merge', label: 'Merge', description: 'Merge branches' },
+      { type: 'flow.approval', label: 'Approval', description: 'Wait for manual approval' },
     ],
   },
   {